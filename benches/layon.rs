@@ -0,0 +1,145 @@
+//! 解析、面積の計算、集計の処理時間を測るベンチマーク（criterion。`cargo bench`、`cargo bench -- aggregate` で絞り込み）。
+//! コード中のコメントにある性能の記述（頂点数で分けたチャンク、`simd` フィーチャーなど）を確かめられるようにする。
+//! `simd` の効果は `cargo bench --bench layon -- --save-baseline scalar kernel` の後に
+//! `cargo bench --bench layon --features simd -- --baseline scalar kernel` で比べる。
+//!
+//! 入力は 2 種類:
+//! - `tests/fixtures/n03_11_sample.geojson`: 埼玉県の行政区域データから抽出した Feature（座標は小数点以下 6 桁。golden テストと共用）
+//! - 合成したデータ: 格子状に並べた小さな正方形と、頂点の多い 1 つのリング（海岸線の代わり）
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use geojson::{Feature, FeatureCollection, Geometry, JsonObject, JsonValue, Value};
+use layon::{
+    aggregate::{self, Metric},
+    pipeline::{CancellationToken, Pipeline, Schedule},
+    source::{Input, InputOptions},
+};
+use std::hint::black_box;
+
+/// 実データから抽出した入力
+const FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/n03_11_sample.geojson"
+);
+
+fn fixture() -> FeatureCollection {
+    Input::parse(FIXTURE, InputOptions::default())
+        .and_then(|input| input.read().map_err(|err| err.to_string()))
+        .expect("ベンチマーク用のデータを読み込めません")
+}
+
+fn parse(c: &mut Criterion) {
+    let grid_text = serde_json::to_string(&grid(100, 10)).unwrap();
+    c.bench_function("parse/fixture", |b| b.iter(fixture));
+    c.bench_function("parse/grid-10000", |b| {
+        b.iter(|| black_box(&grid_text).parse::<geojson::GeoJson>().unwrap())
+    });
+}
+
+fn kernel(c: &mut Criterion) {
+    let fixture = fixture();
+    let coastline = ring_feature(100_000);
+    for (name, metric) in [
+        ("area", Metric::Area),
+        ("geodesic-area", Metric::GeodesicArea),
+    ] {
+        c.bench_function(&format!("kernel/{}/ring-100000", name), |b| {
+            b.iter(|| aggregate::feature_area(black_box(&coastline), metric))
+        });
+        c.bench_function(&format!("kernel/{}/fixture", name), |b| {
+            b.iter(|| {
+                fixture
+                    .features
+                    .iter()
+                    .map(|feature| aggregate::feature_area(feature, metric))
+                    .sum::<f64>()
+            })
+        });
+    }
+}
+
+fn aggregate(c: &mut Criterion) {
+    let fixture = fixture();
+    let grid = grid(100, 10);
+    for (name, schedule) in [
+        ("uniform", Schedule::Uniform),
+        ("by-cost", Schedule::ByCost),
+    ] {
+        for (input, collection) in [("fixture", &fixture), ("grid-10000", &grid)] {
+            c.bench_function(&format!("aggregate/{}/{}", name, input), |b| {
+                b.iter(|| {
+                    aggregate::aggregate_with(
+                        collection,
+                        "N03_004",
+                        false,
+                        Metric::Area,
+                        schedule,
+                        &CancellationToken::new(),
+                    )
+                    .unwrap()
+                })
+            });
+        }
+    }
+    c.bench_function("aggregate/dissolve/grid-10000", |b| {
+        b.iter(|| aggregate::aggregate(&grid, "N03_004", true))
+    });
+}
+
+fn pipeline(c: &mut Criterion) {
+    c.bench_function("pipeline/fixture", |b| {
+        b.iter(|| Pipeline::read(FIXTURE).run().unwrap())
+    });
+}
+
+criterion_group!(benches, parse, kernel, aggregate, pipeline);
+criterion_main!(benches);
+
+/// `size` × `size` 個の正方形を並べ、`groups` × `groups` のブロックごとに同じ集計キーを付ける
+fn grid(size: usize, groups: usize) -> FeatureCollection {
+    let block = size.div_ceil(groups);
+    let features = (0..size * size)
+        .map(|i| {
+            let (x, y) = ((i % size) as f64, (i / size) as f64);
+            let ring = vec![
+                vec![x, y],
+                vec![x + 1.0, y],
+                vec![x + 1.0, y + 1.0],
+                vec![x, y + 1.0],
+                vec![x, y],
+            ];
+            let key = format!("group-{}", (i / size / block) * groups + i % size / block);
+            feature(Value::Polygon(vec![ring]), key)
+        })
+        .collect();
+    FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    }
+}
+
+/// 頂点が `vertices` 個の、半径が揺らぐ円（海岸線のように頂点の多いリング）。座標は経度・緯度の範囲に収める
+fn ring_feature(vertices: usize) -> Feature {
+    let mut ring: Vec<Vec<f64>> = (0..vertices)
+        .map(|i| {
+            let angle = i as f64 / vertices as f64 * std::f64::consts::TAU;
+            let radius = 0.5 + 0.05 * (angle * 37.0).sin();
+            vec![139.5 + radius * angle.cos(), 35.9 + radius * angle.sin()]
+        })
+        .collect();
+    ring.push(ring[0].clone());
+    feature(Value::Polygon(vec![ring]), "coast".to_string())
+}
+
+fn feature(value: Value, key: String) -> Feature {
+    let mut properties = JsonObject::new();
+    properties.insert("N03_004".to_string(), JsonValue::from(key));
+    Feature {
+        bbox: None,
+        geometry: Some(Geometry::new(value)),
+        id: None,
+        properties: Some(properties),
+        foreign_members: None,
+    }
+}
@@ -0,0 +1,18 @@
+// cargo でビルドしたライブラリを、Node.js が読み込める名前 (layon.node) でこのディレクトリにコピーする
+const { execFileSync } = require("node:child_process");
+const fs = require("node:fs");
+const path = require("node:path");
+
+const root = path.resolve(__dirname, "../..");
+execFileSync("cargo", ["build", "--release", "-p", "layon-node"], {
+  cwd: root,
+  stdio: "inherit",
+});
+const library = {
+  darwin: "liblayon_node.dylib",
+  win32: "layon_node.dll",
+}[process.platform] ?? "liblayon_node.so";
+fs.copyFileSync(
+  path.join(root, "target", "release", library),
+  path.join(__dirname, "layon.node"),
+);
@@ -0,0 +1,4 @@
+// Node-API の関数は Node.js が読み込んだときに解決されるため、リンク時に未定義のままにしておく設定を napi-build に任せる
+fn main() {
+    napi_build::setup();
+}
@@ -0,0 +1,2 @@
+// ネイティブモジュール (npm run build で作る layon.node) をそのまま公開する
+module.exports = require("./layon.node");
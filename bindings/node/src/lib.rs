@@ -0,0 +1,105 @@
+//! Node.js から集計を呼ぶためのネイティブモジュール（napi-rs）。
+//! バイナリを子プロセスで起動せずに、`layon::pipeline::Pipeline` で集計した結果を JavaScript の配列で受け取れる。
+//!
+//! ```js
+//! const layon = require("./bindings/node");
+//! const rows = layon.aggregate("src/N03-20240101_11.geojson", {
+//!   groupBy: "N03_004",
+//!   metric: "geodesic-area",
+//!   where: "N03_004 != '秩父市'",
+//! });
+//! // [{ key: "さいたま市", area: 217.43..., count: 10 }, ...]
+//! ```
+//!
+//! オプションはすべて省略できる（既定の集計キーは N03_004、集計値は area）。metric には area, geodesic-area, length（線の長さ）のほか
+//! `--projection` と同じ投影法の名前を指定できる。複数のレイヤーを集計した場合は行に layer が付く。
+//! 集計は呼び出したスレッドで行う（その間 JavaScript の処理は止まる）。エラーは例外として投げる。
+//!
+//! ビルドは `npm run build`（`cargo build --release -p layon-node` の結果を layon.node にコピーする）。
+
+use layon::{
+    aggregate::{Metric, Projection},
+    pipeline::Pipeline,
+};
+use napi::{Error, Result};
+use napi_derive::napi;
+
+/// `aggregate` のオプション
+#[napi(object)]
+pub struct AggregateOptions {
+    /// 集計キーにするプロパティ
+    pub group_by: Option<String>,
+    /// 集計値
+    pub metric: Option<String>,
+    /// `--where` と同じ条件
+    #[napi(js_name = "where")]
+    pub filter: Option<String>,
+}
+
+/// 集計結果の 1 行
+#[napi(object)]
+pub struct Row {
+    pub key: String,
+    /// 複数のレイヤーを集計した場合のレイヤー名
+    pub layer: Option<String>,
+    pub area: f64,
+    pub count: u32,
+}
+
+/// `path` の Feature を集計キーごとに集計し、集計値の降順の行を返す
+#[napi]
+pub fn aggregate(path: String, options: Option<AggregateOptions>) -> Result<Vec<Row>> {
+    let mut pipeline = Pipeline::read(path);
+    if let Some(options) = options {
+        if let Some(group_by) = options.group_by {
+            pipeline = pipeline.group_by(group_by);
+        }
+        if let Some(metric) = options.metric {
+            pipeline = pipeline.metric(parse_metric(&metric).map_err(Error::from_reason)?);
+        }
+        if let Some(filter) = options.filter {
+            pipeline = pipeline.filter(filter);
+        }
+    }
+    let rows = pipeline
+        .run()
+        .map_err(|err| Error::from_reason(err.to_string()))?
+        .rows;
+    Ok(rows
+        .into_iter()
+        .map(|row| Row {
+            key: row.key,
+            layer: row.layer,
+            area: row.area,
+            count: row.count as u32,
+        })
+        .collect())
+}
+
+/// `area`, `geodesic-area` または投影法の名前
+fn parse_metric(name: &str) -> std::result::Result<Metric, String> {
+    match name {
+        "area" => Ok(Metric::Area),
+        "geodesic-area" => Ok(Metric::GeodesicArea),
+        "length" => Ok(Metric::GeodesicLength),
+        _ => Projection::parse(name)
+            .map(Metric::Projected)
+            .map_err(|_| format!("不明な集計値です: {}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_are_parsed_like_the_cli() {
+        assert!(matches!(parse_metric("area"), Ok(Metric::Area)));
+        assert!(matches!(parse_metric("length"), Ok(Metric::GeodesicLength)));
+        assert!(matches!(parse_metric("utm-54"), Ok(Metric::Projected(_))));
+        assert_eq!(
+            parse_metric("volume").err().unwrap(),
+            "不明な集計値です: volume"
+        );
+    }
+}
@@ -0,0 +1,27 @@
+// npm run build の後に npm test で実行する
+const assert = require("node:assert");
+const path = require("node:path");
+const layon = require(".");
+
+const fixture = path.resolve(__dirname, "../../tests/fixtures/n03_11_sample.geojson");
+
+const rows = layon.aggregate(fixture, { groupBy: "N03_004", metric: "geodesic-area" });
+assert.ok(rows.length > 0);
+for (const row of rows) {
+  assert.strictEqual(typeof row.key, "string");
+  assert.ok(row.area > 0);
+  assert.ok(Number.isInteger(row.count));
+}
+// 面積の降順
+assert.deepStrictEqual(
+  rows.map((row) => row.area),
+  rows.map((row) => row.area).sort((a, b) => b - a),
+);
+
+const filtered = layon.aggregate(fixture, { where: `N03_004 != '${rows[0].key}'` });
+assert.strictEqual(filtered.length, rows.length - 1);
+
+assert.throws(() => layon.aggregate(fixture, { metric: "volume" }), /不明な集計値です/);
+// 引数の型の誤りは napi-rs が変換するときに投げる
+assert.throws(() => layon.aggregate(42), { code: "StringExpected" });
+console.log("ok");
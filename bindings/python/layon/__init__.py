@@ -0,0 +1,202 @@
+"""layon のライブラリ (Rust) で集計し、集計結果を Python のオブジェクトとして受け取る。
+
+集計は拡張モジュール layon._layon (pyo3) が GIL を外して並列に行う。コマンドを起動したり一時ファイルを書いたりはしない
+(``pip install bindings/python`` で maturin がビルドする)。
+
+    >>> import layon
+    >>> result = layon.aggregate("src/N03-20240101_11.geojson", metric="geodesic-area")
+    >>> result          # Jupyter では見出しを押すと並べ替えられる表になる
+    >>> result.to_pandas()
+    >>> table = layon.aggregate_arrow("src/N03-20240101_11.geojson")  # aggregate と同じ列の pyarrow.Table
+    >>> shapes = layon.dissolve("src/N03-20240101_11.geojson", group_by="N03_004")
+    >>> geopandas.GeoDataFrame.from_features(shapes)                # __geo_interface__
+
+pyarrow, pandas, geopandas はそれぞれを使うメソッドを呼んだときにだけ読み込む。
+"""
+
+import html
+import itertools
+import json
+import os
+
+from ._layon import LayonError, aggregate_rows, dissolve_geojson
+
+__all__ = [
+    "LayonError",
+    "Result",
+    "Shapes",
+    "aggregate",
+    "aggregate_arrow",
+    "dissolve",
+]
+
+# _repr_html_ で表示する行の数の上限
+HTML_ROWS = 200
+
+_table_ids = itertools.count()
+
+
+def aggregate(path, group_by="N03_004", metric="area", where=None):
+    """`group_by` ごとの面積を集計する (layon の既定の動作と同じ)。
+
+    metric は "area" (座標の単位)、"geodesic-area" (楕円体上の km²)、"length" (線の長さ)
+    または投影法の名前 ("utm-auto" など)。
+    where は集計する Feature の条件 (例: "N03_004 != '秩父市'")。
+    """
+    return Result(aggregate_rows(path, group_by, metric, where), group_by=group_by, metric=metric)
+
+
+def aggregate_arrow(path, group_by="N03_004", metric="area", where=None):
+    """`aggregate` と同じく集計し、同じ列 (key, area, count) の pyarrow.Table で返す"""
+    return aggregate(path, group_by=group_by, metric=metric, where=where).to_arrow()
+
+
+def dissolve(path, group_by="N03_004", metric="geodesic-area", where=None):
+    """`group_by` ごとにポリゴンをディゾルブし、面積を付けた境界を返す (簡略化しない)"""
+    collection = json.loads(dissolve_geojson(path, group_by, metric, where))
+    return Shapes(collection, group_by=group_by, metric=metric)
+
+
+class Result:
+    """集計結果 (面積の降順)。行は key, area, count (複数のレイヤーでは layer も) を持つ dict"""
+
+    def __init__(self, rows, group_by, metric):
+        self.rows = rows
+        self.group_by = group_by
+        self.metric = metric
+
+    def __len__(self):
+        return len(self.rows)
+
+    def __iter__(self):
+        return iter(self.rows)
+
+    def __getitem__(self, index):
+        return self.rows[index]
+
+    def __repr__(self):
+        return "<layon.Result {} 行 ({} ごとの {})>".format(len(self), self.group_by, self.metric)
+
+    def columns(self):
+        """列の名前 (layer は複数のレイヤーを集計した場合だけ)"""
+        names = ["key", "area", "count"]
+        if any("layer" in row for row in self.rows):
+            names.insert(0, "layer")
+        return names
+
+    def to_dict(self):
+        """列ごとの値のリスト"""
+        return {name: [row.get(name) for row in self.rows] for name in self.columns()}
+
+    def to_arrow(self):
+        import pyarrow
+
+        return pyarrow.table(self.to_dict())
+
+    def to_pandas(self):
+        import pandas
+
+        return pandas.DataFrame(self.to_dict(), columns=self.columns())
+
+    def _repr_html_(self):
+        columns = self.columns()
+        rows = [[row.get(name) for name in columns] for row in self.rows]
+        caption = "{} ごとの {} ({} 行)".format(self.group_by, self.metric, len(self))
+        return _html_table(caption, columns, rows)
+
+
+class Shapes:
+    """ディゾルブした境界 (GeoJSON の FeatureCollection)。Feature は name, area, count のプロパティを持つ"""
+
+    def __init__(self, collection, group_by, metric):
+        self.collection = collection
+        self.group_by = group_by
+        self.metric = metric
+
+    @property
+    def __geo_interface__(self):
+        return self.collection
+
+    def __len__(self):
+        return len(self.collection["features"])
+
+    def __repr__(self):
+        return "<layon.Shapes {} 個の境界 ({} ごと)>".format(len(self), self.group_by)
+
+    def to_geopandas(self):
+        import geopandas
+
+        # layon は経度・緯度 (RFC 7946) で書き出す
+        return geopandas.GeoDataFrame.from_features(self.collection, crs="EPSG:4326")
+
+    def _repr_html_(self):
+        columns = ["name", "area", "count"]
+        rows = [
+            [feature["properties"].get(name) for name in columns]
+            for feature in self.collection["features"]
+        ]
+        caption = "{} ごとの境界と {} ({} 個)".format(self.group_by, self.metric, len(self))
+        return _html_table(caption, columns, rows)
+
+
+def _html_table(caption, columns, rows):
+    """見出しを押すとその列で並べ替える HTML の表 (数値の列は数値として並べる)"""
+    table_id = "layon-{}-{}".format(os.getpid(), next(_table_ids))
+    shown = rows[:HTML_ROWS]
+    head = "".join(
+        '<th style="cursor:pointer" onclick="layonSort(\'{}\', {})">{}</th>'.format(
+            table_id, i, html.escape(name)
+        )
+        for i, name in enumerate(columns)
+    )
+    body = "".join(
+        "<tr>{}</tr>".format("".join(_html_cell(value) for value in row)) for row in shown
+    )
+    more = ""
+    if len(rows) > len(shown):
+        more = "<p>ほか {} 行 (to_pandas() などで全体を見られます)</p>".format(len(rows) - len(shown))
+    return (
+        '<table id="{id}"><caption>{caption}</caption><thead><tr>{head}</tr></thead>'
+        "<tbody>{body}</tbody></table>{more}<script>{script}</script>"
+    ).format(
+        id=table_id,
+        caption=html.escape(caption),
+        head=head,
+        body=body,
+        more=more,
+        script=_SORT_SCRIPT,
+    )
+
+
+def _html_cell(value):
+    if value is None:
+        return "<td></td>"
+    if isinstance(value, (int, float)):
+        return '<td style="text-align:right" data-value="{}">{}</td>'.format(
+            value, html.escape(format(value, ".6g") if isinstance(value, float) else str(value))
+        )
+    return "<td>{}</td>".format(html.escape(str(value)))
+
+
+# 同じ列をもう一度押すと逆順にする
+_SORT_SCRIPT = """
+window.layonSort = window.layonSort || function (id, column) {
+  var table = document.getElementById(id);
+  var body = table.tBodies[0];
+  var rows = Array.prototype.slice.call(body.rows);
+  var descending = table.dataset.column == column && table.dataset.order != "desc";
+  rows.sort(function (a, b) {
+    var x = a.cells[column], y = b.cells[column];
+    var order;
+    if (x.dataset.value !== undefined && y.dataset.value !== undefined) {
+      order = parseFloat(x.dataset.value) - parseFloat(y.dataset.value);
+    } else {
+      order = x.textContent.localeCompare(y.textContent);
+    }
+    return descending ? -order : order;
+  });
+  rows.forEach(function (row) { body.appendChild(row); });
+  table.dataset.column = column;
+  table.dataset.order = descending ? "desc" : "asc";
+};
+"""
@@ -0,0 +1,95 @@
+//! Python から集計を呼ぶための拡張モジュール（pyo3）。Python のパッケージ layon（layon/__init__.py）が読み込む。
+//! コマンドを子プロセスで起動せずに、`layon::pipeline::Pipeline` で集計した結果を Python のオブジェクトで返す。
+//! 集計の間は GIL を外すため、ほかのスレッドの Python の処理は止まらない。
+
+use layon::{
+    aggregate::{Metric, Projection},
+    pipeline::Pipeline,
+    sink,
+};
+use pyo3::{create_exception, exceptions::PyRuntimeError, prelude::*, types::PyDict};
+
+create_exception!(
+    _layon,
+    LayonError,
+    PyRuntimeError,
+    "layon の集計に失敗した（メッセージは layon のエラーメッセージ）"
+);
+
+/// 集計キーごとの面積。面積の降順の行（key, area, count。複数のレイヤーを集計した場合は layer も）の dict を返す
+#[pyfunction]
+#[pyo3(signature = (path, group_by, metric, r#where=None))]
+fn aggregate_rows<'py>(
+    py: Python<'py>,
+    path: String,
+    group_by: String,
+    metric: &str,
+    r#where: Option<String>,
+) -> PyResult<Vec<Bound<'py, PyDict>>> {
+    let metric = parse_metric(metric)?;
+    let rows = py
+        .allow_threads(|| run(pipeline(path, group_by, metric, r#where)))
+        .map_err(LayonError::new_err)?;
+    rows.into_iter()
+        .map(|row| {
+            let dict = PyDict::new(py);
+            if let Some(layer) = row.layer {
+                dict.set_item("layer", layer)?;
+            }
+            dict.set_item("key", row.key)?;
+            dict.set_item("area", row.area)?;
+            dict.set_item("count", row.count)?;
+            Ok(dict)
+        })
+        .collect()
+}
+
+/// 集計キーごとにディゾルブしたジオメトリの GeoJSON（プロパティは name, area, count）
+#[pyfunction]
+#[pyo3(signature = (path, group_by, metric, r#where=None))]
+fn dissolve_geojson(
+    py: Python<'_>,
+    path: String,
+    group_by: String,
+    metric: &str,
+    r#where: Option<String>,
+) -> PyResult<String> {
+    let metric = parse_metric(metric)?;
+    py.allow_threads(|| {
+        let rows = run(pipeline(path, group_by, metric, r#where).geometry(true))?;
+        Ok::<_, String>(sink::geojson::dissolved(&rows).to_string())
+    })
+    .map_err(LayonError::new_err)
+}
+
+fn pipeline(path: String, group_by: String, metric: Metric, filter: Option<String>) -> Pipeline {
+    let pipeline = Pipeline::read(path).group_by(group_by).metric(metric);
+    match filter {
+        Some(filter) => pipeline.filter(filter),
+        None => pipeline,
+    }
+}
+
+fn run(pipeline: Pipeline) -> Result<Vec<layon::aggregate::GroupResult>, String> {
+    Ok(pipeline.run().map_err(|err| err.to_string())?.rows)
+}
+
+/// `area`, `geodesic-area` または投影法の名前
+fn parse_metric(name: &str) -> PyResult<Metric> {
+    match name {
+        "area" => Ok(Metric::Area),
+        "geodesic-area" => Ok(Metric::GeodesicArea),
+        "length" => Ok(Metric::GeodesicLength),
+        _ => Projection::parse(name)
+            .map(Metric::Projected)
+            .map_err(|_| LayonError::new_err(format!("不明な集計値です: {}", name))),
+    }
+}
+
+#[pymodule]
+fn _layon(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add("LayonError", module.py().get_type::<LayonError>())?;
+    module.add_function(wrap_pyfunction!(aggregate_rows, module)?)?;
+    module.add_function(wrap_pyfunction!(dissolve_geojson, module)?)?;
+    Ok(())
+}
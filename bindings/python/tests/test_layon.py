@@ -0,0 +1,74 @@
+# bindings/python で maturin develop (または pip install .) した後に
+# python -m unittest discover bindings/python/tests で実行する
+import os
+import unittest
+
+import layon
+
+ROOT = os.path.abspath(os.path.join(os.path.dirname(__file__), "..", "..", ".."))
+FIXTURE = os.path.join(ROOT, "tests", "fixtures", "n03_11_sample.geojson")
+
+
+def installed(module):
+    try:
+        __import__(module)
+        return True
+    except ImportError:
+        return False
+
+
+class AggregateTest(unittest.TestCase):
+    def test_rows_are_sorted_by_area(self):
+        result = layon.aggregate(FIXTURE, metric="geodesic-area")
+        self.assertEqual(len(result), 8)
+        self.assertEqual(result.columns(), ["key", "area", "count"])
+        areas = [row["area"] for row in result]
+        self.assertEqual(areas, sorted(areas, reverse=True))
+
+    def test_where_filters_features(self):
+        first = layon.aggregate(FIXTURE)[0]["key"]
+        result = layon.aggregate(FIXTURE, where="N03_004 != '{}'".format(first))
+        self.assertEqual(len(result), 7)
+        self.assertNotIn(first, [row["key"] for row in result])
+
+    def test_errors_raise_the_layon_message(self):
+        with self.assertRaisesRegex(layon.LayonError, "不明な集計値です: volume"):
+            layon.aggregate(FIXTURE, metric="volume")
+        with self.assertRaises(layon.LayonError):
+            layon.aggregate(os.path.join(ROOT, "missing.geojson"))
+
+    def test_html_repr_is_a_sortable_escaped_table(self):
+        result = layon.Result(
+            [{"key": "<a&b>", "area": 1.5, "count": 2}], group_by="N03_004", metric="area"
+        )
+        page = result._repr_html_()
+        self.assertIn("&lt;a&amp;b&gt;", page)
+        self.assertIn('data-value="1.5"', page)
+        self.assertIn("layonSort", page)
+
+    def test_dissolve_exposes_geo_interface(self):
+        shapes = layon.dissolve(FIXTURE)
+        collection = shapes.__geo_interface__
+        self.assertEqual(collection["type"], "FeatureCollection")
+        self.assertEqual(len(shapes), 8)
+        self.assertEqual(
+            sorted(collection["features"][0]["properties"]), ["area", "count", "name"]
+        )
+        self.assertIn("<table", shapes._repr_html_())
+
+    @unittest.skipUnless(installed("pyarrow"), "pyarrow がありません")
+    def test_arrow_has_the_same_columns_as_aggregate(self):
+        table = layon.aggregate_arrow(FIXTURE)
+        result = layon.aggregate(FIXTURE)
+        self.assertEqual(table.column_names, result.columns())
+        self.assertEqual(table.to_pydict(), result.to_dict())
+
+    @unittest.skipUnless(installed("geopandas"), "geopandas がありません")
+    def test_geopandas(self):
+        frame = layon.dissolve(FIXTURE).to_geopandas()
+        self.assertEqual(len(frame), 8)
+        self.assertEqual(frame.crs.to_epsg(), 4326)
+
+
+if __name__ == "__main__":
+    unittest.main()
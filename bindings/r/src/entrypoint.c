@@ -0,0 +1,7 @@
+// Rust のスタティックライブラリがリンク時に取り除かれないよう、登録の関数を C から呼ぶ
+
+void R_init_layon_extendr(void *dll);
+
+void R_init_layon(void *dll) {
+    R_init_layon_extendr(dll);
+}
@@ -0,0 +1,185 @@
+//! R パッケージ layon のネイティブコード（extendr）。
+//! コマンドを子プロセスで起動せずに、`layon::pipeline::Pipeline` や `layon::zonal` で集計した結果を R のリストで返す。
+//! data.frame や sf のオブジェクトにするのは R 側（R/layon.R）で行う。
+//!
+//! ここで定義した関数の R のラッパーは R/extendr-wrappers.R にある（`rextendr::document()` で作り直せる）。
+
+use extendr_api::prelude::*;
+use layon::{
+    aggregate::{Metric, Projection},
+    pipeline::Pipeline,
+    sink,
+    source::{Input, InputOptions},
+    zonal::{self, Bands, Raster},
+};
+use std::fmt::Display;
+
+/// 集計キーごとの面積。key, layer（複数のレイヤーを集計した場合のみ）, area, count の列のリストを返す
+#[extendr]
+fn aggregate_rows(
+    path: &str,
+    group_by: &str,
+    metric: &str,
+    filter: Nullable<String>,
+) -> Result<List> {
+    let rows = pipeline(path, group_by, metric, filter)?
+        .run()
+        .map_err(fail)?
+        .rows;
+    let keys: Vec<String> = rows.iter().map(|row| row.key.clone()).collect();
+    let areas: Vec<f64> = rows.iter().map(|row| row.area).collect();
+    let counts: Vec<i32> = rows.iter().map(|row| row.count as i32).collect();
+    if rows.iter().any(|row| row.layer.is_some()) {
+        let layers: Vec<String> = rows
+            .iter()
+            .map(|row| row.layer.clone().unwrap_or_default())
+            .collect();
+        return Ok(list!(
+            key = keys,
+            layer = layers,
+            area = areas,
+            count = counts
+        ));
+    }
+    Ok(list!(key = keys, area = areas, count = counts))
+}
+
+/// 集計キーごとにディゾルブしたジオメトリの GeoJSON（プロパティは name, area, count）
+#[extendr]
+fn dissolve_geojson(
+    path: &str,
+    group_by: &str,
+    metric: &str,
+    filter: Nullable<String>,
+) -> Result<String> {
+    let rows = pipeline(path, group_by, metric, filter)?
+        .geometry(true)
+        .run()
+        .map_err(fail)?
+        .rows;
+    Ok(sink::geojson::dissolved(&rows).to_string())
+}
+
+/// ラスターの画素値のポリゴンごとの集計。
+/// `bands` を指定すれば City, Band, Pixels, Area、`bin_width` を指定すれば City, Value, Count、
+/// どちらもなければ City, Count, Sum, Mean, Min, Max の列のリストを返す
+#[extendr]
+fn zonal_rows(
+    raster: &str,
+    path: &str,
+    group_by: &str,
+    bands: Nullable<String>,
+    bin_width: Nullable<f64>,
+    metric: &str,
+) -> Result<List> {
+    let raster = Raster::read(raster).map_err(fail)?;
+    let collection = Input::parse(path, InputOptions::default())
+        .map_err(fail)?
+        .read()
+        .map_err(fail)?;
+    if let Nullable::NotNull(bands) = bands {
+        let metric = match metric {
+            "area" => Metric::Area,
+            "geodesic-area" => Metric::GeodesicArea,
+            _ => {
+                return Err(fail(format!(
+                    "帯の面積は area か geodesic-area です: {}",
+                    metric
+                )))
+            }
+        };
+        let bands = Bands::parse(&bands).map_err(fail)?;
+        let rows = zonal::bands(&collection, group_by, &raster, &bands, metric);
+        let cities: Vec<String> = rows.iter().map(|row| row.city.clone()).collect();
+        let names: Vec<String> = rows.iter().map(|row| row.band.clone()).collect();
+        let pixels: Vec<i32> = rows.iter().map(|row| row.pixels as i32).collect();
+        let areas: Vec<f64> = rows.iter().map(|row| row.area).collect();
+        return Ok(list!(
+            City = cities,
+            Band = names,
+            Pixels = pixels,
+            Area = areas
+        ));
+    }
+    let bin_width = match bin_width {
+        Nullable::NotNull(width) => Some(width),
+        Nullable::Null => None,
+    };
+    let rows = zonal::zonal(&collection, group_by, &raster, bin_width);
+    if bin_width.is_some() {
+        let bins: Vec<(&str, f64, usize)> = rows
+            .iter()
+            .flat_map(|row| {
+                row.histogram
+                    .iter()
+                    .map(|&(value, count)| (row.city.as_str(), value, count))
+            })
+            .collect();
+        let cities: Vec<&str> = bins.iter().map(|bin| bin.0).collect();
+        let values: Vec<f64> = bins.iter().map(|bin| bin.1).collect();
+        let counts: Vec<i32> = bins.iter().map(|bin| bin.2 as i32).collect();
+        return Ok(list!(City = cities, Value = values, Count = counts));
+    }
+    // 画素がない集計キーの平均・最小・最大は NA にする
+    let stat = |value: fn(&zonal::ZonalRow) -> f64| -> Vec<Rfloat> {
+        rows.iter()
+            .map(|row| {
+                if row.count > 0 {
+                    Rfloat::from(value(row))
+                } else {
+                    Rfloat::na()
+                }
+            })
+            .collect()
+    };
+    let cities: Vec<String> = rows.iter().map(|row| row.city.clone()).collect();
+    let counts: Vec<i32> = rows.iter().map(|row| row.count as i32).collect();
+    let sums: Vec<f64> = rows.iter().map(|row| row.sum).collect();
+    Ok(list!(
+        City = cities,
+        Count = counts,
+        Sum = sums,
+        Mean = stat(|row| row.mean().unwrap()),
+        Min = stat(|row| row.min),
+        Max = stat(|row| row.max)
+    ))
+}
+
+fn pipeline(
+    path: &str,
+    group_by: &str,
+    metric: &str,
+    filter: Nullable<String>,
+) -> Result<Pipeline> {
+    let mut pipeline = Pipeline::read(path)
+        .group_by(group_by)
+        .metric(parse_metric(metric).map_err(fail)?);
+    if let Nullable::NotNull(filter) = filter {
+        pipeline = pipeline.filter(filter);
+    }
+    Ok(pipeline)
+}
+
+/// `area`, `geodesic-area` または投影法の名前
+fn parse_metric(name: &str) -> std::result::Result<Metric, String> {
+    match name {
+        "area" => Ok(Metric::Area),
+        "geodesic-area" => Ok(Metric::GeodesicArea),
+        "length" => Ok(Metric::GeodesicLength),
+        _ => Projection::parse(name)
+            .map(Metric::Projected)
+            .map_err(|_| format!("不明な集計値です: {}", name)),
+    }
+}
+
+/// layon のエラーを R のエラーにする
+fn fail(err: impl Display) -> Error {
+    Error::Other(err.to_string())
+}
+
+extendr_module! {
+    mod layon;
+    fn aggregate_rows;
+    fn dissolve_geojson;
+    fn zonal_rows;
+}
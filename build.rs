@@ -0,0 +1,14 @@
+//! grpc フィーチャーを有効にしたときに、proto/layon.proto から gRPC のメッセージとサービスのコードを生成する。
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/layon.proto");
+        let files = protox::compile(["proto/layon.proto"], ["proto"])
+            .expect("proto/layon.proto を解析できません");
+        tonic_build::configure()
+            .compile_fds(files)
+            .expect("gRPC のコードを生成できません");
+    }
+}
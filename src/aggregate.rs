@@ -0,0 +1,701 @@
+use crate::{
+    area,
+    cancel::{CancellationToken, Cancelled},
+    flat::FlatPolygons,
+    geometry_type::{GeometryType, TypeCounts, TypeTotals},
+    ids,
+    numeric::{Accumulator, NumericAggregate},
+    progress::Progress,
+    schedule::{self, Schedule},
+    source::LAYER_PROPERTY,
+    timing::{Stage, Timings},
+};
+use geo::{BooleanOps, Geometry, MultiPolygon};
+use geojson::{Feature, FeatureCollection, JsonObject, JsonValue};
+use rayon::prelude::*; // 並列処理用
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// `Metric::Projected` の投影法
+pub use crate::projection::Projection;
+
+/// 集計キー（市町村など）ごとの集計結果。
+/// serde で JSON などに書き出せる（ジオメトリは含めない）
+#[derive(Serialize, Deserialize)]
+pub struct GroupResult {
+    /// 集計キーの値（市町村名など）
+    pub key: String,
+    /// 入力のレイヤー（`Extras::layers` を指定した場合のみ。レイヤーと集計キーの組ごとに集計する）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+    /// 面積の合計（単位は集計した `Metric` による）
+    pub area: f64,
+    /// 集計した Feature の数
+    pub count: usize,
+    /// 市町村内のポリゴンを結合（ディゾルブ）したジオメトリ。
+    /// 出力先がジオメトリを必要とする場合のみ計算する。
+    #[serde(skip)]
+    pub geometry: Option<MultiPolygon<f64>>,
+    /// 集計した Feature の ID（入力の順）。`Extras::ids` を指定した場合のみ集める
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ids: Vec<String>,
+    /// 数値のプロパティの集計値（列の名前と値。`Extras::numeric` の順）。値を持つ Feature がなければ None。
+    /// `Extras::geometry_types` を指定した場合は、その後ろに種類別の数と線の長さ（`geometry_type::COLUMNS`）
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "serialize_values",
+        deserialize_with = "deserialize_pairs"
+    )]
+    pub values: Vec<(String, Option<f64>)>,
+    /// 引き継いだプロパティの値（プロパティと値。`Pipeline::keep` の順）
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "serialize_kept",
+        deserialize_with = "deserialize_pairs"
+    )]
+    pub kept: Vec<(String, String)>,
+    /// 階級分けの名前（`Pipeline::classify` を指定した場合のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+}
+
+/// 集計値を `{"pop_sum": 123.0, ...}` の形で書き出す（指定した順のまま）
+fn serialize_values<S: Serializer>(
+    values: &[(String, Option<f64>)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(values.iter().map(|(name, value)| (name, value)))
+}
+
+fn serialize_kept<S: Serializer>(
+    kept: &[(String, String)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(kept.iter().map(|(name, value)| (name, value)))
+}
+
+/// `{"pop_sum": 123.0, ...}` の形の集計値や引き継いだプロパティを、書かれた順のまま読み込む
+fn deserialize_pairs<'de, D: Deserializer<'de>, V: Deserialize<'de>>(
+    deserializer: D,
+) -> Result<Vec<(String, V)>, D::Error> {
+    struct Pairs<V>(std::marker::PhantomData<V>);
+
+    impl<'de, V: Deserialize<'de>> Visitor<'de> for Pairs<V> {
+        type Value = Vec<(String, V)>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("名前と値のマップ")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut pairs = Vec::with_capacity(map.size_hint().unwrap_or(0));
+            while let Some(pair) = map.next_entry()? {
+                pairs.push(pair);
+            }
+            Ok(pairs)
+        }
+    }
+
+    deserializer.deserialize_map(Pairs(std::marker::PhantomData))
+}
+
+/// 面積と数のほかに集めるもの
+#[derive(Clone, Default)]
+pub struct Extras {
+    /// ディゾルブしたジオメトリ
+    pub geometry: bool,
+    /// Feature の ID（ID のない Feature は位置から作る）
+    pub ids: bool,
+    /// 数値のプロパティの集計
+    pub numeric: Vec<NumericAggregate>,
+    /// ジオメトリの種類ごとの Feature の数と線の長さ
+    pub geometry_types: bool,
+    /// Feature の `source::LAYER_PROPERTY` のレイヤーごとに分けて集計する
+    pub layers: bool,
+}
+
+/// レイヤーごとに分けて集計する場合の、集計用の HashMap のキーのレイヤーと集計キーの区切り
+const LAYER_SEPARATOR: char = '\u{1f}';
+
+/// 集計する値
+#[derive(Clone, Copy, PartialEq)]
+pub enum Metric {
+    /// 座標の単位のままの平面上の面積（経度・緯度なら「度²」）
+    Area,
+    /// 楕円体（WGS84）上の面積 (km²)。座標は経度・緯度であること
+    GeodesicArea,
+    /// 投影した平面上の面積 (km²)。座標は経度・緯度であること
+    Projected(Projection),
+    /// 線（LineString / MultiLineString）の大円距離の長さ (km)。道路や河川のデータ向けで、ポリゴンの周長は含めない。
+    /// 座標は経度・緯度であること
+    GeodesicLength,
+}
+
+/// FeatureCollection を `group_by` のプロパティ（既定は市町村名 N03_004）ごとに集計し、面積の降順で返す
+pub fn aggregate(
+    collection: &FeatureCollection,
+    group_by: &str,
+    with_geometry: bool,
+) -> Vec<GroupResult> {
+    // 中断しないトークンなので Err にはならない
+    match aggregate_with(
+        collection,
+        group_by,
+        with_geometry,
+        Metric::Area,
+        Schedule::ByCost,
+        &CancellationToken::new(),
+    ) {
+        Ok(rows) => rows,
+        Err(_) => unreachable!(),
+    }
+}
+
+/// 集計する値と並列処理の分け方を指定して集計する。
+/// `cancel` が中断されると、残りのチャンク（`Uniform` では Feature）を処理せずに `Cancelled` を返す
+pub fn aggregate_with(
+    collection: &FeatureCollection,
+    group_by: &str,
+    with_geometry: bool,
+    metric: Metric,
+    schedule: Schedule,
+    cancel: &CancellationToken,
+) -> Result<Vec<GroupResult>, Cancelled> {
+    let extras = Extras {
+        geometry: with_geometry,
+        ..Extras::default()
+    };
+    let aggregation = aggregate_until(collection, group_by, &extras, metric, schedule, cancel);
+    match aggregation.cancelled {
+        Some(cancelled) => Err(cancelled),
+        None => Ok(aggregation.rows),
+    }
+}
+
+/// 中断された場合も含めた集計結果
+pub struct Aggregation {
+    /// 集計結果（面積の降順）。中断された場合は処理し終えたチャンクの分だけ
+    pub rows: Vec<GroupResult>,
+    /// 集計した Feature の数
+    pub processed: usize,
+    /// 中断されて一部の Feature だけの結果になった場合、その理由
+    pub cancelled: Option<Cancelled>,
+    /// 変換（convert）、計算（compute）、集約（reduce）にかかった時間
+    pub timings: Timings,
+    /// 集計した Feature のジオメトリの種類ごとの数
+    pub types: TypeCounts,
+}
+
+/// `aggregate_with` と同じく集計し、中断された場合はそれまでに処理し終えたチャンクの結果を返す。
+/// `extras` で指定したもの（ジオメトリ、Feature の ID、数値のプロパティの集計値）もグループごとに集める
+pub fn aggregate_until(
+    collection: &FeatureCollection,
+    group_by: &str,
+    extras: &Extras,
+    metric: Metric,
+    schedule: Schedule,
+    cancel: &CancellationToken,
+) -> Aggregation {
+    aggregate_observed(
+        collection,
+        group_by,
+        extras,
+        metric,
+        schedule,
+        cancel,
+        &Progress::default(),
+    )
+}
+
+/// `aggregate_until` と同じく集計し、チャンクを処理し終えるたびに `progress` に Feature の数を伝える
+pub fn aggregate_observed(
+    collection: &FeatureCollection,
+    group_by: &str,
+    extras: &Extras,
+    metric: Metric,
+    schedule: Schedule,
+    cancel: &CancellationToken,
+    progress: &Progress,
+) -> Aggregation {
+    let table = Table::new(group_by, extras, metric);
+
+    // 各 Feature を並列に処理（処理し終えた Feature の数を数える）
+    // 座標はスレッドごとに使い回す平らなバッファに読み込む（geojson の値を複製・変換しない）
+    let processed = AtomicUsize::new(0);
+    match schedule {
+        Schedule::Uniform => collection.features.par_iter().enumerate().for_each_init(
+            FlatPolygons::default,
+            |flat, (position, feature)| {
+                if !cancel.is_cancelled() {
+                    table.add(flat, position, feature);
+                    processed.fetch_add(1, Ordering::Relaxed);
+                    progress.advance(1);
+                }
+            },
+        ),
+        // 頂点数で分けたチャンクを 1 つずつ別のタスクにする（小さなチャンクをまとめさせない）
+        Schedule::ByCost => {
+            // チャンクは入力の順に連続しているので、長さを足していけば先頭の位置がわかる
+            let chunks = schedule::chunks_by_cost(&collection.features);
+            let starts: Vec<usize> = chunks
+                .iter()
+                .scan(0, |start, chunk| {
+                    let current = *start;
+                    *start += chunk.len();
+                    Some(current)
+                })
+                .collect();
+            chunks
+                .into_par_iter()
+                .zip(starts)
+                .with_max_len(1)
+                .for_each_init(FlatPolygons::default, |flat, (chunk, start)| {
+                    if cancel.is_cancelled() {
+                        return;
+                    }
+                    for (i, feature) in chunk.iter().enumerate() {
+                        table.add(flat, start + i, feature);
+                    }
+                    processed.fetch_add(chunk.len(), Ordering::Relaxed);
+                    progress.advance(chunk.len());
+                })
+        }
+    }
+    let processed = processed.into_inner();
+    // 最後のチャンクの後で中断された場合は、すべて集計できているので中断とみなさない
+    let cancelled = if processed < collection.features.len() {
+        cancel.check().err()
+    } else {
+        None
+    };
+    let (rows, types, timings) = table.finish();
+    Aggregation {
+        rows,
+        processed,
+        cancelled,
+        timings,
+        types,
+    }
+}
+
+/// 集計キーごとの途中の集計値。複数のスレッドから Feature を加え（`add`）、
+/// 別に集めた表と足し合わせて（`merge`）、最後に集計結果にする（`finish`）
+pub(crate) struct Table<'a> {
+    group_by: &'a str,
+    extras: &'a Extras,
+    metric: Metric,
+    // Mutex は複数のスレッドから安全にデータにアクセスするための同期プリミティブ
+    /// 集計キー -> (面積, Feature の数)
+    areas: Mutex<HashMap<String, (f64, usize)>>,
+    /// ディゾルブ用に集めるポリゴン
+    geometries: Mutex<HashMap<String, Vec<MultiPolygon<f64>>>>,
+    /// 集計キー -> (入力の中での位置, ID)。並列に処理するため、最後に位置の順に並べ直す
+    ids: Mutex<HashMap<String, Vec<(usize, String)>>>,
+    /// 数値のプロパティの途中の集計値（`extras.numeric` の順）
+    numeric: Mutex<HashMap<String, Vec<Accumulator>>>,
+    /// ジオメトリの種類別の数と線の長さ
+    type_totals: Mutex<HashMap<String, TypeTotals>>,
+    /// 面積を持たない Feature を知らせるために、全体でもジオメトリの種類ごとに数える
+    type_counts: Mutex<TypeCounts>,
+    /// 変換と計算の時間（全スレッドの合計、ナノ秒）
+    convert_nanos: AtomicU64,
+    compute_nanos: AtomicU64,
+    /// `merge` で足し合わせた時間
+    reduce: Duration,
+}
+
+impl<'a> Table<'a> {
+    pub(crate) fn new(group_by: &'a str, extras: &'a Extras, metric: Metric) -> Table<'a> {
+        Table {
+            group_by,
+            extras,
+            metric,
+            areas: Mutex::default(),
+            geometries: Mutex::default(),
+            ids: Mutex::default(),
+            numeric: Mutex::default(),
+            type_totals: Mutex::default(),
+            type_counts: Mutex::default(),
+            convert_nanos: AtomicU64::new(0),
+            compute_nanos: AtomicU64::new(0),
+            reduce: Duration::ZERO,
+        }
+    }
+
+    /// 入力の中で `position` 番目の Feature の面積などを加える（`flat` は座標を読み込むバッファ）
+    pub(crate) fn add(&self, flat: &mut FlatPolygons, position: usize, feature: &Feature) {
+        self.add_with(flat, position, feature, |flat| measure(flat, self.metric))
+    }
+
+    /// `add` と同じく加え、面積は読み込んだポリゴンから `measure` で求める
+    pub(crate) fn add_with(
+        &self,
+        flat: &mut FlatPolygons,
+        position: usize,
+        feature: &Feature,
+        measure: impl FnOnce(&FlatPolygons) -> f64,
+    ) {
+        let elapsed = |start: Instant| start.elapsed().as_nanos() as u64;
+        let Some(geometry) = &feature.geometry else {
+            self.type_counts.lock().unwrap().add(GeometryType::Empty);
+            return;
+        };
+        let start = Instant::now();
+        flat.load(&geometry.value);
+        self.convert_nanos
+            .fetch_add(elapsed(start), Ordering::Relaxed);
+        if !flat.is_complete() {
+            self.type_counts.lock().unwrap().invalid += 1;
+            return;
+        }
+        let start = Instant::now();
+        let area = measure(flat);
+        self.compute_nanos
+            .fetch_add(elapsed(start), Ordering::Relaxed);
+        self.type_counts.lock().unwrap().add(GeometryType::of(flat));
+
+        // 市町村名を取得して面積を集計
+        let Some(properties) = &feature.properties else {
+            return;
+        };
+        let Some(city_name_str) = properties.get(self.group_by).and_then(JsonValue::as_str) else {
+            return;
+        };
+        let extras = self.extras;
+        // レイヤーごとに分ける場合は、レイヤーの名前と集計キーをつないだものをキーにする
+        let city_name_str = &map_key(properties, city_name_str, extras.layers);
+        // 面積を集計（スレッドセーフに更新）
+        let mut map = self.areas.lock().unwrap();
+        let entry = map.entry(city_name_str.to_string()).or_insert((0.0, 0));
+        entry.0 += area;
+        entry.1 += 1;
+        drop(map);
+
+        if !extras.numeric.is_empty() {
+            let mut map = self.numeric.lock().unwrap();
+            let accumulators = map
+                .entry(city_name_str.to_string())
+                .or_insert_with(|| vec![Accumulator::default(); extras.numeric.len()]);
+            for (aggregate, accumulator) in extras.numeric.iter().zip(accumulators) {
+                if let Some(value) = aggregate.value(feature) {
+                    accumulator.add(value, area);
+                }
+            }
+        }
+
+        if extras.geometry_types {
+            let mut map = self.type_totals.lock().unwrap();
+            map.entry(city_name_str.to_string())
+                .or_default()
+                .add(flat, self.metric);
+        }
+
+        if extras.ids {
+            let id = ids::text_or_position(feature, position);
+            let mut map = self.ids.lock().unwrap();
+            map.entry(city_name_str.to_string())
+                .or_default()
+                .push((position, id));
+        }
+
+        if extras.geometry {
+            let start = Instant::now();
+            let polygons = flat.to_multi_polygon();
+            self.convert_nanos
+                .fetch_add(elapsed(start), Ordering::Relaxed);
+            if let Some(polygons) = polygons {
+                let mut map = self.geometries.lock().unwrap();
+                map.entry(city_name_str.to_string())
+                    .or_default()
+                    .push(polygons);
+            }
+        }
+    }
+
+    /// 別に集めた表（同じ集計キーのプロパティと `Extras` のもの）を足し合わせる
+    pub(crate) fn merge(&mut self, other: Table) {
+        let start = Instant::now();
+        let areas = self.areas.get_mut().unwrap();
+        for (key, (area, count)) in other.areas.into_inner().unwrap() {
+            let entry = areas.entry(key).or_insert((0.0, 0));
+            entry.0 += area;
+            entry.1 += count;
+        }
+        let geometries = self.geometries.get_mut().unwrap();
+        for (key, polygons) in other.geometries.into_inner().unwrap() {
+            geometries.entry(key).or_default().extend(polygons);
+        }
+        let ids = self.ids.get_mut().unwrap();
+        for (key, list) in other.ids.into_inner().unwrap() {
+            ids.entry(key).or_default().extend(list);
+        }
+        let numeric = self.numeric.get_mut().unwrap();
+        for (key, accumulators) in other.numeric.into_inner().unwrap() {
+            match numeric.get_mut(&key) {
+                Some(merged) => {
+                    for (merged, accumulator) in merged.iter_mut().zip(accumulators) {
+                        merged.merge(&accumulator);
+                    }
+                }
+                None => {
+                    numeric.insert(key, accumulators);
+                }
+            }
+        }
+        let type_totals = self.type_totals.get_mut().unwrap();
+        for (key, totals) in other.type_totals.into_inner().unwrap() {
+            type_totals.entry(key).or_default().merge(&totals);
+        }
+        self.type_counts
+            .get_mut()
+            .unwrap()
+            .merge(other.type_counts.into_inner().unwrap());
+        *self.convert_nanos.get_mut() += other.convert_nanos.into_inner();
+        *self.compute_nanos.get_mut() += other.compute_nanos.into_inner();
+        self.reduce += other.reduce + start.elapsed();
+    }
+
+    /// 集計結果（面積の降順）と、ジオメトリの種類ごとの数、変換・計算・集約にかかった時間
+    pub(crate) fn finish(self) -> (Vec<GroupResult>, TypeCounts, Timings) {
+        let extras = self.extras;
+        let reduce_start = Instant::now();
+        // Mutexから取り出し、ベクターに変換して面積でソートする
+        // HashMap は順序が保証されていないため、Vec に変換してソートする
+        let mut geometries = self.geometries.into_inner().unwrap();
+        let mut id_lists = self.ids.into_inner().unwrap();
+        let mut numeric_values = self.numeric.into_inner().unwrap();
+        let mut type_totals = self.type_totals.into_inner().unwrap();
+        let mut sorted_areas: Vec<GroupResult> = self
+            .areas
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|(k, (area, count))| GroupResult {
+                key: match k.split_once(LAYER_SEPARATOR) {
+                    Some((_, key)) if extras.layers => key.to_string(),
+                    _ => k.clone(),
+                },
+                layer: extras
+                    .layers
+                    .then(|| {
+                        k.split_once(LAYER_SEPARATOR)
+                            .map(|(layer, _)| layer.to_string())
+                    })
+                    .flatten(),
+                area,
+                count,
+                geometry: geometries.remove(&k).map(dissolve),
+                ids: id_lists
+                    .remove(&k)
+                    .map(|mut ids| {
+                        ids.sort_unstable_by_key(|&(position, _)| position);
+                        ids.into_iter().map(|(_, id)| id).collect()
+                    })
+                    .unwrap_or_default(),
+                values: numeric_values
+                    .remove(&k)
+                    .map(|accumulators| {
+                        extras
+                            .numeric
+                            .iter()
+                            .zip(accumulators)
+                            .map(|(aggregate, accumulator)| {
+                                (aggregate.column(), accumulator.result(aggregate.function))
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default()
+                    .into_iter()
+                    .chain(
+                        type_totals
+                            .remove(&k)
+                            .map(|t| t.values())
+                            .unwrap_or_default(),
+                    )
+                    .collect(),
+                kept: Vec::new(),
+                class: None,
+            })
+            .collect();
+
+        // 面積で降順にソート（レイヤーごとに分けた場合はレイヤーの名前の順に、その中で面積の降順）
+        sorted_areas.sort_by(|a, b| a.layer.cmp(&b.layer).then(b.area.total_cmp(&a.area)));
+
+        let mut timings = Timings::default();
+        let nanos = |total: AtomicU64| Duration::from_nanos(total.into_inner());
+        timings.record(Stage::Convert, nanos(self.convert_nanos));
+        timings.record(Stage::Compute, nanos(self.compute_nanos));
+        timings.record(Stage::Reduce, self.reduce + reduce_start.elapsed());
+        (
+            sorted_areas,
+            self.type_counts.into_inner().unwrap(),
+            timings,
+        )
+    }
+}
+
+/// 集計用の HashMap のキー（`layers` なら `レイヤー LAYER_SEPARATOR 集計キー`）
+pub(crate) fn map_key(properties: &JsonObject, key: &str, layers: bool) -> String {
+    if !layers {
+        return key.to_string();
+    }
+    let layer = properties.get(LAYER_PROPERTY).and_then(JsonValue::as_str);
+    format!("{}{}{}", layer.unwrap_or_default(), LAYER_SEPARATOR, key)
+}
+
+/// `map_key` で作ったキーをレイヤーと集計キーに分ける
+pub(crate) fn split_map_key(key: String, layers: bool) -> (Option<String>, String) {
+    match key.split_once(LAYER_SEPARATOR) {
+        Some((layer, name)) if layers => (Some(layer.to_string()), name.to_string()),
+        _ => (None, key),
+    }
+}
+
+/// 1 つの Feature の面積（単位は `metric` による。ジオメトリがなければ 0）
+pub fn feature_area(feature: &Feature, metric: Metric) -> f64 {
+    let Some(geometry) = &feature.geometry else {
+        return 0.0;
+    };
+    let mut flat = FlatPolygons::default();
+    flat.load(&geometry.value);
+    measure(&flat, metric)
+}
+
+/// 読み込んだポリゴンの面積
+pub(crate) fn measure(flat: &FlatPolygons, metric: Metric) -> f64 {
+    match metric {
+        Metric::Area => flat.unsigned_area(),
+        Metric::GeodesicArea => flat.to_multi_polygon().map_or(0.0, |polygons| {
+            polygons.iter().map(area::geodesic_area).sum::<f64>() / 1e6
+        }),
+        Metric::Projected(projection) => projection.area(flat) / 1e6,
+        Metric::GeodesicLength => flat.geodesic_length(),
+    }
+}
+
+/// ポリゴン系のジオメトリを MultiPolygon に変換する。
+/// GeometryCollection は入れ子になったものも含めてメンバーのポリゴンを集める（ポリゴンがなければ None）
+pub fn to_multi_polygon(geometry: Geometry<f64>) -> Option<MultiPolygon<f64>> {
+    match geometry {
+        Geometry::Polygon(polygon) => Some(MultiPolygon::new(vec![polygon])),
+        Geometry::MultiPolygon(polygons) => Some(polygons),
+        Geometry::GeometryCollection(geometries) => {
+            let polygons: Vec<_> = geometries
+                .into_iter()
+                .filter_map(to_multi_polygon)
+                .flatten()
+                .collect();
+            (!polygons.is_empty()).then(|| MultiPolygon::new(polygons))
+        }
+        _ => None,
+    }
+}
+
+/// 複数の MultiPolygon を結合して 1 つにする（並列にペアごとに union していく）
+fn dissolve(polygons: Vec<MultiPolygon<f64>>) -> MultiPolygon<f64> {
+    polygons
+        .into_par_iter()
+        .reduce(|| MultiPolygon::new(vec![]), |a, b| a.union(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geojson::{Geometry, JsonObject, Value};
+
+    fn feature(city: &str, ring: Vec<Vec<f64>>) -> Feature {
+        let mut properties = JsonObject::new();
+        properties.insert("N03_004".to_string(), city.into());
+        Feature {
+            geometry: Some(Geometry::new(Value::Polygon(vec![ring]))),
+            properties: Some(properties),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn features_with_short_positions_are_counted_and_skipped() {
+        let square = vec![
+            vec![0.0, 0.0],
+            vec![1.0, 0.0],
+            vec![1.0, 1.0],
+            vec![0.0, 1.0],
+            vec![0.0, 0.0],
+        ];
+        let mut broken = square.clone();
+        broken[2] = vec![1.0];
+        let collection = FeatureCollection {
+            bbox: None,
+            features: vec![feature("A", square), feature("B", broken)],
+            foreign_members: None,
+        };
+        let aggregation = aggregate_until(
+            &collection,
+            "N03_004",
+            &Extras::default(),
+            Metric::Area,
+            Schedule::ByCost,
+            &CancellationToken::new(),
+        );
+        assert_eq!(aggregation.types.invalid, 1);
+        assert_eq!(aggregation.types.polygons, 1);
+        assert_eq!(aggregation.rows.len(), 1);
+        assert_eq!(aggregation.rows[0].key, "A");
+        assert!((aggregation.rows[0].area - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn group_results_round_trip_through_json() {
+        let row = GroupResult {
+            key: "川越市".to_string(),
+            layer: Some("boundaries".to_string()),
+            area: 109.13,
+            count: 2,
+            geometry: None,
+            ids: vec!["11201-1".to_string(), "11201-2".to_string()],
+            values: vec![
+                ("pop_sum".to_string(), Some(352_000.0)),
+                ("households_mean".to_string(), None),
+            ],
+            kept: vec![
+                ("N03_003".to_string(), String::new()),
+                ("N03_001".to_string(), "埼玉県".to_string()),
+            ],
+            class: Some("100km²以上".to_string()),
+        };
+        let json = serde_json::to_string(&row).unwrap();
+        assert!(
+            json.contains(r#""values":{"pop_sum":352000.0,"households_mean":null}"#),
+            "{}",
+            json
+        );
+
+        let read: GroupResult = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.key, row.key);
+        assert_eq!(read.layer, row.layer);
+        assert_eq!(read.area, row.area);
+        assert_eq!(read.count, row.count);
+        assert_eq!(read.ids, row.ids);
+        // 名前の順ではなく、書き出した順のまま読み込む
+        assert_eq!(read.values, row.values);
+        assert_eq!(read.kept, row.kept);
+        assert_eq!(read.class, row.class);
+
+        // 集計値などのない行は、その項目を省いて書き出し、空のまま読み込む
+        let read: GroupResult =
+            serde_json::from_str(r#"{"key":"小川町","area":60.36,"count":1}"#).unwrap();
+        assert!(read.values.is_empty() && read.kept.is_empty() && read.ids.is_empty());
+    }
+}
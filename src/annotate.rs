@@ -0,0 +1,177 @@
+//! 入力の Feature に計算した値をプロパティとして書き足す（`layon annotate`）。
+//! 集計表ではなく、元の GeoJSON に面積と周長が付いたものを QGIS などで使いたい場合に。
+
+use crate::{
+    aggregate::{self, Metric},
+    flat::FlatPolygons,
+};
+use geo::{EuclideanLength, GeodesicLength, LineString, MultiPolygon};
+use geojson::{Feature, FeatureCollection, JsonObject, JsonValue};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// 面積を入れるプロパティ
+pub const AREA: &str = "area";
+/// 周長を入れるプロパティ
+pub const PERIMETER: &str = "perimeter";
+/// グループの面積の合計を入れるプロパティ
+pub const GROUP_AREA: &str = "group_area";
+
+/// 各 Feature に面積（`area`）と周長（`perimeter`）を書き足す。
+/// 単位は `metric` による（`Area` なら座標の単位、`GeodesicArea` なら km² と km）。
+/// `group_by` を指定すると、同じ値を持つ Feature の面積の合計（`group_area`）も書き足す
+pub fn annotate(collection: &mut FeatureCollection, metric: Metric, group_by: Option<&str>) {
+    let areas: Vec<f64> = collection
+        .features
+        .par_iter_mut()
+        .map_init(FlatPolygons::default, |flat, feature| {
+            let (area, perimeter) = measure(flat, feature, metric);
+            let properties = feature.properties.get_or_insert_with(JsonObject::new);
+            properties.insert(AREA.to_string(), JsonValue::from(area));
+            properties.insert(PERIMETER.to_string(), JsonValue::from(perimeter));
+            area
+        })
+        .collect();
+
+    let Some(group_by) = group_by else {
+        return;
+    };
+    let mut totals = HashMap::<String, f64>::new();
+    for (feature, area) in collection.features.iter().zip(&areas) {
+        if let Some(key) = group_key(feature, group_by) {
+            *totals.entry(key.to_string()).or_default() += area;
+        }
+    }
+    collection.features.par_iter_mut().for_each(|feature| {
+        let total = group_key(feature, group_by).and_then(|key| totals.get(key));
+        if let (Some(&total), Some(properties)) = (total, feature.properties.as_mut()) {
+            properties.insert(GROUP_AREA.to_string(), JsonValue::from(total));
+        }
+    });
+}
+
+/// 集計キーの値（文字列でなければ None）
+fn group_key<'a>(feature: &'a Feature, group_by: &str) -> Option<&'a str> {
+    feature.properties.as_ref()?.get(group_by)?.as_str()
+}
+
+/// 面積と、外周と穴の長さの合計（ポリゴンでなければどちらも 0）
+fn measure(flat: &mut FlatPolygons, feature: &Feature, metric: Metric) -> (f64, f64) {
+    let Some(geometry) = &feature.geometry else {
+        return (0.0, 0.0);
+    };
+    flat.load(&geometry.value);
+    let perimeter = flat
+        .to_multi_polygon()
+        .map_or(0.0, |polygons| rings_length(&polygons, metric));
+    (aggregate::measure(flat, metric), perimeter)
+}
+
+fn rings_length(polygons: &MultiPolygon<f64>, metric: Metric) -> f64 {
+    let length = |ring: &LineString<f64>| match metric {
+        Metric::Area => ring.euclidean_length(),
+        // m を km にする
+        // 投影しても周長はほとんど変わらないため、楕円体上の長さにする
+        Metric::GeodesicArea | Metric::Projected(_) | Metric::GeodesicLength => {
+            ring.geodesic_length() / 1e3
+        }
+    };
+    polygons
+        .iter()
+        .flat_map(|polygon| std::iter::once(polygon.exterior()).chain(polygon.interiors()))
+        .map(length)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection(features: &[(JsonValue, &str)]) -> FeatureCollection {
+        let features: Vec<String> = features
+            .iter()
+            .map(|(city, geometry)| {
+                format!(
+                    r#"{{"type":"Feature","properties":{{"city":{}}},"geometry":{}}}"#,
+                    city, geometry
+                )
+            })
+            .collect();
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        )
+        .parse()
+        .unwrap()
+    }
+
+    fn values(collection: &FeatureCollection, property: &str) -> Vec<Option<f64>> {
+        collection
+            .features
+            .iter()
+            .map(|feature| feature.property(property).and_then(JsonValue::as_f64))
+            .collect()
+    }
+
+    #[test]
+    fn areas_perimeters_and_group_totals_are_added() {
+        let features = || {
+            collection(&[
+                // 2 × 2 の正方形から 1 × 1 の穴を除いたもの
+                (
+                    "川越市".into(),
+                    r#"{"type":"Polygon","coordinates":[[[0,0],[2,0],[2,2],[0,2],[0,0]],[[0.5,0.5],[0.5,1.5],[1.5,1.5],[1.5,0.5],[0.5,0.5]]]}"#,
+                ),
+                (
+                    "川越市".into(),
+                    r#"{"type":"Polygon","coordinates":[[[5,5],[6,5],[6,6],[5,6],[5,5]]]}"#,
+                ),
+                // 文字列でない集計キーはグループにしない
+                (
+                    11.into(),
+                    r#"{"type":"Polygon","coordinates":[[[7,7],[8,7],[8,8],[7,8],[7,7]]]}"#,
+                ),
+                ("所沢市".into(), r#"{"type":"Point","coordinates":[3,3]}"#),
+            ])
+        };
+        let mut collection = features();
+        annotate(&mut collection, Metric::Area, Some("city"));
+        assert_eq!(
+            values(&collection, AREA),
+            [Some(3.0), Some(1.0), Some(1.0), Some(0.0)]
+        );
+        // 周長には穴の長さも含める
+        assert_eq!(
+            values(&collection, PERIMETER),
+            [Some(12.0), Some(4.0), Some(4.0), Some(0.0)]
+        );
+        assert_eq!(
+            values(&collection, GROUP_AREA),
+            [Some(4.0), Some(4.0), None, Some(0.0)]
+        );
+        // 元のプロパティは残す
+        assert_eq!(
+            collection.features[0].property("city"),
+            Some(&JsonValue::from("川越市"))
+        );
+
+        let mut collection = features();
+        annotate(&mut collection, Metric::Area, None);
+        assert!(values(&collection, GROUP_AREA).iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn geodesic_metrics_give_square_kilometres_and_kilometres() {
+        let mut collection = collection(&[(
+            "赤道".into(),
+            r#"{"type":"Polygon","coordinates":[[[0,0],[1,0],[1,1],[0,1],[0,0]]]}"#,
+        )]);
+        annotate(&mut collection, Metric::GeodesicArea, Some("city"));
+        let area = values(&collection, AREA)[0].unwrap();
+        let perimeter = values(&collection, PERIMETER)[0].unwrap();
+        // 赤道の経度 1 度はおよそ 111.3 km、経線の 1 度はおよそ 110.6 km
+        assert!((area - 12308.0).abs() < 5.0, "{}", area);
+        assert!((perimeter - 443.7).abs() < 0.5, "{}", perimeter);
+        assert_eq!(values(&collection, GROUP_AREA)[0], Some(area));
+    }
+}
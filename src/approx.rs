@@ -0,0 +1,191 @@
+//! Feature を無作為に抽出した概算の集計（`--approx --sample 0.1`）。
+//!
+//! とても大きなデータで結果の見当をすばやく付けるために、Feature をそれぞれ確率 `fraction` で選んで面積を求め、
+//! 集計キーごとの合計を `1 / fraction` 倍して全体の合計を推定する（Horvitz–Thompson 推定量）。
+//! 分散は選ばれた Feature の値の二乗和から `(1 - fraction) / fraction² × Σ y²` で推定し、
+//! 正規近似の 95% 信頼区間を付ける。選ばれた Feature の分は確かにあるので、区間の下限はその合計より小さくしない。
+//! 選ぶかどうかは `subset::sample` と同じく種と Feature の位置だけで決まる。
+//!
+//! 抽出した Feature が 1 つもない集計キーは結果に現れない。
+//! Feature の数が少ない集計キーほど区間は広く、正規近似も粗くなる。
+
+use crate::{
+    aggregate::{self, Metric},
+    pipeline::Pipeline,
+    subset,
+};
+use std::{collections::HashMap, error::Error};
+
+/// 95% 信頼区間の標準正規分布の分位点
+const Z: f64 = 1.959_963_984_540_054;
+
+/// 抽出の仕方
+#[derive(Clone, Copy, Debug)]
+pub struct Sampling {
+    /// Feature を選ぶ確率（0 より大きく 1 以下）
+    pub fraction: f64,
+    /// 乱数の種
+    pub seed: u64,
+}
+
+/// 集計キーごとの推定値
+pub struct EstimateRow {
+    pub key: String,
+    /// 推定した面積の合計
+    pub area: f64,
+    /// 面積の 95% 信頼区間の半分の幅
+    pub area_margin: f64,
+    /// 推定した Feature の数
+    pub count: f64,
+    /// Feature の数の 95% 信頼区間の半分の幅
+    pub count_margin: f64,
+    /// 抽出した Feature の面積の合計
+    pub sampled_area: f64,
+    /// 抽出した Feature の数
+    pub sampled: usize,
+}
+
+impl EstimateRow {
+    /// 面積の信頼区間
+    pub fn area_interval(&self) -> (f64, f64) {
+        (
+            (self.area - self.area_margin).max(self.sampled_area),
+            self.area + self.area_margin,
+        )
+    }
+
+    /// Feature の数の信頼区間
+    pub fn count_interval(&self) -> (f64, f64) {
+        (
+            (self.count - self.count_margin).max(self.sampled as f64),
+            self.count + self.count_margin,
+        )
+    }
+}
+
+/// 概算の集計結果
+pub struct Approximation {
+    /// 推定した面積の降順
+    pub rows: Vec<EstimateRow>,
+    /// 抽出した Feature の数
+    pub sampled: usize,
+    /// 抽出する前の Feature の数
+    pub total: usize,
+}
+
+/// 集計キーごとの (抽出した面積の合計, 面積の二乗和, 抽出した数)
+type Sums = HashMap<String, (f64, f64, usize)>;
+
+/// `pipeline` の Feature を `sampling` で抽出して `group_by` ごとに集計し、全体の合計を推定する
+pub fn estimate(
+    pipeline: Pipeline,
+    group_by: &str,
+    metric: Metric,
+    sampling: Sampling,
+) -> Result<Approximation, Box<dyn Error>> {
+    let fraction = sampling.fraction;
+    let (sums, sampled, total) = pipeline.map_reduce(
+        |view| {
+            let mut sums = Sums::new();
+            if subset::uniform(sampling.seed, view.index as u64) >= fraction {
+                return (sums, 0, 1);
+            }
+            if let Some(key) = view.text(group_by) {
+                let area = aggregate::feature_area(view.feature, metric);
+                sums.insert(key.to_string(), (area, area * area, 1));
+            }
+            (sums, 1, 1)
+        },
+        || (Sums::new(), 0, 0),
+        |(mut a, a_sampled, a_total), (b, b_sampled, b_total)| {
+            for (key, (area, squares, count)) in b {
+                let sum = a.entry(key).or_insert((0.0, 0.0, 0));
+                sum.0 += area;
+                sum.1 += squares;
+                sum.2 += count;
+            }
+            (a, a_sampled + b_sampled, a_total + b_total)
+        },
+    )?;
+
+    let scale = (1.0 - fraction).max(0.0);
+    let mut rows: Vec<EstimateRow> = sums
+        .into_iter()
+        .map(|(key, (area, squares, count))| EstimateRow {
+            key,
+            area: area / fraction,
+            area_margin: Z * (scale * squares).sqrt() / fraction,
+            count: count as f64 / fraction,
+            count_margin: Z * (scale * count as f64).sqrt() / fraction,
+            sampled_area: area,
+            sampled: count,
+        })
+        .collect();
+    rows.sort_by(|a, b| b.area.total_cmp(&a.area).then_with(|| a.key.cmp(&b.key)));
+    Ok(Approximation {
+        rows,
+        sampled,
+        total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    #[test]
+    fn approx_extrapolates_sampled_totals_with_intervals() {
+        let exact = Pipeline::read(FIXTURE)
+            .metric(Metric::GeodesicArea)
+            .run()
+            .unwrap()
+            .rows;
+
+        // すべての Feature を選べば推定値は正確な合計になり、区間の幅は 0
+        let all = estimate(
+            Pipeline::read(FIXTURE),
+            "N03_004",
+            Metric::GeodesicArea,
+            Sampling {
+                fraction: 1.0,
+                seed: 0,
+            },
+        )
+        .unwrap();
+        assert_eq!(all.sampled, all.total);
+        assert_eq!(all.rows.len(), exact.len());
+        for row in &all.rows {
+            let city = exact.iter().find(|city| city.key == row.key).unwrap();
+            assert!((row.area - city.area).abs() < 1e-9);
+            assert_eq!(row.count, city.count as f64);
+            assert_eq!(row.area_interval(), (row.area, row.area));
+        }
+
+        let half = estimate(
+            Pipeline::read(FIXTURE),
+            "N03_004",
+            Metric::GeodesicArea,
+            Sampling {
+                fraction: 0.5,
+                seed: 7,
+            },
+        )
+        .unwrap();
+        assert!(half.sampled > 0 && half.sampled < half.total);
+        let sampled: usize = half.rows.iter().map(|row| row.sampled).sum();
+        assert_eq!(sampled, half.sampled);
+        for row in &half.rows {
+            assert!((row.area - row.sampled_area * 2.0).abs() < 1e-9);
+            let (low, high) = row.area_interval();
+            assert!(low >= row.sampled_area && low <= row.area && row.area <= high);
+            // 区間の下限は抽出した分より小さくならない
+            let (count_low, _) = row.count_interval();
+            assert!(count_low >= row.sampled as f64);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! ポリゴンの面積の計算（座標の単位のまま、平面上で求める）。
+//! `simd` フィーチャーを有効にすると、頂点の多いリング（海岸線など）は
+//! wide の `f64x4` で 4 つの辺の外積をまとめて計算する（SSE2 / AVX / NEON などに合わせて wide が命令を選ぶ）。
+
+use geo::{Coord, GeodesicArea, LineString, Polygon};
+
+/// ベクトル化した計算を使うリングの頂点数の下限（小さいリングは 1 レーンで十分速い）
+#[cfg(feature = "simd")]
+const LARGE_RING: usize = 64;
+
+/// 一度に計算する頂点の組の数（f64 の 256 ビットレジスタ 1 つ分）
+#[cfg(feature = "simd")]
+const LANES: usize = 4;
+
+/// リングの面積（向きによらず正の値）
+pub fn ring_area(coords: &[Coord<f64>]) -> f64 {
+    (twice_signed_area(coords) / 2.0).abs()
+}
+
+/// geojson の位置の列のリングの符号付き面積（反時計回りなら正）。
+/// `ring_area` と同じく最初の頂点を原点にずらしてから求めるので、経度・緯度の小さいリングでも向きを誤らない
+pub fn signed_position_area(ring: &[Vec<f64>]) -> f64 {
+    let Some(origin) = ring.first() else {
+        return 0.0;
+    };
+    let (ox, oy) = (origin[0], origin[1]);
+    ring.windows(2)
+        .map(|pair| (pair[0][0] - ox) * (pair[1][1] - oy) - (pair[1][0] - ox) * (pair[0][1] - oy))
+        .sum::<f64>()
+        / 2.0
+}
+
+/// 楕円体（WGS84）上のポリゴンの面積 (m²)。
+/// リングの向きによらないように、外周と穴をそれぞれ絶対値で計算する
+pub fn geodesic_area(polygon: &Polygon<f64>) -> f64 {
+    let ring_area = |ring: &LineString<f64>| geodesic_ring_area(&ring.0);
+    ring_area(polygon.exterior()) - polygon.interiors().iter().map(ring_area).sum::<f64>()
+}
+
+/// 楕円体（WGS84）上のリングの面積 (m²。向きによらず正の値)
+pub fn geodesic_ring_area(coords: &[Coord<f64>]) -> f64 {
+    Polygon::new(LineString::from(coords.to_vec()), vec![])
+        .geodesic_area_signed()
+        .abs()
+}
+
+/// リングの符号付き面積の 2 倍。
+/// 桁落ちを防ぐため、geo と同様に最初の頂点を原点にずらしてから外積を足し合わせる
+/// （そのため閉じていないリングでも、最後の頂点から最初の頂点への辺は 0 になり結果は同じ）
+fn twice_signed_area(coords: &[Coord<f64>]) -> f64 {
+    if coords.len() < 3 {
+        return 0.0;
+    }
+    let origin = coords[0];
+    let cross = |a: Coord<f64>, b: Coord<f64>| {
+        (a.x - origin.x) * (b.y - origin.y) - (b.x - origin.x) * (a.y - origin.y)
+    };
+
+    // 辺の数。辺 i は頂点 i と i + 1 を結ぶ
+    let edges = coords.len() - 1;
+    #[cfg(feature = "simd")]
+    if edges >= LARGE_RING {
+        use wide::f64x4;
+        let (ox, oy) = (f64x4::splat(origin.x), f64x4::splat(origin.y));
+        let lane = |at: usize, f: fn(&Coord<f64>) -> f64| -> f64x4 {
+            f64x4::from(std::array::from_fn::<f64, LANES, _>(|k| f(&coords[at + k])))
+        };
+        let mut sum = f64x4::ZERO;
+        let mut i = 0;
+        while i + LANES <= edges {
+            let (ax, ay) = (lane(i, |c| c.x) - ox, lane(i, |c| c.y) - oy);
+            let (bx, by) = (lane(i + 1, |c| c.x) - ox, lane(i + 1, |c| c.y) - oy);
+            sum += ax * by - bx * ay;
+            i += LANES;
+        }
+        let rest: f64 = (i..edges).map(|i| cross(coords[i], coords[i + 1])).sum();
+        return sum.reduce_add() + rest;
+    }
+    (0..edges).map(|i| cross(coords[i], coords[i + 1])).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 経度 139 度・緯度 35 度あたりの約 1 cm 四方のリング
+    fn tiny_square(clockwise: bool) -> Vec<Vec<f64>> {
+        let (x, y, d) = (139.123456789, 35.987654321, 1e-7);
+        let mut ring = vec![
+            vec![x, y],
+            vec![x + d, y],
+            vec![x + d, y + d],
+            vec![x, y + d],
+            vec![x, y],
+        ];
+        if clockwise {
+            ring.reverse();
+        }
+        ring
+    }
+
+    #[test]
+    fn signed_position_area_keeps_precision_and_orientation_of_small_rings() {
+        let area = signed_position_area(&tiny_square(false));
+        assert!((area - 1e-14).abs() < 1e-20, "{}", area);
+        assert!(signed_position_area(&tiny_square(true)) < 0.0);
+        let coords: Vec<Coord<f64>> = tiny_square(false)
+            .iter()
+            .map(|p| Coord { x: p[0], y: p[1] })
+            .collect();
+        assert!((ring_area(&coords) - area).abs() < 1e-20);
+        assert_eq!(signed_position_area(&[]), 0.0);
+    }
+
+    /// 頂点の多いリング（`simd` フィーチャーではベクトルで計算する）も辺ごとに足した面積と一致する
+    #[test]
+    fn large_rings_match_the_edge_by_edge_sum() {
+        let coords: Vec<Coord<f64>> = (0..=1001)
+            .map(|i| {
+                let angle = i as f64 / 1001.0 * std::f64::consts::TAU;
+                let radius = 0.5 + 0.05 * (angle * 37.0).sin();
+                Coord {
+                    x: 139.5 + radius * angle.cos(),
+                    y: 35.9 + radius * angle.sin(),
+                }
+            })
+            .collect();
+        let expected: f64 = coords
+            .windows(2)
+            .map(|pair| {
+                let (a, b) = (pair[0] - coords[0], pair[1] - coords[0]);
+                a.x * b.y - b.x * a.y
+            })
+            .sum::<f64>()
+            / 2.0;
+        let area = ring_area(&coords);
+        assert!(
+            (area - expected.abs()).abs() < 1e-12 * area,
+            "{} {}",
+            area,
+            expected
+        );
+    }
+}
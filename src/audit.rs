@@ -0,0 +1,174 @@
+//! 集計に含めなかった Feature と、ジオメトリを変えた Feature の記録（`--audit-log`）。
+//!
+//! 前処理では、条件式や集計キーの一覧で外した Feature、重複として取り除いた Feature、
+//! 自己交差を修復した Feature、別のレイヤーを差し引いた Feature が集計結果から見えなくなる。
+//! どの Feature がなぜ外れたか、面積がどれだけ変わったかを 1 行ずつ残し、集計結果を後から確かめられるようにする。
+
+use crate::{
+    aggregate::{self, Metric},
+    dedup::Duplicate,
+    filter::KeyFilter,
+    ids,
+};
+use geojson::{Feature, FeatureCollection, JsonValue};
+use rayon::prelude::*;
+use std::fmt;
+
+/// Feature に対して行ったこと
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// 集計に含めなかった
+    Skipped,
+    /// 自己交差を修復した（`make_valid`）
+    Repaired,
+    /// 別のレイヤーを差し引いた（`subtract`）
+    Clipped,
+    /// 重複として取り除いた（`dedup`）
+    Deduplicated,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Action::Skipped => "skipped",
+            Action::Repaired => "repaired",
+            Action::Clipped => "clipped",
+            Action::Deduplicated => "deduplicated",
+        })
+    }
+}
+
+/// 1 つの Feature の記録（面積の単位は `Metric` による）
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// Feature の ID（なければ入力の中での位置 `#0`, `#1`, …）
+    pub id: String,
+    /// 集計キーの値（なければ空）
+    pub key: String,
+    pub action: Action,
+    /// 理由や詳細
+    pub reason: String,
+    /// 前処理の前の面積
+    pub area_before: f64,
+    /// 前処理の後の面積（集計に含めなかった Feature は None）
+    pub area_after: Option<f64>,
+}
+
+/// 前処理の段階ごとに記録を集める
+pub(crate) struct Audit<'a> {
+    group_by: &'a str,
+    metric: Metric,
+    /// 集計キーのない Feature を外した理由
+    unkeyed: String,
+    pub entries: Vec<Entry>,
+}
+
+impl<'a> Audit<'a> {
+    pub fn new(group_by: &'a str, metric: Metric, unkeyed: String) -> Audit<'a> {
+        Audit {
+            group_by,
+            metric,
+            unkeyed,
+            entries: Vec::new(),
+        }
+    }
+
+    /// すべての Feature の面積（ジオメトリを変える段階の前に測っておく）
+    pub fn areas(&self, collection: &FeatureCollection) -> Vec<f64> {
+        collection
+            .features
+            .par_iter()
+            .map(|feature| aggregate::feature_area(feature, self.metric))
+            .collect()
+    }
+
+    /// 集計に含めなかった Feature を記録する
+    pub fn skip(&mut self, feature: &Feature, reason: impl Into<String>) {
+        let area = aggregate::feature_area(feature, self.metric);
+        let entry = self.entry(feature, Action::Skipped, reason.into(), area, None);
+        self.entries.push(entry);
+    }
+
+    /// 集計キーの一覧で外す Feature を記録する（`KeyFilter::retain` の前に呼ぶ）
+    pub fn keys(&mut self, collection: &FeatureCollection, keys: &KeyFilter) {
+        for feature in &collection.features {
+            match self.key(feature) {
+                None => self.skip(feature, self.unkeyed.clone()),
+                Some(key) => {
+                    if let Some(reason) = keys.rejects(key) {
+                        self.skip(feature, reason);
+                    }
+                }
+            }
+        }
+    }
+
+    /// 前処理の後に残った Feature のうち、集計で読み飛ばすもの（ジオメトリや集計キーがないもの）を記録する
+    pub fn unusable(&mut self, collection: &FeatureCollection) {
+        for feature in &collection.features {
+            if feature.geometry.is_none() {
+                self.skip(feature, "ジオメトリがない");
+            } else if self.key(feature).is_none() {
+                self.skip(feature, self.unkeyed.clone());
+            }
+        }
+    }
+
+    /// `positions` の Feature のジオメトリを変えたことを記録する（面積は `before` と今の値を比べる）
+    pub fn changed(
+        &mut self,
+        collection: &FeatureCollection,
+        positions: &[usize],
+        before: &[f64],
+        action: Action,
+        reason: &str,
+    ) {
+        for &position in positions {
+            let feature = &collection.features[position];
+            let after = aggregate::feature_area(feature, self.metric);
+            let entry = self.entry(
+                feature,
+                action,
+                reason.to_string(),
+                before[position],
+                Some(after),
+            );
+            self.entries.push(entry);
+        }
+    }
+
+    /// 重複として取り除いた Feature を記録する（`before` は重複を除く前の面積）
+    pub fn duplicates(&mut self, duplicates: &[Duplicate], before: &[f64]) {
+        self.entries
+            .extend(duplicates.iter().map(|duplicate| Entry {
+                id: duplicate.id.clone(),
+                key: duplicate.name.clone().unwrap_or_default(),
+                action: Action::Deduplicated,
+                reason: format!("{} と同じ内容", duplicate.original_id),
+                area_before: before[duplicate.index],
+                area_after: None,
+            }));
+    }
+
+    fn entry(
+        &self,
+        feature: &Feature,
+        action: Action,
+        reason: String,
+        area_before: f64,
+        area_after: Option<f64>,
+    ) -> Entry {
+        Entry {
+            id: ids::text(feature).unwrap_or_default(),
+            key: self.key(feature).unwrap_or_default().to_string(),
+            action,
+            reason,
+            area_before,
+            area_after,
+        }
+    }
+
+    fn key<'f>(&self, feature: &'f Feature) -> Option<&'f str> {
+        feature.property(self.group_by).and_then(JsonValue::as_str)
+    }
+}
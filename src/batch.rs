@@ -0,0 +1,191 @@
+//! 複数のファイルをそれぞれ集計するバッチ処理（`layon batch`）。
+//!
+//! 47 都道府県のファイルをまとめて集計するときに、1 つのファイルが壊れていても全体が無駄にならないようにする。
+//! 読み込みや集計に失敗したファイルは、待ち時間を倍にしながら決まった回数まで試し直す
+//! （ネットワーク越しのファイルや PostGIS の一時的なエラー向け）。
+//! 試し直しても失敗したファイルは、`keep_going` なら記録して次のファイルへ進み、そうでなければそこで止める。
+
+use crate::{aggregate::GroupResult, log};
+use std::{
+    error::Error,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// 失敗したファイルを試し直す回数と間隔
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Retry {
+    /// 最初の 1 回の後に試し直す回数
+    pub retries: u32,
+    /// 1 回目の試し直しまでの待ち時間（その後は倍ずつ長くする）
+    pub delay: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            retries: 0,
+            delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Retry {
+    /// `retry` 回目（1 始まり）の試し直しの前に待つ時間
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+    }
+}
+
+/// 1 つのファイルの結果
+pub enum Outcome {
+    /// 集計できた
+    Done(Vec<GroupResult>),
+    /// 試し直しても失敗した（最後のエラー）
+    Failed(String),
+    /// 前のファイルが失敗して止めたため、集計しなかった
+    Skipped,
+}
+
+/// ファイルごとの結果
+pub struct FileResult {
+    /// 入力元
+    pub source: String,
+    pub outcome: Outcome,
+    /// 試した回数（集計しなかったなら 0）
+    pub attempts: u32,
+    /// 試し直しの待ち時間も含めた処理時間
+    pub elapsed: Duration,
+}
+
+impl FileResult {
+    /// 状態の表に書く名前
+    pub fn status(&self) -> &'static str {
+        match self.outcome {
+            Outcome::Done(_) => "完了",
+            Outcome::Failed(_) => "失敗",
+            Outcome::Skipped => "未実行",
+        }
+    }
+}
+
+/// `sources` を順に `aggregate` で集計する
+pub fn run<F>(
+    sources: &[String],
+    retry: Retry,
+    keep_going: bool,
+    mut aggregate: F,
+) -> Vec<FileResult>
+where
+    F: FnMut(&str) -> Result<Vec<GroupResult>, Box<dyn Error>>,
+{
+    let mut results = Vec::with_capacity(sources.len());
+    let mut stopped = false;
+    for source in sources {
+        if stopped {
+            results.push(FileResult {
+                source: source.clone(),
+                outcome: Outcome::Skipped,
+                attempts: 0,
+                elapsed: Duration::ZERO,
+            });
+            continue;
+        }
+        let start = Instant::now();
+        let mut attempts = 0;
+        let outcome = loop {
+            attempts += 1;
+            match aggregate(source) {
+                Ok(rows) => break Outcome::Done(rows),
+                Err(err) if attempts > retry.retries => break Outcome::Failed(err.to_string()),
+                Err(err) => {
+                    let delay = retry.delay(attempts);
+                    log::warning!(
+                        "警告: {} の集計に失敗しました ({} 回目): {}。{:.1} 秒後に試し直します",
+                        source,
+                        attempts,
+                        err,
+                        delay.as_secs_f64()
+                    );
+                    thread::sleep(delay);
+                }
+            }
+        };
+        stopped = matches!(outcome, Outcome::Failed(_)) && !keep_going;
+        results.push(FileResult {
+            source: source.clone(),
+            outcome,
+            attempts,
+            elapsed: start.elapsed(),
+        });
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aggregate::Metric, pipeline::Pipeline};
+    use std::fs;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    #[test]
+    fn batch_retries_failed_files_and_keeps_going_past_them() {
+        let dir = std::env::temp_dir().join(format!("layon-batch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let broken = dir.join("broken.geojson");
+        fs::write(&broken, r#"{"type":"FeatureCollection","features":["#).unwrap();
+        let sources = [
+            FIXTURE.to_string(),
+            broken.to_str().unwrap().to_string(),
+            FIXTURE.to_string(),
+        ];
+        let retry = Retry {
+            retries: 2,
+            delay: std::time::Duration::ZERO,
+        };
+        let aggregate = |source: &str| {
+            Ok(Pipeline::read(source)
+                .group_by("N03_004")
+                .metric(Metric::Area)
+                .run()?
+                .rows)
+        };
+
+        let files = run(&sources, retry, true, aggregate);
+        let statuses: Vec<_> = files.iter().map(|f| (f.status(), f.attempts)).collect();
+        assert_eq!(statuses, [("完了", 1), ("失敗", 3), ("完了", 1)]);
+        let Outcome::Failed(err) = &files[1].outcome else {
+            unreachable!()
+        };
+        assert!(err.contains("broken.geojson"), "{}", err);
+
+        let output = dir.join("batch.csv");
+        crate::sink::csv::write_batch(output.to_str().unwrap(), &files).unwrap();
+        let text = fs::read_to_string(&output).unwrap();
+        let expected = aggregate(FIXTURE).unwrap();
+        assert_eq!(text.lines().count(), 1 + 2 * expected.len());
+        assert!(!text.contains("broken"));
+
+        // --keep-going がなければ失敗したファイルで止め、残りは集計しない
+        let files = run(&sources, retry, false, aggregate);
+        let statuses: Vec<_> = files.iter().map(|f| (f.status(), f.attempts)).collect();
+        assert_eq!(statuses, [("完了", 1), ("失敗", 3), ("未実行", 0)]);
+
+        let backoff = Retry {
+            retries: 3,
+            delay: std::time::Duration::from_millis(100),
+        };
+        assert_eq!(
+            (1..=3)
+                .map(|n| backoff.delay(n).as_millis())
+                .collect::<Vec<_>>(),
+            [100, 200, 400]
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! 塗り分け地図の階級の境界（`layon breaks`）。
+//! グループごとに集計した値から、自然分類（Jenks）、分位数、等間隔のいずれかで境界を求める。
+
+use crate::aggregate::GroupResult;
+
+/// 境界の求め方
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    /// 階級内の分散の合計が最小になるように分ける（Fisher-Jenks の動的計画法）
+    Jenks,
+    /// 各階級のグループの数がほぼ同じになるように分ける
+    Quantile,
+    /// 最小値から最大値までを同じ幅に分ける
+    EqualInterval,
+}
+
+impl Method {
+    pub fn parse(name: &str) -> Result<Method, String> {
+        match name {
+            "jenks" => Ok(Method::Jenks),
+            "quantile" => Ok(Method::Quantile),
+            "equal-interval" => Ok(Method::EqualInterval),
+            _ => Err(format!(
+                "境界の求め方が不正です: {} (jenks, quantile, equal-interval のいずれか)",
+                name
+            )),
+        }
+    }
+}
+
+/// 1 つの階級
+pub struct ClassBreak {
+    /// 下限（最初の階級は最小値）
+    pub lower: f64,
+    /// 上限（この値を含む）
+    pub upper: f64,
+    /// この階級に入るグループの数
+    pub groups: usize,
+}
+
+/// `classes` 個の階級の境界（最小値、各階級の上限の順に `classes + 1` 個）。
+/// 値の種類が階級の数より少なければ、値の種類の数に減らす。値がなければ空
+pub fn breaks(values: &[f64], classes: usize, method: Method) -> Vec<f64> {
+    let mut sorted: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    sorted.sort_by(f64::total_cmp);
+    let mut distinct = sorted.clone();
+    distinct.dedup();
+    let classes = classes.min(distinct.len());
+    if classes == 0 {
+        return Vec::new();
+    }
+    let (min, max) = (sorted[0], sorted[sorted.len() - 1]);
+    let mut breaks = match method {
+        Method::Jenks => jenks(&sorted, classes),
+        Method::Quantile => (0..=classes)
+            .map(|i| sorted[(sorted.len() - 1) * i / classes])
+            .collect(),
+        Method::EqualInterval => (0..=classes)
+            .map(|i| min + (max - min) * i as f64 / classes as f64)
+            .collect(),
+    };
+    // 丸めの誤差で最大値が最後の階級から外れないように
+    breaks[classes] = max;
+    // 同じ上限の階級は 1 つにまとめる（最初の階級の上限は最小値と同じでもよい）
+    let mut upper = breaks.split_off(1);
+    upper.dedup();
+    breaks.extend(upper);
+    breaks
+}
+
+/// ソート済みの値を分散の合計が最小になるように分けた境界
+fn jenks(sorted: &[f64], classes: usize) -> Vec<f64> {
+    let n = sorted.len();
+    // lower[l][j]: 先頭の l 個を j 個の階級に分けたときの最後の階級の先頭（1 始まり）
+    // variance[l][j]: そのときの階級内の偏差平方和の合計
+    let mut lower = vec![vec![0usize; classes + 1]; n + 1];
+    let mut variance = vec![vec![f64::INFINITY; classes + 1]; n + 1];
+    for j in 1..=classes {
+        lower[1][j] = 1;
+        variance[1][j] = 0.0;
+    }
+    for l in 2..=n {
+        let (mut sum, mut squares) = (0.0, 0.0);
+        let mut deviation = 0.0;
+        for m in 1..=l {
+            // 最後の階級を sorted[l - m .. l] とする
+            let first = l - m + 1;
+            let value = sorted[first - 1];
+            sum += value;
+            squares += value * value;
+            deviation = squares - sum * sum / m as f64;
+            if first > 1 {
+                for j in 2..=classes {
+                    let total = deviation + variance[first - 1][j - 1];
+                    if total <= variance[l][j] {
+                        lower[l][j] = first;
+                        variance[l][j] = total;
+                    }
+                }
+            }
+        }
+        lower[l][1] = 1;
+        variance[l][1] = deviation;
+    }
+
+    let mut breaks = vec![0.0; classes + 1];
+    breaks[0] = sorted[0];
+    breaks[classes] = sorted[n - 1];
+    let mut end = n;
+    for j in (2..=classes).rev() {
+        let first = lower[end][j];
+        breaks[j - 1] = sorted[first - 2];
+        end = first - 1;
+    }
+    breaks
+}
+
+/// 値の階級（1 始まり。境界が空なら 0）
+pub fn class_of(breaks: &[f64], value: f64) -> usize {
+    let classes = breaks.len().saturating_sub(1);
+    (1..=classes)
+        .find(|&i| value <= breaks[i])
+        .unwrap_or(classes)
+}
+
+/// 階級ごとの範囲とグループの数
+pub fn classes(breaks: &[f64], rows: &[GroupResult]) -> Vec<ClassBreak> {
+    let mut classes: Vec<ClassBreak> = breaks
+        .windows(2)
+        .map(|pair| ClassBreak {
+            lower: pair[0],
+            upper: pair[1],
+            groups: 0,
+        })
+        .collect();
+    for row in rows {
+        if let Some(class) = classes.get_mut(class_of(breaks, row.area).wrapping_sub(1)) {
+            class.groups += 1;
+        }
+    }
+    classes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_breaks_for_each_method() {
+        let clusters = [1.0, 2.0, 3.0, 10.0, 11.0, 12.0, 20.0, 21.0, 22.0];
+        assert_eq!(breaks(&clusters, 3, Method::Jenks), [1.0, 3.0, 12.0, 22.0]);
+        // 並び順や非数によらない
+        let shuffled = [22.0, 1.0, f64::NAN, 12.0, 3.0, 20.0, 2.0, 11.0, 10.0, 21.0];
+        assert_eq!(breaks(&shuffled, 3, Method::Jenks), [1.0, 3.0, 12.0, 22.0]);
+
+        let values: Vec<f64> = (1..=9).map(f64::from).collect();
+        assert_eq!(breaks(&values, 3, Method::Quantile), [1.0, 3.0, 6.0, 9.0]);
+        assert_eq!(
+            breaks(&[0.0, 2.5, 7.0, 10.0], 4, Method::EqualInterval),
+            [0.0, 2.5, 5.0, 7.5, 10.0]
+        );
+        assert!(breaks(&[], 3, Method::Jenks).is_empty());
+        assert!(breaks(&[f64::NAN], 3, Method::Quantile).is_empty());
+        assert!(Method::parse("natural").is_err());
+    }
+
+    #[test]
+    fn fewer_distinct_values_than_classes_reduce_the_classes() {
+        let values = [5.0, 1.0, 5.0, 1.0, 5.0];
+        assert_eq!(breaks(&values, 4, Method::Jenks), [1.0, 1.0, 5.0]);
+        assert_eq!(breaks(&values, 4, Method::EqualInterval), [1.0, 3.0, 5.0]);
+        // 分位数の上限が同じになった階級は 1 つにまとめる
+        assert_eq!(breaks(&values, 4, Method::Quantile), [1.0, 5.0]);
+        assert_eq!(breaks(&[3.0, 3.0], 5, Method::Jenks), [3.0, 3.0]);
+    }
+
+    #[test]
+    fn values_are_counted_in_the_class_whose_upper_bound_includes_them() {
+        let bounds = [1.0, 3.0, 12.0, 22.0];
+        let indices: Vec<usize> = [1.0, 3.0, 3.5, 12.0, 22.0, 100.0]
+            .iter()
+            .map(|&value| class_of(&bounds, value))
+            .collect();
+        assert_eq!(indices, [1, 1, 2, 2, 3, 3]);
+        assert_eq!(class_of(&[], 1.0), 0);
+
+        let row = |area| GroupResult {
+            key: String::new(),
+            layer: None,
+            area,
+            count: 1,
+            geometry: None,
+            ids: Vec::new(),
+            values: Vec::new(),
+            kept: Vec::new(),
+            class: None,
+        };
+        let rows = [row(1.0), row(2.0), row(11.0), row(21.0), row(22.0)];
+        let classes: Vec<_> = classes(&bounds, &rows)
+            .iter()
+            .map(|class| (class.lower, class.upper, class.groups))
+            .collect();
+        assert_eq!(classes, [(1.0, 3.0, 2), (3.0, 12.0, 1), (12.0, 22.0, 2)]);
+    }
+}
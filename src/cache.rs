@@ -0,0 +1,160 @@
+//! 集計結果のキャッシュ（`--cache-dir`）。
+//! ノートブックや CI で同じ集計を何度も実行する場合に、入力ファイルの内容と指定したオプションが
+//! 前回と同じなら、キャッシュしておいた出力をそのまま書き出して集計を省く。
+//! キーは layon のバージョン、正規化したオプション、入力ファイルの内容の SHA-256 で、
+//! 入力ファイルが変われば（同じパスでも）別のキーになる。
+//! `--only-file` などオプションの値に指定したファイルも、出力先を除いてすべて内容をキーに含める。
+
+use crate::sha256;
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+/// キャッシュの 1 つの項目（ファイルはまだないこともある）
+pub struct Entry {
+    path: PathBuf,
+}
+
+impl Entry {
+    /// `dir` の中の、オプション `arguments` と入力ファイル `inputs`（既定の入力元などオプションにないもの）に対応する項目
+    pub fn new(dir: &str, arguments: &[String], inputs: &[&str]) -> io::Result<Entry> {
+        // 入力ファイルの区切りがずれても同じキーにならないように、大きさも含める
+        let mut header = format!("layon {}\n", env!("CARGO_PKG_VERSION"));
+        let arguments = normalize(arguments);
+        for argument in &arguments {
+            header.push_str(argument);
+            header.push('\0');
+        }
+        let mut files: Vec<&str> = inputs.to_vec();
+        for path in file_arguments(&arguments) {
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+        let mut reader: Box<dyn Read> = Box::new(io::empty());
+        for path in files {
+            header.push_str(&format!("\n{} バイト", fs::metadata(path)?.len()));
+            reader = Box::new(reader.chain(File::open(path)?));
+        }
+        let key = sha256::hex_digest(io::Cursor::new(header.into_bytes()).chain(reader))?;
+        Ok(Entry {
+            path: Path::new(dir).join(key),
+        })
+    }
+
+    /// キャッシュした出力があれば `output` に書き出して true を返す
+    pub fn restore(&self, output: &str) -> io::Result<bool> {
+        if !self.path.is_file() {
+            return Ok(false);
+        }
+        fs::copy(&self.path, output)?;
+        Ok(true)
+    }
+
+    /// 書き出した `output` をキャッシュする（途中で止まっても壊れた項目が残らないよう、別名で書いてから置き換える）
+    pub fn store(&self, output: &str) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temporary = self.path.with_extension("tmp");
+        fs::copy(output, &temporary)?;
+        fs::rename(&temporary, &self.path)
+    }
+}
+
+/// キーに使うオプション。`--name=value` は `--name` と `value` に分け、キャッシュ自体と一時ディレクトリ（出力を変えない）のオプションは除く
+fn normalize(arguments: &[String]) -> Vec<String> {
+    let mut normalized = Vec::new();
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        let (name, value) = match argument.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+            _ => (argument.as_str(), None),
+        };
+        match name {
+            "--no-cache" => {}
+            "--cache-dir" | "--tmpdir" | "--tmpdir-limit" => {
+                if value.is_none() {
+                    arguments.next();
+                }
+            }
+            _ => {
+                normalized.push(name.to_string());
+                normalized.extend(value.map(str::to_string));
+            }
+        }
+    }
+    normalized
+}
+
+/// オプションの値に指定したファイル（`-iFILE` のような短いオプションも）。出力先のファイルは除く
+fn file_arguments(arguments: &[String]) -> Vec<&str> {
+    let is_file = |path: &str| Path::new(path).is_file();
+    let mut files = Vec::new();
+    let mut output = false;
+    for argument in arguments {
+        let value = match argument.as_str() {
+            // 出力先の内容は前回の結果で変わる
+            _ if output => None,
+            "-o" | "--output" => None,
+            argument if is_file(argument) => Some(argument),
+            argument
+                if argument.len() > 2
+                    && argument.starts_with('-')
+                    && !argument.starts_with("--")
+                    && !argument.starts_with("-o") =>
+            {
+                argument.get(2..).filter(|value| is_file(value))
+            }
+            _ => None,
+        };
+        output = matches!(argument.as_str(), "-o" | "--output");
+        files.extend(value);
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contents_of_files_given_to_options_change_the_key() {
+        let dir = std::env::temp_dir().join(format!("layon-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let only = dir.join("only.txt");
+        let output = dir.join("output.csv");
+        let arguments: Vec<String> = [
+            "--only-file",
+            only.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ]
+        .map(str::to_string)
+        .to_vec();
+        let key = |arguments: &[String]| {
+            Entry::new(dir.to_str().unwrap(), arguments, &[])
+                .unwrap()
+                .path
+        };
+
+        fs::write(&only, "川越市\n").unwrap();
+        let first = key(&arguments);
+        // 出力先の内容はキーに含めない
+        fs::write(&output, "City,Area\n").unwrap();
+        assert_eq!(key(&arguments), first);
+        // 同じパスでも内容が変われば別のキーになる
+        fs::write(&only, "所沢市\n").unwrap();
+        assert_ne!(key(&arguments), first);
+        // --name=value の形でも同じ
+        let joined = vec![format!("--only-file={}", only.display())];
+        fs::write(&only, "川越市\n").unwrap();
+        let before = key(&joined);
+        fs::write(&only, "所沢市\n").unwrap();
+        assert_ne!(key(&joined), before);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
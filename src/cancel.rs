@@ -0,0 +1,85 @@
+//! 処理の中断（キャンセルと制限時間）。
+//! 並列処理はチャンクの合間にトークンを確認し、中断されていれば残りのチャンクを処理せずに終える。
+
+use std::{
+    error::Error,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// 中断を伝えるトークン。複製したトークンは同じ中断の状態を共有する
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    /// この時刻を過ぎたら中断されたものとみなす
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// 今から `timeout` だけ経つと中断されるトークン（中断の状態は元のトークンと共有する）
+    pub fn with_timeout(&self, timeout: Duration) -> CancellationToken {
+        let deadline = Instant::now() + timeout;
+        CancellationToken {
+            cancelled: Arc::clone(&self.cancelled),
+            deadline: Some(self.deadline.map_or(deadline, |d| d.min(deadline))),
+        }
+    }
+
+    /// 処理を中断させる（別のスレッドからも呼べる）
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.timed_out()
+    }
+
+    fn timed_out(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// 中断されていればエラーにする
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            Err(Cancelled { timed_out: false })
+        } else if self.timed_out() {
+            Err(Cancelled { timed_out: true })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// 処理が中断されたことを表すエラー
+pub struct Cancelled {
+    /// 制限時間を超えたために中断したか
+    pub timed_out: bool,
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.timed_out {
+            write!(f, "制限時間を超えたため処理を中断しました")
+        } else {
+            write!(f, "処理が中断されました")
+        }
+    }
+}
+
+// main から返したときにもメッセージがそのまま表示されるようにする
+impl fmt::Debug for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Error for Cancelled {}
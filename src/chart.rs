@@ -0,0 +1,109 @@
+//! 端末に表示する横棒グラフ（`--chart`）。
+//! 表計算ソフトに書き出さなくても、上位のグループの大きさをその場で比べられるようにする。
+
+/// 棒の端に使う 1/8 刻みのブロック文字（1/8 から 7/8 まで）
+const EIGHTHS: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+/// ラベルと値の組を幅 `width` 桁に収まる横棒グラフにする。
+/// 棒の長さは最大値に比例させる（負の値は 0 として扱う）
+pub fn bars(items: &[(String, f64)], width: usize) -> String {
+    let values: Vec<String> = items.iter().map(|(_, value)| value.to_string()).collect();
+    let label_width = items
+        .iter()
+        .map(|(label, _)| display_width(label))
+        .max()
+        .unwrap_or(0);
+    let value_width = values.iter().map(|value| value.len()).max().unwrap_or(0);
+    // ラベルと値の間に空白を 1 桁ずつ入れ、残りを棒に使う（狭くても 10 桁は確保する）
+    let bar_width = width.saturating_sub(label_width + value_width + 2).max(10);
+    let max = items.iter().map(|&(_, value)| value).fold(0.0, f64::max);
+
+    let mut chart = String::new();
+    for ((label, value), text) in items.iter().zip(&values) {
+        let eighths = if max > 0.0 {
+            (value.max(0.0) / max * (bar_width * 8) as f64).round() as usize
+        } else {
+            0
+        };
+        let mut bar = "█".repeat(eighths / 8);
+        if eighths % 8 > 0 {
+            bar.push(EIGHTHS[eighths % 8 - 1]);
+        }
+        chart.push_str(&format!(
+            "{}{} {}{} {}\n",
+            label,
+            " ".repeat(label_width - display_width(label)),
+            bar,
+            " ".repeat(bar_width - display_width(&bar)),
+            text
+        ));
+    }
+    chart
+}
+
+/// 端末での表示幅（全角の文字は 2 桁とみなす）
+pub fn display_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+/// 1 文字の表示幅
+pub fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115f
+        | 0x2e80..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6 => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(pairs: &[(&str, f64)]) -> Vec<(String, f64)> {
+        pairs
+            .iter()
+            .map(|&(label, value)| (label.to_string(), value))
+            .collect()
+    }
+
+    #[test]
+    fn draws_bars_in_proportion_to_the_largest_value() {
+        // 全角のラベルは 2 桁として揃え、残りの 10 桁を棒に使う
+        let chart = bars(&items(&[("浦和区", 4.0), ("西区", 1.0)]), 19);
+        assert_eq!(
+            chart,
+            "浦和区 ██████████ 4\n\
+             西区   ██▌        1\n"
+        );
+    }
+
+    #[test]
+    fn ends_bars_with_eighth_blocks() {
+        let chart = bars(&items(&[("a", 8.0), ("b", 1.0)]), 14);
+        assert_eq!(
+            chart,
+            "a ██████████ 8\n\
+             b █▎         1\n"
+        );
+    }
+
+    #[test]
+    fn negative_values_and_narrow_terminals_get_empty_bars() {
+        let chart = bars(&items(&[("a", -1.0), ("b", 0.0)]), 1);
+        assert_eq!(
+            chart,
+            format!("a{}-1\nb{}0\n", " ".repeat(12), " ".repeat(12))
+        );
+    }
+
+    #[test]
+    fn counts_wide_characters_as_two_columns() {
+        assert_eq!(display_width("浦和区 A"), 8);
+        assert_eq!(display_width("ｶﾅ"), 2);
+    }
+}
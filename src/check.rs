@@ -0,0 +1,283 @@
+//! 出力した CSV と、リポジトリに置いた期待する結果の CSV との突き合わせ（`--check expected.csv`）。
+//!
+//! 行政区域データを更新したときに集計結果が変わっていないことを CI で確かめるために、
+//! 行の見出し（1 列目。先頭がレイヤーの列ならレイヤーと 2 列目）で行を対応付け、列ごとに値を比べる。
+//! 数値は相対誤差が許容誤差以下なら同じとみなし（期待値が 0 なら差の絶対値で比べる）、それ以外の値は文字列として比べる。
+//! 数値は出力と同じ書式（`--decimal-mark` など）で読む。
+
+use crate::sink::csv::Format;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+};
+
+/// 既定の許容誤差（相対誤差）
+pub const DEFAULT_TOLERANCE: f64 = 1e-6;
+
+/// 突き合わせの設定
+#[derive(Clone, Debug)]
+pub struct Check {
+    /// 期待する結果の CSV ファイル
+    pub expected: String,
+    /// 数値の相対誤差の上限
+    pub tolerance: f64,
+}
+
+/// 期待する結果との違い
+#[derive(Debug, PartialEq)]
+pub enum Difference {
+    /// 列の見出しが違う（行は比べない）
+    Header {
+        expected: Vec<String>,
+        actual: Vec<String>,
+    },
+    /// 期待する結果にだけある行
+    Missing { key: String },
+    /// 出力にだけある行
+    Unexpected { key: String },
+    /// 値が違う
+    Value {
+        key: String,
+        column: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difference::Header { expected, actual } => write!(
+                f,
+                "列が違います: 期待 {} / 出力 {}",
+                expected.join(","),
+                actual.join(",")
+            ),
+            Difference::Missing { key } => write!(f, "- {} (出力にない行)", key),
+            Difference::Unexpected { key } => write!(f, "+ {} (期待する結果にない行)", key),
+            Difference::Value {
+                key,
+                column,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "~ {} の {}: 期待 {} / 出力 {}",
+                key, column, expected, actual
+            ),
+        }
+    }
+}
+
+/// 突き合わせの結果
+pub struct Report {
+    /// 比べた行の数（期待する結果の行の数）
+    pub rows: usize,
+    pub differences: Vec<Difference>,
+}
+
+/// 見出しと、行の見出しと値の組（ファイルの中の順）
+struct Table {
+    header: Vec<String>,
+    rows: Vec<(String, Vec<String>)>,
+}
+
+impl Table {
+    fn read(path: &str, format: &Format) -> Result<Table, Box<dyn Error>> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(format.dialect.delimiter)
+            .flexible(true)
+            .from_path(path)
+            .map_err(|err| format!("{}: {}", path, err))?;
+        let header: Vec<String> = rdr
+            .headers()
+            .map_err(|err| format!("{}: {}", path, err))?
+            .iter()
+            .map(str::to_string)
+            .collect();
+        let keys = if header.first().map(String::as_str) == Some("Layer") {
+            2
+        } else {
+            1
+        };
+        let mut rows = Vec::new();
+        let mut seen = HashMap::new();
+        for (line, record) in rdr.records().enumerate() {
+            let record = record.map_err(|err| format!("{}: {}", path, err))?;
+            let values: Vec<String> = record.iter().map(str::to_string).collect();
+            let key = values[..keys.min(values.len())].join(" / ");
+            // 見出しの行が 1 行目
+            if let Some(previous) = seen.insert(key.clone(), line + 2) {
+                return Err(format!(
+                    "{}: {} 行目と {} 行目の行の見出しが同じです ({})",
+                    path,
+                    previous,
+                    line + 2,
+                    key
+                )
+                .into());
+            }
+            rows.push((key, values));
+        }
+        Ok(Table { header, rows })
+    }
+}
+
+/// 出力した `actual` を `check` の期待する結果と比べる
+pub fn compare(actual: &str, check: &Check, format: &Format) -> Result<Report, Box<dyn Error>> {
+    let expected = Table::read(&check.expected, format)?;
+    let actual = Table::read(actual, format)?;
+    if expected.header != actual.header {
+        return Ok(Report {
+            rows: expected.rows.len(),
+            differences: vec![Difference::Header {
+                expected: expected.header,
+                actual: actual.header,
+            }],
+        });
+    }
+
+    let found: HashMap<&str, &[String]> = actual
+        .rows
+        .iter()
+        .map(|(key, values)| (key.as_str(), values.as_slice()))
+        .collect();
+    let mut differences = Vec::new();
+    for (key, values) in &expected.rows {
+        let Some(actual) = found.get(key.as_str()) else {
+            differences.push(Difference::Missing { key: key.clone() });
+            continue;
+        };
+        for (i, column) in expected.header.iter().enumerate() {
+            let want = values.get(i).map(String::as_str).unwrap_or_default();
+            let got = actual.get(i).map(String::as_str).unwrap_or_default();
+            if !matches(want, got, check.tolerance, format) {
+                differences.push(Difference::Value {
+                    key: key.clone(),
+                    column: column.clone(),
+                    expected: want.to_string(),
+                    actual: got.to_string(),
+                });
+            }
+        }
+    }
+    let known: HashSet<&str> = expected.rows.iter().map(|(key, _)| key.as_str()).collect();
+    differences.extend(
+        actual
+            .rows
+            .iter()
+            .filter(|(key, _)| !known.contains(key.as_str()))
+            .map(|(key, _)| Difference::Unexpected { key: key.clone() }),
+    );
+    Ok(Report {
+        rows: expected.rows.len(),
+        differences,
+    })
+}
+
+/// 2 つの値が許容誤差の範囲で同じかどうか
+fn matches(expected: &str, actual: &str, tolerance: f64, format: &Format) -> bool {
+    if expected == actual {
+        return true;
+    }
+    match (format.numbers.parse(expected), format.numbers.parse(actual)) {
+        (Some(expected), Some(actual)) => {
+            let difference = (actual - expected).abs();
+            if expected == 0.0 {
+                difference <= tolerance
+            } else {
+                difference <= tolerance * expected.abs()
+            }
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aggregate::Metric,
+        pipeline::{Csv, Pipeline},
+    };
+    use std::{fmt::Write, fs};
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+    const GOLDEN: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/golden/n03_11_sample_geodesic.csv"
+    );
+
+    #[test]
+    fn check_reports_rows_that_deviate_from_the_expected_csv() {
+        let dir = std::env::temp_dir().join(format!("layon-check-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("area.csv");
+        Pipeline::read(FIXTURE)
+            .metric(Metric::GeodesicArea)
+            .sink(Csv::new(output.to_str().unwrap()))
+            .run()
+            .unwrap();
+        let format = Format::default();
+        let same = Check {
+            expected: GOLDEN.to_string(),
+            tolerance: DEFAULT_TOLERANCE,
+        };
+        let report = compare(output.to_str().unwrap(), &same, &format).unwrap();
+        assert_eq!(report.rows, 8);
+        assert!(report.differences.is_empty());
+
+        // 1 行目の面積をずらし、2 行目を消し、知らない行を加える
+        let text = fs::read_to_string(GOLDEN).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        let (first, area) = lines[1].rsplit_once(',').unwrap();
+        let area: f64 = area.parse().unwrap();
+        let (second, _) = lines[2].rsplit_once(',').unwrap();
+        let write = |area: f64| {
+            let mut expected = format!("{}\n{},{}\n", lines[0], first, area);
+            for line in &lines[3..] {
+                writeln!(expected, "{}", line).unwrap();
+            }
+            expected.push_str("架空市,1\n");
+            let path = dir.join("expected.csv");
+            fs::write(&path, expected).unwrap();
+            path.to_str().unwrap().to_string()
+        };
+
+        let changed = Check {
+            expected: write(area * 1.01),
+            tolerance: DEFAULT_TOLERANCE,
+        };
+        let report = compare(output.to_str().unwrap(), &changed, &format).unwrap();
+        assert_eq!(report.rows, 8);
+        assert_eq!(
+            report.differences,
+            vec![
+                Difference::Value {
+                    key: first.to_string(),
+                    column: "Area".to_string(),
+                    expected: (area * 1.01).to_string(),
+                    actual: area.to_string(),
+                },
+                Difference::Missing {
+                    key: "架空市".to_string()
+                },
+                Difference::Unexpected {
+                    key: second.to_string()
+                },
+            ]
+        );
+
+        // 許容誤差の範囲のずれは違いとみなさない
+        let loose = Check {
+            expected: write(area * (1.0 + 1e-8)),
+            tolerance: DEFAULT_TOLERANCE,
+        };
+        let report = compare(output.to_str().unwrap(), &loose, &format).unwrap();
+        assert_eq!(report.differences.len(), 2);
+    }
+}
@@ -0,0 +1,261 @@
+//! 集計した列の値による階級分け（`--classify 'area: <10=small, <100=medium, else=large'`）。
+//! 塗り分け地図（コロプレス図）の階級を用意するときに使う。
+//! 規則は先頭から順に比べ、最初に当てはまったものの名前を付ける。
+
+use crate::{aggregate::GroupResult, derive};
+use std::collections::BTreeMap;
+
+/// 階級分けの設定
+#[derive(Clone, Debug, PartialEq)]
+pub struct Classification {
+    /// 比べる列（`area`, `count`, `values` の列）
+    pub column: String,
+    pub(crate) rules: Vec<Rule>,
+}
+
+/// 1 つの階級
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    /// 当てはまる条件（None なら `else`）
+    pub bound: Option<(Bound, f64)>,
+    pub label: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bound {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Classification {
+    /// `列: <値=名前, <=値=名前, ..., else=名前` を解析する
+    pub fn parse(text: &str) -> Result<Classification, String> {
+        let (column, rules) = text
+            .split_once(':')
+            .ok_or_else(|| format!("--classify の指定が不正です (列: 規則, ...): {}", text))?;
+        let column = column.trim();
+        if column.is_empty() {
+            return Err("--classify の列を指定してください".to_string());
+        }
+        let mut parsed = Vec::new();
+        for rule in rules.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+            let invalid = || format!("--classify の規則が不正です: {}", rule);
+            let (condition, label) = rule.rsplit_once('=').ok_or_else(invalid)?;
+            let (condition, label) = (condition.trim(), label.trim());
+            if label.is_empty() {
+                return Err(invalid());
+            }
+            let bound = if condition == "else" {
+                None
+            } else {
+                let (bound, threshold) = [
+                    ("<=", Bound::Le),
+                    (">=", Bound::Ge),
+                    ("<", Bound::Lt),
+                    (">", Bound::Gt),
+                ]
+                .into_iter()
+                .find_map(|(prefix, bound)| Some((bound, condition.strip_prefix(prefix)?)))
+                .ok_or_else(invalid)?;
+                let threshold: f64 = threshold.trim().parse().map_err(|_| invalid())?;
+                Some((bound, threshold))
+            };
+            if parsed
+                .last()
+                .is_some_and(|last: &Rule| last.bound.is_none())
+            {
+                return Err("--classify の else は最後の規則にしてください".to_string());
+            }
+            parsed.push(Rule {
+                bound,
+                label: label.to_string(),
+            });
+        }
+        if parsed.is_empty() {
+            return Err("--classify の規則を指定してください".to_string());
+        }
+        Ok(Classification {
+            column: column.to_string(),
+            rules: parsed,
+        })
+    }
+
+    /// 値の階級の名前（値がないか、どの規則にも当てはまらなければ None）
+    pub fn label(&self, value: Option<f64>) -> Option<&str> {
+        let value = value?;
+        self.rules
+            .iter()
+            .find(|rule| match rule.bound {
+                None => true,
+                Some((Bound::Lt, threshold)) => value < threshold,
+                Some((Bound::Le, threshold)) => value <= threshold,
+                Some((Bound::Gt, threshold)) => value > threshold,
+                Some((Bound::Ge, threshold)) => value >= threshold,
+            })
+            .map(|rule| rule.label.as_str())
+    }
+
+    /// 行ごとに階級の名前を `GroupResult::class` に付ける
+    pub fn apply(&self, rows: &mut [GroupResult]) {
+        for row in rows {
+            row.class = self
+                .label(derive::column(row, &self.column))
+                .map(str::to_string);
+        }
+    }
+}
+
+/// 階級ごとの小計
+pub struct ClassTotal {
+    /// 階級の名前（どれにも当てはまらない行は空）
+    pub class: String,
+    /// 行（集計キー）の数
+    pub groups: usize,
+    /// Feature の数の合計
+    pub count: usize,
+    /// 面積の合計
+    pub area: f64,
+}
+
+/// 階級ごとの小計（規則の順。どの階級にも当てはまらない行があれば最後）
+pub fn totals(classification: &Classification, rows: &[GroupResult]) -> Vec<ClassTotal> {
+    let mut totals = BTreeMap::<usize, ClassTotal>::new();
+    for row in rows {
+        let position = row
+            .class
+            .as_ref()
+            .and_then(|class| {
+                classification
+                    .rules
+                    .iter()
+                    .position(|rule| &rule.label == class)
+            })
+            .unwrap_or(classification.rules.len());
+        let total = totals.entry(position).or_insert_with(|| ClassTotal {
+            class: row.class.clone().unwrap_or_default(),
+            groups: 0,
+            count: 0,
+            area: 0.0,
+        });
+        total.groups += 1;
+        total.count += row.count;
+        total.area += row.area;
+    }
+    totals.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(key: &str, area: f64, count: usize) -> GroupResult {
+        GroupResult {
+            key: key.to_string(),
+            layer: None,
+            area,
+            count,
+            geometry: None,
+            ids: Vec::new(),
+            values: vec![("pop_sum".to_string(), None)],
+            kept: Vec::new(),
+            class: None,
+        }
+    }
+
+    #[test]
+    fn rules_are_parsed_and_the_first_match_labels_the_value() {
+        let classification =
+            Classification::parse("area: <10=small, <=100 = medium, >1000=huge, else=large")
+                .unwrap();
+        assert_eq!(classification.column, "area");
+        assert_eq!(
+            classification.rules[1],
+            Rule {
+                bound: Some((Bound::Le, 100.0)),
+                label: "medium".to_string(),
+            }
+        );
+        let labels: Vec<Option<&str>> = [
+            Some(9.9),
+            Some(10.0),
+            Some(100.0),
+            Some(5000.0),
+            Some(500.0),
+            None,
+        ]
+        .into_iter()
+        .map(|value| classification.label(value))
+        .collect();
+        assert_eq!(
+            labels,
+            [
+                Some("small"),
+                Some("medium"),
+                Some("medium"),
+                Some("huge"),
+                Some("large"),
+                None
+            ]
+        );
+        // else がなければどれにも当てはまらない値もある
+        let open = Classification::parse("count: >=2=many").unwrap();
+        assert_eq!(open.label(Some(1.0)), None);
+        assert_eq!(open.label(Some(2.0)), Some("many"));
+
+        for invalid in [
+            "<10=small",
+            ": <10=small",
+            "area:",
+            "area: <10",
+            "area: <10=",
+            "area: =10=small",
+            "area: <ten=small",
+            "area: else=large, <10=small",
+        ] {
+            assert!(Classification::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn totals_follow_the_rule_order_with_unmatched_rows_last() {
+        let classification = Classification::parse("area: <10=small, <100=medium").unwrap();
+        let mut rows = [
+            row("a", 500.0, 1),
+            row("b", 50.0, 2),
+            row("c", 5.0, 3),
+            row("d", 60.0, 4),
+            row("e", 1.0, 5),
+        ];
+        classification.apply(&mut rows);
+        let classes: Vec<Option<&str>> = rows.iter().map(|row| row.class.as_deref()).collect();
+        assert_eq!(
+            classes,
+            [
+                None,
+                Some("medium"),
+                Some("small"),
+                Some("medium"),
+                Some("small")
+            ]
+        );
+        let totals: Vec<_> = totals(&classification, &rows)
+            .into_iter()
+            .map(|total| (total.class, total.groups, total.count, total.area))
+            .collect();
+        assert_eq!(
+            totals,
+            [
+                ("small".to_string(), 2, 8, 6.0),
+                ("medium".to_string(), 2, 6, 110.0),
+                (String::new(), 1, 1, 500.0),
+            ]
+        );
+
+        // 値のない列で分けた行はどの階級にも入らない
+        let by_population = Classification::parse("pop_sum: else=all").unwrap();
+        by_population.apply(&mut rows);
+        assert!(rows.iter().all(|row| row.class.is_none()));
+    }
+}
@@ -0,0 +1,719 @@
+//! 既定のサブコマンド（集計キーごとの面積の集計）の引数の解析。
+
+use super::{
+    parse_aggregate_metric, parse_char, split_option, terminal_width, value, Command,
+    DEFAULT_GROUP_BY, DEFAULT_INPUT, DEFAULT_OUTPUT, DEFAULT_SAMPLE,
+};
+use crate::{
+    aggregate::Metric,
+    approx::Sampling,
+    check::{self, Check},
+    classify::Classification,
+    derive::{self, Derived, SortKey},
+    filter::KeyFilter,
+    geometry_type,
+    gpu::Backend,
+    keep::{Keep, Policy},
+    normalize::KeyNormalization,
+    numeric::NumericAggregate,
+    pivot::PivotSpec,
+    projection::Projection,
+    scratch,
+    sink::{
+        csv::{self, Dialect, Quote},
+        number::{NumberFormat, Style},
+        report, Output,
+    },
+    smooth::Smoothing,
+    source::{Input, InputOptions},
+    template::KeyTemplate,
+    transform::MAX_PRECISION,
+};
+use std::time::Duration;
+
+/// コマンドライン引数を解析した結果
+pub struct Options {
+    pub input: Input,
+    pub group_by: String,
+    /// 集計する Feature の条件式
+    pub filter: Option<String>,
+    /// 複数のプロパティから集計キーを作るテンプレート（`group_by` はテンプレートの文字列）
+    pub group_by_template: Option<KeyTemplate>,
+    /// 集計の前に集計キーの値をそろえる規則
+    pub normalize_key: Option<KeyNormalization>,
+    /// 集計キーの一覧による絞り込み（`--only`, `--exclude` などを指定しなければ None）
+    pub keys: Option<KeyFilter>,
+    /// 集計の前に入力を RFC 7946 の規則で検証する
+    pub validate_input: bool,
+    pub metric: Metric,
+    /// 面積を計算するバックエンド
+    pub backend: Backend,
+    /// 集計の前に重複した Feature を取り除く場合の設定
+    pub dedup: Option<DedupOptions>,
+    /// 自己交差したポリゴンを修復してから集計する
+    pub make_valid: bool,
+    /// 集計の前にポリゴンを差し引くレイヤー
+    pub subtract: Option<String>,
+    /// 面積のほかに集計する数値のプロパティ
+    pub agg: Vec<NumericAggregate>,
+    /// ジオメトリの種類ごとの数と線の長さも集計する
+    pub by_geometry_type: bool,
+    /// 集計した後に計算する列
+    pub derived: Vec<Derived>,
+    /// 隣接する市町村との境界の長さで重み付けして平滑化する列
+    pub smooth: Option<Smoothing>,
+    /// 結果に引き継ぐプロパティ
+    pub keep: Option<Keep>,
+    /// 列の値による階級分け
+    pub classify: Option<Classification>,
+    /// 階級ごとの小計を出力する CSV ファイル
+    pub class_totals: Option<String>,
+    /// 結果を並べる列
+    pub sort: Option<SortKey>,
+    /// 先頭から出力する行の数
+    pub top: Option<usize>,
+    /// 縦持ちの表の代わりに出力するクロス集計
+    pub pivot: Option<PivotSpec>,
+    /// 縦持ちの表の代わりに、この投影法の平面上の面積と楕円体上の面積を比べて出力する
+    pub compare_methods: Option<Projection>,
+    /// 縦持ちの表の代わりに、抽出した Feature から推定した概算を出力する
+    pub approx: Option<Sampling>,
+    /// Feature を分けて集計するプロパティ
+    pub partition_by: Option<String>,
+    /// 分割ごとの結果を保存するディレクトリ
+    pub checkpoint_dir: Option<String>,
+    /// 途中のデータを書き出す一時ディレクトリを作る場所
+    pub tmpdir: Option<String>,
+    /// 一時ディレクトリに書き出す大きさの上限（バイト）
+    pub tmpdir_limit: Option<u64>,
+    /// 集計用の表をメモリに置く大きさの目安の上限（バイト）
+    pub memory_limit: Option<u64>,
+    /// Feature ごとの外周と穴の面積の内訳を出力する CSV ファイル
+    pub ring_report: Option<String>,
+    /// 前処理で外した Feature とジオメトリを変えた Feature の記録を出力する CSV ファイル
+    pub audit_log: Option<String>,
+    /// グループごとの Feature の ID を出力する CSV ファイル
+    pub list_ids: Option<String>,
+    /// 段階ごとの処理時間を出力する JSON ファイル
+    pub timing_json: Option<String>,
+    /// 集計を中断するまでの時間
+    pub timeout: Option<Duration>,
+    /// 集計せずに実行計画を表示する
+    pub dry_run: bool,
+    /// 端末に地図を表示する場合の幅（桁数）
+    pub term_map: Option<usize>,
+    /// 横棒グラフに表示する上位のグループの数
+    pub chart: Option<usize>,
+    /// 来歴を書き出す JSON ファイル
+    pub provenance: Option<String>,
+    /// 解析したコマンドライン引数（来歴に記録する）
+    pub arguments: Vec<String>,
+    /// 集計結果をキャッシュするディレクトリ（`--no-cache` なら None）
+    pub cache: Option<String>,
+    /// 出力した CSV と比べる期待する結果
+    pub check: Option<Check>,
+    pub output: Output,
+}
+
+/// 重複した Feature の取り除き方
+#[derive(Default)]
+pub struct DedupOptions {
+    /// 座標を丸める格子の幅（None なら完全に一致するものだけ）
+    pub tolerance: Option<f64>,
+    /// 取り除いた Feature の一覧を出力する CSV ファイル
+    pub report: Option<String>,
+}
+
+/// 既定のサブコマンドの引数を解析する。`arguments` は来歴に記録する元の引数列
+pub(super) fn parse<I: Iterator<Item = String>>(
+    mut args: I,
+    arguments: Vec<String>,
+) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut output = DEFAULT_OUTPUT.to_string();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut input_options = InputOptions::default();
+    let mut dedup = None;
+    let mut make_valid = false;
+    let mut subtract = None;
+    let mut agg = Vec::new();
+    let mut by_geometry_type = false;
+    let mut pivot = None;
+    let mut derived = Vec::new();
+    let mut smooth = None;
+    let mut keep_properties = Vec::new();
+    let mut keep_conflict = None;
+    let mut classify = None;
+    let mut class_totals = None;
+    let mut sort = None;
+    let mut top = None;
+    let mut list_ids = None;
+    let mut ring_report = None;
+    let mut audit_log = None;
+    let mut template = None;
+    let mut timing_json = None;
+    let mut filter = None;
+    let mut group_by_template = None;
+    let mut normalize_key = None;
+    let mut keys = KeyFilter::default();
+    let mut validate_input = false;
+    let mut metric = Metric::Area;
+    let mut backend = Backend::Cpu;
+    let mut projection = None;
+    let mut compare_methods = false;
+    let mut approx = false;
+    let mut sample = None;
+    let mut seed = None;
+    let mut partition_by = None;
+    let mut checkpoint_dir = None;
+    let mut tmpdir = None;
+    let mut tmpdir_limit = None;
+    let mut memory_limit = None;
+    let mut timeout = None;
+    let mut dry_run = false;
+    let mut term_map = None;
+    let mut chart = None;
+    let mut provenance = None;
+    let mut cache = None;
+    let mut no_cache = false;
+    let mut check = None;
+    let mut check_tolerance = None;
+    let mut numbers = NumberFormat::default();
+    let mut number_style = None;
+    let mut dialect = Dialect::default();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "--group-by-template" => {
+                group_by_template = Some(
+                    KeyTemplate::parse(&value(&name, inline, &mut args)?)
+                        .map_err(|err| format!("--group-by-template: {}", err))?,
+                )
+            }
+            "--sql" => input_options.sql = Some(value(&name, inline, &mut args)?),
+            "--geometry-column" => {
+                input_options.geometry_column = Some(value(&name, inline, &mut args)?)
+            }
+            "--admin-level" => input_options.admin_level = Some(value(&name, inline, &mut args)?),
+            "--zoom" => input_options.zoom = Some(value(&name, inline, &mut args)?),
+            "--skip-invalid" => input_options.skip_invalid = true,
+            "--layer" => input_options.layers.push(value(&name, inline, &mut args)?),
+            "--validate-input" => validate_input = true,
+            "--where" => {
+                let expression = value(&name, inline, &mut args)?;
+                // 書式の誤りは読み込む前に知らせる
+                crate::filter::Filter::parse(&expression)
+                    .map_err(|err| format!("--where の条件式が不正です: {}", err))?;
+                filter = Some(expression);
+            }
+            "--only" => keys.include(KeyFilter::split(&value(&name, inline, &mut args)?)),
+            "--only-file" => keys.include(KeyFilter::read(&value(&name, inline, &mut args)?)?),
+            "--exclude" => keys
+                .exclude
+                .extend(KeyFilter::split(&value(&name, inline, &mut args)?)),
+            "--exclude-file" => keys
+                .exclude
+                .extend(KeyFilter::read(&value(&name, inline, &mut args)?)?),
+            "--normalize-key" => {
+                normalize_key = Some(KeyNormalization::parse(&value(&name, inline, &mut args)?)?)
+            }
+            "--metric" => metric = parse_aggregate_metric(&value(&name, inline, &mut args)?)?,
+            "--projection" => {
+                projection = Some(Projection::parse(&value(&name, inline, &mut args)?)?)
+            }
+            "--backend" => backend = value(&name, inline, &mut args)?.parse()?,
+            "--compare-methods" => compare_methods = true,
+            "--approx" => approx = true,
+            "--sample" => {
+                let f = value(&name, inline, &mut args)?;
+                sample = match f.parse::<f64>() {
+                    Ok(f) if f > 0.0 && f <= 1.0 => Some(f),
+                    _ => {
+                        return Err(format!(
+                            "--sample の値が不正です (0 より大きく 1 以下): {}",
+                            f
+                        ))
+                    }
+                };
+            }
+            "--seed" => {
+                let n = value(&name, inline, &mut args)?;
+                seed = Some(
+                    n.parse()
+                        .map_err(|_| format!("--seed の値が不正です: {}", n))?,
+                );
+            }
+            "--partition-by" => partition_by = Some(value(&name, inline, &mut args)?),
+            "--checkpoint-dir" => checkpoint_dir = Some(value(&name, inline, &mut args)?),
+            "--tmpdir" => tmpdir = Some(value(&name, inline, &mut args)?),
+            "--tmpdir-limit" => {
+                let size = value(&name, inline, &mut args)?;
+                tmpdir_limit = Some(
+                    scratch::parse_size(&size)
+                        .map_err(|err| format!("--tmpdir-limit の値が不正です: {}", err))?,
+                );
+            }
+            "--memory-limit" => {
+                let size = value(&name, inline, &mut args)?;
+                memory_limit = Some(
+                    scratch::parse_size(&size)
+                        .map_err(|err| format!("--memory-limit の値が不正です: {}", err))?,
+                );
+            }
+            "--dedup" => {
+                dedup.get_or_insert_with(DedupOptions::default);
+            }
+            "--dedup-tolerance" => {
+                let t = value(&name, inline, &mut args)?;
+                let tolerance = match t.parse::<f64>() {
+                    Ok(t) if t > 0.0 => t,
+                    _ => return Err(format!("--dedup-tolerance の値が不正です: {}", t)),
+                };
+                dedup.get_or_insert_with(DedupOptions::default).tolerance = Some(tolerance);
+            }
+            "--make-valid" => make_valid = true,
+            "--subtract" => subtract = Some(value(&name, inline, &mut args)?),
+            "--agg" => agg = NumericAggregate::parse_list(&value(&name, inline, &mut args)?)?,
+            "--by-geometry-type" => by_geometry_type = true,
+            "--derive" => derived.push(Derived::parse(&value(&name, inline, &mut args)?)?),
+            "--smooth" => smooth = Some(Smoothing::new(value(&name, inline, &mut args)?)),
+            "--keep" => keep_properties.extend(
+                value(&name, inline, &mut args)?
+                    .split(',')
+                    .map(|property| property.trim().to_string()),
+            ),
+            "--keep-conflict" => {
+                keep_conflict = Some(Policy::parse(&value(&name, inline, &mut args)?)?)
+            }
+            "--classify" => {
+                classify = Some(Classification::parse(&value(&name, inline, &mut args)?)?)
+            }
+            "--class-totals" => class_totals = Some(value(&name, inline, &mut args)?),
+            "--sort" => sort = Some(SortKey::parse(&value(&name, inline, &mut args)?)?),
+            "--top" => {
+                let n = value(&name, inline, &mut args)?;
+                top = match n.parse::<usize>() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => return Err(format!("--top の値が不正です: {}", n)),
+                };
+            }
+            "--pivot" => pivot = Some(PivotSpec::parse(&value(&name, inline, &mut args)?)?),
+            "--ring-report" => ring_report = Some(value(&name, inline, &mut args)?),
+            "--audit-log" => audit_log = Some(value(&name, inline, &mut args)?),
+            "--template" => {
+                template = Some(report::Template::read(&value(&name, inline, &mut args)?)?)
+            }
+            "--list-ids" => list_ids = Some(value(&name, inline, &mut args)?),
+            "--timing-json" => timing_json = Some(value(&name, inline, &mut args)?),
+            "--dedup-report" => {
+                let report = value(&name, inline, &mut args)?;
+                dedup.get_or_insert_with(DedupOptions::default).report = Some(report);
+            }
+            "--timeout" => {
+                let t = value(&name, inline, &mut args)?;
+                timeout = match t.parse::<f64>() {
+                    Ok(seconds) if seconds > 0.0 && seconds.is_finite() => {
+                        Some(Duration::from_secs_f64(seconds))
+                    }
+                    _ => return Err(format!("--timeout の値が不正です: {}", t)),
+                };
+            }
+            "--dry-run" => dry_run = true,
+            "--term-map" => {
+                let width = match inline {
+                    Some(width) => match width.parse::<usize>() {
+                        Ok(width) if width > 0 => width,
+                        _ => return Err(format!("--term-map の幅が不正です: {}", width)),
+                    },
+                    None => terminal_width(),
+                };
+                term_map = Some(width);
+            }
+            "--chart" => {
+                let count = match inline {
+                    Some(count) => match count.parse::<usize>() {
+                        Ok(count) if count > 0 => count,
+                        _ => return Err(format!("--chart の数が不正です: {}", count)),
+                    },
+                    None => 20,
+                };
+                chart = Some(count);
+            }
+            // 既定の出力先は --output がすべて決まってから決める
+            "--provenance" => provenance = Some(inline),
+            "--cache-dir" => cache = Some(value(&name, inline, &mut args)?),
+            "--no-cache" => no_cache = true,
+            "--check" => check = Some(value(&name, inline, &mut args)?),
+            "--check-tolerance" => {
+                let t = value(&name, inline, &mut args)?;
+                check_tolerance = match t.parse::<f64>() {
+                    Ok(t) if t.is_finite() && t >= 0.0 => Some(t),
+                    _ => return Err(format!("--check-tolerance の値が不正です: {}", t)),
+                };
+            }
+            "--number-format" => {
+                number_style = Some(Style::parse(&value(&name, inline, &mut args)?)?)
+            }
+            "--decimals" => {
+                let n = value(&name, inline, &mut args)?;
+                numbers.decimals = match n.parse::<usize>() {
+                    Ok(n) if n <= MAX_PRECISION as usize => Some(n),
+                    _ => {
+                        return Err(format!(
+                            "--decimals の値が不正です (0〜{}): {}",
+                            MAX_PRECISION, n
+                        ))
+                    }
+                };
+            }
+            "--thousands-separator" => {
+                numbers.thousands = Some(parse_char(&name, &value(&name, inline, &mut args)?)?)
+            }
+            "--decimal-mark" => {
+                numbers.decimal_mark = parse_char(&name, &value(&name, inline, &mut args)?)?
+            }
+            "--delimiter" => {
+                dialect.delimiter = Dialect::parse_delimiter(&value(&name, inline, &mut args)?)?
+            }
+            "--quote" => dialect.quote = Quote::parse(&value(&name, inline, &mut args)?)?,
+            "--line-ending" => {
+                dialect.crlf = match value(&name, inline, &mut args)?.as_str() {
+                    "lf" => false,
+                    "crlf" => true,
+                    other => return Err(format!("不明な行の終わりです: {}", other)),
+                }
+            }
+            "--bom" => dialect.bom = true,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    if let Some(projection) = projection {
+        if metric != Metric::Area {
+            return Err("--projection と --metric は同時に指定できません".to_string());
+        }
+        metric = Metric::Projected(projection);
+    }
+    if backend == Backend::Gpu && (metric != Metric::Area || compare_methods || approx) {
+        return Err(
+            "--backend gpu は平面上の面積 (--metric area) の集計でだけ使えます (--projection, --compare-methods, --approx とは同時に指定できません)"
+                .to_string(),
+        );
+    }
+    numbers.style = match (number_style, numbers.decimals) {
+        (Some(style), _) => style,
+        (None, Some(_)) => Style::Fixed,
+        (None, None) => Style::Plain,
+    };
+    if let Some(template) = &group_by_template {
+        if group_by != DEFAULT_GROUP_BY {
+            return Err("-g と --group-by-template は一緒に指定できません".to_string());
+        }
+        group_by = template.property().to_string();
+    }
+    let mut output = match template {
+        Some(template) => Output::template(
+            &output,
+            report::Report {
+                template,
+                input: input.clone(),
+                group_by: group_by.clone(),
+                metric: match metric {
+                    Metric::Area => "area".to_string(),
+                    Metric::GeodesicArea => "geodesic-area".to_string(),
+                    Metric::Projected(projection) => projection.name(),
+                    Metric::GeodesicLength => "length".to_string(),
+                },
+            },
+        )?,
+        None => Output::parse(&output)?,
+    };
+    output.name_key_column(&group_by);
+    let csv_format = csv::Format { numbers, dialect };
+    csv_format.check()?;
+    if let Output::Csv(_, format) = &mut output {
+        *format = csv_format;
+    } else if csv_format.numbers != NumberFormat::default() {
+        return Err(
+            "--number-format, --decimals, --thousands-separator, --decimal-mark は CSV の出力でのみ使えます"
+                .to_string(),
+        );
+    } else if csv_format.dialect != Dialect::default() {
+        return Err(
+            "--delimiter, --quote, --line-ending, --bom は CSV の出力でのみ使えます".to_string(),
+        );
+    }
+    if !agg.is_empty() && !output.has_columns() {
+        return Err("--agg は CSV と JSON、テンプレートの出力でのみ使えます".to_string());
+    }
+    if by_geometry_type && !output.has_columns() {
+        return Err(
+            "--by-geometry-type は CSV と JSON、テンプレートの出力でのみ使えます".to_string(),
+        );
+    }
+    if !derived.is_empty() && !output.has_columns() {
+        return Err("--derive は CSV と JSON、テンプレートの出力でのみ使えます".to_string());
+    }
+    let mut columns: Vec<String> = agg.iter().map(NumericAggregate::column).collect();
+    if by_geometry_type {
+        columns.extend(geometry_type::COLUMNS.map(str::to_string));
+    }
+    derive::check(&derived, &columns)?;
+    let mut known: Vec<&str> = ["area", "count"]
+        .into_iter()
+        .chain(columns.iter().map(String::as_str))
+        .chain(derived.iter().map(|d| d.name.as_str()))
+        .collect();
+    let smoothed = smooth.as_ref().map(Smoothing::name);
+    if let Some(smoothing) = &smooth {
+        if !known.contains(&smoothing.column.as_str()) {
+            return Err(format!(
+                "--smooth の列がありません: {} (使える列: {})",
+                smoothing.column,
+                known.join(", ")
+            ));
+        }
+        if !output.has_columns() {
+            return Err("--smooth は CSV と JSON、テンプレートの出力でのみ使えます".to_string());
+        }
+    }
+    known.extend(smoothed.as_deref());
+    let keep = match (keep_properties.is_empty(), keep_conflict) {
+        (true, None) => None,
+        (true, Some(_)) => {
+            return Err("--keep-conflict は --keep と一緒に指定してください".to_string())
+        }
+        (false, policy) => {
+            if let Some(property) = keep_properties.iter().find(|p| p.is_empty()) {
+                return Err(format!("--keep のプロパティが空です: '{}'", property));
+            }
+            if !output.has_columns() {
+                return Err("--keep は CSV と JSON、テンプレートの出力でのみ使えます".to_string());
+            }
+            Some(Keep {
+                properties: keep_properties,
+                policy: policy.unwrap_or_default(),
+            })
+        }
+    };
+    if let Some(key) = &sort {
+        if !known.contains(&key.column.as_str()) {
+            return Err(format!("--sort の列がありません: {}", key.column));
+        }
+    }
+    if let Some(classification) = &classify {
+        if !known.contains(&classification.column.as_str()) {
+            return Err(format!(
+                "--classify の列がありません: {}",
+                classification.column
+            ));
+        }
+        if !output.has_columns() {
+            return Err("--classify は CSV と JSON、テンプレートの出力でのみ使えます".to_string());
+        }
+    } else if class_totals.is_some() {
+        return Err("--class-totals は --classify と一緒に指定してください".to_string());
+    }
+    // 集計キーごとの行を作らない --pivot と --compare-methods では使えないオプション
+    let row_options = !agg.is_empty()
+        || by_geometry_type
+        || !derived.is_empty()
+        || smooth.is_some()
+        || keep.is_some()
+        || classify.is_some()
+        || sort.is_some()
+        || top.is_some()
+        || list_ids.is_some()
+        || ring_report.is_some()
+        || audit_log.is_some()
+        || timing_json.is_some()
+        || term_map.is_some()
+        || chart.is_some()
+        || provenance.is_some();
+    const ROW_OPTIONS: &str = "--agg, --by-geometry-type, --derive, --smooth, --keep, --classify, --sort, --top, --list-ids, --ring-report, --audit-log, --timing-json, --term-map, --chart, --provenance";
+    if pivot.is_some() {
+        if !matches!(output, Output::Csv(..)) {
+            return Err("--pivot は CSV の出力でのみ使えます".to_string());
+        }
+        if row_options {
+            return Err(format!("--pivot は {} と同時に指定できません", ROW_OPTIONS));
+        }
+    }
+    let compare_methods = match (compare_methods, metric) {
+        (false, _) => None,
+        (true, Metric::GeodesicArea) => {
+            return Err(
+                "--compare-methods は --metric geodesic-area と同時に指定できません (平面上の面積の投影法は --projection で選びます)"
+                    .to_string(),
+            )
+        }
+        (true, Metric::Projected(projection)) => Some(projection),
+        (true, Metric::Area) => Some(Projection::UtmAuto),
+        (true, Metric::GeodesicLength) => {
+            return Err(
+                "--compare-methods は --metric length と同時に指定できません (面積の計算方法を比べます)"
+                    .to_string(),
+            )
+        }
+    };
+    if metric == Metric::GeodesicLength && ring_report.is_some() {
+        return Err(
+            "--ring-report は --metric length と同時に指定できません (リングの面積の内訳を出力します)"
+                .to_string(),
+        );
+    }
+    if compare_methods.is_some() {
+        if !matches!(output, Output::Csv(..)) {
+            return Err("--compare-methods は CSV の出力でのみ使えます".to_string());
+        }
+        if pivot.is_some() {
+            return Err("--compare-methods と --pivot は同時に指定できません".to_string());
+        }
+        if row_options {
+            return Err(format!(
+                "--compare-methods は {} と同時に指定できません",
+                ROW_OPTIONS
+            ));
+        }
+    }
+    if partition_by.is_some() && (pivot.is_some() || compare_methods.is_some()) {
+        return Err(
+            "--partition-by は --pivot, --compare-methods と同時に指定できません".to_string(),
+        );
+    }
+    if checkpoint_dir.is_some() {
+        if partition_by.is_none() {
+            return Err("--checkpoint-dir は --partition-by と一緒に指定してください".to_string());
+        }
+        if term_map.is_some() || output.needs_geometry() {
+            return Err(
+                "--checkpoint-dir はディゾルブしたジオメトリを使う出力 (SQL, PostGIS) や --term-map と同時に指定できません"
+                    .to_string(),
+            );
+        }
+    }
+    if memory_limit.is_some()
+        && (!agg.is_empty()
+            || list_ids.is_some()
+            || by_geometry_type
+            || partition_by.is_some()
+            || pivot.is_some()
+            || compare_methods.is_some()
+            || term_map.is_some()
+            || output.needs_geometry())
+    {
+        return Err(
+            "--memory-limit は面積と Feature の数だけを集計するため、--agg, --list-ids, --by-geometry-type, --partition-by, --pivot, --compare-methods, --term-map やジオメトリを使う出力 (SQL, PostGIS) と同時に指定できません"
+                .to_string(),
+        );
+    }
+    let approx = match (approx, sample, seed) {
+        (false, None, None) => None,
+        (false, _, _) => {
+            return Err("--sample と --seed は --approx と一緒に指定してください".to_string())
+        }
+        (true, sample, seed) => Some(Sampling {
+            fraction: sample.unwrap_or(DEFAULT_SAMPLE),
+            seed: seed.unwrap_or(0),
+        }),
+    };
+    if approx.is_some() {
+        if !matches!(output, Output::Csv(..)) {
+            return Err("--approx は CSV の出力でのみ使えます".to_string());
+        }
+        if pivot.is_some()
+            || compare_methods.is_some()
+            || partition_by.is_some()
+            || memory_limit.is_some()
+        {
+            return Err(
+                "--approx は --pivot, --compare-methods, --partition-by, --memory-limit と同時に指定できません"
+                    .to_string(),
+            );
+        }
+        if row_options {
+            return Err(format!(
+                "--approx は {} と同時に指定できません",
+                ROW_OPTIONS
+            ));
+        }
+    }
+    let check = match (check, check_tolerance) {
+        (None, None) => None,
+        (None, Some(_)) => {
+            return Err("--check-tolerance は --check と一緒に指定してください".to_string())
+        }
+        (Some(expected), tolerance) => Some(Check {
+            expected,
+            tolerance: tolerance.unwrap_or(check::DEFAULT_TOLERANCE),
+        }),
+    };
+    if check.is_some() {
+        if !matches!(output, Output::Csv(..)) {
+            return Err("--check は CSV の出力でのみ使えます".to_string());
+        }
+        if dry_run {
+            return Err("--check と --dry-run は同時に指定できません".to_string());
+        }
+    }
+    let provenance = match provenance {
+        Some(Some(path)) => Some(path),
+        Some(None) => match output.path() {
+            Some(path) => Some(format!("{}.provenance.json", path)),
+            None => return Err(
+                "出力先がファイルではないため、--provenance=FILE で来歴の出力先を指定してください"
+                    .to_string(),
+            ),
+        },
+        None => None,
+    };
+    Ok(Command::Run(Box::new(Options {
+        input: Input::parse(&input, input_options)?,
+        group_by,
+        filter,
+        group_by_template,
+        normalize_key,
+        keys: (keys.only.is_some() || !keys.exclude.is_empty()).then_some(keys),
+        validate_input,
+        metric,
+        backend,
+        dedup,
+        make_valid,
+        subtract,
+        agg,
+        by_geometry_type,
+        derived,
+        smooth,
+        keep,
+        classify,
+        class_totals,
+        sort,
+        top,
+        pivot,
+        compare_methods,
+        approx,
+        partition_by,
+        checkpoint_dir,
+        tmpdir,
+        tmpdir_limit,
+        memory_limit,
+        ring_report,
+        audit_log,
+        list_ids,
+        timing_json,
+        timeout,
+        dry_run,
+        term_map,
+        chart,
+        provenance,
+        arguments,
+        cache: cache.filter(|_| !no_cache),
+        check,
+        output,
+    })))
+}
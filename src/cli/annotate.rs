@@ -0,0 +1,65 @@
+//! `layon annotate` の引数の解析。
+
+use super::{parse_coord_precision, parse_metric, split_option, value, Command, DEFAULT_INPUT};
+use crate::{
+    aggregate::Metric,
+    mapping::PropertyMap,
+    sink::{check_output, FileFormat},
+    source::{Input, InputOptions},
+    transform::{GeometryTransform, Winding},
+};
+
+/// annotate サブコマンドの引数
+pub struct AnnotateOptions {
+    pub input: Input,
+    pub metric: Metric,
+    /// グループの面積の合計を加える場合の集計キー
+    pub group_by: Option<String>,
+    /// 出力するプロパティの対応（値を加えた後で適用する）
+    pub map: Option<PropertyMap>,
+    /// 書き出す GeoJSON のジオメトリの変換
+    pub transform: GeometryTransform,
+    /// 出力先の GeoJSON ファイル（"-" は標準出力）
+    pub output: String,
+}
+
+/// annotate サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut metric = Metric::Area;
+    let mut group_by = None;
+    let mut map = None;
+    let mut transform = GeometryTransform::default();
+    let mut output = "-".to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "--metric" => metric = parse_metric(&value(&name, inline, &mut args)?)?,
+            "-g" | "--group-by" => group_by = Some(value(&name, inline, &mut args)?),
+            "--map" => map = Some(PropertyMap::parse(&value(&name, inline, &mut args)?)?),
+            "--coord-precision" => {
+                transform.precision =
+                    Some(parse_coord_precision(&value(&name, inline, &mut args)?)?)
+            }
+            "--legacy-winding" => transform.winding = Winding::Legacy,
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ if !arg.starts_with('-') => input = arg,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    if output != "-" {
+        check_output("annotate", &output, &[FileFormat::GeoJson])?;
+    }
+    Ok(Command::Annotate(AnnotateOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        metric,
+        group_by,
+        map,
+        transform,
+        output,
+    }))
+}
@@ -0,0 +1,79 @@
+//! `layon batch` の引数の解析。
+
+use super::{
+    parse_aggregate_metric, split_option, value, Command, DEFAULT_GROUP_BY, DEFAULT_OUTPUT,
+};
+use crate::{
+    aggregate::Metric,
+    batch::Retry,
+    sink::{check_output, FileFormat},
+    source::{Input, InputOptions},
+};
+use std::time::Duration;
+
+/// batch サブコマンドの引数
+pub struct BatchOptions {
+    /// 集計する入力元（指定した順）
+    pub sources: Vec<String>,
+    pub group_by: String,
+    pub metric: Metric,
+    pub retry: Retry,
+    /// 失敗したファイルがあっても残りのファイルを集計するか
+    pub keep_going: bool,
+    pub output: String,
+}
+
+/// batch サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut sources = Vec::new();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut metric = Metric::Area;
+    let mut retry = Retry::default();
+    let mut keep_going = false;
+    let mut output = DEFAULT_OUTPUT.to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "--metric" => metric = parse_aggregate_metric(&value(&name, inline, &mut args)?)?,
+            "--retries" => {
+                let n = value(&name, inline, &mut args)?;
+                retry.retries = n
+                    .parse()
+                    .map_err(|_| format!("--retries の値が不正です: {}", n))?;
+            }
+            "--retry-delay" => {
+                let t = value(&name, inline, &mut args)?;
+                retry.delay = match t.parse::<f64>() {
+                    Ok(seconds) if seconds >= 0.0 && seconds.is_finite() => {
+                        Duration::from_secs_f64(seconds)
+                    }
+                    _ => return Err(format!("--retry-delay の値が不正です: {}", t)),
+                };
+            }
+            "--keep-going" => keep_going = true,
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ if !arg.starts_with('-') => sources.push(arg),
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    if sources.is_empty() {
+        return Err("batch には集計する入力元を指定してください".to_string());
+    }
+    check_output("batch", &output, &[FileFormat::Csv])?;
+    // 形式を判定できない入力元は、集計を始める前にエラーにする
+    for source in &sources {
+        Input::parse(source, InputOptions::default())?;
+    }
+    Ok(Command::Batch(BatchOptions {
+        sources,
+        group_by,
+        metric,
+        retry,
+        keep_going,
+        output,
+    }))
+}
@@ -0,0 +1,56 @@
+//! `layon bbox` の引数の解析。
+
+use super::{parse_coord_precision, split_option, value, Command, DEFAULT_GROUP_BY, DEFAULT_INPUT};
+use crate::{
+    sink::{check_output, FileFormat},
+    source::{Input, InputOptions},
+    transform::{GeometryTransform, Winding},
+};
+
+/// bbox サブコマンドの引数
+pub struct BboxOptions {
+    pub input: Input,
+    /// グループのキー（`--total-only` なら None で、全体の範囲だけを出力する）
+    pub group_by: Option<String>,
+    /// 書き出す GeoJSON のジオメトリの変換
+    pub transform: GeometryTransform,
+    /// 出力先（"-" は標準出力）
+    pub output: String,
+}
+
+/// bbox サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut group_by = Some(DEFAULT_GROUP_BY.to_string());
+    let mut transform = GeometryTransform::default();
+    let mut output = "-".to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = Some(value(&name, inline, &mut args)?),
+            "--total-only" => group_by = None,
+            "--coord-precision" => {
+                transform.precision =
+                    Some(parse_coord_precision(&value(&name, inline, &mut args)?)?)
+            }
+            "--legacy-winding" => transform.winding = Winding::Legacy,
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            // オプションでない引数は入力元として扱う
+            _ if !arg.starts_with('-') => input = arg,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    if output != "-" {
+        check_output("bbox", &output, &[FileFormat::Csv, FileFormat::GeoJson])?;
+    }
+    Ok(Command::Bbox(BboxOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        group_by,
+        transform,
+        output,
+    }))
+}
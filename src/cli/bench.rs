@@ -0,0 +1,41 @@
+//! `layon bench` の引数の解析。
+
+use super::{split_option, value, Command, DEFAULT_GROUP_BY, DEFAULT_INPUT};
+use crate::source::{Input, InputOptions};
+
+/// bench サブコマンドの引数
+pub struct BenchOptions {
+    pub input: Input,
+    pub group_by: String,
+    pub iterations: usize,
+}
+
+/// bench サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut iterations = 5;
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "-n" | "--iterations" => {
+                let n = value(&name, inline, &mut args)?;
+                iterations = match n.parse::<usize>() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("{} の値が不正です: {}", name, n)),
+                };
+            }
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    Ok(Command::Bench(BenchOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        group_by,
+        iterations,
+    }))
+}
@@ -0,0 +1,81 @@
+//! `layon breaks` の引数の解析。
+
+use super::{
+    parse_coord_precision, parse_metric, split_option, value, Command, DEFAULT_GROUP_BY,
+    DEFAULT_INPUT,
+};
+use crate::{
+    aggregate::Metric,
+    breaks::Method,
+    sink::{check_output, FileFormat},
+    source::{Input, InputOptions},
+    transform::{GeometryTransform, Winding},
+};
+
+/// breaks サブコマンドの引数
+pub struct BreaksOptions {
+    pub input: Input,
+    pub group_by: String,
+    pub metric: Metric,
+    pub method: Method,
+    pub classes: usize,
+    /// 階級を付けたジオメトリを出力する GeoJSON ファイル
+    pub geojson: Option<String>,
+    /// 書き出す GeoJSON のジオメトリの変換
+    pub transform: GeometryTransform,
+    /// 境界の出力先（"-" は標準出力）
+    pub output: String,
+}
+
+/// breaks サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut metric = Metric::Area;
+    let mut method = Method::Jenks;
+    let mut classes = 5;
+    let mut geojson = None;
+    let mut transform = GeometryTransform::default();
+    let mut output = "-".to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "--metric" => metric = parse_metric(&value(&name, inline, &mut args)?)?,
+            "--method" => method = Method::parse(&value(&name, inline, &mut args)?)?,
+            "-k" | "--classes" => {
+                let k = value(&name, inline, &mut args)?;
+                classes = match k.parse::<usize>() {
+                    Ok(k) if k > 0 => k,
+                    _ => return Err(format!("--classes の値が不正です: {}", k)),
+                };
+            }
+            "--geojson" => geojson = Some(value(&name, inline, &mut args)?),
+            "--coord-precision" => {
+                transform.precision =
+                    Some(parse_coord_precision(&value(&name, inline, &mut args)?)?)
+            }
+            "--legacy-winding" => transform.winding = Winding::Legacy,
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ if !arg.starts_with('-') => input = arg,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    if output != "-" {
+        check_output("breaks", &output, &[FileFormat::Csv])?;
+    }
+    Ok(Command::Breaks(BreaksOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        group_by,
+        metric,
+        method,
+        classes,
+        geojson,
+        transform,
+        output,
+    }))
+}
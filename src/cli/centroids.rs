@@ -0,0 +1,67 @@
+//! `layon centroids` の引数の解析。
+
+use super::{parse_coord_precision, split_option, value, Command, DEFAULT_GROUP_BY, DEFAULT_INPUT};
+use crate::{
+    sink::{check_output, FileFormat},
+    source::{Input, InputOptions},
+    transform::{GeometryTransform, Winding},
+};
+
+/// centroids サブコマンドの引数
+pub struct CentroidsOptions {
+    pub input: Input,
+    pub group_by: String,
+    pub precision: Option<f64>,
+    /// 書き出す GeoJSON のジオメトリの変換
+    pub transform: GeometryTransform,
+    /// 出力先（"-" は標準出力）
+    pub output: String,
+}
+
+/// centroids サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut precision = None;
+    let mut transform = GeometryTransform::default();
+    let mut output = "-".to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "--precision" => {
+                let p = value(&name, inline, &mut args)?;
+                precision = match p.parse::<f64>() {
+                    Ok(p) if p > 0.0 => Some(p),
+                    _ => return Err(format!("--precision の値が不正です: {}", p)),
+                };
+            }
+            "--coord-precision" => {
+                transform.precision =
+                    Some(parse_coord_precision(&value(&name, inline, &mut args)?)?)
+            }
+            "--legacy-winding" => transform.winding = Winding::Legacy,
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ if !arg.starts_with('-') => input = arg,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    if output != "-" {
+        check_output(
+            "centroids",
+            &output,
+            &[FileFormat::Csv, FileFormat::GeoJson],
+        )?;
+    }
+    Ok(Command::Centroids(CentroidsOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        group_by,
+        precision,
+        transform,
+        output,
+    }))
+}
@@ -0,0 +1,86 @@
+//! `layon generate` の引数の解析。
+
+use super::{parse_coord_precision, split_option, value, Command};
+use crate::{
+    generate::{self, Generator, PropertySpec},
+    transform::GeometryTransform,
+};
+
+/// generate サブコマンドの引数
+pub struct GenerateOptions {
+    pub generator: Generator,
+    /// 書き出す GeoJSON のジオメトリの変換
+    pub transform: GeometryTransform,
+    /// 出力先の GeoJSON ファイル（"-" は標準出力）
+    pub output: String,
+}
+
+/// generate サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut features = 1000;
+    let mut vertices = 16;
+    let mut schema = generate::DEFAULT_SCHEMA.to_string();
+    let mut bbox = generate::DEFAULT_BBOX;
+    let mut seed = 0;
+    let mut transform = GeometryTransform::default();
+    let mut output = "-".to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-n" | "--features" => {
+                let n = value(&name, inline, &mut args)?;
+                features = n
+                    .parse()
+                    .map_err(|_| format!("{} の値が不正です: {}", name, n))?;
+            }
+            "--vertices" => {
+                let n = value(&name, inline, &mut args)?;
+                vertices = match n.parse() {
+                    Ok(n) if n >= 3 => n,
+                    _ => return Err(format!("--vertices の値が不正です (3 以上): {}", n)),
+                };
+            }
+            "--schema" => schema = value(&name, inline, &mut args)?,
+            "--bbox" => {
+                let text = value(&name, inline, &mut args)?;
+                let values: Vec<f64> = text
+                    .split(',')
+                    .map(|v| v.trim().parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| format!("--bbox の値が不正です: {}", text))?;
+                bbox = match values[..] {
+                    [min_x, min_y, max_x, max_y] if min_x < max_x && min_y < max_y => {
+                        [min_x, min_y, max_x, max_y]
+                    }
+                    _ => return Err(format!("--bbox の値が不正です: {}", text)),
+                };
+            }
+            "--seed" => {
+                let n = value(&name, inline, &mut args)?;
+                seed = n
+                    .parse()
+                    .map_err(|_| format!("--seed の値が不正です: {}", n))?;
+            }
+            "--coord-precision" => {
+                transform.precision =
+                    Some(parse_coord_precision(&value(&name, inline, &mut args)?)?)
+            }
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    Ok(Command::Generate(GenerateOptions {
+        generator: Generator {
+            features,
+            vertices,
+            properties: PropertySpec::parse_schema(&schema)?,
+            bbox,
+            seed,
+        },
+        transform,
+        output,
+    }))
+}
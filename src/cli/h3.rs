@@ -0,0 +1,55 @@
+//! `layon h3` の引数の解析。
+
+use super::{parse_metric, split_option, value, Command, DEFAULT_GROUP_BY, DEFAULT_INPUT};
+use crate::{
+    aggregate::Metric,
+    h3,
+    sink::{check_output, FileFormat},
+    source::{Input, InputOptions},
+};
+
+/// h3 サブコマンドの引数
+pub struct H3Options {
+    pub input: Input,
+    pub group_by: String,
+    pub resolution: h3o::Resolution,
+    pub metric: Metric,
+    /// 出力先（"-" は標準出力）
+    pub output: String,
+}
+
+/// h3 サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut resolution = h3::DEFAULT_RESOLUTION;
+    let mut metric = Metric::GeodesicArea;
+    let mut output = "-".to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "-r" | "--resolution" => {
+                resolution = h3::parse_resolution(&value(&name, inline, &mut args)?)?
+            }
+            "--metric" => metric = parse_metric(&value(&name, inline, &mut args)?)?,
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ if !arg.starts_with('-') => input = arg,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    if output != "-" {
+        check_output("h3", &output, &[FileFormat::Csv])?;
+    }
+    Ok(Command::H3(H3Options {
+        input: Input::parse(&input, InputOptions::default())?,
+        group_by,
+        resolution,
+        metric,
+        output,
+    }))
+}
@@ -0,0 +1,67 @@
+//! `layon hierarchy` の引数の解析。
+
+use super::{parse_metric, split_option, value, Command, DEFAULT_INPUT};
+use crate::{
+    aggregate::Metric,
+    hierarchy,
+    sink::{check_output, FileFormat},
+    source::{Input, InputOptions},
+};
+
+/// hierarchy サブコマンドの引数
+pub struct HierarchyOptions {
+    pub input: Input,
+    /// 階層のプロパティ（上の階層から順）
+    pub levels: Vec<String>,
+    pub metric: Metric,
+    /// 出力先（"-" は標準出力）
+    pub output: String,
+}
+
+/// hierarchy サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut levels: Vec<String> = hierarchy::DEFAULT_LEVELS
+        .iter()
+        .map(|level| level.to_string())
+        .collect();
+    let mut metric = Metric::GeodesicArea;
+    let mut output = "-".to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "--levels" => {
+                levels = value(&name, inline, &mut args)?
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|level| !level.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                if levels.is_empty() {
+                    return Err("--levels に階層のプロパティを指定してください".to_string());
+                }
+            }
+            "--metric" => metric = parse_metric(&value(&name, inline, &mut args)?)?,
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ if !arg.starts_with('-') => input = arg,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    if output != "-" {
+        check_output(
+            "hierarchy",
+            &output,
+            &[FileFormat::Json, FileFormat::GraphMl],
+        )?;
+    }
+    Ok(Command::Hierarchy(HierarchyOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        levels,
+        metric,
+        output,
+    }))
+}
@@ -0,0 +1,72 @@
+//! `layon hull` の引数の解析。
+
+use super::{
+    parse_coord_precision, parse_metric, split_option, value, Command, DEFAULT_GROUP_BY,
+    DEFAULT_INPUT,
+};
+use crate::{
+    aggregate::Metric,
+    hull::Hull,
+    sink::{check_output, FileFormat},
+    source::{Input, InputOptions},
+    transform::{GeometryTransform, Winding},
+};
+
+/// hull サブコマンドの引数
+pub struct HullOptions {
+    pub input: Input,
+    pub group_by: String,
+    pub hull: Hull,
+    pub metric: Metric,
+    /// 書き出す GeoJSON のジオメトリの変換
+    pub transform: GeometryTransform,
+    /// 出力先（"-" は標準出力）
+    pub output: String,
+}
+
+/// hull サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut hull = Hull::Convex;
+    let mut metric = Metric::GeodesicArea;
+    let mut transform = GeometryTransform::default();
+    let mut output = "-".to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "--alpha" => {
+                let a = value(&name, inline, &mut args)?;
+                hull = match a.parse::<f64>() {
+                    Ok(alpha) if alpha > 0.0 && alpha.is_finite() => Hull::Concave { alpha },
+                    _ => return Err(format!("--alpha の値が不正です: {}", a)),
+                };
+            }
+            "--metric" => metric = parse_metric(&value(&name, inline, &mut args)?)?,
+            "--coord-precision" => {
+                transform.precision =
+                    Some(parse_coord_precision(&value(&name, inline, &mut args)?)?)
+            }
+            "--legacy-winding" => transform.winding = Winding::Legacy,
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ if !arg.starts_with('-') => input = arg,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    if output != "-" {
+        check_output("hull", &output, &[FileFormat::Csv, FileFormat::GeoJson])?;
+    }
+    Ok(Command::Hull(HullOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        group_by,
+        hull,
+        metric,
+        transform,
+        output,
+    }))
+}
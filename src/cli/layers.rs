@@ -0,0 +1,23 @@
+//! `layon layers` の引数の解析。
+
+use super::{split_option, value, Command};
+use crate::source::{Input, InputOptions};
+
+/// layers サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = None;
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = Some(value(&name, inline, &mut args)?),
+            _ if !arg.starts_with('-') => input = Some(arg),
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+    let input = input.ok_or("レイヤーを一覧する入力元を指定してください")?;
+    Ok(Command::Layers(Input::parse(
+        &input,
+        InputOptions::default(),
+    )?))
+}
@@ -0,0 +1,101 @@
+//! `layon locate` の引数の解析。
+
+use super::{parse_metric, split_option, value, Command, DEFAULT_GROUP_BY, DEFAULT_INPUT};
+use crate::{
+    aggregate::Metric,
+    locate::PointsCsv,
+    source::{Input, InputOptions},
+};
+use geo::Point;
+
+/// locate サブコマンドの引数
+pub struct LocateOptions {
+    pub input: Input,
+    /// 出力するポリゴンのプロパティ
+    pub columns: Vec<String>,
+    /// 探す点
+    pub points: Vec<Point<f64>>,
+    /// 点の代わりに読み込む点の CSV
+    pub points_csv: Option<PointsCsv>,
+    pub metric: Metric,
+    pub max_distance: Option<f64>,
+    /// 出力先（"-" は標準出力）
+    pub output: String,
+}
+
+/// locate サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut columns = None;
+    let mut points = Vec::new();
+    let mut points_csv = None;
+    let mut x_column = None;
+    let mut y_column = None;
+    let mut metric = Metric::Area;
+    let mut max_distance = None;
+    let mut output = "-".to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "--columns" => {
+                let list: Vec<String> = value(&name, inline, &mut args)?
+                    .split(',')
+                    .map(|column| column.trim().to_string())
+                    .filter(|column| !column.is_empty())
+                    .collect();
+                if list.is_empty() {
+                    return Err("--columns にプロパティを指定してください".to_string());
+                }
+                columns = Some(list);
+            }
+            "--points" => points_csv = Some(value(&name, inline, &mut args)?),
+            "--x-column" => x_column = Some(value(&name, inline, &mut args)?),
+            "--y-column" => y_column = Some(value(&name, inline, &mut args)?),
+            "--metric" => metric = parse_metric(&value(&name, inline, &mut args)?)?,
+            "--max-distance" => {
+                let d = value(&name, inline, &mut args)?;
+                max_distance = match d.parse::<f64>() {
+                    Ok(d) if d >= 0.0 => Some(d),
+                    _ => return Err(format!("--max-distance の値が不正です: {}", d)),
+                };
+            }
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            // 負の経度（-73.9,40.7 など）も点として受け付ける
+            _ if !arg.starts_with('-') || parse_point(&arg).is_some() => points.push(
+                parse_point(&arg).ok_or_else(|| format!("点の指定が不正です (X,Y): {}", arg))?,
+            ),
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+    match (&points_csv, points.is_empty()) {
+        (None, true) => return Err("探す点 (X,Y) か --points を指定してください".to_string()),
+        (Some(_), false) => return Err("点 (X,Y) と --points は同時に指定できません".to_string()),
+        _ => {}
+    }
+
+    Ok(Command::Locate(LocateOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        columns: columns.unwrap_or_else(|| vec![group_by]),
+        points,
+        points_csv: points_csv.map(|path| PointsCsv {
+            path,
+            x_column,
+            y_column,
+        }),
+        metric,
+        max_distance,
+        output,
+    }))
+}
+
+/// `X,Y` の点
+fn parse_point(text: &str) -> Option<Point<f64>> {
+    let (x, y) = text.split_once(',')?;
+    let point: Point<f64> = Point::new(x.trim().parse().ok()?, y.trim().parse().ok()?);
+    (point.x().is_finite() && point.y().is_finite()).then_some(point)
+}
@@ -0,0 +1,53 @@
+//! `layon mesh` の引数の解析。
+
+use super::{parse_metric, split_option, value, Command, DEFAULT_GROUP_BY, DEFAULT_INPUT};
+use crate::{
+    aggregate::Metric,
+    mesh::MeshLevel,
+    sink::{check_output, FileFormat},
+    source::{Input, InputOptions},
+};
+
+/// mesh サブコマンドの引数
+pub struct MeshOptions {
+    pub input: Input,
+    pub group_by: String,
+    pub level: MeshLevel,
+    pub metric: Metric,
+    /// 出力先（"-" は標準出力）
+    pub output: String,
+}
+
+/// mesh サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut level = MeshLevel::Third;
+    let mut metric = Metric::GeodesicArea;
+    let mut output = "-".to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "--level" => level = MeshLevel::parse(&value(&name, inline, &mut args)?)?,
+            "--metric" => metric = parse_metric(&value(&name, inline, &mut args)?)?,
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ if !arg.starts_with('-') => input = arg,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    if output != "-" {
+        check_output("mesh", &output, &[FileFormat::Csv])?;
+    }
+    Ok(Command::Mesh(MeshOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        group_by,
+        level,
+        metric,
+        output,
+    }))
+}
@@ -0,0 +1,1159 @@
+//! コマンドライン引数の解析。
+//!
+//! `USAGE` がサブコマンドとオプションの定義で、補完スクリプトと man ページもここから作る。
+//! 既定のサブコマンドと各サブコマンドの引数は、それぞれのモジュールで解析する。
+
+mod aggregate;
+mod annotate;
+mod batch;
+mod bbox;
+mod bench;
+mod breaks;
+mod centroids;
+mod generate;
+mod h3;
+mod hierarchy;
+mod hull;
+mod layers;
+mod locate;
+mod mesh;
+mod overlay;
+mod prefectures;
+mod schema;
+mod serve;
+mod stream;
+mod subset;
+mod timeseries;
+mod tui;
+mod verify;
+mod zonal;
+
+pub use aggregate::Options;
+pub use annotate::AnnotateOptions;
+pub use batch::BatchOptions;
+pub use bbox::BboxOptions;
+pub use bench::BenchOptions;
+pub use breaks::BreaksOptions;
+pub use centroids::CentroidsOptions;
+pub use generate::GenerateOptions;
+pub use h3::H3Options;
+pub use hierarchy::HierarchyOptions;
+pub use hull::HullOptions;
+pub use locate::LocateOptions;
+pub use mesh::MeshOptions;
+pub use overlay::OverlayOptions;
+pub use prefectures::PrefecturesOptions;
+pub use schema::SchemaOptions;
+pub use serve::ServeOptions;
+pub use stream::StreamOptions;
+pub use subset::{Selection, SubsetOptions};
+pub use timeseries::TimeSeriesOptions;
+pub use tui::TuiOptions;
+pub use verify::VerifyOptions;
+pub use zonal::ZonalOptions;
+
+use crate::{aggregate::Metric, source::Input, transform::MAX_PRECISION};
+
+/// 入力ファイルの既定値（引数なしで実行した場合はこれまで通りの動作になる）
+pub const DEFAULT_INPUT: &str = "src/N03-20240101_11.geojson";
+/// 集計キーの既定値（市町村名）
+pub const DEFAULT_GROUP_BY: &str = "N03_004";
+/// 出力先の既定値
+pub const DEFAULT_OUTPUT: &str = "output.csv";
+/// --approx で Feature を選ぶ確率の既定値
+pub const DEFAULT_SAMPLE: f64 = 0.1;
+/// prefectures の都道府県名のプロパティの既定値
+pub const DEFAULT_PREFECTURE: &str = "N03_001";
+/// prefectures の簡略化の許容誤差の既定値（度。約 100 m）
+pub const DEFAULT_SIMPLIFY: f64 = 0.001;
+
+pub const USAGE: &str = "\
+使い方: layon [オプション]
+        layon overlay [オプション]
+        layon zonal --raster <FILE> [オプション]
+        layon head [-n <N>] [オプション]
+        layon sample --fraction <F> [オプション]
+        layon bbox [SOURCE] [オプション]
+        layon layers <SOURCE>
+        layon annotate [SOURCE] [オプション]
+        layon centroids [SOURCE] [オプション]
+        layon hull [SOURCE] [オプション]
+        layon prefectures [SOURCE] [オプション]
+        layon hierarchy [SOURCE] [オプション]
+        layon mesh [SOURCE] [オプション]
+        layon h3 [SOURCE] [オプション]
+        layon breaks [SOURCE] [オプション]
+        layon locate [オプション] <X,Y>...
+        layon locate --points <CSV> [オプション]
+        layon verify --official <CSV> [オプション]
+        layon timeseries [オプション] <SOURCE>...
+        layon batch [オプション] <SOURCE>...
+        layon bench [オプション]
+        layon generate [オプション]
+        layon tui [SOURCE] [オプション]
+        layon stream [オプション]
+        layon serve [SOURCE] [オプション]
+        layon schema [SOURCE] [オプション]
+        layon completions <bash|zsh|fish>
+        layon man
+
+サブコマンド:
+  (省略)                 集計キーごとの面積を集計する
+  overlay                2 つのレイヤーを重ね、キーの組ごとの交差面積を集計する
+  zonal                  ラスターの画素値をポリゴンごとに集計する (平均・合計・ヒストグラム)
+  head                   先頭の Feature を GeoJSON で出力する
+  sample                 Feature を無作為に抽出して GeoJSON で出力する (テスト用の小さなデータ作りに)
+  annotate               入力の各 Feature に面積と周長 (とグループの面積の合計) をプロパティとして加えた GeoJSON を出力する
+  bbox                   全体とグループごとの範囲 (外接矩形) と重心を出力する
+  layers                 GeoPackage, MBTiles, ZIP などのレイヤーの名前、Feature の数、ジオメトリの種類を CSV で標準出力に書き出す
+  centroids              グループごとにラベルを置く代表点 (到達不能極) を出力する
+  hull                   グループごとの凸包 (または凹包) の面積と、ポリゴンが占める割合 (充填率) を出力する
+  prefectures            市区町村のポリゴンを都道府県ごとにディゾルブし、簡略化した境界と面積を出力する
+  hierarchy              都道府県 → 市区町村 → 区の包含関係を、面積を付けた節点と辺のグラフ (JSON / GraphML) で出力する
+  mesh                   標準地域メッシュ (1km / 500m) の区画で切り分け、集計キー × メッシュコードごとの面積を集計する
+  h3                     H3 のセルで覆い、集計キー × セルの番号ごとにセルが覆う面積を集計する
+  locate                 点 (経度,緯度など) を含む市町村を探す (どれにも含まれなければ最も近い市町村と距離)
+  breaks                 グループごとの面積から塗り分け地図の階級の境界 (自然分類・分位数・等間隔) を求める
+  timeseries             複数の年次のデータから市町村ごとの面積の推移を横持ちの表にし、合併・分割を記録する
+  verify                 楕円体上で計算した面積を公式の面積 (全国都道府県市区町村別面積調) と比べる
+  batch                  複数のファイル (例: 47 都道府県) をそれぞれ集計して 1 つの表にし、ファイルごとの結果の表を表示する
+  bench                  並列処理の分け方 (Feature の数 / 頂点数) ごとに集計の処理時間を比べる
+  generate               合成したポリゴンの GeoJSON を出力する (ベンチマーク用や、実データを共有できない不具合報告に)
+  tui                    集計結果を端末で対話的に見て回る (並べ替え、市町村の Feature の一覧、CSV への出力)
+  stream                 ストリームから Feature を受け取り続けて集計し、一定の間隔で集計結果を出力する
+  serve                  データセットを読み込んだまま HTTP で集計結果を返し、集計表と地図のページ (/dashboard) を出す
+  schema                 プロパティの型を推定し、Feature のプロパティを読む型付きの Rust の構造体を生成する
+  completions            シェルの補完スクリプト (bash / zsh / fish) を標準出力に書き出す
+  man                    man ページ (roff) を標準出力に書き出す (例: layon man > layon.1)
+
+オプション:
+  -i, --input <SOURCE>   入力元 (既定: src/N03-20240101_11.geojson)
+                           *.geojson                      GeoJSON ファイル
+                           *.geojsonl, *.ndjson など      1 行に 1 つの Feature を並べた GeoJSON (*.geojsons, *.jsonl も)
+                           *.geojson.gz, *.csv.zst など   gzip / zstd で圧縮した GeoJSON, 1 行 1 Feature の GeoJSON, CSV / TSV
+                           (bgzip や複数のフレームの zstd で圧縮したものはすべてのコアで並列に展開する)
+                           *.csv, *.tsv                   WKT / 16 進 WKB のジオメトリ列を持つ表
+                           *.kml, *.kmz                   KML/KMZ の Placemark (ポリゴン)
+                           *.osm.pbf                      OpenStreetMap の行政界 (boundary=administrative)
+                           *.mbtiles                      ベクタータイルのポリゴン
+                           *.gpkg                         GeoPackage のベクターのレイヤー
+                           *.zip                          GeoJSON, CSV, KML などのファイルをまとめた ZIP (ファイルごとのレイヤー。unzip を利用)
+                           *.gdb, *.dxf, *.shp など       GDAL のライブラリで読み込む (gdal フィーチャー有効時)
+                           postgres://…[?table=<TABLE>]   PostGIS から読み込む (psql を利用)
+      --sql <QUERY>      PostGIS から読み込むクエリ (例: 'SELECT * FROM n03')
+      --geometry-column <COLUMN>
+                         CSV / TSV のジオメトリ列 (既定: wkt, geom, geometry などを探す)
+      --admin-level <N>  OSM PBF から取り出す行政界の admin_level (例: 7 = 市区町村)
+      --zoom <Z>         MBTiles から読み込むズームレベル (既定: 最も詳細なレベル)
+      --layer <NAME>     GeoPackage, MBTiles, ZIP, FileGDB などから読み込むレイヤー (既定: すべてのレイヤー)
+                           (繰り返して指定すると複数のレイヤーを読み込む。複数のレイヤーの Feature を集計すると、
+                           CSV と JSON の出力にレイヤーの列 (Layer / layer) が加わり、レイヤーと集計キーの組ごとの行になる)
+      --skip-invalid     1 行 1 Feature の GeoJSON の解析できない行を、位置 (バイト目と行番号) を表示して読み飛ばす
+  -g, --group-by <PROPERTY>
+                         集計キーにするプロパティ (既定: N03_004、OSM なら name など)
+      --group-by-template <TEMPLATE>
+                         複数のプロパティから集計キーを作る (-g の代わり。例: '{N03_001}/{N03_004}')
+                           ({PROPERTY/REGEX/} は値の正規表現に一致した部分。例: '{N03_007/^(..)/}' は団体コードの先頭 2 桁)
+      --where <EXPR>     条件を満たす Feature だけを集計する
+                           (例: N03_001 == '埼玉県' && N03_004 != '秩父市'、比較は == != < <= > >=)
+      --only <KEYS>      集計キーがカンマで区切った一覧にある Feature だけを集計する (例: さいたま市,川越市)
+      --only-file <FILE> 集計キーがファイル (1 行に 1 つ。# で始まる行は読み飛ばす) にある Feature だけを集計する
+      --exclude <KEYS>   集計キーがカンマで区切った一覧にある Feature を除いて集計する
+      --exclude-file <FILE>
+                         集計キーがファイルにある Feature を除いて集計する
+                           (一覧は --normalize-key でそろえた後の集計キーと比べる)
+      --normalize-key <RULES>
+                         集計の前に集計キーの値をそろえる (カンマで区切って並べる。例: trim,width)
+                           trim                           前後の空白 (全角の空白も) を除く
+                           width                          全角の英数字・記号を半角に、半角カナを全角にそろえる
+                           honorific                      末尾の敬称 (様、殿、御中、さん) を除く
+                           case                           英字を小文字にそろえる
+                           all                            上のすべて
+      --metric <NAME>    集計する値 (既定: area)
+                           area                           座標の単位のままの面積
+                           geodesic-area                  楕円体上の面積 (km²、座標は経度・緯度)
+                           length                         線 (LineString) の大円距離の長さ (km、座標は経度・緯度。道路や河川のデータ向け)
+                           (length の値も Area の列に出力する。ポリゴンの周長は含めない)
+      --projection <NAME>
+                         指定した投影法で投影した平面上の面積を集計する (km²、座標は経度・緯度。--metric の代わりに指定する)
+                           jgd2011-albers                 日本全域向けのアルベルス正積円錐図法 (面積の歪みがない)
+                           utm-auto                       Feature の重心の経度からゾーンを選んだ UTM (面積の誤差は 0.2% 以内)
+                           laea-auto                      Feature ごとに重心を中心にしたランベルト正積方位図法 (最も正確だが jgd2011-albers より遅い)
+                           (utm-54 や utm-54s のように UTM のゾーンを指定することもできる)
+      --compare-methods  投影した平面上の面積と楕円体上の面積の両方で集計し、集計キーごとの差と相対差を CSV に出力する
+                           (投影法は --projection で選ぶ。既定: utm-auto)
+      --backend <NAME>   面積を計算するバックエンド (既定: cpu)
+                           cpu                            rayon で Feature ごとに並列に計算する
+                           gpu                            wgpu のコンピュートシェーダーで辺ごとに計算する (平面上の面積だけ。gpu フィーチャーでビルドした場合)
+      --approx           Feature を無作為に抽出して集計し、集計キーごとの合計を 95% 信頼区間付きで推定した概算を CSV に出力する
+                           (とても大きなデータで結果の見当をすばやく付けるために。列は EstimatedArea, AreaLow, AreaHigh など)
+      --sample <F>       --approx で Feature を選ぶ確率 (0 より大きく 1 以下、既定: 0.1)
+      --seed <N>         --approx の乱数の種 (既定: 0。同じ種なら同じ Feature が選ばれる)
+      --validate-input   集計の前に入力を RFC 7946 の規則で検証し、違反があれば Feature の ID とともに表示して終了する
+                           (crs メンバーがないこと、座標が経度・緯度の範囲にあること、リングが閉じていて外周が反時計回りであること)
+      --dedup            集計の前に重複した Feature (ジオメトリと属性が同じもの) を取り除く
+      --dedup-tolerance <T>
+                         座標をこの幅の格子に丸めて比べ、近い重複も取り除く (--dedup も有効になる)
+      --dedup-report <FILE>
+                         取り除いた Feature の一覧を CSV に出力する (--dedup も有効になる)
+      --agg <SPEC>       数値のプロパティもグループごとに集計する (CSV と JSON、テンプレートの出力のみ)
+                           (プロパティ:方法 をカンマで区切る。例: pop:sum,pop:area_weighted_mean)
+                           (方法: sum, mean, min, max, area_weighted_mean (面積で重み付けした平均),
+                            area_weighted_sum (値と面積の積の合計))
+      --by-geometry-type 種類ごとの Feature の数と線の長さ、点の数も集計する (CSV と JSON、テンプレートの出力のみ)
+                           (列: polygon_count, line_count, line_length, point_count。長さは geodesic-area なら km)
+      --derive <EXPR>    集計した後に式で計算した列を加える (CSV と JSON、テンプレートの出力のみ。複数指定できる)
+                           (例: 'density = pop_sum / area'。列は area, count, --agg の列と前に定義した列、演算は + - * / と括弧)
+      --smooth <COLUMN>  列の値を、隣接する市町村の値と共有する境界の長さで重み付けして平均した <COLUMN>_smoothed 列を加える
+                           (CSV と JSON、テンプレートの出力のみ。自分と隣接する市町村全体を半々に平均する。隣接は頂点を共有する辺で判定)
+      --keep <PROPERTIES>
+                         プロパティの値をそのまま引き継いだ列を加える (CSV と JSON、テンプレートの出力のみ。例: N03_001,N03_007)
+      --keep-conflict <POLICY>
+                         同じグループの Feature で --keep の値が食い違う場合 (既定: first。食い違いは警告で知らせる)
+                           first                          入力で最初に現れた値
+                           error                          エラーにして、食い違ったグループと値を表示する
+                           list-all                       すべての値を現れた順に '; ' でつなぐ
+                           majority                       最も多くの Feature が持つ値 (同じ数なら先に現れた値)
+      --sort <COLUMN>    結果を並べる列 (既定: area。:asc を付けると昇順、値のない行は最後)
+      --top <N>          並べた結果の先頭の N 行だけを出力する
+      --classify <SPEC>  列の値で階級分けした Class 列を加える (CSV と JSON、テンプレートの出力のみ。規則は先頭から順に比べる)
+                           (例: 'area: <10=small, <100=medium, else=large'。比較は < <= > >=)
+      --class-totals <FILE>
+                         階級ごとの小計 (行の数、Feature の数、面積) を CSV に出力する (--classify と使う)
+      --pivot <SPEC>     縦持ちの表の代わりに、2 つのプロパティの組ごとのクロス集計の表を CSV に出力する
+                           (例: rows=N03_001,cols=N03_003,value=area。value は area (既定) か count。行と列の合計を付ける)
+      --ring-report <FILE>
+                         Feature ごとの外周の面積、穴の数と面積、差し引いた面積を CSV に出力する (湖などの穴の確認に)
+      --audit-log <FILE>
+                         前処理で外した Feature とジオメトリを変えた Feature を、理由と前後の面積とともに CSV に出力する
+                           (条件式や --only で外したもの、重複、集計キーのないもの、--make-valid や --subtract で変えたもの)
+      --list-ids <FILE>  グループごとに集計した Feature の ID (なければ入力の中での位置 #0, #1, …) を CSV に出力する
+      --timing-json <FILE>
+                         段階ごと (read, parse, prepare, convert, compute, reduce, write) の処理時間を JSON に出力する (内訳を端末にも表示する)
+      --subtract <SOURCE>
+                         集計の前に別のレイヤー (湖や河川などの水域) のポリゴンを差し引き、陸地の面積を求める (形式は --input と同じ)
+      --make-valid       自己交差したポリゴン (8 の字など) を交差する点で分けて修復してから集計する
+      --timeout <SECONDS>
+                         この秒数を過ぎたら集計を中断し、それまでの結果を出力する
+                           (SIGINT / SIGTERM を受け取った場合も同様。2 回目ですぐ終了する)
+      --partition-by <PROPERTY>
+                         Feature をこのプロパティ (例: N03_001) の値ごとに分け、分割ごとに並列に集計してからまとめる
+                           (全国のデータで集計用の表がキャッシュに載りやすくなる。集計キーが複数の分割にあると --agg の平均はエラー)
+      --checkpoint-dir <DIR>
+                         集計し終えた分割の結果をこのディレクトリに保存し、次の実行では保存した分割を集計し直さない
+                           (--partition-by と一緒に指定する。中断した全国の集計を続きから再開する場合に)
+      --tmpdir <DIR>     途中のデータを書き出す一時ディレクトリを作る場所 (既定: TMPDIR か /tmp。終了時に消す)
+                           (指定すると --partition-by の分割の Feature を集計するまでここに書き出し、メモリを空ける。
+                           ZIP の展開にも使う。強制終了で残ったディレクトリは次の実行で消す)
+      --tmpdir-limit <SIZE>
+                         一時ディレクトリに書き出す大きさの上限 (例: 512M, 20G。超える前にエラーで止める)
+      --memory-limit <SIZE>
+                         集計用の表の大きさの目安がこれを超えたら、表を一時ディレクトリに書き出しながら集計する
+                           (メッシュコードや筆 ID など集計キーがとても多い場合に。例: 4G。面積と Feature の数だけを集計し、
+                           --agg, --list-ids, --by-geometry-type, --partition-by やジオメトリを使う出力とは同時に指定できない)
+      --dry-run          集計せずに、実行計画と設定の確認結果 (集計キー、座標、出力先) を表示する
+      --term-map[=WIDTH] 集計値の四分位で塗り分けた粗い地図を端末に表示する (既定の幅: 端末の幅か 80 桁)
+      --chart[=N]        面積の大きい上位 N 個のグループを横棒グラフで端末に表示する (既定: 20)
+      --provenance[=FILE]
+                         入力と出力の SHA-256、バージョン、オプション、時刻を JSON に記録する (既定: <出力先>.provenance.json)
+      --cache-dir <DIR>  入力ファイルの内容とオプションが前回と同じなら、このディレクトリにキャッシュした結果をそのまま出力する
+                           (LAYON_CACHE_DIR で指定しておくと、同じ集計を繰り返すノートブックや CI で速くなる。
+                           出力先がファイルで、--ring-report などのほかの出力がない場合だけ使う)
+      --no-cache         キャッシュを使わずに集計する (--cache-dir や LAYON_CACHE_DIR を指定していても)
+      --check <CSV>      出力した CSV を期待する結果の CSV と比べ、違いを行ごとに表示してエラーで終了する
+                           (境界データを更新したときに集計結果が変わっていないことを CI で確かめるために。
+                           行は 1 列目 (レイヤーの列があればレイヤーと 2 列目) の値で対応付ける。CSV の出力のみ)
+      --check-tolerance <T>
+                         --check で数値を同じとみなす相対誤差の上限 (既定: 1e-6。期待値が 0 なら差の絶対値の上限)
+      --number-format <STYLE>
+                         CSV に書き出す数値の書き方 (既定: plain)
+                           plain                          値を表すのに必要な桁だけ (例: 0.06868719372434)
+                           fixed                          小数点以下の桁数をそろえる (既定: 6 桁)
+                           scientific                     指数表記 (例: 6.868719e-2)
+      --decimals <N>     小数点以下の桁数 (--number-format を指定しなければ fixed になる)
+      --thousands-separator <CHAR>
+                         整数部を 3 桁ごとに区切る文字 (例: ',' なら 1,234.5。指数表記では使わない)
+      --decimal-mark <CHAR>
+                         小数点の文字 (既定: '.'。ドイツ語やフランス語のロケールの表計算ソフトなら ',')
+      --delimiter <CHAR> CSV の区切り文字 (既定: ','。タブなら tab)
+      --quote <STYLE>    CSV の値を引用符で囲む場合 (既定: necessary)
+                           necessary                      区切り文字や引用符、改行を含む値だけ
+                           always                         すべての値
+                           non-numeric                    数値でない値
+                           never                          囲まない (区切り文字を含む値があると読み戻せない)
+      --line-ending <STYLE>
+                         CSV の行の終わり (既定: lf)
+                           lf                             LF
+                           crlf                           CRLF (Windows のソフトや RFC 4180 に厳密なパーサー向け)
+      --bom              CSV の先頭に UTF-8 の BOM を書く (日本語版の Excel で文字化けせずに開けるように)
+  -o, --output <TARGET>  出力先 (既定: output.csv。--template を使わない場合は次のいずれか)
+                           *.csv                          CSV ファイル
+                           *.json                         JSON ファイル (key, area, count の配列)
+                           *.arrow, *.feather             Arrow IPC ファイル形式
+                           *.arrows                       Arrow IPC ストリーミング形式
+                           *.sql                          PostGIS 向けの SQL スクリプト (集計キーの列名は集計キーのプロパティの名前を小文字にしたもの)
+                           postgres://…?table=<TABLE>     PostGIS に直接書き込む (psql を利用)
+                             (集計キーの列名は &key=<COLUMN> で指定する。既定: 集計キーのプロパティの名前を小文字にしたもの)
+                           redis://…[/DB][?prefix=<P>]    Redis のキー <P><集計キー> に面積、<P><集計キー>:count に数を書き込む (既定の P: layon:)
+      --template <FILE>  -o のファイルを Handlebars の書式のテンプレートで書く (Markdown の報告書や LaTeX の表など)
+                           ({{#each rows}}, {{#if}}, {{round area 2}}, {{{latex key}}} などが使える。先頭の文脈は rows, total, input, group_by, metric)
+  -h, --help             このヘルプを表示する
+
+overlay のオプション:
+  -i, --input <SOURCE>   左側のレイヤー (既定: src/N03-20240101_11.geojson)
+      --overlay <SOURCE> 右側のレイヤー (形式は --input と同じ、例: 浸水想定区域)
+  -g, --group-by <PROPERTY>
+                         左側のレイヤーの集計キー (既定: N03_004)
+      --overlay-key <PROPERTY>
+                         右側のレイヤーの集計キー (例: A31_205)
+      --metric <NAME>    交差面積の計算方法 (既定: area = 座標の単位、geodesic-area = 楕円体上の km²)
+      --projection <NAME>
+                         指定した投影法で投影した平面上の交差面積を集計する (km²、--metric の代わりに指定する。投影法は aggregate と同じ)
+  -o, --output <FILE>    出力先の CSV ファイル (既定: output.csv)。City, Zone, Area の縦持ちの表になる
+
+zonal のオプション:
+      --raster <FILE>    集計するラスター (GeoTIFF、1 バンド目を使う。座標系は入力と同じであること)
+  -i, --input <SOURCE>   ポリゴンのレイヤー (既定: src/N03-20240101_11.geojson)
+  -g, --group-by <PROPERTY>
+                         集計キー (既定: N03_004)
+      --histogram        統計の代わりに画素値ごとの画素数を City, Value, Count の表で出力する
+      --bin-width <W>    ヒストグラムの階級の幅 (既定: 値ごと。指定すると --histogram も有効になる)
+      --bands <LIST>     統計の代わりに値の帯ごとの面積を City, Band, Pixels, Area の表で出力する
+                           (境界をカンマで区切る。例: 10,100 で 10 未満、10 以上 100 未満、100 以上。標高帯の集計に)
+      --metric <NAME>    --bands の面積 (既定: area = 座標の単位、geodesic-area = 楕円体上の km²)
+      --chart            平均値の横棒グラフ (--histogram ではすべてのポリゴンを合わせたヒストグラム、--bands では帯ごとの面積) を端末に表示する
+  -o, --output <FILE>    出力先の CSV ファイル (既定: output.csv)
+
+head / sample のオプション:
+  -i, --input <SOURCE>   入力元 (既定: src/N03-20240101_11.geojson)
+  -n, --count <N>        head で出力する Feature の数 (既定: 10)
+      --fraction <F>     sample で各 Feature を選ぶ確率 (0〜1、例: 0.01)
+      --seed <N>         sample の乱数の種 (既定: 0。同じ種なら同じ Feature が選ばれる)
+      --map <MAP>        残すプロパティと出力する名前 (例: 'N03_001=prefecture,N03_004=city'。= のない名前はそのまま残す)
+      --coord-precision <N>
+                         GeoJSON の座標を小数点以下 N 桁に丸める (例: 6 = 約 10 cm。出力が小さくなる)
+      --legacy-winding   GeoJSON のポリゴンの外周を時計回りにする (既定: RFC 7946 の通り反時計回り)
+  -o, --output <FILE>    出力先の GeoJSON ファイル (既定: - = 標準出力)
+
+annotate のオプション:
+  SOURCE, -i, --input <SOURCE>
+                         入力元 (既定: src/N03-20240101_11.geojson)
+      --metric <NAME>    面積と周長の計算方法 (既定: area = 座標の単位。geodesic-area なら km² と km)
+  -g, --group-by <PROPERTY>
+                         指定すると、この値ごとの面積の合計を group_area として加える
+      --map <MAP>        残すプロパティと出力する名前 (例: 'N03_001=prefecture,N03_004=city'。area などの加えた値も対象。= のない名前はそのまま残す)
+      --coord-precision <N>
+                         GeoJSON の座標を小数点以下 N 桁に丸める (例: 6 = 約 10 cm。出力が小さくなる)
+      --legacy-winding   GeoJSON のポリゴンの外周を時計回りにする (既定: RFC 7946 の通り反時計回り)
+  -o, --output <FILE>    出力先の GeoJSON ファイル (既定: - = 標準出力)
+
+bbox のオプション:
+  SOURCE, -i, --input <SOURCE>
+                         入力元 (既定: src/N03-20240101_11.geojson)
+  -g, --group-by <PROPERTY>
+                         全体に加えて範囲を出力するグループのキー (既定: N03_004)
+      --total-only       全体の範囲だけを出力する
+      --coord-precision <N>
+                         GeoJSON の座標を小数点以下 N 桁に丸める (例: 6 = 約 10 cm。出力が小さくなる)
+      --legacy-winding   GeoJSON のポリゴンの外周を時計回りにする (既定: RFC 7946 の通り反時計回り)
+  -o, --output <TARGET>  出力先 (既定: - = 標準出力に CSV)
+                           *.geojson                      範囲を長方形の Feature にした GeoJSON
+                           それ以外                        CSV ファイル
+
+centroids のオプション:
+  SOURCE, -i, --input <SOURCE>
+                         入力元 (既定: src/N03-20240101_11.geojson)
+  -g, --group-by <PROPERTY>
+                         グループのキー (既定: N03_004)
+      --precision <P>    代表点を探す精度 (座標の単位、既定: ポリゴンの大きさの 1/1000)
+      --coord-precision <N>
+                         GeoJSON の座標を小数点以下 N 桁に丸める (例: 6 = 約 10 cm。出力が小さくなる)
+      --legacy-winding   GeoJSON のポリゴンの外周を時計回りにする (既定: RFC 7946 の通り反時計回り)
+  -o, --output <TARGET>  出力先 (既定: - = 標準出力に CSV)
+                           *.geojson                      点の GeoJSON
+                           それ以外                        CSV ファイル
+
+hull のオプション:
+  SOURCE, -i, --input <SOURCE>
+                         入力元 (既定: src/N03-20240101_11.geojson)
+  -g, --group-by <PROPERTY>
+                         グループのキー (既定: N03_004)
+      --alpha <R>        凸包の代わりに凹包 (アルファシェイプ) を求める (離れた島は別の部分になる)
+                           (頂点のドロネー三角形分割のうち、外接円の半径が R 以下の三角形を残す。座標の単位。例: 0.02 = 約 2 km)
+      --metric <NAME>    面積 (既定: geodesic-area = km²)
+      --coord-precision <N>
+                         GeoJSON の座標を小数点以下 N 桁に丸める (例: 6 = 約 10 cm。出力が小さくなる)
+      --legacy-winding   GeoJSON のポリゴンの外周を時計回りにする (既定: RFC 7946 の通り反時計回り)
+  -o, --output <TARGET>  出力先 (既定: - = 標準出力に CSV)
+                           *.geojson                      包む形に名前 (name)、面積 (area, hull_area)、充填率 (fill_ratio) を付けた GeoJSON
+                           それ以外                        CSV ファイル (Name, Area, HullArea, FillRatio)
+
+prefectures のオプション:
+  SOURCE, -i, --input <SOURCE>
+                         入力元 (既定: src/N03-20240101_11.geojson)
+  -g, --group-by <PROPERTY>
+                         都道府県名のプロパティ (既定: N03_001)
+      --metric <NAME>    集計する値 (既定: geodesic-area = km²)
+      --simplify <TOLERANCE>
+                         境界を簡略化する許容誤差 (座標の単位、既定: 0.001 = 約 100 m、0 なら簡略化しない)
+                           (面積は簡略化する前の境界で求める)
+      --coord-precision <N>
+                         GeoJSON の座標を小数点以下 N 桁に丸める (例: 6 = 約 10 cm。出力が小さくなる)
+      --legacy-winding   GeoJSON のポリゴンの外周を時計回りにする (既定: RFC 7946 の通り反時計回り)
+      --title <TEXT>     HTML の出力の見出し (既定: <入力元> の <PROPERTY> ごとの集計)
+  -o, --output <TARGET>  出力先 (既定: prefectures.geojson)
+                           *.geojson                      境界に名前 (name)、面積 (area)、Feature の数 (count) を付けた GeoJSON
+                           *.html                         境界、集計結果、地図のビューアーを 1 つにまとめた HTML
+                                                          (データは JSON のまま埋め込む。保存や共有に)
+                           それ以外                        面積の CSV ファイル (Name, Area, Count。- = 標準出力)
+
+hierarchy のオプション:
+  SOURCE, -i, --input <SOURCE>
+                         入力元 (既定: src/N03-20240101_11.geojson)
+      --levels <PROPERTIES>
+                         上の階層から順に、カンマで区切った階層のプロパティ (既定: N03_001,N03_004,N03_005)
+                           (値が null や空の階層は飛ばす。例: N03_001,N03_003,N03_004,N03_005 なら郡も節点にする)
+      --metric <NAME>    節点の面積 (既定: geodesic-area = km²)
+  -o, --output <TARGET>  出力先 (既定: - = 標準出力に JSON)
+                           *.graphml                      GraphML
+                           それ以外                        JSON (nodes と edges の配列)
+
+mesh のオプション:
+  SOURCE, -i, --input <SOURCE>
+                         入力元 (既定: src/N03-20240101_11.geojson、座標は経度・緯度)
+  -g, --group-by <PROPERTY>
+                         集計キーのプロパティ (既定: N03_004)
+      --level <SIZE>     メッシュの大きさ (既定: 1km)
+                           1km                            3 次メッシュ (8 桁のコード)
+                           500m                           2 分の 1 地域メッシュ (9 桁のコード)
+      --metric <NAME>    交差部分の面積 (既定: geodesic-area = km²)
+  -o, --output <TARGET>  出力先 (既定: - = 標準出力に CSV。City, Mesh, Area)
+
+h3 のオプション:
+  SOURCE, -i, --input <SOURCE>
+                         入力元 (既定: src/N03-20240101_11.geojson、座標は経度・緯度)
+  -g, --group-by <PROPERTY>
+                         集計キーのプロパティ (既定: N03_004)
+  -r, --resolution <N>   H3 の解像度 (0 から 15、既定: 8 = セルの平均の面積が約 0.74 km²)
+      --metric <NAME>    セルが覆う部分の面積 (既定: geodesic-area = km²)
+  -o, --output <TARGET>  出力先 (既定: - = 標準出力に CSV。City, H3, Area)
+
+breaks のオプション:
+  SOURCE, -i, --input <SOURCE>
+                         入力元 (既定: src/N03-20240101_11.geojson)
+  -g, --group-by <PROPERTY>
+                         集計キー (既定: N03_004)
+      --metric <NAME>    集計する値 (既定: area。geodesic-area なら km²)
+      --method <NAME>    境界の求め方 (既定: jenks)
+                           jenks                          階級内の分散の合計が最小になるように分ける (自然分類)
+                           quantile                       各階級のグループの数がほぼ同じになるように分ける
+                           equal-interval                 最小値から最大値までを同じ幅に分ける
+  -k, --classes <N>      階級の数 (既定: 5)
+      --geojson <FILE>   グループのジオメトリに階級 (class、1 始まり) を付けた GeoJSON も出力する
+      --coord-precision <N>
+                         GeoJSON の座標を小数点以下 N 桁に丸める (例: 6 = 約 10 cm。出力が小さくなる)
+      --legacy-winding   GeoJSON のポリゴンの外周を時計回りにする (既定: RFC 7946 の通り反時計回り)
+  -o, --output <FILE>    境界の CSV (Class, Lower, Upper, Groups) の出力先 (既定: - = 標準出力)
+
+locate のオプション:
+  -i, --input <SOURCE>   ポリゴンのレイヤー (既定: src/N03-20240101_11.geojson)
+  -g, --group-by <PROPERTY>
+                         出力するポリゴンのプロパティ (既定: N03_004)
+      --columns <LIST>   出力するポリゴンのプロパティをカンマで区切って並べる (例: N03_001,N03_004。-g の代わりに)
+      --points <CSV>     点の CSV ファイル (- なら標準入力)。少しずつ読み込んで並列に検索し、
+                         元の行の後ろにプロパティと Distance の列を加えて出力する (数百万行の逆ジオコーディングに)
+      --x-column <COLUMN>
+                         点の CSV の X (経度) の列 (既定: x, lon, longitude などの見出しを探す)
+      --y-column <COLUMN>
+                         点の CSV の Y (緯度) の列 (既定: y, lat, latitude などの見出しを探す)
+      --metric <NAME>    距離の単位 (既定: area = 座標の単位。geodesic-area なら km、座標は経度・緯度)
+      --max-distance <D> どのポリゴンにも含まれない点は、この距離までのポリゴンだけを探す (既定: 制限なし)
+  -o, --output <FILE>    出力先の CSV ファイル (既定: - = 標準出力)。
+                         X, Y, <PROPERTY>, Distance の表になる (含まれる点の距離は 0、見つからなければ空欄)
+
+verify のオプション:
+      --official <CSV>   公式の面積の表 (UTF-8 の CSV。面積は km²)
+      --official-key <COLUMN>
+                         公式の表の市区町村名 (--join code ならコード) の列 (既定: 市区町村名 や 団体コード などの見出しを探す)
+      --official-prefecture <COLUMN>
+                         公式の表の都道府県名の列 (--join prefecture の場合。既定: 都道府県名 などの見出しを探す)
+      --official-area <COLUMN>
+                         公式の表の面積の列 (既定: 面積 などの見出しを探す。値は先頭の数を使い、後ろの注記は無視する)
+      --join <KIND>      公式の表と突き合わせる値 (既定: name)
+                           name                           市区町村名
+                           prefecture                     都道府県名と市区町村名の組 (同じ名前の市町村を区別する)
+                           code                           全国地方公共団体コード (6 桁の検査数字付きのコードも使える)
+  -i, --input <SOURCE>   入力元 (既定: src/N03-20240101_11.geojson、座標は経度・緯度)
+  -g, --group-by <PROPERTY>
+                         公式の表の市区町村名 (--join code ならコード) と突き合わせるプロパティ (既定: N03_004、--join code なら N03_007)
+      --prefecture-by <PROPERTY>
+                         公式の表の都道府県名と突き合わせるプロパティ (--join prefecture の場合。既定: N03_001)
+  -o, --output <FILE>    出力先の CSV ファイル (既定: output.csv)。
+                         City, Computed, Official, Error, RelativeError の表になる
+
+timeseries のオプション:
+  <SOURCE>...            年次ごとの入力元 (古い順)。[LABEL=]SOURCE の形式で、
+                         LABEL を省略するとファイル名の先頭の数字 4 桁 (例: N03-20240101 なら 2024) を使う
+  -g, --group-by <PROPERTY>
+                         集計キー (既定: N03_004)
+  -o, --output <FILE>    出力先の CSV ファイル (既定: output.csv)。
+                         City, <年次>..., Changes の表になる (Changes は合併・分割・名称変更など)
+
+batch のオプション:
+  <SOURCE>...            集計する入力元 (形式は --input と同じ)
+  -g, --group-by <PROPERTY>
+                         集計キー (既定: N03_004)
+      --metric <NAME>    集計する値 (既定: area。geodesic-area なら楕円体上の km²、length なら線の長さの km)
+      --retries <N>      失敗したファイルを試し直す回数 (既定: 0)
+      --retry-delay <SECONDS>
+                         1 回目の試し直しまでの待ち時間 (既定: 1。その後は倍ずつ長くする)
+      --keep-going       試し直しても失敗したファイルを記録して次のファイルへ進む
+                           (指定しなければ最初に失敗したファイルで止め、結果を出力しない。
+                           指定しても失敗したファイルがあれば、集計できたファイルを出力した後に終了コード 1 で終わる)
+  -o, --output <FILE>    出力先の CSV ファイル (既定: output.csv)。Source, City, Area, Count の表になる
+
+bench のオプション:
+  -i, --input <SOURCE>   入力元 (既定: src/N03-20240101_11.geojson)
+  -g, --group-by <PROPERTY>
+                         集計キー (既定: N03_004)
+  -n, --iterations <N>   分け方ごとに集計を繰り返す回数 (既定: 5)
+
+generate のオプション:
+  -n, --features <N>     生成する Feature の数 (既定: 1000。格子状に並べ、互いに重ならない)
+      --vertices <N>     1 つのポリゴンの頂点の数 (既定: 16、3 以上)
+      --schema <SCHEMA>  プロパティの定義を NAME:KIND[:ARG] のカンマ区切りで (既定: N03_004:category:20)
+                           NAME:category[:N]       NAME_1 〜 NAME_N の N 種類の文字列 (既定: 10)
+                           NAME:int[:MIN..MAX]     範囲内の整数 (既定: 0..100000)
+                           NAME:float[:MIN..MAX]   範囲内の小数 (既定: 0..1)
+                           NAME:sequence           0 から順に振った番号
+      --bbox <MINX,MINY,MAXX,MAXY>
+                         ポリゴンを並べる範囲 (既定: 138.7,35.75,139.9,36.3 = 埼玉県のあたりの経度・緯度)
+      --seed <N>         乱数の種 (既定: 0。同じ種なら同じデータになる)
+      --coord-precision <N>
+                         GeoJSON の座標を小数点以下 N 桁に丸める (例: 6 = 約 10 cm。出力が小さくなる)
+  -o, --output <FILE>    出力先の GeoJSON ファイル (既定: - = 標準出力)
+
+tui のオプション:
+  SOURCE, -i, --input <SOURCE>
+                         入力元 (既定: src/N03-20240101_11.geojson)
+  -g, --group-by <PROPERTY>
+                         集計キー (既定: N03_004)
+      --metric <NAME>    集計する値 (既定: area。geodesic-area も指定できる)
+  -o, --output <FILE>    e キーで一覧を書き出す CSV ファイル (既定: output.csv)
+
+stream のオプション:
+      --source <SOURCE>  Feature を受け取る元 (既定: -)
+                           -                              標準入力 (1 行 1 Feature の GeoJSON。Kafka は kcat -C などから流す)
+                           nats://host[:port]/subject     NATS の subject を購読する (メッセージは Feature の GeoJSON)
+  -g, --group-by <PROPERTY>
+                         集計キー (既定: N03_004)
+      --metric <NAME>    集計する値 (既定: area。geodesic-area も指定できる)
+      --where <EXPR>     条件を満たす Feature だけを集計する
+      --interval <SECONDS>
+                         集計結果を出力する間隔 (既定: 10 秒。受け取り元が終わったときと中断したときにも出力する。
+                           前の出力が終わっていなければ、その回は出力しない)
+  -o, --output <TARGET>  出力先 (既定: output.csv。*.csv, *.json, *.arrow, *.arrows, redis://…。毎回その時点の集計結果で上書きする)
+
+serve のオプション:
+  SOURCE, -i, --input <SOURCE>
+                         入力元 (既定: src/N03-20240101_11.geojson)
+  -g, --group-by <PROPERTY>
+                         既定の集計キー (既定: N03_004)
+      --metric <NAME>    既定の集計値 (既定: area。geodesic-area, length, 投影法の名前も指定できる)
+      --listen <ADDR>    待ち受けるアドレス (既定: 127.0.0.1:8080)
+      --grpc <ADDR>      HTTP と並べて gRPC のサービス (proto/layon.proto) を待ち受けるアドレス (grpc フィーチャー有効時)
+                           (Aggregate, Locate, Convert。データセットは HTTP と共有する)
+      --dataset <NAME>   起動時のデータセットの名前 (既定: default。追い出さず、dataset を省略したときに使う)
+      --max-datasets <N> 起動時のものも含めてメモリに置くデータセットの数 (既定: 4、1 なら登録を受け付けない)
+                           (超えたら最も長く使われていないものから追い出す)
+      --allow-register <DIR>
+                         POST /datasets (と gRPC の Convert) で DIR の下のファイルを読み込めるようにする
+                           (指定しなければ受け付けない。登録の API には認証がないため、信頼できるネットワークでだけ使う)
+      --allow-external-sources
+                         PostGIS (postgres://) と外部コマンド (unzip, psql) を使う入力元の登録も受け付ける
+      --simplify <TOLERANCE>
+                         地図の境界を簡略化する許容誤差 (座標の単位、既定: 0.001 = 約 100 m、0 なら簡略化しない)
+                           (GET /dashboard                ダッシュボード。集計表、地図、集計キー・集計値・条件式の入力欄)
+                           (GET /api/aggregate            集計結果の JSON。クエリ: group_by, metric, where)
+                           (GET /api/boundaries           ディゾルブして簡略化した境界の GeoJSON。クエリ: group_by)
+                           (GET /api/locate               点を含む (なければ最も近い) Feature。クエリ: x, y)
+                           (GET /datasets                 読み込んだデータセットの一覧。/api/* はクエリの dataset で選ぶ)
+                           (POST /datasets                {\"name\": …, \"source\": …} の入力元を読み込んで加える。--allow-register のとき)
+                           (DELETE /datasets/<NAME>       データセットを取り除く)
+
+schema のオプション:
+  SOURCE, -i, --input <SOURCE>
+                         入力元 (既定: src/N03-20240101_11.geojson)
+      --name <TYPE>      構造体の名前 (既定: Properties)
+      --map <MAP>        フィールドにするプロパティとフィールドの名前 (例: 'N03_001=prefecture,N03_004=city')
+                           (既定: すべてのプロパティを小文字にした名前で)
+      --sample <N>       型の推定に使う先頭の Feature の数 (既定: 1000、0 ならすべて)
+  -o, --output <FILE>    出力先の .rs ファイル (既定: - = 標準出力)
+
+環境変数:
+  LAYON_<OPTION>         オプションの値 (例: LAYON_GROUP_BY=N03_003、LAYON_DEDUP=1。コマンドラインの指定が優先される)
+  LAYON_LOG_FORMAT       json にすると、メッセージを 1 行 1 件の JSON (time, level, message) で出力する
+  LAYON_LOG_LEVEL        debug にすると、段階ごとの処理時間などの診断情報も出力する (既定: info)
+";
+
+/// 引数の解析結果。ヘルプ表示の場合は処理を行わずに終了する。
+pub enum Command {
+    Run(Box<Options>),
+    Overlay(OverlayOptions),
+    Zonal(ZonalOptions),
+    Subset(SubsetOptions),
+    Annotate(AnnotateOptions),
+    Bbox(BboxOptions),
+    Layers(Input),
+    Centroids(CentroidsOptions),
+    Hull(HullOptions),
+    Prefectures(PrefecturesOptions),
+    Hierarchy(HierarchyOptions),
+    Mesh(MeshOptions),
+    H3(H3Options),
+    Breaks(BreaksOptions),
+    Locate(LocateOptions),
+    Verify(VerifyOptions),
+    TimeSeries(TimeSeriesOptions),
+    Batch(BatchOptions),
+    Bench(BenchOptions),
+    Generate(GenerateOptions),
+    Tui(TuiOptions),
+    Stream(StreamOptions),
+    Serve(ServeOptions),
+    Schema(SchemaOptions),
+    Completions(Shell),
+    Man,
+    Help,
+}
+
+/// 補完スクリプトを生成するシェル
+#[derive(Clone, Copy)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Command {
+    /// 結果を標準出力に書き出すかどうか（その場合は処理時間などのメッセージを出さない）
+    pub fn writes_to_stdout(&self) -> bool {
+        match self {
+            Command::Subset(options) => options.output == "-",
+            Command::Annotate(options) => options.output == "-",
+            Command::Generate(options) => options.output == "-",
+            Command::Bbox(options) => options.output == "-",
+            Command::Centroids(options) => options.output == "-",
+            Command::Hull(options) => options.output == "-",
+            Command::Prefectures(options) => options.output == "-",
+            Command::Hierarchy(options) => options.output == "-",
+            Command::Mesh(options) => options.output == "-",
+            Command::H3(options) => options.output == "-",
+            Command::Breaks(options) => options.output == "-",
+            Command::Locate(options) => options.output == "-",
+            Command::Schema(options) => options.output == "-",
+            Command::Layers(_)
+            | Command::Tui(_)
+            | Command::Completions(_)
+            | Command::Man
+            | Command::Help => true,
+            _ => false,
+        }
+    }
+
+    /// プログラム名を除いた引数列を解析する
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+        let args: Vec<String> = args.into_iter().collect();
+        let arguments = args.clone();
+        let mut args = args.into_iter().peekable();
+        match args.peek().map(String::as_str) {
+            Some("overlay") => {
+                args.next();
+                return overlay::parse(args);
+            }
+            Some("zonal") => {
+                args.next();
+                return zonal::parse(args);
+            }
+            Some(name @ ("head" | "sample")) => {
+                let head = name == "head";
+                args.next();
+                return subset::parse(args, head);
+            }
+            Some("generate") => {
+                args.next();
+                return generate::parse(args);
+            }
+            Some("annotate") => {
+                args.next();
+                return annotate::parse(args);
+            }
+            Some("bbox") => {
+                args.next();
+                return bbox::parse(args);
+            }
+            Some("layers") => {
+                args.next();
+                return layers::parse(args);
+            }
+            Some("centroids") => {
+                args.next();
+                return centroids::parse(args);
+            }
+            Some("hull") => {
+                args.next();
+                return hull::parse(args);
+            }
+            Some("prefectures") => {
+                args.next();
+                return prefectures::parse(args);
+            }
+            Some("hierarchy") => {
+                args.next();
+                return hierarchy::parse(args);
+            }
+            Some("mesh") => {
+                args.next();
+                return mesh::parse(args);
+            }
+            Some("h3") => {
+                args.next();
+                return h3::parse(args);
+            }
+            Some("breaks") => {
+                args.next();
+                return breaks::parse(args);
+            }
+            Some("locate") => {
+                args.next();
+                return locate::parse(args);
+            }
+            Some("verify") => {
+                args.next();
+                return verify::parse(args);
+            }
+            Some("timeseries") => {
+                args.next();
+                return timeseries::parse(args);
+            }
+            Some("batch") => {
+                args.next();
+                return batch::parse(args);
+            }
+            Some("bench") => {
+                args.next();
+                return bench::parse(args);
+            }
+            Some("tui") => {
+                args.next();
+                return tui::parse(args);
+            }
+            Some("stream") => {
+                args.next();
+                return stream::parse(args);
+            }
+            Some("serve") => {
+                args.next();
+                return serve::parse(args);
+            }
+            Some("schema") => {
+                args.next();
+                return schema::parse(args);
+            }
+            Some("completions") => {
+                args.next();
+                let shell = match args.next().as_deref() {
+                    Some("bash") => Shell::Bash,
+                    Some("zsh") => Shell::Zsh,
+                    Some("fish") => Shell::Fish,
+                    Some(name) => return Err(format!("未対応のシェルです: {}", name)),
+                    None => return Err("シェル (bash, zsh, fish) を指定してください".to_string()),
+                };
+                return match args.next() {
+                    Some(arg) => Err(format!("不明な引数です: {}", arg)),
+                    None => Ok(Command::Completions(shell)),
+                };
+            }
+            Some("man") => {
+                args.next();
+                return match args.next() {
+                    Some(arg) => Err(format!("不明な引数です: {}", arg)),
+                    None => Ok(Command::Man),
+                };
+            }
+            _ => {}
+        }
+        aggregate::parse(args, arguments)
+    }
+}
+/// 端末の幅（環境変数 COLUMNS、なければ 80 桁）
+pub fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .filter(|&columns| columns > 0)
+        .unwrap_or(80)
+}
+
+/// `--coord-precision` の値
+fn parse_coord_precision(digits: &str) -> Result<u32, String> {
+    match digits.parse::<u32>() {
+        Ok(digits) if digits <= MAX_PRECISION => Ok(digits),
+        _ => Err(format!(
+            "--coord-precision の値が不正です (0〜{}): {}",
+            MAX_PRECISION, digits
+        )),
+    }
+}
+
+/// 1 文字の値（`--thousands-separator` など）
+fn parse_char(name: &str, value: &str) -> Result<char, String> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(format!("{} には 1 文字を指定してください: {}", name, value)),
+    }
+}
+
+/// `--metric` の値
+/// 集計のコマンド（と batch）の `--metric` の値。面積のほかに線の長さも集計できる
+fn parse_aggregate_metric(name: &str) -> Result<Metric, String> {
+    match name {
+        "length" => Ok(Metric::GeodesicLength),
+        _ => parse_metric(name),
+    }
+}
+
+fn parse_metric(name: &str) -> Result<Metric, String> {
+    match name {
+        "area" => Ok(Metric::Area),
+        "geodesic-area" => Ok(Metric::GeodesicArea),
+        _ => Err(format!("不明な集計値です: {}", name)),
+    }
+}
+
+/// 引数をオプション名と `--name=value` 形式で指定された値に分ける
+fn split_option(arg: &str) -> (String, Option<String>) {
+    match arg.split_once('=') {
+        Some((name, value)) if name.starts_with("--") => {
+            (name.to_string(), Some(value.to_string()))
+        }
+        _ => (arg.to_string(), None),
+    }
+}
+
+/// オプションの値を取り出す（`--name value` と `--name=value` の両方を受け付ける）
+fn value<I: Iterator<Item = String>>(
+    name: &str,
+    inline: Option<String>,
+    args: &mut I,
+) -> Result<String, String> {
+    match inline {
+        Some(value) => Ok(value),
+        None => args
+            .next()
+            .ok_or_else(|| format!("{} には値を指定してください", name)),
+    }
+}
+
+/// `USAGE` から読み取ったサブコマンドの定義（補完スクリプトと man ページの生成に使う）
+pub struct CommandSpec {
+    /// サブコマンド名（サブコマンドなしの場合は空）
+    pub name: &'static str,
+    pub description: &'static str,
+    pub options: Vec<OptionSpec>,
+}
+
+/// `USAGE` から読み取ったオプションの定義
+#[derive(Clone)]
+pub struct OptionSpec {
+    /// `-i` など
+    pub short: Option<&'static str>,
+    /// `--input` など（位置引数の行では None）
+    pub long: Option<&'static str>,
+    /// 値の名前（`<SOURCE>` の SOURCE。値をとらないオプションは None）。
+    /// 位置引数の行では `<SOURCE>...` のような表記そのまま
+    pub value: Option<&'static str>,
+    /// 値を省略できるか（`--term-map[=WIDTH]`。値は `--term-map=100` の形でだけ渡せる）
+    pub optional: bool,
+    /// 値の候補（`--metric` の area など、説明の続きに並べたもの）
+    pub choices: Vec<&'static str>,
+    /// 説明の 1 行目
+    pub description: &'static str,
+    /// 説明の続きの行（先頭の空白を除く）
+    pub details: Vec<&'static str>,
+}
+
+impl OptionSpec {
+    /// 値がファイルのパスや入力元か（補完でファイル名を候補にする）
+    pub fn takes_path(&self) -> bool {
+        matches!(self.value, Some("FILE" | "SOURCE" | "CSV" | "TARGET"))
+    }
+}
+
+/// オプションの行の説明が始まる桁（これより深い字下げの行は説明の続き）
+const DESCRIPTION_COLUMN: usize = 25;
+
+/// `USAGE` のサブコマンドの一覧と、サブコマンドごとのオプションの節を読み取る。
+/// 先頭はサブコマンドなしの場合（名前は空）
+pub fn spec() -> Vec<CommandSpec> {
+    let mut commands = vec![CommandSpec {
+        name: "",
+        description: "",
+        options: Vec::new(),
+    }];
+    // 今の節がどのサブコマンドのものか（None はオプションの節以外）
+    let mut targets: Option<Vec<usize>> = None;
+    let mut in_subcommands = false;
+    let mut pending: Option<OptionSpec> = None;
+
+    let flush = |commands: &mut Vec<CommandSpec>,
+                 targets: &Option<Vec<usize>>,
+                 pending: &mut Option<OptionSpec>| {
+        if let (Some(option), Some(targets)) = (pending.take(), targets) {
+            for &i in targets {
+                commands[i].options.push(option.clone());
+            }
+        }
+    };
+
+    for line in USAGE.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if trimmed.len() == line.len() {
+            // 節の見出し
+            flush(&mut commands, &targets, &mut pending);
+            in_subcommands = line == "サブコマンド:";
+            targets = if line == "オプション:" {
+                Some(vec![0])
+            } else {
+                line.strip_suffix(" のオプション:").map(|names| {
+                    names
+                        .split(" / ")
+                        .filter_map(|name| commands.iter().position(|c| c.name == name))
+                        .collect()
+                })
+            };
+            continue;
+        }
+
+        let indent = line.len() - trimmed.len();
+        if in_subcommands {
+            let (name, description) = split_columns(trimmed);
+            if name == "(省略)" {
+                commands[0].description = description;
+            } else {
+                commands.push(CommandSpec {
+                    name,
+                    description,
+                    options: Vec::new(),
+                });
+            }
+        } else if targets.is_some() && indent < DESCRIPTION_COLUMN {
+            flush(&mut commands, &targets, &mut pending);
+            pending = Some(parse_option_line(trimmed));
+        } else if let Some(option) = &mut pending {
+            if option.description.is_empty() {
+                option.description = trimmed;
+                continue;
+            }
+            let (word, _) = split_columns(trimmed);
+            let is_choice = indent > DESCRIPTION_COLUMN
+                && word.starts_with(|c: char| c.is_ascii_lowercase())
+                && word
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+            if is_choice {
+                option.choices.push(word);
+            }
+            option.details.push(trimmed);
+        }
+    }
+    flush(&mut commands, &targets, &mut pending);
+    commands
+}
+
+/// `-i, --input <SOURCE>   説明` または `<SOURCE>...   説明` の形の行を読む
+fn parse_option_line(line: &'static str) -> OptionSpec {
+    let mut short = None;
+    let mut long = None;
+    let mut value = None;
+    let mut optional = false;
+    // 名前の後の 2 つ以上の空白から説明が始まる（説明の中の `--bands` や `-o` は名前ではない）
+    let names_end = line.find("  ").unwrap_or(line.len());
+    let mut rest = line;
+    while !rest.is_empty() && line.len() - rest.len() < names_end {
+        let end = rest.find(' ').unwrap_or(rest.len());
+        let token = rest[..end].trim_end_matches(',');
+        if let Some((name, rest)) = token.split_once("[=").filter(|_| token.starts_with("--")) {
+            long = Some(name);
+            value = Some(rest.trim_end_matches(']'));
+            optional = true;
+        } else if token.starts_with("--") {
+            long = Some(token);
+        } else if token.starts_with('-') {
+            short = Some(token);
+        } else if token.starts_with('<') && long.is_some() {
+            value = Some(token.trim_start_matches('<').trim_end_matches('>'));
+        } else if token.starts_with('<') && short.is_none() && value.is_none() {
+            value = Some(token);
+        } else if !(long.is_none() && token.chars().all(|c| c.is_ascii_uppercase())) {
+            // `SOURCE, -i, --input` の位置引数の名前は読み飛ばし、それ以外は説明の始まり
+            break;
+        }
+        rest = rest[end..].trim_start();
+    }
+    OptionSpec {
+        short,
+        long,
+        value,
+        optional,
+        choices: Vec::new(),
+        description: rest,
+        details: Vec::new(),
+    }
+}
+
+/// コマンドライン引数の前に、環境変数 `LAYON_<OPTION>` から作ったオプションを補う。
+/// 例えば LAYON_GROUP_BY=N03_003 は `--group-by=N03_003`、LAYON_DEDUP=1 は `--dedup` になる。
+/// コマンドラインで指定したオプションは環境変数を使わない（`--layer` など繰り返せるオプションも、
+/// 環境変数の値に加えるのではなくコマンドラインの値で置き換える）
+pub fn with_env(args: Vec<String>) -> Result<Vec<String>, String> {
+    with_variables(args, |variable| std::env::var(variable).ok())
+}
+
+fn with_variables(
+    args: Vec<String>,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<String>, String> {
+    let commands = spec();
+    let (command, rest) = match args.first() {
+        Some(first) if commands[1..].iter().any(|c| c.name == first.as_str()) => {
+            (first.as_str(), &args[1..])
+        }
+        _ => ("", &args[..]),
+    };
+    let spec = commands.iter().find(|c| c.name == command).unwrap();
+
+    let mut from_env = Vec::new();
+    for option in &spec.options {
+        let Some(long) = option.long.filter(|&long| long != "--help") else {
+            continue;
+        };
+        let variable = format!(
+            "LAYON_{}",
+            long.trim_start_matches("--")
+                .replace('-', "_")
+                .to_uppercase()
+        );
+        let Some(value) = var(&variable) else {
+            continue;
+        };
+        let given = rest.iter().any(|arg| {
+            arg == long
+                || arg
+                    .strip_prefix(long)
+                    .is_some_and(|rest| rest.starts_with('='))
+                || option.short.is_some_and(|short| {
+                    arg == short || (option.value.is_some() && arg.starts_with(short))
+                })
+        });
+        if given {
+            continue;
+        }
+        // 値をとるもの（省略できるものはフラグとして有効にする値でなければ）は値を付ける
+        let with_value = if option.optional {
+            !value.is_empty() && !is_true(&value)
+        } else {
+            option.value.is_some()
+        };
+        if with_value {
+            from_env.push(format!("{}={}", long, value));
+        } else {
+            match value.as_str() {
+                "" | "0" | "false" | "no" | "off" => {}
+                _ if is_true(&value) => from_env.push(long.to_string()),
+                _ => return Err(format!("{} の値が不正です: {}", variable, value)),
+            }
+        }
+    }
+
+    let mut result: Vec<String> = args
+        .first()
+        .filter(|_| !command.is_empty())
+        .cloned()
+        .into_iter()
+        .collect();
+    result.extend(from_env);
+    result.extend(rest.iter().cloned());
+    Ok(result)
+}
+
+/// 環境変数でフラグを有効にする値
+fn is_true(value: &str) -> bool {
+    matches!(value, "1" | "true" | "yes" | "on")
+}
+
+/// 2 つ以上の空白で区切られた 2 つの列に分ける
+fn split_columns(line: &'static str) -> (&'static str, &'static str) {
+    match line.split_once("  ") {
+        Some((head, rest)) => (head, rest.trim_start()),
+        None => (line, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn command_line_values_replace_environment_values() {
+        let var = |variable: &str| match variable {
+            "LAYON_LAYER" => Some("boundaries".to_string()),
+            "LAYON_GROUP_BY" => Some("N03_003".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            with_variables(strings(&["--layer", "rivers"]), var).unwrap(),
+            strings(&["--group-by=N03_003", "--layer", "rivers"])
+        );
+        assert_eq!(
+            with_variables(strings(&["-gN03_004"]), var).unwrap(),
+            strings(&["--layer=boundaries", "-gN03_004"])
+        );
+        assert_eq!(
+            with_variables(strings(&[]), var).unwrap(),
+            strings(&["--layer=boundaries", "--group-by=N03_003"])
+        );
+    }
+    #[test]
+    fn option_names_end_where_the_description_starts() {
+        let metric = parse_option_line("--metric <NAME>    --bands の面積 (既定: area)");
+        assert_eq!(metric.long, Some("--metric"));
+        assert_eq!(metric.value, Some("NAME"));
+        assert_eq!(metric.description, "--bands の面積 (既定: area)");
+
+        let template = parse_option_line("--template <FILE>  -o のファイルを書く");
+        assert_eq!((template.short, template.long), (None, Some("--template")));
+
+        let input = parse_option_line("-i, --input <SOURCE>   入力元");
+        assert_eq!((input.short, input.long), (Some("-i"), Some("--input")));
+        assert_eq!(input.value, Some("SOURCE"));
+        assert!(!input.optional);
+    }
+
+    #[test]
+    fn reads_optional_values() {
+        let chart = parse_option_line("--chart[=N]        横棒グラフを表示する");
+        assert_eq!(chart.long, Some("--chart"));
+        assert_eq!(chart.value, Some("N"));
+        assert!(chart.optional);
+        assert_eq!(chart.description, "横棒グラフを表示する");
+    }
+}
@@ -0,0 +1,68 @@
+//! `layon overlay` の引数の解析。
+
+use super::{
+    parse_metric, split_option, value, Command, DEFAULT_GROUP_BY, DEFAULT_INPUT, DEFAULT_OUTPUT,
+};
+use crate::{
+    aggregate::Metric,
+    projection::Projection,
+    sink::{check_output, FileFormat},
+    source::{Input, InputOptions},
+};
+
+/// overlay サブコマンドの引数
+pub struct OverlayOptions {
+    pub input: Input,
+    pub group_by: String,
+    pub overlay: Input,
+    pub overlay_key: String,
+    pub metric: Metric,
+    pub output: String,
+}
+
+/// overlay サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut overlay = None;
+    let mut overlay_key = None;
+    let mut metric = Metric::Area;
+    let mut projection = None;
+    let mut output = DEFAULT_OUTPUT.to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "--overlay" => overlay = Some(value(&name, inline, &mut args)?),
+            "--overlay-key" => overlay_key = Some(value(&name, inline, &mut args)?),
+            "--metric" => metric = parse_metric(&value(&name, inline, &mut args)?)?,
+            "--projection" => {
+                projection = Some(Projection::parse(&value(&name, inline, &mut args)?)?)
+            }
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    let overlay = overlay.ok_or("overlay には --overlay で右側のレイヤーを指定してください")?;
+    let overlay_key = overlay_key
+        .ok_or("overlay には --overlay-key で右側のレイヤーの集計キーを指定してください")?;
+    if let Some(projection) = projection {
+        if metric != Metric::Area {
+            return Err("--projection と --metric は同時に指定できません".to_string());
+        }
+        metric = Metric::Projected(projection);
+    }
+    check_output("overlay", &output, &[FileFormat::Csv])?;
+    Ok(Command::Overlay(OverlayOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        group_by,
+        overlay: Input::parse(&overlay, InputOptions::default())?,
+        overlay_key,
+        metric,
+        output,
+    }))
+}
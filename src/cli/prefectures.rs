@@ -0,0 +1,77 @@
+//! `layon prefectures` の引数の解析。
+
+use super::{
+    parse_coord_precision, parse_metric, split_option, value, Command, DEFAULT_INPUT,
+    DEFAULT_PREFECTURE, DEFAULT_SIMPLIFY,
+};
+use crate::{
+    aggregate::Metric,
+    source::{Input, InputOptions},
+    transform::{GeometryTransform, Winding},
+};
+
+/// prefectures サブコマンドの引数
+pub struct PrefecturesOptions {
+    pub input: Input,
+    /// 都道府県名のプロパティ
+    pub group_by: String,
+    pub metric: Metric,
+    /// 簡略化の許容誤差（座標の単位。0 なら簡略化しない）
+    pub tolerance: f64,
+    /// 書き出す GeoJSON のジオメトリの変換
+    pub transform: GeometryTransform,
+    /// HTML の出力の見出し
+    pub title: Option<String>,
+    /// 出力先（"-" は標準出力）
+    pub output: String,
+}
+
+/// prefectures サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut group_by = DEFAULT_PREFECTURE.to_string();
+    let mut metric = Metric::GeodesicArea;
+    let mut tolerance = DEFAULT_SIMPLIFY;
+    let mut transform = GeometryTransform::default();
+    let mut title = None;
+    let mut output = "prefectures.geojson".to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "--metric" => metric = parse_metric(&value(&name, inline, &mut args)?)?,
+            "--simplify" => {
+                let t = value(&name, inline, &mut args)?;
+                tolerance = match t.parse::<f64>() {
+                    Ok(t) if t >= 0.0 && t.is_finite() => t,
+                    _ => return Err(format!("--simplify の値が不正です: {}", t)),
+                };
+            }
+            "--coord-precision" => {
+                transform.precision =
+                    Some(parse_coord_precision(&value(&name, inline, &mut args)?)?)
+            }
+            "--legacy-winding" => transform.winding = Winding::Legacy,
+            "--title" => title = Some(value(&name, inline, &mut args)?),
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ if !arg.starts_with('-') => input = arg,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    if title.is_some() && !output.to_lowercase().ends_with(".html") {
+        return Err("--title は HTML の出力でのみ使えます".to_string());
+    }
+    Ok(Command::Prefectures(PrefecturesOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        group_by,
+        metric,
+        tolerance,
+        transform,
+        title,
+        output,
+    }))
+}
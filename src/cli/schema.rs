@@ -0,0 +1,56 @@
+//! `layon schema` の引数の解析。
+
+use super::{split_option, value, Command, DEFAULT_INPUT};
+use crate::{
+    mapping::PropertyMap,
+    schema,
+    source::{Input, InputOptions},
+};
+
+/// schema サブコマンドの引数
+pub struct SchemaOptions {
+    pub input: Input,
+    /// 構造体の名前
+    pub name: String,
+    pub map: Option<PropertyMap>,
+    /// 型の推定に使う Feature の数（0 ならすべて）
+    pub sample: usize,
+    /// 出力先（"-" は標準出力）
+    pub output: String,
+}
+
+/// schema サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut type_name = "Properties".to_string();
+    let mut map = None;
+    let mut sample = schema::DEFAULT_SAMPLE;
+    let mut output = "-".to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "--name" => type_name = value(&name, inline, &mut args)?,
+            "--map" => map = Some(PropertyMap::parse(&value(&name, inline, &mut args)?)?),
+            "--sample" => {
+                let n = value(&name, inline, &mut args)?;
+                sample = n
+                    .parse()
+                    .map_err(|_| format!("{} の値が不正です: {}", name, n))?;
+            }
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ if !arg.starts_with('-') => input = arg,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    Ok(Command::Schema(SchemaOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        name: type_name,
+        map,
+        sample,
+        output,
+    }))
+}
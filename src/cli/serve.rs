@@ -0,0 +1,103 @@
+//! `layon serve` の引数の解析。
+
+use super::{split_option, value, Command, DEFAULT_GROUP_BY, DEFAULT_INPUT, DEFAULT_SIMPLIFY};
+use crate::{
+    serve,
+    source::{Input, InputOptions},
+};
+
+/// serve サブコマンドの引数
+pub struct ServeOptions {
+    pub input: Input,
+    pub group_by: String,
+    /// 既定の集計値の名前（解析できることは確認済み）
+    pub metric: String,
+    /// 待ち受けるアドレス
+    pub listen: String,
+    /// gRPC のサービスを待ち受けるアドレス
+    #[cfg(feature = "grpc")]
+    pub grpc: Option<String>,
+    /// 地図の境界を簡略化する許容誤差
+    pub tolerance: f64,
+    /// 起動時のデータセットの名前
+    pub dataset: String,
+    /// メモリに置くデータセットの数
+    pub max_datasets: usize,
+    /// `POST /datasets` で登録できるファイルを置くディレクトリ（None なら登録を受け付けない）
+    pub allow_register: Option<String>,
+    /// PostGIS と外部コマンドを使う入力元の登録も受け付ける
+    pub allow_external_sources: bool,
+}
+
+/// serve サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut metric = "area".to_string();
+    let mut listen = "127.0.0.1:8080".to_string();
+    #[cfg(feature = "grpc")]
+    let mut grpc = None;
+    let mut tolerance = DEFAULT_SIMPLIFY;
+    let mut dataset = "default".to_string();
+    let mut max_datasets = serve::DEFAULT_MAX_DATASETS;
+    let mut allow_register = None;
+    let mut allow_external_sources = false;
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "--dataset" => {
+                dataset = value(&name, inline, &mut args)?;
+                serve::registry::check_name(&dataset)?;
+            }
+            "--max-datasets" => {
+                let n = value(&name, inline, &mut args)?;
+                max_datasets = match n.parse::<usize>() {
+                    Ok(n) if n >= 1 => n,
+                    _ => return Err(format!("--max-datasets の値が不正です: {}", n)),
+                };
+            }
+            "--allow-register" => allow_register = Some(value(&name, inline, &mut args)?),
+            "--allow-external-sources" => allow_external_sources = true,
+            "--metric" => {
+                metric = value(&name, inline, &mut args)?;
+                serve::parse_metric(&metric)?;
+            }
+            "--listen" => listen = value(&name, inline, &mut args)?,
+            #[cfg(feature = "grpc")]
+            "--grpc" => grpc = Some(value(&name, inline, &mut args)?),
+            "--simplify" => {
+                let t = value(&name, inline, &mut args)?;
+                tolerance = match t.parse::<f64>() {
+                    Ok(t) if t >= 0.0 && t.is_finite() => t,
+                    _ => return Err(format!("--simplify の値が不正です: {}", t)),
+                };
+            }
+            _ if !arg.starts_with('-') => input = arg,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    if allow_external_sources && allow_register.is_none() {
+        return Err(
+            "--allow-external-sources は --allow-register と同時に指定してください".to_string(),
+        );
+    }
+
+    Ok(Command::Serve(ServeOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        group_by,
+        metric,
+        listen,
+        #[cfg(feature = "grpc")]
+        grpc,
+        tolerance,
+        dataset,
+        max_datasets,
+        allow_register,
+        allow_external_sources,
+    }))
+}
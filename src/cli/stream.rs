@@ -0,0 +1,70 @@
+//! `layon stream` の引数の解析。
+
+use super::{parse_metric, split_option, value, Command, DEFAULT_GROUP_BY, DEFAULT_OUTPUT};
+use crate::{aggregate::Metric, sink::Output, stream::StreamSource};
+use std::time::Duration;
+
+/// stream サブコマンドの引数
+pub struct StreamOptions {
+    pub source: StreamSource,
+    pub group_by: String,
+    pub metric: Metric,
+    /// `--where` の条件式（解析できることは確認済み）
+    pub filter: Option<String>,
+    /// 集計結果を出力する間隔
+    pub interval: Duration,
+    pub output: Output,
+}
+
+/// stream サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut source = "-".to_string();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut metric = Metric::Area;
+    let mut filter = None;
+    let mut interval = Duration::from_secs(10);
+    let mut output = DEFAULT_OUTPUT.to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--source" => source = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "--metric" => metric = parse_metric(&value(&name, inline, &mut args)?)?,
+            "--where" => {
+                let expression = value(&name, inline, &mut args)?;
+                crate::filter::Filter::parse(&expression)
+                    .map_err(|err| format!("--where の条件式が不正です: {}", err))?;
+                filter = Some(expression);
+            }
+            "--interval" => {
+                let t = value(&name, inline, &mut args)?;
+                interval = match t.parse::<f64>() {
+                    Ok(seconds) if seconds > 0.0 && seconds.is_finite() => {
+                        Duration::from_secs_f64(seconds)
+                    }
+                    _ => return Err(format!("--interval の値が不正です: {}", t)),
+                };
+            }
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    let output = Output::parse(&output)?;
+    if output.needs_geometry() {
+        return Err(
+            "stream の出力先にはジオメトリを書き出す形式 (*.sql, postgres://) は使えません"
+                .to_string(),
+        );
+    }
+    Ok(Command::Stream(StreamOptions {
+        source: StreamSource::parse(&source)?,
+        group_by,
+        metric,
+        filter,
+        interval,
+        output,
+    }))
+}
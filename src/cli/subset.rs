@@ -0,0 +1,101 @@
+//! `layon head` と `layon sample` の引数の解析。
+
+use super::{parse_coord_precision, split_option, value, Command, DEFAULT_INPUT};
+use crate::{
+    mapping::PropertyMap,
+    sink::{check_output, FileFormat},
+    source::{Input, InputOptions},
+    transform::{GeometryTransform, Winding},
+};
+
+/// head / sample で取り出す Feature の選び方
+pub enum Selection {
+    /// 先頭から指定した数
+    Head(usize),
+    /// 指定した確率で無作為に
+    Sample { fraction: f64, seed: u64 },
+}
+
+/// head / sample サブコマンドの引数
+pub struct SubsetOptions {
+    pub input: Input,
+    pub selection: Selection,
+    /// 出力するプロパティの対応
+    pub map: Option<PropertyMap>,
+    /// 書き出す GeoJSON のジオメトリの変換
+    pub transform: GeometryTransform,
+    /// 出力先の GeoJSON ファイル（"-" は標準出力）
+    pub output: String,
+}
+
+/// head / sample サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(
+    mut args: I,
+    head: bool,
+) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut count = 10;
+    let mut fraction = None;
+    let mut seed = 0;
+    let mut map = None;
+    let mut transform = GeometryTransform::default();
+    let mut output = "-".to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-n" | "--count" if head => {
+                let n = value(&name, inline, &mut args)?;
+                count = n
+                    .parse()
+                    .map_err(|_| format!("{} の値が不正です: {}", name, n))?;
+            }
+            "--fraction" if !head => {
+                let f = value(&name, inline, &mut args)?;
+                fraction = match f.parse::<f64>() {
+                    Ok(f) if (0.0..=1.0).contains(&f) => Some(f),
+                    _ => return Err(format!("--fraction の値が不正です (0〜1): {}", f)),
+                };
+            }
+            "--seed" if !head => {
+                let n = value(&name, inline, &mut args)?;
+                seed = n
+                    .parse()
+                    .map_err(|_| format!("--seed の値が不正です: {}", n))?;
+            }
+            "--map" => map = Some(PropertyMap::parse(&value(&name, inline, &mut args)?)?),
+            "--coord-precision" => {
+                transform.precision =
+                    Some(parse_coord_precision(&value(&name, inline, &mut args)?)?)
+            }
+            "--legacy-winding" => transform.winding = Winding::Legacy,
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    let selection = if head {
+        Selection::Head(count)
+    } else {
+        Selection::Sample {
+            fraction: fraction.ok_or("sample には --fraction で抽出する確率を指定してください")?,
+            seed,
+        }
+    };
+    if output != "-" {
+        check_output(
+            if head { "head" } else { "sample" },
+            &output,
+            &[FileFormat::GeoJson],
+        )?;
+    }
+    Ok(Command::Subset(SubsetOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        selection,
+        map,
+        transform,
+        output,
+    }))
+}
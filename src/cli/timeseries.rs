@@ -0,0 +1,67 @@
+//! `layon timeseries` の引数の解析。
+
+use super::{split_option, value, Command, DEFAULT_GROUP_BY, DEFAULT_OUTPUT};
+use crate::{
+    sink::{check_output, FileFormat},
+    source::{Input, InputOptions},
+};
+
+/// timeseries サブコマンドの引数
+pub struct TimeSeriesOptions {
+    /// 年次の名前と入力元の組（古い順）
+    pub vintages: Vec<(String, Input)>,
+    pub group_by: String,
+    pub output: String,
+}
+
+/// timeseries サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut sources = Vec::new();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut output = DEFAULT_OUTPUT.to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ if !arg.starts_with('-') => sources.push(arg),
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    if sources.is_empty() {
+        return Err("timeseries には年次ごとの入力元を指定してください".to_string());
+    }
+    check_output("timeseries", &output, &[FileFormat::Csv])?;
+    let mut vintages: Vec<(String, Input)> = Vec::with_capacity(sources.len());
+    for source in &sources {
+        // postgres://…?table=… の "=" は区切りとみなさない
+        let (label, source) = match source.split_once('=') {
+            Some((label, rest)) if !label.contains([':', '/', '?']) => (label.to_string(), rest),
+            _ => (vintage_label(source), source.as_str()),
+        };
+        if vintages.iter().any(|(other, _)| *other == label) {
+            return Err(format!(
+                "年次 {} が重複しています（LABEL=SOURCE の形式で指定してください）",
+                label
+            ));
+        }
+        vintages.push((label, Input::parse(source, InputOptions::default())?));
+    }
+    Ok(Command::TimeSeries(TimeSeriesOptions {
+        vintages,
+        group_by,
+        output,
+    }))
+}
+
+/// ファイル名から年次の名前を作る（最初の 4 桁以上の数字の先頭 4 桁、なければファイル名）
+fn vintage_label(source: &str) -> String {
+    let file = source.rsplit(['/', '\\']).next().unwrap_or(source);
+    file.split(|c: char| !c.is_ascii_digit())
+        .find(|digits| digits.len() >= 4)
+        .map(|digits| digits[..4].to_string())
+        .unwrap_or_else(|| file.split('.').next().unwrap_or(file).to_string())
+}
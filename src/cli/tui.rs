@@ -0,0 +1,46 @@
+//! `layon tui` の引数の解析。
+
+use super::{
+    parse_metric, split_option, value, Command, DEFAULT_GROUP_BY, DEFAULT_INPUT, DEFAULT_OUTPUT,
+};
+use crate::{
+    aggregate::Metric,
+    source::{Input, InputOptions},
+};
+
+/// tui サブコマンドの引数
+pub struct TuiOptions {
+    pub input: Input,
+    pub group_by: String,
+    pub metric: Metric,
+    /// 一覧を書き出す CSV ファイル
+    pub output: String,
+}
+
+/// tui サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut metric = Metric::Area;
+    let mut output = DEFAULT_OUTPUT.to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "--metric" => metric = parse_metric(&value(&name, inline, &mut args)?)?,
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ if !arg.starts_with('-') => input = arg,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    Ok(Command::Tui(TuiOptions {
+        input: Input::parse(&input, InputOptions::default())?,
+        group_by,
+        metric,
+        output,
+    }))
+}
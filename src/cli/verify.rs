@@ -0,0 +1,86 @@
+//! `layon verify` の引数の解析。
+
+use super::{split_option, value, Command, DEFAULT_GROUP_BY, DEFAULT_INPUT, DEFAULT_OUTPUT};
+use crate::{
+    sink::{check_output, FileFormat},
+    source::{Input, InputOptions},
+    verify::Join,
+};
+
+/// `--join prefecture` で都道府県名と突き合わせるプロパティの既定値
+const DEFAULT_PREFECTURE_BY: &str = "N03_001";
+
+/// `--join code` で全国地方公共団体コードと突き合わせるプロパティの既定値
+const DEFAULT_CODE_BY: &str = "N03_007";
+
+/// verify サブコマンドの引数
+pub struct VerifyOptions {
+    pub official: String,
+    pub official_key: Option<String>,
+    pub official_prefecture: Option<String>,
+    pub official_area: Option<String>,
+    pub input: Input,
+    pub group_by: String,
+    pub join: Join,
+    pub output: String,
+}
+
+/// verify サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut official = None;
+    let mut official_key = None;
+    let mut official_prefecture = None;
+    let mut official_area = None;
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut group_by = None;
+    let mut join = "name".to_string();
+    let mut prefecture_by = None;
+    let mut output = DEFAULT_OUTPUT.to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--official" => official = Some(value(&name, inline, &mut args)?),
+            "--official-key" => official_key = Some(value(&name, inline, &mut args)?),
+            "--official-prefecture" => official_prefecture = Some(value(&name, inline, &mut args)?),
+            "--official-area" => official_area = Some(value(&name, inline, &mut args)?),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = Some(value(&name, inline, &mut args)?),
+            "--join" => join = value(&name, inline, &mut args)?,
+            "--prefecture-by" => prefecture_by = Some(value(&name, inline, &mut args)?),
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    let official =
+        official.ok_or("verify には --official で公式の面積の CSV を指定してください")?;
+    let join = Join::parse(
+        &join,
+        prefecture_by.as_deref().unwrap_or(DEFAULT_PREFECTURE_BY),
+    )?;
+    if !matches!(join, Join::Prefecture { .. })
+        && (prefecture_by.is_some() || official_prefecture.is_some())
+    {
+        return Err(
+            "--prefecture-by と --official-prefecture は --join prefecture と同時に指定してください"
+                .to_string(),
+        );
+    }
+    let group_by = group_by.unwrap_or_else(|| match join {
+        Join::Code => DEFAULT_CODE_BY.to_string(),
+        _ => DEFAULT_GROUP_BY.to_string(),
+    });
+    check_output("verify", &output, &[FileFormat::Csv])?;
+    Ok(Command::Verify(VerifyOptions {
+        official,
+        official_key,
+        official_prefecture,
+        official_area,
+        input: Input::parse(&input, InputOptions::default())?,
+        group_by,
+        join,
+        output,
+    }))
+}
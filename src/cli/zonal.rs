@@ -0,0 +1,79 @@
+//! `layon zonal` の引数の解析。
+
+use super::{
+    parse_metric, split_option, value, Command, DEFAULT_GROUP_BY, DEFAULT_INPUT, DEFAULT_OUTPUT,
+};
+use crate::{
+    aggregate::Metric,
+    sink::{check_output, FileFormat},
+    source::{Input, InputOptions},
+    zonal::Bands,
+};
+
+/// zonal サブコマンドの引数
+pub struct ZonalOptions {
+    pub raster: String,
+    pub input: Input,
+    pub group_by: String,
+    /// ヒストグラムの階級の幅（0 は値ごと）。None なら統計を出力する
+    pub bin_width: Option<f64>,
+    /// 値の帯ごとの面積を出力する場合の帯
+    pub bands: Option<Bands>,
+    /// 帯ごとの面積の求め方
+    pub metric: Metric,
+    /// 結果を横棒グラフで端末に表示する
+    pub chart: bool,
+    pub output: String,
+}
+
+/// zonal サブコマンドの引数を解析する
+pub(super) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut raster = None;
+    let mut input = DEFAULT_INPUT.to_string();
+    let mut group_by = DEFAULT_GROUP_BY.to_string();
+    let mut histogram = false;
+    let mut bin_width = None;
+    let mut bands = None;
+    let mut metric = Metric::Area;
+    let mut chart = false;
+    let mut output = DEFAULT_OUTPUT.to_string();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = split_option(&arg);
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--raster" => raster = Some(value(&name, inline, &mut args)?),
+            "-i" | "--input" => input = value(&name, inline, &mut args)?,
+            "-g" | "--group-by" => group_by = value(&name, inline, &mut args)?,
+            "--histogram" => histogram = true,
+            "--bin-width" => {
+                let width = value(&name, inline, &mut args)?;
+                bin_width = match width.parse::<f64>() {
+                    Ok(width) if width > 0.0 => Some(width),
+                    _ => return Err(format!("--bin-width の値が不正です: {}", width)),
+                };
+            }
+            "--bands" => bands = Some(Bands::parse(&value(&name, inline, &mut args)?)?),
+            "--metric" => metric = parse_metric(&value(&name, inline, &mut args)?)?,
+            "--chart" => chart = true,
+            "-o" | "--output" => output = value(&name, inline, &mut args)?,
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    let raster = raster.ok_or("zonal には --raster でラスターを指定してください")?;
+    if bands.is_some() && (histogram || bin_width.is_some()) {
+        return Err("--bands は --histogram, --bin-width と同時に指定できません".to_string());
+    }
+    check_output("zonal", &output, &[FileFormat::Csv])?;
+    Ok(Command::Zonal(ZonalOptions {
+        raster,
+        input: Input::parse(&input, InputOptions::default())?,
+        group_by,
+        bin_width: bin_width.or(histogram.then_some(0.0)),
+        bands,
+        metric,
+        chart,
+        output,
+    }))
+}
@@ -0,0 +1,461 @@
+//! 既定のサブコマンド: 集計キーごとの面積を集計する。
+
+use super::seconds;
+use crate::cli::{self, Options};
+use crate::{
+    aggregate, approx, cache,
+    cancel::CancellationToken,
+    chart, check, classify, compare, log,
+    pipeline::Pipeline,
+    pivot::{Cells, Crosstab, PivotSpec},
+    provenance::{FileRecord, Provenance},
+    signal, sink, termmap,
+};
+use std::error::Error;
+
+/// 集計キーごとの面積を集計する
+pub fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    // GeoJSON（または PostGIS）から Feature を読み込んで集計する
+    let target = options.output.describe();
+    let output_path = options.output.path().map(str::to_string);
+    let csv_format = match &options.output {
+        sink::Output::Csv(_, format) => format.clone(),
+        _ => sink::csv::Format::default(),
+    };
+    let cache = cache_entry(&options)?;
+    if let (Some(entry), Some(path)) = (&cache, &output_path) {
+        if entry.restore(path)? {
+            log::info!(
+                "入力とオプションが前回と同じため、キャッシュした結果を {} に出力しました (--no-cache で集計し直します)。",
+                target
+            );
+            return Ok(());
+        }
+    }
+    // 来歴には集計に使った入力ファイルのハッシュ値を記録する
+    let input_record = match &options.provenance {
+        Some(_) => Some(FileRecord::new(
+            options.input.describe(),
+            options.input.path(),
+        )?),
+        None => None,
+    };
+    let mut pipeline = Pipeline::from_input(options.input)
+        .group_by(options.group_by.clone())
+        .metric(options.metric)
+        .backend(options.backend)
+        .sink(options.output);
+    if let Some(template) = options.group_by_template {
+        pipeline = pipeline.group_by_template(template);
+    }
+    if let Some(filter) = options.filter {
+        pipeline = pipeline.filter(filter);
+    }
+    if let Some(normalization) = &options.normalize_key {
+        pipeline = pipeline.normalize_keys(normalization.clone());
+    }
+    if let Some(keys) = &options.keys {
+        pipeline = pipeline.keys(keys.clone());
+    }
+    if options.validate_input {
+        pipeline = pipeline.validate(true);
+    }
+    if let Some(dedup) = &options.dedup {
+        pipeline = pipeline.dedup(dedup.tolerance);
+    }
+    if options.make_valid {
+        pipeline = pipeline.make_valid(true);
+    }
+    if let Some(source) = &options.subtract {
+        pipeline = pipeline.subtract(source.clone());
+    }
+    if let Some(timeout) = options.timeout {
+        pipeline = pipeline.timeout(timeout);
+    }
+    if let Some(property) = &options.partition_by {
+        pipeline = pipeline.partition_by(property.clone());
+    }
+    if let Some(dir) = &options.checkpoint_dir {
+        pipeline = pipeline.checkpoint_dir(dir.clone());
+    }
+    if options.tmpdir.is_some() {
+        pipeline = pipeline.spill(true);
+    }
+    if let Some(limit) = options.memory_limit {
+        pipeline = pipeline.memory_limit(limit);
+    }
+    if let Some(projection) = options.compare_methods {
+        let path = output_path.as_deref().unwrap_or_default();
+        let rows = compare::compare(pipeline, &options.group_by, projection)?;
+        sink::csv::write_methods(path, &rows, &csv_format)?;
+        log::info!(
+            "{} で投影した平面上の面積と楕円体上の面積の比較 ({} 行) を {} に出力しました。",
+            projection.name(),
+            rows.len(),
+            target
+        );
+        if let Some(row) = rows
+            .iter()
+            .filter(|row| row.relative_difference().is_some())
+            .max_by(|a, b| {
+                let relative = |row: &compare::MethodRow| row.relative_difference().unwrap().abs();
+                relative(a).total_cmp(&relative(b))
+            })
+        {
+            log::info!(
+                "相対差の絶対値が最も大きいのは {} の {:+.3e} です。",
+                row.key,
+                row.relative_difference().unwrap()
+            );
+        }
+        if let Some(entry) = &cache {
+            entry.store(path)?;
+        }
+        return Ok(());
+    }
+    if let Some(sampling) = options.approx {
+        let path = output_path.as_deref().unwrap_or_default();
+        let approximation =
+            approx::estimate(pipeline, &options.group_by, options.metric, sampling)?;
+        sink::csv::write_estimates(path, &approximation.rows, &csv_format)?;
+        log::warning!(
+            "これは概算です: {} 個の Feature のうち {} 個 (確率 {}) を抽出して、{} 個の集計キーの合計を推定し、95% 信頼区間とともに {} に出力しました。",
+            approximation.total,
+            approximation.sampled,
+            sampling.fraction,
+            approximation.rows.len(),
+            target
+        );
+        if let Some(entry) = &cache {
+            entry.store(path)?;
+        }
+        return Ok(());
+    }
+    if let Some(spec) = &options.pivot {
+        let path = output_path.as_deref().unwrap_or_default();
+        run_pivot(pipeline, spec, options.metric, path, &csv_format, &target)?;
+        if let Some(entry) = &cache {
+            entry.store(path)?;
+        }
+        return Ok(());
+    }
+    // 中断されたら、それまでに集計し終えた分を書き出してから終了する
+    let cancel = CancellationToken::new();
+    signal::cancel_on_signal(&cancel);
+    let mut pipeline = pipeline
+        .cancellation(cancel)
+        .partial(true)
+        .geometry(options.term_map.is_some())
+        .ids(options.list_ids.is_some())
+        .ring_report(options.ring_report.is_some())
+        .audit(options.audit_log.is_some())
+        .numeric(options.agg.clone())
+        .geometry_types(options.by_geometry_type)
+        .derive(options.derived.clone());
+    if let Some(keep) = &options.keep {
+        pipeline = pipeline.keep(keep.clone());
+    }
+    if let Some(smoothing) = &options.smooth {
+        pipeline = pipeline.smooth(smoothing.clone());
+    }
+    if let Some(classification) = &options.classify {
+        pipeline = pipeline.classify(classification.clone());
+    }
+    if let Some(key) = &options.sort {
+        pipeline = pipeline.sort(key.clone());
+    }
+    if let Some(count) = options.top {
+        pipeline = pipeline.top(count);
+    }
+    let result = pipeline.run()?;
+
+    if options.validate_input {
+        log::info!("入力は RFC 7946 の規則に従っています。");
+    }
+    if result.untemplated > 0 {
+        log::warning!(
+            "集計キーのテンプレートのプロパティがない (または正規表現に一致しない) Feature が {} 個あり、集計に含めませんでした。",
+            result.untemplated
+        );
+    }
+    if let Some(property) = &options.partition_by {
+        log::info!(
+            "{} の値で {} 個の分割に分けて集計しました。",
+            property,
+            result.partitions
+        );
+    }
+    if result.restored_partitions > 0 {
+        log::info!(
+            "{} 個の分割は、チェックポイントに保存した結果を読み込みました。",
+            result.restored_partitions
+        );
+    }
+    if result.spills > 0 {
+        log::info!(
+            "集計用の表が --memory-limit を超えたため、{} 回一時ディレクトリに書き出しながら集計しました。",
+            result.spills
+        );
+    }
+    if !result.missing_keys.is_empty() {
+        log::warning!(
+            "集計キーの一覧 (--only, --only-file) のうち {} 個が入力にありません: {}",
+            result.missing_keys.len(),
+            result.missing_keys.join(", ")
+        );
+    }
+    if options.normalize_key.is_some() {
+        log::info!(
+            "集計キーの値をそろえ、{} 個の Feature の値を変えました。",
+            result.normalized
+        );
+    }
+    if !result.conflicts.is_empty() {
+        let policy = options
+            .keep
+            .as_ref()
+            .map_or("first", |keep| keep.policy.name());
+        log::warning!(
+            "--keep の値が {} 箇所で食い違っています ({} で選びました。--keep-conflict で変えられます):",
+            result.conflicts.len(),
+            policy
+        );
+        for conflict in result.conflicts.iter().take(10) {
+            log::warning!("  {} -> {}", conflict.describe(), conflict.chosen);
+        }
+        if result.conflicts.len() > 10 {
+            log::warning!("  ほか {} 箇所", result.conflicts.len() - 10);
+        }
+    }
+    if result.types.invalid > 0 {
+        log::warning!(
+            "座標の足りない位置がある Feature が {} 個ありました。これらは集計していません。",
+            result.types.invalid
+        );
+    }
+    if options.metric == aggregate::Metric::GeodesicLength {
+        if result.types.non_linear() > 0 {
+            log::warning!(
+                "線を持たない Feature が {} 個あります ({})。これらの長さは 0 です (ポリゴンの周長は含めません)。",
+                result.types.non_linear(),
+                result.types.describe_non_linear()
+            );
+        }
+    } else if result.types.non_polygonal() > 0 && !options.by_geometry_type {
+        log::warning!(
+            "面積を持たない Feature が {} 個あります ({})。これらの面積は 0 です (--by-geometry-type で種類ごとの数と線の長さも集計できます)。",
+            result.types.non_polygonal(),
+            result.types.describe()
+        );
+    }
+    if let Some(dedup) = &options.dedup {
+        log::info!(
+            "重複した Feature を {} 個取り除きました。",
+            result.duplicates.len()
+        );
+        if let Some(report) = &dedup.report {
+            sink::csv::write_duplicates(report, &result.duplicates)?;
+            log::info!(
+                "取り除いた Feature の一覧を CSV ファイル ({}) に出力しました。",
+                report
+            );
+        }
+    }
+    if options.make_valid {
+        log::info!(
+            "不正なポリゴンを {} 個の Feature で修復しました。",
+            result.repaired
+        );
+    }
+    if let Some(source) = &options.subtract {
+        log::info!(
+            "{} のポリゴンを {} 個の Feature から差し引きました。",
+            source,
+            result.subtracted
+        );
+    }
+    if let Some(path) = &options.timing_json {
+        result.timings.write_json(path)?;
+        log::info!(
+            "段階ごとの処理時間を JSON ファイル ({}) に出力しました。",
+            path
+        );
+    }
+    if let Some(cancelled) = result.cancelled {
+        log::info!(
+            "途中までの結果 ({} 個中 {} 個の Feature を集計) を {} に出力しました。",
+            result.total,
+            result.processed,
+            target
+        );
+        return Err(cancelled.into());
+    }
+    log::info!("{} に出力しました。", target);
+    if let (Some(entry), Some(path)) = (&cache, &output_path) {
+        entry.store(path)?;
+    }
+    if let (Some(path), Some(classification)) = (&options.class_totals, &options.classify) {
+        sink::csv::write_class_totals(path, &classify::totals(classification, &result.rows))?;
+        log::info!("階級ごとの小計を CSV ファイル ({}) に出力しました。", path);
+    }
+    if let Some(path) = &options.ring_report {
+        sink::csv::write_rings(path, &result.rings)?;
+        let holes: usize = result.rings.iter().map(|row| row.holes).sum();
+        log::info!(
+            "Feature ごとの外周と穴の面積 (穴 {} 個) を CSV ファイル ({}) に出力しました。",
+            holes,
+            path
+        );
+    }
+    if let Some(path) = &options.audit_log {
+        sink::csv::write_audit(path, &result.audit)?;
+        log::info!(
+            "前処理で外した Feature とジオメトリを変えた Feature ({} 件) を CSV ファイル ({}) に出力しました。",
+            result.audit.len(),
+            path
+        );
+    }
+    if let Some(path) = &options.list_ids {
+        sink::csv::write_ids(path, &result.rows)?;
+        log::info!(
+            "グループごとの Feature の ID を CSV ファイル ({}) に出力しました。",
+            path
+        );
+    }
+    if let (Some(path), Some(input)) = (&options.provenance, input_record) {
+        let output = FileRecord::new(target, output_path.as_deref())?;
+        Provenance::new(options.arguments, input, output).write(path)?;
+        log::info!("来歴を JSON ファイル ({}) に出力しました。", path);
+    }
+    if let Some(width) = options.term_map {
+        match termmap::render(&result.rows, width) {
+            Some(map) => print!("{}", map),
+            None => log::info!("地図に描けるポリゴンがありません。"),
+        }
+    }
+    if let Some(count) = options.chart {
+        let mut rows: Vec<_> = result.rows.iter().collect();
+        rows.sort_by(|a, b| b.area.total_cmp(&a.area));
+        let items: Vec<_> = rows
+            .iter()
+            .take(count)
+            .map(|row| (row.key.clone(), row.area))
+            .collect();
+        print!("{}", chart::bars(&items, cli::terminal_width()));
+    }
+    // 全体の処理時間は最後に表示するため、段階ごとの内訳は LAYON_LOG_LEVEL=debug か --timing-json の場合だけ表示する
+    if log::is_debug() || options.timing_json.is_some() {
+        log::info!("段階ごとの処理時間:");
+        for (stage, duration) in result.timings.stages() {
+            log::info!("  {}: {} 秒", stage.label(), seconds(duration));
+        }
+    }
+    Ok(())
+}
+
+/// 出力した CSV を期待する結果と比べ、違いがあれば行ごとに表示してエラーにする
+pub fn run_check(
+    path: &str,
+    check: &check::Check,
+    format: &sink::csv::Format,
+) -> Result<(), Box<dyn Error>> {
+    let report = check::compare(path, check, format)?;
+    if report.differences.is_empty() {
+        log::info!(
+            "出力は期待する結果 ({}、{} 行) と一致しました (許容誤差 {})。",
+            check.expected,
+            report.rows,
+            check.tolerance
+        );
+        return Ok(());
+    }
+    for difference in &report.differences {
+        log::warning!("{}", difference);
+    }
+    Err(format!(
+        "出力が期待する結果 ({}) と {} か所違います (許容誤差 {})",
+        check.expected,
+        report.differences.len(),
+        check.tolerance
+    )
+    .into())
+}
+
+/// `--cache-dir` のキャッシュの項目。
+/// 出力先がファイルでない場合、入力がファイルでない場合、ほかの出力もある場合は使わない（None）
+fn cache_entry(options: &Options) -> Result<Option<cache::Entry>, Box<dyn Error>> {
+    let Some(dir) = &options.cache else {
+        return Ok(None);
+    };
+    // テンプレートを書き換えたら結果も変わる
+    let template = match &options.output {
+        sink::Output::Template(_, report) => Some(report.template.path.as_str()),
+        _ => None,
+    };
+    let inputs: Vec<&str> = options
+        .input
+        .path()
+        .into_iter()
+        .chain(options.subtract.as_deref())
+        .chain(template)
+        .collect();
+    let other_outputs = options
+        .dedup
+        .as_ref()
+        .is_some_and(|dedup| dedup.report.is_some())
+        || options.ring_report.is_some()
+        || options.audit_log.is_some()
+        || options.list_ids.is_some()
+        || options.class_totals.is_some()
+        || options.timing_json.is_some()
+        || options.provenance.is_some()
+        || options.term_map.is_some()
+        || options.chart.is_some();
+    let unusable = if options.output.path().is_none() {
+        Some("出力先がファイルではない")
+    } else if inputs.len() < 1 + options.subtract.iter().count() + template.iter().count()
+        || !inputs
+            .iter()
+            .all(|path| std::path::Path::new(path).is_file())
+    {
+        Some("入力がファイルではない")
+    } else if other_outputs {
+        Some("ほかの出力もある")
+    } else {
+        None
+    };
+    if let Some(reason) = unusable {
+        log::info!("{}ため、キャッシュを使いません。", reason);
+        return Ok(None);
+    }
+    Ok(Some(cache::Entry::new(dir, &options.arguments, &inputs)?))
+}
+
+/// 縦持ちの表の代わりにクロス集計の表を出力する
+fn run_pivot(
+    pipeline: Pipeline,
+    spec: &PivotSpec,
+    metric: aggregate::Metric,
+    path: &str,
+    format: &sink::csv::Format,
+    target: &str,
+) -> Result<(), Box<dyn Error>> {
+    let cancel = CancellationToken::new();
+    signal::cancel_on_signal(&cancel);
+    let cells = pipeline.cancellation(cancel).map_reduce(
+        |view| spec.cell(view.feature, metric),
+        Cells::default,
+        Cells::merge,
+    )?;
+    let crosstab = Crosstab::new(cells, spec.value);
+    sink::csv::write_crosstab(path, &spec.rows, &crosstab, format)?;
+    log::info!(
+        "{} × {} のクロス集計 ({} 行 × {} 列) を {} に出力しました。",
+        spec.rows,
+        spec.columns,
+        crosstab.rows.len(),
+        crosstab.columns.len(),
+        target
+    );
+    Ok(())
+}
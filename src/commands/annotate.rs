@@ -0,0 +1,24 @@
+//! `layon annotate`: 入力の各 Feature に面積と周長 (とグループの面積の合計) をプロパティとして加えた GeoJSON を出力する。
+
+use crate::cli::AnnotateOptions;
+use crate::{annotate, log, sink};
+use std::error::Error;
+
+/// 各 Feature に面積と周長を加えた GeoJSON を出力する
+pub fn run(options: AnnotateOptions) -> Result<(), Box<dyn Error>> {
+    let mut collection = options.input.read()?;
+    annotate::annotate(&mut collection, options.metric, options.group_by.as_deref());
+    if let Some(map) = &options.map {
+        map.apply_all(&mut collection);
+    }
+
+    sink::geojson::write(&options.output, &mut collection, &options.transform)?;
+    if options.output != "-" {
+        log::info!(
+            "{} 個の Feature を GeoJSON ファイル ({}) に出力しました。",
+            collection.features.len(),
+            options.output
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,71 @@
+//! `layon batch`: 複数のファイル (例: 47 都道府県) をそれぞれ集計して 1 つの表にし、ファイルごとの結果の表を表示する。
+
+use crate::cli::BatchOptions;
+use crate::{batch, log, pipeline::Pipeline, sink};
+use std::error::Error;
+
+/// 入力元をそれぞれ集計して 1 つの表にし、ファイルごとの結果の表を表示する
+pub fn run(options: BatchOptions) -> Result<(), Box<dyn Error>> {
+    log::info!("{} 個の入力元を集計します。", options.sources.len());
+    let files = batch::run(
+        &options.sources,
+        options.retry,
+        options.keep_going,
+        |source| {
+            Ok(Pipeline::read(source)
+                .group_by(options.group_by.as_str())
+                .metric(options.metric)
+                .run()?
+                .rows)
+        },
+    );
+
+    log::info!("ファイルごとの結果:");
+    log::info!("  状態    回数    行数     秒数  入力元");
+    for file in &files {
+        let rows = match &file.outcome {
+            batch::Outcome::Done(rows) => rows.len().to_string(),
+            _ => "-".to_string(),
+        };
+        let error = match &file.outcome {
+            batch::Outcome::Failed(err) => format!(": {}", err),
+            _ => String::new(),
+        };
+        // 状態の名前は全角なので、表示幅で揃える
+        let status = file.status();
+        log::info!(
+            "  {}{}  {:>4}  {:>6}  {:>7.3}  {}{}",
+            status,
+            " ".repeat(6 - 2 * status.chars().count()),
+            file.attempts,
+            rows,
+            file.elapsed.as_secs_f64(),
+            file.source,
+            error
+        );
+    }
+
+    let failed: Vec<&str> = files
+        .iter()
+        .filter(|file| matches!(file.outcome, batch::Outcome::Failed(_)))
+        .map(|file| file.source.as_str())
+        .collect();
+    if !failed.is_empty() && !options.keep_going {
+        return Err(format!(
+            "{} の集計に失敗したため、止めました (--keep-going で残りのファイルも集計できます)",
+            failed[0]
+        )
+        .into());
+    }
+    sink::csv::write_batch(&options.output, &files)?;
+    log::info!("CSV ファイル ({}) に出力しました。", options.output);
+    if !failed.is_empty() {
+        return Err(format!(
+            "{} 個のファイルの集計に失敗しました: {}",
+            failed.len(),
+            failed.join(", ")
+        )
+        .into());
+    }
+    Ok(())
+}
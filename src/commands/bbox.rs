@@ -0,0 +1,23 @@
+//! `layon bbox`: 全体とグループごとの範囲 (外接矩形) と重心を出力する。
+
+use super::is_geojson;
+use crate::cli::BboxOptions;
+use crate::{extent, log, sink};
+use std::error::Error;
+
+/// 範囲と重心を出力する
+pub fn run(options: BboxOptions) -> Result<(), Box<dyn Error>> {
+    let collection = options.input.read()?;
+    let rows = extent::extents(&collection, options.group_by.as_deref());
+
+    if is_geojson(&options.output) {
+        sink::geojson::write_extents(&options.output, &rows, &options.transform)?;
+        log::info!("GeoJSON ファイル ({}) に出力しました。", options.output);
+    } else {
+        sink::csv::write_extents(&options.output, &rows)?;
+        if options.output != "-" {
+            log::info!("CSV ファイル ({}) に出力しました。", options.output);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+//! `layon bench`: 並列処理の分け方 (Feature の数 / 頂点数) ごとに集計の処理時間を比べる。
+
+use crate::cli::BenchOptions;
+use crate::{
+    aggregate,
+    cancel::{CancellationToken, Cancelled},
+    log, schedule,
+};
+use std::error::Error;
+use std::time::Instant;
+
+/// 並列処理の分け方ごとに集計の処理時間を測る
+pub fn run(options: BenchOptions) -> Result<(), Box<dyn Error>> {
+    let collection = options.input.read()?;
+    log::info!(
+        "{} 個の Feature を {} スレッドで {} 回ずつ集計します。",
+        collection.features.len(),
+        rayon::current_num_threads(),
+        options.iterations
+    );
+
+    for (label, schedule) in [
+        ("Feature の数で分割", schedule::Schedule::Uniform),
+        ("頂点数で分割", schedule::Schedule::ByCost),
+    ] {
+        let mut times = (0..options.iterations)
+            .map(|_| {
+                let start = Instant::now();
+                aggregate::aggregate_with(
+                    &collection,
+                    &options.group_by,
+                    false,
+                    aggregate::Metric::Area,
+                    schedule,
+                    &CancellationToken::new(),
+                )?;
+                Ok(start.elapsed())
+            })
+            .collect::<Result<Vec<_>, Cancelled>>()?;
+        times.sort();
+        log::info!(
+            "{}: 最短 {:.3} 秒、中央値 {:.3} 秒",
+            label,
+            times[0].as_secs_f64(),
+            times[times.len() / 2].as_secs_f64()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,35 @@
+//! `layon breaks`: グループごとの面積から塗り分け地図の階級の境界 (自然分類・分位数・等間隔) を求める。
+
+use crate::cli::BreaksOptions;
+use crate::{breaks, log, pipeline::Pipeline, sink};
+use std::error::Error;
+
+/// グループごとの面積から階級の境界を求めて出力する
+pub fn run(options: BreaksOptions) -> Result<(), Box<dyn Error>> {
+    let result = Pipeline::from_input(options.input)
+        .group_by(options.group_by)
+        .metric(options.metric)
+        .geometry(options.geojson.is_some())
+        .run()?;
+    let values: Vec<f64> = result.rows.iter().map(|row| row.area).collect();
+    let bounds = breaks::breaks(&values, options.classes, options.method);
+    if bounds.len() < options.classes + 1 && !bounds.is_empty() {
+        log::warning!(
+            "値の種類が少ないため、階級を {} 個に減らしました。",
+            bounds.len() - 1
+        );
+    }
+
+    sink::csv::write_breaks(&options.output, &breaks::classes(&bounds, &result.rows))?;
+    if options.output != "-" {
+        log::info!("CSV ファイル ({}) に出力しました。", options.output);
+    }
+    if let Some(path) = &options.geojson {
+        sink::geojson::write_classes(path, &result.rows, &bounds, &options.transform)?;
+        log::info!(
+            "階級を付けたジオメトリを GeoJSON ファイル ({}) に出力しました。",
+            path
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,23 @@
+//! `layon centroids`: グループごとにラベルを置く代表点 (到達不能極) を出力する。
+
+use super::is_geojson;
+use crate::cli::CentroidsOptions;
+use crate::{label, log, sink};
+use std::error::Error;
+
+/// グループごとのラベルの位置を出力する
+pub fn run(options: CentroidsOptions) -> Result<(), Box<dyn Error>> {
+    let collection = options.input.read()?;
+    let rows = label::labels(&collection, &options.group_by, options.precision);
+
+    if is_geojson(&options.output) {
+        sink::geojson::write_labels(&options.output, &rows, &options.transform)?;
+        log::info!("GeoJSON ファイル ({}) に出力しました。", options.output);
+    } else {
+        sink::csv::write_labels(&options.output, &rows)?;
+        if options.output != "-" {
+            log::info!("CSV ファイル ({}) に出力しました。", options.output);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,356 @@
+//! `--dry-run`: 集計せずに、読み込む入力と行う処理の計画を表示する。
+
+use super::describe_metric;
+use crate::cli::Options;
+use crate::{geometry_type, log, pivot::PivotValue, plan, source::Schema};
+use std::error::Error;
+
+/// 集計せずに実行計画と確認結果を表示する
+pub fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    let schema = options.input.inspect()?;
+
+    log::info!("実行計画:");
+    for (i, stage) in stages(&options, &schema).iter().enumerate() {
+        log::info!("  {}. {}", i + 1, stage);
+    }
+    if let Some(timeout) = options.timeout {
+        log::info!(
+            "  ({:.1} 秒を過ぎたら途中までの結果を出力して終了)",
+            timeout.as_secs_f64()
+        );
+    }
+
+    log::info!("確認:");
+    let checks = plan::check(&schema, &options.group_by, options.metric, &options.output);
+    let mut errors = 0;
+    for check in &checks {
+        match check {
+            plan::Check::Ok(message) => log::info!("  OK   {}", message),
+            plan::Check::Warning(message) => log::info!("  注意 {}", message),
+            plan::Check::Error(message) => {
+                log::info!("  問題 {}", message);
+                errors += 1;
+            }
+        }
+    }
+    if errors > 0 {
+        return Err(format!("確認で {} 件の問題が見つかりました", errors).into());
+    }
+    Ok(())
+}
+
+/// 読み込みから出力までの処理の段階（実行する順）
+fn stages(options: &Options, schema: &Schema) -> Vec<String> {
+    let mut stages = vec![match schema.feature_count {
+        Some(count) => format!(
+            "読み込み: {} (Feature {} 個)",
+            options.input.describe(),
+            count
+        ),
+        None => format!("読み込み: {}", options.input.describe()),
+    }];
+    if options.validate_input {
+        stages.push(
+            "検証: RFC 7946 の規則 (crs メンバー、座標の範囲、リングの閉じ方と向き)".to_string(),
+        );
+    }
+    if let Some(template) = &options.group_by_template {
+        stages.push(format!(
+            "集計キーの作成: {} から {}",
+            template.properties().join(", "),
+            template.property()
+        ));
+    }
+    if let Some(normalization) = &options.normalize_key {
+        stages.push(format!(
+            "正規化: 集計キー ({}) の値をそろえる ({})",
+            options.group_by,
+            normalization.describe()
+        ));
+    }
+    if let Some(filter) = &options.filter {
+        stages.push(format!("絞り込み: {}", filter));
+    }
+    if let Some(keys) = &options.keys {
+        stages.push(format!("絞り込み: {}", keys.describe()));
+    }
+    if let Some(dedup) = &options.dedup {
+        stages.push(match dedup.tolerance {
+            Some(tolerance) => format!("重複の除去: 座標を幅 {} の格子に丸めて比べる", tolerance),
+            None => "重複の除去: ジオメトリと属性が完全に一致するもの".to_string(),
+        });
+    }
+    if options.make_valid {
+        stages.push("修復: 自己交差したポリゴンを交差する点で分けて組み立て直す".to_string());
+    }
+    if let Some(source) = &options.subtract {
+        stages.push(format!(
+            "差し引き: {} のポリゴンを各 Feature から除く",
+            source
+        ));
+    }
+    let metric = describe_metric(options.metric);
+    if let Some(property) = &options.partition_by {
+        stages.push(match &options.checkpoint_dir {
+            Some(dir) => format!(
+                "分割: {} の値ごとに Feature を分けて集計し、分割ごとの結果を {} に保存する (保存済みの分割は読み込む)",
+                property, dir
+            ),
+            None => format!("分割: {} の値ごとに Feature を分けて集計する", property),
+        });
+        if let Some(dir) = &options.tmpdir {
+            stages.push(format!(
+                "一時ファイル: 分割の Feature を集計するまで {} の下の一時ディレクトリに書き出す{}",
+                dir,
+                options
+                    .tmpdir_limit
+                    .map(|limit| format!(" (上限 {} バイト)", limit))
+                    .unwrap_or_default()
+            ));
+        }
+    }
+    if let Some(limit) = options.memory_limit {
+        stages.push(format!(
+            "書き出し: 集計用の表が {} バイトを超えたら、集計キーのハッシュ値で {} 個のファイルに分けて一時ディレクトリに書き出し、最後にまとめる",
+            limit,
+            crate::external::PARTITIONS
+        ));
+    }
+    if let Some(sampling) = options.approx {
+        stages.push(format!(
+            "概算: Feature をそれぞれ確率 {} で抽出し (種 {})、{} ごとの {} の合計を 1/{} 倍して推定する (95% 信頼区間付き)",
+            sampling.fraction,
+            sampling.seed,
+            options.group_by,
+            metric,
+            sampling.fraction
+        ));
+    }
+    match (&options.pivot, options.compare_methods) {
+        _ if options.approx.is_some() => {}
+        (_, Some(projection)) => stages.push(format!(
+            "比較: {} ごとに {} で投影した面積と楕円体上の面積 (km²) を合計し、差と相対差を求める ({} スレッドで並列に処理)",
+            options.group_by,
+            projection.name(),
+            rayon::current_num_threads()
+        )),
+        (Some(spec), None) => stages.push(format!(
+            "クロス集計: 行 {} × 列 {} の組ごとに {} を合計 ({} スレッドで並列に処理)",
+            spec.rows,
+            spec.columns,
+            match spec.value {
+                PivotValue::Area => metric.as_str(),
+                PivotValue::Count => "Feature の数",
+            },
+            rayon::current_num_threads()
+        )),
+        (None, None) => stages.push(format!(
+            "集計: {} ごとに {} を合計 ({} スレッドで頂点数ごとのチャンクに分けて並列に処理)",
+            options.group_by,
+            metric,
+            rayon::current_num_threads()
+        )),
+    }
+    if !options.agg.is_empty() {
+        let columns: Vec<String> = options.agg.iter().map(|a| a.column()).collect();
+        stages.push(format!("数値の集計: {}", columns.join(", ")));
+    }
+    if options.by_geometry_type {
+        stages.push(format!(
+            "種類別の集計: {}",
+            geometry_type::COLUMNS.join(", ")
+        ));
+    }
+    for definition in &options.derived {
+        stages.push(format!("列の計算: {}", definition.name));
+    }
+    if let Some(smoothing) = &options.smooth {
+        stages.push(format!(
+            "平滑化: {} を隣接する市町村と境界の長さで重み付けして平均 ({})",
+            smoothing.column,
+            smoothing.name()
+        ));
+    }
+    if let Some(keep) = &options.keep {
+        stages.push(format!(
+            "引き継ぎ: {} の値を列に加える (食い違えば {})",
+            keep.properties.join(", "),
+            keep.policy.name()
+        ));
+    }
+    if let Some(classification) = &options.classify {
+        let labels: Vec<&str> = classification
+            .rules
+            .iter()
+            .map(|rule| rule.label.as_str())
+            .collect();
+        stages.push(format!(
+            "階級分け: {} の値で {}",
+            classification.column,
+            labels.join(" / ")
+        ));
+    }
+    match (&options.sort, options.top) {
+        (Some(key), Some(top)) => stages.push(format!(
+            "並べ替え: {} の{}で先頭の {} 行",
+            key.column,
+            if key.ascending { "昇順" } else { "降順" },
+            top
+        )),
+        (Some(key), None) => stages.push(format!(
+            "並べ替え: {} の{}",
+            key.column,
+            if key.ascending { "昇順" } else { "降順" }
+        )),
+        (None, Some(top)) => stages.push(format!("並べ替え: 面積の降順で先頭の {} 行", top)),
+        (None, None) => {}
+    }
+    stages.push(format!("出力: {}", options.output.describe()));
+    stages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Command;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    fn options(args: &[&str]) -> Options {
+        match Command::parse(args.iter().map(|arg| arg.to_string())).unwrap() {
+            Command::Run(options) => *options,
+            _ => panic!("集計のコマンドではありません: {:?}", args),
+        }
+    }
+
+    /// 確認の結果の種類（OK、注意、問題）と内容
+    fn checks(options: &Options) -> Vec<(&'static str, String)> {
+        let schema = options.input.inspect().unwrap();
+        plan::check(&schema, &options.group_by, options.metric, &options.output)
+            .into_iter()
+            .map(|check| match check {
+                plan::Check::Ok(message) => ("OK", message),
+                plan::Check::Warning(message) => ("注意", message),
+                plan::Check::Error(message) => ("問題", message),
+            })
+            .collect()
+    }
+
+    fn errors(options: &Options) -> Vec<String> {
+        checks(options)
+            .into_iter()
+            .filter(|(kind, _)| *kind == "問題")
+            .map(|(_, message)| message)
+            .collect()
+    }
+
+    fn output(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("layon-dry-run-{}-{}.csv", name, std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn plan_lists_the_stages_and_a_missing_group_key_is_an_error() {
+        let csv = output("plan");
+        let options = options(&[
+            "-i",
+            FIXTURE,
+            "-g",
+            "N03_999",
+            "--metric",
+            "geodesic-area",
+            "--top",
+            "3",
+            "-o",
+            &csv,
+            "--dry-run",
+        ]);
+        let schema = options.input.inspect().unwrap();
+        let stages = stages(&options, &schema);
+        assert!(stages[0].starts_with("読み込み: "), "{}", stages[0]);
+        assert!(stages[0].ends_with("(Feature 8 個)"), "{}", stages[0]);
+        assert!(
+            stages[1].starts_with("集計: N03_999 ごとに"),
+            "{}",
+            stages[1]
+        );
+        assert_eq!(stages[2], "並べ替え: 面積の降順で先頭の 3 行");
+        assert!(stages[3].starts_with("出力: "), "{}", stages[3]);
+        assert_eq!(stages.len(), 4);
+
+        let errors = errors(&options);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(
+            errors[0].starts_with("集計キー N03_999 が先頭の"),
+            "{}",
+            errors[0]
+        );
+        // 使えるプロパティを挙げる
+        assert!(errors[0].contains("N03_004"), "{}", errors[0]);
+        let err = run(options).unwrap_err().to_string();
+        assert_eq!(err, "確認で 1 件の問題が見つかりました");
+        // 確かめるために作ったファイルは残さない
+        assert!(!std::path::Path::new(&csv).exists());
+
+        let valid = self::options(&["-i", FIXTURE, "-o", &csv, "--dry-run"]);
+        assert!(self::errors(&valid).is_empty());
+        assert!(run(valid).is_ok());
+    }
+
+    #[test]
+    fn an_unwritable_output_is_an_error() {
+        let options = options(&[
+            "-i",
+            FIXTURE,
+            "-o",
+            "/nonexistent-layon-directory/area.csv",
+            "--dry-run",
+        ]);
+        let errors = errors(&options);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(
+            errors[0].starts_with("/nonexistent-layon-directory/area.csv に書き込めません"),
+            "{}",
+            errors[0]
+        );
+    }
+
+    #[test]
+    fn coordinates_outside_lon_lat_are_an_error_for_geodesic_areas() {
+        let input = std::env::temp_dir().join(format!(
+            "layon-dry-run-projected-{}.geojson",
+            std::process::id()
+        ));
+        std::fs::write(
+            &input,
+            r#"{"type":"FeatureCollection","features":[{"type":"Feature","properties":{"N03_004":"川越市"},"geometry":{"type":"Polygon","coordinates":[[[-30000,5000],[-29000,5000],[-29000,6000],[-30000,5000]]]}}]}"#,
+        )
+        .unwrap();
+        let input = input.to_str().unwrap();
+        let csv = output("projected");
+        let geodesic = options(&[
+            "-i",
+            input,
+            "--metric",
+            "geodesic-area",
+            "-o",
+            &csv,
+            "--dry-run",
+        ]);
+        assert_eq!(
+            errors(&geodesic),
+            ["先頭の Feature の座標が経度・緯度の範囲を超えています (geodesic-area, length と --projection には経度・緯度の座標が必要です)"]
+        );
+        // 平面上の面積は座標の単位のまま求められる
+        let planar = options(&["-i", input, "-o", &csv, "--dry-run"]);
+        assert!(errors(&planar).is_empty());
+        assert!(!checks(&planar)
+            .iter()
+            .any(|(_, message)| message.contains("経度・緯度")));
+        std::fs::remove_file(input).unwrap();
+    }
+}
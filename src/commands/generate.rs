@@ -0,0 +1,20 @@
+//! `layon generate`: 合成したポリゴンの GeoJSON を出力する (ベンチマーク用や、実データを共有できない不具合報告に)。
+
+use crate::cli::GenerateOptions;
+use crate::log;
+use std::error::Error;
+
+/// 合成した Feature の GeoJSON を出力する
+pub fn run(options: GenerateOptions) -> Result<(), Box<dyn Error>> {
+    options
+        .generator
+        .write(&options.output, &options.transform)?;
+    if options.output != "-" {
+        log::info!(
+            "{} 個の Feature を GeoJSON ファイル ({}) に出力しました。",
+            options.generator.features,
+            options.output
+        );
+    }
+    Ok(())
+}
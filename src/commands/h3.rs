@@ -0,0 +1,22 @@
+//! `layon h3`: H3 のセルで覆い、集計キー × セルの番号ごとにセルが覆う面積を集計する。
+
+use crate::cli::H3Options;
+use crate::{h3, log, sink};
+use std::error::Error;
+
+/// 属性の階層から包含関係のグラフを作って出力する
+/// 標準地域メッシュの区画ごとに面積を集計する
+pub fn run(options: H3Options) -> Result<(), Box<dyn Error>> {
+    let collection = options.input.read()?;
+    let rows = h3::aggregate(
+        &collection,
+        &options.group_by,
+        options.resolution,
+        options.metric,
+    )?;
+    sink::csv::write_h3(&options.output, &rows)?;
+    if options.output != "-" {
+        log::info!("CSV ファイル ({}) に出力しました。", options.output);
+    }
+    Ok(())
+}
@@ -0,0 +1,41 @@
+//! `layon hierarchy`: 都道府県 → 市区町村 → 区の包含関係を、面積を付けた節点と辺のグラフ (JSON / GraphML) で出力する。
+
+use crate::cli::HierarchyOptions;
+use crate::{hierarchy, log, sink};
+use std::error::Error;
+
+pub fn run(options: HierarchyOptions) -> Result<(), Box<dyn Error>> {
+    let collection = options.input.read()?;
+    let hierarchy = hierarchy::build(&collection, &options.levels, options.metric);
+    if hierarchy.skipped > 0 {
+        log::warning!(
+            "{} 個の Feature はどの階層のプロパティ ({}) にも値がないため、グラフに含めませんでした。",
+            hierarchy.skipped,
+            options.levels.join(", ")
+        );
+    }
+    let uncontained = hierarchy.uncontained();
+    if uncontained > 0 {
+        log::warning!(
+            "{} 本の辺で、子の代表点が親のポリゴンに含まれていません (出力の contained が false の辺)。",
+            uncontained
+        );
+    }
+
+    let graphml = options.output.to_lowercase().ends_with(".graphml");
+    if graphml {
+        sink::graph::write_graphml(&options.output, &hierarchy)?;
+    } else {
+        sink::graph::write_json(&options.output, &hierarchy)?;
+    }
+    if options.output != "-" {
+        log::info!(
+            "{} 個の節点と {} 本の辺を {} ファイル ({}) に出力しました。",
+            hierarchy.nodes.len(),
+            hierarchy.edges.len(),
+            if graphml { "GraphML" } else { "JSON" },
+            options.output
+        );
+    }
+    Ok(())
+}
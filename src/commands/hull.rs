@@ -0,0 +1,30 @@
+//! `layon hull`: グループごとの凸包 (または凹包) の面積と、ポリゴンが占める割合 (充填率) を出力する。
+
+use super::is_geojson;
+use crate::cli::HullOptions;
+use crate::{hull, log, sink};
+use std::error::Error;
+
+/// グループごとの包む形の面積と充填率を出力する
+pub fn run(options: HullOptions) -> Result<(), Box<dyn Error>> {
+    let collection = options.input.read()?;
+    let geojson = is_geojson(&options.output);
+    let rows = hull::hulls(
+        &collection,
+        &options.group_by,
+        options.hull,
+        options.metric,
+        geojson,
+    )?;
+
+    if geojson {
+        sink::geojson::write_hulls(&options.output, &rows, &options.transform)?;
+        log::info!("GeoJSON ファイル ({}) に出力しました。", options.output);
+    } else {
+        sink::csv::write_hulls(&options.output, &rows)?;
+        if options.output != "-" {
+            log::info!("CSV ファイル ({}) に出力しました。", options.output);
+        }
+    }
+    Ok(())
+}
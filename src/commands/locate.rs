@@ -0,0 +1,54 @@
+//! `layon locate`: 点 (経度,緯度など) を含む市町村を探す (どれにも含まれなければ最も近い市町村と距離)。
+
+use crate::cli::LocateOptions;
+use crate::{locate, log, sink};
+use std::error::Error;
+
+/// 点を含む（またはいちばん近い）ポリゴンを探して出力する
+pub fn run(options: LocateOptions) -> Result<(), Box<dyn Error>> {
+    let collection = options.input.read()?;
+    if let Some(points) = &options.points_csv {
+        let summary = locate::locate_csv(
+            &collection,
+            &options.columns,
+            points,
+            options.metric,
+            options.max_distance,
+            &options.output,
+        )?;
+        if options.output != "-" {
+            log::info!(
+                "{} 行のうち、{} 行は最も近いポリゴンを返し、{} 行は見つからず、{} 行は座標を読めませんでした。",
+                summary.rows,
+                summary.outside,
+                summary.missing,
+                summary.invalid
+            );
+            log::info!("CSV ファイル ({}) に出力しました。", options.output);
+        }
+        return Ok(());
+    }
+    let rows = locate::locate_all(
+        &collection,
+        &options.columns,
+        &options.points,
+        options.metric,
+        options.max_distance,
+    );
+    sink::csv::write_locations(&options.output, &options.columns, &rows)?;
+    if options.output != "-" {
+        let outside = rows
+            .iter()
+            .filter(|row| row.distance.is_some_and(|d| d > 0.0))
+            .count();
+        let missing = rows.iter().filter(|row| row.distance.is_none()).count();
+        log::info!(
+            "{} 個の点のうち、{} 個は最も近いポリゴンを返し、{} 個は見つかりませんでした。",
+            rows.len(),
+            outside,
+            missing
+        );
+        log::info!("CSV ファイル ({}) に出力しました。", options.output);
+    }
+    Ok(())
+}
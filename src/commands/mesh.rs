@@ -0,0 +1,20 @@
+//! `layon mesh`: 標準地域メッシュ (1km / 500m) の区画で切り分け、集計キー × メッシュコードごとの面積を集計する。
+
+use crate::cli::MeshOptions;
+use crate::{log, mesh, sink};
+use std::error::Error;
+
+pub fn run(options: MeshOptions) -> Result<(), Box<dyn Error>> {
+    let collection = options.input.read()?;
+    let rows = mesh::aggregate(
+        &collection,
+        &options.group_by,
+        options.level,
+        options.metric,
+    );
+    sink::csv::write_mesh(&options.output, &rows)?;
+    if options.output != "-" {
+        log::info!("CSV ファイル ({}) に出力しました。", options.output);
+    }
+    Ok(())
+}
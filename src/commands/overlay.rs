@@ -0,0 +1,22 @@
+//! `layon overlay`: 2 つのレイヤーを重ね、キーの組ごとの交差面積を集計する。
+
+use crate::cli::OverlayOptions;
+use crate::{log, overlay, sink};
+use std::error::Error;
+
+/// 2 つのレイヤーを重ねて交差面積を集計する
+pub fn run(options: OverlayOptions) -> Result<(), Box<dyn Error>> {
+    let left = options.input.read()?;
+    let right = options.overlay.read()?;
+    let rows = overlay::overlay(
+        &left,
+        &options.group_by,
+        &right,
+        &options.overlay_key,
+        options.metric,
+    );
+
+    sink::csv::write_overlay(&options.output, &rows)?;
+    log::info!("CSV ファイル ({}) に出力しました。", options.output);
+    Ok(())
+}
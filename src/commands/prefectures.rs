@@ -0,0 +1,48 @@
+//! `layon prefectures`: 市区町村のポリゴンを都道府県ごとにディゾルブし、簡略化した境界と面積を出力する。
+
+use super::{describe_metric, is_geojson};
+use crate::cli::PrefecturesOptions;
+use crate::{log, prefecture, sink};
+use std::error::Error;
+
+/// 市区町村を都道府県ごとにディゾルブし、簡略化した境界と面積を出力する
+pub fn run(options: PrefecturesOptions) -> Result<(), Box<dyn Error>> {
+    let input = options.input.describe();
+    let rows = prefecture::dissolve(
+        options.input,
+        &options.group_by,
+        options.metric,
+        options.tolerance,
+    )?;
+
+    if options.output.to_lowercase().ends_with(".html") {
+        let summary = sink::bundle::Summary {
+            title: options
+                .title
+                .unwrap_or_else(|| format!("{} の {} ごとの集計", input, options.group_by)),
+            input,
+            group_by: options.group_by,
+            metric: describe_metric(options.metric),
+            simplify: options.tolerance,
+        };
+        sink::bundle::write(&options.output, &rows, &options.transform, &summary)?;
+        log::info!(
+            "{} 個の境界と集計結果を HTML ファイル ({}) にまとめて出力しました。",
+            rows.len(),
+            options.output
+        );
+    } else if is_geojson(&options.output) {
+        sink::geojson::write_dissolved(&options.output, &rows, &options.transform)?;
+        log::info!(
+            "{} 個の都道府県の境界を GeoJSON ファイル ({}) に出力しました。",
+            rows.len(),
+            options.output
+        );
+    } else {
+        sink::csv::write_dissolved(&options.output, &rows)?;
+        if options.output != "-" {
+            log::info!("CSV ファイル ({}) に出力しました。", options.output);
+        }
+    }
+    Ok(())
+}
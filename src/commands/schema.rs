@@ -0,0 +1,25 @@
+//! `layon schema`: プロパティの型を推定し、Feature のプロパティを読む型付きの Rust の構造体を生成する。
+
+use crate::cli::SchemaOptions;
+use crate::{log, schema};
+use std::error::Error;
+
+/// プロパティの型を推定し、型付きの構造体のソースを出力する
+pub fn run(options: SchemaOptions) -> Result<(), Box<dyn Error>> {
+    let source = options.input.describe();
+    let collection = options.input.read()?;
+    let schema = schema::Schema::infer(&collection, options.sample, options.map.as_ref())?;
+    let text = schema.to_rust(&options.name, &source)?;
+    if options.output == "-" {
+        print!("{}", text);
+    } else {
+        std::fs::write(&options.output, text)?;
+        log::info!(
+            "{} 個のフィールドの構造体 {} を {} に出力しました。",
+            schema.fields.len(),
+            options.name,
+            options.output
+        );
+    }
+    Ok(())
+}
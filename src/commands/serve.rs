@@ -0,0 +1,48 @@
+//! `layon serve`: データセットを読み込んだまま HTTP で集計結果を返し、集計表と地図のページ (/dashboard) を出す。
+
+use crate::cli::ServeOptions;
+use crate::{log, serve};
+use std::error::Error;
+
+/// データセットを読み込み、HTTP で集計結果とダッシュボードを返し続ける
+pub fn run(options: ServeOptions) -> Result<(), Box<dyn Error>> {
+    let name = options.input.describe();
+    let collection = options.input.read()?;
+    log::info!(
+        "{} 個の Feature を読み込みました。",
+        collection.features.len()
+    );
+    let dataset = serve::registry::Dataset::new(options.dataset, name, collection);
+    let mut server =
+        serve::Server::new(dataset, options.group_by, options.metric, options.tolerance)
+            .max_datasets(options.max_datasets);
+    if let Some(dir) = &options.allow_register {
+        let registration = serve::registry::Registration::new(dir, options.allow_external_sources)
+            .map_err(|err| {
+                format!(
+                    "--allow-register のディレクトリ {} を開けません: {}",
+                    dir, err
+                )
+            })?;
+        log::info!("{} の下のファイルをデータセットとして登録できます。", dir);
+        server = server.allow_register(registration);
+    }
+    let server = std::sync::Arc::new(server);
+    #[cfg(feature = "grpc")]
+    if let Some(address) = options.grpc {
+        let server = std::sync::Arc::clone(&server);
+        log::info!("gRPC のサービスを {} で待ち受けています。", address);
+        std::thread::spawn(move || {
+            if let Err(err) = serve::grpc::listen(server, &address) {
+                log::warning!(
+                    "警告: gRPC のサービスを {} で待ち受けられません: {}",
+                    address,
+                    err
+                );
+            }
+        });
+    }
+    log::info!("http://{}/dashboard で待ち受けています。", options.listen);
+    server.listen(&options.listen)?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! `layon stream`: ストリームから Feature を受け取り続けて集計し、一定の間隔で集計結果を出力する。
+
+use crate::cli::StreamOptions;
+use crate::{
+    aggregate,
+    cancel::CancellationToken,
+    filter::Filter,
+    log, signal,
+    stream::{self, Measure, Measured, RollingAggregate},
+};
+use std::error::Error;
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// ストリームから Feature を受け取り続け、一定の間隔でその時点の集計結果を出力する。
+/// 受け取り元が終わるか中断されたら、最後の集計結果を出力して終了する
+pub fn run(options: StreamOptions) -> Result<(), Box<dyn Error>> {
+    let filter = options.filter.as_deref().map(Filter::parse).transpose()?;
+    let measure = Measure::new(&options.group_by, options.metric, filter);
+    let measured = stream::spawn_stages(options.source.subscribe()?, measure);
+    let cancel = CancellationToken::new();
+    signal::cancel_on_signal(&cancel);
+    log::info!(
+        "{} から Feature を受け取り、{} 秒ごとに {} に出力します。",
+        options.source.describe(),
+        options.interval.as_secs_f64(),
+        options.output.describe()
+    );
+
+    // 出力のステージ。書き込みの間に集計が止まらないよう別のスレッドで書き出し、待たせるスナップショットは 1 つまでにする
+    let (snapshots, pending) = mpsc::sync_channel::<(usize, Vec<aggregate::GroupResult>)>(1);
+    let output = options.output;
+    let writer = thread::spawn(move || -> Result<(), String> {
+        for (features, rows) in pending {
+            output.write(&rows).map_err(|err| err.to_string())?;
+            log::info!(
+                "Feature {} 個 ({} グループ) の集計結果を出力しました。",
+                features,
+                rows.len()
+            );
+        }
+        Ok(())
+    });
+    // 最後のスナップショットを出力し終えるのを待つ（出力のエラーはここで返す）
+    let finish = |snapshots: SyncSender<_>, rolling: &RollingAggregate| {
+        // 出力のステージが終わっていれば、送れなくてもそのエラーを返す
+        let _ = snapshots.send((rolling.features(), rolling.snapshot()));
+        drop(snapshots);
+        writer.join().expect("出力のスレッドが異常終了しました")
+    };
+
+    let mut rolling = RollingAggregate::default();
+    // 中断されたことに気づけるよう、メッセージが来なくても一定の間隔で確認する
+    let poll = Duration::from_millis(200);
+    let mut next = Instant::now() + options.interval;
+    let (mut skipped, mut dropped) = (0, 0);
+    loop {
+        match measured.recv_timeout(next.saturating_duration_since(Instant::now()).min(poll)) {
+            Ok(Ok(Measured::Features(values))) => rolling.add(values),
+            Ok(Ok(Measured::Skipped { position, error })) => {
+                log::warning!("メッセージを読み飛ばしました ({}): {}", position, error);
+                skipped += 1;
+            }
+            Ok(Err(err)) => {
+                finish(snapshots, &rolling)?;
+                return Err(err.into());
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if cancel.is_cancelled() {
+            log::info!("中断しました。");
+            break;
+        }
+        if Instant::now() >= next {
+            // 前のスナップショットをまだ書き出している間は飛ばす（次のスナップショットに含まれる）
+            match snapshots.try_send((rolling.features(), rolling.snapshot())) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => dropped += 1,
+                Err(TrySendError::Disconnected(_)) => break,
+            }
+            next = Instant::now() + options.interval;
+        }
+    }
+    finish(snapshots, &rolling)?;
+    if dropped > 0 {
+        log::info!(
+            "出力が追いつかなかったため、途中の集計結果を {} 回出力しませんでした。",
+            dropped
+        );
+    }
+    if skipped > 0 {
+        log::info!("読み込めなかったメッセージが {} 件ありました。", skipped);
+    }
+    Ok(())
+}
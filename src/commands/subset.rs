@@ -0,0 +1,87 @@
+//! `layon head` / `layon sample`: 先頭の、または無作為に抽出した Feature を GeoJSON で出力する。
+
+use crate::cli::{Selection, SubsetOptions};
+use crate::{log, sink, subset};
+use std::error::Error;
+
+/// Feature の一部を取り出して GeoJSON で出力する
+pub fn run(options: SubsetOptions) -> Result<(), Box<dyn Error>> {
+    let mut subset = match options.selection {
+        Selection::Head(count) => subset::read_head(&options.input, count)?,
+        Selection::Sample { fraction, seed } => {
+            subset::sample(options.input.read()?, fraction, seed)
+        }
+    };
+    if let Some(map) = &options.map {
+        map.apply_all(&mut subset);
+    }
+
+    sink::geojson::write(&options.output, &mut subset, &options.transform)?;
+    if options.output != "-" {
+        log::info!(
+            "{} 個の Feature を GeoJSON ファイル ({}) に出力しました。",
+            subset.features.len(),
+            options.output
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Command;
+    use geojson::FeatureCollection;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    fn parse(args: &[&str]) -> Result<SubsetOptions, String> {
+        match Command::parse(args.iter().map(|arg| arg.to_string()))? {
+            Command::Subset(options) => Ok(options),
+            _ => panic!("head / sample のコマンドではありません: {:?}", args),
+        }
+    }
+
+    /// 書き出した GeoJSON の Feature の N03_007（団体コード）
+    fn codes(args: &[&str], output: &str) -> Vec<String> {
+        let mut args = args.to_vec();
+        args.extend(["-i", FIXTURE, "-o", output]);
+        run(parse(&args).unwrap()).unwrap();
+        let collection: FeatureCollection =
+            std::fs::read_to_string(output).unwrap().parse().unwrap();
+        collection
+            .features
+            .iter()
+            .map(|feature| feature.property("N03_007").unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn head_and_sample_write_the_selected_features() {
+        let output =
+            std::env::temp_dir().join(format!("layon-subset-{}.geojson", std::process::id()));
+        let output = output.to_str().unwrap();
+        let all = codes(&["head", "-n", "100"], output);
+        assert_eq!(all.len(), 8);
+        assert_eq!(codes(&["head", "--count=3"], output), all[..3]);
+        assert_eq!(codes(&["sample", "--fraction", "1"], output), all);
+
+        let half = codes(&["sample", "--fraction", "0.5", "--seed", "3"], output);
+        assert_eq!(
+            codes(&["sample", "--fraction", "0.5", "--seed", "3"], output),
+            half
+        );
+        assert!(half.iter().all(|code| all.contains(code)));
+        std::fs::remove_file(output).unwrap();
+
+        assert!(parse(&["head", "-n", "three"]).is_err());
+        assert!(parse(&["sample"]).is_err());
+        assert!(parse(&["sample", "--fraction", "1.5"]).is_err());
+        // 数は head だけ、確率と種は sample だけに指定できる
+        assert!(parse(&["sample", "-n", "3", "--fraction", "0.5"]).is_err());
+        assert!(parse(&["head", "--seed", "3"]).is_err());
+    }
+}
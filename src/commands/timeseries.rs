@@ -0,0 +1,26 @@
+//! `layon timeseries`: 複数の年次のデータから市町村ごとの面積の推移を横持ちの表にし、合併・分割を記録する。
+
+use crate::cli::TimeSeriesOptions;
+use crate::{log, sink, timeseries};
+use std::error::Error;
+
+/// 年次ごとの面積の推移を集計する
+pub fn run(options: TimeSeriesOptions) -> Result<(), Box<dyn Error>> {
+    let mut vintages = Vec::with_capacity(options.vintages.len());
+    for (label, input) in options.vintages {
+        let collection = input.read()?;
+        vintages.push(timeseries::Vintage { label, collection });
+    }
+    let rows = timeseries::timeseries(&vintages, &options.group_by);
+    let labels: Vec<&str> = vintages.iter().map(|v| v.label.as_str()).collect();
+
+    let changed = rows.iter().filter(|row| !row.changes.is_empty()).count();
+    log::info!(
+        "{} 市町村のうち {} 市町村に合併・分割などの変化がありました。",
+        rows.len(),
+        changed
+    );
+    sink::csv::write_timeseries(&options.output, &labels, &rows)?;
+    log::info!("CSV ファイル ({}) に出力しました。", options.output);
+    Ok(())
+}
@@ -0,0 +1,538 @@
+//! 端末で集計結果を見て回る対話モード（`layon tui`）。
+//! グループの一覧を並べ替えながら眺め、市町村を選ぶとその Feature の一覧に入り、
+//! その場で CSV に書き出せる。描画は ratatui で、キー入力と raw モードの切り替えは crossterm で行う。
+//! 画面の状態（並び順、選択中の行、詳細の一覧）は `App` が持ち、描画とは分けてある。
+
+use crate::cli::TuiOptions;
+use crate::{
+    aggregate::{self, GroupResult},
+    sink,
+};
+use geojson::FeatureCollection;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Alignment, Constraint, Layout},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Cell, Row, Table, TableState},
+    Frame,
+};
+use std::{
+    error::Error,
+    io::{self, IsTerminal},
+};
+
+/// 一覧の並び順
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Sort {
+    Area,
+    Count,
+    Key,
+}
+
+impl Sort {
+    fn next(self) -> Sort {
+        match self {
+            Sort::Area => Sort::Count,
+            Sort::Count => Sort::Key,
+            Sort::Key => Sort::Area,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Sort::Area => "面積",
+            Sort::Count => "Feature の数",
+            Sort::Key => "名前",
+        }
+    }
+}
+
+/// 選択中の行と、画面の先頭に表示している行（描画するときに ratatui が選択中の行が入るようにずらす）
+#[derive(Default)]
+struct Cursor {
+    selected: usize,
+    offset: usize,
+}
+
+impl Cursor {
+    fn move_by(&mut self, delta: isize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        self.selected = self.selected.saturating_add_signed(delta).min(len - 1);
+    }
+
+    /// 表を描画し、ずらした先頭の行を覚えておく
+    fn render(&mut self, frame: &mut Frame, table: Table, area: ratatui::layout::Rect) {
+        let mut state = TableState::new()
+            .with_offset(self.offset)
+            .with_selected(Some(self.selected));
+        frame.render_stateful_widget(table, area, &mut state);
+        self.offset = state.offset();
+    }
+}
+
+/// 市町村の Feature の一覧（入力の中での位置と面積）
+struct Detail {
+    key: String,
+    features: Vec<(usize, f64)>,
+    cursor: Cursor,
+}
+
+struct App {
+    options: TuiOptions,
+    collection: FeatureCollection,
+    groups: Vec<GroupResult>,
+    sort: Sort,
+    descending: bool,
+    cursor: Cursor,
+    detail: Option<Detail>,
+    /// 最下行に表示するメッセージ
+    status: String,
+}
+
+const HELP: &str =
+    "↑↓/jk 移動  PgUp/PgDn  Enter 詳細  ←/Esc 戻る  s 並び替え  r 逆順  e CSV に出力  q 終了";
+
+/// 見出し、表の見出し、状態、操作の説明の行を除いた、表の行の数
+const CHROME_ROWS: u16 = 4;
+
+/// 対話モードを実行する
+pub fn run(options: TuiOptions) -> Result<(), Box<dyn Error>> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err("tui は端末から実行してください".into());
+    }
+    let collection = options.input.read()?;
+    let groups = aggregate::aggregate_with(
+        &collection,
+        &options.group_by,
+        false,
+        options.metric,
+        crate::schedule::Schedule::ByCost,
+        &crate::cancel::CancellationToken::new(),
+    )?;
+    let mut app = App::new(options, collection, groups);
+
+    // 別の画面に切り替えて raw モードにする（パニックしたときも元に戻す）
+    let mut terminal = ratatui::init();
+    let result = (|| -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| app.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let page = terminal.size()?.height.saturating_sub(CHROME_ROWS).max(1) as isize;
+            if !app.handle(key.code, page) {
+                return Ok(());
+            }
+        }
+    })();
+    ratatui::restore();
+    Ok(result?)
+}
+
+impl App {
+    /// 面積の降順に並べた一覧から始める
+    fn new(options: TuiOptions, collection: FeatureCollection, groups: Vec<GroupResult>) -> App {
+        let status = format!(
+            "{} 個の Feature を {} ごとに集計しました",
+            collection.features.len(),
+            options.group_by
+        );
+        let mut app = App {
+            options,
+            collection,
+            groups,
+            sort: Sort::Area,
+            descending: true,
+            cursor: Cursor::default(),
+            detail: None,
+            status: String::new(),
+        };
+        app.sort_all();
+        app.status = status;
+        app
+    }
+
+    /// キーを処理する（終了する場合は false）
+    fn handle(&mut self, key: KeyCode, page: isize) -> bool {
+        let len = match &self.detail {
+            Some(detail) => detail.features.len(),
+            None => self.groups.len(),
+        };
+        let cursor = match &mut self.detail {
+            Some(detail) => &mut detail.cursor,
+            None => &mut self.cursor,
+        };
+        match key {
+            KeyCode::Char('q') => return false,
+            KeyCode::Up | KeyCode::Char('k') => cursor.move_by(-1, len),
+            KeyCode::Down | KeyCode::Char('j') => cursor.move_by(1, len),
+            KeyCode::PageUp => cursor.move_by(-page, len),
+            KeyCode::PageDown => cursor.move_by(page, len),
+            KeyCode::Home | KeyCode::Char('g') => cursor.selected = 0,
+            KeyCode::End | KeyCode::Char('G') => cursor.selected = len.saturating_sub(1),
+            KeyCode::Enter | KeyCode::Right if self.detail.is_none() => self.open_detail(),
+            KeyCode::Esc | KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => {
+                self.detail = None
+            }
+            KeyCode::Char('s') => {
+                self.sort = self.sort.next();
+                // 名前は昇順、数値は降順から始める
+                self.descending = self.sort != Sort::Key;
+                self.sort_all();
+            }
+            KeyCode::Char('r') => {
+                self.descending = !self.descending;
+                self.sort_all();
+            }
+            KeyCode::Char('e') => self.export(),
+            _ => {}
+        }
+        true
+    }
+
+    fn sort_all(&mut self) {
+        let (sort, descending) = (self.sort, self.descending);
+        self.groups.sort_by(|a, b| {
+            let ordering = match sort {
+                Sort::Area => a.area.total_cmp(&b.area),
+                Sort::Count => a.count.cmp(&b.count),
+                Sort::Key => a.key.cmp(&b.key),
+            };
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        if let Some(detail) = &mut self.detail {
+            detail.features.sort_by(|a, b| match sort {
+                Sort::Area | Sort::Count if descending => b.1.total_cmp(&a.1),
+                Sort::Area | Sort::Count => a.1.total_cmp(&b.1),
+                Sort::Key if descending => b.0.cmp(&a.0),
+                Sort::Key => a.0.cmp(&b.0),
+            });
+        }
+        self.status = format!(
+            "{}の{}に並べ替えました",
+            self.sort.label(),
+            if self.descending { "降順" } else { "昇順" }
+        );
+    }
+
+    /// 選択中の市町村の Feature の一覧に入る
+    fn open_detail(&mut self) {
+        let Some(group) = self.groups.get(self.cursor.selected) else {
+            return;
+        };
+        let key = group.key.clone();
+        let mut features: Vec<_> = self
+            .collection
+            .features
+            .iter()
+            .enumerate()
+            .filter(|(_, feature)| {
+                feature
+                    .property(&self.options.group_by)
+                    .and_then(|value| value.as_str())
+                    == Some(key.as_str())
+            })
+            .map(|(i, feature)| (i, aggregate::feature_area(feature, self.options.metric)))
+            .collect();
+        features.sort_by(|a, b| b.1.total_cmp(&a.1));
+        self.status = format!("{} の {} 個の Feature", key, features.len());
+        self.detail = Some(Detail {
+            key,
+            features,
+            cursor: Cursor::default(),
+        });
+    }
+
+    /// 表示中の並び順でグループの一覧を CSV に書き出す
+    fn export(&mut self) {
+        self.status = match sink::csv::write(
+            &self.options.output,
+            &self.groups,
+            &sink::csv::Format::default(),
+        ) {
+            Ok(()) => format!(
+                "{} 件のグループを CSV ファイル ({}) に出力しました",
+                self.groups.len(),
+                self.options.output
+            ),
+            Err(err) => format!("出力に失敗しました: {}", err),
+        };
+    }
+
+    /// 画面全体を描画する
+    fn draw(&mut self, frame: &mut Frame) {
+        let [title, table, status, help] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let bold = Style::new().add_modifier(Modifier::BOLD);
+        let underlined = Style::new().add_modifier(Modifier::UNDERLINED);
+        let reversed = Style::new().add_modifier(Modifier::REVERSED);
+        let right = |text: String| Cell::from(Line::from(text).alignment(Alignment::Right));
+
+        match &mut self.detail {
+            None => {
+                let order = if self.descending { "↓" } else { "↑" };
+                frame.render_widget(
+                    Line::from(vec![
+                        Span::styled("layon tui", bold),
+                        Span::raw(format!(
+                            "  {} グループ  並び順: {}{}",
+                            self.groups.len(),
+                            self.sort.label(),
+                            order
+                        )),
+                    ]),
+                    title,
+                );
+                let rows = self.groups.iter().map(|group| {
+                    Row::new([
+                        Cell::from(group.key.as_str()),
+                        right(format!("{:.6}", group.area)),
+                        right(group.count.to_string()),
+                    ])
+                });
+                let widths = [
+                    Constraint::Min(24),
+                    Constraint::Length(16),
+                    Constraint::Length(8),
+                ];
+                let header = Row::new([
+                    Cell::from(self.options.group_by.as_str()),
+                    right("面積".to_string()),
+                    right("Feature".to_string()),
+                ])
+                .style(underlined);
+                let list = Table::new(rows, widths)
+                    .header(header)
+                    .column_spacing(2)
+                    .row_highlight_style(reversed);
+                self.cursor.render(frame, list, table);
+            }
+            Some(detail) => {
+                frame.render_widget(
+                    Line::from(vec![
+                        Span::styled(detail.key.as_str(), bold),
+                        Span::raw(format!("  {} 個の Feature", detail.features.len())),
+                    ]),
+                    title,
+                );
+                let group_by = &self.options.group_by;
+                let collection = &self.collection;
+                let rows = detail.features.iter().map(|&(index, area)| {
+                    let properties = collection.features[index]
+                        .properties
+                        .iter()
+                        .flatten()
+                        .filter(|(name, _)| *name != group_by)
+                        .map(|(name, value)| match value.as_str() {
+                            Some(text) => format!("{}={}", name, text),
+                            None => format!("{}={}", name, value),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    Row::new([
+                        right(index.to_string()),
+                        right(format!("{:.6}", area)),
+                        Cell::from(properties),
+                    ])
+                });
+                let widths = [
+                    Constraint::Length(8),
+                    Constraint::Length(16),
+                    Constraint::Fill(1),
+                ];
+                let header = Row::new([
+                    right("位置".to_string()),
+                    right("面積".to_string()),
+                    Cell::from("プロパティ"),
+                ])
+                .style(underlined);
+                let list = Table::new(rows, widths)
+                    .header(header)
+                    .column_spacing(2)
+                    .row_highlight_style(reversed);
+                detail.cursor.render(frame, list, table);
+            }
+        }
+
+        // 状態の行は行全体を反転表示にする
+        frame.render_widget(Line::raw(self.status.as_str()).style(reversed), status);
+        frame.render_widget(Line::raw(HELP), help);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aggregate::Metric,
+        chart::char_width,
+        source::{Input, InputOptions},
+    };
+    use ratatui::{backend::TestBackend, Terminal};
+
+    /// 甲（面積 4 の Feature が 1 つ）、乙（面積 1 と 2 の Feature が 2 つ）、丙（面積 3 の Feature が 1 つ）
+    fn app() -> App {
+        let square = |city: &str, size: f64| {
+            format!(
+                r#"{{"type":"Feature","properties":{{"N03_004":"{}","size":{}}},"geometry":{{"type":"Polygon","coordinates":[[[0,0],[{s},0],[{s},{s}],[0,{s}],[0,0]]]}}}}"#,
+                city,
+                size * size,
+                s = size
+            )
+        };
+        let collection: FeatureCollection = format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            [
+                square("乙", 1.0),
+                square("甲", 2.0),
+                square("乙", 2f64.sqrt()),
+                square("丙", 3f64.sqrt()),
+            ]
+            .join(",")
+        )
+        .parse()
+        .unwrap();
+        let groups = aggregate::aggregate(&collection, "N03_004", false);
+        let options = TuiOptions {
+            input: Input::parse("unused.geojson", InputOptions::default()).unwrap(),
+            group_by: "N03_004".to_string(),
+            metric: Metric::Area,
+            output: "unused.csv".to_string(),
+        };
+        App::new(options, collection, groups)
+    }
+
+    fn keys(app: &App) -> Vec<&str> {
+        app.groups.iter().map(|group| group.key.as_str()).collect()
+    }
+
+    #[test]
+    fn sort_cycles_through_area_count_and_name() {
+        let mut app = app();
+        // 面積の降順から始める（乙は 1 + 2.0000000000000004、丙は 2.9999999999999996）
+        assert_eq!(keys(&app), ["甲", "乙", "丙"]);
+
+        assert!(app.handle(KeyCode::Char('s'), 10));
+        assert_eq!(app.sort, Sort::Count);
+        assert!(app.descending);
+        assert_eq!(keys(&app)[0], "乙");
+
+        app.handle(KeyCode::Char('s'), 10);
+        assert_eq!(app.sort, Sort::Key);
+        // 名前は昇順から始める
+        assert!(!app.descending);
+        assert_eq!(keys(&app), ["丙", "乙", "甲"]);
+        assert_eq!(app.status, "名前の昇順に並べ替えました");
+
+        app.handle(KeyCode::Char('r'), 10);
+        assert_eq!(keys(&app), ["甲", "乙", "丙"]);
+        app.handle(KeyCode::Char('s'), 10);
+        assert_eq!((app.sort, app.descending), (Sort::Area, true));
+    }
+
+    #[test]
+    fn cursor_stays_inside_the_list() {
+        let mut app = app();
+        app.handle(KeyCode::Up, 10);
+        assert_eq!(app.cursor.selected, 0);
+        app.handle(KeyCode::Char('j'), 10);
+        app.handle(KeyCode::Down, 10);
+        app.handle(KeyCode::Down, 10);
+        assert_eq!(app.cursor.selected, 2);
+        app.handle(KeyCode::PageUp, 2);
+        assert_eq!(app.cursor.selected, 0);
+        app.handle(KeyCode::PageDown, 2);
+        assert_eq!(app.cursor.selected, 2);
+        app.handle(KeyCode::Char('g'), 10);
+        assert_eq!(app.cursor.selected, 0);
+        app.handle(KeyCode::Char('G'), 10);
+        assert_eq!(app.cursor.selected, 2);
+        assert!(!app.handle(KeyCode::Char('q'), 10));
+    }
+
+    #[test]
+    fn enter_drills_down_into_the_features_of_the_group() {
+        let mut app = app();
+        app.handle(KeyCode::Char('s'), 10);
+        // Feature の数の降順で先頭の乙に入る
+        app.handle(KeyCode::Enter, 10);
+        let detail = app.detail.as_ref().unwrap();
+        assert_eq!(detail.key, "乙");
+        // 面積の降順（入力の 3 番目が面積 2、1 番目が面積 1）
+        let positions: Vec<_> = detail.features.iter().map(|(i, _)| *i).collect();
+        assert_eq!(positions, [2, 0]);
+        assert_eq!(app.status, "乙 の 2 個の Feature");
+
+        // 詳細の中のカーソルは一覧とは別
+        app.handle(KeyCode::Down, 10);
+        app.handle(KeyCode::Down, 10);
+        assert_eq!(app.detail.as_ref().unwrap().cursor.selected, 1);
+        assert_eq!(app.cursor.selected, 0);
+        // 詳細の中では Enter で入り直さない
+        app.handle(KeyCode::Enter, 10);
+        assert_eq!(app.detail.as_ref().unwrap().cursor.selected, 1);
+
+        app.handle(KeyCode::Esc, 10);
+        assert!(app.detail.is_none());
+        assert_eq!(app.cursor.selected, 0);
+    }
+
+    #[test]
+    fn draws_the_list_and_the_detail() {
+        let mut app = app();
+        let mut terminal = Terminal::new(TestBackend::new(60, 8)).unwrap();
+        let screen = |terminal: &Terminal<TestBackend>| {
+            let buffer = terminal.backend().buffer();
+            // 全角の文字の右半分のセルは読み飛ばす
+            (0..buffer.area.height)
+                .map(|y| {
+                    let mut line = String::new();
+                    let mut x = 0;
+                    while x < buffer.area.width {
+                        let symbol = buffer[(x, y)].symbol();
+                        line.push_str(symbol);
+                        x += symbol.chars().map(char_width).sum::<usize>().max(1) as u16;
+                    }
+                    line
+                })
+                .collect::<Vec<_>>()
+        };
+
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let lines = screen(&terminal);
+        assert!(
+            lines[0].starts_with("layon tui  3 グループ  並び順: 面積↓"),
+            "{:?}",
+            lines
+        );
+        assert!(lines[1].starts_with("N03_004"));
+        assert!(lines[2].starts_with("甲"));
+        assert!(
+            lines[2].trim_end().ends_with("4.000000         1"),
+            "{:?}",
+            lines[2]
+        );
+        assert!(lines[7].starts_with("↑↓/jk 移動"));
+
+        app.handle(KeyCode::Enter, 10);
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let lines = screen(&terminal);
+        assert!(lines[0].starts_with("甲  1 個の Feature"), "{:?}", lines);
+        assert!(lines[2].contains("size=4"), "{:?}", lines[2]);
+    }
+}
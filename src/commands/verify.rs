@@ -0,0 +1,45 @@
+//! `layon verify`: 楕円体上で計算した面積を公式の面積 (全国都道府県市区町村別面積調) と比べる。
+
+use crate::cli::VerifyOptions;
+use crate::{log, sink, verify};
+use std::error::Error;
+
+/// 計算した面積を公式の面積と比べる
+pub fn run(options: VerifyOptions) -> Result<(), Box<dyn Error>> {
+    let official = verify::read_official(
+        &options.official,
+        &options.join,
+        options.official_key.as_deref(),
+        options.official_prefecture.as_deref(),
+        options.official_area.as_deref(),
+    )?;
+    let collection = options.input.read()?;
+    let rows = verify::verify(&collection, &options.group_by, &options.join, &official);
+
+    // 両方にある市町村の相対誤差の絶対値の平均と最大
+    let errors: Vec<(&str, f64)> = rows
+        .iter()
+        .filter_map(|row| Some((row.city.as_str(), row.relative_error()?.abs())))
+        .collect();
+    let missing = rows.iter().filter(|row| row.official.is_none()).count();
+    let extra = rows.iter().filter(|row| row.computed.is_none()).count();
+    log::info!(
+        "{} 市町村を比較しました（公式の表にない: {}、入力にない: {}）。",
+        errors.len(),
+        missing,
+        extra
+    );
+    if let Some((city, max)) = errors.iter().max_by(|a, b| a.1.total_cmp(&b.1)) {
+        let mean = errors.iter().map(|(_, e)| e).sum::<f64>() / errors.len() as f64;
+        log::info!(
+            "相対誤差: 平均 {:.3}%、最大 {:.3}% ({})",
+            mean * 100.0,
+            max * 100.0,
+            city
+        );
+    }
+
+    sink::csv::write_verification(&options.output, &rows)?;
+    log::info!("CSV ファイル ({}) に出力しました。", options.output);
+    Ok(())
+}
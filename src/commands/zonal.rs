@@ -0,0 +1,68 @@
+//! `layon zonal`: ラスターの画素値をポリゴンごとに集計する (平均・合計・ヒストグラム)。
+
+use crate::cli::{self, ZonalOptions};
+use crate::{chart, log, raster, sink, zonal};
+use std::error::Error;
+
+/// ラスターの画素値をポリゴンごとに集計する
+pub fn run(options: ZonalOptions) -> Result<(), Box<dyn Error>> {
+    let raster = raster::Raster::read(&options.raster)?;
+    let collection = options.input.read()?;
+    if let Some(bands) = &options.bands {
+        let rows = zonal::bands(
+            &collection,
+            &options.group_by,
+            &raster,
+            bands,
+            options.metric,
+        );
+        sink::csv::write_bands(&options.output, &rows)?;
+        log::info!("CSV ファイル ({}) に出力しました。", options.output);
+        if options.chart {
+            // すべての集計キーを合わせた帯ごとの面積
+            let mut items: Vec<(String, f64)> = Vec::new();
+            for row in &rows {
+                match items.iter_mut().find(|(band, _)| *band == row.band) {
+                    Some((_, area)) => *area += row.area,
+                    None => items.push((row.band.clone(), row.area)),
+                }
+            }
+            print!("{}", chart::bars(&items, cli::terminal_width()));
+        }
+        return Ok(());
+    }
+    let rows = zonal::zonal(&collection, &options.group_by, &raster, options.bin_width);
+
+    if options.bin_width.is_some() {
+        sink::csv::write_histogram(&options.output, &rows)?;
+    } else {
+        sink::csv::write_zonal(&options.output, &rows)?;
+    }
+    log::info!("CSV ファイル ({}) に出力しました。", options.output);
+    if options.chart {
+        print!("{}", zonal_chart(&rows, options.bin_width.is_some()));
+    }
+    Ok(())
+}
+
+/// zonal の結果の横棒グラフ。ヒストグラムはすべてのポリゴンの画素数を階級ごとに合わせる
+fn zonal_chart(rows: &[zonal::ZonalRow], histogram: bool) -> String {
+    let items: Vec<_> = if histogram {
+        let mut bins: Vec<(f64, usize)> = Vec::new();
+        for &(value, count) in rows.iter().flat_map(|row| &row.histogram) {
+            match bins.iter_mut().find(|(v, _)| *v == value) {
+                Some((_, total)) => *total += count,
+                None => bins.push((value, count)),
+            }
+        }
+        bins.sort_by(|a, b| a.0.total_cmp(&b.0));
+        bins.into_iter()
+            .map(|(value, count)| (value.to_string(), count as f64))
+            .collect()
+    } else {
+        rows.iter()
+            .filter_map(|row| Some((row.city.clone(), row.mean()?)))
+            .collect()
+    };
+    chart::bars(&items, cli::terminal_width())
+}
@@ -0,0 +1,116 @@
+//! 平面上の面積と楕円体上の面積の比較（`--compare-methods`）。
+//! 同じ Feature を投影法（既定: utm-auto）で投影した平面上の面積と楕円体（WGS84）上の面積の両方で集計し、
+//! 集計キーごとの差と相対差を求める。これまで平面上の面積で集計してきた場合に、その誤差の大きさを確かめるために使う。
+
+use crate::{
+    aggregate::{self, Metric},
+    pipeline::Pipeline,
+    projection::Projection,
+};
+use std::{collections::HashMap, error::Error};
+
+/// 集計キーごとの比較結果
+pub struct MethodRow {
+    pub key: String,
+    /// 投影した平面上の面積 (km²)
+    pub planar: f64,
+    /// 楕円体上の面積 (km²)
+    pub geodesic: f64,
+}
+
+impl MethodRow {
+    /// 差 (平面上の面積 - 楕円体上の面積, km²)
+    pub fn difference(&self) -> f64 {
+        self.planar - self.geodesic
+    }
+
+    /// 相対差（楕円体上の面積に対する割合。楕円体上の面積が 0 なら None）
+    pub fn relative_difference(&self) -> Option<f64> {
+        (self.geodesic != 0.0).then(|| self.difference() / self.geodesic)
+    }
+}
+
+/// `pipeline` の Feature を `group_by` ごとに 2 つの方法で集計し、楕円体上の面積の降順で返す
+pub fn compare(
+    pipeline: Pipeline,
+    group_by: &str,
+    projection: Projection,
+) -> Result<Vec<MethodRow>, Box<dyn Error>> {
+    let sums = pipeline.map_reduce(
+        |view| {
+            let mut sums = HashMap::new();
+            if let Some(key) = view.feature.property(group_by).and_then(|v| v.as_str()) {
+                sums.insert(
+                    key.to_string(),
+                    (
+                        aggregate::feature_area(view.feature, Metric::Projected(projection)),
+                        aggregate::feature_area(view.feature, Metric::GeodesicArea),
+                    ),
+                );
+            }
+            sums
+        },
+        HashMap::new,
+        |mut a: HashMap<String, (f64, f64)>, b| {
+            for (key, (planar, geodesic)) in b {
+                let sum = a.entry(key).or_insert((0.0, 0.0));
+                sum.0 += planar;
+                sum.1 += geodesic;
+            }
+            a
+        },
+    )?;
+    let mut rows: Vec<MethodRow> = sums
+        .into_iter()
+        .map(|(key, (planar, geodesic))| MethodRow {
+            key,
+            planar,
+            geodesic,
+        })
+        .collect();
+    rows.sort_by(|a, b| {
+        b.geodesic
+            .total_cmp(&a.geodesic)
+            .then_with(|| a.key.cmp(&b.key))
+    });
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    #[test]
+    fn compare_methods_reports_planar_error_against_geodesic_area() {
+        let rows = compare(Pipeline::read(FIXTURE), "N03_004", Projection::UtmAuto).unwrap();
+        let expected = |metric: Metric| {
+            Pipeline::read(FIXTURE)
+                .metric(metric)
+                .run()
+                .unwrap()
+                .rows
+                .into_iter()
+                .map(|row| (row.key, row.area))
+                .collect::<std::collections::HashMap<_, _>>()
+        };
+        let (planar, geodesic) = (
+            expected(Metric::Projected(Projection::UtmAuto)),
+            expected(Metric::GeodesicArea),
+        );
+        assert_eq!(rows.len(), geodesic.len());
+        assert!(rows
+            .windows(2)
+            .all(|pair| pair[0].geodesic >= pair[1].geodesic));
+        for row in &rows {
+            assert!((row.planar - planar[&row.key]).abs() < 1e-9);
+            assert!((row.geodesic - geodesic[&row.key]).abs() < 1e-9);
+            // UTM の面積の誤差は 0.2% 以内
+            assert!(row.relative_difference().unwrap().abs() < 0.002);
+        }
+    }
+}
@@ -0,0 +1,466 @@
+//! シェルの補完スクリプトの生成（`layon completions`）。
+//! サブコマンドとオプションは `cli::USAGE` から読み取るため、ヘルプに書いたものがそのまま補完される。
+
+use crate::cli::{self, CommandSpec, OptionSpec, Shell};
+use std::fmt::Write;
+
+/// `completions` サブコマンドの引数の候補
+const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+/// 補完スクリプトを生成する
+pub fn generate(shell: Shell) -> String {
+    let commands = cli::spec();
+    match shell {
+        Shell::Bash => bash(&commands),
+        Shell::Zsh => zsh(&commands),
+        Shell::Fish => fish(&commands),
+    }
+}
+
+/// 名前のあるオプション
+fn named(command: &CommandSpec) -> impl Iterator<Item = &OptionSpec> {
+    command
+        .options
+        .iter()
+        .filter(|option| option.long.is_some())
+}
+
+/// オプションの名前（短い名前と長い名前）
+fn names(option: &OptionSpec) -> impl Iterator<Item = &'static str> {
+    option.short.into_iter().chain(option.long)
+}
+
+/// 補完の説明用に、説明から ` (...)` の補足を除く
+fn summary(description: &str) -> String {
+    let mut text = String::new();
+    let mut rest = description;
+    while let Some(start) = rest.find(" (") {
+        let Some(end) = rest[start..].find(')') else {
+            break;
+        };
+        text.push_str(&rest[..start]);
+        rest = &rest[start + end + 1..];
+        // 括弧の後に続く語との間の空白も除く（`面積 (…) と比べる` → `面積と比べる`）
+        rest = rest.strip_prefix(' ').unwrap_or(rest);
+    }
+    text.push_str(rest);
+    text.trim().to_string()
+}
+
+fn subcommands(commands: &[CommandSpec]) -> impl Iterator<Item = &CommandSpec> {
+    commands.iter().filter(|command| !command.name.is_empty())
+}
+
+fn bash(commands: &[CommandSpec]) -> String {
+    // 値の補完はオプションの名前だけで決める（同じ名前のオプションは同じ種類の値をとる）
+    let mut choices = Vec::new();
+    let mut paths = Vec::new();
+    let mut values = Vec::new();
+    for option in commands.iter().flat_map(named) {
+        // 値を省略できるものは `--chart=5` の形でだけ値をとるため、次の語を補完しない
+        if option.value.is_none() || option.optional {
+            continue;
+        }
+        let group = if !option.choices.is_empty() {
+            choices.push(option);
+            continue;
+        } else if option.takes_path() {
+            &mut paths
+        } else {
+            &mut values
+        };
+        for name in names(option) {
+            if !group.contains(&name) {
+                group.push(name);
+            }
+        }
+    }
+
+    let mut s = String::new();
+    writeln!(s, "# layon の bash 補完 (layon completions bash で生成)").unwrap();
+    writeln!(s, "_layon() {{").unwrap();
+    writeln!(s, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"").unwrap();
+    writeln!(s, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"").unwrap();
+    writeln!(s, "    case \"$prev\" in").unwrap();
+    for option in choices {
+        writeln!(
+            s,
+            "        {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;",
+            names(option).collect::<Vec<_>>().join("|"),
+            option.choices.join(" ")
+        )
+        .unwrap();
+    }
+    if !paths.is_empty() {
+        writeln!(
+            s,
+            "        {}) COMPREPLY=($(compgen -f -- \"$cur\")); return ;;",
+            paths.join("|")
+        )
+        .unwrap();
+    }
+    if !values.is_empty() {
+        writeln!(s, "        {}) return ;;", values.join("|")).unwrap();
+    }
+    writeln!(s, "    esac").unwrap();
+    writeln!(s, "    local opts").unwrap();
+    writeln!(s, "    case \"${{COMP_WORDS[1]}}\" in").unwrap();
+    for command in subcommands(commands) {
+        let words = if command.name == "completions" {
+            SHELLS.join(" ")
+        } else {
+            named(command).flat_map(names).collect::<Vec<_>>().join(" ")
+        };
+        writeln!(s, "        {}) opts=\"{}\" ;;", command.name, words).unwrap();
+    }
+    writeln!(s, "        *)").unwrap();
+    writeln!(
+        s,
+        "            opts=\"{}\"",
+        named(&commands[0])
+            .flat_map(names)
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+    .unwrap();
+    writeln!(
+        s,
+        "            [ \"$COMP_CWORD\" -eq 1 ] && opts=\"$opts {}\"",
+        subcommands(commands)
+            .map(|command| command.name)
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+    .unwrap();
+    writeln!(s, "            ;;").unwrap();
+    writeln!(s, "    esac").unwrap();
+    writeln!(s, "    COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\"))").unwrap();
+    writeln!(s, "}}").unwrap();
+    writeln!(s, "complete -o default -F _layon layon").unwrap();
+    s
+}
+
+/// zsh の単一引用符の中に入れる文字列（`[]` は _arguments の説明の区切りになるためエスケープする）
+fn zsh_quote(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+/// _arguments に渡すオプションの定義
+fn zsh_option(option: &OptionSpec) -> String {
+    let names: Vec<_> = names(option).collect();
+    let mut spec = if names.len() > 1 {
+        format!("'({})'{{{}}}'", names.join(" "), names.join(","))
+    } else if option.optional {
+        format!("'{}=-", names[0])
+    } else {
+        format!("'{}", names[0])
+    };
+    write!(spec, "[{}]", zsh_quote(&summary(option.description))).unwrap();
+    if let Some(value) = option.value {
+        let action = if !option.choices.is_empty() {
+            format!("({})", option.choices.join(" "))
+        } else if option.takes_path() {
+            "_files".to_string()
+        } else {
+            " ".to_string()
+        };
+        let separator = if option.optional { "::" } else { ":" };
+        write!(spec, "{}{}:{}", separator, value, action).unwrap();
+    }
+    spec.push('\'');
+    spec
+}
+
+fn zsh_arguments(s: &mut String, command: &CommandSpec, indent: &str) {
+    let mut specs: Vec<_> = named(command).map(zsh_option).collect();
+    // 位置引数（bbox の SOURCE など）は入力元としてファイル名を補完する
+    if command.options.iter().any(|option| option.long.is_none()) {
+        specs.push("'*:SOURCE:_files'".to_string());
+    }
+    write!(s, "{}_arguments -s", indent).unwrap();
+    for spec in specs {
+        write!(s, " \\\n{}    {}", indent, spec).unwrap();
+    }
+    writeln!(s).unwrap();
+}
+
+fn zsh(commands: &[CommandSpec]) -> String {
+    let mut s = String::new();
+    writeln!(s, "#compdef layon").unwrap();
+    writeln!(s, "# layon の zsh 補完 (layon completions zsh で生成)").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "_layon() {{").unwrap();
+    writeln!(s, "    local -a subcommands").unwrap();
+    writeln!(s, "    subcommands=(").unwrap();
+    for command in subcommands(commands) {
+        writeln!(
+            s,
+            "        '{}:{}'",
+            command.name,
+            summary(command.description).replace('\'', "'\\''")
+        )
+        .unwrap();
+    }
+    writeln!(s, "    )").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "    case $words[2] in").unwrap();
+    for command in subcommands(commands) {
+        writeln!(s, "        {})", command.name).unwrap();
+        writeln!(s, "            shift words").unwrap();
+        writeln!(s, "            (( CURRENT-- ))").unwrap();
+        if command.name == "completions" {
+            writeln!(s, "            _arguments '1:shell:({})'", SHELLS.join(" ")).unwrap();
+        } else if named(command).next().is_none() {
+            writeln!(s, "            _message 'オプションはありません'").unwrap();
+        } else {
+            zsh_arguments(&mut s, command, "            ");
+        }
+        writeln!(s, "            ;;").unwrap();
+    }
+    writeln!(s, "        *)").unwrap();
+    writeln!(
+        s,
+        "            if (( CURRENT == 2 )) && [[ $words[CURRENT] != -* ]]; then"
+    )
+    .unwrap();
+    writeln!(s, "                _describe 'サブコマンド' subcommands").unwrap();
+    writeln!(s, "            else").unwrap();
+    zsh_arguments(&mut s, &commands[0], "                ");
+    writeln!(s, "            fi").unwrap();
+    writeln!(s, "            ;;").unwrap();
+    writeln!(s, "    esac").unwrap();
+    writeln!(s, "}}").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "_layon \"$@\"").unwrap();
+    s
+}
+
+/// fish の単一引用符の中に入れる文字列
+fn fish_quote(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn fish_option(s: &mut String, condition: &str, option: &OptionSpec) {
+    write!(s, "complete -c layon -n '{}'", condition).unwrap();
+    if let Some(short) = option.short {
+        write!(s, " -s {}", short.trim_start_matches('-')).unwrap();
+    }
+    if let Some(long) = option.long {
+        write!(s, " -l {}", long.trim_start_matches("--")).unwrap();
+    }
+    if option.value.is_some() && !option.optional {
+        if !option.choices.is_empty() {
+            write!(s, " -x -a '{}'", option.choices.join(" ")).unwrap();
+        } else if option.takes_path() {
+            write!(s, " -r -F").unwrap();
+        } else {
+            write!(s, " -x").unwrap();
+        }
+    }
+    writeln!(s, " -d '{}'", fish_quote(&summary(option.description))).unwrap();
+}
+
+fn fish(commands: &[CommandSpec]) -> String {
+    let names: Vec<_> = subcommands(commands).map(|command| command.name).collect();
+    let main_condition = format!("not __fish_seen_subcommand_from {}", names.join(" "));
+
+    let mut s = String::new();
+    writeln!(s, "# layon の fish 補完 (layon completions fish で生成)").unwrap();
+    writeln!(s, "complete -c layon -f").unwrap();
+    for command in subcommands(commands) {
+        writeln!(
+            s,
+            "complete -c layon -n __fish_use_subcommand -a {} -d '{}'",
+            command.name,
+            fish_quote(&summary(command.description))
+        )
+        .unwrap();
+    }
+    for option in named(&commands[0]) {
+        fish_option(&mut s, &main_condition, option);
+    }
+    for command in subcommands(commands) {
+        let condition = format!("__fish_seen_subcommand_from {}", command.name);
+        if command.name == "completions" {
+            writeln!(
+                s,
+                "complete -c layon -n '{}' -a '{}'",
+                condition,
+                SHELLS.join(" ")
+            )
+            .unwrap();
+        }
+        if command.options.iter().any(|option| option.long.is_none()) {
+            // 位置引数の入力元はファイル名を補完する
+            writeln!(s, "complete -c layon -n '{}' -F", condition).unwrap();
+        }
+        for option in named(command) {
+            fish_option(&mut s, &condition, option);
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::cli::USAGE;
+
+    /// `cli::spec` を通さずに `USAGE` から読んだ、節ごとのサブコマンド（サブコマンドなしは空）とオプションの名前
+    pub(crate) fn usage_sections() -> Vec<(Vec<&'static str>, Vec<&'static str>)> {
+        let mut sections: Vec<(Vec<&'static str>, Vec<&'static str>)> = Vec::new();
+        let mut in_options = false;
+        for line in USAGE.lines() {
+            let trimmed = line.trim_start();
+            if !line.is_empty() && trimmed.len() == line.len() {
+                in_options = true;
+                if line == "オプション:" {
+                    sections.push((vec![""], Vec::new()));
+                } else if let Some(names) = line.strip_suffix(" のオプション:") {
+                    sections.push((names.split(" / ").collect(), Vec::new()));
+                } else {
+                    in_options = false;
+                }
+                continue;
+            }
+            if !in_options || !trimmed.starts_with('-') || line.len() - trimmed.len() >= 25 {
+                continue;
+            }
+            let names = trimmed.split("  ").next().unwrap();
+            let options = &mut sections.last_mut().unwrap().1;
+            for token in names.split_whitespace() {
+                let token = token.trim_end_matches(',');
+                let name = token.split("[=").next().unwrap();
+                if name.starts_with('-') {
+                    options.push(name);
+                }
+            }
+        }
+        sections
+    }
+
+    /// `USAGE` のサブコマンドの一覧の名前
+    pub(crate) fn usage_subcommands() -> Vec<&'static str> {
+        USAGE
+            .lines()
+            .skip_while(|line| *line != "サブコマンド:")
+            .skip(1)
+            .take_while(|line| !line.is_empty())
+            .filter(|line| line.len() - line.trim_start().len() == 2)
+            .map(|line| line.split_whitespace().next().unwrap())
+            .filter(|name| *name != "(省略)")
+            .collect()
+    }
+
+    #[test]
+    fn usage_lists_subcommands_and_options() {
+        let subcommands = usage_subcommands();
+        assert!(subcommands.contains(&"overlay") && subcommands.contains(&"man"));
+        let sections = usage_sections();
+        let zonal = sections
+            .iter()
+            .find(|(names, _)| names == &["zonal"])
+            .unwrap();
+        assert!(zonal.1.contains(&"--metric") && zonal.1.contains(&"--raster"));
+    }
+
+    #[test]
+    fn bash_completes_every_subcommand_and_option() {
+        let script = generate(Shell::Bash);
+        for (names, options) in usage_sections() {
+            for name in names {
+                let prefix = if name.is_empty() {
+                    "            opts=\"".to_string()
+                } else {
+                    format!("        {}) opts=\"", name)
+                };
+                let line = script
+                    .lines()
+                    .find(|line| line.starts_with(&prefix))
+                    .unwrap_or_else(|| panic!("{:?} の行がありません", name));
+                let words: Vec<_> = line[prefix.len()..]
+                    .split('"')
+                    .next()
+                    .unwrap()
+                    .split(' ')
+                    .collect();
+                for option in &options {
+                    assert!(words.contains(option), "{:?}: {}", name, option);
+                }
+            }
+        }
+        for name in usage_subcommands() {
+            assert!(script.contains(&format!("        {}) ", name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn zsh_completes_every_subcommand_and_option() {
+        let script = generate(Shell::Zsh);
+        for name in usage_subcommands() {
+            assert!(script.contains(&format!("        '{}:", name)), "{}", name);
+        }
+        for (names, options) in usage_sections() {
+            for name in names {
+                // サブコマンドの _arguments は `name)` から `;;` まで
+                let body = if name.is_empty() {
+                    script.split("\n        *)\n").nth(1).unwrap()
+                } else {
+                    let start = script
+                        .find(&format!("\n        {})\n", name))
+                        .unwrap_or_else(|| panic!("{} の節がありません", name));
+                    let rest = &script[start..];
+                    &rest[..rest.find(";;").unwrap()]
+                };
+                for option in &options {
+                    let quoted = [format!("'{}[", option), format!("'{}=-[", option)];
+                    let grouped = format!("{},", option);
+                    let last = format!(",{}}}", option);
+                    assert!(
+                        quoted.iter().any(|q| body.contains(q.as_str()))
+                            || body.contains(&grouped)
+                            || body.contains(&last),
+                        "{:?}: {}",
+                        name,
+                        option
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fish_completes_every_subcommand_and_option() {
+        let script = generate(Shell::Fish);
+        for name in usage_subcommands() {
+            assert!(script.contains(&format!(" -a {} ", name)), "{}", name);
+        }
+        for (names, options) in usage_sections() {
+            for name in names {
+                let condition = if name.is_empty() {
+                    "-n 'not __fish_seen_subcommand_from ".to_string()
+                } else {
+                    format!("-n '__fish_seen_subcommand_from {}'", name)
+                };
+                let lines: Vec<_> = script
+                    .lines()
+                    .filter(|line| line.contains(&condition))
+                    .collect();
+                for option in &options {
+                    let flag = match option.strip_prefix("--") {
+                        Some(long) => format!(" -l {} ", long),
+                        None => format!(" -s {} ", &option[1..]),
+                    };
+                    assert!(
+                        lines.iter().any(|line| line.contains(&flag)),
+                        "{:?}: {}",
+                        name,
+                        option
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! 重複した Feature の検出と除去。
+//! 複数の都道府県のファイルを結合すると、元のタイルの境目に同じ Feature が重複して入ることがある。
+//! ジオメトリと属性からハッシュを作り、同じハッシュの Feature は正規化したジオメトリと属性を比べて、
+//! 一致したものは最初の 1 つだけを残す（ハッシュが偶然一致しただけの Feature は残す）。
+
+use crate::ids;
+use geojson::{Feature, FeatureCollection, Value};
+use rayon::prelude::*;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+/// 取り除いた Feature
+pub struct Duplicate {
+    /// 取り除いた Feature の位置（0 始まり）
+    pub index: usize,
+    /// 取り除いた Feature の ID
+    pub id: String,
+    /// 残した同じ内容の Feature の位置
+    pub original: usize,
+    /// 残した Feature の ID
+    pub original_id: String,
+    /// 集計キーのプロパティの値
+    pub name: Option<String>,
+}
+
+/// 量子化した座標
+type Point = (i64, i64);
+
+/// 重複した Feature を取り除き、残りの FeatureCollection と取り除いた Feature の一覧を返す。
+/// `tolerance` を指定すると座標をその幅の格子に丸めてから比べる（近い重複も取り除く）。
+/// 指定しなければ座標が完全に一致するものだけを重複とみなす。
+/// リングの始点や向き、ポリゴンの順序の違いは無視し、属性はすべてのプロパティを比べる。
+pub fn dedup(
+    collection: FeatureCollection,
+    tolerance: Option<f64>,
+    group_by: &str,
+) -> (FeatureCollection, Vec<Duplicate>) {
+    let hashes: Vec<u64> = collection
+        .features
+        .par_iter()
+        .map(|feature| fingerprint(feature, tolerance))
+        .collect();
+    dedup_hashed(collection, &hashes, tolerance, group_by)
+}
+
+/// ハッシュを計算済みの Feature から重複を取り除く
+fn dedup_hashed(
+    collection: FeatureCollection,
+    hashes: &[u64],
+    tolerance: Option<f64>,
+    group_by: &str,
+) -> (FeatureCollection, Vec<Duplicate>) {
+    // ハッシュ -> 残した Feature（features の中の位置, 元の位置, ID）
+    let mut seen = HashMap::<u64, Vec<(usize, usize, String)>>::new();
+    let mut duplicates = Vec::new();
+    let mut features: Vec<Feature> = Vec::with_capacity(collection.features.len());
+    for (index, (feature, &hash)) in collection.features.into_iter().zip(hashes).enumerate() {
+        let candidates = seen.entry(hash).or_default();
+        // ハッシュが一致しても内容が同じとは限らないので比べる
+        let original = if candidates.is_empty() {
+            None
+        } else {
+            let key = content(&feature, tolerance);
+            candidates
+                .iter()
+                .find(|(kept, _, _)| content(&features[*kept], tolerance) == key)
+        };
+        match original {
+            Some((_, original, original_id)) => duplicates.push(Duplicate {
+                index,
+                id: ids::text_or_position(&feature, index),
+                original: *original,
+                original_id: original_id.clone(),
+                name: feature
+                    .property(group_by)
+                    .and_then(|name| name.as_str())
+                    .map(str::to_string),
+            }),
+            None => {
+                candidates.push((
+                    features.len(),
+                    index,
+                    ids::text_or_position(&feature, index),
+                ));
+                features.push(feature);
+            }
+        }
+    }
+
+    (
+        FeatureCollection {
+            features,
+            ..collection
+        },
+        duplicates,
+    )
+}
+
+/// 比較に使う Feature の内容（正規化したジオメトリと、キーの順に並べたプロパティ）
+#[derive(Hash, PartialEq, Eq)]
+struct Content {
+    shape: Option<Shape>,
+    properties: Vec<(String, String)>,
+}
+
+fn content(feature: &Feature, tolerance: Option<f64>) -> Content {
+    let mut properties: Vec<(String, String)> = feature
+        .properties
+        .iter()
+        .flatten()
+        .map(|(key, value)| (key.clone(), value.to_string()))
+        .collect();
+    properties.sort();
+    Content {
+        shape: feature
+            .geometry
+            .as_ref()
+            .map(|geometry| normalize(&geometry.value, tolerance)),
+        properties,
+    }
+}
+
+/// Feature のジオメトリと属性のハッシュ
+fn fingerprint(feature: &Feature, tolerance: Option<f64>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content(feature, tolerance).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 比較用に正規化したジオメトリ（種類ごとの座標の列）
+#[derive(Hash, PartialEq, Eq)]
+enum Shape {
+    Points(Vec<Point>),
+    Lines(Vec<Vec<Point>>),
+    Polygons(Vec<Vec<Vec<Point>>>),
+    Collection(Vec<Shape>),
+}
+
+/// Polygon と 1 つだけの MultiPolygon のように、表現が違っても同じ形なら同じ値にする
+fn normalize(value: &Value, tolerance: Option<f64>) -> Shape {
+    let point = |position: &Vec<f64>| quantize(position, tolerance);
+    let line = |positions: &[Vec<f64>]| {
+        let mut line: Vec<Point> = positions.iter().map(point).collect();
+        // 線は向きを揃える（端点の小さい方から）
+        if line.last() < line.first() {
+            line.reverse();
+        }
+        line
+    };
+    let polygon = |rings: &[Vec<Vec<f64>>]| {
+        let mut rings: Vec<Vec<Point>> = rings
+            .iter()
+            .map(|ring| normalize_ring(ring.iter().map(point).collect()))
+            .collect();
+        if rings.len() > 1 {
+            rings[1..].sort();
+        }
+        rings
+    };
+    match value {
+        Value::Point(position) => Shape::Points(vec![point(position)]),
+        Value::MultiPoint(positions) => {
+            Shape::Points(sorted(positions.iter().map(point).collect()))
+        }
+        Value::LineString(positions) => Shape::Lines(vec![line(positions)]),
+        Value::MultiLineString(lines) => Shape::Lines(sorted(
+            lines.iter().map(|positions| line(positions)).collect(),
+        )),
+        Value::Polygon(rings) => Shape::Polygons(vec![polygon(rings)]),
+        Value::MultiPolygon(polygons) => Shape::Polygons(sorted(
+            polygons.iter().map(|rings| polygon(rings)).collect(),
+        )),
+        Value::GeometryCollection(geometries) => Shape::Collection(
+            geometries
+                .iter()
+                .map(|geometry| normalize(&geometry.value, tolerance))
+                .collect(),
+        ),
+    }
+}
+
+/// 順序の違いを無視するために並べ替える
+fn sorted<T: Ord>(mut items: Vec<T>) -> Vec<T> {
+    items.sort();
+    items
+}
+
+/// 閉じたリングを、最小の頂点から始まり、隣の頂点の小さい方へ進む向きに揃える
+fn normalize_ring(mut ring: Vec<Point>) -> Vec<Point> {
+    if ring.len() > 1 && ring.first() == ring.last() {
+        ring.pop();
+    }
+    // 丸めで重なった連続する頂点はまとめる
+    ring.dedup();
+    let Some(start) = (0..ring.len()).min_by_key(|&i| ring[i]) else {
+        return ring;
+    };
+    ring.rotate_left(start);
+    if ring.len() > 2 && ring[ring.len() - 1] < ring[1] {
+        ring[1..].reverse();
+    }
+    ring
+}
+
+/// 座標を整数の組にする（格子の幅がなければビット表現をそのまま使う）
+fn quantize(position: &[f64], tolerance: Option<f64>) -> Point {
+    let (x, y) = (position[0], position[1]);
+    match tolerance {
+        Some(t) if t > 0.0 => ((x / t).round() as i64, (y / t).round() as i64),
+        // -0.0 と 0.0 は同じ座標として扱う
+        _ => ((x + 0.0).to_bits() as i64, (y + 0.0).to_bits() as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geojson::{Geometry, JsonObject};
+
+    fn feature(city: &str, x: f64) -> Feature {
+        let mut properties = JsonObject::new();
+        properties.insert("N03_004".to_string(), city.into());
+        Feature {
+            geometry: Some(Geometry::new(Value::Point(vec![x, 35.0]))),
+            properties: Some(properties),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn features_with_colliding_hashes_are_compared_before_dropping() {
+        let collection = FeatureCollection {
+            bbox: None,
+            features: vec![
+                feature("川越市", 139.0),
+                feature("所沢市", 139.5),
+                feature("川越市", 139.0),
+            ],
+            foreign_members: None,
+        };
+        // すべてのハッシュが衝突した場合でも、内容の違う Feature は残す
+        let (kept, duplicates) = dedup_hashed(collection, &[7, 7, 7], None, "N03_004");
+        assert_eq!(kept.features.len(), 2);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!((duplicates[0].index, duplicates[0].original), (2, 0));
+        assert_eq!(duplicates[0].name.as_deref(), Some("川越市"));
+    }
+
+    #[test]
+    fn rings_are_compared_regardless_of_start_and_direction() {
+        let square = |ring: &[(f64, f64)]| {
+            Value::Polygon(vec![ring.iter().map(|&(x, y)| vec![x, y]).collect()])
+        };
+        let a = square(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)]);
+        let b = square(&[(1.0, 1.0), (1.0, 0.0), (0.0, 0.0), (0.0, 1.0), (1.0, 1.0)]);
+        // 0.001 の格子に丸めると同じ頂点になる
+        let c = square(&[
+            (0.0, 0.0),
+            (1.0004, 0.0),
+            (1.0, 1.0),
+            (0.0, 1.0),
+            (0.0, 0.0),
+        ]);
+        assert!(normalize(&a, None) == normalize(&b, None));
+        assert!(normalize(&a, None) != normalize(&c, None));
+        assert!(normalize(&a, Some(0.001)) == normalize(&c, Some(0.001)));
+        let multi = Value::MultiPolygon(vec![match &b {
+            Value::Polygon(rings) => rings.clone(),
+            _ => unreachable!(),
+        }]);
+        assert!(normalize(&a, None) == normalize(&multi, None));
+    }
+}
@@ -0,0 +1,437 @@
+//! 集計した後に計算する列（`--derive 'density = pop_sum / area'`）と、列による並べ替え（`--sort`, `--top`）。
+//!
+//! 式に使えるもの:
+//! - 列: `area`, `count`、`--agg` の列（`pop_sum` など）、前に定義した列
+//! - 数値、四則演算 `+ - * /`、単項の `-`、括弧
+//!
+//! 使った列の値がない場合や、0 で割った場合は値なし（CSV では空欄、JSON では null）になる。
+
+use crate::aggregate::GroupResult;
+use std::cmp::Ordering;
+
+/// 集計した後に計算する列
+#[derive(Clone, Debug, PartialEq)]
+pub struct Derived {
+    pub name: String,
+    pub(crate) expression: Expression,
+}
+
+/// 解析済みの式
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expression {
+    Number(f64),
+    Column(String),
+    Negate(Box<Expression>),
+    Binary(Box<Expression>, Operator, Box<Expression>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl Derived {
+    /// `名前 = 式` を解析する
+    pub fn parse(text: &str) -> Result<Derived, String> {
+        let (name, expression) = text
+            .split_once('=')
+            .ok_or_else(|| format!("--derive の指定が不正です (名前 = 式): {}", text))?;
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(is_name_char) {
+            return Err(format!("--derive の列の名前が不正です: {}", name));
+        }
+        Ok(Derived {
+            name: name.to_string(),
+            expression: Expression::parse(expression)?,
+        })
+    }
+}
+
+impl Expression {
+    /// 式を解析する
+    pub fn parse(text: &str) -> Result<Expression, String> {
+        let mut parser = Parser {
+            chars: text.chars().collect(),
+            position: 0,
+        };
+        let expression = parser.sum()?;
+        parser.skip_whitespace();
+        if parser.position < parser.chars.len() {
+            let rest: String = parser.chars[parser.position..].iter().collect();
+            return Err(format!("式の末尾に余分な文字があります: {}", rest));
+        }
+        Ok(expression)
+    }
+
+    /// 式に使った列の名前（現れる順）
+    pub fn columns(&self) -> Vec<&str> {
+        match self {
+            Expression::Number(_) => Vec::new(),
+            Expression::Column(name) => vec![name.as_str()],
+            Expression::Negate(a) => a.columns(),
+            Expression::Binary(a, _, b) => {
+                let mut columns = a.columns();
+                columns.extend(b.columns());
+                columns
+            }
+        }
+    }
+
+    /// 集計結果の行で式の値を求める（値のない列を使ったか、結果が有限でなければ None）
+    pub fn evaluate(&self, row: &GroupResult) -> Option<f64> {
+        let value = match self {
+            Expression::Number(number) => *number,
+            Expression::Column(name) => column(row, name)?,
+            Expression::Negate(a) => -a.evaluate(row)?,
+            Expression::Binary(a, operator, b) => {
+                let (a, b) = (a.evaluate(row)?, b.evaluate(row)?);
+                match operator {
+                    Operator::Add => a + b,
+                    Operator::Subtract => a - b,
+                    Operator::Multiply => a * b,
+                    Operator::Divide => a / b,
+                }
+            }
+        };
+        value.is_finite().then_some(value)
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// 集計結果の行の列の値（`area`, `count`, `values` の列）
+pub fn column(row: &GroupResult, name: &str) -> Option<f64> {
+    match name {
+        "area" => Some(row.area),
+        "count" => Some(row.count as f64),
+        _ => row
+            .values
+            .iter()
+            .find(|(column, _)| column == name)
+            .and_then(|(_, value)| *value),
+    }
+}
+
+/// 式の列が `columns`（`area` と `count` のほかに使える列）のどれかか確かめる。
+/// 定義した列は、後に定義する列の式から使える
+pub fn check(derived: &[Derived], columns: &[String]) -> Result<(), String> {
+    let mut known: Vec<&str> = vec!["area", "count"];
+    known.extend(columns.iter().map(String::as_str));
+    for definition in derived {
+        if known.contains(&definition.name.as_str()) {
+            return Err(format!(
+                "--derive の列の名前が重複しています: {}",
+                definition.name
+            ));
+        }
+        if let Some(unknown) = definition
+            .expression
+            .columns()
+            .into_iter()
+            .find(|name| !known.contains(name))
+        {
+            return Err(format!(
+                "--derive の式に不明な列があります: {} (使える列: {})",
+                unknown,
+                known.join(", ")
+            ));
+        }
+        known.push(&definition.name);
+    }
+    Ok(())
+}
+
+/// 行ごとに式の値を求め、`GroupResult::values` の後ろに加える
+pub fn apply(rows: &mut [GroupResult], derived: &[Derived]) {
+    for row in rows {
+        for definition in derived {
+            let value = definition.expression.evaluate(row);
+            row.values.push((definition.name.clone(), value));
+        }
+    }
+}
+
+/// 行を並べる列と向き
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortKey {
+    pub column: String,
+    /// 昇順にするか（既定は降順）
+    pub ascending: bool,
+}
+
+impl SortKey {
+    /// `列[:asc|:desc]` を解析する
+    pub fn parse(text: &str) -> Result<SortKey, String> {
+        let (column, ascending) = match text.rsplit_once(':') {
+            Some((column, "asc")) => (column, true),
+            Some((column, "desc")) => (column, false),
+            Some(_) => return Err(format!("--sort の向きが不正です: {} (asc か desc)", text)),
+            None => (text, false),
+        };
+        let column = column.trim();
+        if column.is_empty() {
+            return Err("--sort の列を指定してください".to_string());
+        }
+        Ok(SortKey {
+            column: column.to_string(),
+            ascending,
+        })
+    }
+
+    /// 行を並べ替える（値のない行は向きによらず最後、同じ値なら集計キーの順）
+    pub fn sort(&self, rows: &mut [GroupResult]) {
+        rows.sort_by(|a, b| {
+            let ordering = match (column(a, &self.column), column(b, &self.column)) {
+                (Some(x), Some(y)) if self.ascending => x.total_cmp(&y),
+                (Some(x), Some(y)) => y.total_cmp(&x),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+            ordering.then_with(|| a.key.cmp(&b.key))
+        });
+    }
+}
+
+/// 式の字句を読みながら組み立てる
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .get(self.position)
+            .is_some_and(|c| c.is_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    /// 空白を飛ばした次の文字
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.position).copied()
+    }
+
+    fn sum(&mut self) -> Result<Expression, String> {
+        let mut expression = self.product()?;
+        while let Some(operator) = match self.peek() {
+            Some('+') => Some(Operator::Add),
+            Some('-') => Some(Operator::Subtract),
+            _ => None,
+        } {
+            self.position += 1;
+            expression =
+                Expression::Binary(Box::new(expression), operator, Box::new(self.product()?));
+        }
+        Ok(expression)
+    }
+
+    fn product(&mut self) -> Result<Expression, String> {
+        let mut expression = self.unary()?;
+        while let Some(operator) = match self.peek() {
+            Some('*') => Some(Operator::Multiply),
+            Some('/') => Some(Operator::Divide),
+            _ => None,
+        } {
+            self.position += 1;
+            expression =
+                Expression::Binary(Box::new(expression), operator, Box::new(self.unary()?));
+        }
+        Ok(expression)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        match self.peek() {
+            Some('-') => {
+                self.position += 1;
+                Ok(Expression::Negate(Box::new(self.unary()?)))
+            }
+            Some('(') => {
+                self.position += 1;
+                let expression = self.sum()?;
+                if self.peek() != Some(')') {
+                    return Err("式の括弧が閉じていません".to_string());
+                }
+                self.position += 1;
+                Ok(expression)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.position;
+                while self
+                    .chars
+                    .get(self.position)
+                    .is_some_and(|&c| c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E')
+                {
+                    self.position += 1;
+                }
+                let text: String = self.chars[start..self.position].iter().collect();
+                text.parse()
+                    .map(Expression::Number)
+                    .map_err(|_| format!("式の数値が不正です: {}", text))
+            }
+            Some(c) if is_name_char(c) => {
+                let start = self.position;
+                while self
+                    .chars
+                    .get(self.position)
+                    .is_some_and(|&c| is_name_char(c))
+                {
+                    self.position += 1;
+                }
+                Ok(Expression::Column(
+                    self.chars[start..self.position].iter().collect(),
+                ))
+            }
+            Some(c) => Err(format!("式が不正です: {}", c)),
+            None => Err("式が途中で終わっています".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Pipeline;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    fn row(key: &str, area: f64, pop: Option<f64>) -> GroupResult {
+        GroupResult {
+            key: key.to_string(),
+            layer: None,
+            area,
+            count: 2,
+            geometry: None,
+            ids: Vec::new(),
+            values: vec![("pop_sum".to_string(), pop)],
+            kept: Vec::new(),
+            class: None,
+        }
+    }
+
+    fn column(name: &str) -> Box<Expression> {
+        Box::new(Expression::Column(name.to_string()))
+    }
+
+    #[test]
+    fn expressions_follow_precedence_and_parentheses() {
+        assert_eq!(
+            Expression::parse("pop_sum / area - 1").unwrap(),
+            Expression::Binary(
+                Box::new(Expression::Binary(
+                    column("pop_sum"),
+                    Operator::Divide,
+                    column("area")
+                )),
+                Operator::Subtract,
+                Box::new(Expression::Number(1.0)),
+            )
+        );
+        assert_eq!(
+            Expression::parse("-(area + count) * 2.5e1").unwrap(),
+            Expression::Binary(
+                Box::new(Expression::Negate(Box::new(Expression::Binary(
+                    column("area"),
+                    Operator::Add,
+                    column("count")
+                )))),
+                Operator::Multiply,
+                Box::new(Expression::Number(25.0)),
+            )
+        );
+        let derived = Derived::parse(" density = pop_sum / area ").unwrap();
+        assert_eq!(derived.name, "density");
+        assert_eq!(derived.expression.columns(), ["pop_sum", "area"]);
+
+        for invalid in ["(area", "area +", "area 2", "1..2", "area % 2", ""] {
+            assert!(Expression::parse(invalid).is_err(), "{}", invalid);
+        }
+        assert!(Derived::parse("area * 2").is_err());
+        assert!(Derived::parse("per-area = area").is_err());
+    }
+
+    #[test]
+    fn division_by_zero_and_missing_values_give_no_value() {
+        let mut rows = [
+            row("a", 4.0, Some(8.0)),
+            row("b", 0.0, Some(1.0)),
+            row("c", 1.0, None),
+        ];
+        let derived = [
+            Derived::parse("density = pop_sum / area").unwrap(),
+            Derived::parse("per_feature = density / count").unwrap(),
+            Derived::parse("zero = 0 / area").unwrap(),
+        ];
+        let columns = ["pop_sum".to_string()];
+        assert!(check(&derived, &columns).is_ok());
+        apply(&mut rows, &derived);
+        let values: Vec<Vec<Option<f64>>> = rows
+            .iter()
+            .map(|row| row.values[1..].iter().map(|(_, value)| *value).collect())
+            .collect();
+        assert_eq!(
+            values,
+            [
+                vec![Some(2.0), Some(1.0), Some(0.0)],
+                // 1 / 0 も 0 / 0 も値なし
+                vec![None, None, None],
+                vec![None, None, Some(0.0)],
+            ]
+        );
+
+        // 後に定義する列は使えず、名前は重複できない
+        assert!(check(&derived[1..], &columns).is_err());
+        assert!(check(&[Derived::parse("count = area").unwrap()], &columns).is_err());
+    }
+
+    #[test]
+    fn rows_are_sorted_with_missing_values_last_and_cut_at_top() {
+        let rows = || {
+            vec![
+                row("c", 2.0, Some(1.0)),
+                row("a", 1.0, None),
+                row("b", 3.0, Some(1.0)),
+                row("d", 0.5, Some(5.0)),
+            ]
+        };
+        let keys = |sort: &str| {
+            let mut rows = rows();
+            SortKey::parse(sort).unwrap().sort(&mut rows);
+            rows.iter().map(|row| row.key.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(keys("area"), ["b", "c", "a", "d"]);
+        assert_eq!(keys("area:asc"), ["d", "a", "c", "b"]);
+        // 同じ値なら集計キーの順、値のない行は向きによらず最後
+        assert_eq!(keys("pop_sum:desc"), ["d", "b", "c", "a"]);
+        assert_eq!(keys("pop_sum:asc"), ["b", "c", "d", "a"]);
+        assert!(SortKey::parse("area:up").is_err());
+        assert!(SortKey::parse(":asc").is_err());
+
+        let result = Pipeline::read(FIXTURE)
+            .derive(vec![Derived::parse("half = area / 2").unwrap()])
+            .sort(SortKey::parse("half:asc").unwrap())
+            .top(3)
+            .run()
+            .unwrap();
+        assert_eq!(result.rows.len(), 3);
+        assert!(result
+            .rows
+            .windows(2)
+            .all(|pair| pair[0].area <= pair[1].area));
+        for row in &result.rows {
+            assert_eq!(row.values, [("half".to_string(), Some(row.area / 2.0))]);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! 全体とグループごとの範囲（外接矩形）と重心（`layon bbox`）。
+//! 重心はポリゴンの面積で重み付けし、面積を持たない Feature だけのグループは Feature の重心の平均にする。
+
+use crate::aggregate::to_multi_polygon;
+use geo::{Area, BoundingRect, Centroid, Coord, Geometry, Rect};
+use geojson::FeatureCollection;
+use rayon::prelude::*;
+use std::collections::{hash_map::Entry, HashMap};
+
+/// 範囲と重心
+pub struct ExtentRow {
+    /// 集計キーの値（全体の場合は None）
+    pub name: Option<String>,
+    pub rect: Rect<f64>,
+    pub centroid: Coord<f64>,
+}
+
+/// 途中の集計値
+#[derive(Clone, Copy)]
+struct Extent {
+    rect: Rect<f64>,
+    /// 面積で重み付けした重心の和と面積の合計
+    weighted: (f64, f64, f64),
+    /// 面積を持たない Feature だけの場合に使う、重心の単純な和と個数
+    plain: (f64, f64, usize),
+}
+
+impl Extent {
+    fn new(geometry: &Geometry<f64>) -> Option<Extent> {
+        let rect = geometry.bounding_rect()?;
+        let centroid = geometry.centroid()?;
+        let area = to_multi_polygon(geometry.clone()).map_or(0.0, |p| p.unsigned_area());
+        Some(Extent {
+            rect,
+            weighted: (centroid.x() * area, centroid.y() * area, area),
+            plain: (centroid.x(), centroid.y(), 1),
+        })
+    }
+
+    fn merge(&mut self, other: Extent) {
+        let (a, b) = (self.rect, other.rect);
+        self.rect = Rect::new(
+            Coord {
+                x: a.min().x.min(b.min().x),
+                y: a.min().y.min(b.min().y),
+            },
+            Coord {
+                x: a.max().x.max(b.max().x),
+                y: a.max().y.max(b.max().y),
+            },
+        );
+        self.weighted.0 += other.weighted.0;
+        self.weighted.1 += other.weighted.1;
+        self.weighted.2 += other.weighted.2;
+        self.plain.0 += other.plain.0;
+        self.plain.1 += other.plain.1;
+        self.plain.2 += other.plain.2;
+    }
+
+    fn centroid(&self) -> Coord<f64> {
+        let (x, y, area) = self.weighted;
+        if area > 0.0 {
+            Coord {
+                x: x / area,
+                y: y / area,
+            }
+        } else {
+            let (x, y, n) = self.plain;
+            Coord {
+                x: x / n as f64,
+                y: y / n as f64,
+            }
+        }
+    }
+}
+
+/// 全体の範囲と重心を求める。`group_by` を指定した場合はそのプロパティごとの範囲も求める。
+/// 先頭が全体の行で、続いてキーの昇順に並ぶ。
+pub fn extents(collection: &FeatureCollection, group_by: Option<&str>) -> Vec<ExtentRow> {
+    let map = collection
+        .features
+        .par_iter()
+        .fold(HashMap::new, |mut map, feature| {
+            let Some(extent) = feature
+                .geometry
+                .as_ref()
+                .and_then(|geometry| Geometry::<f64>::try_from(geometry.value.clone()).ok())
+                .and_then(|geometry| Extent::new(&geometry))
+            else {
+                return map;
+            };
+            let name = group_by.and_then(|key| {
+                feature
+                    .properties
+                    .as_ref()?
+                    .get(key)?
+                    .as_str()
+                    .map(str::to_string)
+            });
+            if name.is_some() {
+                add(&mut map, name, extent);
+            }
+            add(&mut map, None, extent);
+            map
+        })
+        .reduce(HashMap::new, |mut a, b| {
+            for (key, extent) in b {
+                add(&mut a, key, extent);
+            }
+            a
+        });
+
+    let mut rows: Vec<ExtentRow> = map
+        .into_iter()
+        .map(|(name, extent)| ExtentRow {
+            name,
+            rect: extent.rect,
+            centroid: extent.centroid(),
+        })
+        .collect();
+    // None（全体）が先頭になる
+    rows.sort_by(|a, b| a.name.cmp(&b.name));
+    rows
+}
+
+fn add(map: &mut HashMap<Option<String>, Extent>, key: Option<String>, extent: Extent) {
+    match map.entry(key) {
+        Entry::Occupied(mut entry) => entry.get_mut().merge(extent),
+        Entry::Vacant(entry) => {
+            entry.insert(extent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection(features: &[(Option<&str>, &str)]) -> FeatureCollection {
+        let features: Vec<String> = features
+            .iter()
+            .map(|(city, geometry)| {
+                let properties = match city {
+                    Some(city) => format!(r#"{{"city":"{}"}}"#, city),
+                    None => "{}".to_string(),
+                };
+                format!(
+                    r#"{{"type":"Feature","properties":{},"geometry":{}}}"#,
+                    properties, geometry
+                )
+            })
+            .collect();
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        )
+        .parse()
+        .unwrap()
+    }
+
+    fn square(x: f64, y: f64, size: f64) -> String {
+        format!(
+            r#"{{"type":"Polygon","coordinates":[[[{x},{y}],[{},{y}],[{},{}],[{x},{}],[{x},{y}]]]}}"#,
+            x + size,
+            x + size,
+            y + size,
+            y + size
+        )
+    }
+
+    fn row(row: &ExtentRow) -> (Option<&str>, [f64; 4], (f64, f64)) {
+        let (min, max) = (row.rect.min(), row.rect.max());
+        (
+            row.name.as_deref(),
+            [min.x, min.y, max.x, max.y],
+            (row.centroid.x, row.centroid.y),
+        )
+    }
+
+    #[test]
+    fn overall_and_group_extents_with_area_weighted_centroids() {
+        let collection = collection(&[
+            (Some("a"), &square(0.0, 0.0, 2.0)),
+            (Some("a"), &square(4.0, 0.0, 1.0)),
+            (Some("b"), &square(10.0, 10.0, 1.0)),
+            // 面積を持たない Feature だけなら、その重心
+            (Some("c"), r#"{"type":"Point","coordinates":[0,20]}"#),
+            // 集計キーのない Feature は全体の範囲にだけ含める
+            (None, &square(-3.0, -3.0, 1.0)),
+            (Some("d"), "null"),
+        ]);
+        let by_city = extents(&collection, Some("city"));
+        let rows: Vec<_> = by_city.iter().map(row).collect();
+        assert_eq!(rows.len(), 4);
+        let (name, rect, (x, y)) = rows[0];
+        assert_eq!((name, rect), (None, [-3.0, -3.0, 11.0, 20.0]));
+        // 面積 4, 1, 1, 1 の正方形の重心の重み付き平均（点は面積 0）
+        assert!((x - 16.5 / 7.0).abs() < 1e-12 && (y - 12.5 / 7.0).abs() < 1e-12);
+        let (name, rect, (x, y)) = rows[1];
+        assert_eq!((name, rect), (Some("a"), [0.0, 0.0, 5.0, 2.0]));
+        assert!((x - 1.7).abs() < 1e-12 && (y - 0.9).abs() < 1e-12);
+        assert_eq!(rows[2], (Some("b"), [10.0, 10.0, 11.0, 11.0], (10.5, 10.5)));
+        assert_eq!(rows[3], (Some("c"), [0.0, 20.0, 0.0, 20.0], (0.0, 20.0)));
+
+        let overall = extents(&collection, None);
+        assert_eq!(overall.len(), 1);
+        assert_eq!(row(&overall[0]).1, [-3.0, -3.0, 11.0, 20.0]);
+        assert!(extents(&self::collection(&[]), None).is_empty());
+    }
+}
@@ -0,0 +1,263 @@
+//! 集計キーの種類がとても多い場合の、一時ディレクトリに書き出しながらの集計（`--memory-limit`）。
+//!
+//! メッシュコードや筆ポリゴンの ID のように集計キーが数千万種類になると、集計用の HashMap がメモリに収まらない。
+//! Feature を一定の数ずつ集計し、集計用の表の大きさの目安が上限を超えたら、表の行を集計キーのハッシュ値で
+//! `PARTITIONS` 個のファイルに振り分けて書き出し（`scratch` の一時ディレクトリ）、表を空にして続ける。
+//! 最後にファイルごとに（同じ集計キーは同じファイルにあるので、ファイルごとに独立に）行をまとめる。
+//! 上限を超えなければ何も書き出さず、`aggregate::aggregate_until` と同じ結果になる。
+//!
+//! 集計するのは面積と Feature の数だけ（ディゾルブ、ID、数値のプロパティ、ジオメトリの種類別の集計はしない）。
+
+use crate::{
+    aggregate::{self, Aggregation, Extras, GroupResult, Metric},
+    cancel::CancellationToken,
+    flat::FlatPolygons,
+    geometry_type::{GeometryType, TypeCounts},
+    progress::Progress,
+    scratch,
+    timing::{Stage, Timings},
+};
+use geojson::FeatureCollection;
+use rayon::prelude::*;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    error::Error,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+/// 書き出すファイルの数（まとめるときに一度にメモリに置くのは、集計キーのおよそこの数分の 1）
+pub const PARTITIONS: usize = 64;
+/// 作った書き出し先の数（一時ディレクトリの中の名前に使う）
+static SPILLS: AtomicUsize = AtomicUsize::new(0);
+/// 一度に集計する Feature の数
+const BATCH: usize = 1 << 16;
+/// 集計用の表の 1 行の、集計キーの文字列のほかの大きさの目安（String、面積、数、HashMap の管理領域）
+const ENTRY_OVERHEAD: u64 = 64;
+
+/// 集計用の表（集計キー -> (面積, Feature の数)）
+type Table = HashMap<String, (f64, usize)>;
+
+/// 書き出しながら集計した結果
+pub struct External {
+    pub aggregation: Aggregation,
+    /// 集計用の表を書き出した回数（上限を超えなければ 0）
+    pub spills: usize,
+}
+
+/// 集計用の表の大きさの目安が `limit` バイトを超えるたびに一時ディレクトリに書き出しながら、`group_by` ごとに集計する
+pub fn aggregate(
+    collection: &FeatureCollection,
+    group_by: &str,
+    extras: &Extras,
+    metric: Metric,
+    limit: u64,
+    cancel: &CancellationToken,
+    progress: &Progress,
+) -> Result<External, Box<dyn Error>> {
+    if extras.geometry || extras.ids || !extras.numeric.is_empty() || extras.geometry_types {
+        return Err(
+            "書き出しながらの集計 (memory_limit) では面積と Feature の数だけを集計できます".into(),
+        );
+    }
+    let compute_start = Instant::now();
+    let mut table: Table = HashMap::new();
+    let mut size = 0;
+    let mut spill: Option<Spill> = None;
+    let mut spills = 0;
+    let mut processed = 0;
+    let mut types = TypeCounts::default();
+    for batch in collection.features.chunks(BATCH) {
+        if cancel.is_cancelled() {
+            break;
+        }
+        let (partial, batch_types) = batch
+            .par_iter()
+            .fold(
+                || {
+                    (
+                        FlatPolygons::default(),
+                        HashMap::new(),
+                        TypeCounts::default(),
+                    )
+                },
+                |(mut flat, mut map, mut types), feature| {
+                    let Some(geometry) = &feature.geometry else {
+                        types.add(GeometryType::Empty);
+                        return (flat, map, types);
+                    };
+                    flat.load(&geometry.value);
+                    types.add(GeometryType::of(&flat));
+                    if let Some(properties) = &feature.properties {
+                        if let Some(key) = properties.get(group_by).and_then(|v| v.as_str()) {
+                            let key = aggregate::map_key(properties, key, extras.layers);
+                            let entry: &mut (f64, usize) = map.entry(key).or_default();
+                            entry.0 += aggregate::measure(&flat, metric);
+                            entry.1 += 1;
+                        }
+                    }
+                    (flat, map, types)
+                },
+            )
+            .map(|(_, map, types)| (map, types))
+            .reduce(
+                || (HashMap::new(), TypeCounts::default()),
+                |(mut a, mut a_types), (b, b_types)| {
+                    merge(&mut a, b);
+                    a_types.merge(b_types);
+                    (a, a_types)
+                },
+            );
+        types.merge(batch_types);
+        processed += batch.len();
+        progress.advance(batch.len());
+        for (key, (area, count)) in partial {
+            if !table.contains_key(&key) {
+                size += key.len() as u64 + ENTRY_OVERHEAD;
+            }
+            let entry = table.entry(key).or_default();
+            entry.0 += area;
+            entry.1 += count;
+        }
+        if size > limit {
+            let spill = match &mut spill {
+                Some(spill) => spill,
+                None => spill.insert(Spill::create()?),
+            };
+            spill.write(&mut table)?;
+            spills += 1;
+            size = 0;
+        }
+    }
+    // 最後の集計の後で中断された場合は、すべて集計できているので中断とみなさない
+    let cancelled = if processed < collection.features.len() {
+        cancel.check().err()
+    } else {
+        None
+    };
+    let mut timings = Timings::default();
+    timings.record(Stage::Compute, compute_start.elapsed());
+
+    let reduce_start = Instant::now();
+    let tables = match spill {
+        None => vec![table],
+        Some(mut spill) => {
+            spill.write(&mut table)?;
+            spill.read_all()?
+        }
+    };
+    let mut rows: Vec<GroupResult> = tables
+        .into_par_iter()
+        .flat_map_iter(|table| {
+            table.into_iter().map(|(key, (area, count))| {
+                let (layer, key) = aggregate::split_map_key(key, extras.layers);
+                GroupResult {
+                    key,
+                    layer,
+                    area,
+                    count,
+                    geometry: None,
+                    ids: Vec::new(),
+                    values: Vec::new(),
+                    kept: Vec::new(),
+                    class: None,
+                }
+            })
+        })
+        .collect();
+    rows.sort_by(|a, b| a.layer.cmp(&b.layer).then(b.area.total_cmp(&a.area)));
+    timings.record(Stage::Reduce, reduce_start.elapsed());
+    Ok(External {
+        aggregation: Aggregation {
+            rows,
+            processed,
+            cancelled,
+            timings,
+            types,
+        },
+        spills,
+    })
+}
+
+fn merge(a: &mut Table, b: Table) {
+    for (key, (area, count)) in b {
+        let entry = a.entry(key).or_default();
+        entry.0 += area;
+        entry.1 += count;
+    }
+}
+
+/// 集計キーのハッシュ値で振り分けたファイル
+struct Spill {
+    dir: PathBuf,
+    files: Vec<BufWriter<File>>,
+}
+
+impl Spill {
+    fn create() -> Result<Spill, Box<dyn Error>> {
+        let scratch = scratch::current()?;
+        let dir = scratch.subdir(&format!(
+            "aggregate-{}",
+            SPILLS.fetch_add(1, Ordering::Relaxed)
+        ))?;
+        let files = (0..PARTITIONS)
+            .map(|i| File::create(dir.join(i.to_string())).map(BufWriter::new))
+            .collect::<Result<_, _>>()?;
+        Ok(Spill { dir, files })
+    }
+
+    /// 表の行を書き出して表を空にする（1 行は 集計キーの長さ (u32)、集計キー、面積 (f64)、数 (u64)。いずれもリトルエンディアン）
+    fn write(&mut self, table: &mut Table) -> Result<(), Box<dyn Error>> {
+        // 書き出す前に大きさを数え、一時ディレクトリの上限を超えるなら書き出さない
+        let size = table.keys().map(|key| 4 + key.len() as u64 + 16).sum();
+        scratch::current()?
+            .reserve(size)
+            .map_err(|err| err.to_string())?;
+        for (key, (area, count)) in table.drain() {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            let file = &mut self.files[hasher.finish() as usize % PARTITIONS];
+            file.write_all(&(key.len() as u32).to_le_bytes())?;
+            file.write_all(key.as_bytes())?;
+            file.write_all(&area.to_le_bytes())?;
+            file.write_all(&(count as u64).to_le_bytes())?;
+        }
+        for file in &mut self.files {
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    /// ファイルごとに（並列に）行をまとめてから、一時ディレクトリを消す
+    fn read_all(self) -> Result<Vec<Table>, Box<dyn Error>> {
+        drop(self.files);
+        let tables = (0..PARTITIONS)
+            .into_par_iter()
+            .map(|i| {
+                let bytes = fs::read(self.dir.join(i.to_string()))?;
+                let mut table = HashMap::new();
+                let mut rest = &bytes[..];
+                while !rest.is_empty() {
+                    let (length, tail) = rest.split_at(4);
+                    let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+                    let (key, tail) = tail.split_at(length);
+                    let (area, tail) = tail.split_at(8);
+                    let (count, tail) = tail.split_at(8);
+                    let entry: &mut (f64, usize) = table
+                        .entry(String::from_utf8_lossy(key).into_owned())
+                        .or_default();
+                    entry.0 += f64::from_le_bytes(area.try_into().unwrap());
+                    entry.1 += u64::from_le_bytes(count.try_into().unwrap()) as usize;
+                    rest = tail;
+                }
+                Ok(table)
+            })
+            .collect::<Result<Vec<_>, std::io::Error>>();
+        let _ = scratch::current()?.remove_subdir(&self.dir);
+        Ok(tables?)
+    }
+}
@@ -0,0 +1,470 @@
+//! Feature のプロパティに対する条件式（`--where` / `Pipeline::filter`）。
+//!
+//! 例: `N03_001 == '埼玉県' && N03_004 != '秩父市'`、`population >= 100000`、`!(N03_003)`
+//! - 比較: `==`, `!=`, `<`, `<=`, `>`, `>=`（右辺は文字列、数値、true / false / null）
+//! - 論理演算: `&&`, `||`, `!` と括弧
+//! - プロパティ名だけの場合は、そのプロパティがあって null でないこと
+//!
+//! 集計キーの一覧による絞り込み（`--only`, `--exclude` など）は [`KeyFilter`] で行う。
+
+use geojson::{Feature, FeatureCollection, JsonValue};
+use std::{cmp::Ordering, collections::HashSet, fs};
+
+/// 解析済みの条件式
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    /// プロパティがあって null でない
+    Exists(String),
+    Compare(String, Operator, Literal),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+pub enum Literal {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    Null,
+}
+
+impl Filter {
+    /// 条件式を解析する
+    pub fn parse(text: &str) -> Result<Filter, String> {
+        let mut tokens = Tokens::new(text)?;
+        let filter = or(&mut tokens)?;
+        if let Some(token) = tokens.next() {
+            return Err(format!(
+                "条件式の末尾に余分な文字があります: {}",
+                token.text()
+            ));
+        }
+        Ok(filter)
+    }
+
+    /// Feature が条件を満たすか
+    pub fn matches(&self, feature: &Feature) -> bool {
+        match self {
+            Filter::And(a, b) => a.matches(feature) && b.matches(feature),
+            Filter::Or(a, b) => a.matches(feature) || b.matches(feature),
+            Filter::Not(a) => !a.matches(feature),
+            Filter::Exists(name) => feature.property(name).is_some_and(|v| !v.is_null()),
+            Filter::Compare(name, operator, literal) => {
+                let value = feature.property(name).unwrap_or(&JsonValue::Null);
+                match compare(value, literal) {
+                    Some(ordering) => match operator {
+                        Operator::Eq => ordering == Ordering::Equal,
+                        Operator::Ne => ordering != Ordering::Equal,
+                        Operator::Lt => ordering == Ordering::Less,
+                        Operator::Le => ordering != Ordering::Greater,
+                        Operator::Gt => ordering == Ordering::Greater,
+                        Operator::Ge => ordering != Ordering::Less,
+                    },
+                    // 型が合わない値どうしは等しくないものとして扱う
+                    None => *operator == Operator::Ne,
+                }
+            }
+        }
+    }
+}
+
+/// 集計キーの一覧による絞り込み。`only` を指定すれば一覧にある集計キーだけを、`exclude` にある集計キーを除いて集計する
+#[derive(Clone, Debug, Default)]
+pub struct KeyFilter {
+    pub only: Option<Vec<String>>,
+    pub exclude: Vec<String>,
+}
+
+impl KeyFilter {
+    /// カンマで区切った集計キーの一覧（前後の空白は除く）
+    pub fn split(text: &str) -> Vec<String> {
+        text.split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// 1 行に 1 つの集計キーを書いたファイル（空行と # で始まる行は読み飛ばす）
+    pub fn read(path: &str) -> Result<Vec<String>, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("集計キーの一覧 {} を読み込めません: {}", path, err))?;
+        Ok(text
+            .lines()
+            .map(|line| line.trim_start_matches('\u{feff}').trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// `only` に集計キーを加える
+    pub fn include(&mut self, keys: Vec<String>) {
+        self.only.get_or_insert_with(Vec::new).extend(keys);
+    }
+
+    /// 一覧の説明（実行計画の表示用）
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(only) = &self.only {
+            parts.push(format!("集計キーが {} 個の一覧にあるもの", only.len()));
+        }
+        if !self.exclude.is_empty() {
+            parts.push(format!("{} 個の集計キーを除く", self.exclude.len()));
+        }
+        parts.join("、")
+    }
+
+    /// 集計キーが一覧の条件を満たさなければ、その理由
+    pub fn rejects(&self, key: &str) -> Option<&'static str> {
+        if self.exclude.iter().any(|excluded| excluded == key) {
+            Some("除く集計キーの一覧にある")
+        } else if self
+            .only
+            .as_ref()
+            .is_some_and(|only| !only.iter().any(|kept| kept == key))
+        {
+            Some("集計キーの一覧にない")
+        } else {
+            None
+        }
+    }
+
+    /// `property` の値が一覧の条件を満たす Feature だけを残し、`only` のうち入力になかった集計キーを返す
+    pub fn retain(&self, collection: &mut FeatureCollection, property: &str) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self.retain_seen(collection, property, &mut seen);
+        self.missing(&seen)
+    }
+
+    /// `retain` と同じく絞り込み、`only` のうち見つかった集計キーを `seen` に加える（まとまりごとに絞り込む場合）
+    pub fn retain_seen(
+        &self,
+        collection: &mut FeatureCollection,
+        property: &str,
+        seen: &mut HashSet<String>,
+    ) {
+        let only: Option<HashSet<&str>> = self
+            .only
+            .as_ref()
+            .map(|only| only.iter().map(String::as_str).collect());
+        let exclude: HashSet<&str> = self.exclude.iter().map(String::as_str).collect();
+        collection.features.retain(|feature| {
+            let Some(key) = feature.property(property).and_then(JsonValue::as_str) else {
+                return false;
+            };
+            if only.as_ref().is_some_and(|only| !only.contains(key)) || exclude.contains(key) {
+                return false;
+            }
+            if only.is_some() {
+                seen.insert(key.to_string());
+            }
+            true
+        });
+    }
+
+    /// `only` のうち `seen` になかった集計キー（一覧の順）
+    pub fn missing(&self, seen: &HashSet<String>) -> Vec<String> {
+        let exclude: HashSet<&str> = self.exclude.iter().map(String::as_str).collect();
+        let mut missing = Vec::new();
+        for key in self.only.iter().flatten() {
+            if !seen.contains(key) && !exclude.contains(key.as_str()) && !missing.contains(key) {
+                missing.push(key.clone());
+            }
+        }
+        missing
+    }
+}
+
+/// プロパティの値とリテラルを比べる。数値のリテラルとは、数値の文字列も数値として比べる
+fn compare(value: &JsonValue, literal: &Literal) -> Option<Ordering> {
+    match (value, literal) {
+        (JsonValue::Null, Literal::Null) => Some(Ordering::Equal),
+        (JsonValue::Bool(a), Literal::Bool(b)) => Some(a.cmp(b)),
+        (JsonValue::String(a), Literal::Text(b)) => Some(a.as_str().cmp(b.as_str())),
+        (JsonValue::Number(a), Literal::Number(b)) => a.as_f64()?.partial_cmp(b),
+        (JsonValue::String(a), Literal::Number(b)) => a.trim().parse::<f64>().ok()?.partial_cmp(b),
+        _ => None,
+    }
+}
+
+fn or(tokens: &mut Tokens) -> Result<Filter, String> {
+    let mut filter = and(tokens)?;
+    while tokens.eat(&Token::Or) {
+        filter = Filter::Or(Box::new(filter), Box::new(and(tokens)?));
+    }
+    Ok(filter)
+}
+
+fn and(tokens: &mut Tokens) -> Result<Filter, String> {
+    let mut filter = unary(tokens)?;
+    while tokens.eat(&Token::And) {
+        filter = Filter::And(Box::new(filter), Box::new(unary(tokens)?));
+    }
+    Ok(filter)
+}
+
+fn unary(tokens: &mut Tokens) -> Result<Filter, String> {
+    match tokens.next() {
+        Some(Token::Not) => Ok(Filter::Not(Box::new(unary(tokens)?))),
+        Some(Token::Open) => {
+            let filter = or(tokens)?;
+            if !tokens.eat(&Token::Close) {
+                return Err("条件式の括弧が閉じていません".to_string());
+            }
+            Ok(filter)
+        }
+        Some(Token::Name(name)) => {
+            let Some(Token::Operator(operator)) = tokens.peek() else {
+                return Ok(Filter::Exists(name));
+            };
+            let operator = *operator;
+            tokens.next();
+            let literal = match tokens.next() {
+                Some(Token::Text(text)) => Literal::Text(text),
+                Some(Token::Number(number)) => Literal::Number(number),
+                Some(Token::Name(word)) => match word.as_str() {
+                    "true" => Literal::Bool(true),
+                    "false" => Literal::Bool(false),
+                    "null" => Literal::Null,
+                    // 引用符のない文字列も受け付ける（`N03_004 == 川越市` など）
+                    _ => Literal::Text(word),
+                },
+                Some(token) => return Err(format!("比較の右辺が不正です: {}", token.text())),
+                None => return Err(format!("{} の比較の右辺がありません", name)),
+            };
+            Ok(Filter::Compare(name, operator, literal))
+        }
+        Some(token) => Err(format!("条件式が不正です: {}", token.text())),
+        None => Err("条件式が途中で終わっています".to_string()),
+    }
+}
+
+enum Token {
+    Name(String),
+    Text(String),
+    Number(f64),
+    Operator(Operator),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Token {
+    /// エラーメッセージ用の表記
+    fn text(&self) -> String {
+        match self {
+            Token::Name(name) => name.clone(),
+            Token::Text(text) => format!("'{}'", text),
+            Token::Number(number) => number.to_string(),
+            Token::Operator(operator) => match operator {
+                Operator::Eq => "==",
+                Operator::Ne => "!=",
+                Operator::Lt => "<",
+                Operator::Le => "<=",
+                Operator::Gt => ">",
+                Operator::Ge => ">=",
+            }
+            .to_string(),
+            Token::And => "&&".to_string(),
+            Token::Or => "||".to_string(),
+            Token::Not => "!".to_string(),
+            Token::Open => "(".to_string(),
+            Token::Close => ")".to_string(),
+        }
+    }
+}
+
+/// 条件式を字句に分けたもの
+struct Tokens {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Tokens {
+    fn new(text: &str) -> Result<Tokens, String> {
+        let mut tokens = Vec::new();
+        let mut chars = text.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+                continue;
+            }
+            chars.next();
+            let next = chars.peek().copied();
+            let token = match (c, next) {
+                ('&', Some('&'))
+                | ('|', Some('|'))
+                | ('=', Some('='))
+                | ('!', Some('='))
+                | ('<', Some('='))
+                | ('>', Some('=')) => {
+                    chars.next();
+                    match c {
+                        '&' => Token::And,
+                        '|' => Token::Or,
+                        '=' => Token::Operator(Operator::Eq),
+                        '!' => Token::Operator(Operator::Ne),
+                        '<' => Token::Operator(Operator::Le),
+                        _ => Token::Operator(Operator::Ge),
+                    }
+                }
+                // `=` 1 つも等号として受け付ける
+                ('=', _) => Token::Operator(Operator::Eq),
+                ('<', _) => Token::Operator(Operator::Lt),
+                ('>', _) => Token::Operator(Operator::Gt),
+                ('!', _) => Token::Not,
+                ('(', _) => Token::Open,
+                (')', _) => Token::Close,
+                ('\'' | '"', _) => {
+                    let mut text = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('\\') => text.extend(chars.next()),
+                            Some(q) if q == c => break,
+                            Some(ch) => text.push(ch),
+                            None => return Err("条件式の文字列が閉じていません".to_string()),
+                        }
+                    }
+                    Token::Text(text)
+                }
+                _ => {
+                    let mut word = c.to_string();
+                    while let Some(&ch) = chars.peek() {
+                        if ch.is_whitespace() || "&|=!<>()'\"".contains(ch) {
+                            break;
+                        }
+                        word.push(ch);
+                        chars.next();
+                    }
+                    match word.parse::<f64>() {
+                        Ok(number) if c.is_ascii_digit() || c == '-' || c == '.' => {
+                            Token::Number(number)
+                        }
+                        _ => Token::Name(word),
+                    }
+                }
+            };
+            tokens.push(token);
+        }
+        Ok(Tokens {
+            tokens: tokens.into_iter().peekable(),
+        })
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        self.tokens.next()
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.tokens.peek()
+    }
+
+    /// 次の字句が `token` と同じ種類なら読み進める
+    fn eat(&mut self, token: &Token) -> bool {
+        let matched = self
+            .peek()
+            .is_some_and(|next| std::mem::discriminant(next) == std::mem::discriminant(token));
+        if matched {
+            self.next();
+        }
+        matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geojson::JsonObject;
+
+    fn feature(properties: JsonValue) -> Feature {
+        let properties: JsonObject = serde_json::from_value(properties).unwrap();
+        Feature {
+            properties: Some(properties),
+            ..Default::default()
+        }
+    }
+
+    fn matches(expression: &str, properties: JsonValue) -> bool {
+        Filter::parse(expression)
+            .unwrap()
+            .matches(&feature(properties))
+    }
+
+    #[test]
+    fn comparisons_combine_with_precedence_and_parentheses() {
+        let city =
+            serde_json::json!({"N03_001": "埼玉県", "N03_004": "秩父市", "population": "60000"});
+        assert!(!matches(
+            "N03_001 == '埼玉県' && N03_004 != '秩父市'",
+            city.clone()
+        ));
+        // && は || より先に結び付く
+        assert!(matches(
+            "N03_004 == 秩父市 || N03_001 == '東京都' && population > 1",
+            city.clone()
+        ));
+        assert!(!matches(
+            "(N03_004 == 秩父市 || N03_001 == '東京都') && population > 100000",
+            city.clone()
+        ));
+        // 数値の文字列は数値として比べる
+        assert!(matches(
+            "population >= 6e4 && population < 60000.5",
+            city.clone()
+        ));
+        assert!(matches("!(N03_003) && N03_004", city));
+    }
+
+    #[test]
+    fn values_of_different_types_are_only_unequal() {
+        let properties = serde_json::json!({"code": 11201, "flag": true, "note": null});
+        assert!(matches("code != '11201'", properties.clone()));
+        assert!(!matches("code == '11201'", properties.clone()));
+        assert!(!matches("code < 'x'", properties.clone()));
+        assert!(matches("flag == true && note == null", properties.clone()));
+        assert!(matches("missing == null", properties));
+    }
+
+    #[test]
+    fn malformed_expressions_are_rejected() {
+        let err = |expression: &str| Filter::parse(expression).err().unwrap();
+        assert_eq!(err("(a == 1"), "条件式の括弧が閉じていません");
+        assert_eq!(err("a == 'x"), "条件式の文字列が閉じていません");
+        assert_eq!(err("a =="), "a の比較の右辺がありません");
+        assert_eq!(err("a == 1 )"), "条件式の末尾に余分な文字があります: )");
+        assert_eq!(err("a &&"), "条件式が途中で終わっています");
+    }
+
+    #[test]
+    fn key_filters_keep_listed_keys_and_report_missing_ones() {
+        let mut collection = FeatureCollection {
+            bbox: None,
+            features: ["川越市", "所沢市", "秩父市"]
+                .iter()
+                .map(|city| feature(serde_json::json!({ "N03_004": city })))
+                .collect(),
+            foreign_members: None,
+        };
+        let mut keys = KeyFilter {
+            only: None,
+            exclude: vec!["所沢市".to_string()],
+        };
+        keys.include(KeyFilter::split("川越市, 所沢市,,飯能市"));
+        assert_eq!(keys.rejects("秩父市"), Some("集計キーの一覧にない"));
+        assert_eq!(keys.rejects("所沢市"), Some("除く集計キーの一覧にある"));
+        assert_eq!(keys.rejects("川越市"), None);
+        // 除く一覧にもある所沢市は、なかった集計キーに含めない
+        assert_eq!(keys.retain(&mut collection, "N03_004"), ["飯能市"]);
+        assert_eq!(collection.features.len(), 1);
+    }
+}
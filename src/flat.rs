@@ -0,0 +1,276 @@
+//! 座標を 1 つの平らな Vec に並べて持つポリゴン。
+//! geojson の `Vec<Vec<Vec<f64>>>` を geo のジオメトリに変換すると頂点ごとに割り当てが発生するため、
+//! 面積の計算などは geojson の値から直接この形に読み込んで行う。
+//! バッファはスレッドごとに使い回し（`clear` しても確保した領域は残る）、
+//! geo のアルゴリズムが必要な場合だけ `to_multi_polygon` で変換する。
+//! 面積を持たない線と点も、長さと数を求められるように読み込んでおく。
+
+use crate::area;
+use geo::{Coord, HaversineDistance, LineString, MultiPolygon, Point, Polygon};
+use geojson::Value;
+
+#[derive(Default)]
+pub struct FlatPolygons {
+    /// すべてのリングの頂点
+    coords: Vec<Coord<f64>>,
+    /// リングごとの `coords` の終わりの位置
+    ring_ends: Vec<usize>,
+    /// ポリゴンごとの `ring_ends` の終わりの位置（各ポリゴンの最初のリングが外周）
+    polygon_ends: Vec<usize>,
+    /// すべての線（LineString）の頂点
+    line_coords: Vec<Coord<f64>>,
+    /// 線ごとの `line_coords` の終わりの位置
+    line_ends: Vec<usize>,
+    /// 点の数（MultiPoint は点ごとに数える）
+    points: usize,
+    /// 座標が 2 つ未満で読み飛ばした位置の数
+    short_positions: usize,
+}
+
+impl FlatPolygons {
+    /// geojson のジオメトリのポリゴンを読み込む（前の内容は捨てる）
+    pub fn load(&mut self, value: &Value) {
+        self.coords.clear();
+        self.ring_ends.clear();
+        self.polygon_ends.clear();
+        self.line_coords.clear();
+        self.line_ends.clear();
+        self.points = 0;
+        self.short_positions = 0;
+        self.push(value);
+    }
+
+    /// GeometryCollection は入れ子になったものも含めて、メンバーを 1 つの Feature のものとして平らに並べる
+    fn push(&mut self, value: &Value) {
+        match value {
+            Value::Polygon(rings) => self.push_polygon(rings),
+            Value::MultiPolygon(polygons) => {
+                for rings in polygons {
+                    self.push_polygon(rings);
+                }
+            }
+            Value::LineString(line) => self.push_line(line),
+            Value::MultiLineString(lines) => {
+                for line in lines {
+                    self.push_line(line);
+                }
+            }
+            Value::Point(_) => self.points += 1,
+            Value::MultiPoint(points) => self.points += points.len(),
+            Value::GeometryCollection(geometries) => {
+                for geometry in geometries {
+                    self.push(&geometry.value);
+                }
+            }
+        }
+    }
+
+    fn push_polygon(&mut self, rings: &[Vec<Vec<f64>>]) {
+        for ring in rings {
+            for position in ring {
+                match coord(position) {
+                    Some(coord) => self.coords.push(coord),
+                    None => self.short_positions += 1,
+                }
+            }
+            self.ring_ends.push(self.coords.len());
+        }
+        self.polygon_ends.push(self.ring_ends.len());
+    }
+
+    fn push_line(&mut self, line: &[Vec<f64>]) {
+        for position in line {
+            match coord(position) {
+                Some(coord) => self.line_coords.push(coord),
+                None => self.short_positions += 1,
+            }
+        }
+        self.line_ends.push(self.line_coords.len());
+    }
+
+    /// すべての位置に X と Y があったか（なければ、その位置を読み飛ばした形になっている）
+    pub fn is_complete(&self) -> bool {
+        self.short_positions == 0
+    }
+
+    /// 読み込んだポリゴンの数
+    pub fn polygon_count(&self) -> usize {
+        self.polygon_ends.len()
+    }
+
+    /// 読み込んだ線の数
+    pub fn line_count(&self) -> usize {
+        self.line_ends.len()
+    }
+
+    /// 読み込んだ点の数
+    pub fn point_count(&self) -> usize {
+        self.points
+    }
+
+    /// 線ごとの頂点の列
+    fn lines(&self) -> impl Iterator<Item = &[Coord<f64>]> + '_ {
+        let mut start = 0;
+        self.line_ends.iter().map(move |&end| {
+            let line = &self.line_coords[start..end];
+            start = end;
+            line
+        })
+    }
+
+    /// ポリゴンのすべてのリングの頂点の列（外周と穴を区別しない）
+    pub fn rings(&self) -> impl Iterator<Item = &[Coord<f64>]> + '_ {
+        let mut start = 0;
+        self.ring_ends.iter().map(move |&end| {
+            let ring = &self.coords[start..end];
+            start = end;
+            ring
+        })
+    }
+
+    /// 線の長さの合計（座標の単位のまま、平面上で求める）
+    pub fn length(&self) -> f64 {
+        self.lines()
+            .flat_map(|line| line.windows(2))
+            .map(|pair| (pair[1].x - pair[0].x).hypot(pair[1].y - pair[0].y))
+            .sum()
+    }
+
+    /// 線の長さの合計の大円距離 (km)。座標は経度・緯度であること
+    pub fn geodesic_length(&self) -> f64 {
+        self.lines()
+            .flat_map(|line| line.windows(2))
+            .map(|pair| Point::from(pair[0]).haversine_distance(&Point::from(pair[1])))
+            .sum::<f64>()
+            / 1e3
+    }
+
+    /// ポリゴンごとのリングの頂点の列（最初が外周、残りが穴）
+    fn polygons(&self) -> impl Iterator<Item = Vec<&[Coord<f64>]>> + '_ {
+        let mut ring = 0;
+        self.polygon_ends.iter().map(move |&end| {
+            let rings = (ring..end)
+                .map(|i| {
+                    let start = if i == 0 { 0 } else { self.ring_ends[i - 1] };
+                    &self.coords[start..self.ring_ends[i]]
+                })
+                .collect();
+            ring = end;
+            rings
+        })
+    }
+
+    /// ポリゴンごとの外周の面積と、穴の数と面積の合計（`ring_area` で 1 つのリングの面積を求める）
+    pub fn ring_areas(&self, ring_area: impl Fn(&[Coord<f64>]) -> f64) -> Vec<(f64, usize, f64)> {
+        self.polygons()
+            .filter_map(|rings| {
+                let (exterior, holes) = rings.split_first()?;
+                Some((
+                    ring_area(exterior),
+                    holes.len(),
+                    // 穴がなければ -0 ではなく 0 にする
+                    holes.iter().fold(0.0, |sum, ring| sum + ring_area(ring)),
+                ))
+            })
+            .collect()
+    }
+
+    /// 面積の合計（穴を除く。geo の unsigned_area と同じ扱い）
+    pub fn unsigned_area(&self) -> f64 {
+        self.area_with(area::ring_area)
+    }
+
+    /// `ring_area` で求めたリングの面積の合計（穴を除く）
+    pub fn area_with(&self, ring_area: impl Fn(&[Coord<f64>]) -> f64) -> f64 {
+        self.polygons()
+            .map(|rings| match rings.split_first() {
+                Some((exterior, holes)) => {
+                    ring_area(exterior) - holes.iter().map(|ring| ring_area(ring)).sum::<f64>()
+                }
+                None => 0.0,
+            })
+            .sum()
+    }
+
+    /// `rings` の順に求めたリングの面積（正の値）から、面積の合計（穴を除く）を求める
+    #[cfg(feature = "gpu")]
+    pub fn area_from(&self, ring_areas: &[f64]) -> f64 {
+        let mut ring = 0;
+        self.polygon_ends
+            .iter()
+            .map(|&end| {
+                let area = match ring_areas[ring..end].split_first() {
+                    Some((exterior, holes)) => exterior - holes.iter().sum::<f64>(),
+                    None => 0.0,
+                };
+                ring = end;
+                area
+            })
+            .sum()
+    }
+
+    /// ポリゴンの重心（座標の単位のまま平面上で求める。穴は除く。ポリゴンがなければ None）
+    pub fn centroid(&self) -> Option<Coord<f64>> {
+        let (mut weight, mut x, mut y) = (0.0, 0.0, 0.0);
+        for rings in self.polygons() {
+            for (i, ring) in rings.iter().enumerate() {
+                let Some(&origin) = ring.first() else {
+                    continue;
+                };
+                // 最初の頂点を原点にした、三角形ごとの面積と重心の和
+                let (mut area, mut cx, mut cy) = (0.0, 0.0, 0.0);
+                for pair in ring.windows(2) {
+                    let (a, b) = (pair[0] - origin, pair[1] - origin);
+                    let cross = a.x * b.y - b.x * a.y;
+                    area += cross;
+                    cx += (a.x + b.x) * cross;
+                    cy += (a.y + b.y) * cross;
+                }
+                if area == 0.0 {
+                    continue;
+                }
+                // 外周は足し、穴は引く（リングの向きによらない）
+                let sign = if i == 0 { 1.0 } else { -1.0 } * area.signum();
+                weight += sign * area;
+                x += sign * (cx / 3.0 + origin.x * area);
+                y += sign * (cy / 3.0 + origin.y * area);
+            }
+        }
+        if weight > 0.0 {
+            return Some(Coord {
+                x: x / weight,
+                y: y / weight,
+            });
+        }
+        // 面積のないポリゴンは最初の頂点
+        self.polygon_ends
+            .first()
+            .and_then(|_| self.coords.first().copied())
+    }
+
+    /// geo の MultiPolygon に変換する（GeometryCollection の中のポリゴンも含める。ポリゴンがなければ None）
+    pub fn to_multi_polygon(&self) -> Option<MultiPolygon<f64>> {
+        if self.polygon_ends.is_empty() {
+            return None;
+        }
+        let polygons = self.polygons().filter_map(|rings| {
+            let (exterior, holes) = rings.split_first()?;
+            Some(Polygon::new(
+                LineString::from(exterior.to_vec()),
+                holes
+                    .iter()
+                    .map(|ring| LineString::from(ring.to_vec()))
+                    .collect(),
+            ))
+        });
+        Some(MultiPolygon::new(polygons.collect()))
+    }
+}
+
+/// 位置の X と Y（2 つ未満なら None）
+fn coord(position: &[f64]) -> Option<Coord<f64>> {
+    match position {
+        [x, y, ..] => Some(Coord { x: *x, y: *y }),
+        _ => None,
+    }
+}
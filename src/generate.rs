@@ -0,0 +1,342 @@
+//! 合成した FeatureCollection の生成（`layon generate`）。
+//! ベンチマーク用の大きな入力や、実データを共有できない場合の不具合報告用のデータを作る。
+//! 格子のセルごとに 1 つずつ星形のポリゴン（自己交差せず、互いに重ならない）を置き、
+//! 指定したスキーマのプロパティを付ける。同じ種なら同じデータになる。
+
+use crate::{subset, transform::GeometryTransform};
+use geojson::{Feature, Geometry, JsonObject, JsonValue, Value};
+use rayon::prelude::*;
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::Range,
+};
+
+/// 一度に並列に作って書き出す Feature の数（全体をメモリに持たないように）
+const BATCH: usize = 4096;
+
+/// プロパティの値の作り方
+#[derive(Clone, Debug, PartialEq)]
+pub enum PropertyKind {
+    /// `NAME_1` 〜 `NAME_N` の N 種類の文字列（集計キーに）
+    Category(usize),
+    /// 範囲内の一様な整数
+    Int(Range<i64>),
+    /// 範囲内の一様な小数
+    Float(Range<f64>),
+    /// 0 から順に振った番号
+    Sequence,
+}
+
+/// 生成するプロパティ
+#[derive(Clone, Debug, PartialEq)]
+pub struct PropertySpec {
+    pub name: String,
+    pub kind: PropertyKind,
+}
+
+/// 既定のポリゴンを並べる範囲（埼玉県のあたりの経度・緯度）
+pub const DEFAULT_BBOX: [f64; 4] = [138.7, 35.75, 139.9, 36.3];
+
+/// 既定のスキーマ（そのまま既定の集計キーで集計できるように）
+pub const DEFAULT_SCHEMA: &str = "N03_004:category:20";
+
+impl PropertySpec {
+    /// `名前:種類[:引数]` をカンマで区切って並べたものを解析する。
+    /// 種類は `category[:N]`（既定 10）、`int[:MIN..MAX]`（既定 0..100000）、
+    /// `float[:MIN..MAX]`（既定 0..1）、`sequence`
+    pub fn parse_schema(text: &str) -> Result<Vec<PropertySpec>, String> {
+        let mut specs: Vec<PropertySpec> = Vec::new();
+        for entry in text.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let mut parts = entry.splitn(3, ':');
+            let name = parts.next().unwrap_or_default().trim();
+            let kind = parts.next().map(str::trim);
+            let argument = parts.next().map(str::trim);
+            if name.is_empty() {
+                return Err(format!("プロパティの名前がありません: {}", entry));
+            }
+            let invalid = || format!("プロパティの定義が不正です: {}", entry);
+            let kind = match (kind, argument) {
+                (Some("category"), None) => PropertyKind::Category(10),
+                (Some("category"), Some(n)) => match n.parse() {
+                    Ok(n) if n > 0 => PropertyKind::Category(n),
+                    _ => return Err(invalid()),
+                },
+                (Some("int"), None) => PropertyKind::Int(0..100_000),
+                (Some("int"), Some(range)) => {
+                    PropertyKind::Int(parse_range(range).ok_or_else(invalid)?)
+                }
+                (Some("float"), None) => PropertyKind::Float(0.0..1.0),
+                (Some("float"), Some(range)) => {
+                    PropertyKind::Float(parse_range(range).ok_or_else(invalid)?)
+                }
+                (Some("sequence"), None) => PropertyKind::Sequence,
+                _ => return Err(invalid()),
+            };
+            if specs.iter().any(|spec| spec.name == name) {
+                return Err(format!("プロパティの名前が重複しています: {}", name));
+            }
+            specs.push(PropertySpec {
+                name: name.to_string(),
+                kind,
+            });
+        }
+        Ok(specs)
+    }
+
+    /// `index` 番目の Feature のプロパティの値
+    fn value(&self, index: usize, draws: &mut Draws) -> JsonValue {
+        match &self.kind {
+            PropertyKind::Category(n) => {
+                let k = ((draws.next() * *n as f64) as usize).min(n - 1) + 1;
+                JsonValue::from(format!("{}_{}", self.name, k))
+            }
+            PropertyKind::Int(range) => {
+                let span = (range.end - range.start) as f64;
+                JsonValue::from(range.start + (draws.next() * span) as i64)
+            }
+            PropertyKind::Float(range) => {
+                JsonValue::from(range.start + draws.next() * (range.end - range.start))
+            }
+            PropertyKind::Sequence => JsonValue::from(index),
+        }
+    }
+}
+
+/// `MIN..MAX`（MIN < MAX）
+fn parse_range<T: std::str::FromStr + PartialOrd>(text: &str) -> Option<Range<T>> {
+    let (min, max) = text.split_once("..")?;
+    let range = min.trim().parse().ok()?..max.trim().parse().ok()?;
+    (range.start < range.end).then_some(range)
+}
+
+/// 生成するデータの設定
+pub struct Generator {
+    /// Feature の数
+    pub features: usize,
+    /// 1 つのポリゴンの頂点の数（3 以上）
+    pub vertices: usize,
+    pub properties: Vec<PropertySpec>,
+    /// ポリゴンを並べる範囲（最小 x, 最小 y, 最大 x, 最大 y）
+    pub bbox: [f64; 4],
+    pub seed: u64,
+}
+
+impl Generator {
+    /// `index` 番目の Feature
+    pub fn feature(&self, index: usize) -> Feature {
+        let mut draws = Draws {
+            seed: self.seed,
+            index: (index as u64) << 32,
+        };
+        let properties: JsonObject = self
+            .properties
+            .iter()
+            .map(|spec| (spec.name.clone(), spec.value(index, &mut draws)))
+            .collect();
+        Feature {
+            bbox: None,
+            geometry: Some(Geometry::new(Value::Polygon(vec![
+                self.ring(index, &mut draws)
+            ]))),
+            id: None,
+            properties: Some(properties),
+            foreign_members: None,
+        }
+    }
+
+    /// 格子の `index` 番目のセルに収まる、中心から見て角度の順に頂点を並べた星形のリング（反時計回り）
+    fn ring(&self, index: usize, draws: &mut Draws) -> Vec<Vec<f64>> {
+        let columns = (self.features as f64).sqrt().ceil().max(1.0) as usize;
+        let rows = self.features.div_ceil(columns).max(1);
+        let [min_x, min_y, max_x, max_y] = self.bbox;
+        let (width, height) = (
+            (max_x - min_x) / columns as f64,
+            (max_y - min_y) / rows as f64,
+        );
+        let center = (
+            min_x + (index % columns) as f64 * width + width / 2.0,
+            min_y + (index / columns) as f64 * height + height / 2.0,
+        );
+        let radius = width.min(height) * 0.45;
+
+        // 隣り合う頂点の角度の差が半周を超えると辺が交差しうるので、等間隔から少しだけずらす
+        let jitter = if self.vertices >= 4 { 0.9 } else { 0.4 };
+        let mut ring: Vec<Vec<f64>> = (0..self.vertices)
+            .map(|i| {
+                let angle = (i as f64 + jitter * draws.next()) / self.vertices as f64
+                    * std::f64::consts::TAU;
+                let r = radius * (0.5 + 0.5 * draws.next());
+                vec![center.0 + r * angle.cos(), center.1 + r * angle.sin()]
+            })
+            .collect();
+        ring.push(ring[0].clone());
+        ring
+    }
+
+    /// GeoJSON で書き出す（`path` が "-" なら標準出力）。
+    /// Feature をまとめて作らず、少しずつ並列に作っては書き出す
+    pub fn write(&self, path: &str, transform: &GeometryTransform) -> Result<(), Box<dyn Error>> {
+        let mut out: Box<dyn Write> = if path == "-" {
+            Box::new(BufWriter::new(io::stdout().lock()))
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        write!(out, "{{\"type\":\"FeatureCollection\",\"features\":[")?;
+        for start in (0..self.features).step_by(BATCH) {
+            let end = (start + BATCH).min(self.features);
+            let features: Vec<Feature> = (start..end)
+                .into_par_iter()
+                .map(|index| {
+                    let mut feature = self.feature(index);
+                    if let Some(geometry) = &mut feature.geometry {
+                        transform.apply(&mut geometry.value);
+                    }
+                    feature
+                })
+                .collect();
+            for (i, feature) in features.iter().enumerate() {
+                if start + i > 0 {
+                    write!(out, ",")?;
+                }
+                serde_json::to_writer(&mut out, feature)?;
+            }
+        }
+        writeln!(out, "]}}")?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Feature ごとの乱数の列（種と Feature の位置だけで決まる）
+struct Draws {
+    seed: u64,
+    index: u64,
+}
+
+impl Draws {
+    /// [0, 1) の一様乱数
+    fn next(&mut self) -> f64 {
+        self.index += 1;
+        subset::uniform(self.seed, self.index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geojson::FeatureCollection;
+
+    fn generator(features: usize, seed: u64) -> Generator {
+        Generator {
+            features,
+            vertices: 7,
+            properties: PropertySpec::parse_schema(
+                "N03_004:category:3, pop:int:10..20, share:float, id:sequence",
+            )
+            .unwrap(),
+            bbox: DEFAULT_BBOX,
+            seed,
+        }
+    }
+
+    fn write(generator: &Generator, name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "layon-generate-{}-{}.geojson",
+            name,
+            std::process::id()
+        ));
+        generator
+            .write(path.to_str().unwrap(), &GeometryTransform::default())
+            .unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        text
+    }
+
+    #[test]
+    fn schemas_are_parsed() {
+        assert_eq!(
+            PropertySpec::parse_schema(DEFAULT_SCHEMA).unwrap(),
+            [PropertySpec {
+                name: "N03_004".to_string(),
+                kind: PropertyKind::Category(20),
+            }]
+        );
+        let kinds: Vec<PropertyKind> = PropertySpec::parse_schema("a:category,b:int,c:float:-1..1")
+            .unwrap()
+            .into_iter()
+            .map(|spec| spec.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                PropertyKind::Category(10),
+                PropertyKind::Int(0..100_000),
+                PropertyKind::Float(-1.0..1.0),
+            ]
+        );
+        for invalid in [
+            "a",
+            ":int",
+            "a:text",
+            "a:category:0",
+            "a:int:5..5",
+            "a:int:1.5..2",
+            "a:sequence:3",
+            "a:int,a:float",
+        ] {
+            assert!(PropertySpec::parse_schema(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn features_have_the_requested_vertices_and_properties() {
+        // 一度に書き出す数を超える数にして、まとまりの境目も確かめる
+        let generator = generator(BATCH + 10, 1);
+        let text = write(&generator, "counts");
+        let collection: FeatureCollection = text.parse().unwrap();
+        assert_eq!(collection.features.len(), BATCH + 10);
+
+        let [min_x, min_y, max_x, max_y] = DEFAULT_BBOX;
+        for (index, feature) in collection.features.iter().enumerate() {
+            let Some(Value::Polygon(rings)) = feature.geometry.as_ref().map(|g| &g.value) else {
+                panic!("ポリゴンではありません: {}", index);
+            };
+            assert_eq!(rings.len(), 1);
+            let ring = &rings[0];
+            assert_eq!(ring.len(), 8);
+            assert_eq!(ring[0], ring[7]);
+            assert!(ring
+                .iter()
+                .all(|p| (min_x..=max_x).contains(&p[0]) && (min_y..=max_y).contains(&p[1])));
+            // 反時計回り（符号付き面積が正）
+            let twice: f64 = ring
+                .windows(2)
+                .map(|pair| pair[0][0] * pair[1][1] - pair[1][0] * pair[0][1])
+                .sum();
+            assert!(twice > 0.0);
+
+            let properties = feature.properties.as_ref().unwrap();
+            let names: Vec<&str> = properties.keys().map(String::as_str).collect();
+            assert_eq!(names.len(), 4);
+            let city = properties["N03_004"].as_str().unwrap();
+            assert!(
+                ["N03_004_1", "N03_004_2", "N03_004_3"].contains(&city),
+                "{}",
+                city
+            );
+            let pop = properties["pop"].as_i64().unwrap();
+            assert!((10..20).contains(&pop));
+            let share = properties["share"].as_f64().unwrap();
+            assert!((0.0..1.0).contains(&share));
+            assert_eq!(properties["id"].as_u64(), Some(index as u64));
+        }
+
+        // 同じ種なら同じデータ、違う種なら違うデータ
+        let small = write(&self::generator(50, 1), "same");
+        assert_eq!(write(&self::generator(50, 1), "again"), small);
+        assert_ne!(write(&self::generator(50, 2), "other"), small);
+    }
+}
@@ -0,0 +1,389 @@
+//! 無作為に作ったポリゴンで、面積の計算とジオメトリの処理（修復、向きの変換、重複の除去、投影）が満たすべき性質を確かめる単体テスト。
+//! ポリゴンは proptest の戦略で作るので、失敗したケースは縮めた（頂点の少ない）ものが表示され、
+//! proptest-regressions に記録されて次回から先に試される。
+
+use crate::{
+    aggregate::{self, Metric},
+    dedup,
+    projection::Projection,
+    repair,
+    transform::{GeometryTransform, Winding},
+};
+use geo::{Coord, LineString, MultiPolygon, Polygon};
+use geojson::{Feature, FeatureCollection, Geometry, JsonObject, JsonValue, PointType, Value};
+use proptest::{prelude::*, sample::Index, test_runner::TestCaseError};
+
+/// 性質ごとに試すケースの数
+const CASES: u32 = 200;
+
+/// 閉じた点列
+type Ring = Vec<(f64, f64)>;
+
+/// 中心から見て角度の順に頂点を並べた星形の（自己交差のない）リング。閉じた点列で返す。
+/// 隣り合う頂点の角度の差が半周を超えると辺が中心の反対側を横切って交差しうるため、
+/// 4 点以上を等間隔から少しずつずらして置く
+fn star_ring(center: (f64, f64), radius: f64) -> impl Strategy<Value = Ring> {
+    (4usize..50)
+        .prop_flat_map(|vertices| prop::collection::vec((0.0..0.9f64, 0.2..1.0f64), vertices))
+        .prop_map(move |offsets| {
+            let vertices = offsets.len() as f64;
+            let mut ring: Ring = offsets
+                .iter()
+                .enumerate()
+                .map(|(i, &(offset, scale))| {
+                    let angle = (i as f64 + offset) / vertices * std::f64::consts::TAU;
+                    let r = radius * scale;
+                    (center.0 + r * angle.cos(), center.1 + r * angle.sin())
+                })
+                .collect();
+            ring.push(ring[0]);
+            ring
+        })
+}
+
+/// 中心を `x`, `y` の範囲から選んだ星形のリング（中心と組にする）
+fn star_ring_in(
+    x: std::ops::Range<f64>,
+    y: std::ops::Range<f64>,
+    radius: f64,
+) -> impl Strategy<Value = ((f64, f64), Ring)> {
+    (x, y).prop_flat_map(move |center| (Just(center), star_ring(center, radius)))
+}
+
+/// 星形のリングの頂点の順を入れ替えた、自己交差の多い（不正な）リング
+fn tangled_ring() -> impl Strategy<Value = Ring> {
+    star_ring((0.0, 0.0), 1.0).prop_flat_map(|mut ring| {
+        ring.pop();
+        Just(ring).prop_shuffle().prop_map(|mut ring| {
+            ring.push(ring[0]);
+            ring
+        })
+    })
+}
+
+/// 長方形の頂点を 1 組入れ替えた、対角線で交差する蝶ネクタイ形の（不正な）リングと、長方形の幅と高さ
+fn bowtie() -> impl Strategy<Value = (Ring, f64, f64)> {
+    (-50.0..50.0, -50.0..50.0, 0.1..10.0, 0.1..10.0).prop_map(|(x, y, w, h)| {
+        (
+            vec![(x, y), (x + w, y + h), (x + w, y), (x, y + h), (x, y)],
+            w,
+            h,
+        )
+    })
+}
+
+/// 始点を `shift` だけずらした（閉じる点を付け直した）リング
+fn rotate(ring: &[(f64, f64)], shift: Index) -> Ring {
+    let shift = 1 + shift.index(ring.len() - 2);
+    let mut rotated: Ring = ring[shift..ring.len() - 1]
+        .iter()
+        .chain(&ring[..shift])
+        .copied()
+        .collect();
+    rotated.push(rotated[0]);
+    rotated
+}
+
+fn shoelace(ring: &[(f64, f64)]) -> f64 {
+    ring.windows(2)
+        .map(|pair| pair[0].0 * pair[1].1 - pair[1].0 * pair[0].1)
+        .sum::<f64>()
+        / 2.0
+}
+
+fn positions(ring: &[(f64, f64)]) -> Vec<PointType> {
+    ring.iter().map(|&(x, y)| vec![x, y]).collect()
+}
+
+fn feature(value: Value) -> Feature {
+    let mut properties = JsonObject::new();
+    properties.insert("N03_004".to_string(), JsonValue::from("a"));
+    Feature {
+        bbox: None,
+        geometry: Some(Geometry::new(value)),
+        id: None,
+        properties: Some(properties),
+        foreign_members: None,
+    }
+}
+
+fn polygon_feature(rings: &[Vec<(f64, f64)>]) -> Feature {
+    feature(Value::Polygon(
+        rings.iter().map(|ring| positions(ring)).collect(),
+    ))
+}
+
+fn multi_polygon(rings: &[(f64, f64)]) -> MultiPolygon<f64> {
+    let coords = rings.iter().map(|&(x, y)| Coord { x, y }).collect();
+    MultiPolygon::new(vec![Polygon::new(LineString::new(coords), Vec::new())])
+}
+
+fn assert_close(actual: f64, expected: f64, context: &str) -> Result<(), TestCaseError> {
+    let tolerance = 1e-9 * expected.abs().max(1.0);
+    prop_assert!(
+        (actual - expected).abs() <= tolerance,
+        "{}: {} != {}",
+        context,
+        actual,
+        expected
+    );
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(CASES))]
+
+    #[test]
+    fn area_matches_shoelace((_, ring) in star_ring_in(-180.0..180.0, -80.0..80.0, 1.0)) {
+        let area =
+            aggregate::feature_area(&polygon_feature(std::slice::from_ref(&ring)), Metric::Area);
+        assert_close(area, shoelace(&ring).abs(), "星形")?;
+    }
+
+    #[test]
+    fn area_of_invalid_rings_is_the_shoelace_without_repair(
+        ring in tangled_ring(),
+        (bowtie, _, _) in bowtie(),
+    ) {
+        // 修復しなければ、自己交差したリングも靴紐公式の値（向きの逆の部分は打ち消し合う）になる
+        for ring in [ring, bowtie] {
+            let area =
+                aggregate::feature_area(&polygon_feature(std::slice::from_ref(&ring)), Metric::Area);
+            assert_close(area, shoelace(&ring).abs(), "自己交差")?;
+        }
+    }
+
+    #[test]
+    fn area_ignores_orientation_and_start_vertex(
+        ring in star_ring((139.0, 35.0), 0.5),
+        shift in any::<Index>(),
+    ) {
+        for metric in [Metric::Area, Metric::GeodesicArea] {
+            let expected =
+                aggregate::feature_area(&polygon_feature(std::slice::from_ref(&ring)), metric);
+
+            let mut reversed = ring.clone();
+            reversed.reverse();
+            let area = aggregate::feature_area(&polygon_feature(&[reversed]), metric);
+            assert_close(area, expected, "逆向き")?;
+
+            let area = aggregate::feature_area(&polygon_feature(&[rotate(&ring, shift)]), metric);
+            assert_close(area, expected, "始点をずらしたもの")?;
+        }
+    }
+
+    #[test]
+    fn hole_is_subtracted(
+        (center, exterior) in star_ring_in(-10.0..10.0, -10.0..10.0, 1.0),
+        scale in 0.1..0.9,
+    ) {
+        // 星形のリングを中心に向けて縮めたものは内側に収まる
+        let hole: Ring = exterior
+            .iter()
+            .rev()
+            .map(|&(x, y)| {
+                (
+                    center.0 + (x - center.0) * scale,
+                    center.1 + (y - center.1) * scale,
+                )
+            })
+            .collect();
+        let area =
+            aggregate::feature_area(&polygon_feature(&[exterior.clone(), hole]), Metric::Area);
+        let expected = shoelace(&exterior).abs() * (1.0 - scale * scale);
+        assert_close(area, expected, "穴")?;
+    }
+
+    #[test]
+    fn make_valid_leaves_valid_polygons_alone(ring in star_ring((0.0, 0.0), 1.0)) {
+        prop_assert!(
+            repair::make_valid(&multi_polygon(&ring)).is_none(),
+            "自己交差のないリングを修復した"
+        );
+    }
+
+    #[test]
+    fn make_valid_fills_both_halves_of_a_bowtie((bowtie, w, h) in bowtie()) {
+        let mut collection = FeatureCollection {
+            bbox: None,
+            features: vec![polygon_feature(&[bowtie])],
+            foreign_members: None,
+        };
+        prop_assert_eq!(repair::make_valid_each(&mut collection), [0]);
+        let area = aggregate::feature_area(&collection.features[0], Metric::Area);
+        assert_close(area, w * h / 2.0, "蝶ネクタイ形")?;
+    }
+
+    #[test]
+    fn make_valid_output_is_valid(ring in tangled_ring()) {
+        let Some(repaired) = repair::make_valid(&multi_polygon(&ring)) else {
+            return Ok(());
+        };
+        // 修復した結果をもう一度修復しても変わらない（自己交差が残っていない）
+        prop_assert!(repair::make_valid(&repaired).is_none());
+        // 頂点はすべて半径 1 の円の中にあるので、面積は円の面積を超えない
+        let area = aggregate::feature_area(&feature(Value::from(&repaired)), Metric::Area);
+        prop_assert!(area > 0.0 && area <= std::f64::consts::PI, "{}", area);
+    }
+
+    #[test]
+    fn transform_orients_rings(
+        (center, exterior) in star_ring_in(-10.0..10.0, -10.0..10.0, 1.0),
+        reverse_exterior in any::<bool>(),
+        reverse_hole in any::<bool>(),
+    ) {
+        let mut exterior = exterior;
+        let mut hole: Ring = exterior
+            .iter()
+            .map(|&(x, y)| {
+                (
+                    center.0 + (x - center.0) / 2.0,
+                    center.1 + (y - center.1) / 2.0,
+                )
+            })
+            .collect();
+        // 入力の向きは無作為にする
+        if reverse_exterior {
+            exterior.reverse();
+        }
+        if reverse_hole {
+            hole.reverse();
+        }
+        for (winding, exterior_sign) in [(Winding::Rfc7946, 1.0), (Winding::Legacy, -1.0)] {
+            let mut value = Value::Polygon(vec![positions(&exterior), positions(&hole)]);
+            let transform = GeometryTransform {
+                winding,
+                ..GeometryTransform::default()
+            };
+            transform.apply(&mut value);
+            let Value::Polygon(rings) = value else {
+                unreachable!()
+            };
+            let signed = |ring: &Vec<PointType>| {
+                let ring: Ring = ring.iter().map(|p| (p[0], p[1])).collect();
+                shoelace(&ring)
+            };
+            prop_assert!(signed(&rings[0]) * exterior_sign > 0.0, "外周");
+            prop_assert!(signed(&rings[1]) * exterior_sign < 0.0, "穴");
+        }
+    }
+
+    #[test]
+    fn rounding_keeps_rings_closed(
+        // 丸めるとほとんどの頂点が重なる小さなリング
+        (_, ring) in star_ring_in(-10.0..10.0, -10.0..10.0, 0.01),
+        precision in 0u32..4,
+    ) {
+        let mut value = Value::Polygon(vec![positions(&ring)]);
+        GeometryTransform {
+            precision: Some(precision),
+            ..GeometryTransform::default()
+        }
+        .apply(&mut value);
+        let Value::Polygon(rings) = value else {
+            unreachable!()
+        };
+        let ring = &rings[0];
+        prop_assert!(ring.len() >= 4, "{} 点", ring.len());
+        prop_assert_eq!(ring.first(), ring.last());
+        let scale = 10f64.powi(precision as i32);
+        for x in ring.iter().flatten() {
+            prop_assert_eq!((x * scale).round() / scale, *x);
+        }
+    }
+
+    #[test]
+    fn dedup_removes_copies_with_shifted_start(
+        rings in prop::collection::vec(
+            (star_ring_in(0.0..100.0, 0.0..100.0, 1.0), prop::option::of(any::<Index>())),
+            1..20,
+        ),
+    ) {
+        let mut features = Vec::new();
+        let mut copies = 0;
+        for ((_, ring), shift) in &rings {
+            features.push(polygon_feature(std::slice::from_ref(ring)));
+            // 始点をずらしただけの同じリングは重複とみなされる
+            if let Some(shift) = shift {
+                features.push(polygon_feature(&[rotate(ring, *shift)]));
+                copies += 1;
+            }
+        }
+        let collection = FeatureCollection {
+            bbox: None,
+            features,
+            foreign_members: None,
+        };
+        let (kept, duplicates) = dedup::dedup(collection, None, "N03_004");
+        prop_assert_eq!(duplicates.len(), copies);
+        prop_assert_eq!(kept.features.len(), rings.len());
+    }
+
+    #[test]
+    fn albers_area_matches_geodesic_area(
+        // 日本の範囲
+        (_, ring) in star_ring_in(128.0..146.0, 26.0..45.0, 0.1),
+    ) {
+        let feature = polygon_feature(&[ring]);
+        let projected =
+            aggregate::feature_area(&feature, Metric::Projected(Projection::Jgd2011Albers));
+        let geodesic = aggregate::feature_area(&feature, Metric::GeodesicArea);
+        // 正積なので、辺を投影面上の直線とみなす違い（頂点の少ないリングで 1e-5 程度）だけが残る
+        prop_assert!(
+            (projected - geodesic).abs() <= 1e-4 * geodesic,
+            "{} != {}",
+            projected,
+            geodesic
+        );
+    }
+
+    #[test]
+    fn laea_auto_area_matches_geodesic_area_anywhere(
+        (_, ring) in star_ring_in(-179.0..179.0, -85.0..85.0, 0.1),
+    ) {
+        let feature = polygon_feature(&[ring]);
+        let projected = aggregate::feature_area(&feature, Metric::Projected(Projection::LaeaAuto));
+        let geodesic = aggregate::feature_area(&feature, Metric::GeodesicArea);
+        prop_assert!(
+            (projected - geodesic).abs() <= 1e-4 * geodesic,
+            "{} != {}",
+            projected,
+            geodesic
+        );
+    }
+
+    #[test]
+    fn utm_auto_area_is_within_scale_error(
+        (_, ring) in star_ring_in(-179.0..179.0, -80.0..80.0, 0.1),
+    ) {
+        let feature = polygon_feature(&[ring]);
+        let projected = aggregate::feature_area(&feature, Metric::Projected(Projection::UtmAuto));
+        let geodesic = aggregate::feature_area(&feature, Metric::GeodesicArea);
+        // 縮尺係数は 0.9996 から（ゾーンの端の）約 1.001 まで
+        let ratio = projected / geodesic;
+        prop_assert!((0.999..=1.0025).contains(&ratio), "{}", ratio);
+    }
+}
+
+#[test]
+fn projection_names_round_trip() {
+    for name in [
+        "jgd2011-albers",
+        "utm-auto",
+        "laea-auto",
+        "utm-54",
+        "utm-19s",
+    ] {
+        assert_eq!(Projection::parse(name).unwrap().name(), name);
+    }
+    assert_eq!(
+        Projection::parse("utm-54n").unwrap(),
+        Projection::Utm {
+            zone: 54,
+            north: true
+        }
+    );
+    for name in ["utm-0", "utm-61", "utm-", "albers"] {
+        assert!(Projection::parse(name).is_err(), "{}", name);
+    }
+}
@@ -0,0 +1,157 @@
+//! 面積を持たないジオメトリ（LineString, Point など）の扱い。
+//! 面積だけを集計すると、線や点の Feature は面積 0 として数に入るだけで気付きにくい。
+//! 集計のときにジオメトリの種類ごとの Feature の数を数えて知らせ、
+//! `--by-geometry-type` を指定すれば、グループごとに種類別の数と線の長さも集計する。
+
+use crate::{aggregate::Metric, flat::FlatPolygons};
+
+/// Feature のジオメトリの種類（GeometryCollection は、ポリゴン、線、点の順に含むもので決める）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GeometryType {
+    /// Polygon / MultiPolygon
+    Polygon,
+    /// LineString / MultiLineString
+    Line,
+    /// Point / MultiPoint
+    Point,
+    /// ジオメトリがないか、空のもの
+    Empty,
+}
+
+impl GeometryType {
+    /// 読み込んだジオメトリの種類
+    pub(crate) fn of(flat: &FlatPolygons) -> GeometryType {
+        if flat.polygon_count() > 0 {
+            GeometryType::Polygon
+        } else if flat.line_count() > 0 {
+            GeometryType::Line
+        } else if flat.point_count() > 0 {
+            GeometryType::Point
+        } else {
+            GeometryType::Empty
+        }
+    }
+}
+
+/// ジオメトリの種類ごとの Feature の数
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TypeCounts {
+    pub polygons: usize,
+    pub lines: usize,
+    pub points: usize,
+    pub empty: usize,
+    /// 座標の足りない位置があり、集計しなかった Feature
+    pub invalid: usize,
+}
+
+impl TypeCounts {
+    pub(crate) fn add(&mut self, geometry_type: GeometryType) {
+        match geometry_type {
+            GeometryType::Polygon => self.polygons += 1,
+            GeometryType::Line => self.lines += 1,
+            GeometryType::Point => self.points += 1,
+            GeometryType::Empty => self.empty += 1,
+        }
+    }
+
+    /// 別に数えた数を足す
+    pub fn merge(&mut self, other: TypeCounts) {
+        self.polygons += other.polygons;
+        self.lines += other.lines;
+        self.points += other.points;
+        self.empty += other.empty;
+        self.invalid += other.invalid;
+    }
+
+    /// 面積を持たない Feature の数
+    pub fn non_polygonal(&self) -> usize {
+        self.lines + self.points + self.empty
+    }
+
+    /// 面積を持たない Feature の内訳（例: "線 3 個, 点 5 個"）
+    pub fn describe(&self) -> String {
+        breakdown(&[
+            ("線", self.lines),
+            ("点", self.points),
+            ("ジオメトリなし", self.empty),
+        ])
+    }
+
+    /// 線を持たない Feature の数（`Metric::GeodesicLength` では長さが 0 になる）
+    pub fn non_linear(&self) -> usize {
+        self.polygons + self.points + self.empty
+    }
+
+    /// 線を持たない Feature の内訳（例: "ポリゴン 2 個, 点 5 個"）
+    pub fn describe_non_linear(&self) -> String {
+        breakdown(&[
+            ("ポリゴン", self.polygons),
+            ("点", self.points),
+            ("ジオメトリなし", self.empty),
+        ])
+    }
+}
+
+fn breakdown(counts: &[(&str, usize)]) -> String {
+    counts
+        .iter()
+        .filter(|&&(_, count)| count > 0)
+        .map(|(label, count)| format!("{} {} 個", label, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `--by-geometry-type` で `GroupResult::values` に加える列
+pub const COLUMNS: [&str; 4] = ["polygon_count", "line_count", "line_length", "point_count"];
+
+/// グループごとの種類別の集計
+#[derive(Clone, Debug, Default)]
+pub struct TypeTotals {
+    /// ポリゴンを持つ Feature の数
+    polygons: usize,
+    /// 線だけを持つ Feature の数
+    lines: usize,
+    /// 線の長さの合計（`Metric::Area` なら座標の単位、そうでなければ km）
+    length: f64,
+    /// 点の数（MultiPoint は点ごとに数える）
+    points: usize,
+}
+
+impl TypeTotals {
+    pub(crate) fn add(&mut self, flat: &FlatPolygons, metric: Metric) {
+        match GeometryType::of(flat) {
+            GeometryType::Polygon => self.polygons += 1,
+            GeometryType::Line => self.lines += 1,
+            GeometryType::Point | GeometryType::Empty => {}
+        }
+        self.length += match metric {
+            Metric::Area => flat.length(),
+            Metric::GeodesicArea | Metric::Projected(_) | Metric::GeodesicLength => {
+                flat.geodesic_length()
+            }
+        };
+        self.points += flat.point_count();
+    }
+
+    pub(crate) fn merge(&mut self, other: &TypeTotals) {
+        self.polygons += other.polygons;
+        self.lines += other.lines;
+        self.length += other.length;
+        self.points += other.points;
+    }
+
+    /// `COLUMNS` の順の値
+    pub fn values(&self) -> Vec<(String, Option<f64>)> {
+        let values = [
+            self.polygons as f64,
+            self.lines as f64,
+            self.length,
+            self.points as f64,
+        ];
+        COLUMNS
+            .iter()
+            .zip(values)
+            .map(|(column, value)| (column.to_string(), Some(value)))
+            .collect()
+    }
+}
@@ -0,0 +1,331 @@
+//! 面積を計算するバックエンドの選択（`--backend`）と、GPU で面積を計算するバックエンド。
+//!
+//! `gpu` フィーチャーを有効にすると、平らに並べた頂点（`flat.rs`）を wgpu のコンピュートシェーダーに渡し、
+//! 辺ごとの外積を GPU で並列に求める。リングごとの和と外周・穴の組み合わせは CPU で f64 で足し合わせる。
+//! WGSL の f64 はほとんどの GPU で使えないため、頂点はリングの最初の頂点を原点にずらしてから f32 にする
+//! （市町村程度の大きさのリングなら、CPU で求めた面積との差は相対値で 1e-6 程度）。
+//! 平面上の面積（`Metric::Area`）だけに対応し、ほかの集計値では使えない。
+
+use std::str::FromStr;
+
+/// 面積を計算するバックエンド
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Backend {
+    /// rayon で Feature ごとに並列に計算する
+    #[default]
+    Cpu,
+    /// wgpu のコンピュートシェーダーで辺ごとに計算する（`gpu` フィーチャー）
+    Gpu,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Backend, String> {
+        match name {
+            "cpu" => Ok(Backend::Cpu),
+            "gpu" if cfg!(feature = "gpu") => Ok(Backend::Gpu),
+            "gpu" => Err(
+                "GPU バックエンドを使うには gpu フィーチャーを有効にしてビルドしてください (cargo build --features gpu)"
+                    .to_string(),
+            ),
+            _ => Err(format!("不明なバックエンドです: {} (cpu, gpu)", name)),
+        }
+    }
+}
+
+#[cfg(feature = "gpu")]
+pub use kernel::{aggregate, Kernel, RingAreas};
+
+#[cfg(feature = "gpu")]
+mod kernel {
+    use crate::{
+        aggregate::{Aggregation, Extras, Metric, Table},
+        cancel::CancellationToken,
+        flat::FlatPolygons,
+        progress::Progress,
+    };
+    use geojson::Feature;
+    use rayon::prelude::*;
+    use std::sync::mpsc;
+    use wgpu::util::DeviceExt;
+
+    /// 辺ごとの外積を求めるシェーダー。`has_next[i]` が 0 の頂点はリングの最後（次の頂点がない）
+    const SHADER: &str = "
+@group(0) @binding(0) var<storage, read> coords: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read> has_next: array<u32>;
+@group(0) @binding(2) var<storage, read_write> products: array<f32>;
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if (i >= arrayLength(&products)) {
+        return;
+    }
+    if (has_next[i] == 0u) {
+        products[i] = 0.0;
+        return;
+    }
+    let a = coords[i];
+    let b = coords[i + 1u];
+    products[i] = a.x * b.y - b.x * a.y;
+}
+";
+
+    /// ワークグループの大きさ（シェーダーの `workgroup_size` と同じ）
+    const WORKGROUP: usize = 256;
+
+    /// 1 回のディスパッチで扱う頂点の数（座標のバッファが 32 MiB になる）
+    const DISPATCH_VERTICES: usize = 1 << 22;
+
+    /// 作成した GPU のデバイスとパイプライン
+    pub struct Kernel {
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        pipeline: wgpu::ComputePipeline,
+    }
+
+    /// Feature ごとの、リングの面積（`FlatPolygons::rings` の順）
+    #[derive(Default)]
+    pub struct RingAreas {
+        areas: Vec<f64>,
+        /// Feature ごとの `areas` の終わりの位置
+        ends: Vec<usize>,
+    }
+
+    impl RingAreas {
+        /// `index` 番目の Feature のリングの面積
+        pub fn feature(&self, index: usize) -> &[f64] {
+            let start = if index == 0 { 0 } else { self.ends[index - 1] };
+            &self.areas[start..self.ends[index]]
+        }
+    }
+
+    impl Kernel {
+        /// 既定のアダプターでデバイスを作る（GPU がなければエラー）
+        pub fn new() -> Result<Kernel, String> {
+            let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+            let adapter = pollster::block_on(
+                instance.request_adapter(&wgpu::RequestAdapterOptions::default()),
+            )
+            .map_err(|err| format!("GPU が見つかりません: {}", err))?;
+            let (device, queue) =
+                pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default()))
+                    .map_err(|err| format!("GPU のデバイスを作れません: {}", err))?;
+            let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("layon ring area"),
+                source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+            });
+            let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("layon ring area"),
+                layout: None,
+                module: &module,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+            Ok(Kernel {
+                device,
+                queue,
+                pipeline,
+            })
+        }
+
+        /// Feature ごとのリングの面積（向きによらず正の値）
+        pub fn ring_areas(&self, features: &[Feature]) -> Result<RingAreas, String> {
+            // リングの最初の頂点を原点にずらした頂点と、次の頂点があるか
+            let mut coords: Vec<[f32; 2]> = Vec::new();
+            let mut has_next: Vec<u32> = Vec::new();
+            // リングごとの `coords` の終わりの位置と、Feature ごとのリングの数の終わりの位置
+            let mut ring_ends = Vec::new();
+            let mut feature_ends = Vec::with_capacity(features.len());
+            let mut flat = FlatPolygons::default();
+            for feature in features {
+                if let Some(geometry) = &feature.geometry {
+                    flat.load(&geometry.value);
+                    for ring in flat.rings() {
+                        let Some(origin) = ring.first() else {
+                            ring_ends.push(coords.len());
+                            continue;
+                        };
+                        for (i, coord) in ring.iter().enumerate() {
+                            coords.push([(coord.x - origin.x) as f32, (coord.y - origin.y) as f32]);
+                            has_next.push(u32::from(i + 1 < ring.len()));
+                        }
+                        ring_ends.push(coords.len());
+                    }
+                }
+                feature_ends.push(ring_ends.len());
+            }
+
+            let mut products = Vec::with_capacity(coords.len());
+            for start in (0..coords.len()).step_by(DISPATCH_VERTICES) {
+                let end = (start + DISPATCH_VERTICES).min(coords.len());
+                // 最後の辺のために、次のまとまりの最初の頂点も渡す
+                let with_next = (end + 1).min(coords.len());
+                products.extend(self.products(&coords[start..with_next], &has_next[start..end])?);
+            }
+
+            let mut areas = Vec::with_capacity(ring_ends.len());
+            let mut start = 0;
+            for &end in &ring_ends {
+                let twice: f64 = products[start..end].iter().map(|&p| f64::from(p)).sum();
+                areas.push((twice / 2.0).abs());
+                start = end;
+            }
+            Ok(RingAreas {
+                areas,
+                ends: feature_ends,
+            })
+        }
+
+        /// 頂点ごとの、次の頂点との外積（`has_next` の数だけ）
+        fn products(&self, coords: &[[f32; 2]], has_next: &[u32]) -> Result<Vec<f32>, String> {
+            let device = &self.device;
+            let storage = |label, contents: &[u8]| {
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents,
+                    usage: wgpu::BufferUsages::STORAGE,
+                })
+            };
+            let coords = storage("coords", bytemuck::cast_slice(coords));
+            let next = storage("has_next", bytemuck::cast_slice(has_next));
+            let size = std::mem::size_of_val(has_next) as u64;
+            let output = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("products"),
+                size,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let readback = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("readback"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.pipeline.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: coords.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: next.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: output.as_entire_binding(),
+                    },
+                ],
+            });
+            let mut encoder = device.create_command_encoder(&Default::default());
+            {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(has_next.len().div_ceil(WORKGROUP) as u32, 1, 1);
+            }
+            encoder.copy_buffer_to_buffer(&output, 0, &readback, 0, size);
+            self.queue.submit([encoder.finish()]);
+
+            let slice = readback.slice(..);
+            let (sender, receiver) = mpsc::channel();
+            slice.map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+            device
+                .poll(wgpu::PollType::Wait)
+                .map_err(|err| format!("GPU の計算を待てません: {}", err))?;
+            receiver
+                .recv()
+                .map_err(|err| err.to_string())?
+                .map_err(|err| format!("GPU の計算結果を読めません: {}", err))?;
+            let products = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+            readback.unmap();
+            Ok(products)
+        }
+    }
+
+    /// `aggregate::aggregate_observed` と同じく集計し、面積は GPU で求める（中断はリングの面積を求めた後で確かめる）
+    pub fn aggregate(
+        kernel: &Kernel,
+        features: &[Feature],
+        group_by: &str,
+        extras: &Extras,
+        cancel: &CancellationToken,
+        progress: &Progress,
+    ) -> Result<Aggregation, String> {
+        let rings = kernel.ring_areas(features)?;
+        let table = Table::new(group_by, extras, Metric::Area);
+        let cancelled = cancel.check().err();
+        if cancelled.is_none() {
+            features.par_iter().enumerate().for_each_init(
+                FlatPolygons::default,
+                |flat, (position, feature)| {
+                    table.add_with(flat, position, feature, |flat| {
+                        flat.area_from(rings.feature(position))
+                    });
+                },
+            );
+            progress.advance(features.len());
+        }
+        let processed = if cancelled.is_some() {
+            0
+        } else {
+            features.len()
+        };
+        let (rows, types, timings) = table.finish();
+        Ok(Aggregation {
+            rows,
+            processed,
+            cancelled,
+            timings,
+            types,
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::aggregate;
+
+        #[test]
+        fn gpu_areas_match_the_cpu_areas() {
+            // GPU のない環境では確かめられない
+            let Ok(kernel) = Kernel::new() else {
+                return;
+            };
+            let collection: geojson::FeatureCollection =
+                include_str!("../tests/fixtures/n03_11_sample.geojson")
+                    .parse()
+                    .unwrap();
+            let rings = kernel.ring_areas(&collection.features).unwrap();
+            for (i, feature) in collection.features.iter().enumerate() {
+                let cpu = aggregate::feature_area(feature, Metric::Area);
+                let mut flat = FlatPolygons::default();
+                flat.load(&feature.geometry.as_ref().unwrap().value);
+                let gpu = flat.area_from(rings.feature(i));
+                assert!((gpu - cpu).abs() <= cpu * 1e-5, "{}: {} != {}", i, gpu, cpu);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_backend_names() {
+        assert_eq!("cpu".parse::<Backend>(), Ok(Backend::Cpu));
+        assert_eq!("gpu".parse::<Backend>().is_ok(), cfg!(feature = "gpu"));
+        assert!("tpu"
+            .parse::<Backend>()
+            .unwrap_err()
+            .contains("不明なバックエンド"));
+    }
+}
@@ -0,0 +1,218 @@
+//! H3 のセルごとの面積の集計（`layon h3`）。
+//!
+//! H3 を使う分析基盤に渡せるよう、Feature のポリゴンを指定した解像度の H3 のセル（h3o で求める）で覆い、
+//! 集計キー × セルの番号ごとに、セルとポリゴンの交差部分の面積を合計する。座標は経度・緯度であること。
+//!
+//! ポリゴンにかかるセルをすべて求め、境界にかかるセルだけを交差で切り取る
+//! （ポリゴンの内側に収まるセルはセル全体の面積にする）。
+
+use crate::{
+    aggregate::{self, to_multi_polygon, Metric},
+    flat::FlatPolygons,
+};
+use geo::{BooleanOps, Geometry, MultiPolygon};
+use geojson::{FeatureCollection, Value};
+use h3o::{
+    geom::{self, ContainmentMode, PolyfillConfig, ToCells, ToGeo},
+    CellIndex, Resolution,
+};
+use rayon::prelude::*;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// `--resolution` の既定値（セルの面積の平均が約 0.74 km²）
+pub const DEFAULT_RESOLUTION: Resolution = Resolution::Eight;
+
+/// 集計キー × セルごとの交差部分の面積
+pub struct H3Row {
+    pub city: String,
+    /// セルの番号（16 進数の文字列）
+    pub cell: String,
+    pub area: f64,
+}
+
+/// `0` から `15` の解像度
+pub fn parse_resolution(text: &str) -> Result<Resolution, String> {
+    text.parse::<u8>()
+        .ok()
+        .and_then(|resolution| Resolution::try_from(resolution).ok())
+        .ok_or_else(|| format!("H3 の解像度が不正です: {} (0 から 15)", text))
+}
+
+/// `group_by` × セルごとに、ポリゴンとセルの交差部分の面積を合計する（集計キー、セルの番号の順に並べる）。
+/// 経度・緯度として正しくない座標の Feature はエラーにする
+pub fn aggregate(
+    collection: &FeatureCollection,
+    group_by: &str,
+    resolution: Resolution,
+    metric: Metric,
+) -> Result<Vec<H3Row>, String> {
+    let areas = collection
+        .features
+        .par_iter()
+        .enumerate()
+        .try_fold(
+            HashMap::<(String, String), f64>::new,
+            |mut map, (i, feature)| -> Result<_, String> {
+                let Some(city) = feature
+                    .properties
+                    .as_ref()
+                    .and_then(|properties| properties.get(group_by))
+                    .and_then(|value| value.as_str())
+                else {
+                    return Ok(map);
+                };
+                let Some(polygons) = feature
+                    .geometry
+                    .as_ref()
+                    .and_then(|geometry| Geometry::<f64>::try_from(geometry.value.clone()).ok())
+                    .and_then(to_multi_polygon)
+                else {
+                    return Ok(map);
+                };
+                let cells = split(&polygons, resolution, metric)
+                    .map_err(|err| format!("{} 番目の Feature ({}): {}", i + 1, city, err))?;
+                for (cell, area) in cells {
+                    *map.entry((city.to_string(), cell)).or_insert(0.0) += area;
+                }
+                Ok(map)
+            },
+        )
+        .try_reduce(HashMap::new, |mut a, b| {
+            for (key, area) in b {
+                *a.entry(key).or_insert(0.0) += area;
+            }
+            Ok(a)
+        })?;
+
+    let mut rows: Vec<H3Row> = areas
+        .into_iter()
+        .map(|((city, cell), area)| H3Row { city, cell, area })
+        .collect();
+    rows.sort_by(|a, b| a.city.cmp(&b.city).then_with(|| a.cell.cmp(&b.cell)));
+    Ok(rows)
+}
+
+/// ポリゴンをセルで切り分け、セルごとの面積を返す（面積が 0 のセルは含めない）
+fn split(
+    polygons: &MultiPolygon<f64>,
+    resolution: Resolution,
+    metric: Metric,
+) -> Result<Vec<(String, f64)>, String> {
+    let config = PolyfillConfig::new(resolution);
+    let mut covering = BTreeSet::<CellIndex>::new();
+    let mut inside = HashSet::<CellIndex>::new();
+    for polygon in polygons {
+        let polygon =
+            geom::Polygon::from_degrees(polygon.clone()).map_err(|err| err.to_string())?;
+        covering.extend(polygon.to_cells(config.containment_mode(ContainmentMode::Covers)));
+        inside.extend(polygon.to_cells(config.containment_mode(ContainmentMode::ContainsBoundary)));
+    }
+
+    let mut cells = Vec::new();
+    let mut flat = FlatPolygons::default();
+    for cell in covering {
+        let boundary = MultiPolygon::new(vec![cell.to_geom(true).expect("セルの境界は必ず求まる")]);
+        let piece = if inside.contains(&cell) {
+            boundary
+        } else {
+            polygons.intersection(&boundary)
+        };
+        if piece.0.is_empty() {
+            continue;
+        }
+        flat.load(&Value::from(&piece));
+        let area = aggregate::measure(&flat, metric);
+        if area > 0.0 {
+            cells.push((cell.to_string(), area));
+        }
+    }
+    Ok(cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pipeline::Pipeline,
+        source::{Input, InputOptions},
+    };
+    use geo::{polygon, Area};
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    /// 点 (経度, 緯度) を含むセルの番号
+    fn cell_at(resolution: Resolution, lon: f64, lat: f64) -> Result<String, String> {
+        h3o::LatLng::new(lat, lon)
+            .map(|point| point.to_cell(resolution).to_string())
+            .map_err(|err| format!("経度・緯度が不正です ({}, {}): {}", lon, lat, err))
+    }
+
+    #[test]
+    fn cells_cover_the_polygon_without_gaps_or_overlaps() {
+        // 東京駅のあたりの約 2 km 四方
+        let square = polygon![
+            (x: 139.757, y: 35.672),
+            (x: 139.779, y: 35.672),
+            (x: 139.779, y: 35.690),
+            (x: 139.757, y: 35.690),
+            (x: 139.757, y: 35.672),
+        ];
+        let cells = split(
+            &MultiPolygon::new(vec![square.clone()]),
+            Resolution::Nine,
+            Metric::Area,
+        )
+        .unwrap();
+        assert!(cells.len() > 10, "{}", cells.len());
+        let total: f64 = cells.iter().map(|(_, area)| area).sum();
+        assert!(
+            (total - square.unsigned_area()).abs() < 1e-9 * total,
+            "{}",
+            total
+        );
+        assert!(cells
+            .iter()
+            .all(|(cell, _)| cell.len() == 15 && cell.starts_with("89")));
+        assert_eq!(
+            cell_at(Resolution::Nine, 139.7671, 35.6812).unwrap().len(),
+            15
+        );
+        assert!(parse_resolution("16").is_err());
+    }
+
+    #[test]
+    fn cells_cover_each_city_exactly_once() {
+        let collection = Input::parse(FIXTURE, InputOptions::default())
+            .unwrap()
+            .read()
+            .unwrap();
+        let cities = Pipeline::read(FIXTURE)
+            .metric(Metric::GeodesicArea)
+            .run()
+            .unwrap()
+            .rows;
+        let resolution = parse_resolution("7").unwrap();
+        let rows = aggregate(&collection, "N03_004", resolution, Metric::GeodesicArea).unwrap();
+        assert!(rows
+            .iter()
+            .all(|row| row.cell.starts_with("87") && row.cell.len() == 15));
+        for city in &cities {
+            let cells: Vec<_> = rows.iter().filter(|row| row.city == city.key).collect();
+            let total: f64 = cells.iter().map(|row| row.area).sum();
+            assert!(
+                (total - city.area).abs() < city.area * 1e-6,
+                "{}: {} != {}",
+                city.key,
+                total,
+                city.area
+            );
+            // 同じ市町村のセルは 1 行にまとめる
+            let mut unique: Vec<_> = cells.iter().map(|row| &row.cell).collect();
+            unique.dedup();
+            assert_eq!(unique.len(), cells.len());
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! 都道府県 → 市区町村 → 区の包含関係のグラフ（`layon hierarchy`）。
+//!
+//! 階層は属性（既定: N03_001, N03_004, N03_005）の値から作る。Feature ごとに、値のある（null や空でない）
+//! 階層のプロパティを上から順にたどった経路が節点になり（例: 埼玉県 → さいたま市 → 西区）、隣り合う節点を辺でつなぐ。
+//! 節点の ID は経路を `/` でつないだもの（別の都道府県にある同じ名前の市を区別する）。
+//!
+//! 都道府県と市区町村のポリゴンが同じファイルにある場合など、節点そのものを表す Feature（その節点が経路の最後になる Feature）
+//! があれば、その面積を節点の面積にし、子の節点の代表点が親の Feature のポリゴンに含まれるかを幾何的に確かめる。
+//! 節点そのものを表す Feature がなければ、面積は子の節点の合計にする（包含は属性から決まるので確かめない）。
+
+use crate::aggregate::{self, to_multi_polygon, Metric};
+use geo::{Contains, Geometry, InteriorPoint, MultiPolygon, Point};
+use geojson::{FeatureCollection, JsonValue};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// 既定の階層のプロパティ（都道府県名、市区町村名、政令指定都市の区名）
+pub const DEFAULT_LEVELS: [&str; 3] = ["N03_001", "N03_004", "N03_005"];
+
+/// グラフの節点
+pub struct Node {
+    /// 経路を `/` でつないだ ID（例: "埼玉県/さいたま市/西区"）
+    pub id: String,
+    /// 節点の名前（プロパティの値）
+    pub name: String,
+    /// 階層のプロパティの名前
+    pub level: String,
+    /// 面積（単位は集計した `Metric` による）
+    pub area: f64,
+    /// 面積に含めた Feature の数
+    pub count: usize,
+}
+
+/// 親から子への包含の辺（`Hierarchy::nodes` の位置）
+pub struct Edge {
+    pub parent: usize,
+    pub child: usize,
+    /// 子の代表点が親の Feature のポリゴンに含まれるか（親そのものを表す Feature がなければ None）
+    pub contained: Option<bool>,
+}
+
+/// 包含関係のグラフ（節点は親が子より前に並ぶ）
+pub struct Hierarchy {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    /// どの階層のプロパティにも値がなく、グラフに含めなかった Feature の数
+    pub skipped: usize,
+}
+
+impl Hierarchy {
+    /// 代表点が親のポリゴンに含まれなかった辺の数
+    pub fn uncontained(&self) -> usize {
+        self.edges
+            .iter()
+            .filter(|edge| edge.contained == Some(false))
+            .count()
+    }
+}
+
+/// `levels` のプロパティ（上の階層から順）の値から包含関係のグラフを作る
+pub fn build(collection: &FeatureCollection, levels: &[String], metric: Metric) -> Hierarchy {
+    let areas: Vec<f64> = collection
+        .features
+        .par_iter()
+        .map(|feature| aggregate::feature_area(feature, metric))
+        .collect();
+
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut parents: Vec<Option<usize>> = Vec::new();
+    // 節点そのものを表す Feature と、節点の下にある最初の Feature（代表点を取る）
+    let mut own: Vec<Vec<usize>> = Vec::new();
+    let mut first: Vec<usize> = Vec::new();
+    let mut skipped = 0;
+    for (i, feature) in collection.features.iter().enumerate() {
+        let mut id = String::new();
+        let mut parent = None;
+        for level in levels {
+            let Some(name) = feature
+                .property(level)
+                .and_then(JsonValue::as_str)
+                .filter(|name| !name.is_empty())
+            else {
+                continue;
+            };
+            if !id.is_empty() {
+                id.push('/');
+            }
+            id.push_str(name);
+            let node = *index.entry(id.clone()).or_insert_with(|| {
+                nodes.push(Node {
+                    id: id.clone(),
+                    name: name.to_string(),
+                    level: level.clone(),
+                    area: 0.0,
+                    count: 0,
+                });
+                parents.push(parent);
+                own.push(Vec::new());
+                first.push(i);
+                nodes.len() - 1
+            });
+            parent = Some(node);
+        }
+        match parent {
+            Some(node) => own[node].push(i),
+            None => skipped += 1,
+        }
+    }
+
+    // 子は親より後ろに並ぶので、後ろから子の面積を親に足していく
+    let mut children_area = vec![0.0; nodes.len()];
+    let mut children_count = vec![0; nodes.len()];
+    for node in (0..nodes.len()).rev() {
+        let (area, count) = if own[node].is_empty() {
+            (children_area[node], children_count[node])
+        } else {
+            (own[node].iter().map(|&i| areas[i]).sum(), own[node].len())
+        };
+        nodes[node].area = area;
+        nodes[node].count = count;
+        if let Some(parent) = parents[node] {
+            children_area[parent] += area;
+            children_count[parent] += count;
+        }
+    }
+
+    let polygons = |i: usize| -> Option<MultiPolygon<f64>> {
+        let geometry: Geometry<f64> = collection.features[i]
+            .geometry
+            .as_ref()?
+            .value
+            .clone()
+            .try_into()
+            .ok()?;
+        to_multi_polygon(geometry)
+    };
+    // 子を持ち、節点そのものを表す Feature がある節点のポリゴン（辺ごとに変換し直さないよう先に変換しておく）
+    let mut has_children = vec![false; nodes.len()];
+    for parent in parents.iter().flatten() {
+        has_children[*parent] = true;
+    }
+    let shapes: Vec<Option<Vec<MultiPolygon<f64>>>> = (0..nodes.len())
+        .into_par_iter()
+        .map(|node| {
+            (has_children[node] && !own[node].is_empty())
+                .then(|| own[node].iter().filter_map(|&i| polygons(i)).collect())
+        })
+        .collect();
+    let edges = parents
+        .par_iter()
+        .enumerate()
+        .filter_map(|(child, parent)| {
+            let parent = (*parent)?;
+            let contained = shapes[parent].as_ref().map(|shapes| {
+                let point: Option<Point<f64>> =
+                    polygons(first[child]).and_then(|p| p.interior_point());
+                point.is_some_and(|point| shapes.iter().any(|shape| shape.contains(&point)))
+            });
+            Some(Edge {
+                parent,
+                child,
+                contained,
+            })
+        })
+        .collect();
+
+    Hierarchy {
+        nodes,
+        edges,
+        skipped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pipeline::Pipeline,
+        source::{Input, InputOptions},
+    };
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    #[test]
+    fn links_levels_and_checks_containment_against_parent_features() {
+        let mut collection = Input::parse(FIXTURE, InputOptions::default())
+            .unwrap()
+            .read()
+            .unwrap();
+        let levels: Vec<String> = ["N03_001", "N03_003", "N03_004"].map(String::from).into();
+        let hierarchy = build(&collection, &levels, Metric::Area);
+        // 県 1、郡 3、市町村 8
+        assert_eq!(hierarchy.nodes.len(), 12);
+        assert_eq!(hierarchy.edges.len(), 11);
+        assert_eq!(hierarchy.uncontained(), 0);
+        let node = |id: &str| hierarchy.nodes.iter().find(|node| node.id == id).unwrap();
+        assert_eq!(node("埼玉県/秩父郡").count, 2);
+        assert_eq!(node("埼玉県/秩父郡/皆野町").level, "N03_004");
+        let total: f64 =
+            Pipeline::from_input(Input::parse(FIXTURE, InputOptions::default()).unwrap())
+                .run()
+                .unwrap()
+                .rows
+                .iter()
+                .map(|row| row.area)
+                .sum();
+        assert!((node("埼玉県").area - total).abs() < 1e-12);
+
+        // 県そのものを表す Feature があれば、その面積を使い、市町村の代表点が県のポリゴンに含まれるかを確かめる
+        let mut prefecture: geojson::Feature = serde_json::from_value(serde_json::json!({
+            "type": "Feature",
+            "properties": {"N03_001": "埼玉県", "N03_003": null, "N03_004": null},
+            "geometry": {"type": "Polygon", "coordinates": [[[139.0, 35.9], [139.3, 35.9], [139.3, 36.3], [139.0, 36.3], [139.0, 35.9]]]}
+        }))
+        .unwrap();
+        collection.features.insert(0, prefecture.clone());
+        prefecture.set_property("N03_001", "東京都");
+        collection.features.push(prefecture);
+        let hierarchy = build(&collection, &levels, Metric::Area);
+        let node = |id: &str| hierarchy.nodes.iter().find(|node| node.id == id).unwrap();
+        assert_eq!(node("埼玉県").count, 1);
+        assert!((node("埼玉県").area - 0.12).abs() < 1e-9);
+        let outside: Vec<&str> = hierarchy
+            .edges
+            .iter()
+            .filter(|edge| edge.contained == Some(false))
+            .map(|edge| hierarchy.nodes[edge.child].id.as_str())
+            .collect();
+        assert_eq!(
+            outside,
+            [
+                "埼玉県/東松山市",
+                "埼玉県/鴻巣市",
+                "埼玉県/上尾市",
+                "埼玉県/入間郡",
+                "埼玉県/比企郡"
+            ]
+        );
+        // 郡そのものを表す Feature はないので、郡から町への辺は確かめない
+        assert!(hierarchy
+            .edges
+            .iter()
+            .filter(|edge| hierarchy.nodes[edge.parent].level == "N03_003")
+            .all(|edge| edge.contained.is_none()));
+        assert_eq!(node("東京都").count, 1);
+    }
+}
@@ -0,0 +1,148 @@
+//! Feature ごとの外周と穴の面積の内訳（`--ring-report`）。
+//! 湖などを穴として持つ市町村では、集計した面積が公表値や想定と食い違うことがある。
+//! 外周の面積、穴の数と面積、差し引いた正味の面積を Feature ごとに並べ、どの Feature で差が出たかを調べられるようにする。
+
+use crate::{aggregate::Metric, area, flat::FlatPolygons, ids};
+use geojson::FeatureCollection;
+use rayon::prelude::*;
+
+/// 1 つの Feature の内訳（面積の単位は `Metric` による）
+pub struct RingArea {
+    /// Feature の ID（なければ入力の中での位置 `#0`, `#1`, …）
+    pub id: String,
+    /// 集計キーの値（なければ空）
+    pub key: String,
+    /// ポリゴンの数
+    pub polygons: usize,
+    /// 穴の数
+    pub holes: usize,
+    /// 外周の面積の合計
+    pub exterior: f64,
+    /// 穴の面積の合計
+    pub hole_area: f64,
+}
+
+impl RingArea {
+    /// 外周から穴を差し引いた面積（集計に使う面積と同じ）
+    pub fn net(&self) -> f64 {
+        self.exterior - self.hole_area
+    }
+}
+
+/// ポリゴンを持つ Feature ごとの内訳（入力の順）
+pub fn report(collection: &FeatureCollection, group_by: &str, metric: Metric) -> Vec<RingArea> {
+    collection
+        .features
+        .par_iter()
+        .enumerate()
+        .filter_map(|(position, feature)| {
+            let mut flat = FlatPolygons::default();
+            flat.load(&feature.geometry.as_ref()?.value);
+            let polygons = match metric {
+                Metric::Area => flat.ring_areas(area::ring_area),
+                // 線の長さを集計する場合も、リングの面積は楕円体上で求める
+                Metric::GeodesicArea | Metric::GeodesicLength => {
+                    flat.ring_areas(|ring| area::geodesic_ring_area(ring) / 1e6)
+                }
+                Metric::Projected(projection) => {
+                    let projector = projection.at(flat.centroid()?);
+                    flat.ring_areas(|ring| projector.ring_area(ring) / 1e6)
+                }
+            };
+            if polygons.is_empty() {
+                return None;
+            }
+            Some(RingArea {
+                id: ids::text_or_position(feature, position),
+                key: feature
+                    .property(group_by)
+                    .and_then(|value| value.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                polygons: polygons.len(),
+                holes: polygons.iter().map(|&(_, holes, _)| holes).sum(),
+                exterior: polygons.iter().map(|&(exterior, _, _)| exterior).sum(),
+                hole_area: polygons.iter().map(|&(_, _, area)| area).sum(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aggregate, pipeline::Pipeline};
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    #[test]
+    fn exterior_hole_and_net_areas_per_feature() {
+        let collection: FeatureCollection = r#"{"type":"FeatureCollection","features":[
+            {"type":"Feature","id":"lake","properties":{"N03_004":"川越市"},"geometry":{"type":"MultiPolygon","coordinates":[
+                [[[0,0],[4,0],[4,4],[0,4],[0,0]],[[1,1],[1,2],[2,2],[2,1],[1,1]],[[1,3],[3,3],[3,2.5],[1,2.5],[1,3]]],
+                [[[10,0],[11,0],[11,1],[10,1],[10,0]]]
+            ]}},
+            {"type":"Feature","properties":{"N03_004":"所沢市"},"geometry":{"type":"Point","coordinates":[0,0]}},
+            {"type":"Feature","properties":{},"geometry":{"type":"Polygon","coordinates":[[[0,0],[0,1],[2,1],[2,0],[0,0]]]}}
+        ]}"#
+        .parse()
+        .unwrap();
+        let rows = report(&collection, "N03_004", Metric::Area);
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|row| {
+                (
+                    row.id.as_str(),
+                    row.key.as_str(),
+                    row.polygons,
+                    row.holes,
+                    row.exterior,
+                    row.hole_area,
+                    row.net(),
+                )
+            })
+            .collect();
+        // ポリゴンのない Feature は載せず、向きによらず面積は正の値
+        assert_eq!(
+            rows,
+            [
+                ("lake", "川越市", 2, 2, 17.0, 2.0, 15.0),
+                ("#2", "", 1, 0, 2.0, 0.0, 2.0),
+            ]
+        );
+        assert_eq!(
+            aggregate::feature_area(&collection.features[0], Metric::Area),
+            15.0
+        );
+    }
+
+    #[test]
+    fn net_areas_add_up_to_the_aggregated_areas() {
+        let collection: FeatureCollection =
+            std::fs::read_to_string(FIXTURE).unwrap().parse().unwrap();
+        let rows = report(&collection, "N03_004", Metric::GeodesicArea);
+        assert_eq!(rows.len(), collection.features.len());
+        let aggregated = Pipeline::read(FIXTURE)
+            .metric(Metric::GeodesicArea)
+            .run()
+            .unwrap()
+            .rows;
+        for city in &aggregated {
+            let net: f64 = rows
+                .iter()
+                .filter(|row| row.key == city.key)
+                .map(RingArea::net)
+                .sum();
+            assert!(
+                (net - city.area).abs() <= 1e-9 * city.area,
+                "{}: {} != {}",
+                city.key,
+                net,
+                city.area
+            );
+        }
+    }
+}
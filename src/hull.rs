@@ -0,0 +1,261 @@
+//! グループごとの凸包と凹包（アルファシェイプ）（`hull` サブコマンド）。
+//!
+//! 島の多い町村や飛び地のある市町村は、面積に比べて広い範囲に散らばっている。グループのポリゴンを包む形を求め、
+//! その面積と、包む形のうちポリゴンが占める割合（充填率）で散らばり方を表す。
+//! 凸包は頂点をすべて含む最小の凸多角形で、島と島の間の海も含む。凹包は頂点のドロネー三角形分割のうち、
+//! 外接円の半径が `alpha` 以下の三角形だけを残したもの（アルファシェイプ）で、離れた島は別の部分になる。
+
+use crate::{
+    aggregate::{self, to_multi_polygon, Metric},
+    area::ring_area,
+    flat::FlatPolygons,
+};
+use geo::{
+    Contains, ConvexHull, Coord, Geometry, InteriorPoint, LineString, MultiPolygon, Polygon,
+    Triangle, TriangulateSpade, Winding,
+};
+use geojson::{FeatureCollection, JsonValue, Value};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// 包む形の種類
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hull {
+    Convex,
+    /// 外接円の半径が `alpha`（座標の単位）以下の三角形を残すアルファシェイプ
+    Concave {
+        alpha: f64,
+    },
+}
+
+/// 1 つのグループの結果（面積の単位は `Metric` による）
+pub struct HullRow {
+    pub name: String,
+    /// ポリゴンの面積
+    pub area: f64,
+    /// 包む形の面積
+    pub hull_area: f64,
+    /// 包む形（`geometry` を指定した場合のみ）
+    pub geometry: Option<MultiPolygon<f64>>,
+}
+
+impl HullRow {
+    /// 包む形の面積のうちポリゴンが占める割合（包む形の面積が 0 なら None）。
+    /// 凹包は頂点だけから作るため、ポリゴンの縁の一部がはみ出して 1 を超えることがある
+    pub fn fill_ratio(&self) -> Option<f64> {
+        (self.hull_area > 0.0).then(|| self.area / self.hull_area)
+    }
+}
+
+/// `group_by` のプロパティごとに包む形の面積を求める（名前の順）。
+/// `geometry` を指定すると包む形そのものも返す（凹包では三角形を結合するため時間がかかる）
+pub fn hulls(
+    collection: &FeatureCollection,
+    group_by: &str,
+    hull: Hull,
+    metric: Metric,
+    geometry: bool,
+) -> Result<Vec<HullRow>, String> {
+    let mut groups: HashMap<&str, Vec<Polygon<f64>>> = HashMap::new();
+    for feature in &collection.features {
+        let Some(name) = feature.property(group_by).and_then(JsonValue::as_str) else {
+            continue;
+        };
+        let polygons = feature
+            .geometry
+            .as_ref()
+            .and_then(|geometry| Geometry::<f64>::try_from(geometry.value.clone()).ok())
+            .and_then(to_multi_polygon);
+        if let Some(polygons) = polygons {
+            groups.entry(name).or_default().extend(polygons);
+        }
+    }
+
+    let mut rows = groups
+        .into_par_iter()
+        .map(|(name, polygons)| {
+            let polygons = MultiPolygon::new(polygons);
+            let area = measure(&polygons, metric);
+            let (hull_area, shape) = match hull {
+                Hull::Convex => {
+                    let shape = MultiPolygon::new(vec![polygons.convex_hull()]);
+                    (measure(&shape, metric), geometry.then_some(shape))
+                }
+                Hull::Concave { alpha } => {
+                    let triangles = alpha_triangles(&polygons, alpha)
+                        .map_err(|err| format!("{} の三角形分割に失敗しました: {}", name, err))?;
+                    // 三角形分割の三角形は重ならないので、面積は三角形ごとの和になる
+                    let hull_area = triangles
+                        .par_iter()
+                        .map(|triangle| {
+                            measure(&MultiPolygon::new(vec![triangle.to_polygon()]), metric)
+                        })
+                        .sum();
+                    (hull_area, geometry.then(|| union(triangles)))
+                }
+            };
+            Ok(HullRow {
+                name: name.to_string(),
+                area,
+                hull_area,
+                geometry: shape,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    rows.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(rows)
+}
+
+/// 頂点のドロネー三角形分割のうち、外接円の半径が `alpha` 以下の三角形
+fn alpha_triangles(polygons: &MultiPolygon<f64>, alpha: f64) -> Result<Vec<Triangle<f64>>, String> {
+    let triangles = polygons
+        .unconstrained_triangulation()
+        .map_err(|err| err.to_string())?;
+    Ok(triangles
+        .into_iter()
+        .filter(|triangle| circumradius(triangle) <= alpha)
+        .collect())
+}
+
+/// 外接円の半径（3 辺の長さの積 / (4 × 面積)。潰れた三角形は無限大）
+fn circumradius(triangle: &Triangle<f64>) -> f64 {
+    let [a, b, c] = triangle.to_array();
+    let (ab, bc, ca) = (
+        (b.x - a.x).hypot(b.y - a.y),
+        (c.x - b.x).hypot(c.y - b.y),
+        (a.x - c.x).hypot(a.y - c.y),
+    );
+    let area = ((b.x - a.x) * (c.y - a.y) - (c.x - a.x) * (b.y - a.y)).abs() / 2.0;
+    if area > 0.0 {
+        ab * bc * ca / (4.0 * area)
+    } else {
+        f64::INFINITY
+    }
+}
+
+/// 向きをそろえた三角形を結合する。2 つの三角形が共有する辺は内側の辺なので除き、残った辺をつないでリングにする
+/// （三角形の数が多いと union を繰り返すのは遅いため）
+fn union(triangles: Vec<Triangle<f64>>) -> MultiPolygon<f64> {
+    type Vertex = (u64, u64);
+    let vertex = |c: Coord<f64>| ((c.x + 0.0).to_bits(), (c.y + 0.0).to_bits());
+    let coord = |(x, y): Vertex| Coord {
+        x: f64::from_bits(x),
+        y: f64::from_bits(y),
+    };
+
+    let mut edges = HashSet::<(Vertex, Vertex)>::new();
+    for triangle in &triangles {
+        let [a, b, c] = triangle.to_array();
+        // 反時計回りにそろえる
+        let [a, b, c] = if (b.x - a.x) * (c.y - a.y) - (c.x - a.x) * (b.y - a.y) < 0.0 {
+            [a, c, b]
+        } else {
+            [a, b, c]
+        };
+        for (from, to) in [(a, b), (b, c), (c, a)] {
+            let (from, to) = (vertex(from), vertex(to));
+            if !edges.remove(&(to, from)) {
+                edges.insert((from, to));
+            }
+        }
+    }
+    let mut next = HashMap::<Vertex, Vec<Vertex>>::new();
+    for &(from, to) in &edges {
+        next.entry(from).or_default().push(to);
+    }
+
+    // 反時計回りのリングが外周、時計回りのリングが穴
+    let mut exteriors = Vec::new();
+    let mut holes = Vec::new();
+    while let Some(start) = next.keys().next().copied() {
+        let mut ring = vec![coord(start)];
+        let mut current = start;
+        while let Some(targets) = next.get_mut(&current) {
+            let to = targets.pop().unwrap_or(start);
+            if targets.is_empty() {
+                next.remove(&current);
+            }
+            ring.push(coord(to));
+            current = to;
+            if current == start {
+                break;
+            }
+        }
+        let ring = LineString::new(ring);
+        if ring.0.len() < 4 || !ring.is_closed() {
+            continue;
+        }
+        if ring.is_ccw() {
+            exteriors.push(Polygon::new(ring, vec![]));
+        } else {
+            holes.push(ring);
+        }
+    }
+    for hole in holes {
+        let Some(point) = Polygon::new(hole.clone(), vec![]).interior_point() else {
+            continue;
+        };
+        // 穴を囲む外周のうち最も小さいもの
+        let exterior = exteriors
+            .iter_mut()
+            .filter(|polygon| Polygon::new(polygon.exterior().clone(), vec![]).contains(&point))
+            .min_by(|a, b| {
+                let area = |polygon: &Polygon<f64>| ring_area(&polygon.exterior().0);
+                area(a).total_cmp(&area(b))
+            });
+        if let Some(exterior) = exterior {
+            exterior.interiors_push(hole);
+        }
+    }
+    MultiPolygon::new(exteriors)
+}
+
+fn measure(polygons: &MultiPolygon<f64>, metric: Metric) -> f64 {
+    let mut flat = FlatPolygons::default();
+    flat.load(&Value::from(polygons));
+    aggregate::measure(&flat, metric)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::Area;
+
+    #[test]
+    fn hulls_measure_how_much_of_the_hull_the_polygons_fill() {
+        let square = |west: f64| {
+            format!(
+                r#"{{"type":"Feature","properties":{{"N03_004":"A"}},"geometry":{{"type":"Polygon","coordinates":[[[{w},0],[{e},0],[{e},1],[{w},1],[{w},0]]]}}}}"#,
+                w = west,
+                e = west + 1.0
+            )
+        };
+        // 離れた 2 つの島
+        let collection: FeatureCollection = format!(
+            r#"{{"type":"FeatureCollection","features":[{},{}]}}"#,
+            square(0.0),
+            square(3.0)
+        )
+        .parse()
+        .unwrap();
+
+        let convex = hulls(&collection, "N03_004", Hull::Convex, Metric::Area, true).unwrap();
+        assert_eq!(convex.len(), 1);
+        assert_eq!((convex[0].area, convex[0].hull_area), (2.0, 4.0));
+        assert_eq!(convex[0].fill_ratio(), Some(0.5));
+
+        // 島の中の三角形の外接円の半径は √2 / 2、島の間をつなぐ三角形は √5 / 2 なので、島ごとに分かれる
+        let concave = hulls(
+            &collection,
+            "N03_004",
+            Hull::Concave { alpha: 1.0 },
+            Metric::Area,
+            true,
+        )
+        .unwrap();
+        assert!((concave[0].hull_area - 2.0).abs() < 1e-12);
+        let geometry = concave[0].geometry.as_ref().unwrap();
+        assert_eq!(geometry.0.len(), 2);
+        assert!((geometry.unsigned_area() - 2.0).abs() < 1e-12);
+    }
+}
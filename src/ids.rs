@@ -0,0 +1,49 @@
+//! Feature の ID（GeoJSON の `id`）。
+//! 集計結果を監査できるように、どの Feature がどのグループに入ったか、どの Feature を取り除いたかを ID で追う。
+//! ID のない Feature には、入力の中での位置から作った ID（`#0`, `#1`, …）を付ける。
+//! 位置は絞り込みの前に数えるので、同じ入力なら条件式を変えても同じ ID になる。
+
+use geojson::{feature::Id, Feature, FeatureCollection, JsonValue};
+
+/// ID のない Feature に位置から作った ID を付け、付けた数を返す
+pub fn assign(collection: &mut FeatureCollection) -> usize {
+    assign_from(&mut collection.features, 0)
+}
+
+/// 入力の `start` 番目から続く Feature に `assign` と同じ ID を付ける（まとまりごとに読み込む場合）
+pub fn assign_from(features: &mut [Feature], start: usize) -> usize {
+    let mut assigned = 0;
+    for (i, feature) in features.iter_mut().enumerate() {
+        if feature.id.is_none() {
+            feature.id = Some(Id::String(generated(start + i)));
+            assigned += 1;
+        }
+    }
+    assigned
+}
+
+/// Feature の ID を文字列にしたもの（なければ None）
+pub fn text(feature: &Feature) -> Option<String> {
+    match feature.id.as_ref()? {
+        Id::String(id) => Some(id.clone()),
+        Id::Number(id) => Some(id.to_string()),
+    }
+}
+
+/// Feature の ID。なければ位置から作る
+pub fn text_or_position(feature: &Feature, position: usize) -> String {
+    text(feature).unwrap_or_else(|| generated(position))
+}
+
+/// 解析する前の Feature の JSON から ID を取り出す（解析できない Feature のエラーに添える）
+pub fn from_json(text: &str) -> Option<String> {
+    match serde_json::from_str::<JsonValue>(text).ok()?.get("id")? {
+        JsonValue::String(id) => Some(id.clone()),
+        JsonValue::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+fn generated(position: usize) -> String {
+    format!("#{}", position)
+}
@@ -0,0 +1,194 @@
+//! zlib (RFC 1950) と gzip (RFC 1952) の展開（flate2 を使う）と、zstd (RFC 8878) の展開（zstd を使う）。
+//!
+//! OSM PBF のブロックや MBTiles のタイルなど、圧縮されたデータを読むために使う。
+//!
+//! DEFLATE のストリームは途中から展開し始められないため、1 つのストリームは 1 スレッドで展開する。
+//! gzip のファイルが BGZF（`bgzip` で圧縮したもの）のように独立したメンバーに分かれていて、
+//! ヘッダーにメンバーの大きさがあれば、メンバーごとに並列に展開する（`gunzip`）。
+//! zstd のフレームは独立して展開できるため、複数のフレームをつないだファイル
+//! （`zstd -B` や `pzstd`, seekable 形式で圧縮したもの）はフレームごとに並列に展開する（`unzstd`）。
+
+use flate2::bufread::{GzDecoder, ZlibDecoder};
+use rayon::prelude::*;
+use std::{io::Read, ops::Range};
+
+/// zlib 形式のデータを展開する（Adler-32 のチェックサムも検証する）
+pub fn zlib(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(data.len() * 4);
+    ZlibDecoder::new(data)
+        .read_to_end(&mut out)
+        .map_err(|err| message("zlib", err))?;
+    Ok(out)
+}
+
+/// gzip 形式のデータを展開する（CRC-32 と元のサイズも検証する）
+pub fn gzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    member(data).map(|(out, _)| out)
+}
+
+/// gzip のファイル全体を展開する（複数のメンバーをつないだものは、展開した結果を順につなげる）。
+/// すべてのメンバーのヘッダーに BGZF の大きさがあれば、メンバーごとに並列に展開する
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    if let Some(blocks) = bgzf_blocks(data) {
+        let parts = blocks
+            .into_par_iter()
+            .map(|range| gzip(&data[range]))
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(parts.concat());
+    }
+    let (mut out, mut pos) = member(data)?;
+    // 末尾の 0 埋め（テープなどに書いたもの）は無視する
+    while data[pos..].iter().any(|&b| b != 0) {
+        let (part, used) = member(&data[pos..])?;
+        out.extend_from_slice(&part);
+        pos += used;
+    }
+    Ok(out)
+}
+
+/// zstd のファイル全体を展開する（複数のフレームはフレームごとに並列に展開し、順につなげる）。
+/// スキップ可能なフレーム（seekable 形式の索引など）は読み飛ばす
+pub fn unzstd(data: &[u8]) -> Result<Vec<u8>, String> {
+    let parts = zstd_frames(data)?
+        .into_par_iter()
+        .map(|range| zstd::stream::decode_all(&data[range]).map_err(|err| message("zstd", err)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(parts.concat())
+}
+
+/// zstd のフレームの範囲（ブロックのヘッダーだけを読んで区切る）
+fn zstd_frames(data: &[u8]) -> Result<Vec<Range<usize>>, String> {
+    let mut frames = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let size = zstd::zstd_safe::find_frame_compressed_size(&data[pos..]).map_err(|code| {
+            format!(
+                "zstd データを展開できません: {}",
+                zstd::zstd_safe::get_error_name(code)
+            )
+        })?;
+        frames.push(pos..pos + size);
+        pos += size;
+    }
+    Ok(frames)
+}
+
+/// BGZF のメンバーの範囲（BGZF でないメンバーがあれば None）
+fn bgzf_blocks(data: &[u8]) -> Option<Vec<Range<usize>>> {
+    let mut blocks = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let size = bgzf_size(&data[pos..])?;
+        if pos + size > data.len() {
+            return None;
+        }
+        blocks.push(pos..pos + size);
+        pos += size;
+    }
+    (!blocks.is_empty()).then_some(blocks)
+}
+
+/// ヘッダーの追加フィールドの BC サブフィールドにあるメンバーの大きさ（BSIZE + 1 バイト）
+fn bgzf_size(data: &[u8]) -> Option<usize> {
+    const FEXTRA: u8 = 0x04;
+    if data.len() < 18 || data[..3] != [0x1F, 0x8B, 8] || data[3] & FEXTRA == 0 {
+        return None;
+    }
+    let xlen = u16::from_le_bytes([data[10], data[11]]) as usize;
+    let mut extra = data.get(12..12 + xlen)?;
+    while extra.len() >= 4 {
+        let len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+        let payload = extra.get(4..4 + len)?;
+        if extra[..2] == *b"BC" && len == 2 {
+            return Some(u16::from_le_bytes([payload[0], payload[1]]) as usize + 1);
+        }
+        extra = &extra[4 + len..];
+    }
+    None
+}
+
+/// gzip の 1 つのメンバーを展開し、展開結果とメンバーのバイト数を返す
+fn member(data: &[u8]) -> Result<(Vec<u8>, usize), String> {
+    let mut decoder = GzDecoder::new(data);
+    let mut out = Vec::with_capacity(data.len() * 4);
+    decoder
+        .read_to_end(&mut out)
+        .map_err(|err| message("gzip", err))?;
+    // 展開し終えたところで、読み込み元はメンバーの末尾（CRC-32 と元のサイズの後）まで進んでいる
+    let rest = decoder.into_inner();
+    Ok((out, data.len() - rest.len()))
+}
+
+/// 展開できなかった理由（チェックサムが一致しないことは分かるように書く）
+fn message(format: &str, err: std::io::Error) -> String {
+    if err.to_string().contains("checksum") {
+        format!("{} データのチェックサムが一致しません", format)
+    } else {
+        format!("{} データを展開できません: {}", format, err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{
+        write::{GzEncoder, ZlibEncoder},
+        Compression, GzBuilder,
+    };
+    use std::io::Write;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// BC サブフィールドに大きさを書いた BGZF のメンバー
+    fn bgzf(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzBuilder::new()
+            .extra(vec![b'B', b'C', 2, 0, 0, 0])
+            .write(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        let mut member = encoder.finish().unwrap();
+        let size = (member.len() - 1) as u16;
+        member[16..18].copy_from_slice(&size.to_le_bytes());
+        member
+    }
+
+    #[test]
+    fn concatenated_members_and_trailing_zeros_are_read() {
+        let mut data = [compress(b"hello, "), compress(b"world")].concat();
+        data.extend([0; 16]);
+        assert_eq!(gunzip(&data).unwrap(), b"hello, world");
+        assert_eq!(gzip(&data).unwrap(), b"hello, ");
+    }
+
+    #[test]
+    fn bgzf_members_are_read_in_order() {
+        let data = [bgzf(b"abc"), bgzf(b"def"), bgzf(b"")].concat();
+        assert_eq!(bgzf_blocks(&data).unwrap().len(), 3);
+        assert_eq!(gunzip(&data).unwrap(), b"abcdef");
+    }
+
+    #[test]
+    fn zlib_checks_the_checksum() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"layon").unwrap();
+        let mut data = encoder.finish().unwrap();
+        assert_eq!(zlib(&data).unwrap(), b"layon");
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert!(zlib(&data).is_err());
+    }
+
+    #[test]
+    fn zstd_frames_are_read_in_order() {
+        let frame = |data: &[u8]| zstd::bulk::compress(data, 3).unwrap();
+        // スキップ可能なフレーム（マジックナンバー 0x184D2A5?、4 バイトの長さ）
+        let skippable = [0x50, 0x2A, 0x4D, 0x18, 2, 0, 0, 0, 0xAB, 0xCD];
+        let data = [frame(b"abc"), skippable.to_vec(), frame(b"def")].concat();
+        assert_eq!(zstd_frames(&data).unwrap().len(), 3);
+        assert_eq!(unzstd(&data).unwrap(), b"abcdef");
+        assert!(unzstd(&data[..data.len() - 2]).is_err());
+    }
+}
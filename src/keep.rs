@@ -0,0 +1,187 @@
+//! 集計結果にプロパティの値をそのまま引き継ぐ列（`--keep N03_001,N03_007`）。
+//!
+//! 市町村名で集計したときに都道府県名や行政区域コードも表に残せるようにする。
+//! 同じグループの Feature で値が食い違う場合（同じ名前の市町村が別の都道府県にあるなど）の扱いは `Policy` で選ぶ。
+//! 値が 1 つもない場合は空にする。数値や真偽値のプロパティは JSON の表記の文字列にする。
+
+use crate::aggregate::{self, GroupResult};
+use geojson::{FeatureCollection, JsonValue};
+use std::collections::HashMap;
+
+/// グループの中で値が食い違う場合の扱い
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Policy {
+    /// 入力で最初に現れた値
+    #[default]
+    First,
+    /// 集計をエラーにする
+    Error,
+    /// すべての値を現れた順に "; " でつなぐ
+    ListAll,
+    /// 最も多くの Feature が持つ値（同じ数なら先に現れた値）
+    Majority,
+}
+
+impl Policy {
+    /// `--keep-conflict` の値
+    pub fn parse(name: &str) -> Result<Policy, String> {
+        match name {
+            "first" => Ok(Policy::First),
+            "error" => Ok(Policy::Error),
+            "list-all" => Ok(Policy::ListAll),
+            "majority" => Ok(Policy::Majority),
+            _ => Err(format!(
+                "不明な --keep-conflict の値です: {} (first, error, list-all, majority のいずれか)",
+                name
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Policy::First => "first",
+            Policy::Error => "error",
+            Policy::ListAll => "list-all",
+            Policy::Majority => "majority",
+        }
+    }
+}
+
+/// 引き継ぐプロパティと食い違いの扱い
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keep {
+    /// プロパティ（列の順）
+    pub properties: Vec<String>,
+    pub policy: Policy,
+}
+
+/// 値が食い違ったグループとプロパティ
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    pub layer: Option<String>,
+    pub key: String,
+    pub property: String,
+    /// 値とその値を持つ Feature の数（入力で現れた順）
+    pub values: Counts,
+    /// 列に書いた値（`Policy::Error` なら空）
+    pub chosen: String,
+}
+
+impl Conflict {
+    /// メッセージ用の説明（例: "府中市 の N03_001: 東京都 (3), 広島県 (1)"）
+    pub fn describe(&self) -> String {
+        let values: Vec<String> = self
+            .values
+            .iter()
+            .map(|(value, count)| format!("{} ({})", value, count))
+            .collect();
+        let key = match &self.layer {
+            Some(layer) => format!("{}/{}", layer, self.key),
+            None => self.key.clone(),
+        };
+        format!("{} の {}: {}", key, self.property, values.join(", "))
+    }
+}
+
+/// 値とその値を持つ Feature の数（入力で現れた順）
+type Counts = Vec<(String, usize)>;
+
+/// グループ（レイヤーと集計キー）ごと、プロパティごとの値と数
+#[derive(Debug, Default)]
+pub struct Collected {
+    values: HashMap<(Option<String>, String), Vec<Counts>>,
+}
+
+impl Keep {
+    /// 集計する Feature（ジオメトリと集計キーを持つもの）からプロパティの値を集める
+    pub(crate) fn collect(
+        &self,
+        collection: &FeatureCollection,
+        group_by: &str,
+        layers: bool,
+    ) -> Collected {
+        let mut collected = Collected::default();
+        for feature in &collection.features {
+            let (Some(properties), Some(_)) = (&feature.properties, &feature.geometry) else {
+                continue;
+            };
+            let Some(key) = properties.get(group_by).and_then(JsonValue::as_str) else {
+                continue;
+            };
+            let group =
+                aggregate::split_map_key(aggregate::map_key(properties, key, layers), layers);
+            let lists = collected
+                .values
+                .entry(group)
+                .or_insert_with(|| vec![Vec::new(); self.properties.len()]);
+            for (property, list) in self.properties.iter().zip(lists) {
+                let value = match properties.get(property) {
+                    None | Some(JsonValue::Null) => continue,
+                    Some(JsonValue::String(text)) => text.clone(),
+                    Some(value) => value.to_string(),
+                };
+                match list.iter_mut().find(|(seen, _)| *seen == value) {
+                    Some((_, count)) => *count += 1,
+                    None => list.push((value, 1)),
+                }
+            }
+        }
+        collected
+    }
+
+    /// 集めた値を `GroupResult::kept` に書き、食い違ったものを返す。
+    /// `Policy::Error` で食い違いがあればエラー（食い違いを列挙する）
+    pub(crate) fn apply(
+        &self,
+        rows: &mut [GroupResult],
+        collected: &Collected,
+    ) -> Result<Vec<Conflict>, String> {
+        let mut conflicts = Vec::new();
+        for row in rows.iter_mut() {
+            let lists = collected.values.get(&(row.layer.clone(), row.key.clone()));
+            for (i, property) in self.properties.iter().enumerate() {
+                let values = lists.map_or(&[][..], |lists| &lists[i]);
+                let chosen = match (values, self.policy) {
+                    ([], _) => String::new(),
+                    ([(value, _)], _) | ([(value, _), ..], Policy::First) => value.clone(),
+                    (_, Policy::Error) => String::new(),
+                    (_, Policy::ListAll) => values
+                        .iter()
+                        .map(|(value, _)| value.as_str())
+                        .collect::<Vec<_>>()
+                        .join("; "),
+                    (_, Policy::Majority) => {
+                        // max_by_key は同じ数なら後のものを返すため、逆順に探す
+                        let (value, _) =
+                            values.iter().rev().max_by_key(|(_, count)| *count).unwrap();
+                        value.clone()
+                    }
+                };
+                if values.len() > 1 {
+                    conflicts.push(Conflict {
+                        layer: row.layer.clone(),
+                        key: row.key.clone(),
+                        property: property.clone(),
+                        values: values.to_vec(),
+                        chosen: chosen.clone(),
+                    });
+                }
+                row.kept.push((property.clone(), chosen));
+            }
+        }
+        if self.policy == Policy::Error && !conflicts.is_empty() {
+            return Err(format!(
+                "--keep のプロパティの値が {} 箇所で食い違っています: {}{}",
+                conflicts.len(),
+                conflicts
+                    .iter()
+                    .take(5)
+                    .map(Conflict::describe)
+                    .collect::<Vec<_>>()
+                    .join("; "),
+                if conflicts.len() > 5 { " など" } else { "" }
+            ));
+        }
+        Ok(conflicts)
+    }
+}
@@ -0,0 +1,283 @@
+//! グループごとにラベルを置く代表点（`layon centroids`）。
+//! 重心は凹んだポリゴンの外に出ることがあるため、ポリゴンの内側で境界から最も遠い点（到達不能極）を使う。
+
+use crate::aggregate::to_multi_polygon;
+use geo::{BoundingRect, Centroid, Coord, Geometry, Polygon};
+use geojson::FeatureCollection;
+use rayon::prelude::*;
+use std::{cmp::Ordering, collections::BinaryHeap, collections::HashMap};
+
+/// ラベルの位置
+pub struct LabelRow {
+    pub name: String,
+    pub point: Coord<f64>,
+    /// 点からポリゴンの境界までの距離（座標の単位）
+    pub distance: f64,
+}
+
+/// `group_by` のプロパティごとに、ラベルを置く代表点として到達不能極
+/// （ポリゴンの内側で境界から最も遠い点）を求める。
+/// グループが複数のポリゴンからなる場合は、境界から最も離れた点がとれたポリゴンのものを使う。
+/// `precision` は探索を打ち切る精度で、省略した場合はポリゴンの大きさの 1/1000 にする。
+pub fn labels(
+    collection: &FeatureCollection,
+    group_by: &str,
+    precision: Option<f64>,
+) -> Vec<LabelRow> {
+    let mut groups: HashMap<&str, Vec<Polygon<f64>>> = HashMap::new();
+    for feature in &collection.features {
+        let Some(name) = feature
+            .properties
+            .as_ref()
+            .and_then(|properties| properties.get(group_by))
+            .and_then(|name| name.as_str())
+        else {
+            continue;
+        };
+        let geometry = feature
+            .geometry
+            .as_ref()
+            .and_then(|geometry| Geometry::<f64>::try_from(geometry.value.clone()).ok());
+        if let Some(polygons) = geometry.and_then(to_multi_polygon) {
+            groups.entry(name).or_default().extend(polygons);
+        }
+    }
+
+    let mut rows: Vec<LabelRow> = groups
+        .into_par_iter()
+        .filter_map(|(name, polygons)| {
+            let (point, distance) = polygons
+                .par_iter()
+                .filter_map(|polygon| polylabel(polygon, precision))
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))?;
+            Some(LabelRow {
+                name: name.to_string(),
+                point,
+                distance,
+            })
+        })
+        .collect();
+    rows.sort_by(|a, b| a.name.cmp(&b.name));
+    rows
+}
+
+/// 探索するセル（正方形）
+struct Cell {
+    center: Coord<f64>,
+    /// 一辺の半分
+    half: f64,
+    /// 中心から境界までの符号付き距離（内側が正）
+    distance: f64,
+    /// セル内で取り得る距離の上限
+    potential: f64,
+}
+
+impl Cell {
+    fn new(center: Coord<f64>, half: f64, polygon: &Polygon<f64>) -> Cell {
+        let distance = signed_distance(center, polygon);
+        Cell {
+            center,
+            half,
+            distance,
+            potential: distance + half * std::f64::consts::SQRT_2,
+        }
+    }
+}
+
+impl PartialEq for Cell {
+    fn eq(&self, other: &Self) -> bool {
+        self.potential == other.potential
+    }
+}
+
+impl Eq for Cell {}
+
+impl PartialOrd for Cell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Cell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.potential
+            .partial_cmp(&other.potential)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+/// 到達不能極を求める（Mapbox の polylabel と同じ、セルを 4 分割していく分枝限定法）
+fn polylabel(polygon: &Polygon<f64>, precision: Option<f64>) -> Option<(Coord<f64>, f64)> {
+    let rect = polygon.bounding_rect()?;
+    let (width, height) = (rect.width(), rect.height());
+    let size = width.min(height);
+    if size <= 0.0 {
+        return Some((rect.min(), 0.0));
+    }
+    let precision = precision.unwrap_or(width.max(height) / 1000.0);
+
+    // 外接矩形をセルで覆う
+    let mut queue = BinaryHeap::new();
+    let half = size / 2.0;
+    let mut x = rect.min().x;
+    while x < rect.max().x {
+        let mut y = rect.min().y;
+        while y < rect.max().y {
+            queue.push(Cell::new(
+                Coord {
+                    x: x + half,
+                    y: y + half,
+                },
+                half,
+                polygon,
+            ));
+            y += size;
+        }
+        x += size;
+    }
+
+    // 重心（内側にあれば）と外接矩形の中心を最初の候補にする
+    let mut best = Cell::new(rect.center(), 0.0, polygon);
+    if let Some(centroid) = polygon.centroid() {
+        let cell = Cell::new(centroid.0, 0.0, polygon);
+        if cell.distance > best.distance {
+            best = cell;
+        }
+    }
+
+    while let Some(cell) = queue.pop() {
+        if cell.distance > best.distance {
+            best = Cell::new(cell.center, 0.0, polygon);
+        }
+        // これ以上分割しても精度以上に良くならないセルは捨てる
+        if cell.potential - best.distance <= precision {
+            continue;
+        }
+        let half = cell.half / 2.0;
+        for (dx, dy) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+            queue.push(Cell::new(
+                Coord {
+                    x: cell.center.x + dx * half,
+                    y: cell.center.y + dy * half,
+                },
+                half,
+                polygon,
+            ));
+        }
+    }
+
+    Some((best.center, best.distance))
+}
+
+/// 点からポリゴンの境界までの距離。内側なら正、外側なら負
+fn signed_distance(point: Coord<f64>, polygon: &Polygon<f64>) -> f64 {
+    let mut inside = false;
+    let mut min = f64::INFINITY;
+    for ring in std::iter::once(polygon.exterior()).chain(polygon.interiors()) {
+        for line in ring.lines() {
+            let (a, b) = (line.start, line.end);
+            if (a.y > point.y) != (b.y > point.y)
+                && point.x < (b.x - a.x) * (point.y - a.y) / (b.y - a.y) + a.x
+            {
+                inside = !inside;
+            }
+            min = min.min(segment_distance(point, a, b));
+        }
+    }
+    if inside {
+        min
+    } else {
+        -min
+    }
+}
+
+fn segment_distance(p: Coord<f64>, a: Coord<f64>, b: Coord<f64>) -> f64 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length = dx * dx + dy * dy;
+    let t = if length > 0.0 {
+        (((p.x - a.x) * dx + (p.y - a.y) * dy) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (x, y) = (a.x + t * dx - p.x, a.y + t * dy - p.y);
+    (x * x + y * y).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::Contains;
+
+    /// 10 × 10 の正方形から右側の 8 × 6 を切り欠いた C の字（太さ 2）
+    const C_SHAPE: &str = "[[[0,0],[10,0],[10,2],[2,2],[2,8],[10,8],[10,10],[0,10],[0,0]]]";
+
+    fn collection(features: &[(&str, &str)]) -> FeatureCollection {
+        let features: Vec<String> = features
+            .iter()
+            .map(|(city, coordinates)| {
+                format!(
+                    r#"{{"type":"Feature","properties":{{"city":"{}"}},"geometry":{{"type":"Polygon","coordinates":{}}}}}"#,
+                    city, coordinates
+                )
+            })
+            .collect();
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        )
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn the_pole_of_a_concave_polygon_lies_inside_it() {
+        let collection = collection(&[("c", C_SHAPE)]);
+        let polygon: Polygon<f64> = Geometry::<f64>::try_from(
+            collection.features[0]
+                .geometry
+                .as_ref()
+                .unwrap()
+                .value
+                .clone(),
+        )
+        .unwrap()
+        .try_into()
+        .unwrap();
+        // 重心は切り欠いた部分にあり、ポリゴンの外になる
+        let centroid = polygon.centroid().unwrap();
+        assert!(!polygon.contains(&centroid));
+        assert!(signed_distance(centroid.0, &polygon) < 0.0);
+
+        let rows = labels(&collection, "city", Some(1e-3));
+        assert_eq!(rows.len(), 1);
+        let label = &rows[0];
+        assert!(
+            polygon.contains(&geo::Point::from(label.point)),
+            "{:?}",
+            label.point
+        );
+        // 最も遠いのは角の内側の (t, t) で、外側の辺と内側の角 (2, 2) から等しく t = 4 - 2√2 離れた点
+        let pole = 4.0 - 2.0 * std::f64::consts::SQRT_2;
+        assert!((label.distance - pole).abs() <= 1e-3, "{}", label.distance);
+        assert!((signed_distance(label.point, &polygon) - label.distance).abs() < 1e-12);
+    }
+
+    #[test]
+    fn groups_use_the_polygon_farthest_from_its_boundary() {
+        let collection = collection(&[
+            ("b", "[[[0,0],[1,0],[1,1],[0,1],[0,0]]]"),
+            ("b", "[[[10,0],[14,0],[14,4],[10,4],[10,0]]]"),
+            ("a", C_SHAPE),
+            // 面積のないポリゴンは境界上の点になる
+            ("z", "[[[20,0],[21,0],[22,0],[20,0]]]"),
+        ]);
+        let rows = labels(&collection, "city", None);
+        let names: Vec<&str> = rows.iter().map(|row| row.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "z"]);
+        let b = &rows[1];
+        assert!((b.point.x - 12.0).abs() < 0.01 && (b.point.y - 2.0).abs() < 0.01);
+        assert!((b.distance - 2.0).abs() < 0.01);
+        assert_eq!(rows[2].distance, 0.0);
+        assert!(labels(&collection, "N03_004", None).is_empty());
+    }
+}
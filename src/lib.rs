@@ -0,0 +1,100 @@
+//! 行政区域データなどのポリゴンを読み込み、属性ごとに面積を集計するライブラリ。
+//! コマンドラインの `layon` と同じ処理を [`pipeline::Pipeline`] で組み立てて実行できる。
+//!
+//! ```no_run
+//! use layon::pipeline::{Csv, Pipeline};
+//! use layon::aggregate::Metric;
+//!
+//! let result = Pipeline::read("src/N03-20240101_11.geojson")
+//!     .filter("N03_004 != '秩父市'")
+//!     .group_by("N03_004")
+//!     .metric(Metric::GeodesicArea)
+//!     .sink(Csv::new("output.csv"))
+//!     .run()?;
+//! println!("{} 市町村", result.rows.len());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! ライブラリとして公開するのは、[`pipeline`] と、その設定や結果に使う [`aggregate`]、[`source`]、[`sink`]、
+//! R パッケージが使う [`zonal`] だけ。ほかのモジュールはコマンドラインの `layon` の実装のためのもの。
+
+pub mod aggregate;
+mod annotate;
+mod approx;
+mod area;
+mod audit;
+mod batch;
+mod breaks;
+mod cache;
+mod cancel;
+mod chart;
+mod check;
+mod classify;
+mod cli;
+mod commands;
+mod compare;
+mod completions;
+mod dedup;
+mod derive;
+mod extent;
+mod external;
+mod filter;
+mod flat;
+mod generate;
+#[cfg(test)]
+mod geometry_tests;
+mod geometry_type;
+mod gpu;
+mod h3;
+mod hierarchy;
+mod holes;
+mod hull;
+mod ids;
+mod inflate;
+mod keep;
+mod label;
+mod locate;
+mod log;
+mod man;
+mod mapping;
+mod markup;
+mod mesh;
+mod normalize;
+mod numeric;
+mod overlay;
+mod partition;
+pub mod pipeline;
+mod pivot;
+mod plan;
+mod prefecture;
+mod progress;
+mod projection;
+mod provenance;
+mod psql;
+mod raster;
+mod repair;
+mod schedule;
+mod schema;
+mod scratch;
+mod serve;
+mod sha256;
+mod signal;
+pub mod sink;
+mod smooth;
+pub mod source;
+mod stream;
+mod subset;
+mod subtract;
+mod template;
+mod termmap;
+mod time;
+mod timeseries;
+mod timing;
+mod transform;
+mod validate;
+mod verify;
+mod visit;
+pub mod zonal;
+
+#[doc(hidden)]
+pub use commands::main;
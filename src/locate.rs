@@ -0,0 +1,513 @@
+//! 点を含むポリゴンの検索（`layon locate`）。
+//! ポリゴンの外接矩形を R-tree に入れて候補を絞り、含むポリゴンを探す。
+//! 海上や境界の隙間など、どのポリゴンにも含まれない点は、最も近いポリゴンとその距離を返す。
+//! 数百万行の点の CSV も、少しずつ読み込んで並列に検索し、元の行に列を加えて書き出せる（`--points`）。
+
+use crate::aggregate::{to_multi_polygon, Metric};
+use csv::{ReaderBuilder, StringRecord, Writer};
+use geo::{
+    BoundingRect, Closest, ClosestPoint, EuclideanDistance, Geometry, HaversineDistance,
+    Intersects, MultiPolygon, Point,
+};
+use geojson::{FeatureCollection, JsonValue};
+use rayon::prelude::*;
+use rstar::{primitives::GeomWithData, primitives::Rectangle, RTree};
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+};
+
+/// 点の CSV を一度に読み込んで並列に検索する行の数（全体をメモリに持たないように）
+const BATCH: usize = 1 << 16;
+
+/// X・Y の列を指定しなかった場合に探す列名（大文字・小文字は区別しない）
+const X_COLUMNS: &[&str] = &["x", "lon", "lng", "long", "longitude", "経度"];
+const Y_COLUMNS: &[&str] = &["y", "lat", "latitude", "緯度"];
+
+/// 含むポリゴンがない場合に、大円距離で比べる候補の数の下限（外接矩形までの距離の近い順）
+const NEAREST_CANDIDATES: usize = 8;
+
+/// 経線に沿った 1 度あたりの大円距離 (km、`HaversineDistance` の地球の半径 6371.0088 km による)
+const KM_PER_DEGREE: f64 = 6371.0088 * std::f64::consts::PI / 180.0;
+
+/// 検索の結果
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    /// 見つかったポリゴンの Feature の位置（入力の中での 0 始まりの番号）
+    pub feature: usize,
+    /// ポリゴンまでの距離（含まれる場合は 0。単位は `Index::locate` の `metric` による）
+    pub distance: f64,
+}
+
+/// 1 つの点の検索結果（出力する行）
+pub struct Location {
+    pub point: Point<f64>,
+    /// 見つかったポリゴンの、出力するプロパティの値（見つからないか、プロパティがなければ空）
+    pub values: Vec<String>,
+    /// ポリゴンまでの距離（見つからなければ None）
+    pub distance: Option<f64>,
+}
+
+/// 点ごとに並列に検索し、見つかったポリゴンの `columns` のプロパティを付ける
+pub fn locate_all(
+    collection: &FeatureCollection,
+    columns: &[String],
+    points: &[Point<f64>],
+    metric: Metric,
+    max_distance: Option<f64>,
+) -> Vec<Location> {
+    let index = Index::new(collection);
+    points
+        .par_iter()
+        .map(|&point| {
+            let hit = index.locate(point, metric, max_distance);
+            Location {
+                point,
+                values: values(collection, hit, columns),
+                distance: hit.map(|hit| hit.distance),
+            }
+        })
+        .collect()
+}
+
+/// 見つかったポリゴンのプロパティの値（文字列でなければ JSON の表記）
+fn values(collection: &FeatureCollection, hit: Option<Hit>, columns: &[String]) -> Vec<String> {
+    columns
+        .iter()
+        .map(
+            |column| match hit.and_then(|hit| collection.features[hit.feature].property(column)) {
+                None | Some(JsonValue::Null) => String::new(),
+                Some(JsonValue::String(text)) => text.clone(),
+                Some(value) => value.to_string(),
+            },
+        )
+        .collect()
+}
+
+/// 点の CSV の読み込み方
+pub struct PointsCsv {
+    /// 点の CSV ファイル（"-" なら標準入力）
+    pub path: String,
+    /// X（経度）の列（指定しなければ x, lon, longitude などの見出しを探す）
+    pub x_column: Option<String>,
+    /// Y（緯度）の列（指定しなければ y, lat, latitude などの見出しを探す）
+    pub y_column: Option<String>,
+}
+
+/// `locate_csv` で検索した点の数
+#[derive(Default)]
+pub struct CsvSummary {
+    pub rows: usize,
+    /// どのポリゴンにも含まれず、最も近いポリゴンを返した行
+    pub outside: usize,
+    /// ポリゴンが見つからなかった行
+    pub missing: usize,
+    /// X・Y を数値として読めなかった行
+    pub invalid: usize,
+}
+
+/// 点の CSV を `BATCH` 行ずつ読み込んで並列に検索し、元の行の後ろに `columns` のプロパティと
+/// 距離の列を加えて `output` に書き出す（"-" なら標準出力。行の順は変えない）
+pub fn locate_csv(
+    collection: &FeatureCollection,
+    columns: &[String],
+    points: &PointsCsv,
+    metric: Metric,
+    max_distance: Option<f64>,
+    output: &str,
+) -> Result<CsvSummary, Box<dyn Error>> {
+    let input: Box<dyn Read> = if points.path == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(&points.path)?))
+    };
+    let out: Box<dyn Write> = if output == "-" {
+        Box::new(BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(BufWriter::new(File::create(output)?))
+    };
+    let mut rdr = ReaderBuilder::new().flexible(true).from_reader(input);
+    let mut wtr = Writer::from_writer(out);
+
+    let headers = rdr.headers()?.clone();
+    let x = column_index(
+        &headers,
+        points.x_column.as_deref(),
+        X_COLUMNS,
+        "X",
+        "--x-column",
+    )?;
+    let y = column_index(
+        &headers,
+        points.y_column.as_deref(),
+        Y_COLUMNS,
+        "Y",
+        "--y-column",
+    )?;
+    let mut header = headers.clone();
+    for column in columns {
+        header.push_field(column);
+    }
+    header.push_field("Distance");
+    wtr.write_record(&header)?;
+
+    let index = Index::new(collection);
+    let mut summary = CsvSummary::default();
+    let mut batch = Vec::with_capacity(BATCH);
+    let mut records = rdr.records();
+    loop {
+        batch.clear();
+        for record in records.by_ref().take(BATCH) {
+            batch.push(record?);
+        }
+        if batch.is_empty() {
+            break;
+        }
+        let results: Vec<Option<(Option<Hit>, Vec<String>)>> = batch
+            .par_iter()
+            .map(|record| {
+                let coordinate = |i: usize| record.get(i)?.trim().parse::<f64>().ok();
+                let point = Point::new(coordinate(x)?, coordinate(y)?);
+                let hit = index.locate(point, metric, max_distance);
+                Some((hit, values(collection, hit, columns)))
+            })
+            .collect();
+        for (record, result) in batch.iter_mut().zip(results) {
+            summary.rows += 1;
+            match result {
+                Some((hit, values)) => {
+                    match hit {
+                        Some(hit) if hit.distance > 0.0 => summary.outside += 1,
+                        Some(_) => {}
+                        None => summary.missing += 1,
+                    }
+                    for value in &values {
+                        record.push_field(value);
+                    }
+                    record.push_field(&hit.map(|hit| hit.distance.to_string()).unwrap_or_default());
+                }
+                None => {
+                    summary.invalid += 1;
+                    for _ in 0..=columns.len() {
+                        record.push_field("");
+                    }
+                }
+            }
+            wtr.write_record(&*record)?;
+        }
+    }
+    wtr.flush()?;
+    Ok(summary)
+}
+
+/// X・Y の列の位置
+fn column_index(
+    headers: &StringRecord,
+    name: Option<&str>,
+    candidates: &[&str],
+    label: &str,
+    option: &str,
+) -> Result<usize, String> {
+    match name {
+        Some(name) => headers.iter().position(|h| h == name),
+        None => headers
+            .iter()
+            .position(|h| candidates.iter().any(|c| h.trim().eq_ignore_ascii_case(c))),
+    }
+    .ok_or_else(|| {
+        format!(
+            "{} の列が見つかりません ({} で指定してください。列: {})",
+            label,
+            option,
+            headers.iter().collect::<Vec<_>>().join(", ")
+        )
+    })
+}
+
+/// ポリゴンの索引
+pub struct Index {
+    /// (Feature の位置, ポリゴン)
+    polygons: Vec<(usize, MultiPolygon<f64>)>,
+    tree: RTree<GeomWithData<Rectangle<[f64; 2]>, usize>>,
+}
+
+impl Index {
+    /// ポリゴン（Polygon または MultiPolygon）の Feature から索引を作る
+    pub fn new(collection: &FeatureCollection) -> Index {
+        let polygons: Vec<(usize, MultiPolygon<f64>)> = collection
+            .features
+            .par_iter()
+            .enumerate()
+            .filter_map(|(i, feature)| {
+                let geometry: Geometry<f64> =
+                    feature.geometry.as_ref()?.value.clone().try_into().ok()?;
+                Some((i, to_multi_polygon(geometry)?))
+            })
+            .collect();
+        let tree = RTree::bulk_load(
+            polygons
+                .iter()
+                .enumerate()
+                .filter_map(|(i, (_, polygons))| {
+                    let rect = polygons.bounding_rect()?;
+                    Some(GeomWithData::new(
+                        Rectangle::from_corners(
+                            [rect.min().x, rect.min().y],
+                            [rect.max().x, rect.max().y],
+                        ),
+                        i,
+                    ))
+                })
+                .collect(),
+        );
+        Index { polygons, tree }
+    }
+
+    /// 点を含むポリゴン（境界上も含む）を探し、なければ最も近いポリゴンを返す。
+    /// `metric` が `Metric::Area` なら距離は座標の単位、そうでなければ大円距離の km（座標は経度・緯度）。
+    /// `max_distance` より遠いポリゴンしかなければ None
+    pub fn locate(
+        &self,
+        point: Point<f64>,
+        metric: Metric,
+        max_distance: Option<f64>,
+    ) -> Option<Hit> {
+        let query = [point.x(), point.y()];
+        if let Some(i) = self
+            .tree
+            .locate_all_at_point(&query)
+            .map(|candidate| candidate.data)
+            .filter(|&i| self.polygons[i].1.intersects(&point))
+            .min()
+        {
+            return Some(Hit {
+                feature: self.polygons[i].0,
+                distance: 0.0,
+            });
+        }
+
+        // 経度 1 度の長さは緯度によって違うため、経度・緯度のままの平面上で最も近いポリゴンが
+        // 大円距離でも最も近いとは限らない。外接矩形までの距離の近い順に候補を調べ、
+        // 候補ごとの（平面上で最も近い点までの）大円距離で比べる
+        let geodesic = metric != Metric::Area;
+        let mut best: Option<(usize, f64)> = None;
+        for (examined, (candidate, envelope_distance_2)) in self
+            .tree
+            .nearest_neighbor_iter_with_distance_2(&query)
+            .enumerate()
+        {
+            if let Some((_, distance)) = best {
+                let envelope = envelope_distance_2.sqrt();
+                let farther = if geodesic {
+                    examined >= NEAREST_CANDIDATES
+                        && min_geodesic_distance(point, envelope) > distance
+                } else {
+                    envelope > distance
+                };
+                if farther {
+                    break;
+                }
+            }
+            let polygons = &self.polygons[candidate.data].1;
+            let closest = match polygons.closest_point(&point) {
+                Closest::Intersection(p) | Closest::SinglePoint(p) => p,
+                Closest::Indeterminate => continue,
+            };
+            let distance = if geodesic {
+                point.haversine_distance(&closest) / 1e3
+            } else {
+                point.euclidean_distance(&closest)
+            };
+            if best.is_none_or(|(_, best)| distance < best) {
+                best = Some((candidate.data, distance));
+            }
+        }
+        let (i, distance) = best?;
+        if max_distance.is_some_and(|max| distance > max) {
+            return None;
+        }
+        Some(Hit {
+            feature: self.polygons[i].0,
+            distance,
+        })
+    }
+}
+
+/// 経度・緯度の平面上で `point` から `degrees` 以上離れた点までの、大円距離の下限 (km)。
+/// その範囲の緯度のうち最も極に近い緯度で、経度 1 度が最も短くなることから求める
+fn min_geodesic_distance(point: Point<f64>, degrees: f64) -> f64 {
+    let latitude = (point.y().abs() + degrees).min(90.0);
+    degrees * KM_PER_DEGREE * latitude.to_radians().cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// x が `x0` から `x1`、y が `y0` から `y1` までの長方形を並べたレイヤー（`name` のプロパティ付き）
+    fn layer(rects: &[(&str, [f64; 4])]) -> FeatureCollection {
+        let features: Vec<String> = rects
+            .iter()
+            .map(|(name, [x0, y0, x1, y1])| {
+                format!(
+                    r#"{{"type":"Feature","properties":{{"name":"{name}"}},"geometry":{{"type":"Polygon","coordinates":[[[{x0},{y0}],[{x1},{y0}],[{x1},{y1}],[{x0},{y1}],[{x0},{y0}]]]}}}}"#
+                )
+            })
+            .collect();
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        )
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn points_in_geometry_collection_members_are_located() {
+        // 本体のポリゴンと、入れ子の GeometryCollection の中の飛び地と線を持つ Feature
+        let collection: FeatureCollection = r#"{"type":"FeatureCollection","features":[
+            {"type":"Feature","properties":{},"geometry":{"type":"Polygon","coordinates":[[[0,0],[1,0],[1,1],[0,1],[0,0]]]}},
+            {"type":"Feature","properties":{},"geometry":{"type":"GeometryCollection","geometries":[
+                {"type":"LineString","coordinates":[[2,2],[3,3]]},
+                {"type":"GeometryCollection","geometries":[
+                    {"type":"Polygon","coordinates":[[[5,5],[6,5],[6,6],[5,6],[5,5]]]}
+                ]}
+            ]}}
+        ]}"#
+        .parse()
+        .unwrap();
+        let hit = Index::new(&collection)
+            .locate(Point::new(5.5, 5.5), Metric::Area, None)
+            .unwrap();
+        assert_eq!(
+            hit,
+            Hit {
+                feature: 1,
+                distance: 0.0
+            }
+        );
+    }
+
+    #[test]
+    fn points_outside_every_polygon_get_the_nearest_one() {
+        let collection = layer(&[("甲", [0.0, 0.0, 1.0, 1.0]), ("乙", [3.0, 0.0, 4.0, 1.0])]);
+        let index = Index::new(&collection);
+
+        // 境界上の点は含まれる
+        let hit = index.locate(Point::new(1.0, 0.5), Metric::Area, None);
+        assert_eq!(
+            hit,
+            Some(Hit {
+                feature: 0,
+                distance: 0.0
+            })
+        );
+
+        let hit = index
+            .locate(Point::new(2.5, 0.5), Metric::Area, None)
+            .unwrap();
+        assert_eq!(hit.feature, 1);
+        assert!((hit.distance - 0.5).abs() < 1e-12);
+        let hit = index
+            .locate(Point::new(0.5, -2.0), Metric::Area, None)
+            .unwrap();
+        assert_eq!(hit.feature, 0);
+        assert!((hit.distance - 2.0).abs() < 1e-12);
+
+        // 大円距離では km（赤道付近の緯度 1 度は約 111 km）
+        let hit = index
+            .locate(Point::new(0.5, -1.0), Metric::GeodesicArea, None)
+            .unwrap();
+        assert_eq!(hit.feature, 0);
+        assert!(
+            (hit.distance - KM_PER_DEGREE).abs() < 0.01,
+            "{}",
+            hit.distance
+        );
+
+        // 最も近いポリゴンも max_distance より遠ければ見つからない
+        assert_eq!(
+            index.locate(Point::new(0.5, -2.0), Metric::Area, Some(1.5)),
+            None
+        );
+        assert_eq!(
+            index
+                .locate(Point::new(0.5, 0.5), Metric::Area, Some(0.0))
+                .map(|hit| hit.feature),
+            Some(0)
+        );
+        assert_eq!(
+            Index::new(&layer(&[])).locate(Point::new(0.0, 0.0), Metric::Area, None),
+            None
+        );
+    }
+
+    #[test]
+    fn nearest_polygons_are_ranked_by_great_circle_distance() {
+        // 北緯 60 度では経度 1 度が緯度 1 度の約半分の長さになる。
+        // 平面上では北の乙 (1 度) が東の甲 (1.5 度) より近いが、大円距離では甲 (約 83 km) が乙 (約 111 km) より近い
+        let collection = layer(&[
+            ("甲", [11.5, 59.5, 12.5, 60.5]),
+            ("乙", [9.5, 61.0, 10.5, 62.0]),
+        ]);
+        let index = Index::new(&collection);
+        let point = Point::new(10.0, 60.0);
+        assert_eq!(index.locate(point, Metric::Area, None).unwrap().feature, 1);
+        let hit = index.locate(point, Metric::GeodesicArea, None).unwrap();
+        assert_eq!(hit.feature, 0);
+        assert!((80.0..90.0).contains(&hit.distance), "{}", hit.distance);
+    }
+
+    #[test]
+    fn csv_points_are_located_in_batches_keeping_the_rows() {
+        let collection = layer(&[("甲", [0.0, 0.0, 1.0, 1.0]), ("乙", [3.0, 0.0, 4.0, 1.0])]);
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("layon-locate-{}.csv", std::process::id()));
+        let output = dir.join(format!("layon-locate-out-{}.csv", std::process::id()));
+        // BATCH 行を超える行数で、まとまりの境目でも行の順を変えないことを確かめる
+        let mut contents = String::from("id,lng,lat\n");
+        for i in 0..BATCH + 3 {
+            let x = match i % 3 {
+                0 => "0.5",
+                1 => "3.5",
+                _ => "2.1",
+            };
+            contents.push_str(&format!("{},{},0.5\n", i, x));
+        }
+        contents.push_str("bad,east,0.5\nfar,100,0.5\n");
+        std::fs::write(&input, contents).unwrap();
+
+        let points = PointsCsv {
+            path: input.to_string_lossy().into_owned(),
+            x_column: None,
+            y_column: None,
+        };
+        let summary = locate_csv(
+            &collection,
+            &["name".to_string()],
+            &points,
+            Metric::Area,
+            Some(10.0),
+            &output.to_string_lossy(),
+        )
+        .unwrap();
+        let written = std::fs::read_to_string(&output).unwrap();
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+
+        assert_eq!(summary.rows, BATCH + 5);
+        assert_eq!(summary.outside, (BATCH + 3) / 3);
+        assert_eq!(summary.missing, 1);
+        assert_eq!(summary.invalid, 1);
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), BATCH + 6);
+        assert_eq!(lines[0], "id,lng,lat,name,Distance");
+        assert_eq!(lines[1], "0,0.5,0.5,甲,0");
+        assert_eq!(lines[2], "1,3.5,0.5,乙,0");
+        assert!(lines[3].starts_with("2,2.1,0.5,乙,0.899"), "{}", lines[3]);
+        assert_eq!(lines[BATCH + 1], format!("{},3.5,0.5,乙,0", BATCH));
+        assert_eq!(lines[BATCH + 4], "bad,east,0.5,,");
+        assert_eq!(lines[BATCH + 5], "far,100,0.5,,");
+    }
+}
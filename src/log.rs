@@ -0,0 +1,85 @@
+//! 進み具合や結果のメッセージと警告の出力（コマンドとライブラリで共通）。
+//! 環境変数 LAYON_LOG_FORMAT=json のときは、コンテナのログ基盤で扱いやすいよう
+//! 1 行 1 件の JSON（time, level, message）で出力する。
+//! 処理時間の内訳などの診断情報（debug）は、環境変数 LAYON_LOG_LEVEL=debug のときだけ出力する。
+
+use crate::time;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::SystemTime,
+};
+
+/// JSON で出力するか
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// 診断情報（debug）も出力するか
+static DEBUG: AtomicBool = AtomicBool::new(false);
+
+/// 環境変数 LAYON_LOG_FORMAT から出力の形式を、LAYON_LOG_LEVEL から出力する段階を決める
+pub fn init() -> Result<(), String> {
+    match std::env::var("LAYON_LOG_FORMAT").as_deref() {
+        Ok("json") => JSON.store(true, Ordering::Relaxed),
+        Ok("text") | Ok("") | Err(_) => {}
+        Ok(format) => return Err(format!("LAYON_LOG_FORMAT の値が不正です: {}", format)),
+    }
+    match std::env::var("LAYON_LOG_LEVEL").as_deref() {
+        Ok("debug") => DEBUG.store(true, Ordering::Relaxed),
+        Ok("info") | Ok("") | Err(_) => {}
+        Ok(level) => {
+            return Err(format!(
+                "LAYON_LOG_LEVEL の値が不正です: {} (info, debug)",
+                level
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// JSON で出力しているか
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// 診断情報（debug）も出力しているか
+pub fn is_debug() -> bool {
+    DEBUG.load(Ordering::Relaxed)
+}
+
+/// メッセージを出力する（テキストでは info は標準出力、それ以外は標準エラー出力。debug は `is_debug` の場合だけ）
+pub fn write(level: &str, message: &str) {
+    if level == "debug" && !is_debug() {
+        return;
+    }
+    if is_json() {
+        let record = serde_json::json!({
+            "time": time::rfc3339(SystemTime::now()),
+            "level": level,
+            "message": message,
+        });
+        if level == "info" {
+            println!("{}", record);
+        } else {
+            eprintln!("{}", record);
+        }
+    } else if level == "info" {
+        println!("{}", message);
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+/// 通常のメッセージ（`println!` と同じ書式。`log::info!` として使う）
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log::write("info", &format!($($arg)*))
+    };
+}
+
+/// 警告（`eprintln!` と同じ書式。`log::warning!` として使う）
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::log::write("warn", &format!($($arg)*))
+    };
+}
+
+pub(crate) use {info, warning};
@@ -0,0 +1,157 @@
+//! man ページの生成（`layon man`）。
+//! 補完スクリプトと同じく `cli::USAGE` から読み取った定義を roff の書式にする。
+
+use crate::cli::{self, OptionSpec, USAGE};
+use std::fmt::Write;
+
+/// man ページ（roff）を生成する
+pub fn render() -> String {
+    let commands = cli::spec();
+    let mut s = String::new();
+    writeln!(
+        s,
+        ".TH LAYON 1 \"\" \"layon {}\" \"User Commands\"",
+        env!("CARGO_PKG_VERSION")
+    )
+    .unwrap();
+    writeln!(s, ".SH NAME").unwrap();
+    writeln!(
+        s,
+        "layon \\- ポリゴンを属性ごとに集計する (行政区域データの市町村別の面積など)"
+    )
+    .unwrap();
+
+    // 使い方の最初の段落（空行まで）がそのまま書式になる
+    writeln!(s, ".SH SYNOPSIS").unwrap();
+    writeln!(s, ".nf").unwrap();
+    for line in USAGE.lines().take_while(|line| !line.is_empty()) {
+        let line = line.trim_start_matches("使い方:").trim();
+        writeln!(s, "{}", escape(line)).unwrap();
+    }
+    writeln!(s, ".fi").unwrap();
+
+    writeln!(s, ".SH DESCRIPTION").unwrap();
+    writeln!(s, "{}。", escape(commands[0].description)).unwrap();
+    writeln!(s, ".SH サブコマンド").unwrap();
+    for command in commands.iter().filter(|command| !command.name.is_empty()) {
+        writeln!(s, ".TP").unwrap();
+        writeln!(s, "\\fB{}\\fR", command.name).unwrap();
+        writeln!(s, "{}", escape(command.description)).unwrap();
+    }
+
+    for command in &commands {
+        if command.options.is_empty() {
+            continue;
+        }
+        if command.name.is_empty() {
+            writeln!(s, ".SH オプション").unwrap();
+        } else {
+            writeln!(s, ".SH \"{} のオプション\"", command.name).unwrap();
+        }
+        for option in &command.options {
+            write_option(&mut s, option);
+        }
+    }
+
+    // 環境変数の節は「名前  説明」の 2 列だけなので、そのまま項目にする
+    let environment = USAGE
+        .lines()
+        .skip_while(|line| *line != "環境変数:")
+        .skip(1)
+        .take_while(|line| !line.is_empty());
+    writeln!(s, ".SH ENVIRONMENT").unwrap();
+    for line in environment {
+        let (name, description) = line.trim().split_once("  ").unwrap_or((line.trim(), ""));
+        writeln!(s, ".TP").unwrap();
+        writeln!(s, "\\fB{}\\fR", escape(name)).unwrap();
+        writeln!(s, "{}", escape(description.trim_start())).unwrap();
+    }
+    s
+}
+
+fn write_option(s: &mut String, option: &OptionSpec) {
+    writeln!(s, ".TP").unwrap();
+    let mut names: Vec<_> = option
+        .short
+        .into_iter()
+        .chain(option.long)
+        .map(|name| format!("\\fB{}\\fR", escape(name)))
+        .collect();
+    match (option.long, option.value) {
+        (Some(_), Some(value)) if option.optional => {
+            let last = names.pop().unwrap_or_default();
+            names.push(format!("{}[=\\fI{}\\fR]", last, escape(value)));
+        }
+        (Some(_), Some(value)) => {
+            let last = names.pop().unwrap_or_default();
+            names.push(format!("{} \\fI{}\\fR", last, escape(value)));
+        }
+        // 位置引数
+        (None, Some(value)) => names.push(format!("\\fI{}\\fR", escape(value))),
+        _ => {}
+    }
+    writeln!(s, "{}", names.join(", ")).unwrap();
+    writeln!(s, "{}", escape(option.description)).unwrap();
+    if !option.details.is_empty() {
+        // 候補の表などは桁をそろえて書いてあるため、詰め直さずにそのまま出す
+        writeln!(s, ".nf").unwrap();
+        for line in &option.details {
+            writeln!(s, "{}", escape(line)).unwrap();
+        }
+        writeln!(s, ".fi").unwrap();
+    }
+}
+
+/// roff で特別な意味を持つ文字をエスケープする
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    // 行頭の . と ' は命令とみなされる
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::completions::tests::{usage_sections, usage_subcommands};
+
+    #[test]
+    fn lists_every_subcommand_and_option() {
+        let page = render();
+        for name in usage_subcommands() {
+            assert!(
+                page.contains(&format!(".TP\n\\fB{}\\fR\n", name)),
+                "{}",
+                name
+            );
+        }
+        for (names, options) in usage_sections() {
+            for name in names {
+                let heading = if name.is_empty() {
+                    ".SH オプション\n".to_string()
+                } else {
+                    format!(".SH \"{} のオプション\"\n", name)
+                };
+                let start = page
+                    .find(&heading)
+                    .unwrap_or_else(|| panic!("{} の節がありません", heading));
+                let rest = &page[start + heading.len()..];
+                let section = &rest[..rest.find(".SH ").unwrap_or(rest.len())];
+                for option in &options {
+                    let escaped = format!("\\fB{}\\fR", escape(option));
+                    assert!(section.contains(&escaped), "{:?}: {}", name, option);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn escapes_roff_characters() {
+        assert_eq!(escape("--input"), "\\-\\-input");
+        assert_eq!(escape(".hidden"), "\\&.hidden");
+        assert_eq!(escape("a\\b"), "a\\eb");
+    }
+}
@@ -0,0 +1,131 @@
+//! 出力するプロパティの選択と名前の付け替え（`--map`）。
+//! `N03_001=prefecture,N03_004=city` のように書き、出力先のスキーマに N03_* の名前を持ち込まないようにする。
+
+use geojson::{FeatureCollection, JsonObject};
+use rayon::prelude::*;
+
+/// 残すプロパティと出力する名前の対応
+pub struct PropertyMap {
+    entries: Vec<(String, String)>,
+}
+
+impl PropertyMap {
+    /// `元の名前=新しい名前` をカンマで区切って並べたものを解析する。
+    /// `=` のない名前はそのままの名前で残す
+    pub fn parse(text: &str) -> Result<PropertyMap, String> {
+        let mut entries = Vec::new();
+        for entry in text
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (from, to) = match entry.split_once('=') {
+                Some((from, to)) => (from.trim(), to.trim()),
+                None => (entry, entry),
+            };
+            if from.is_empty() || to.is_empty() {
+                return Err(format!("プロパティの対応が不正です: {}", entry));
+            }
+            if entries.iter().any(|(_, existing)| existing == to) {
+                return Err(format!("出力するプロパティの名前が重複しています: {}", to));
+            }
+            entries.push((from.to_string(), to.to_string()));
+        }
+        if entries.is_empty() {
+            return Err("残すプロパティを指定してください".to_string());
+        }
+        Ok(PropertyMap { entries })
+    }
+
+    /// 元の名前と新しい名前（指定した順）
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.entries
+    }
+
+    /// プロパティを対応の通りに選んで名前を付け替える（ないプロパティは出力しない）
+    pub fn apply(&self, properties: &JsonObject) -> JsonObject {
+        self.entries
+            .iter()
+            .filter_map(|(from, to)| Some((to.clone(), properties.get(from)?.clone())))
+            .collect()
+    }
+
+    /// すべての Feature のプロパティに適用する
+    pub fn apply_all(&self, collection: &mut FeatureCollection) {
+        collection.features.par_iter_mut().for_each(|feature| {
+            if let Some(properties) = &feature.properties {
+                feature.properties = Some(self.apply(properties));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geojson::JsonValue;
+
+    fn properties(json: &str) -> JsonObject {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn properties_are_selected_and_renamed() {
+        let map = PropertyMap::parse(" N03_001 = prefecture, N03_004=city ,N03_007,, N03_004=name")
+            .unwrap();
+        assert_eq!(
+            map.entries(),
+            [
+                ("N03_001".to_string(), "prefecture".to_string()),
+                ("N03_004".to_string(), "city".to_string()),
+                ("N03_007".to_string(), "N03_007".to_string()),
+                ("N03_004".to_string(), "name".to_string()),
+            ]
+        );
+        // 指定しなかったプロパティとないプロパティは出力しない
+        let mapped = map.apply(&properties(
+            r#"{"N03_001":"埼玉県","N03_003":null,"N03_004":"川越市"}"#,
+        ));
+        assert_eq!(
+            mapped,
+            properties(r#"{"prefecture":"埼玉県","city":"川越市","name":"川越市"}"#)
+        );
+
+        for invalid in ["", " , ", "=city", "N03_004=", "a=x,b=x", "x,a=x"] {
+            assert!(PropertyMap::parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn every_feature_is_mapped() {
+        let mut collection: FeatureCollection = r#"{"type":"FeatureCollection","features":[
+            {"type":"Feature","properties":{"N03_004":"川越市","N03_007":"11201"},"geometry":null},
+            {"type":"Feature","properties":{"N03_007":"11202"},"geometry":null},
+            {"type":"Feature","properties":null,"geometry":null}
+        ]}"#
+        .parse()
+        .unwrap();
+        PropertyMap::parse("N03_004=city")
+            .unwrap()
+            .apply_all(&mut collection);
+        let mapped: Vec<Option<JsonObject>> = collection
+            .features
+            .into_iter()
+            .map(|feature| feature.properties)
+            .collect();
+        assert_eq!(
+            mapped,
+            [
+                Some(properties(r#"{"city":"川越市"}"#)),
+                Some(JsonObject::new()),
+                None,
+            ]
+        );
+        assert_eq!(
+            PropertyMap::parse("a")
+                .unwrap()
+                .apply(&properties(r#"{"a":1}"#))["a"],
+            JsonValue::from(1)
+        );
+    }
+}
@@ -0,0 +1,32 @@
+//! HTML と XML に文字列を書き込むときのエスケープ（ページの出力、GraphML、テンプレート、サーバーのページで共通）。
+
+/// 本文と属性値（" と ' のどちらで囲んでも）に書ける文字列にする。
+/// `'` は HTML と XML の両方で使える数値文字参照にする
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_markup_and_both_quotes() {
+        assert_eq!(
+            escape(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+        assert_eq!(escape("川越市"), "川越市");
+    }
+}
@@ -0,0 +1,224 @@
+//! 標準地域メッシュ（JIS X 0410）ごとの面積の集計（`layon mesh`）。
+//!
+//! 統計局の地域メッシュ統計に合わせるため、Feature のポリゴンをメッシュの区画（3 次メッシュ = 約 1km、
+//! 2 分の 1 地域メッシュ = 約 500m）で切り分け、集計キー × メッシュコードごとに交差部分の面積を合計する。
+//! 座標は経度・緯度（JGD2011 など）であること。
+//!
+//! 区画は Feature の外接矩形にかかるものだけを調べる。先に緯度方向の帯で切ってから帯の中の区画で切るので、
+//! 大きな Feature でも交差の計算は区画の数に比例する程度で済む。
+
+use crate::{
+    aggregate::{self, to_multi_polygon, Metric},
+    flat::FlatPolygons,
+};
+use geo::{BooleanOps, BoundingRect, Geometry, MultiPolygon, Rect};
+use geojson::{FeatureCollection, Value};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// メッシュの大きさ
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MeshLevel {
+    /// 3 次メッシュ（緯度 30 秒 × 経度 45 秒、約 1km。8 桁のコード）
+    Third,
+    /// 2 分の 1 地域メッシュ（3 次メッシュを 4 つに分けたもの、約 500m。9 桁のコード）
+    Half,
+}
+
+impl MeshLevel {
+    /// `1km` または `500m`
+    pub fn parse(name: &str) -> Result<MeshLevel, String> {
+        match name {
+            "1km" | "3" => Ok(MeshLevel::Third),
+            "500m" | "half" => Ok(MeshLevel::Half),
+            _ => Err(format!(
+                "メッシュの大きさが不正です: {} (1km, 500m のいずれか)",
+                name
+            )),
+        }
+    }
+
+    /// 1 度あたりの区画の数（緯度方向, 経度方向）
+    fn cells_per_degree(self) -> (f64, f64) {
+        match self {
+            MeshLevel::Third => (120.0, 80.0),
+            MeshLevel::Half => (240.0, 160.0),
+        }
+    }
+
+    /// 南から `row` 番目、東経 100 度から東へ `column` 番目の区画のメッシュコード
+    fn code(self, row: i64, column: i64) -> String {
+        let (row, column, quarter) = match self {
+            MeshLevel::Third => (row, column, None),
+            // 南西 1、南東 2、北西 3、北東 4
+            MeshLevel::Half => (row / 2, column / 2, Some(1 + (row % 2) * 2 + column % 2)),
+        };
+        let mut code = format!(
+            "{:02}{:02}{}{}{}{}",
+            row / 80,
+            column / 80,
+            row % 80 / 10,
+            column % 80 / 10,
+            row % 10,
+            column % 10
+        );
+        if let Some(quarter) = quarter {
+            code.push_str(&quarter.to_string());
+        }
+        code
+    }
+}
+
+/// 集計キー × メッシュコードごとの交差部分の面積
+pub struct MeshRow {
+    pub city: String,
+    pub mesh: String,
+    pub area: f64,
+}
+
+/// `group_by` × メッシュコードごとに、ポリゴンと区画の交差部分の面積を合計する（集計キー、メッシュコードの順に並べる）
+pub fn aggregate(
+    collection: &FeatureCollection,
+    group_by: &str,
+    level: MeshLevel,
+    metric: Metric,
+) -> Vec<MeshRow> {
+    let areas = collection
+        .features
+        .par_iter()
+        .fold(HashMap::<(String, String), f64>::new, |mut map, feature| {
+            let Some(city) = feature
+                .properties
+                .as_ref()
+                .and_then(|properties| properties.get(group_by))
+                .and_then(|value| value.as_str())
+            else {
+                return map;
+            };
+            let Some(polygons) = feature
+                .geometry
+                .as_ref()
+                .and_then(|geometry| Geometry::<f64>::try_from(geometry.value.clone()).ok())
+                .and_then(to_multi_polygon)
+            else {
+                return map;
+            };
+            for (mesh, area) in split(&polygons, level, metric) {
+                *map.entry((city.to_string(), mesh)).or_insert(0.0) += area;
+            }
+            map
+        })
+        .reduce(HashMap::new, |mut a, b| {
+            for (key, area) in b {
+                *a.entry(key).or_insert(0.0) += area;
+            }
+            a
+        });
+
+    let mut rows: Vec<MeshRow> = areas
+        .into_iter()
+        .map(|((city, mesh), area)| MeshRow { city, mesh, area })
+        .collect();
+    rows.sort_by(|a, b| a.city.cmp(&b.city).then_with(|| a.mesh.cmp(&b.mesh)));
+    rows
+}
+
+/// ポリゴンを区画で切り分け、区画ごとの面積を返す（面積が 0 の区画は含めない）
+fn split(polygons: &MultiPolygon<f64>, level: MeshLevel, metric: Metric) -> Vec<(String, f64)> {
+    let Some(rect) = polygons.bounding_rect() else {
+        return Vec::new();
+    };
+    let (rows, columns) = level.cells_per_degree();
+    let row_range = (rect.min().y * rows).floor() as i64..=(rect.max().y * rows).floor() as i64;
+    let column_range = ((rect.min().x - 100.0) * columns).floor() as i64
+        ..=((rect.max().x - 100.0) * columns).floor() as i64;
+    let mut cells = Vec::new();
+    let mut flat = FlatPolygons::default();
+    for row in row_range {
+        let (south, north) = (row as f64 / rows, (row + 1) as f64 / rows);
+        let band = polygons.intersection(&cell(rect.min().x, south, rect.max().x, north));
+        if band.0.is_empty() {
+            continue;
+        }
+        for column in column_range.clone() {
+            let (west, east) = (
+                100.0 + column as f64 / columns,
+                100.0 + (column + 1) as f64 / columns,
+            );
+            let piece = band.intersection(&cell(west, south, east, north));
+            if piece.0.is_empty() {
+                continue;
+            }
+            flat.load(&Value::from(&piece));
+            let area = aggregate::measure(&flat, metric);
+            if area > 0.0 {
+                cells.push((level.code(row, column), area));
+            }
+        }
+    }
+    cells
+}
+
+/// 経度・緯度の範囲の矩形
+fn cell(west: f64, south: f64, east: f64, north: f64) -> MultiPolygon<f64> {
+    MultiPolygon::new(vec![Rect::new((west, south), (east, north)).to_polygon()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pipeline::Pipeline,
+        source::{Input, InputOptions},
+    };
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    /// 点 (経度, 緯度) を含む区画のメッシュコード
+    fn code_at(level: MeshLevel, lon: f64, lat: f64) -> String {
+        let (rows, columns) = level.cells_per_degree();
+        level.code(
+            (lat * rows).floor() as i64,
+            ((lon - 100.0) * columns).floor() as i64,
+        )
+    }
+
+    #[test]
+    fn areas_add_up_to_each_city_and_use_jis_codes() {
+        // 東京駅は 3 次メッシュ 53394611 の北西の 2 分の 1 地域メッシュにある
+        assert_eq!(code_at(MeshLevel::Third, 139.7671, 35.6812), "53394611");
+        assert_eq!(code_at(MeshLevel::Half, 139.7671, 35.6812), "533946113");
+
+        let collection = Input::parse(FIXTURE, InputOptions::default())
+            .unwrap()
+            .read()
+            .unwrap();
+        let cities = Pipeline::read(FIXTURE)
+            .metric(Metric::GeodesicArea)
+            .run()
+            .unwrap()
+            .rows;
+        for level in [MeshLevel::Third, MeshLevel::Half] {
+            let rows = aggregate(&collection, "N03_004", level, Metric::GeodesicArea);
+            let digits = if level == MeshLevel::Third { 8 } else { 9 };
+            assert!(rows.iter().all(|row| row.mesh.len() == digits));
+            for city in &cities {
+                let total: f64 = rows
+                    .iter()
+                    .filter(|row| row.city == city.key)
+                    .map(|row| row.area)
+                    .sum();
+                assert!(
+                    (total - city.area).abs() < city.area * 1e-6,
+                    "{}: {} != {}",
+                    city.key,
+                    total,
+                    city.area
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! 集計キーの正規化（`--normalize-key`）。
+//! 手入力の混じったデータでは "さいたま市 " と "さいたま市"、"ｻｲﾀﾏ" と "サイタマ" が別の行になってしまうため、
+//! 集計の前に集計キーのプロパティの値をそろえる。文字列でない値は変えない。
+
+use geojson::{FeatureCollection, JsonValue};
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// `honorific` で末尾から除く敬称
+pub const HONORIFICS: [&str; 4] = ["御中", "さん", "様", "殿"];
+
+/// 半角カタカナ（U+FF61〜U+FF9F）に対応する全角の文字
+const HALF_WIDTH_KANA: &str = "。「」、・ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン゛゜";
+
+/// 集計キーのそろえ方
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KeyNormalization {
+    /// 前後の空白（全角の空白も）を除く
+    pub trim: bool,
+    /// 全角の英数字・記号と空白を半角に、半角のカタカナを全角にそろえる
+    pub width: bool,
+    /// 末尾の敬称（`HONORIFICS`）を除く
+    pub honorific: bool,
+    /// 英字を小文字にそろえる
+    pub case: bool,
+}
+
+impl KeyNormalization {
+    /// `trim,width` のようにカンマで区切った規則を解析する（`all` ならすべて）
+    pub fn parse(text: &str) -> Result<KeyNormalization, String> {
+        let mut normalization = KeyNormalization::default();
+        for rule in text
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+        {
+            match rule {
+                "trim" => normalization.trim = true,
+                "width" => normalization.width = true,
+                "honorific" => normalization.honorific = true,
+                "case" => normalization.case = true,
+                "all" => {
+                    normalization = KeyNormalization {
+                        trim: true,
+                        width: true,
+                        honorific: true,
+                        case: true,
+                    }
+                }
+                _ => return Err(format!("不明な正規化の規則です: {}", rule)),
+            }
+        }
+        if normalization == KeyNormalization::default() {
+            return Err(
+                "正規化の規則 (trim, width, honorific, case, all) を指定してください".to_string(),
+            );
+        }
+        Ok(normalization)
+    }
+
+    /// 実行計画の表示用の説明（例: "前後の空白を除く、全角と半角をそろえる"）
+    pub fn describe(&self) -> String {
+        [
+            (self.trim, "前後の空白を除く"),
+            (self.width, "全角と半角をそろえる"),
+            (self.honorific, "末尾の敬称を除く"),
+            (self.case, "英字を小文字にそろえる"),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, label)| *label)
+        .collect::<Vec<_>>()
+        .join("、")
+    }
+
+    /// 1 つの値をそろえる（全角と半角、空白、敬称、大文字と小文字の順）
+    pub fn apply(&self, key: &str) -> String {
+        let mut key = if self.width {
+            unify_width(key)
+        } else {
+            key.to_string()
+        };
+        if self.trim {
+            key = key.trim().to_string();
+        }
+        if self.honorific {
+            if let Some(stripped) = HONORIFICS
+                .iter()
+                .find_map(|suffix| key.strip_suffix(suffix))
+            {
+                // "田中 様" のように空白を挟んだ敬称も除く
+                key = stripped.trim_end().to_string();
+            }
+        }
+        if self.case {
+            key = key.to_lowercase();
+        }
+        key
+    }
+
+    /// 各 Feature の `property` の値をそろえ、値が変わった Feature の数を返す
+    pub fn normalize_all(&self, collection: &mut FeatureCollection, property: &str) -> usize {
+        let changed = AtomicUsize::new(0);
+        collection.features.par_iter_mut().for_each(|feature| {
+            let Some(JsonValue::String(value)) = feature
+                .properties
+                .as_mut()
+                .and_then(|properties| properties.get_mut(property))
+            else {
+                return;
+            };
+            let normalized = self.apply(value);
+            if normalized != *value {
+                *value = normalized;
+                changed.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        changed.into_inner()
+    }
+}
+
+/// 全角の ASCII の文字と空白を半角に、半角のカタカナ（濁点・半濁点を含む）を全角にする
+fn unify_width(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c as u32 {
+            0xFF01..=0xFF5E => result.push(char::from_u32(c as u32 - 0xFEE0).unwrap()),
+            0x3000 => result.push(' '),
+            code @ 0xFF61..=0xFF9F => {
+                let kana = HALF_WIDTH_KANA
+                    .chars()
+                    .nth((code - 0xFF61) as usize)
+                    .unwrap();
+                match (kana, result.pop()) {
+                    // 濁点と半濁点は前の文字と合わせて 1 文字にする（ガ、パ、ヴ）
+                    ('゛', Some('ウ')) => result.push('ヴ'),
+                    ('゛', Some(previous)) if voiced(previous) => {
+                        result.push(char::from_u32(previous as u32 + 1).unwrap())
+                    }
+                    ('゜', Some(previous @ 'ハ'..='ホ')) if voiced(previous) => {
+                        result.push(char::from_u32(previous as u32 + 2).unwrap())
+                    }
+                    (kana, previous) => {
+                        result.extend(previous);
+                        result.push(kana);
+                    }
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// 濁点を付けられる清音のカタカナ（カ〜ト、ハ〜ホの行。次の文字が濁音になる）
+fn voiced(c: char) -> bool {
+    "カキクケコサシスセソタチツテトハヒフヘホ".contains(c)
+}
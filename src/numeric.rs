@@ -0,0 +1,311 @@
+//! 数値のプロパティの集計（`--agg pop:sum,pop:area_weighted_mean`）。
+//! 面積のほかに、あらかじめ結合しておいた人口などの数値のプロパティもグループごとに集計する。
+//! 数値でない値（と数値として読めない文字列）を持つ Feature は、そのプロパティの集計に含めない。
+
+use geojson::{Feature, JsonValue};
+
+/// 集計の方法
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Function {
+    Sum,
+    Mean,
+    Min,
+    Max,
+    /// Feature の面積を重みにした平均（人口密度などの比率の値に）
+    AreaWeightedMean,
+    /// 値と Feature の面積の積の合計（密度から総量を求める場合などに）
+    AreaWeightedSum,
+}
+
+impl Function {
+    const ALL: [Function; 6] = [
+        Function::Sum,
+        Function::Mean,
+        Function::Min,
+        Function::Max,
+        Function::AreaWeightedMean,
+        Function::AreaWeightedSum,
+    ];
+
+    /// `--agg` で指定する名前
+    pub fn name(self) -> &'static str {
+        match self {
+            Function::Sum => "sum",
+            Function::Mean => "mean",
+            Function::Min => "min",
+            Function::Max => "max",
+            Function::AreaWeightedMean => "area_weighted_mean",
+            Function::AreaWeightedSum => "area_weighted_sum",
+        }
+    }
+}
+
+/// 1 つのプロパティの集計
+#[derive(Clone, Debug, PartialEq)]
+pub struct NumericAggregate {
+    pub(crate) property: String,
+    pub(crate) function: Function,
+}
+
+impl NumericAggregate {
+    /// `プロパティ:方法` をカンマで区切って並べたものを解析する
+    pub fn parse_list(text: &str) -> Result<Vec<NumericAggregate>, String> {
+        let mut aggregates: Vec<NumericAggregate> = Vec::new();
+        for entry in text.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (property, function) = entry
+                .rsplit_once(':')
+                .ok_or_else(|| format!("集計の指定が不正です (プロパティ:方法): {}", entry))?;
+            let function = Function::ALL
+                .into_iter()
+                .find(|f| f.name() == function.trim())
+                .ok_or_else(|| {
+                    format!(
+                        "集計の方法が不正です: {} ({} のいずれか)",
+                        function,
+                        Function::ALL.map(Function::name).join(", ")
+                    )
+                })?;
+            let aggregate = NumericAggregate {
+                property: property.trim().to_string(),
+                function,
+            };
+            if aggregate.property.is_empty() {
+                return Err(format!("集計するプロパティがありません: {}", entry));
+            }
+            if aggregates.contains(&aggregate) {
+                return Err(format!("集計の指定が重複しています: {}", entry));
+            }
+            aggregates.push(aggregate);
+        }
+        if aggregates.is_empty() {
+            return Err("集計するプロパティを指定してください".to_string());
+        }
+        Ok(aggregates)
+    }
+
+    /// 出力する列の名前（例: `pop_sum`）
+    pub fn column(&self) -> String {
+        format!("{}_{}", self.property, self.function.name())
+    }
+
+    /// Feature のプロパティの値（数値として読めなければ None）
+    pub fn value(&self, feature: &Feature) -> Option<f64> {
+        match feature.property(&self.property)? {
+            JsonValue::Number(number) => number.as_f64(),
+            // 属性を CSV から結合すると数値が文字列になっていることが多い
+            JsonValue::String(text) => text.trim().parse().ok(),
+            _ => None,
+        }
+        .filter(|value: &f64| value.is_finite())
+    }
+}
+
+/// グループごとの途中の集計値
+#[derive(Clone, Copy)]
+pub struct Accumulator {
+    count: usize,
+    sum: f64,
+    min: f64,
+    max: f64,
+    /// 値と面積の積の合計
+    weighted_sum: f64,
+    /// 値を持つ Feature の面積の合計
+    weight: f64,
+}
+
+impl Default for Accumulator {
+    fn default() -> Accumulator {
+        Accumulator {
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            weighted_sum: 0.0,
+            weight: 0.0,
+        }
+    }
+}
+
+impl Accumulator {
+    /// 面積が `area` の Feature の値を加える
+    pub fn add(&mut self, value: f64, area: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.weighted_sum += value * area;
+        self.weight += area;
+    }
+
+    /// 別に集めた途中の集計値を足し合わせる
+    pub fn merge(&mut self, other: &Accumulator) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.weighted_sum += other.weighted_sum;
+        self.weight += other.weight;
+    }
+
+    /// 集計値（値を持つ Feature がなければ None）
+    pub fn result(&self, function: Function) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        match function {
+            Function::Sum => Some(self.sum),
+            Function::Mean => Some(self.sum / self.count as f64),
+            Function::Min => Some(self.min),
+            Function::Max => Some(self.max),
+            Function::AreaWeightedMean => {
+                (self.weight > 0.0).then(|| self.weighted_sum / self.weight)
+            }
+            Function::AreaWeightedSum => Some(self.weighted_sum),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Pipeline;
+
+    #[test]
+    fn aggregates_are_parsed_and_values_read_from_numbers_and_strings() {
+        let aggregates = NumericAggregate::parse_list(" pop:sum, pop:area_weighted_mean,").unwrap();
+        let columns: Vec<String> = aggregates.iter().map(NumericAggregate::column).collect();
+        assert_eq!(columns, ["pop_sum", "pop_area_weighted_mean"]);
+        // プロパティの名前に : を含めてもよい
+        assert_eq!(
+            NumericAggregate::parse_list("a:b:max").unwrap()[0].column(),
+            "a:b_max"
+        );
+        for invalid in ["", "pop", ":sum", "pop:median", "pop:sum,pop:sum"] {
+            assert!(
+                NumericAggregate::parse_list(invalid).is_err(),
+                "{}",
+                invalid
+            );
+        }
+
+        let feature: Feature = r#"{"type":"Feature","properties":{"a":1.5,"b":" 2e3 ","c":"多い","d":null,"e":true},"geometry":null}"#
+            .parse()
+            .unwrap();
+        let values: Vec<Option<f64>> = ["a", "b", "c", "d", "e", "f"]
+            .iter()
+            .map(|property| {
+                NumericAggregate::parse_list(&format!("{}:sum", property)).unwrap()[0]
+                    .value(&feature)
+            })
+            .collect();
+        assert_eq!(values, [Some(1.5), Some(2000.0), None, None, None, None]);
+    }
+
+    #[test]
+    fn sums_means_and_area_weighted_values_per_group() {
+        let square = |city: &str, width: f64, properties: &str| {
+            format!(
+                r#"{{"type":"Feature","properties":{{"N03_004":"{}"{}}},"geometry":{{"type":"Polygon","coordinates":[[[0,0],[{w},0],[{w},1],[0,1],[0,0]]]}}}}"#,
+                city,
+                properties,
+                w = width
+            )
+        };
+        let features = [
+            square("川越市", 1.0, r#","pop":10,"density":100"#),
+            square("川越市", 3.0, r#","pop":"20","density":50"#),
+            // 値のない Feature は平均にも重みにも含めない
+            square("川越市", 2.0, ""),
+            square("所沢市", 1.0, r#","pop":null"#),
+        ];
+        let path =
+            std::env::temp_dir().join(format!("layon-numeric-{}.geojson", std::process::id()));
+        std::fs::write(
+            &path,
+            format!(
+                r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+                features.join(",")
+            ),
+        )
+        .unwrap();
+        let result = Pipeline::read(path.to_str().unwrap())
+            .numeric(
+                NumericAggregate::parse_list(
+                    "pop:sum,pop:mean,pop:min,pop:max,density:area_weighted_mean,density:area_weighted_sum",
+                )
+                .unwrap(),
+            )
+            .run()
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let values = |key: &str| -> Vec<(String, Option<f64>)> {
+            result
+                .rows
+                .iter()
+                .find(|row| row.key == key)
+                .unwrap()
+                .values
+                .clone()
+        };
+        let expected = |values: [Option<f64>; 6]| -> Vec<(String, Option<f64>)> {
+            [
+                "pop_sum",
+                "pop_mean",
+                "pop_min",
+                "pop_max",
+                "density_area_weighted_mean",
+                "density_area_weighted_sum",
+            ]
+            .iter()
+            .map(|column| column.to_string())
+            .zip(values)
+            .collect()
+        };
+        // 重み付き平均は (100 × 1 + 50 × 3) / 4、重み付き合計は 100 × 1 + 50 × 3
+        assert_eq!(
+            values("川越市"),
+            expected([
+                Some(30.0),
+                Some(15.0),
+                Some(10.0),
+                Some(20.0),
+                Some(62.5),
+                Some(250.0)
+            ])
+        );
+        assert_eq!(values("所沢市"), expected([None; 6]));
+    }
+
+    #[test]
+    fn merged_accumulators_match_a_single_accumulator() {
+        let values = [(3.0, 1.0), (-1.0, 2.0), (4.0, 0.5), (1.0, 0.0)];
+        let mut whole = Accumulator::default();
+        let (mut first, mut second) = (Accumulator::default(), Accumulator::default());
+        for (i, &(value, area)) in values.iter().enumerate() {
+            whole.add(value, area);
+            if i % 2 == 0 { &mut first } else { &mut second }.add(value, area);
+        }
+        first.merge(&second);
+        for function in Function::ALL {
+            assert_eq!(
+                first.result(function),
+                whole.result(function),
+                "{:?}",
+                function
+            );
+        }
+        assert_eq!(whole.result(Function::Min), Some(-1.0));
+        assert_eq!(
+            whole.result(Function::AreaWeightedSum),
+            Some(3.0 - 2.0 + 2.0)
+        );
+
+        // 面積のない Feature だけなら重み付き平均は求められない
+        let mut points = Accumulator::default();
+        points.add(5.0, 0.0);
+        assert_eq!(points.result(Function::AreaWeightedMean), None);
+        assert_eq!(points.result(Function::Mean), Some(5.0));
+        assert_eq!(Accumulator::default().result(Function::Sum), None);
+    }
+}
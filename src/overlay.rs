@@ -0,0 +1,220 @@
+//! 2 つのレイヤーの重ね合わせ（`overlay` サブコマンド）。
+//!
+//! 市町村の境界と浸水想定区域のように、集計キーの異なる 2 つのポリゴンのレイヤーを重ね、
+//! (左側のキー, 右側のキー) の組ごとに交差した部分の面積を合計する。
+//! 面積は `aggregate` と同じ `Metric` で求める（座標の単位のまま、楕円体上、または投影した平面上）。
+
+use crate::{
+    aggregate::{self, to_multi_polygon, Metric},
+    flat::FlatPolygons,
+};
+use geo::{Area, BooleanOps, BoundingRect, Geometry, MultiPolygon};
+use geojson::{FeatureCollection, Value};
+use rayon::prelude::*;
+use rstar::{primitives::GeomWithData, primitives::Rectangle, RTree, AABB};
+use std::collections::HashMap;
+
+/// 2 つのレイヤーの重なりの集計結果（市町村 × ゾーンごとの交差面積）
+pub struct OverlayRow {
+    pub city: String,
+    pub zone: String,
+    pub area: f64,
+}
+
+/// 集計キーとポリゴンの組
+type Keyed = (String, MultiPolygon<f64>);
+
+/// `left` と `right` のポリゴンを総当たりで交差させ、キーの組ごとに交差面積を合計する。
+/// 右側のレイヤーを R-tree に入れ、外接矩形が重なる組だけを候補にする。
+/// 面積は `metric` で求める（`Metric::GeodesicLength` は線の長さのため 0 になる）。
+pub fn overlay(
+    left: &FeatureCollection,
+    left_key: &str,
+    right: &FeatureCollection,
+    right_key: &str,
+    metric: Metric,
+) -> Vec<OverlayRow> {
+    let left = keyed_polygons(left, left_key);
+    let right = keyed_polygons(right, right_key);
+
+    let tree = RTree::bulk_load(
+        right
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (_, polygons))| {
+                let rect = polygons.bounding_rect()?;
+                Some(GeomWithData::new(
+                    Rectangle::from_corners(
+                        [rect.min().x, rect.min().y],
+                        [rect.max().x, rect.max().y],
+                    ),
+                    i,
+                ))
+            })
+            .collect(),
+    );
+
+    // 左側の Feature ごとに並列に交差を計算し、(市町村, ゾーン) -> 面積 にまとめる
+    let areas = left
+        .par_iter()
+        .fold(
+            HashMap::<(&str, &str), f64>::new,
+            |mut map, (city, polygons)| {
+                let Some(rect) = polygons.bounding_rect() else {
+                    return map;
+                };
+                let envelope =
+                    AABB::from_corners([rect.min().x, rect.min().y], [rect.max().x, rect.max().y]);
+                for candidate in tree.locate_in_envelope_intersecting(&envelope) {
+                    let (zone, other) = &right[candidate.data];
+                    let area = measure(&polygons.intersection(other), metric);
+                    if area > 0.0 {
+                        *map.entry((city.as_str(), zone.as_str())).or_insert(0.0) += area;
+                    }
+                }
+                map
+            },
+        )
+        .reduce(HashMap::new, |mut a, b| {
+            for (key, area) in b {
+                *a.entry(key).or_insert(0.0) += area;
+            }
+            a
+        });
+
+    let mut rows: Vec<OverlayRow> = areas
+        .into_iter()
+        .map(|((city, zone), area)| OverlayRow {
+            city: city.to_string(),
+            zone: zone.to_string(),
+            area,
+        })
+        .collect();
+
+    // 市町村ごとに、交差面積の降順に並べる
+    rows.sort_by(|a, b| a.city.cmp(&b.city).then(b.area.total_cmp(&a.area)));
+    rows
+}
+
+/// 交差した部分の面積
+fn measure(polygons: &MultiPolygon<f64>, metric: Metric) -> f64 {
+    match metric {
+        Metric::Area => polygons.unsigned_area(),
+        _ => {
+            let mut flat = FlatPolygons::default();
+            flat.load(&Value::from(polygons));
+            aggregate::measure(&flat, metric)
+        }
+    }
+}
+
+/// キーのプロパティを持つポリゴンの Feature を取り出す
+fn keyed_polygons(collection: &FeatureCollection, key: &str) -> Vec<Keyed> {
+    collection
+        .features
+        .par_iter()
+        .filter_map(|feature| {
+            let name = feature.properties.as_ref()?.get(key)?.as_str()?;
+            let geometry: Geometry<f64> =
+                feature.geometry.as_ref()?.value.clone().try_into().ok()?;
+            Some((name.to_string(), to_multi_polygon(geometry)?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projection::Projection;
+
+    /// x が `x0` から `x1`、y が `y0` から `y1` までの長方形を並べたレイヤー
+    fn layer(key: &str, rects: &[(&str, [f64; 4])]) -> FeatureCollection {
+        let features: Vec<String> = rects
+            .iter()
+            .map(|(name, [x0, y0, x1, y1])| {
+                format!(
+                    r#"{{"type":"Feature","properties":{{"{key}":"{name}"}},"geometry":{{"type":"Polygon","coordinates":[[[{x0},{y0}],[{x1},{y0}],[{x1},{y1}],[{x0},{y1}],[{x0},{y0}]]]}}}}"#
+                )
+            })
+            .collect();
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        )
+        .parse()
+        .unwrap()
+    }
+
+    fn rows(rows: &[OverlayRow]) -> Vec<(&str, &str, f64)> {
+        rows.iter()
+            .map(|row| (row.city.as_str(), row.zone.as_str(), row.area))
+            .collect()
+    }
+
+    #[test]
+    fn intersection_areas_are_summed_per_key_pair() {
+        let cities = layer(
+            "N03_004",
+            &[
+                ("甲", [0.0, 0.0, 2.0, 2.0]),
+                ("乙", [2.0, 0.0, 4.0, 2.0]),
+                // 同じ集計キーの 2 つ目のポリゴンの交差面積も足す
+                ("甲", [1.0, 2.0, 2.0, 3.0]),
+            ],
+        );
+        let zones = layer(
+            "zone",
+            &[
+                ("A", [1.0, 0.0, 2.5, 1.0]),
+                ("B", [1.5, 1.5, 4.0, 3.0]),
+                // 外接矩形も重ならない
+                ("C", [10.0, 10.0, 11.0, 11.0]),
+                // 辺で接するだけで面積はない
+                ("D", [4.0, 0.0, 5.0, 2.0]),
+            ],
+        );
+        let result = overlay(&cities, "N03_004", &zones, "zone", Metric::Area);
+        assert_eq!(
+            rows(&result),
+            [
+                ("乙", "B", 1.0),
+                ("乙", "A", 0.5),
+                ("甲", "A", 1.0),
+                ("甲", "B", 0.75),
+            ]
+        );
+    }
+
+    #[test]
+    fn intersection_areas_follow_the_metric() {
+        let cities = layer("N03_004", &[("甲", [139.0, 35.0, 140.0, 36.0])]);
+        let zones = layer("zone", &[("A", [139.5, 35.0, 141.0, 36.0])]);
+        // 交差した部分（東経 139.5〜140 度、北緯 35〜36 度）の面積と同じになる
+        let expected = layer("zone", &[("A", [139.5, 35.0, 140.0, 36.0])]);
+        for (name, metric) in [
+            ("geodesic-area", Metric::GeodesicArea),
+            (
+                "jgd2011-albers",
+                Metric::Projected(Projection::parse("jgd2011-albers").unwrap()),
+            ),
+        ] {
+            let result = overlay(&cities, "N03_004", &zones, "zone", metric);
+            let area = aggregate::feature_area(&expected.features[0], metric);
+            assert_eq!(result.len(), 1);
+            assert!(
+                (result[0].area - area).abs() < 1e-6 * area,
+                "{}: {} != {}",
+                name,
+                result[0].area,
+                area
+            );
+        }
+        // 緯度 35 度付近の経度 0.5 度 × 緯度 1 度は約 5,100 km²
+        let geodesic = overlay(&cities, "N03_004", &zones, "zone", Metric::GeodesicArea);
+        assert!(
+            (5000.0..5200.0).contains(&geodesic[0].area),
+            "{}",
+            geodesic[0].area
+        );
+    }
+}
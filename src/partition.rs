@@ -0,0 +1,394 @@
+//! 都道府県などのプロパティで分けた集計（`--partition-by`, `--checkpoint-dir`）。
+//!
+//! 全国のデータを集計する場合に、先に Feature を `partition_by` のプロパティ（N03_001 など）の値ごとの分割に分け、
+//! 分割ごとに別の集計用の表で並列に集計してから結果をまとめる。同じ分割の Feature は連続して並ぶため、
+//! 1 つの集計用の表に全国の市町村が集まる場合よりもキャッシュに載りやすい。
+//!
+//! チェックポイントのディレクトリを指定すると、集計し終えた分割の結果を 1 つずつファイルに書き出し、
+//! 次に同じ集計を実行したときはその分割を集計し直さずに読み込む（途中で中断した集計をその続きから再開できる）。
+//! ファイルの名前は layon のバージョン、集計の設定、分割の Feature の内容から作るため、入力が変われば使われない。
+//!
+//! 一時ディレクトリに書き出す（`Storage::spill`）場合は、分けた Feature を分割ごとに一時ディレクトリ（`scratch`）のファイルにして
+//! メモリから外し、集計する分割だけを読み込む。
+//!
+//! 同じ集計キーが複数の分割にある場合（別の都道府県の同じ名前の市など）は、分けずに集計した場合と同じく 1 行にまとめる。
+//! ただし平均（mean, area-weighted-mean）は分割ごとの結果からは求められないため、エラーにする。
+
+use crate::{
+    aggregate::{self, Aggregation, Extras, GroupResult, Metric},
+    cancel::CancellationToken,
+    geometry_type::TypeCounts,
+    numeric::Function,
+    schedule::Schedule,
+    scratch, sha256,
+    timing::Timings,
+};
+use geo::BooleanOps;
+use geojson::{Feature, FeatureCollection, JsonValue};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// 分けて集計した結果
+pub struct Partitioned {
+    pub aggregation: Aggregation,
+    /// 分割の数
+    pub partitions: usize,
+    /// チェックポイントから読み込んだ分割の数
+    pub restored: usize,
+}
+
+/// 分割の途中の結果の置き場所
+#[derive(Clone, Copy, Default)]
+pub struct Storage<'a> {
+    /// 集計し終えた分割の結果を保存するディレクトリ
+    pub checkpoint_dir: Option<&'a str>,
+    /// 集計するまで分割の Feature を一時ディレクトリに書き出しておく
+    pub spill: bool,
+}
+
+/// 分けた Feature（メモリの中か、一時ディレクトリのファイル）
+enum Part {
+    Memory(FeatureCollection),
+    Spilled(PathBuf),
+}
+
+impl Part {
+    fn load(self) -> Result<FeatureCollection, Box<dyn Error + Send + Sync>> {
+        match self {
+            Part::Memory(collection) => Ok(collection),
+            Part::Spilled(path) => {
+                let features: Vec<Feature> = serde_json::from_slice(&fs::read(&path)?)?;
+                scratch::current()?.remove(&path)?;
+                Ok(FeatureCollection {
+                    bbox: None,
+                    features,
+                    foreign_members: None,
+                })
+            }
+        }
+    }
+}
+
+/// チェックポイントのファイルの内容
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    partition: String,
+    /// 集計した Feature の数
+    processed: usize,
+    rows: Vec<CheckpointRow>,
+    /// ジオメトリの種類ごとの数（面、線、点、空）
+    types: [usize; 4],
+    /// 座標が足りず集計しなかった Feature の数（これより前のチェックポイントにはない）
+    #[serde(default)]
+    invalid: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CheckpointRow {
+    key: String,
+    layer: Option<String>,
+    area: f64,
+    count: usize,
+    ids: Vec<String>,
+    values: Vec<(String, Option<f64>)>,
+}
+
+/// `partition_by` の値で Feature を分け（値が文字列でない Feature は名前のない 1 つの分割にまとめる）、
+/// 分割ごとに並列に集計してから結果をまとめる
+pub fn aggregate(
+    collection: FeatureCollection,
+    partition_by: &str,
+    group_by: &str,
+    extras: &Extras,
+    metric: Metric,
+    cancel: &CancellationToken,
+    storage: Storage,
+) -> Result<Partitioned, Box<dyn Error>> {
+    if storage.checkpoint_dir.is_some() && extras.geometry {
+        return Err("ディゾルブしたジオメトリはチェックポイントに保存できません".into());
+    }
+    let partitions = split(collection, partition_by);
+    let count = partitions.len();
+    let partitions: Vec<(String, Part)> = if storage.spill {
+        let scratch = scratch::current()?;
+        partitions
+            .into_par_iter()
+            .map(|(name, collection)| {
+                let path = scratch
+                    .store(&serde_json::to_vec(&collection.features)?)
+                    .map_err(|err| err.to_string())?;
+                Ok((name, Part::Spilled(path)))
+            })
+            .collect::<Result<_, Box<dyn Error + Send + Sync>>>()
+            .map_err(|err| err as Box<dyn Error>)?
+    } else {
+        partitions
+            .into_iter()
+            .map(|(name, collection)| (name, Part::Memory(collection)))
+            .collect()
+    };
+    let results = partitions
+        .into_par_iter()
+        .map(|(name, part)| {
+            let collection = part.load()?;
+            let path = match storage.checkpoint_dir {
+                Some(dir) => Some(checkpoint_path(
+                    dir,
+                    &name,
+                    &collection,
+                    group_by,
+                    extras,
+                    metric,
+                )?),
+                None => None,
+            };
+            if let Some(checkpoint) = path.as_deref().map(restore).transpose()?.flatten() {
+                return Ok((name, checkpoint, true));
+            }
+            let aggregation = aggregate::aggregate_until(
+                &collection,
+                group_by,
+                extras,
+                metric,
+                Schedule::ByCost,
+                cancel,
+            );
+            // 中断された分割は途中までの結果なので保存しない
+            if let (Some(path), None) = (&path, &aggregation.cancelled) {
+                store(path, &name, &aggregation)?;
+            }
+            Ok((name, aggregation, false))
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error + Send + Sync>>>()
+        .map_err(|err| err as Box<dyn Error>)?;
+
+    let restored = results.iter().filter(|(_, _, restored)| *restored).count();
+    let mut processed = 0;
+    let mut cancelled = None;
+    let mut timings = Timings::default();
+    let mut types = TypeCounts::default();
+    let mut partition_rows = Vec::new();
+    for (name, aggregation, _) in results {
+        processed += aggregation.processed;
+        cancelled = cancelled.or(aggregation.cancelled);
+        timings.merge(aggregation.timings);
+        types.merge(aggregation.types);
+        partition_rows.push((name, aggregation.rows));
+    }
+    Ok(Partitioned {
+        aggregation: Aggregation {
+            rows: merge(partition_rows, extras)?,
+            processed,
+            cancelled,
+            timings,
+            types,
+        },
+        partitions: count,
+        restored,
+    })
+}
+
+/// Feature を `property` の値ごとに分ける（分割は値が初めて現れた順、分割の中の Feature は入力の順）
+fn split(collection: FeatureCollection, property: &str) -> Vec<(String, FeatureCollection)> {
+    let mut index = HashMap::new();
+    let mut partitions: Vec<(String, Vec<Feature>)> = Vec::new();
+    for feature in collection.features {
+        let name = feature
+            .property(property)
+            .and_then(JsonValue::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let i = *index.entry(name.clone()).or_insert_with(|| {
+            partitions.push((name, Vec::new()));
+            partitions.len() - 1
+        });
+        partitions[i].1.push(feature);
+    }
+    partitions
+        .into_iter()
+        .map(|(name, features)| {
+            (
+                name,
+                FeatureCollection {
+                    bbox: None,
+                    features,
+                    foreign_members: None,
+                },
+            )
+        })
+        .collect()
+}
+
+/// 分割ごとの結果を 1 つにまとめ、面積の降順（レイヤーごとに分けた場合はレイヤーの名前の順）に並べる
+fn merge(
+    partitions: Vec<(String, Vec<GroupResult>)>,
+    extras: &Extras,
+) -> Result<Vec<GroupResult>, String> {
+    let mut rows: Vec<GroupResult> = Vec::new();
+    // (レイヤー, 集計キー) -> (rows の中の位置, 最初に現れた分割)
+    let mut index: HashMap<(Option<String>, String), (usize, String)> = HashMap::new();
+    for (name, partition_rows) in partitions {
+        for row in partition_rows {
+            let id = (row.layer.clone(), row.key.clone());
+            let Some((i, first)) = index.get(&id) else {
+                index.insert(id, (rows.len(), name.clone()));
+                rows.push(row);
+                continue;
+            };
+            let merged = &mut rows[*i];
+            merged.area += row.area;
+            merged.count += row.count;
+            merged.ids.extend(row.ids);
+            merged.geometry = match (merged.geometry.take(), row.geometry) {
+                (Some(a), Some(b)) => Some(a.union(&b)),
+                (a, b) => a.or(b),
+            };
+            for (position, ((column, value), (_, other))) in
+                merged.values.iter_mut().zip(row.values).enumerate()
+            {
+                // `Extras::numeric` の後ろは種類別の数と線の長さ（どちらも合計）
+                let function = extras
+                    .numeric
+                    .get(position)
+                    .map_or(Function::Sum, |aggregate| aggregate.function);
+                *value = match (function, *value, other) {
+                    (_, None, other) => other,
+                    (_, value, None) => value,
+                    (Function::Sum | Function::AreaWeightedSum, Some(a), Some(b)) => Some(a + b),
+                    (Function::Min, Some(a), Some(b)) => Some(a.min(b)),
+                    (Function::Max, Some(a), Some(b)) => Some(a.max(b)),
+                    (Function::Mean | Function::AreaWeightedMean, ..) => {
+                        return Err(format!(
+                            "集計キー {} が複数の分割 ({}, {}) にあるため、{} を分割ごとの結果から求められません (--partition-by を外すか、集計キーを分割ごとに分かれるものにしてください)",
+                            merged.key, first, name, column
+                        ))
+                    }
+                };
+            }
+        }
+    }
+    rows.sort_by(|a, b| a.layer.cmp(&b.layer).then(b.area.total_cmp(&a.area)));
+    Ok(rows)
+}
+
+/// 分割のチェックポイントのファイル。集計の設定と分割の Feature の内容の SHA-256 を名前にする
+fn checkpoint_path(
+    dir: &str,
+    name: &str,
+    collection: &FeatureCollection,
+    group_by: &str,
+    extras: &Extras,
+    metric: Metric,
+) -> io::Result<PathBuf> {
+    let metric = match metric {
+        Metric::Area => "area".to_string(),
+        Metric::GeodesicArea => "geodesic-area".to_string(),
+        Metric::Projected(projection) => projection.name(),
+        Metric::GeodesicLength => "length".to_string(),
+    };
+    let numeric: Vec<String> = extras.numeric.iter().map(|n| n.column()).collect();
+    let mut header = format!(
+        "layon {}\n{}\0{}\0{}\0{}\0{}\0{}\0{}\n",
+        env!("CARGO_PKG_VERSION"),
+        name,
+        group_by,
+        metric,
+        extras.ids,
+        extras.geometry_types,
+        extras.layers,
+        numeric.join(",")
+    )
+    .into_bytes();
+    header.extend(serde_json::to_vec(&collection.features)?);
+    let key = sha256::hex_digest(io::Cursor::new(header))?;
+    Ok(Path::new(dir).join(format!("{}.json", key)))
+}
+
+/// チェックポイントがあれば読み込む
+fn restore(path: &Path) -> Result<Option<Aggregation>, Box<dyn Error + Send + Sync>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let checkpoint: Checkpoint = serde_json::from_str(&text).map_err(|err| {
+        format!(
+            "チェックポイント {} を読み込めません: {}",
+            path.display(),
+            err
+        )
+    })?;
+    let [polygons, lines, points, empty] = checkpoint.types;
+    let rows: Vec<GroupResult> = checkpoint
+        .rows
+        .into_iter()
+        .map(|row| GroupResult {
+            key: row.key,
+            layer: row.layer,
+            area: row.area,
+            count: row.count,
+            geometry: None,
+            ids: row.ids,
+            values: row.values,
+            kept: Vec::new(),
+            class: None,
+        })
+        .collect();
+    Ok(Some(Aggregation {
+        rows,
+        processed: checkpoint.processed,
+        cancelled: None,
+        timings: Timings::default(),
+        types: TypeCounts {
+            polygons,
+            lines,
+            points,
+            empty,
+            invalid: checkpoint.invalid,
+        },
+    }))
+}
+
+/// 分割の結果をチェックポイントに書き出す（途中で止まっても壊れたファイルが残らないよう、別名で書いてから置き換える）
+fn store(
+    path: &Path,
+    name: &str,
+    aggregation: &Aggregation,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let checkpoint = Checkpoint {
+        partition: name.to_string(),
+        processed: aggregation.processed,
+        rows: aggregation
+            .rows
+            .iter()
+            .map(|row| CheckpointRow {
+                key: row.key.clone(),
+                layer: row.layer.clone(),
+                area: row.area,
+                count: row.count,
+                ids: row.ids.clone(),
+                values: row.values.clone(),
+            })
+            .collect(),
+        types: [
+            aggregation.types.polygons,
+            aggregation.types.lines,
+            aggregation.types.points,
+            aggregation.types.empty,
+        ],
+        invalid: aggregation.types.invalid,
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, serde_json::to_vec(&checkpoint)?)?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
@@ -0,0 +1,1368 @@
+//! 読み込み・絞り込み・重複の除去・集計・出力をまとめて実行するビルダー。
+//! コマンドラインの既定の動作（サブコマンドなし）もこれを使う。
+//! FeatureCollection 全体を必要とする設定を使わなければ、読み込み・解析・計算・集約を別々のスレッドで動かし、
+//! 上限のあるチャネルでつないで読み込みながら集計する。
+
+use crate::{
+    aggregate::{self, Aggregation, Extras, GroupResult, Metric, Table},
+    audit::{self, Audit},
+    dedup, derive, external,
+    filter::Filter,
+    flat::FlatPolygons,
+    holes, ids, keep, partition, repair,
+    sink::{self, Output},
+    source::{Chunk, ChunkParser, Input, InputOptions, LAYER_PROPERTY},
+    subtract::{self, Mask},
+    validate::{self, Report},
+    visit,
+};
+/// `Pipeline` の設定と結果に使う型（定義したモジュールはライブラリの外に公開しない）
+pub use crate::{
+    audit::{Action, Entry as AuditEntry},
+    cancel::{CancellationToken, Cancelled},
+    classify::Classification,
+    dedup::Duplicate,
+    derive::{Derived, SortKey},
+    filter::KeyFilter,
+    geometry_type::TypeCounts,
+    gpu::Backend,
+    holes::RingArea,
+    keep::{Conflict, Keep, Policy},
+    normalize::KeyNormalization,
+    numeric::NumericAggregate,
+    progress::{Event, Progress, Snapshot},
+    schedule::Schedule,
+    smooth::{Adjacency, Smoothing},
+    template::KeyTemplate,
+    timing::{Stage, Timings},
+    visit::FeatureView,
+};
+use geojson::{Feature, FeatureCollection};
+use rayon::prelude::*;
+use std::{
+    collections::HashSet,
+    error::Error,
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// 段階に分けて集計するときに、段階の間で受け渡す Feature のまとまりの大きさ
+const CHUNK_FEATURES: usize = 4096;
+
+/// 段階の間のチャネルに溜めておくまとまりの数の上限
+const QUEUED_CHUNKS: usize = 4;
+
+/// 集計結果の書き出し先
+pub trait Sink {
+    /// 書き出しにディゾルブしたジオメトリが必要かどうか
+    fn needs_geometry(&self) -> bool {
+        false
+    }
+
+    fn write(&self, rows: &[GroupResult]) -> Result<(), Box<dyn Error>>;
+}
+
+impl Sink for Output {
+    fn needs_geometry(&self) -> bool {
+        Output::needs_geometry(self)
+    }
+
+    fn write(&self, rows: &[GroupResult]) -> Result<(), Box<dyn Error>> {
+        Output::write(self, rows)
+    }
+}
+
+/// CSV ファイルへの書き出し
+pub struct Csv {
+    path: String,
+    format: sink::csv::Format,
+}
+
+impl Csv {
+    pub fn new(path: impl Into<String>) -> Csv {
+        Csv {
+            path: path.into(),
+            format: sink::csv::Format::default(),
+        }
+    }
+
+    /// 数値の書き方などの書式
+    pub fn format(mut self, format: sink::csv::Format) -> Csv {
+        self.format = format;
+        self
+    }
+}
+
+impl Sink for Csv {
+    fn write(&self, rows: &[GroupResult]) -> Result<(), Box<dyn Error>> {
+        sink::csv::write(&self.path, rows, &self.format)
+    }
+}
+
+/// 入力元。パスや URL の文字列は `run` のときに判定する（エラーもそこで返す）
+enum Source {
+    Text(String),
+    Input(Input),
+}
+
+/// 集計の設定。`run` を呼ぶまでは何も読み込まない
+pub struct Pipeline {
+    source: Source,
+    filter: Option<String>,
+    /// 集計キーの一覧による絞り込み
+    keys: Option<KeyFilter>,
+    /// 読み込んだ入力を RFC 7946 の規則で検証するか
+    validate: bool,
+    group_by: String,
+    metric: Metric,
+    /// 面積を計算するバックエンド
+    backend: Backend,
+    /// 重複を取り除く場合の座標の格子の幅（`Some(None)` は完全に一致するものだけ）
+    dedup: Option<Option<f64>>,
+    /// 自己交差したポリゴンを修復してから集計するか
+    make_valid: bool,
+    /// 集計の前にポリゴンを差し引くレイヤー（`--input` と同じ形式のパスや URL）
+    subtract: Option<String>,
+    sink: Option<Box<dyn Sink>>,
+    cancel: CancellationToken,
+    timeout: Option<Duration>,
+    /// 中断されたときに途中までの結果を書き出すか
+    partial: bool,
+    /// 書き出し先が使わなくても、ディゾルブしたジオメトリを結果に含めるか
+    geometry: bool,
+    /// 集計した Feature の ID を結果に含めるか
+    ids: bool,
+    /// Feature ごとの外周と穴の面積の内訳を結果に含めるか
+    rings: bool,
+    /// 集計に含めなかった Feature とジオメトリを変えた Feature の記録を結果に含めるか
+    audit: bool,
+    /// 面積のほかに集計する数値のプロパティ
+    numeric: Vec<NumericAggregate>,
+    /// ジオメトリの種類ごとの数と線の長さを集計するか
+    geometry_types: bool,
+    /// 集計した後に計算する列
+    derived: Vec<Derived>,
+    /// 隣接するグループとの境界の長さで重み付けして平滑化する列
+    smoothing: Option<Smoothing>,
+    /// 結果に引き継ぐプロパティ
+    keep: Option<Keep>,
+    /// 列の値による階級分け
+    classification: Option<Classification>,
+    /// 集計キーを作るテンプレート
+    template: Option<KeyTemplate>,
+    /// 集計キーのそろえ方
+    normalization: Option<KeyNormalization>,
+    /// Feature を分けて集計するプロパティ
+    partition_by: Option<String>,
+    /// 分割ごとの結果を保存するディレクトリ
+    checkpoint_dir: Option<String>,
+    /// 分割の Feature を一時ディレクトリに書き出しておく
+    spill: bool,
+    /// 集計用の表をメモリに置く大きさの目安の上限（超えたら一時ディレクトリに書き出しながら集計する）
+    memory_limit: Option<u64>,
+    /// 結果を並べる列（指定しなければ面積の降順）
+    sort: Option<SortKey>,
+    /// 並べた結果の先頭から残す行の数
+    top: Option<usize>,
+    /// 進み具合の通知先
+    progress: Progress,
+}
+
+/// `load` で読み込んで前処理した入力
+struct Loaded {
+    collection: FeatureCollection,
+    duplicates: Vec<Duplicate>,
+    /// 修復した Feature の数
+    repaired: usize,
+    /// 差し引くレイヤーと重なった Feature の数
+    subtracted: usize,
+    /// テンプレートから集計キーを作れなかった Feature の数
+    untemplated: usize,
+    /// 集計キーを正規化して値が変わった Feature の数
+    normalized: usize,
+    /// 集計キーの一覧のうち、入力になかったもの
+    missing_keys: Vec<String>,
+    /// 複数のレイヤーの Feature があるか（あればレイヤーごとに分けて集計する）
+    layers: bool,
+    /// 前処理の記録
+    audit: Vec<audit::Entry>,
+    /// 読み込みの途中で中断された場合、その理由（`collection` は読み込めた分だけ）
+    cancelled: Option<Cancelled>,
+}
+
+/// 段階に分けて集計するときに、計算の段階で前処理した Feature の数など
+#[derive(Default)]
+struct Prepared {
+    /// 集計の対象になった Feature の数
+    total: usize,
+    untemplated: usize,
+    normalized: usize,
+    repaired: usize,
+    missing_keys: Vec<String>,
+    /// 前処理にかかった時間
+    elapsed: Duration,
+}
+
+/// 段階に分けて集計するときの、計算の段階で使う設定
+struct Compute<'a> {
+    group_by: &'a str,
+    metric: Metric,
+    template: Option<&'a KeyTemplate>,
+    normalization: Option<&'a KeyNormalization>,
+    filter: Option<&'a Filter>,
+    keys: Option<&'a KeyFilter>,
+    make_valid: bool,
+    extras: &'a Extras,
+    progress: &'a Progress,
+}
+
+impl<'a> Compute<'a> {
+    /// 計算の段階。解析したまとまりごとに前処理（テンプレート、正規化、ID、絞り込み、修復）をして、
+    /// 面積などを集めた表を集約の段階に渡す
+    fn run(
+        &self,
+        parsed: Receiver<Result<Vec<Feature>, String>>,
+        computed: SyncSender<Result<Table<'a>, String>>,
+    ) -> Prepared {
+        let mut prepared = Prepared::default();
+        let mut seen = HashSet::new();
+        // まとまりの先頭の、入力の中での位置（絞り込みの前に数える）
+        let mut position = 0;
+        for features in parsed {
+            let features = match features {
+                Ok(features) => features,
+                Err(err) => {
+                    let _ = computed.send(Err(err));
+                    break;
+                }
+            };
+            let start = Instant::now();
+            let read = features.len();
+            let mut collection = FeatureCollection {
+                bbox: None,
+                features,
+                foreign_members: None,
+            };
+            if let Some(template) = self.template {
+                prepared.untemplated += template.assign_all(&mut collection);
+            }
+            if let Some(normalization) = self.normalization {
+                prepared.normalized += normalization.normalize_all(&mut collection, self.group_by);
+            }
+            ids::assign_from(&mut collection.features, position);
+            if let Some(filter) = self.filter {
+                collection
+                    .features
+                    .retain(|feature| filter.matches(feature));
+            }
+            if let Some(keys) = self.keys {
+                keys.retain_seen(&mut collection, self.group_by, &mut seen);
+            }
+            if self.make_valid {
+                prepared.repaired += repair::make_valid_each(&mut collection).len();
+            }
+            prepared.elapsed += start.elapsed();
+
+            let count = collection.features.len();
+            self.progress.add_total(count);
+            let table = Table::new(self.group_by, self.extras, self.metric);
+            collection
+                .features
+                .par_iter()
+                .enumerate()
+                .for_each_init(FlatPolygons::default, |flat, (i, feature)| {
+                    table.add(flat, position + i, feature)
+                });
+            self.progress.advance(count);
+            prepared.total += count;
+            position += read;
+            if computed.send(Ok(table)).is_err() {
+                break;
+            }
+        }
+        if let Some(keys) = self.keys {
+            prepared.missing_keys = keys.missing(&seen);
+        }
+        prepared
+    }
+}
+
+/// `run` の結果
+pub struct PipelineResult {
+    /// 集計結果（`sort` を指定しなければ面積の降順）
+    pub rows: Vec<GroupResult>,
+    /// 重複として取り除いた Feature（`dedup` を指定しなければ空）
+    pub duplicates: Vec<Duplicate>,
+    /// 自己交差を修復した Feature の数（`make_valid` を指定しなければ 0）
+    pub repaired: usize,
+    /// 差し引くレイヤーと重なった Feature の数（`subtract` を指定しなければ 0）
+    pub subtracted: usize,
+    /// 分けて集計した分割の数（`partition_by` を指定しなければ 0）
+    pub partitions: usize,
+    /// チェックポイントから読み込んだ分割の数
+    pub restored_partitions: usize,
+    /// 集計用の表を一時ディレクトリに書き出した回数（`memory_limit` を指定しなければ 0）
+    pub spills: usize,
+    /// テンプレートから集計キーを作れず、集計に含めなかった Feature の数（`group_by_template` を指定しなければ 0）
+    pub untemplated: usize,
+    /// 集計キーを正規化して値が変わった Feature の数（`normalize_keys` を指定しなければ 0）
+    pub normalized: usize,
+    /// `keys` の集計キーの一覧のうち、入力になかったもの（一覧の順）
+    pub missing_keys: Vec<String>,
+    /// Feature ごとの外周と穴の面積の内訳（`ring_report` を指定しなければ空）
+    pub rings: Vec<RingArea>,
+    /// 集計に含めなかった Feature とジオメトリを変えた Feature の記録（前処理の順。`audit` を指定しなければ空）
+    pub audit: Vec<audit::Entry>,
+    /// `keep` のプロパティの値が食い違ったグループ（`keep` を指定しなければ空）
+    pub conflicts: Vec<keep::Conflict>,
+    /// 集計した Feature の数
+    pub processed: usize,
+    /// 集計の対象になった Feature の数（絞り込みと重複の除去の後）
+    pub total: usize,
+    /// `partial` を指定して中断された場合、その理由（`rows` は途中までの結果）
+    pub cancelled: Option<Cancelled>,
+    /// 段階ごとにかかった時間
+    pub timings: Timings,
+    /// 集計した Feature のジオメトリの種類ごとの数（線や点は面積 0 として数に入る）
+    pub types: TypeCounts,
+}
+
+impl Pipeline {
+    /// 入力元（`--input` と同じ形式のパスや URL）から始める
+    pub fn read(source: impl Into<String>) -> Pipeline {
+        Pipeline::with_source(Source::Text(source.into()))
+    }
+
+    /// 判定済みの入力元から始める（`--sql` などの入力形式ごとのオプションを使う場合）
+    pub fn from_input(input: Input) -> Pipeline {
+        Pipeline::with_source(Source::Input(input))
+    }
+
+    fn with_source(source: Source) -> Pipeline {
+        Pipeline {
+            source,
+            filter: None,
+            keys: None,
+            validate: false,
+            group_by: "N03_004".to_string(),
+            metric: Metric::Area,
+            backend: Backend::Cpu,
+            dedup: None,
+            make_valid: false,
+            subtract: None,
+            sink: None,
+            cancel: CancellationToken::new(),
+            timeout: None,
+            partial: false,
+            geometry: false,
+            ids: false,
+            rings: false,
+            audit: false,
+            numeric: Vec::new(),
+            geometry_types: false,
+            derived: Vec::new(),
+            smoothing: None,
+            keep: None,
+            classification: None,
+            template: None,
+            normalization: None,
+            partition_by: None,
+            checkpoint_dir: None,
+            spill: false,
+            memory_limit: None,
+            sort: None,
+            top: None,
+            progress: Progress::default(),
+        }
+    }
+
+    /// 条件式（`--where` と同じ書式）を満たす Feature だけを集計する
+    pub fn filter(mut self, expression: impl Into<String>) -> Pipeline {
+        self.filter = Some(expression.into());
+        self
+    }
+
+    /// 集計キーが一覧にある（または除く一覧にない）Feature だけを集計する（正規化した後の値で比べる）
+    pub fn keys(mut self, keys: KeyFilter) -> Pipeline {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// 読み込んだ入力を RFC 7946 の規則（座標の範囲、リングの閉じ方と向きなど）で検証し、
+    /// 違反があれば集計せずに `validate::Report` のエラーを返す
+    pub fn validate(mut self, validate: bool) -> Pipeline {
+        self.validate = validate;
+        self
+    }
+
+    /// 集計キーにするプロパティ（既定: N03_004）
+    pub fn group_by(mut self, property: impl Into<String>) -> Pipeline {
+        self.group_by = property.into();
+        self
+    }
+
+    /// 複数のプロパティからテンプレートで集計キーを作る（`group_by` の代わり）。
+    /// 集計キーはテンプレートの文字列を名前にしたプロパティに入れる
+    pub fn group_by_template(mut self, template: KeyTemplate) -> Pipeline {
+        self.group_by = template.property().to_string();
+        self.template = Some(template);
+        self
+    }
+
+    /// 集計する値（既定: 座標の単位のままの面積）
+    pub fn metric(mut self, metric: Metric) -> Pipeline {
+        self.metric = metric;
+        self
+    }
+
+    /// 面積を計算するバックエンド（既定: CPU。GPU は平面上の面積だけに対応する）
+    pub fn backend(mut self, backend: Backend) -> Pipeline {
+        self.backend = backend;
+        self
+    }
+
+    /// 集計の前に重複した Feature を取り除く（`tolerance` は座標を丸める格子の幅）
+    pub fn dedup(mut self, tolerance: Option<f64>) -> Pipeline {
+        self.dedup = Some(tolerance);
+        self
+    }
+
+    /// 集計結果の書き出し先（指定しなければ結果を返すだけ）
+    pub fn sink(mut self, sink: impl Sink + 'static) -> Pipeline {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// 実行を中断するためのトークン（別のスレッドから `cancel` を呼ぶと、読み込みでは次の行や Feature の前で、
+    /// 集計では次のチャンクの前で止まる）
+    pub fn cancellation(mut self, token: CancellationToken) -> Pipeline {
+        self.cancel = token;
+        self
+    }
+
+    /// 実行を始めてから `timeout` を過ぎたら中断する
+    pub fn timeout(mut self, timeout: Duration) -> Pipeline {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// 自己交差したポリゴン（8 の字など）を交差する点で分けて組み立て直してから面積を求める
+    pub fn make_valid(mut self, make_valid: bool) -> Pipeline {
+        self.make_valid = make_valid;
+        self
+    }
+
+    /// 集計の前に、別のレイヤー（湖や河川などの水域）のポリゴンを各 Feature から差し引く
+    /// （行政区域の面積の代わりに陸地の面積を求める場合）
+    pub fn subtract(mut self, source: impl Into<String>) -> Pipeline {
+        self.subtract = Some(source.into());
+        self
+    }
+
+    /// 結果の `GroupResult::geometry` にディゾルブしたジオメトリを含める
+    /// （書き出し先が PostGIS などジオメトリを使うものなら指定しなくても含まれる）
+    pub fn geometry(mut self, geometry: bool) -> Pipeline {
+        self.geometry = geometry;
+        self
+    }
+
+    /// 結果の `GroupResult::ids` に、グループごとに集計した Feature の ID を入力の順に含める
+    pub fn ids(mut self, ids: bool) -> Pipeline {
+        self.ids = ids;
+        self
+    }
+
+    /// 結果の `PipelineResult::rings` に、Feature ごとの外周と穴の面積の内訳を含める（単位は `metric` による）
+    pub fn ring_report(mut self, rings: bool) -> Pipeline {
+        self.rings = rings;
+        self
+    }
+
+    /// 結果の `PipelineResult::audit` に、集計に含めなかった Feature（条件式や集計キーの一覧で外したもの、
+    /// 重複、集計キーやジオメトリがないもの）と、修復や差し引きでジオメトリを変えた Feature を理由と面積の変化とともに含める
+    pub fn audit(mut self, audit: bool) -> Pipeline {
+        self.audit = audit;
+        self
+    }
+
+    /// 結果の `GroupResult::values` に、数値のプロパティをグループごとに集計した値を含める
+    pub fn numeric(mut self, aggregates: Vec<NumericAggregate>) -> Pipeline {
+        self.numeric = aggregates;
+        self
+    }
+
+    /// 結果の `GroupResult::values` に、ジオメトリの種類ごとの Feature の数と線の長さ、点の数を含める
+    /// （列は `geometry_type::COLUMNS`。長さの単位は `metric` による）
+    pub fn geometry_types(mut self, geometry_types: bool) -> Pipeline {
+        self.geometry_types = geometry_types;
+        self
+    }
+
+    /// 集計した後に、式で計算した列を `GroupResult::values` の後ろに加える
+    pub fn derive(mut self, derived: Vec<Derived>) -> Pipeline {
+        self.derived = derived;
+        self
+    }
+
+    /// 結果の `GroupResult::values` の後ろ（`derive` の列の後）に、列の値を隣接するグループとの
+    /// 共有する境界の長さで重み付けして平滑化した列（`Smoothing::name`）を加える
+    pub fn smooth(mut self, smoothing: Smoothing) -> Pipeline {
+        self.smoothing = Some(smoothing);
+        self
+    }
+
+    /// 結果の `GroupResult::kept` に、グループの Feature のプロパティの値を引き継ぐ
+    /// （値が食い違う場合は `Keep::policy` に従い、食い違いを `PipelineResult::conflicts` に返す）
+    pub fn keep(mut self, keep: Keep) -> Pipeline {
+        self.keep = Some(keep);
+        self
+    }
+
+    /// 集計の前に集計キーのプロパティの値をそろえる（絞り込みの条件式もそろえた値で比べる）
+    pub fn normalize_keys(mut self, normalization: KeyNormalization) -> Pipeline {
+        self.normalization = Some(normalization);
+        self
+    }
+
+    /// Feature を `property`（都道府県の N03_001 など）の値ごとに分け、分割ごとに並列に集計してから結果をまとめる
+    pub fn partition_by(mut self, property: impl Into<String>) -> Pipeline {
+        self.partition_by = Some(property.into());
+        self
+    }
+
+    /// 集計し終えた分割の結果を `dir` に保存し、次に同じ集計をするときは読み込んで集計を省く（`partition_by` と使う）
+    pub fn checkpoint_dir(mut self, dir: impl Into<String>) -> Pipeline {
+        self.checkpoint_dir = Some(dir.into());
+        self
+    }
+
+    /// 分けた Feature を集計するまで一時ディレクトリ（`scratch`）に書き出し、集計する分割だけをメモリに置く（`partition_by` と使う）
+    pub fn spill(mut self, spill: bool) -> Pipeline {
+        self.spill = spill;
+        self
+    }
+
+    /// 集計用の表の大きさの目安が `bytes` を超えたら、表を一時ディレクトリ（`scratch`）に書き出しながら集計する。
+    /// 集計キーがとても多い場合に使う（面積と数だけを集計する。`external` を参照）
+    pub fn memory_limit(mut self, bytes: u64) -> Pipeline {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// 列の値で階級分けし、`GroupResult::class` に階級の名前を付ける（計算した列も使える）
+    pub fn classify(mut self, classification: Classification) -> Pipeline {
+        self.classification = Some(classification);
+        self
+    }
+
+    /// 結果を `area`, `count`, `values` のいずれかの列で並べ替える
+    pub fn sort(mut self, key: SortKey) -> Pipeline {
+        self.sort = Some(key);
+        self
+    }
+
+    /// 並べた結果の先頭の `count` 行だけを書き出して返す
+    pub fn top(mut self, count: usize) -> Pipeline {
+        self.top = Some(count);
+        self
+    }
+
+    /// 段階が変わったときと、集計し終えた Feature の数が増えたときに `callback` を呼ぶ（`progress` を参照）。
+    /// `partition_by` を指定した場合、Feature の数はすべての分割を集計し終えたときに通知する
+    pub fn on_progress(self, callback: impl Fn(&Event) + Send + Sync + 'static) -> Pipeline {
+        self.progress(Progress::new(callback))
+    }
+
+    /// 進み具合をこのハンドルに通知する（複製したハンドルで別の処理の進み具合もまとめて受け取れる）
+    pub fn progress(mut self, progress: Progress) -> Pipeline {
+        self.progress = progress;
+        self
+    }
+
+    /// 中断されたときに、途中までの結果を書き出して返す。
+    /// 読み込みの途中で中断された場合は読み込めた Feature を集計し、集計の途中なら集計し終えた分を書き出す
+    /// （重複の除去などの前処理の途中で中断された場合は、これまで通りエラーになる）
+    pub fn partial(mut self, partial: bool) -> Pipeline {
+        self.partial = partial;
+        self
+    }
+
+    /// 読み込みから書き出しまでを実行する。
+    /// 中断された場合は何も書き出さずに `Cancelled` のエラーを返す（`partial` を指定した場合を除く）。
+    ///
+    /// FeatureCollection 全体を必要とする前処理や集計の方法（検証、重複の除去、差し引き、監査、平滑化など）を
+    /// 使わなければ、読み込み → 解析 → 計算 → 集約 → 書き出しの段階に分けて、読み込みながら集計する（`run_stages`）
+    pub fn run(mut self) -> Result<PipelineResult, Box<dyn Error>> {
+        let start = Instant::now();
+        let mut timings = Timings::default();
+        if self.backend == Backend::Gpu {
+            if self.metric != Metric::Area {
+                return Err(
+                    "GPU バックエンドは平面上の面積 (--metric area) だけに対応しています".into(),
+                );
+            }
+            if self.partition_by.is_some() || self.memory_limit.is_some() {
+                return Err(
+                    "GPU バックエンドは partition_by, memory_limit と同時に使えません".into(),
+                );
+            }
+        }
+        let (input, filter) = self.open()?;
+        if self.staged(&input) {
+            let result = self.run_stages(&input, filter.as_ref(), timings)?;
+            return self.finish(start, result, None, None);
+        }
+        let Loaded {
+            collection,
+            duplicates,
+            repaired,
+            subtracted,
+            untemplated,
+            normalized,
+            missing_keys,
+            layers,
+            audit,
+            cancelled: read_cancelled,
+        } = self.load(input, filter, &mut timings)?;
+        let rings = if self.rings {
+            holes::report(&collection, &self.group_by, self.metric)
+        } else {
+            Vec::new()
+        };
+        // 集計の前に隣接を求めておく（分けて集計する場合は FeatureCollection を渡してしまうため）
+        let adjacency = self
+            .smoothing
+            .is_some()
+            .then(|| Adjacency::detect(&collection, &self.group_by));
+        let kept = self
+            .keep
+            .as_ref()
+            .map(|keep| keep.collect(&collection, &self.group_by, layers));
+
+        let extras = self.extras(layers);
+        let total = collection.features.len();
+        self.progress.start(total);
+        let (mut partitions, mut restored_partitions, mut spills) = (0, 0, 0);
+        let aggregation = match &self.partition_by {
+            Some(_) if self.memory_limit.is_some() => {
+                return Err("memory_limit と partition_by は同時に指定できません".into())
+            }
+            Some(property) => {
+                let partitioned = partition::aggregate(
+                    collection,
+                    property,
+                    &self.group_by,
+                    &extras,
+                    self.metric,
+                    &self.cancel,
+                    partition::Storage {
+                        checkpoint_dir: self.checkpoint_dir.as_deref(),
+                        spill: self.spill,
+                    },
+                )?;
+                (partitions, restored_partitions) = (partitioned.partitions, partitioned.restored);
+                self.progress.advance(partitioned.aggregation.processed);
+                partitioned.aggregation
+            }
+            None if self.checkpoint_dir.is_some() => {
+                return Err(
+                    "チェックポイントは分けて集計する場合 (partition_by) にだけ使えます".into(),
+                )
+            }
+            None => match self.memory_limit {
+                Some(limit) => {
+                    let external = external::aggregate(
+                        &collection,
+                        &self.group_by,
+                        &extras,
+                        self.metric,
+                        limit,
+                        &self.cancel,
+                        &self.progress,
+                    )?;
+                    spills = external.spills;
+                    external.aggregation
+                }
+                None => match self.backend {
+                    Backend::Cpu => aggregate::aggregate_observed(
+                        &collection,
+                        &self.group_by,
+                        &extras,
+                        self.metric,
+                        Schedule::ByCost,
+                        &self.cancel,
+                        &self.progress,
+                    ),
+                    #[cfg(feature = "gpu")]
+                    Backend::Gpu => crate::gpu::aggregate(
+                        &crate::gpu::Kernel::new()?,
+                        &collection.features,
+                        &self.group_by,
+                        &extras,
+                        &self.cancel,
+                        &self.progress,
+                    )?,
+                    #[cfg(not(feature = "gpu"))]
+                    Backend::Gpu => {
+                        return Err("gpu フィーチャーを有効にしてビルドしてください".into())
+                    }
+                },
+            },
+        };
+        let Aggregation {
+            rows,
+            processed,
+            cancelled,
+            timings: aggregation_timings,
+            types,
+        } = aggregation;
+        let cancelled = match read_cancelled.or(cancelled) {
+            Some(cancelled) if !self.partial => return Err(cancelled.into()),
+            cancelled => cancelled,
+        };
+        timings.merge(aggregation_timings);
+        let result = PipelineResult {
+            rows,
+            duplicates,
+            repaired,
+            subtracted,
+            partitions,
+            restored_partitions,
+            spills,
+            untemplated,
+            normalized,
+            missing_keys,
+            rings,
+            audit,
+            conflicts: Vec::new(),
+            processed,
+            total,
+            cancelled,
+            timings,
+            types,
+        };
+        self.finish(start, result, adjacency.as_ref(), kept.as_ref())
+    }
+
+    /// 集めるもの（`layers` はレイヤーごとに分けて集計するか）
+    fn extras(&mut self, layers: bool) -> Extras {
+        Extras {
+            geometry: self.geometry || self.sink.as_ref().is_some_and(|sink| sink.needs_geometry()),
+            ids: self.ids,
+            numeric: std::mem::take(&mut self.numeric),
+            geometry_types: self.geometry_types,
+            layers,
+        }
+    }
+
+    /// 集計した結果に計算した列などを加え、並べ替えて書き出す
+    fn finish(
+        &self,
+        start: Instant,
+        mut result: PipelineResult,
+        adjacency: Option<&Adjacency>,
+        kept: Option<&keep::Collected>,
+    ) -> Result<PipelineResult, Box<dyn Error>> {
+        let rows = &mut result.rows;
+        derive::apply(rows, &self.derived);
+        if let (Some(smoothing), Some(adjacency)) = (&self.smoothing, adjacency) {
+            smoothing.apply(rows, adjacency);
+        }
+        if let (Some(keep), Some(kept)) = (&self.keep, kept) {
+            result.conflicts = keep.apply(rows, kept)?;
+        }
+        if let Some(classification) = &self.classification {
+            classification.apply(rows);
+        }
+        if let Some(key) = &self.sort {
+            key.sort(rows);
+        }
+        if let Some(count) = self.top {
+            rows.truncate(count);
+        }
+        if let Some(sink) = &self.sink {
+            self.progress.stage(Stage::Write);
+            result
+                .timings
+                .time(Stage::Write, || sink.write(&result.rows))?;
+        }
+        result.timings.total = start.elapsed();
+        self.progress.finish(result.timings.total);
+        Ok(result)
+    }
+
+    /// 段階に分けて読み込みながら集計できるか（FeatureCollection 全体を必要とするものを使わない場合）
+    fn staged(&self, input: &Input) -> bool {
+        self.backend == Backend::Cpu
+            && !self.validate
+            && self.dedup.is_none()
+            && self.subtract.is_none()
+            && !self.rings
+            && !self.audit
+            && self.smoothing.is_none()
+            && self.keep.is_none()
+            && self.partition_by.is_none()
+            && self.checkpoint_dir.is_none()
+            && self.memory_limit.is_none()
+            && !input.has_layers()
+    }
+
+    /// 読み込み → 解析 → 計算 → 集約 → 書き出しの段階を別々のスレッドで動かし、
+    /// `QUEUED_CHUNKS` 個のまとまり（`CHUNK_FEATURES` 個ずつの Feature）までの上限のあるチャネルでつなぐ。
+    /// 後の段階が追いつかなければ前の段階が待つため、読み込んだ Feature をすべてメモリに置くことはない。
+    /// 集計結果はすべて集約し終えるまで決まらないため、書き出しは最後に 1 度だけ行う（`finish`）
+    fn run_stages(
+        &mut self,
+        input: &Input,
+        filter: Option<&Filter>,
+        mut timings: Timings,
+    ) -> Result<PipelineResult, Box<dyn Error>> {
+        let extras = self.extras(false);
+        let stage = Compute {
+            group_by: &self.group_by,
+            metric: self.metric,
+            template: self.template.as_ref(),
+            normalization: self.normalization.as_ref(),
+            filter,
+            keys: self.keys.as_ref(),
+            make_valid: self.make_valid,
+            extras: &extras,
+            progress: &self.progress,
+        };
+        let stage = &stage;
+        // 段階は同時に進むが、通知は `load` と同じ順にする
+        self.progress.stage(Stage::Read);
+        self.progress.stage(Stage::Prepare);
+        self.progress.start(0);
+        let (read, parse, prepared, reduced) = thread::scope(|scope| {
+            let (chunk_sender, chunks) = mpsc::sync_channel::<Chunk>(QUEUED_CHUNKS);
+            let (parsed_sender, parsed) = mpsc::sync_channel(QUEUED_CHUNKS);
+            let (computed_sender, computed) = mpsc::sync_channel(QUEUED_CHUNKS);
+            let parser = scope.spawn(move || {
+                let mut parser = ChunkParser::default();
+                let mut elapsed = Duration::ZERO;
+                for chunk in chunks {
+                    let start = Instant::now();
+                    let features = parser.parse(chunk);
+                    elapsed += start.elapsed();
+                    let failed = features.is_err();
+                    if parsed_sender.send(features).is_err() || failed {
+                        return elapsed;
+                    }
+                }
+                parser.finish();
+                elapsed
+            });
+            let compute = scope.spawn(move || stage.run(parsed, computed_sender));
+            let reducer = scope.spawn(move || {
+                let mut table = Table::new(stage.group_by, stage.extras, stage.metric);
+                for computed in computed {
+                    table.merge(computed?);
+                }
+                Ok::<_, String>(table)
+            });
+            // 読み込みはこのスレッドで行う（次の段階を待っていた時間は除いて測る）
+            let start = Instant::now();
+            let mut waiting = Duration::ZERO;
+            let read = input.read_chunks(CHUNK_FEATURES, &self.cancel, &mut |chunk| {
+                let start = Instant::now();
+                let sent = chunk_sender.send(chunk).is_ok();
+                waiting += start.elapsed();
+                sent
+            });
+            drop(chunk_sender);
+            let read = read.map(|complete| (complete, start.elapsed().saturating_sub(waiting)));
+            (
+                read,
+                parser.join().unwrap(),
+                compute.join().unwrap(),
+                reducer.join().unwrap(),
+            )
+        });
+        let (complete, read) = read?;
+        let table = reduced?;
+        // 読み込みの途中で中断された場合、`partial` なら読み込めた分を書き出す
+        let cancelled = match self.cancel.check().err().filter(|_| !complete) {
+            Some(cancelled) if !self.partial => return Err(cancelled.into()),
+            cancelled => cancelled,
+        };
+        timings.record(Stage::Read, read);
+        timings.record(Stage::Parse, parse);
+        timings.record(Stage::Prepare, prepared.elapsed);
+        let (rows, types, aggregation_timings) = table.finish();
+        timings.merge(aggregation_timings);
+        Ok(PipelineResult {
+            rows,
+            duplicates: Vec::new(),
+            repaired: prepared.repaired,
+            subtracted: 0,
+            partitions: 0,
+            restored_partitions: 0,
+            spills: 0,
+            untemplated: prepared.untemplated,
+            normalized: prepared.normalized,
+            missing_keys: prepared.missing_keys,
+            rings: Vec::new(),
+            audit: Vec::new(),
+            conflicts: Vec::new(),
+            processed: prepared.total,
+            total: prepared.total,
+            cancelled,
+            timings,
+            types,
+        })
+    }
+
+    /// 集計の代わりに、絞り込みと重複の除去の後の Feature ごとに `visit` を並列に呼ぶ
+    /// （`group_by`, `metric`, `sink` は使わない）
+    pub fn for_each<F>(self, visit: F) -> Result<(), Box<dyn Error>>
+    where
+        F: Fn(FeatureView) + Sync,
+    {
+        self.map_reduce(visit, || (), |_, _| ())
+    }
+
+    /// 集計の代わりに、Feature ごとに `map` を並列に呼んで結果を `reduce` でまとめる
+    pub fn map_reduce<T, M, I, R>(
+        mut self,
+        map: M,
+        identity: I,
+        reduce: R,
+    ) -> Result<T, Box<dyn Error>>
+    where
+        T: Send,
+        M: Fn(FeatureView) -> T + Sync,
+        I: Fn() -> T + Sync + Send,
+        R: Fn(T, T) -> T + Sync + Send,
+    {
+        let (input, filter) = self.open()?;
+        let collection = self
+            .load(input, filter, &mut Timings::default())?
+            .collection;
+        Ok(visit::map_reduce_with(
+            &collection,
+            &self.cancel,
+            map,
+            identity,
+            reduce,
+        )?)
+    }
+
+    /// 入力元を判定し、条件式を解析する（`timeout` はここから数える）
+    fn open(&mut self) -> Result<(Input, Option<Filter>), Box<dyn Error>> {
+        if let Some(timeout) = self.timeout {
+            self.cancel = self.cancel.with_timeout(timeout);
+        }
+        let filter = self.filter.as_deref().map(Filter::parse).transpose()?;
+        let source = std::mem::replace(&mut self.source, Source::Text(String::new()));
+        let input = match source {
+            Source::Text(text) => Input::parse(&text, InputOptions::default())?,
+            Source::Input(input) => input,
+        };
+        Ok((input, filter))
+    }
+
+    /// 入力を読み込み、絞り込みと重複の除去、ポリゴンの修復と差し引きを行う（かかった時間を `timings` に記録する）
+    fn load(
+        &mut self,
+        input: Input,
+        filter: Option<Filter>,
+        timings: &mut Timings,
+    ) -> Result<Loaded, Box<dyn Error>> {
+        self.progress.stage(Stage::Read);
+        let (mut collection, cancelled) = input.read_timed(timings, &self.cancel)?;
+        // 読み込みの途中（または読み込み終えた直後）に中断された場合、`partial` なら読み込めた分を集計して書き出す。
+        // 以降は中断を確かめないトークンで進める（シグナルならもう一度受け取ればすぐに終了する）
+        let cancelled = match cancelled.or_else(|| self.cancel.check().err()) {
+            Some(cancelled) if !self.partial => return Err(cancelled.into()),
+            Some(cancelled) => {
+                self.cancel = CancellationToken::new();
+                Some(cancelled)
+            }
+            None => None,
+        };
+        self.progress.stage(Stage::Prepare);
+        let prepare = Instant::now();
+        if self.validate {
+            let violations = validate::validate(&collection);
+            if !violations.is_empty() {
+                return Err(Report { violations }.into());
+            }
+        }
+        let mut untemplated = 0;
+        if let Some(template) = &self.template {
+            untemplated = template.assign_all(&mut collection);
+        }
+        let mut normalized = 0;
+        if let Some(normalization) = &self.normalization {
+            normalized = normalization.normalize_all(&mut collection, &self.group_by);
+        }
+        // 絞り込みの前に ID を付けておく（位置から作る ID が条件式によって変わらないように）
+        ids::assign(&mut collection);
+        let mut audit = self.audit.then(|| {
+            let unkeyed = match &self.template {
+                Some(_) => "テンプレートから集計キーを作れない".to_string(),
+                None => format!("集計キーのプロパティ {} がない", self.group_by),
+            };
+            Audit::new(&self.group_by, self.metric, unkeyed)
+        });
+        if let Some(filter) = &filter {
+            let expression = self.filter.as_deref().unwrap_or_default();
+            collection.features.retain(|feature| {
+                let matches = filter.matches(feature);
+                if let Some(audit) = audit.as_mut().filter(|_| !matches) {
+                    audit.skip(feature, format!("条件式 {} を満たさない", expression));
+                }
+                matches
+            });
+        }
+        let mut missing_keys = Vec::new();
+        if let Some(keys) = &self.keys {
+            if let Some(audit) = &mut audit {
+                audit.keys(&collection, keys);
+            }
+            missing_keys = keys.retain(&mut collection, &self.group_by);
+        }
+        let mut duplicates = Vec::new();
+        if let Some(tolerance) = self.dedup {
+            let before = audit.as_ref().map(|audit| audit.areas(&collection));
+            (collection, duplicates) = dedup::dedup(collection, tolerance, &self.group_by);
+            if let (Some(audit), Some(before)) = (&mut audit, &before) {
+                audit.duplicates(&duplicates, before);
+            }
+            self.cancel.check()?;
+        }
+        let mut repaired = 0;
+        if self.make_valid {
+            let before = audit.as_ref().map(|audit| audit.areas(&collection));
+            let positions = repair::make_valid_each(&mut collection);
+            repaired = positions.len();
+            if let (Some(audit), Some(before)) = (&mut audit, &before) {
+                audit.changed(
+                    &collection,
+                    &positions,
+                    before,
+                    Action::Repaired,
+                    "自己交差したリングを分けて組み立て直した",
+                );
+            }
+            self.cancel.check()?;
+        }
+        let mut subtracted = 0;
+        if let Some(source) = &self.subtract {
+            let layer = Input::parse(source, InputOptions::default())?.read()?;
+            let before = audit.as_ref().map(|audit| audit.areas(&collection));
+            let positions = subtract::subtract_each(&mut collection, &Mask::new(&layer));
+            subtracted = positions.len();
+            if let (Some(audit), Some(before)) = (&mut audit, &before) {
+                audit.changed(
+                    &collection,
+                    &positions,
+                    before,
+                    Action::Clipped,
+                    &format!("{} と重なる部分を差し引いた", source),
+                );
+            }
+            self.cancel.check()?;
+        }
+        if let Some(audit) = &mut audit {
+            audit.unusable(&collection);
+        }
+        // レイヤーそのものを集計キーにした場合は分けない
+        let layers = input.has_layers()
+            && self.group_by != LAYER_PROPERTY
+            && collection
+                .features
+                .iter()
+                .filter_map(|feature| feature.property(LAYER_PROPERTY)?.as_str())
+                .collect::<HashSet<_>>()
+                .len()
+                > 1;
+        timings.record(Stage::Prepare, prepare.elapsed());
+        Ok(Loaded {
+            collection,
+            duplicates,
+            repaired,
+            subtracted,
+            untemplated,
+            normalized,
+            missing_keys,
+            layers,
+            audit: audit.map(|audit| audit.entries).unwrap_or_default(),
+            cancelled,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch;
+    use std::{fs, sync::Mutex};
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    /// プロセスで 1 つの一時ディレクトリ（`scratch`）を使うテストを 1 つずつ実行する
+    static SCRATCH: Mutex<()> = Mutex::new(());
+
+    /// 1 辺が 1 の正方形のポリゴンを持つ Feature の行
+    fn line(city: &str) -> String {
+        format!(
+            r#"{{"type":"Feature","properties":{{"N03_004":"{}"}},"geometry":{{"type":"Polygon","coordinates":[[[0,0],[1,0],[1,1],[0,1],[0,0]]]}}}}"#,
+            city
+        )
+    }
+
+    fn write_lines(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("layon-{}-{}.geojsonl", name, std::process::id()));
+        let lines: Vec<String> = ["川越市", "所沢市", "川越市"].map(line).to_vec();
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn cancelling_while_reading_writes_the_features_read_so_far() {
+        let path = write_lines("partial");
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let result = Pipeline::read(path.as_str())
+            .cancellation(cancel)
+            .partial(true)
+            .run()
+            .unwrap();
+        // 最初の行を読んだところで止まる
+        assert!(result.cancelled.is_some());
+        assert_eq!(result.total, 1);
+        assert_eq!(result.rows.len(), 1);
+        assert_eq!(result.rows[0].key, "川越市");
+        assert_eq!(result.rows[0].count, 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn staged_results_match_the_collected_results() {
+        // まとまりをまたぐ数の Feature（ID のないものは位置から ID を作る）
+        let path =
+            std::env::temp_dir().join(format!("layon-staged-{}.geojson", std::process::id()));
+        let features: Vec<String> = (0..CHUNK_FEATURES * 2 + 10)
+            .map(|i| {
+                let city = ["川越市", "川越市", "所沢市", "狭山市"][i % 4];
+                line(city).replacen('}', &format!(r#","pop":{}}}"#, i), 1)
+            })
+            .collect();
+        std::fs::write(
+            &path,
+            format!(
+                r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+                features.join(",")
+            ),
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+        let run = |staged: bool| {
+            Pipeline::read(path)
+                .filter("N03_004 != '狭山市'")
+                .ids(true)
+                .numeric(NumericAggregate::parse_list("pop:mean,pop:max").unwrap())
+                // 内訳を求めると FeatureCollection 全体を読み込んでから集計する
+                .ring_report(!staged)
+                .run()
+                .unwrap()
+        };
+        let (staged, collected) = (run(true), run(false));
+        assert_eq!(staged.total, collected.total);
+        assert_eq!(staged.rows.len(), 2);
+        for (a, b) in staged.rows.iter().zip(&collected.rows) {
+            assert_eq!((&a.key, a.area, a.count), (&b.key, b.area, b.count));
+            assert_eq!(a.ids, b.ids);
+            assert_eq!(a.values, b.values);
+        }
+        assert_eq!(staged.rows[0].ids[0], "#0");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn parse_errors_in_later_chunks_are_reported() {
+        let path =
+            std::env::temp_dir().join(format!("layon-invalid-{}.geojsonl", std::process::id()));
+        let mut lines: Vec<String> = (0..CHUNK_FEATURES + 1).map(|_| line("川越市")).collect();
+        lines.push("{".to_string());
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        let err = Pipeline::read(path.to_str().unwrap()).run().err().unwrap();
+        assert!(err
+            .to_string()
+            .contains(&format!("({} 行目)", CHUNK_FEATURES + 2)));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn cancelling_while_reading_is_an_error_without_partial() {
+        let path = write_lines("cancelled");
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let err = Pipeline::read(path.as_str())
+            .cancellation(cancel)
+            .run()
+            .err()
+            .unwrap();
+        assert!(err.is::<Cancelled>());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn partitioned_aggregation_matches_and_resumes_from_checkpoints() {
+        let dir = std::env::temp_dir().join(format!("layon-partition-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let feature = |pref: &str, city: &str, x: f64, pop: f64| {
+            format!(
+                r#"{{"type":"Feature","properties":{{"pref":"{}","city":"{}","pop":{}}},"geometry":{{"type":"Polygon","coordinates":[[[{x},0],[{},0],[{},1],[{x},1],[{x},0]]]}}}}"#,
+                pref,
+                city,
+                pop,
+                x + 1.0,
+                x + 1.0
+            )
+        };
+        let input = dir.join("input.geojson");
+        fs::write(
+            &input,
+            format!(
+                r#"{{"type":"FeatureCollection","features":[{},{},{},{},{}]}}"#,
+                feature("東京都", "府中市", 0.0, 10.0),
+                feature("広島県", "府中市", 2.0, 5.0),
+                feature("東京都", "調布市", 4.0, 7.0),
+                feature("東京都", "調布市", 6.0, 1.0),
+                feature("東京都", "調布市", 8.0, 2.0)
+            ),
+        )
+        .unwrap();
+        let run = |agg: &str, checkpoints: Option<&std::path::PathBuf>| {
+            let mut pipeline = Pipeline::read(input.to_str().unwrap())
+                .group_by("city")
+                .ids(true)
+                .numeric(NumericAggregate::parse_list(agg).unwrap())
+                .partition_by("pref");
+            if let Some(dir) = checkpoints {
+                pipeline = pipeline.checkpoint_dir(dir.to_str().unwrap());
+            }
+            pipeline.run()
+        };
+        let rows = |result: &PipelineResult| -> Vec<String> {
+            result
+                .rows
+                .iter()
+                .map(|row| {
+                    format!(
+                        "{} {} {} {:?} {:?}",
+                        row.key, row.area, row.count, row.ids, row.values
+                    )
+                })
+                .collect()
+        };
+
+        let unpartitioned = Pipeline::read(input.to_str().unwrap())
+            .group_by("city")
+            .ids(true)
+            .numeric(NumericAggregate::parse_list("pop:sum,pop:max").unwrap())
+            .run()
+            .unwrap();
+        let partitioned = run("pop:sum,pop:max", None).unwrap();
+        assert_eq!(partitioned.partitions, 2);
+        assert_eq!(rows(&partitioned), rows(&unpartitioned));
+        assert_eq!(partitioned.rows[1].key, "府中市");
+        assert_eq!(
+            partitioned.rows[1].values[0],
+            ("pop_sum".to_string(), Some(15.0))
+        );
+
+        // 府中市は 2 つの都道府県にあるため、平均は分割ごとの結果から求められない
+        assert!(run("pop:mean", None).is_err());
+
+        let checkpoints = dir.join("checkpoints");
+        let _ = fs::remove_dir_all(&checkpoints);
+        let first = run("pop:sum,pop:max", Some(&checkpoints)).unwrap();
+        assert_eq!(first.restored_partitions, 0);
+        assert_eq!(fs::read_dir(&checkpoints).unwrap().count(), 2);
+        let second = run("pop:sum,pop:max", Some(&checkpoints)).unwrap();
+        assert_eq!(second.restored_partitions, 2);
+        assert_eq!(rows(&second), rows(&unpartitioned));
+        assert_eq!(second.processed, 5);
+        // 集計の設定が変われば、保存した結果は使わない
+        assert_eq!(
+            run("pop:sum", Some(&checkpoints))
+                .unwrap()
+                .restored_partitions,
+            0
+        );
+
+        // 分割の Feature を一時ディレクトリに書き出しても結果は同じで、集計し終えたファイルは残らない
+        let _lock = SCRATCH.lock().unwrap();
+        let spill = |limit: Option<u64>| {
+            scratch::configure(Some(dir.join("tmp")), limit);
+            let result = Pipeline::read(input.to_str().unwrap())
+                .group_by("city")
+                .ids(true)
+                .numeric(NumericAggregate::parse_list("pop:sum,pop:max").unwrap())
+                .partition_by("pref")
+                .spill(true)
+                .run();
+            let scratch = scratch::current().unwrap();
+            assert_eq!(fs::read_dir(scratch.path()).unwrap().count(), 0);
+            assert_eq!(scratch.used(), 0);
+            let path = scratch.path().to_path_buf();
+            drop(scratch);
+            scratch::cleanup();
+            assert!(!path.exists());
+            result
+        };
+        assert_eq!(rows(&spill(None).unwrap()), rows(&unpartitioned));
+        let err = spill(Some(100)).err().unwrap().to_string();
+        assert!(err.contains("上限"), "{}", err);
+    }
+
+    #[test]
+    fn memory_limit_spills_the_table_and_matches_in_memory_aggregation() {
+        let _lock = SCRATCH.lock().unwrap();
+        let dir = std::env::temp_dir().join(format!("layon-external-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        scratch::configure(Some(dir.clone()), None);
+        let run = |limit: Option<u64>| {
+            let mut pipeline = Pipeline::read(FIXTURE).metric(Metric::GeodesicArea);
+            if let Some(limit) = limit {
+                pipeline = pipeline.memory_limit(limit);
+            }
+            pipeline.run().unwrap()
+        };
+        let rows = |result: &PipelineResult| -> Vec<String> {
+            result
+                .rows
+                .iter()
+                .map(|row| format!("{} {} {}", row.key, row.area, row.count))
+                .collect()
+        };
+        let in_memory = run(None);
+        let within = run(Some(1 << 20));
+        assert_eq!(within.spills, 0);
+        assert_eq!(rows(&within), rows(&in_memory));
+        let spilled = run(Some(1));
+        assert_eq!(spilled.spills, 1);
+        assert_eq!(rows(&spilled), rows(&in_memory));
+        assert_eq!(spilled.processed, 8);
+
+        // 面積と数のほかの集計はできない
+        assert!(Pipeline::read(FIXTURE)
+            .ids(true)
+            .memory_limit(1)
+            .run()
+            .is_err());
+        let scratch = scratch::current().unwrap();
+        assert_eq!(fs::read_dir(scratch.path()).unwrap().count(), 0);
+        drop(scratch);
+        scratch::cleanup();
+    }
+}
@@ -0,0 +1,248 @@
+//! クロス集計（`--pivot rows=N03_001,cols=N03_003,value=area`）。
+//! 縦持ちの表の代わりに、行と列の 2 つのプロパティの組ごとの面積（または Feature の数）を横持ちの表にする。
+//! 書き出した後に表計算ソフトでピボットする手間を省く。
+
+use crate::aggregate::{self, Metric};
+use geojson::{Feature, JsonValue};
+use std::collections::{BTreeSet, HashMap};
+
+/// 表の値にするもの
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PivotValue {
+    /// 面積（`--metric` の方法で求める）
+    Area,
+    /// Feature の数
+    Count,
+}
+
+/// クロス集計の設定
+#[derive(Clone, Debug, PartialEq)]
+pub struct PivotSpec {
+    /// 行にするプロパティ
+    pub rows: String,
+    /// 列にするプロパティ
+    pub columns: String,
+    pub value: PivotValue,
+}
+
+impl PivotSpec {
+    /// `rows=PROPERTY,cols=PROPERTY[,value=area|count]` を解析する
+    pub fn parse(text: &str) -> Result<PivotSpec, String> {
+        let (mut rows, mut columns, mut value) = (None, None, PivotValue::Area);
+        for entry in text.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, setting) = entry
+                .split_once('=')
+                .map(|(name, setting)| (name.trim(), setting.trim()))
+                .filter(|(_, setting)| !setting.is_empty())
+                .ok_or_else(|| format!("--pivot の指定が不正です (名前=値): {}", entry))?;
+            match name {
+                "rows" => rows = Some(setting.to_string()),
+                "cols" => columns = Some(setting.to_string()),
+                "value" => {
+                    value = match setting {
+                        "area" => PivotValue::Area,
+                        "count" => PivotValue::Count,
+                        _ => {
+                            return Err(format!(
+                                "--pivot の value が不正です: {} (area か count)",
+                                setting
+                            ))
+                        }
+                    }
+                }
+                _ => return Err(format!("--pivot の不明な指定です: {}", name)),
+            }
+        }
+        match (rows, columns) {
+            (Some(rows), Some(columns)) => Ok(PivotSpec {
+                rows,
+                columns,
+                value,
+            }),
+            _ => Err("--pivot には rows= と cols= を指定してください".to_string()),
+        }
+    }
+
+    /// 1 つの Feature の行と列の組と面積
+    pub fn cell(&self, feature: &Feature, metric: Metric) -> Cells {
+        let key = (key(feature, &self.rows), key(feature, &self.columns));
+        let area = match self.value {
+            PivotValue::Area => aggregate::feature_area(feature, metric),
+            PivotValue::Count => 0.0,
+        };
+        Cells(HashMap::from([(key, (area, 1))]))
+    }
+}
+
+/// プロパティがないか null の Feature の行と列の名前（郡に属さない市など）
+pub const MISSING: &str = "(なし)";
+
+/// 行と列の名前（文字列でなければ JSON の表記）
+fn key(feature: &Feature, property: &str) -> String {
+    match feature.property(property) {
+        None | Some(JsonValue::Null) => MISSING.to_string(),
+        Some(JsonValue::String(text)) => text.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+/// 行と列の組ごとの面積と Feature の数（Feature ごとに作って並列にまとめる）
+#[derive(Default)]
+pub struct Cells(HashMap<(String, String), (f64, usize)>);
+
+impl Cells {
+    pub fn merge(mut self, other: Cells) -> Cells {
+        for (key, (area, count)) in other.0 {
+            let entry = self.0.entry(key).or_insert((0.0, 0));
+            entry.0 += area;
+            entry.1 += count;
+        }
+        self
+    }
+}
+
+/// クロス集計の表
+pub struct Crosstab {
+    /// 列の名前（列にしたプロパティの値の順）
+    pub columns: Vec<String>,
+    /// 行（行にしたプロパティの値の順）
+    pub rows: Vec<CrosstabRow>,
+    /// 列ごとの合計
+    pub totals: Vec<f64>,
+    /// すべての値の合計
+    pub total: f64,
+}
+
+pub struct CrosstabRow {
+    pub key: String,
+    /// 列ごとの値（その組の Feature がなければ None）
+    pub values: Vec<Option<f64>>,
+    /// 行の合計
+    pub total: f64,
+}
+
+impl Crosstab {
+    pub fn new(cells: Cells, value: PivotValue) -> Crosstab {
+        let columns: Vec<String> = cells
+            .0
+            .keys()
+            .map(|(_, column)| column.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let keys: BTreeSet<&String> = cells.0.keys().map(|(row, _)| row).collect();
+        let rows: Vec<CrosstabRow> = keys
+            .into_iter()
+            .map(|key| {
+                let values: Vec<Option<f64>> = columns
+                    .iter()
+                    .map(|column| {
+                        cells.0.get(&(key.clone(), column.clone())).map(
+                            |&(area, count)| match value {
+                                PivotValue::Area => area,
+                                PivotValue::Count => count as f64,
+                            },
+                        )
+                    })
+                    .collect();
+                CrosstabRow {
+                    key: key.clone(),
+                    total: values.iter().flatten().sum(),
+                    values,
+                }
+            })
+            .collect();
+        let totals: Vec<f64> = (0..columns.len())
+            .map(|i| {
+                rows.iter()
+                    .filter_map(|row: &CrosstabRow| row.values[i])
+                    .sum()
+            })
+            .collect();
+        Crosstab {
+            total: totals.iter().sum(),
+            columns,
+            rows,
+            totals,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feature(properties: &str, width: f64) -> Feature {
+        format!(
+            r#"{{"type":"Feature","properties":{},"geometry":{{"type":"Polygon","coordinates":[[[0,0],[{w},0],[{w},1],[0,1],[0,0]]]}}}}"#,
+            properties,
+            w = width
+        )
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn specs_are_parsed() {
+        assert_eq!(
+            PivotSpec::parse(" rows = N03_001, cols=N03_003 ,value=count").unwrap(),
+            PivotSpec {
+                rows: "N03_001".to_string(),
+                columns: "N03_003".to_string(),
+                value: PivotValue::Count,
+            }
+        );
+        assert_eq!(
+            PivotSpec::parse("cols=N03_003,rows=N03_001").unwrap().value,
+            PivotValue::Area
+        );
+        assert!(PivotSpec::parse("rows=N03_001").is_err());
+        assert!(PivotSpec::parse("rows=N03_001,cols=").is_err());
+        assert!(PivotSpec::parse("rows=N03_001,cols=N03_003,value=mean").is_err());
+        assert!(PivotSpec::parse("rows=N03_001,cols=N03_003,sort=area").is_err());
+    }
+
+    #[test]
+    fn missing_properties_and_totals() {
+        let spec = PivotSpec::parse("rows=pref,cols=county").unwrap();
+        let features = [
+            feature(r#"{"pref":"埼玉県","county":"入間郡"}"#, 1.0),
+            feature(r#"{"pref":"埼玉県","county":"入間郡"}"#, 2.0),
+            // 郡に属さない市
+            feature(r#"{"pref":"埼玉県","county":null}"#, 4.0),
+            feature(r#"{"pref":"東京都"}"#, 8.0),
+            feature(r#"{"county":"西多摩郡"}"#, 16.0),
+        ];
+        let cells = features
+            .iter()
+            .map(|feature| spec.cell(feature, Metric::Area))
+            .fold(Cells::default(), Cells::merge);
+        let crosstab = Crosstab::new(cells, spec.value);
+        assert_eq!(crosstab.columns, [MISSING, "入間郡", "西多摩郡"]);
+        let rows: Vec<_> = crosstab
+            .rows
+            .iter()
+            .map(|row| (row.key.as_str(), row.values.clone(), row.total))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (MISSING, vec![None, None, Some(16.0)], 16.0),
+                ("埼玉県", vec![Some(4.0), Some(3.0), None], 7.0),
+                ("東京都", vec![Some(8.0), None, None], 8.0),
+            ]
+        );
+        assert_eq!(crosstab.totals, [12.0, 3.0, 16.0]);
+        assert_eq!(crosstab.total, 31.0);
+
+        let count = PivotSpec::parse("rows=pref,cols=county,value=count").unwrap();
+        let cells = features
+            .iter()
+            .map(|feature| count.cell(feature, Metric::Area))
+            .fold(Cells::default(), Cells::merge);
+        let crosstab = Crosstab::new(cells, count.value);
+        assert_eq!(crosstab.rows[1].values, [Some(1.0), Some(2.0), None]);
+        assert_eq!(crosstab.totals, [2.0, 2.0, 1.0]);
+        assert_eq!(crosstab.total, 5.0);
+    }
+}
@@ -0,0 +1,113 @@
+//! 実行前の確認（`--dry-run`）。
+//! 大きな入力を集計し始めてから設定の誤りに気づかないように、先頭の Feature だけを見て
+//! 集計キー・座標参照系・出力先に問題がないかを確かめる。
+
+use crate::{aggregate::Metric, extent, sink::Output, source::Schema};
+use geojson::FeatureCollection;
+use std::collections::BTreeSet;
+
+/// 確認の結果
+pub enum Check {
+    Ok(String),
+    /// 実行はできるが、意図と違う結果になるかもしれない
+    Warning(String),
+    /// このままでは失敗するか、意味のない結果になる
+    Error(String),
+}
+
+/// 経度・緯度の座標参照系とみなす名前の末尾（EPSG のコードと OGC の CRS84）
+const GEOGRAPHIC_CRS: &[&str] = &["4326", "4612", "6668", "4269", "4258", "CRS84"];
+
+/// 入力の概要と設定を確かめる
+pub fn check(schema: &Schema, group_by: &str, metric: Metric, output: &Output) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    match &schema.sample {
+        None => checks.push(Check::Warning(format!(
+            "この入力形式は読み込むまで集計キー {} があるか確かめられません",
+            group_by
+        ))),
+        Some(sample) => checks.push(check_group_by(sample, group_by)),
+    }
+
+    let geographic_crs = schema.crs.as_ref().map(|crs| {
+        GEOGRAPHIC_CRS
+            .iter()
+            .any(|suffix| crs.to_uppercase().ends_with(suffix))
+    });
+    let bounds = schema.sample.as_ref().and_then(|sample| {
+        let collection = FeatureCollection {
+            bbox: None,
+            features: sample.clone(),
+            foreign_members: None,
+        };
+        extent::extents(&collection, None)
+            .first()
+            .map(|row| row.rect)
+    });
+    let in_lon_lat = bounds.map(|rect| {
+        rect.min().x >= -180.0
+            && rect.max().x <= 180.0
+            && rect.min().y >= -90.0
+            && rect.max().y <= 90.0
+    });
+    match (metric, geographic_crs, in_lon_lat) {
+        (Metric::GeodesicArea | Metric::Projected(_) | Metric::GeodesicLength, Some(false), _) => checks.push(Check::Error(format!(
+            "座標参照系 {} は経度・緯度ではありません (geodesic-area, length と --projection には経度・緯度の座標が必要です)",
+            schema.crs.as_deref().unwrap_or_default()
+        ))),
+        (Metric::GeodesicArea | Metric::Projected(_) | Metric::GeodesicLength, _, Some(false)) => checks.push(Check::Error(
+            "先頭の Feature の座標が経度・緯度の範囲を超えています (geodesic-area, length と --projection には経度・緯度の座標が必要です)"
+                .to_string(),
+        )),
+        (Metric::GeodesicArea | Metric::Projected(_) | Metric::GeodesicLength, _, Some(true)) => {
+            checks.push(Check::Ok("座標は経度・緯度の範囲内です".to_string()))
+        }
+        (Metric::Area, _, Some(true)) if geographic_crs != Some(false) => {
+            checks.push(Check::Ok(
+                "座標は経度・緯度の範囲内です (面積の単位は度²。km² なら --metric geodesic-area)"
+                    .to_string(),
+            ))
+        }
+        (_, _, None) => checks.push(Check::Warning(
+            "座標を確かめられる Feature がありません".to_string(),
+        )),
+        _ => {}
+    }
+    if let Some(crs) = &schema.crs {
+        checks.push(Check::Ok(format!("座標参照系: {}", crs)));
+    }
+
+    checks.push(match output.check_writable() {
+        Ok(()) => Check::Ok(format!("{} に書き込めます", output.describe())),
+        Err(message) => Check::Error(message),
+    });
+    checks
+}
+
+/// 先頭の Feature に集計キーのプロパティがあるか
+fn check_group_by(sample: &[geojson::Feature], group_by: &str) -> Check {
+    let found = sample
+        .iter()
+        .filter(|feature| feature.property(group_by).is_some_and(|v| !v.is_null()))
+        .count();
+    if found > 0 {
+        return Check::Ok(format!(
+            "集計キー {} は先頭の {} 個の Feature のうち {} 個にあります",
+            group_by,
+            sample.len(),
+            found
+        ));
+    }
+    let names: BTreeSet<&str> = sample
+        .iter()
+        .filter_map(|feature| feature.properties.as_ref())
+        .flat_map(|properties| properties.keys().map(String::as_str))
+        .collect();
+    Check::Error(format!(
+        "集計キー {} が先頭の {} 個の Feature にありません (プロパティ: {})",
+        group_by,
+        sample.len(),
+        names.into_iter().collect::<Vec<_>>().join(", ")
+    ))
+}
@@ -0,0 +1,98 @@
+//! 市区町村のポリゴンから都道府県の境界を作る（`layon prefectures`）。
+//! 都道府県名（既定: N03_001）ごとに面積を集計してポリゴンをディゾルブし、境界を簡略化する。
+//! 面積は簡略化する前のジオメトリで求めるため、簡略化の度合いによらず市区町村の面積の合計と一致する。
+
+use crate::{
+    aggregate::{GroupResult, Metric},
+    pipeline::Pipeline,
+    source::Input,
+};
+use geo::{LineString, MultiPolygon, Polygon, Simplify};
+use rayon::prelude::*;
+use std::error::Error;
+
+/// `group_by` ごとにディゾルブし、境界を `tolerance`（座標の単位。0 なら簡略化しない）で簡略化する
+pub fn dissolve(
+    input: Input,
+    group_by: &str,
+    metric: Metric,
+    tolerance: f64,
+) -> Result<Vec<GroupResult>, Box<dyn Error>> {
+    let mut rows = Pipeline::from_input(input)
+        .group_by(group_by)
+        .metric(metric)
+        .geometry(true)
+        .run()?
+        .rows;
+    if tolerance > 0.0 {
+        rows.par_iter_mut().for_each(|row| {
+            row.geometry = row
+                .geometry
+                .take()
+                .map(|polygons| simplify(&polygons, tolerance));
+        });
+    }
+    Ok(rows)
+}
+
+/// Douglas-Peucker 法で簡略化する。3 点未満に潰れた外周のポリゴンと穴は除く
+pub fn simplify(polygons: &MultiPolygon<f64>, tolerance: f64) -> MultiPolygon<f64> {
+    let polygons = polygons
+        .iter()
+        .filter_map(|polygon| {
+            let exterior = polygon.exterior().simplify(&tolerance);
+            if !is_ring(&exterior) {
+                return None;
+            }
+            let interiors = polygon
+                .interiors()
+                .iter()
+                .map(|ring| ring.simplify(&tolerance))
+                .filter(is_ring)
+                .collect();
+            Some(Polygon::new(exterior, interiors))
+        })
+        .collect();
+    MultiPolygon::new(polygons)
+}
+
+/// 面を囲めるリング（閉じた 4 点以上）かどうか
+fn is_ring(ring: &LineString<f64>) -> bool {
+    ring.0.len() >= 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::InputOptions;
+    use geo::CoordsIter;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    #[test]
+    fn dissolved_and_simplified_without_changing_areas() {
+        let input = || Input::parse(FIXTURE, InputOptions::default()).unwrap();
+        let municipalities: f64 = Pipeline::from_input(input())
+            .metric(Metric::GeodesicArea)
+            .run()
+            .unwrap()
+            .rows
+            .iter()
+            .map(|row| row.area)
+            .sum();
+
+        let exact = dissolve(input(), "N03_001", Metric::GeodesicArea, 0.0).unwrap();
+        let simplified = dissolve(input(), "N03_001", Metric::GeodesicArea, 0.001).unwrap();
+        assert_eq!(exact.len(), 1);
+        assert_eq!(simplified[0].key, "埼玉県");
+        assert_eq!(simplified[0].count, 8);
+        assert!((simplified[0].area - municipalities).abs() < 1e-9 * municipalities);
+        assert_eq!(simplified[0].area, exact[0].area);
+
+        let vertices = |rows: &[GroupResult]| rows[0].geometry.as_ref().unwrap().coords_count();
+        assert!(vertices(&simplified) < vertices(&exact));
+    }
+}
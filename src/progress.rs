@@ -0,0 +1,158 @@
+//! 処理の進み具合の通知（`Pipeline::on_progress`）。
+//!
+//! GUI や Web のフロントエンドに組み込んで進み具合を表示するために、段階が変わったときと、
+//! 集計し終えた Feature の数が増えたときにコールバックを呼ぶ。集計の途中の通知は、並列に処理しているスレッドから
+//! 呼ばれるため、`INTERVAL` に 1 回までに間引く（最後の Feature を集計し終えたときは必ず通知する）。
+//! 別々のスレッドからの通知は前後することがあるので、表示する側では集計し終えた数の大きいほうを使う。
+//! 別のスレッドで受け取る場合は、コールバックの中で `std::sync::mpsc` の Sender に送ればよい。
+
+use crate::timing::Stage;
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// 集計の途中の通知の間隔の下限
+const INTERVAL: Duration = Duration::from_millis(100);
+
+/// 通知の内容
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// 段階が始まった（読み込み、前処理、計算、書き出し。計算は集約も含む）
+    Stage(Stage),
+    /// 集計し終えた Feature の数が増えた
+    Processed(Snapshot),
+    /// すべての段階が終わった（エラーで終わった場合は通知しない）
+    Finished { elapsed: Duration },
+}
+
+/// 集計の進み具合
+#[derive(Clone, Copy, Debug)]
+pub struct Snapshot {
+    /// 集計し終えた Feature の数
+    pub processed: usize,
+    /// 集計する Feature の数（絞り込みと重複の除去の後）
+    pub total: usize,
+    /// 集計を始めてからの時間
+    pub elapsed: Duration,
+}
+
+impl Snapshot {
+    /// 集計し終えた割合（0〜1。集計する Feature がなければ 1）
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.processed as f64 / self.total as f64
+        }
+    }
+
+    /// 残りの時間の見込み（これまでの速さが続くとした場合。まだ 1 つも集計していなければ None）
+    pub fn eta(&self) -> Option<Duration> {
+        (self.processed > 0).then(|| {
+            let remaining = self.total.saturating_sub(self.processed) as f64;
+            self.elapsed.mul_f64(remaining / self.processed as f64)
+        })
+    }
+}
+
+type Callback = Box<dyn Fn(&Event) + Send + Sync>;
+
+/// 進み具合を通知するハンドル。複製したハンドルは同じコールバックと数を共有する。
+/// 既定のハンドルは何も通知しない
+#[derive(Clone, Default)]
+pub struct Progress {
+    inner: Option<Arc<Inner>>,
+}
+
+struct Inner {
+    callback: Callback,
+    processed: AtomicUsize,
+    total: AtomicUsize,
+    /// ハンドルを作った時刻（ほかの時刻はここからのナノ秒で持つ）
+    origin: Instant,
+    /// 集計を始めた時刻
+    started: AtomicU64,
+    /// 最後に通知した時刻
+    reported: AtomicU64,
+}
+
+impl Progress {
+    /// `callback` に通知するハンドル
+    pub fn new(callback: impl Fn(&Event) + Send + Sync + 'static) -> Progress {
+        Progress {
+            inner: Some(Arc::new(Inner {
+                callback: Box::new(callback),
+                processed: AtomicUsize::new(0),
+                total: AtomicUsize::new(0),
+                origin: Instant::now(),
+                started: AtomicU64::new(0),
+                reported: AtomicU64::new(0),
+            })),
+        }
+    }
+
+    /// 段階が始まったことを通知する
+    pub(crate) fn stage(&self, stage: Stage) {
+        if let Some(inner) = &self.inner {
+            (inner.callback)(&Event::Stage(stage));
+        }
+    }
+
+    /// `total` 個の Feature の集計を始める（計算の段階を通知する）
+    pub(crate) fn start(&self, total: usize) {
+        if let Some(inner) = &self.inner {
+            inner.processed.store(0, Ordering::Relaxed);
+            inner.total.store(total, Ordering::Relaxed);
+            let now = inner.origin.elapsed().as_nanos() as u64;
+            inner.started.store(now, Ordering::Relaxed);
+            inner.reported.store(now, Ordering::Relaxed);
+            (inner.callback)(&Event::Stage(Stage::Compute));
+        }
+    }
+
+    /// 集計する Feature が `count` 個増えた（読み込みながら集計し、はじめは数が分からない場合）
+    pub(crate) fn add_total(&self, count: usize) {
+        if let Some(inner) = &self.inner {
+            inner.total.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    /// `count` 個の Feature を集計し終えた（前の通知から `INTERVAL` 経つか、すべて集計し終えたら通知する）
+    pub(crate) fn advance(&self, count: usize) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        let processed = inner.processed.fetch_add(count, Ordering::Relaxed) + count;
+        let total = inner.total.load(Ordering::Relaxed);
+        let nanos = inner.origin.elapsed().as_nanos() as u64;
+        let elapsed =
+            Duration::from_nanos(nanos.saturating_sub(inner.started.load(Ordering::Relaxed)));
+        let reported = inner.reported.load(Ordering::Relaxed);
+        let due = nanos.saturating_sub(reported) >= INTERVAL.as_nanos() as u64;
+        // 同時に間隔を過ぎたスレッドのうち 1 つだけが通知する
+        if (due
+            && inner
+                .reported
+                .compare_exchange(reported, nanos, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok())
+            || processed == total
+        {
+            (inner.callback)(&Event::Processed(Snapshot {
+                processed,
+                total,
+                elapsed,
+            }));
+        }
+    }
+
+    /// すべての段階が終わったことを通知する
+    pub(crate) fn finish(&self, elapsed: Duration) {
+        if let Some(inner) = &self.inner {
+            (inner.callback)(&Event::Finished { elapsed });
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! 投影した平面上での面積（`--projection`）。
+//! EPSG コードを覚えていなくても、名前で選んだ投影法で Feature を投影し、平面上の面積 (km²) を求める。
+//! 座標は経度・緯度（JGD2011 / WGS84）であること。楕円体は GRS80（WGS84 との違いは面積に現れない程度）。
+//!
+//! - `jgd2011-albers`: 日本全域向けのアルベルス正積円錐図法。正積なので、どの場所でも面積の歪みがない
+//! - `utm-auto`: Feature の重心の経度からゾーンを選んだ UTM（横メルカトル図法）。
+//!   正積ではないため、中央子午線の付近で約 -0.08%、ゾーンの端で約 +0.2% 面積がずれる
+//! - `utm-54` / `utm-54s`: ゾーン（と南半球）を指定した UTM
+//! - `laea-auto`: Feature ごとに、その重心を中心にしたランベルト正積方位図法で投影する
+//!
+//! `laea-auto` は正積で、しかも Feature の付近の形の歪みも最も小さいため、辺を投影面上の直線とみなす誤差
+//! （頂点の間隔が広いほど大きい）も小さく、日本の外のデータにもそのまま使える。
+//! その代わり、Feature ごとに重心と投影法の定数を求めるため、1 つの投影法を使う `jgd2011-albers` より遅い
+//! （頂点 8 個の Feature 20 万個で、面積の計算の時間は約 2.5 倍。楕円体上の面積の 4 分の 1 程度）。
+//! 頂点の細かい行政区域データでは `jgd2011-albers` との差は 1e-9 程度なので、日本のデータで速さを優先するならそちらでよい。
+//! どれも Feature ごとに並列に計算する。
+
+use crate::{area, flat::FlatPolygons};
+use geo::Coord;
+
+/// GRS80 の長半径 (m)
+const A: f64 = 6_378_137.0;
+/// GRS80 の扁平率
+const F: f64 = 1.0 / 298.257_222_101;
+
+/// `jgd2011-albers` の標準緯線、原点の緯度と中央子午線（度）
+const ALBERS: (f64, f64, f64, f64) = (30.0, 42.0, 36.0, 137.0);
+
+/// UTM の中央子午線の縮尺係数
+const UTM_SCALE: f64 = 0.9996;
+
+/// 面積を求める投影法
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// 日本全域向けのアルベルス正積円錐図法（標準緯線は北緯 30° と 42°、中央子午線は東経 137°）
+    Jgd2011Albers,
+    /// ゾーン（1〜60）を指定した UTM
+    Utm { zone: u8, north: bool },
+    /// Feature ごとに重心の経度からゾーンを選ぶ UTM
+    UtmAuto,
+    /// Feature ごとに重心を中心にしたランベルト正積方位図法
+    LaeaAuto,
+}
+
+impl Projection {
+    /// `--projection` の値（`jgd2011-albers`, `utm-auto`, `utm-54`, `utm-54s` など）
+    pub fn parse(name: &str) -> Result<Projection, String> {
+        match name {
+            "jgd2011-albers" => return Ok(Projection::Jgd2011Albers),
+            "utm-auto" => return Ok(Projection::UtmAuto),
+            "laea-auto" => return Ok(Projection::LaeaAuto),
+            _ => {}
+        }
+        let zone = name
+            .strip_prefix("utm-")
+            .ok_or_else(|| format!("不明な投影法です: {}", name))?;
+        let (zone, north) = match zone.strip_suffix('s') {
+            Some(zone) => (zone, false),
+            None => (zone.strip_suffix('n').unwrap_or(zone), true),
+        };
+        match zone.parse::<u8>() {
+            Ok(zone @ 1..=60) => Ok(Projection::Utm { zone, north }),
+            _ => Err(format!("UTM のゾーンは 1〜60 です: {}", name)),
+        }
+    }
+
+    /// 表示用の名前（`parse` で読める形）
+    pub fn name(&self) -> String {
+        match self {
+            Projection::Jgd2011Albers => "jgd2011-albers".to_string(),
+            Projection::Utm { zone, north: true } => format!("utm-{}", zone),
+            Projection::Utm { zone, north: false } => format!("utm-{}s", zone),
+            Projection::UtmAuto => "utm-auto".to_string(),
+            Projection::LaeaAuto => "laea-auto".to_string(),
+        }
+    }
+
+    /// `center`（経度・緯度）の付近を投影する投影法（`utm-auto` ならここでゾーンを、`laea-auto` なら中心を決める）
+    pub(crate) fn at(&self, center: Coord<f64>) -> Projector {
+        match *self {
+            Projection::Jgd2011Albers => {
+                let (lat1, lat2, lat0, lon0) = ALBERS;
+                Projector::Albers(Albers::new(lat1, lat2, lat0, lon0))
+            }
+            Projection::Utm { zone, north } => Projector::utm(zone, north),
+            Projection::UtmAuto => {
+                let zone = ((center.x + 180.0) / 6.0).floor().clamp(0.0, 59.0) as u8 + 1;
+                Projector::utm(zone, center.y >= 0.0)
+            }
+            Projection::LaeaAuto => Projector::Laea(Laea::new(center)),
+        }
+    }
+
+    /// Feature のポリゴンの投影面上の面積 (m²。穴を除く。ポリゴンがなければ 0)
+    pub(crate) fn area(&self, flat: &FlatPolygons) -> f64 {
+        match flat.centroid() {
+            Some(center) => {
+                let projector = self.at(center);
+                flat.area_with(|ring| projector.ring_area(ring))
+            }
+            None => 0.0,
+        }
+    }
+}
+
+/// ゾーンなどを決めた投影法
+pub enum Projector {
+    Albers(Albers),
+    Laea(Laea),
+    TransverseMercator {
+        /// 中央子午線（度）
+        lon0: f64,
+        false_northing: f64,
+    },
+}
+
+impl Projector {
+    fn utm(zone: u8, north: bool) -> Projector {
+        Projector::TransverseMercator {
+            lon0: zone as f64 * 6.0 - 183.0,
+            false_northing: if north { 0.0 } else { 10_000_000.0 },
+        }
+    }
+
+    /// 経度・緯度を投影面上の座標 (m) にする
+    pub fn forward(&self, coord: Coord<f64>) -> Coord<f64> {
+        match self {
+            Projector::Albers(albers) => albers.forward(coord),
+            Projector::Laea(laea) => laea.forward(coord),
+            Projector::TransverseMercator {
+                lon0,
+                false_northing,
+            } => {
+                let (x, y) = transverse_mercator(coord, *lon0);
+                Coord {
+                    x: 500_000.0 + UTM_SCALE * x,
+                    y: false_northing + UTM_SCALE * y,
+                }
+            }
+        }
+    }
+
+    /// リングの投影面上の面積 (m²。向きによらず正の値)
+    pub fn ring_area(&self, ring: &[Coord<f64>]) -> f64 {
+        let projected: Vec<Coord<f64>> = ring.iter().map(|&coord| self.forward(coord)).collect();
+        area::ring_area(&projected)
+    }
+}
+
+/// 楕円体のアルベルス正積円錐図法（Snyder, Map Projections: A Working Manual, 14 章）
+pub struct Albers {
+    n: f64,
+    c: f64,
+    rho0: f64,
+    lon0: f64,
+}
+
+impl Albers {
+    /// 標準緯線 `lat1`, `lat2`、原点の緯度 `lat0`、中央子午線 `lon0`（度）
+    fn new(lat1: f64, lat2: f64, lat0: f64, lon0: f64) -> Albers {
+        let (m1, m2) = (m(lat1.to_radians()), m(lat2.to_radians()));
+        let (q1, q2) = (q(lat1.to_radians()), q(lat2.to_radians()));
+        let n = (m1 * m1 - m2 * m2) / (q2 - q1);
+        let c = m1 * m1 + n * q1;
+        let rho0 = A * (c - n * q(lat0.to_radians())).sqrt() / n;
+        Albers { n, c, rho0, lon0 }
+    }
+
+    fn forward(&self, coord: Coord<f64>) -> Coord<f64> {
+        let rho = A * (self.c - self.n * q(coord.y.to_radians())).sqrt() / self.n;
+        let theta = self.n * (coord.x - self.lon0).to_radians();
+        Coord {
+            x: rho * theta.sin(),
+            y: self.rho0 - rho * theta.cos(),
+        }
+    }
+}
+
+/// 楕円体のランベルト正積方位図法（Snyder, Map Projections: A Working Manual, 24 章の斜軸の場合）
+pub struct Laea {
+    /// 中心の経度（度）
+    lon0: f64,
+    /// 中心の正積緯度の sin と cos
+    sin_beta1: f64,
+    cos_beta1: f64,
+    /// 極までの q
+    qp: f64,
+    /// 等面積の球の半径 (m)
+    rq: f64,
+    d: f64,
+}
+
+impl Laea {
+    /// `center`（経度・緯度）を中心にする
+    fn new(center: Coord<f64>) -> Laea {
+        let qp = q(std::f64::consts::FRAC_PI_2);
+        let rq = A * (qp / 2.0).sqrt();
+        let phi1 = center.y.to_radians();
+        let beta1 = (q(phi1) / qp).clamp(-1.0, 1.0).asin();
+        Laea {
+            lon0: center.x,
+            sin_beta1: beta1.sin(),
+            cos_beta1: beta1.cos(),
+            qp,
+            rq,
+            // 極を中心にすると cos β1 が 0 になるため、ごく近い値で割る
+            d: A * m(phi1) / (rq * beta1.cos().max(1e-12)),
+        }
+    }
+
+    fn forward(&self, coord: Coord<f64>) -> Coord<f64> {
+        let beta = (q(coord.y.to_radians()) / self.qp).clamp(-1.0, 1.0).asin();
+        let (sin_beta, cos_beta) = beta.sin_cos();
+        let (sin_lambda, cos_lambda) = (coord.x - self.lon0).to_radians().sin_cos();
+        let b = self.rq
+            * (2.0 / (1.0 + self.sin_beta1 * sin_beta + self.cos_beta1 * cos_beta * cos_lambda))
+                .sqrt();
+        Coord {
+            x: b * self.d * cos_beta * sin_lambda,
+            y: b / self.d * (self.cos_beta1 * sin_beta - self.sin_beta1 * cos_beta * cos_lambda),
+        }
+    }
+}
+
+/// 第一離心率
+fn eccentricity() -> f64 {
+    (F * (2.0 - F)).sqrt()
+}
+
+fn m(phi: f64) -> f64 {
+    let e = eccentricity();
+    phi.cos() / (1.0 - (e * phi.sin()).powi(2)).sqrt()
+}
+
+fn q(phi: f64) -> f64 {
+    let e = eccentricity();
+    let sin = phi.sin();
+    (1.0 - e * e)
+        * (sin / (1.0 - (e * sin).powi(2)) - ((1.0 - e * sin) / (1.0 + e * sin)).ln() / (2.0 * e))
+}
+
+/// 中央子午線 `lon0`（度）の横メルカトル図法（縮尺係数 1。Krüger の級数の 3 次まで、誤差は 1 mm 未満）
+fn transverse_mercator(coord: Coord<f64>, lon0: f64) -> (f64, f64) {
+    let n = F / (2.0 - F);
+    let e = eccentricity();
+    let radius = A / (1.0 + n) * (1.0 + n * n / 4.0 + n.powi(4) / 64.0);
+    let alpha = [
+        n / 2.0 - 2.0 * n * n / 3.0 + 5.0 * n.powi(3) / 16.0,
+        13.0 * n * n / 48.0 - 3.0 * n.powi(3) / 5.0,
+        61.0 * n.powi(3) / 240.0,
+    ];
+
+    let phi = coord.y.to_radians();
+    let lambda = (coord.x - lon0).to_radians();
+    let t = (phi.sin().atanh() - e * (e * phi.sin()).atanh()).sinh();
+    let xi = t.atan2(lambda.cos());
+    let eta = (lambda.sin() / (1.0 + t * t).sqrt()).atanh();
+
+    let (mut x, mut y) = (eta, xi);
+    for (j, alpha) in alpha.iter().enumerate() {
+        let k = 2.0 * (j + 1) as f64;
+        x += alpha * (k * xi).cos() * (k * eta).sinh();
+        y += alpha * (k * xi).sin() * (k * eta).cosh();
+    }
+    (radius * x, radius * y)
+}
@@ -0,0 +1,84 @@
+//! TIFF の LZW 圧縮（MSB ファーストの可変長符号、Clear = 256, EOI = 257）の展開。
+
+const CLEAR: usize = 256;
+const END: usize = 257;
+
+pub fn decode(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(data.len() * 3);
+    // 各符号の (直前の符号, 末尾のバイト, 長さ)。直前の符号をたどって展開する
+    let mut table: Vec<(usize, u8, usize)> = Vec::with_capacity(4096);
+    let reset = |table: &mut Vec<(usize, u8, usize)>| {
+        table.clear();
+        table.extend((0..256).map(|b| (usize::MAX, b as u8, 1)));
+        // Clear と EOI の分を埋めておく
+        table.push((usize::MAX, 0, 0));
+        table.push((usize::MAX, 0, 0));
+    };
+    reset(&mut table);
+
+    let (mut bit_pos, total_bits) = (0usize, data.len() * 8);
+    let mut width = 9;
+    let mut previous: Option<usize> = None;
+
+    while bit_pos + width <= total_bits {
+        let mut code = 0usize;
+        for i in 0..width {
+            let bit = data[(bit_pos + i) / 8] >> (7 - (bit_pos + i) % 8) & 1;
+            code = code << 1 | bit as usize;
+        }
+        bit_pos += width;
+
+        if code == CLEAR {
+            reset(&mut table);
+            width = 9;
+            previous = None;
+            continue;
+        }
+        if code == END {
+            break;
+        }
+
+        let start = out.len();
+        match previous {
+            None => {
+                if code >= 256 {
+                    return Err("LZW の先頭の符号が不正です".to_string());
+                }
+                out.push(code as u8);
+            }
+            Some(prev) => {
+                if code < table.len() {
+                    emit(&table, code, &mut out);
+                } else if code == table.len() {
+                    // まだ表にない符号は「直前の列 + その先頭のバイト」
+                    emit(&table, prev, &mut out);
+                    out.push(out[start]);
+                } else {
+                    return Err("LZW の符号が表の範囲外です".to_string());
+                }
+                if table.len() < 4096 {
+                    table.push((prev, out[start], table[prev].2 + 1));
+                }
+            }
+        }
+        previous = Some(code);
+
+        // TIFF の LZW は 1 つ早く符号長を増やす
+        if table.len() + 1 >= 1 << width && width < 12 {
+            width += 1;
+        }
+    }
+    Ok(out)
+}
+
+/// 符号が表す列を出力の末尾に書き出す
+fn emit(table: &[(usize, u8, usize)], code: usize, out: &mut Vec<u8>) {
+    let len = table[code].2;
+    let start = out.len();
+    out.resize(start + len, 0);
+    let mut code = code;
+    for i in (0..len).rev() {
+        out[start + i] = table[code].1;
+        code = table[code].0;
+    }
+}
@@ -0,0 +1,99 @@
+//! ラスターデータ（標高、土地被覆、人口メッシュなど）の読み込み。
+//! 画素の座標はベクターデータと同じ座標系（通常は経度・緯度）である前提で扱う。
+
+mod lzw;
+mod tiff;
+
+use crate::aggregate::Metric;
+use geo::{orient::Direction, GeodesicArea, Orient, Rect};
+use std::{error::Error, fs};
+
+/// 1 バンドのラスター。画素は左上から行ごとに並ぶ
+pub struct Raster {
+    pub width: usize,
+    pub height: usize,
+    /// 左上の角の座標 (x, y)
+    pub origin: (f64, f64),
+    /// 1 画素の幅と高さ（高さは南向きを正とする）
+    pub pixel_size: (f64, f64),
+    /// 欠損値
+    pub nodata: Option<f64>,
+    pub values: Vec<f64>,
+}
+
+impl Raster {
+    /// GeoTIFF ファイルを読み込む
+    pub fn read(path: &str) -> Result<Raster, Box<dyn Error>> {
+        let lower = path.to_lowercase();
+        if !(lower.ends_with(".tif") || lower.ends_with(".tiff")) {
+            return Err(format!("未対応のラスター形式です（GeoTIFF のみ対応）: {}", path).into());
+        }
+        let data = fs::read(path)?;
+        tiff::parse(&data).map_err(|err| format!("{}: {}", path, err).into())
+    }
+
+    /// 画素の値（欠損値は None）
+    pub fn value(&self, col: usize, row: usize) -> Option<f64> {
+        let value = self.values[row * self.width + col];
+        if value.is_nan() || self.nodata == Some(value) {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    /// 画素の中心の座標
+    pub fn center(&self, col: usize, row: usize) -> (f64, f64) {
+        (
+            self.origin.0 + (col as f64 + 0.5) * self.pixel_size.0,
+            self.origin.1 - (row as f64 + 0.5) * self.pixel_size.1,
+        )
+    }
+
+    /// 行ごとの 1 画素の面積（`Metric::GeodesicArea` なら楕円体上の km²、`Metric::Projected` なら投影面上の km²、
+    /// そうでなければ座標の単位）。
+    /// 経度・緯度のラスターでは、画素の面積は緯度によって変わる
+    pub fn pixel_areas(&self, metric: Metric) -> Vec<f64> {
+        let (width, height) = self.pixel_size;
+        (0..self.height)
+            .map(|row| {
+                let top = self.origin.1 - row as f64 * height;
+                let rect = Rect::new((self.origin.0, top), (self.origin.0 + width, top - height));
+                match metric {
+                    Metric::Area => (width * height).abs(),
+                    // 向きによっては地球の残りの面積になるため、反時計回りにそろえる
+                    Metric::GeodesicArea | Metric::GeodesicLength => {
+                        rect.to_polygon()
+                            .orient(Direction::Default)
+                            .geodesic_area_unsigned()
+                            / 1e6
+                    }
+                    Metric::Projected(projection) => {
+                        projection
+                            .at(rect.center())
+                            .ring_area(&rect.to_polygon().exterior().0)
+                            / 1e6
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// 座標の範囲に中心が入る可能性のある画素の列と行の範囲
+    pub fn window(&self, min: (f64, f64), max: (f64, f64)) -> (Range, Range) {
+        let col = |x: f64| (x - self.origin.0) / self.pixel_size.0 - 0.5;
+        let row = |y: f64| (self.origin.1 - y) / self.pixel_size.1 - 0.5;
+        let clamp = |a: f64, b: f64, limit: usize| {
+            let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+            let lo = lo.ceil().max(0.0) as usize;
+            let hi = (hi.floor() + 1.0).clamp(0.0, limit as f64) as usize;
+            lo.min(hi)..hi
+        };
+        (
+            clamp(col(min.0), col(max.0), self.width),
+            clamp(row(min.1), row(max.1), self.height),
+        )
+    }
+}
+
+pub type Range = std::ops::Range<usize>;
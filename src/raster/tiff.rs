@@ -0,0 +1,818 @@
+//! GeoTIFF（TIFF と BigTIFF）の最初の画像から 1 バンド目を読み込む。
+//!
+//! ストリップとタイルの両方の配置、無圧縮・LZW・Deflate・PackBits の圧縮、
+//! 水平差分と浮動小数点の予測子に対応する。地理参照は ModelTiepoint と ModelPixelScale
+//! （または回転なしの ModelTransformation）から取り、GDAL_NODATA を欠損値として扱う。
+
+use super::{lzw, Raster};
+use crate::inflate;
+use std::collections::HashMap;
+
+// タグ番号
+const IMAGE_WIDTH: u16 = 256;
+const IMAGE_LENGTH: u16 = 257;
+const BITS_PER_SAMPLE: u16 = 258;
+const COMPRESSION: u16 = 259;
+const STRIP_OFFSETS: u16 = 273;
+const SAMPLES_PER_PIXEL: u16 = 277;
+const ROWS_PER_STRIP: u16 = 278;
+const STRIP_BYTE_COUNTS: u16 = 279;
+const PLANAR_CONFIGURATION: u16 = 284;
+const PREDICTOR: u16 = 317;
+const TILE_WIDTH: u16 = 322;
+const TILE_LENGTH: u16 = 323;
+const TILE_OFFSETS: u16 = 324;
+const TILE_BYTE_COUNTS: u16 = 325;
+const SAMPLE_FORMAT: u16 = 339;
+const MODEL_PIXEL_SCALE: u16 = 33550;
+const MODEL_TIEPOINT: u16 = 33922;
+const MODEL_TRANSFORMATION: u16 = 34264;
+const GDAL_NODATA: u16 = 42113;
+
+/// IFD のエントリ（型と個数、値の位置）
+struct Entry {
+    kind: u16,
+    count: u64,
+    /// 値そのもの（収まる場合）か値の位置
+    offset: usize,
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+    entries: HashMap<u16, Entry>,
+}
+
+pub fn parse(data: &[u8]) -> Result<Raster, String> {
+    let little_endian = match data.get(..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return Err("TIFF のバイトオーダーが不正です".to_string()),
+    };
+    let mut tiff = Tiff {
+        data,
+        little_endian,
+        entries: HashMap::new(),
+    };
+
+    // 42 = TIFF, 43 = BigTIFF（オフセットが 8 バイト）
+    let big = match tiff.uint(2, 2)? {
+        42 => false,
+        43 => true,
+        _ => return Err("TIFF のマジックナンバーが不正です".to_string()),
+    };
+    let (offset_size, entry_size, count_size) = if big { (8, 20, 8) } else { (4, 12, 2) };
+    let ifd = tiff.uint(if big { 8 } else { 4 }, offset_size)? as usize;
+    let count = tiff.uint(ifd, count_size)? as usize;
+    for i in 0..count {
+        let at = ifd + count_size + i * entry_size;
+        let tag = tiff.uint(at, 2)? as u16;
+        let kind = tiff.uint(at + 2, 2)? as u16;
+        let count = tiff.uint(at + 4, offset_size)?;
+        let value_at = at + 4 + offset_size;
+        let size = type_size(kind) as u64 * count;
+        let offset = if size <= offset_size as u64 {
+            value_at
+        } else {
+            tiff.uint(value_at, offset_size)? as usize
+        };
+        tiff.entries.insert(
+            tag,
+            Entry {
+                kind,
+                count,
+                offset,
+            },
+        );
+    }
+
+    let width = tiff
+        .single(IMAGE_WIDTH)?
+        .ok_or("TIFF に ImageWidth がありません")? as usize;
+    let height = tiff
+        .single(IMAGE_LENGTH)?
+        .ok_or("TIFF に ImageLength がありません")? as usize;
+    let samples = tiff.single(SAMPLES_PER_PIXEL)?.unwrap_or(1) as usize;
+    let bits = tiff
+        .integers(BITS_PER_SAMPLE)?
+        .first()
+        .copied()
+        .unwrap_or(1) as usize;
+    let format = tiff.single(SAMPLE_FORMAT)?.unwrap_or(1);
+    let compression = tiff.single(COMPRESSION)?.unwrap_or(1);
+    let predictor = tiff.single(PREDICTOR)?.unwrap_or(1);
+    let planar = tiff.single(PLANAR_CONFIGURATION)?.unwrap_or(1);
+    if !matches!(bits, 8 | 16 | 32 | 64) || format == 3 && bits < 32 {
+        return Err(format!("{} ビットの画素には対応していません", bits));
+    }
+
+    // ストリップはタイルの幅が画像の幅と同じものとして扱う
+    let (chunk_width, chunk_height, offsets, counts) =
+        match (tiff.single(TILE_WIDTH)?, tiff.single(TILE_LENGTH)?) {
+            (Some(w), Some(h)) => (
+                w as usize,
+                h as usize,
+                tiff.integers(TILE_OFFSETS)?,
+                tiff.integers(TILE_BYTE_COUNTS)?,
+            ),
+            _ => (
+                width,
+                tiff.single(ROWS_PER_STRIP)?
+                    .map_or(height, |rows| (rows as usize).min(height)),
+                tiff.integers(STRIP_OFFSETS)?,
+                tiff.integers(STRIP_BYTE_COUNTS)?,
+            ),
+        };
+    if chunk_width == 0 || chunk_height == 0 || offsets.len() != counts.len() {
+        return Err("TIFF のストリップまたはタイルの定義が不正です".to_string());
+    }
+    let across = width.div_ceil(chunk_width);
+    let down = height.div_ceil(chunk_height);
+    // 1 画素あたりのサンプル数（バンドが別々の平面に分かれている場合は 1）
+    let stride = if planar == 2 { 1 } else { samples };
+    if offsets.len() < across * down {
+        return Err("TIFF のストリップまたはタイルが足りません".to_string());
+    }
+
+    let bytes = bits / 8;
+    let mut values = vec![f64::NAN; width * height];
+    for (index, (&offset, &count)) in offsets.iter().zip(&counts).take(across * down).enumerate() {
+        let raw = data
+            .get(offset as usize..(offset + count) as usize)
+            .ok_or("TIFF のストリップまたはタイルがファイルの範囲外です")?;
+        let mut chunk = match compression {
+            1 => raw.to_vec(),
+            5 => lzw::decode(raw)?,
+            8 | 32946 => inflate::zlib(raw)?,
+            32773 => packbits(raw)?,
+            other => return Err(format!("未対応の TIFF の圧縮方式です: {}", other)),
+        };
+
+        let row_bytes = chunk_width * stride * bytes;
+        // ストリップの最後は高さが足りないことがある
+        let rows = (chunk.len() / row_bytes).min(chunk_height);
+        chunk.truncate(rows * row_bytes);
+        match predictor {
+            1 => {}
+            2 => unpredict_horizontal(&mut chunk, row_bytes, stride, bytes, little_endian),
+            3 => unpredict_float(&mut chunk, row_bytes, stride, bytes),
+            other => return Err(format!("未対応の TIFF の予測子です: {}", other)),
+        }
+        // 浮動小数点の予測子は並べ替えた結果がビッグエンディアンになる
+        let chunk_little_endian = little_endian && predictor != 3;
+
+        let (chunk_x, chunk_y) = (index % across * chunk_width, index / across * chunk_height);
+        for row in 0..rows {
+            let y = chunk_y + row;
+            if y >= height {
+                break;
+            }
+            for col in 0..chunk_width.min(width - chunk_x) {
+                let at = row * row_bytes + col * stride * bytes;
+                let sample = &chunk[at..at + bytes];
+                values[y * width + chunk_x + col] =
+                    sample_value(sample, format, chunk_little_endian);
+            }
+        }
+    }
+
+    let (origin, pixel_size) = georeference(&tiff)?;
+    let nodata = tiff
+        .ascii(GDAL_NODATA)?
+        .and_then(|text| text.trim_end_matches('\0').trim().parse().ok());
+    Ok(Raster {
+        width,
+        height,
+        origin,
+        pixel_size,
+        nodata,
+        values,
+    })
+}
+
+/// 左上の角の座標と画素の大きさ
+type Georeference = ((f64, f64), (f64, f64));
+
+fn georeference(tiff: &Tiff) -> Result<Georeference, String> {
+    let transformation = tiff.doubles(MODEL_TRANSFORMATION)?;
+    if transformation.len() >= 8 {
+        // x = a * 列 + b * 行 + d, y = e * 列 + f * 行 + h
+        let (a, b, d, e, f, h) = (
+            transformation[0],
+            transformation[1],
+            transformation[3],
+            transformation[4],
+            transformation[5],
+            transformation[7],
+        );
+        if b != 0.0 || e != 0.0 {
+            return Err("回転した GeoTIFF には対応していません".to_string());
+        }
+        return Ok(((d, h), (a, -f)));
+    }
+
+    let scale = tiff.doubles(MODEL_PIXEL_SCALE)?;
+    let tiepoint = tiff.doubles(MODEL_TIEPOINT)?;
+    if scale.len() < 2 || tiepoint.len() < 6 {
+        return Err(
+            "GeoTIFF の地理参照（ModelTiepoint と ModelPixelScale）がありません".to_string(),
+        );
+    }
+    let (i, j, x, y) = (tiepoint[0], tiepoint[1], tiepoint[3], tiepoint[4]);
+    Ok(((x - i * scale[0], y + j * scale[1]), (scale[0], scale[1])))
+}
+
+fn type_size(kind: u16) -> usize {
+    match kind {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 | 16 | 17 => 8,
+        _ => 0,
+    }
+}
+
+impl Tiff<'_> {
+    fn bytes(&self, at: usize, len: usize) -> Result<&[u8], String> {
+        self.data
+            .get(at..at + len)
+            .ok_or_else(|| "TIFF が途中で終わっています".to_string())
+    }
+
+    /// 符号なし整数（バイトオーダーに従う）
+    fn uint(&self, at: usize, len: usize) -> Result<u64, String> {
+        let bytes = self.bytes(at, len)?;
+        Ok(if self.little_endian {
+            bytes.iter().rev().fold(0, |v, &b| v << 8 | u64::from(b))
+        } else {
+            bytes.iter().fold(0, |v, &b| v << 8 | u64::from(b))
+        })
+    }
+
+    /// 整数型のタグの値
+    fn integers(&self, tag: u16) -> Result<Vec<u64>, String> {
+        let Some(entry) = self.entries.get(&tag) else {
+            return Ok(Vec::new());
+        };
+        let size = match entry.kind {
+            1 | 3 | 4 | 16 => type_size(entry.kind),
+            _ => return Err(format!("TIFF のタグ {} が整数型ではありません", tag)),
+        };
+        (0..entry.count as usize)
+            .map(|i| self.uint(entry.offset + i * size, size))
+            .collect()
+    }
+
+    fn single(&self, tag: u16) -> Result<Option<u64>, String> {
+        Ok(self.integers(tag)?.first().copied())
+    }
+
+    /// DOUBLE 型のタグの値
+    fn doubles(&self, tag: u16) -> Result<Vec<f64>, String> {
+        let Some(entry) = self.entries.get(&tag) else {
+            return Ok(Vec::new());
+        };
+        if entry.kind != 12 {
+            return Err(format!("TIFF のタグ {} が DOUBLE 型ではありません", tag));
+        }
+        (0..entry.count as usize)
+            .map(|i| Ok(f64::from_bits(self.uint(entry.offset + i * 8, 8)?)))
+            .collect()
+    }
+
+    fn ascii(&self, tag: u16) -> Result<Option<String>, String> {
+        let Some(entry) = self.entries.get(&tag) else {
+            return Ok(None);
+        };
+        let bytes = self.bytes(entry.offset, entry.count as usize)?;
+        Ok(Some(String::from_utf8_lossy(bytes).into_owned()))
+    }
+}
+
+/// 1 サンプルを数値にする
+fn sample_value(bytes: &[u8], format: u64, little_endian: bool) -> f64 {
+    let mut buf = [0u8; 8];
+    let n = bytes.len();
+    if little_endian {
+        buf[..n].copy_from_slice(bytes);
+    } else {
+        for (i, &b) in bytes.iter().rev().enumerate() {
+            buf[i] = b;
+        }
+    }
+    let raw = u64::from_le_bytes(buf);
+    match (format, n) {
+        (3, 4) => f32::from_bits(raw as u32) as f64,
+        (3, _) => f64::from_bits(raw),
+        // 2 = 符号付き整数。上位ビットを符号拡張する
+        (2, _) => ((raw << (64 - n * 8)) as i64 >> (64 - n * 8)) as f64,
+        _ => raw as f64,
+    }
+}
+
+/// 水平差分の予測子（行ごとに左隣のサンプルとの差が入っている）を元に戻す
+fn unpredict_horizontal(
+    chunk: &mut [u8],
+    row_bytes: usize,
+    stride: usize,
+    bytes: usize,
+    little_endian: bool,
+) {
+    let read = |b: &[u8]| -> u64 {
+        if little_endian {
+            b.iter().rev().fold(0, |v, &x| v << 8 | u64::from(x))
+        } else {
+            b.iter().fold(0, |v, &x| v << 8 | u64::from(x))
+        }
+    };
+    let write = |b: &mut [u8], v: u64| {
+        for i in 0..b.len() {
+            let shift = if little_endian { i } else { b.len() - 1 - i } * 8;
+            b[i] = (v >> shift) as u8;
+        }
+    };
+    let step = stride * bytes;
+    for row in chunk.chunks_mut(row_bytes) {
+        for at in (step..row.len()).step_by(bytes) {
+            let left = read(&row[at - step..at - step + bytes]);
+            let current = read(&row[at..at + bytes]);
+            write(&mut row[at..at + bytes], current.wrapping_add(left));
+        }
+    }
+}
+
+/// 浮動小数点の予測子（バイト単位の差分と、上位バイトから順に並べ替えたもの）を元に戻す。
+/// 結果は各サンプルがビッグエンディアンで並ぶ。
+fn unpredict_float(chunk: &mut [u8], row_bytes: usize, stride: usize, bytes: usize) {
+    let samples = row_bytes / bytes;
+    let mut row_out = vec![0u8; row_bytes];
+    for row in chunk.chunks_mut(row_bytes) {
+        for i in stride..row.len() {
+            row[i] = row[i].wrapping_add(row[i - stride]);
+        }
+        for sample in 0..samples {
+            for byte in 0..bytes {
+                row_out[sample * bytes + byte] = row[byte * samples + sample];
+            }
+        }
+        row.copy_from_slice(&row_out);
+    }
+}
+
+/// PackBits（連長圧縮）の展開
+fn packbits(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(data.len() * 2);
+    let mut i = 0;
+    while i < data.len() {
+        let n = data[i] as i8;
+        i += 1;
+        if n >= 0 {
+            let len = n as usize + 1;
+            out.extend_from_slice(
+                data.get(i..i + len)
+                    .ok_or("PackBits のデータが途中で終わっています")?,
+            );
+            i += len;
+        } else if n != -128 {
+            let byte = *data
+                .get(i)
+                .ok_or("PackBits のデータが途中で終わっています")?;
+            out.extend(std::iter::repeat_n(byte, (1 - n as isize) as usize));
+            i += 1;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::Write;
+
+    /// テスト用の TIFF の書き方
+    struct Image {
+        big: bool,
+        little_endian: bool,
+        width: usize,
+        height: usize,
+        bits: usize,
+        /// SampleFormat（1 = 符号なし整数、2 = 符号付き整数、3 = 浮動小数点）
+        format: u64,
+        compression: u64,
+        predictor: u64,
+        /// タイルの幅と高さ（None ならストリップ）
+        tile: Option<(usize, usize)>,
+        rows_per_strip: usize,
+        nodata: Option<&'static str>,
+        values: Vec<f64>,
+    }
+
+    impl Default for Image {
+        fn default() -> Image {
+            Image {
+                big: false,
+                little_endian: true,
+                width: 3,
+                height: 4,
+                bits: 8,
+                format: 1,
+                compression: 1,
+                predictor: 1,
+                tile: None,
+                rows_per_strip: 2,
+                nodata: None,
+                values: (0..12).map(f64::from).collect(),
+            }
+        }
+    }
+
+    /// タグの値
+    enum Value {
+        Shorts(Vec<u64>),
+        /// LONG（BigTIFF では LONG8）
+        Longs(Vec<u64>),
+        Doubles(Vec<f64>),
+        Ascii(&'static str),
+    }
+
+    fn put(out: &mut Vec<u8>, value: u64, len: usize, little_endian: bool) {
+        let bytes = value.to_le_bytes();
+        if little_endian {
+            out.extend_from_slice(&bytes[..len]);
+        } else {
+            out.extend(bytes[..len].iter().rev());
+        }
+    }
+
+    impl Image {
+        /// 1 つのサンプルのビット表現
+        fn sample(&self, value: f64) -> u64 {
+            match (self.format, self.bits) {
+                (3, 32) => u64::from((value as f32).to_bits()),
+                (3, _) => value.to_bits(),
+                (2, bits) => (value as i64 as u64) & (u64::MAX >> (64 - bits)),
+                _ => value as u64,
+            }
+        }
+
+        /// 左上が (x, y) で幅 w, 高さ h のストリップまたはタイルのバイト列（予測子をかけて圧縮したもの）
+        fn chunk(&self, x: usize, y: usize, w: usize, h: usize) -> Vec<u8> {
+            let bytes = self.bits / 8;
+            let mut out = Vec::new();
+            for row in y..y + h {
+                // 画像の外（端のタイル）は 0 で埋める
+                let mut samples: Vec<u64> = (x..x + w)
+                    .map(|col| match (col < self.width, row < self.height) {
+                        (true, true) => self.sample(self.values[row * self.width + col]),
+                        _ => 0,
+                    })
+                    .collect();
+                match self.predictor {
+                    2 => {
+                        let mask = u64::MAX >> (64 - self.bits);
+                        for i in (1..samples.len()).rev() {
+                            samples[i] = samples[i].wrapping_sub(samples[i - 1]) & mask;
+                        }
+                        for &sample in &samples {
+                            put(&mut out, sample, bytes, self.little_endian);
+                        }
+                    }
+                    3 => {
+                        // 上位バイトから順に並べ替え、バイトごとの差分にする
+                        let mut row_bytes = vec![0u8; samples.len() * bytes];
+                        for (i, &sample) in samples.iter().enumerate() {
+                            let be = sample.to_be_bytes();
+                            for byte in 0..bytes {
+                                row_bytes[byte * samples.len() + i] = be[8 - bytes + byte];
+                            }
+                        }
+                        for i in (1..row_bytes.len()).rev() {
+                            row_bytes[i] = row_bytes[i].wrapping_sub(row_bytes[i - 1]);
+                        }
+                        out.extend(row_bytes);
+                    }
+                    _ => {
+                        for &sample in &samples {
+                            put(&mut out, sample, bytes, self.little_endian);
+                        }
+                    }
+                }
+            }
+            match self.compression {
+                5 => lzw_literals(&out),
+                8 => {
+                    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                    encoder.write_all(&out).unwrap();
+                    encoder.finish().unwrap()
+                }
+                32773 => out
+                    .chunks(128)
+                    .flat_map(|run| std::iter::once(run.len() as u8 - 1).chain(run.iter().copied()))
+                    .collect(),
+                _ => out,
+            }
+        }
+
+        /// 原点 (135, 36)、画素の大きさ 0.5 × 0.25 の GeoTIFF のバイト列
+        fn encode(&self) -> Vec<u8> {
+            let (chunk_width, chunk_height) =
+                self.tile.unwrap_or((self.width, self.rows_per_strip));
+            let mut chunks = Vec::new();
+            for y in (0..self.height).step_by(chunk_height) {
+                for x in (0..self.width).step_by(chunk_width) {
+                    // ストリップの最後は画像の高さまで
+                    let h = match self.tile {
+                        Some(_) => chunk_height,
+                        None => chunk_height.min(self.height - y),
+                    };
+                    chunks.push(self.chunk(x, y, chunk_width, h));
+                }
+            }
+
+            let little = self.little_endian;
+            let offset_size = if self.big { 8 } else { 4 };
+            let mut out = Vec::new();
+            out.extend_from_slice(if little { b"II" } else { b"MM" });
+            if self.big {
+                put(&mut out, 43, 2, little);
+                put(&mut out, 8, 2, little);
+                put(&mut out, 0, 2, little);
+            } else {
+                put(&mut out, 42, 2, little);
+            }
+            // IFD の位置はあとで書く
+            let ifd_at = out.len();
+            put(&mut out, 0, offset_size, little);
+
+            let mut offsets = Vec::new();
+            for chunk in &chunks {
+                offsets.push(out.len() as u64);
+                out.extend_from_slice(chunk);
+            }
+            let counts = chunks.iter().map(|chunk| chunk.len() as u64).collect();
+
+            let mut tags = vec![
+                (IMAGE_WIDTH, Value::Longs(vec![self.width as u64])),
+                (IMAGE_LENGTH, Value::Longs(vec![self.height as u64])),
+                (BITS_PER_SAMPLE, Value::Shorts(vec![self.bits as u64])),
+                (COMPRESSION, Value::Shorts(vec![self.compression])),
+                (SAMPLES_PER_PIXEL, Value::Shorts(vec![1])),
+                (PREDICTOR, Value::Shorts(vec![self.predictor])),
+                (SAMPLE_FORMAT, Value::Shorts(vec![self.format])),
+                (MODEL_PIXEL_SCALE, Value::Doubles(vec![0.5, 0.25, 0.0])),
+                (
+                    MODEL_TIEPOINT,
+                    Value::Doubles(vec![0.0, 0.0, 0.0, 135.0, 36.0, 0.0]),
+                ),
+            ];
+            match self.tile {
+                Some((w, h)) => tags.extend([
+                    (TILE_WIDTH, Value::Longs(vec![w as u64])),
+                    (TILE_LENGTH, Value::Longs(vec![h as u64])),
+                    (TILE_OFFSETS, Value::Longs(offsets)),
+                    (TILE_BYTE_COUNTS, Value::Longs(counts)),
+                ]),
+                None => tags.extend([
+                    (
+                        ROWS_PER_STRIP,
+                        Value::Longs(vec![self.rows_per_strip as u64]),
+                    ),
+                    (STRIP_OFFSETS, Value::Longs(offsets)),
+                    (STRIP_BYTE_COUNTS, Value::Longs(counts)),
+                ]),
+            }
+            if let Some(nodata) = self.nodata {
+                tags.push((GDAL_NODATA, Value::Ascii(nodata)));
+            }
+            tags.sort_by_key(|(tag, _)| *tag);
+
+            // 収まらない値は IFD の前に置く
+            let mut entries = Vec::new();
+            for (tag, value) in tags {
+                let (kind, count, mut bytes) = match value {
+                    Value::Shorts(values) => {
+                        let mut bytes = Vec::new();
+                        for &v in &values {
+                            put(&mut bytes, v, 2, little);
+                        }
+                        (3, values.len(), bytes)
+                    }
+                    Value::Longs(values) => {
+                        let mut bytes = Vec::new();
+                        for &v in &values {
+                            put(&mut bytes, v, offset_size, little);
+                        }
+                        (if self.big { 16 } else { 4 }, values.len(), bytes)
+                    }
+                    Value::Doubles(values) => {
+                        let mut bytes = Vec::new();
+                        for &v in &values {
+                            put(&mut bytes, v.to_bits(), 8, little);
+                        }
+                        (12, values.len(), bytes)
+                    }
+                    Value::Ascii(text) => {
+                        let mut bytes = text.as_bytes().to_vec();
+                        bytes.push(0);
+                        (2, bytes.len(), bytes)
+                    }
+                };
+                if bytes.len() > offset_size {
+                    let at = out.len() as u64;
+                    out.extend_from_slice(&bytes);
+                    bytes.clear();
+                    put(&mut bytes, at, offset_size, little);
+                } else {
+                    bytes.resize(offset_size, 0);
+                }
+                entries.push((tag, kind, count, bytes));
+            }
+
+            let ifd = out.len() as u64;
+            let mut ifd_at_bytes = Vec::new();
+            put(&mut ifd_at_bytes, ifd, offset_size, little);
+            out[ifd_at..ifd_at + offset_size].copy_from_slice(&ifd_at_bytes);
+            put(
+                &mut out,
+                entries.len() as u64,
+                if self.big { 8 } else { 2 },
+                little,
+            );
+            for (tag, kind, count, bytes) in entries {
+                put(&mut out, u64::from(tag), 2, little);
+                put(&mut out, kind, 2, little);
+                put(&mut out, count as u64, offset_size, little);
+                out.extend_from_slice(&bytes);
+            }
+            put(&mut out, 0, offset_size, little);
+            out
+        }
+    }
+
+    /// リテラルの符号だけの LZW（符号長が 9 ビットのままになるよう、200 符号ごとに Clear を入れる）
+    fn lzw_literals(data: &[u8]) -> Vec<u8> {
+        let mut codes = vec![256];
+        for run in data.chunks(200) {
+            codes.extend(run.iter().map(|&b| u16::from(b)));
+            codes.push(256);
+        }
+        codes.push(257);
+        let mut out = Vec::new();
+        let (mut bits, mut filled) = (0u32, 0);
+        for code in codes {
+            bits = bits << 9 | u32::from(code);
+            filled += 9;
+            while filled >= 8 {
+                out.push((bits >> (filled - 8)) as u8);
+                filled -= 8;
+            }
+        }
+        if filled > 0 {
+            out.push((bits << (8 - filled)) as u8);
+        }
+        out
+    }
+
+    fn read(image: &Image) -> Raster {
+        parse(&image.encode()).unwrap()
+    }
+
+    #[test]
+    fn every_compression_reads_the_same_strips() {
+        for compression in [1, 5, 8, 32773] {
+            let image = Image {
+                compression,
+                ..Default::default()
+            };
+            let raster = read(&image);
+            assert_eq!((raster.width, raster.height), (3, 4));
+            assert_eq!(raster.values, image.values, "圧縮方式 {}", compression);
+        }
+    }
+
+    #[test]
+    fn big_endian_and_horizontal_predictor() {
+        for (bits, little_endian, compression) in [(16, false, 5), (32, true, 8), (8, true, 1)] {
+            let image = Image {
+                little_endian,
+                bits,
+                compression,
+                predictor: 2,
+                // 左隣との差が負になる値も含める
+                values: vec![
+                    500.0, 3.0, 40000.0, 7.0, 7.0, 0.0, 255.0, 1.0, 2.0, 9.0, 8.0, 65535.0,
+                ]
+                .into_iter()
+                .map(|v: f64| if bits == 8 { v % 256.0 } else { v })
+                .collect(),
+                ..Default::default()
+            };
+            assert_eq!(read(&image).values, image.values, "{} ビット", bits);
+        }
+    }
+
+    #[test]
+    fn floating_point_predictor() {
+        let values = vec![
+            12.5, -3.25, 0.0625, 0.0, 1000.75, -0.5, 3.0, 4.0, 2.5, 98765.5, -1e6, 0.125,
+        ];
+        for (bits, little_endian) in [(32, true), (64, true), (32, false)] {
+            let image = Image {
+                little_endian,
+                bits,
+                format: 3,
+                compression: 8,
+                predictor: 3,
+                values: values.clone(),
+                ..Default::default()
+            };
+            assert_eq!(read(&image).values, values, "{} ビット", bits);
+        }
+    }
+
+    #[test]
+    fn bigtiff_tiles_clip_the_edge_tiles() {
+        // 3 × 4 の画像を 2 × 2 のタイルで（右端のタイルは半分が画像の外）
+        let values: Vec<f64> = (0..12).map(|v| f64::from(v) * 10.0).collect();
+        for big in [false, true] {
+            let image = Image {
+                big,
+                bits: 16,
+                compression: 8,
+                tile: Some((2, 2)),
+                values: values.clone(),
+                ..Default::default()
+            };
+            let raster = read(&image);
+            assert_eq!(raster.values, values, "BigTIFF: {}", big);
+            assert_eq!(raster.origin, (135.0, 36.0));
+            assert_eq!(raster.pixel_size, (0.5, 0.25));
+        }
+    }
+
+    #[test]
+    fn signed_samples_and_nodata() {
+        let image = Image {
+            bits: 16,
+            format: 2,
+            nodata: Some("-9999"),
+            values: vec![
+                -9999.0, -1.0, 2.0, -300.0, 0.0, 5.0, -9999.0, 7.0, 8.0, 9.0, 10.0, 11.0,
+            ],
+            ..Default::default()
+        };
+        let raster = read(&image);
+        assert_eq!(raster.nodata, Some(-9999.0));
+        assert_eq!(raster.values, image.values);
+        assert_eq!(raster.value(0, 0), None);
+        assert_eq!(raster.value(1, 0), Some(-1.0));
+        assert_eq!(raster.value(0, 1), Some(-300.0));
+        assert_eq!(raster.value(0, 2), None);
+        assert_eq!(raster.center(1, 2), (135.75, 35.375));
+    }
+
+    #[test]
+    fn packbits_repeats_and_literals() {
+        // TIFF 6.0 の仕様書の例
+        let packed = [
+            0xFE, 0xAA, 0x02, 0x80, 0x00, 0x2A, 0xFD, 0xAA, 0x03, 0x80, 0x00, 0x2A, 0x22, 0xF7,
+            0xAA,
+        ];
+        let mut expected = vec![0xAA; 3];
+        expected.extend([0x80, 0x00, 0x2A]);
+        expected.extend([0xAA; 4]);
+        expected.extend([0x80, 0x00, 0x2A, 0x22]);
+        expected.extend([0xAA; 10]);
+        assert_eq!(packbits(&packed).unwrap(), expected);
+        assert!(packbits(&[0x02, 0x80]).is_err());
+    }
+
+    #[test]
+    fn lzw_reads_codes_not_yet_in_the_table() {
+        // Clear, 'a', 258（まだ表にない「a + a」）, EOI
+        let mut out = Vec::new();
+        let (mut bits, mut filled) = (0u64, 0usize);
+        for code in [256u64, 97, 258, 257] {
+            bits = bits << 9 | code;
+            filled += 9;
+        }
+        bits <<= 64 - filled;
+        out.extend_from_slice(&bits.to_be_bytes()[..filled.div_ceil(8)]);
+        assert_eq!(lzw::decode(&out).unwrap(), b"aaa");
+    }
+
+    #[test]
+    fn unsupported_files_are_errors() {
+        assert!(parse(b"XX*\0").is_err());
+        let image = Image {
+            compression: 7,
+            ..Default::default()
+        };
+        let err = match parse(&image.encode()) {
+            Err(err) => err,
+            Ok(_) => panic!("JPEG 圧縮は読めないはず"),
+        };
+        assert!(err.contains("圧縮方式"), "{}", err);
+    }
+}
@@ -0,0 +1,193 @@
+//! 不正なポリゴンの修復（`--make-valid`）。
+//! 自己交差したリング（8 の字、蝶ネクタイ形）は、そのまま靴紐公式で面積を求めると
+//! 交差の両側が打ち消し合って小さくなる。交差する点でリングを自己交差のない単純なループに分け、
+//! ループを union して組み立て直す（リングが囲む範囲はすべて塗る）。
+
+use crate::aggregate;
+use geo::{
+    line_intersection::{line_intersection, LineIntersection},
+    BooleanOps, Coord, Geometry, Line, LineString, MultiPolygon, Polygon,
+};
+use geojson::{FeatureCollection, Value};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// すべての Feature の不正なポリゴンを修復し、修復した Feature の位置を返す（`--audit-log`）。
+/// 自己交差のないポリゴンとポリゴン以外のジオメトリは変えない
+pub fn make_valid_each(collection: &mut FeatureCollection) -> Vec<usize> {
+    collection
+        .features
+        .par_iter_mut()
+        .enumerate()
+        .filter_map(|(position, feature)| {
+            let geometry = feature.geometry.as_mut()?;
+            // GeometryCollection を MultiPolygon に置き換えると線や点のメンバーがなくなるため、修復しない
+            if !matches!(geometry.value, Value::Polygon(_) | Value::MultiPolygon(_)) {
+                return None;
+            }
+            let polygons = Geometry::<f64>::try_from(geometry.value.clone())
+                .ok()
+                .and_then(aggregate::to_multi_polygon)?;
+            let repaired = make_valid(&polygons)?;
+            geometry.value = Value::from(&repaired);
+            Some(position)
+        })
+        .collect()
+}
+
+/// 自己交差したリングがあれば修復したポリゴンを返す（なければ None）
+pub fn make_valid(polygons: &MultiPolygon<f64>) -> Option<MultiPolygon<f64>> {
+    let split: Vec<_> = polygons
+        .iter()
+        .map(|polygon| {
+            let (exterior, mut changed) = split_ring(polygon.exterior());
+            let mut holes = Vec::new();
+            for interior in polygon.interiors() {
+                let (loops, split) = split_ring(interior);
+                holes.extend(loops);
+                changed |= split;
+            }
+            (exterior, holes, changed)
+        })
+        .collect();
+    if !split.iter().any(|(_, _, changed)| *changed) {
+        return None;
+    }
+
+    let mut repaired = MultiPolygon::new(Vec::new());
+    for (exterior, holes, changed) in split {
+        let polygon = if changed {
+            let shell = union_all(exterior);
+            let holes = union_all(holes);
+            if holes.0.is_empty() {
+                shell
+            } else {
+                shell.difference(&holes)
+            }
+        } else {
+            // 交差のないポリゴンはそのまま（分けたループを組み立て直さない）
+            let mut rings = exterior.into_iter().map(|polygon| polygon.into_inner().0);
+            let shell = rings.next().unwrap_or_else(|| LineString::new(Vec::new()));
+            let holes = holes
+                .into_iter()
+                .map(|polygon| polygon.into_inner().0)
+                .collect();
+            MultiPolygon::new(vec![Polygon::new(shell, holes)])
+        };
+        repaired.0.extend(polygon.0);
+    }
+    Some(repaired)
+}
+
+fn union_all(polygons: Vec<Polygon<f64>>) -> MultiPolygon<f64> {
+    polygons
+        .into_iter()
+        .map(|polygon| MultiPolygon::new(vec![polygon]))
+        .reduce(|a, b| a.union(&b))
+        .unwrap_or_else(|| MultiPolygon::new(Vec::new()))
+}
+
+/// リングを自己交差のない単純なループに分ける。2 つ目の値はリングに交差があったか
+fn split_ring(ring: &LineString<f64>) -> (Vec<Polygon<f64>>, bool) {
+    let mut points: Vec<Coord<f64>> = ring.0.clone();
+    points.dedup();
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    if points.len() < 3 {
+        return (Vec::new(), false);
+    }
+
+    let crossings = intersections(&points);
+    if crossings.iter().all(Vec::is_empty) {
+        return (vec![Polygon::new(ring.clone(), Vec::new())], false);
+    }
+
+    // 交差する点を両方の辺に挟み込む（同じ点は同じ値なので、後で一致で見つけられる）
+    let mut noded = Vec::with_capacity(points.len() * 2);
+    for (point, mut crossing) in points.iter().zip(crossings) {
+        noded.push(*point);
+        crossing.sort_by(|a, b| a.0.total_cmp(&b.0));
+        noded.extend(crossing.into_iter().map(|(_, point)| point));
+    }
+    noded.dedup();
+
+    // 同じ点に戻ってくるたびに、その間をひとつのループとして切り出す
+    let key = |c: &Coord<f64>| (c.x.to_bits(), c.y.to_bits());
+    let mut loops = Vec::new();
+    let mut stack: Vec<Coord<f64>> = Vec::new();
+    let mut seen: HashMap<(u64, u64), usize> = HashMap::new();
+    for point in noded {
+        if let Some(&start) = seen.get(&key(&point)) {
+            let mut closed: Vec<Coord<f64>> = stack.drain(start + 1..).collect();
+            for c in &closed {
+                seen.remove(&key(c));
+            }
+            closed.insert(0, point);
+            loops.push(closed);
+        } else {
+            seen.insert(key(&point), stack.len());
+            stack.push(point);
+        }
+    }
+    loops.push(stack);
+
+    let polygons = loops
+        .into_iter()
+        .filter(|points| points.len() >= 3)
+        .map(|mut points| {
+            points.push(points[0]);
+            Polygon::new(LineString::new(points), Vec::new())
+        })
+        .collect();
+    (polygons, true)
+}
+
+/// 辺ごとの、隣り合わない辺との交点（辺の始点からの位置と交点）。
+/// 辺を x の範囲の順に並べ、範囲が重なるものだけを比べる
+fn intersections(points: &[Coord<f64>]) -> Vec<Vec<(f64, Coord<f64>)>> {
+    let n = points.len();
+    let line = |i: usize| Line::new(points[i], points[(i + 1) % n]);
+    let mut order: Vec<usize> = (0..n).collect();
+    let min_x = |i: usize| points[i].x.min(points[(i + 1) % n].x);
+    let max_x = |i: usize| points[i].x.max(points[(i + 1) % n].x);
+    order.sort_by(|&a, &b| min_x(a).total_cmp(&min_x(b)));
+
+    let mut crossings = vec![Vec::new(); n];
+    for (k, &i) in order.iter().enumerate() {
+        for &j in &order[k + 1..] {
+            if min_x(j) > max_x(i) {
+                break;
+            }
+            // 隣り合う辺は端点を共有するので比べない
+            let adjacent = (i + 1) % n == j || (j + 1) % n == i;
+            if adjacent {
+                continue;
+            }
+            let (a, b) = (line(i), line(j));
+            if let Some(LineIntersection::SinglePoint { intersection, .. }) =
+                line_intersection(a, b)
+            {
+                crossings[i].push((position(a, intersection), intersection));
+                crossings[j].push((position(b, intersection), intersection));
+            }
+        }
+    }
+    // 辺の端点そのものとの交点は、挟み込まなくても同じ点として見つかる
+    for (i, crossing) in crossings.iter_mut().enumerate() {
+        let a = line(i);
+        crossing.retain(|(_, point)| *point != a.start && *point != a.end);
+    }
+    crossings
+}
+
+/// 辺の上の点の位置（始点が 0、終点が 1）
+fn position(line: Line<f64>, point: Coord<f64>) -> f64 {
+    let d = line.delta();
+    let length = d.x * d.x + d.y * d.y;
+    if length == 0.0 {
+        0.0
+    } else {
+        ((point.x - line.start.x) * d.x + (point.y - line.start.y) * d.y) / length
+    }
+}
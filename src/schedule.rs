@@ -0,0 +1,61 @@
+//! 頂点数に応じた並列処理の分割。
+//! 市区町村のポリゴンは頂点数の差が大きく（小さな区と島の多い沿岸の市など）、
+//! Feature の数で均等に分けると一部のスレッドだけが遅れて終わるため、頂点数の合計が揃うように分ける。
+
+use geojson::{Feature, Value};
+
+/// 1 スレッドあたりのチャンクの数（多いほど偏りは減るが、分割の手間が増える）
+const CHUNKS_PER_THREAD: usize = 4;
+
+/// 並列処理の分け方
+#[derive(Clone, Copy, PartialEq)]
+pub enum Schedule {
+    /// rayon に任せて Feature の数で分ける
+    Uniform,
+    /// 頂点数の合計がほぼ同じになるように分ける
+    ByCost,
+}
+
+/// 頂点数の合計がほぼ同じになるように、連続する Feature のチャンクに分ける。
+/// 1 つで目安を超える Feature はそれだけで 1 つのチャンクになる
+pub fn chunks_by_cost(features: &[Feature]) -> Vec<&[Feature]> {
+    let costs: Vec<usize> = features.iter().map(cost).collect();
+    let total: usize = costs.iter().sum();
+    let target = (total / (rayon::current_num_threads() * CHUNKS_PER_THREAD)).max(1);
+
+    let mut chunks = Vec::new();
+    let (mut start, mut sum) = (0, 0);
+    for (i, &cost) in costs.iter().enumerate() {
+        // 重い Feature は前のチャンクに混ぜない
+        if sum > 0 && sum + cost > target {
+            chunks.push(&features[start..i]);
+            (start, sum) = (i, 0);
+        }
+        sum += cost;
+    }
+    if start < features.len() {
+        chunks.push(&features[start..]);
+    }
+    chunks
+}
+
+/// Feature の処理の重さの目安（頂点数。ジオメトリがなくても 1 とする）
+fn cost(feature: &Feature) -> usize {
+    1 + feature
+        .geometry
+        .as_ref()
+        .map_or(0, |geometry| vertex_count(&geometry.value))
+}
+
+fn vertex_count(value: &Value) -> usize {
+    match value {
+        Value::Point(_) => 1,
+        Value::MultiPoint(points) | Value::LineString(points) => points.len(),
+        Value::MultiLineString(lines) | Value::Polygon(lines) => lines.iter().map(Vec::len).sum(),
+        Value::MultiPolygon(polygons) => polygons.iter().flatten().map(Vec::len).sum(),
+        Value::GeometryCollection(geometries) => geometries
+            .iter()
+            .map(|geometry| vertex_count(&geometry.value))
+            .sum(),
+    }
+}
@@ -0,0 +1,391 @@
+//! Feature のプロパティから型付きの Rust の構造体を生成する（`layon schema`）。
+//!
+//! 読み込んだ Feature の一部からプロパティごとの型（文字列、整数、小数、真偽値）と、
+//! 値がない Feature があるかどうかを推定し、serde で読み込める構造体のソースを書き出す。
+//! `properties.get("N03_004")` のような文字列での参照を、`feature.city` のような型の付いたフィールドに置き換えられるようにする。
+//! 生成したソースは serde（derive）, serde_json, geojson に依存する。
+//!
+//! 生成したファイルをリポジトリに置くほか、build.rs から `Schema::infer` と `Schema::to_rust` を呼んで
+//! `OUT_DIR` に書き出し、`include!` で読み込むこともできる。
+//! 値が null しかないプロパティは型が分からないため `serde_json::Value` にする。
+
+use crate::mapping::PropertyMap;
+use geojson::{FeatureCollection, JsonValue};
+use std::fmt::Write;
+
+/// `--sample` の既定値
+pub const DEFAULT_SAMPLE: usize = 1000;
+
+/// フィールドの型
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    /// null 以外の値がなかった
+    Unknown,
+    Bool,
+    Integer,
+    Float,
+    String,
+    /// 型の異なる値が混ざっている、または配列やオブジェクト
+    Json,
+}
+
+impl Kind {
+    fn of(value: &JsonValue) -> Kind {
+        match value {
+            JsonValue::Null => Kind::Unknown,
+            JsonValue::Bool(_) => Kind::Bool,
+            JsonValue::Number(number) if number.is_i64() => Kind::Integer,
+            JsonValue::Number(_) => Kind::Float,
+            JsonValue::String(_) => Kind::String,
+            JsonValue::Array(_) | JsonValue::Object(_) => Kind::Json,
+        }
+    }
+
+    /// 2 つの値をどちらも表せる型（整数と小数は小数にする）
+    fn merge(self, other: Kind) -> Kind {
+        match (self, other) {
+            (Kind::Unknown, kind) | (kind, Kind::Unknown) => kind,
+            (a, b) if a == b => a,
+            (Kind::Integer, Kind::Float) | (Kind::Float, Kind::Integer) => Kind::Float,
+            _ => Kind::Json,
+        }
+    }
+
+    fn rust_type(self) -> &'static str {
+        match self {
+            Kind::Bool => "bool",
+            Kind::Integer => "i64",
+            Kind::Float => "f64",
+            Kind::String => "String",
+            Kind::Unknown | Kind::Json => "serde_json::Value",
+        }
+    }
+}
+
+/// 構造体のフィールド
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    /// 元のプロパティの名前
+    pub property: String,
+    /// フィールドの名前（Rust の識別子）
+    pub name: String,
+    pub kind: Kind,
+    /// 値がない（または null の）Feature があった
+    pub optional: bool,
+    /// 最初に見つかった null 以外の値（ドキュメントの例に使う）
+    pub example: Option<JsonValue>,
+}
+
+/// 推定したプロパティの型の一覧
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema {
+    /// 推定に使った Feature の数
+    pub sampled: usize,
+    /// フィールド（プロパティの名前の順。`map` を指定した場合はその順）
+    pub fields: Vec<Field>,
+}
+
+impl Schema {
+    /// 先頭の `sample` 個（0 ならすべて）の Feature からプロパティの型を推定する。
+    /// `map` を指定した場合は、その対応にあるプロパティだけを対応の名前のフィールドにする
+    pub fn infer(
+        collection: &FeatureCollection,
+        sample: usize,
+        map: Option<&PropertyMap>,
+    ) -> Result<Schema, String> {
+        let sample = if sample == 0 {
+            collection.features.len()
+        } else {
+            sample.min(collection.features.len())
+        };
+        let mut fields: Vec<Field> = match map {
+            Some(map) => map
+                .entries()
+                .iter()
+                .map(|(from, to)| Field {
+                    property: from.clone(),
+                    name: to.clone(),
+                    kind: Kind::Unknown,
+                    optional: false,
+                    example: None,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        let empty = Default::default();
+        for (i, feature) in collection.features[..sample].iter().enumerate() {
+            let properties = feature.properties.as_ref().unwrap_or(&empty);
+            if map.is_none() {
+                for property in properties.keys() {
+                    if !fields.iter().any(|field| field.property == *property) {
+                        // 先に読んだ Feature にはなかった
+                        fields.push(Field {
+                            property: property.clone(),
+                            name: property.clone(),
+                            kind: Kind::Unknown,
+                            optional: i > 0,
+                            example: None,
+                        });
+                    }
+                }
+            }
+            for field in &mut fields {
+                match properties.get(&field.property) {
+                    None | Some(JsonValue::Null) => field.optional = true,
+                    Some(value) => {
+                        field.kind = field.kind.merge(Kind::of(value));
+                        field.example.get_or_insert_with(|| value.clone());
+                    }
+                }
+            }
+        }
+        if map.is_none() {
+            fields.sort_by(|a, b| a.property.cmp(&b.property));
+        }
+        if fields.is_empty() {
+            return Err("プロパティが 1 つもありません".to_string());
+        }
+        // フィールドの名前を識別子にし、重ならないようにする
+        let mut names: Vec<String> = Vec::with_capacity(fields.len());
+        for field in &mut fields {
+            let base = identifier(&field.name);
+            let mut name = base.clone();
+            let mut n = 2;
+            while names.contains(&name) {
+                name = format!("{}_{}", base, n);
+                n += 1;
+            }
+            names.push(name.clone());
+            field.name = name;
+        }
+        Ok(Schema {
+            sampled: sample,
+            fields,
+        })
+    }
+
+    /// `name` の構造体のソース。`source` は先頭のコメントに書く入力元
+    pub fn to_rust(&self, name: &str, source: &str) -> Result<String, String> {
+        if !is_type_name(name) {
+            return Err(format!("構造体の名前が不正です: {}", name));
+        }
+        let mut s = String::new();
+        writeln!(
+            s,
+            "// layon schema で {} の先頭 {} 個の Feature から生成した。手で編集しないこと。",
+            source, self.sampled
+        )
+        .unwrap();
+        s.push('\n');
+        writeln!(s, "/// Feature のプロパティ").unwrap();
+        writeln!(
+            s,
+            "#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]"
+        )
+        .unwrap();
+        writeln!(s, "pub struct {} {{", name).unwrap();
+        for field in &self.fields {
+            let mut doc = field.property.clone();
+            if let Some(example) = &field.example {
+                let example = example.to_string();
+                let example: String = if example.chars().count() > 40 {
+                    example.chars().take(40).chain("…".chars()).collect()
+                } else {
+                    example
+                };
+                write!(doc, "（例: {}）", example).unwrap();
+            }
+            writeln!(s, "    /// {}", doc).unwrap();
+            // Debug の表記は Rust の文字列リテラルとして読める
+            let rename = format!("{:?}", field.property);
+            let ty = field.kind.rust_type();
+            if field.optional && field.kind != Kind::Unknown && field.kind != Kind::Json {
+                writeln!(s, "    #[serde(rename = {}, default)]", rename).unwrap();
+                writeln!(s, "    pub {}: Option<{}>,", field.name, ty).unwrap();
+            } else {
+                // serde_json::Value はないプロパティを null として読む
+                if field.optional {
+                    writeln!(s, "    #[serde(rename = {}, default)]", rename).unwrap();
+                } else {
+                    writeln!(s, "    #[serde(rename = {})]", rename).unwrap();
+                }
+                writeln!(s, "    pub {}: {},", field.name, ty).unwrap();
+            }
+        }
+        writeln!(s, "}}").unwrap();
+        s.push('\n');
+        writeln!(s, "impl {} {{", name).unwrap();
+        writeln!(s, "    /// 元のプロパティの名前（フィールドの順）").unwrap();
+        writeln!(
+            s,
+            "    pub const PROPERTIES: [&str; {}] = [{}];",
+            self.fields.len(),
+            self.fields
+                .iter()
+                .map(|field| format!("{:?}", field.property))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .unwrap();
+        s.push('\n');
+        writeln!(
+            s,
+            "    /// プロパティを読む（必須のプロパティがない、または型が合わない場合はエラー）"
+        )
+        .unwrap();
+        writeln!(
+            s,
+            "    pub fn from_properties(properties: &geojson::JsonObject) -> Result<Self, serde_json::Error> {{"
+        )
+        .unwrap();
+        writeln!(
+            s,
+            "        serde_json::from_value(serde_json::Value::Object(properties.clone()))"
+        )
+        .unwrap();
+        writeln!(s, "    }}").unwrap();
+        s.push('\n');
+        writeln!(
+            s,
+            "    /// Feature のプロパティを読む（プロパティのない Feature は空として読む）"
+        )
+        .unwrap();
+        writeln!(
+            s,
+            "    pub fn from_feature(feature: &geojson::Feature) -> Result<Self, serde_json::Error> {{"
+        )
+        .unwrap();
+        writeln!(
+            s,
+            "        Self::from_properties(feature.properties.as_ref().unwrap_or(&Default::default()))"
+        )
+        .unwrap();
+        writeln!(s, "    }}").unwrap();
+        writeln!(s, "}}").unwrap();
+        Ok(s)
+    }
+}
+
+/// Rust のキーワード（フィールドの名前にするときは r# を付ける）
+const KEYWORDS: [&str; 38] = [
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while",
+    "abstract", "become", "box", "try",
+];
+
+/// プロパティの名前をフィールドの名前（小文字と数字と _）にする
+fn identifier(name: &str) -> String {
+    let mut s: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if s.chars().all(|c| c == '_') {
+        s = "property".to_string();
+    } else if s.starts_with(|c: char| c.is_ascii_digit()) {
+        s.insert(0, '_');
+    }
+    match s.as_str() {
+        // r# を付けられないキーワード
+        "self" | "super" | "crate" | "_" => format!("{}_", s),
+        _ if KEYWORDS.contains(&s.as_str()) => format!("r#{}", s),
+        _ => s,
+    }
+}
+
+fn is_type_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name)
+        && !matches!(name, "Self" | "self" | "super" | "crate")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{Input, InputOptions};
+    use std::fs;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    /// `layon schema` で生成した型（tests/golden/n03_schema.rs）
+    mod n03 {
+        include!("../tests/golden/n03_schema.rs");
+    }
+
+    #[test]
+    fn schema_generates_typed_structs_that_read_the_sampled_features() {
+        let collection = Input::parse(FIXTURE, InputOptions::default())
+            .unwrap()
+            .read()
+            .unwrap();
+        let map = PropertyMap::parse(
+            "N03_001=prefecture,N03_003=county,N03_004=city,N03_005=ward,N03_007=code",
+        )
+        .unwrap();
+        let inferred = Schema::infer(&collection, 0, Some(&map)).unwrap();
+        let text = inferred
+            .to_rust("N03Feature", "tests/fixtures/n03_11_sample.geojson")
+            .unwrap();
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/n03_schema.rs");
+        if std::env::var_os("LAYON_UPDATE_GOLDEN").is_some() {
+            fs::write(path, &text).unwrap();
+        }
+        // 生成したソースが golden ファイルと同じなら、上の mod n03 でコンパイルできたことになる
+        assert_eq!(text, fs::read_to_string(path).unwrap());
+
+        let features: Vec<n03::N03Feature> = collection
+            .features
+            .iter()
+            .map(|feature| n03::N03Feature::from_feature(feature).unwrap())
+            .collect();
+        assert!(features.iter().all(|f| f.prefecture == "埼玉県"));
+        assert_eq!(features[0].city, "本庄市");
+        assert_eq!(features[0].code, "11211");
+        assert_eq!(
+            features.iter().filter(|f| f.county.is_some()).count(),
+            4,
+            "N03_003 は半分の Feature で null"
+        );
+        assert_eq!(n03::N03Feature::PROPERTIES[2], "N03_004");
+
+        // 必須のプロパティがない Feature は読めない
+        let mut properties = collection.features[0].properties.clone().unwrap();
+        properties.remove("N03_004");
+        assert!(n03::N03Feature::from_properties(&properties).is_err());
+
+        // 整数と小数は小数、型の混ざったものは serde_json::Value、名前はキーワードを避ける
+        let mixed: FeatureCollection = serde_json::from_str(
+            r#"{"type":"FeatureCollection","features":[
+{"type":"Feature","properties":{"type":"a","Pop 2020":1,"flag":true,"x":1},"geometry":null},
+{"type":"Feature","properties":{"type":"b","Pop 2020":1.5,"flag":"yes"},"geometry":null}
+]}"#,
+        )
+        .unwrap();
+        let inferred = Schema::infer(&mixed, 0, None).unwrap();
+        let fields: Vec<_> = inferred
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.kind, field.optional))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("pop_2020", Kind::Float, false),
+                ("flag", Kind::Json, false),
+                ("r#type", Kind::String, false),
+                ("x", Kind::Integer, true),
+            ]
+        );
+        assert!(inferred.to_rust("2Bad", "-").is_err());
+    }
+}
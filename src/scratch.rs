@@ -0,0 +1,254 @@
+//! 一時ファイルを置くディレクトリ（`--tmpdir`, `--tmpdir-limit`）。
+//!
+//! 大きな処理の途中のデータ（分けて集計する分割の Feature、ZIP から展開したファイルなど）をメモリに置ききれない場合に、
+//! ディスクに書き出しておくための場所。ディレクトリはプロセスごとに `layon-scratch-<PID>` を作り、
+//! 処理が終わったら（エラーで終わった場合も）`cleanup` で消す。
+//!
+//! ファイルの名前は内容の SHA-256 にする（同じ内容を何度書き出しても 1 つのファイルで済む）。
+//! 書き出しはロックの中で行うため、並列に書き出すと 1 つずつになる（ディスクの速さが律速になる処理なので許容する）。
+//! 上限を指定すると、書き出したファイルの大きさの合計が上限を超える前にエラーにする。
+//! 2 回目のシグナルなどで消せずに終わったディレクトリは、次に一時ディレクトリを使うときに、
+//! そのプロセスが終わっていれば消す。
+
+use crate::sha256;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// プロセスごとのディレクトリの名前の先頭
+const PREFIX: &str = "layon-scratch-";
+
+/// 一時ディレクトリの設定（`configure` で変える。既定は OS の一時ディレクトリ、上限なし）
+static SETTINGS: Mutex<Option<(PathBuf, Option<u64>)>> = Mutex::new(None);
+/// 使い始めた一時ディレクトリ
+static CURRENT: Mutex<Option<Arc<Scratch>>> = Mutex::new(None);
+
+/// プロセスの一時ディレクトリ
+pub struct Scratch {
+    dir: PathBuf,
+    limit: Option<u64>,
+    /// 書き出したファイルの大きさの合計（バイト）
+    used: AtomicU64,
+    /// `store` で書き出したファイルごとの、まだ `remove` されていない数（同じ内容を何度も書き出した場合）
+    references: Mutex<HashMap<PathBuf, usize>>,
+}
+
+impl Scratch {
+    /// `parent` の下にこのプロセスのディレクトリを作る（終わったプロセスのディレクトリが残っていれば消す）
+    fn create(parent: &Path, limit: Option<u64>) -> io::Result<Scratch> {
+        remove_stale(parent);
+        let dir = parent.join(format!("{}{}", PREFIX, std::process::id()));
+        fs::create_dir_all(&dir)?;
+        Ok(Scratch {
+            dir,
+            limit,
+            used: AtomicU64::new(0),
+            references: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// ディレクトリのパス
+    #[cfg(test)]
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// 書き出したファイルの大きさの合計（バイト）
+    #[cfg(test)]
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// `bytes` を内容の SHA-256 の名前のファイルに書き出し、そのパスを返す（同じ内容のファイルがあれば書き出さない）
+    pub fn store(&self, bytes: &[u8]) -> io::Result<PathBuf> {
+        let path = self.dir.join(sha256::hex_digest(bytes)?);
+        // 同じ内容を並列に書き出す場合も 1 回だけ書き出すよう、書き出し終えるまで数を持っておく
+        let mut references = self.references.lock().unwrap();
+        if let Some(count) = references.get_mut(&path) {
+            *count += 1;
+            return Ok(path);
+        }
+        self.reserve(bytes.len() as u64)?;
+        if let Err(err) = fs::write(&path, bytes) {
+            let _ = fs::remove_file(&path);
+            self.used.fetch_sub(bytes.len() as u64, Ordering::Relaxed);
+            return Err(err);
+        }
+        references.insert(path.clone(), 1);
+        Ok(path)
+    }
+
+    /// `store` で書き出したファイルを使い終えたことを伝える。
+    /// 同じ内容を書き出したところがすべて使い終えたらファイルを消し、その分を上限に戻す
+    pub fn remove(&self, path: &Path) -> io::Result<()> {
+        let mut references = self.references.lock().unwrap();
+        match references.get_mut(path) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                return Ok(());
+            }
+            Some(_) => {
+                references.remove(path);
+            }
+            None => return Ok(()),
+        }
+        let size = fs::metadata(path)?.len();
+        fs::remove_file(path)?;
+        self.used.fetch_sub(size, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// 外部のコマンド（unzip など）が書き出すためのサブディレクトリを作る。
+    /// 書き出した後に `account` で大きさを数える
+    pub fn subdir(&self, name: &str) -> io::Result<PathBuf> {
+        let dir = self.dir.join(name);
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    /// `subdir` に書き出されたファイルの大きさを数える（上限を超えればサブディレクトリを消してエラー）
+    pub fn account(&self, dir: &Path) -> io::Result<()> {
+        let result = self.reserve(size_of(dir)?);
+        if result.is_err() {
+            let _ = fs::remove_dir_all(dir);
+        }
+        result
+    }
+
+    /// `subdir` を消し、その分を上限に戻す（もうなければ何もしない）
+    pub fn remove_subdir(&self, dir: &Path) -> io::Result<()> {
+        if !dir.exists() {
+            return Ok(());
+        }
+        let size = size_of(dir)?;
+        fs::remove_dir_all(dir)?;
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some(used.saturating_sub(size))
+            })
+            .ok();
+        Ok(())
+    }
+
+    /// 自分で書き出したファイルの `size` バイトを数える（上限を超えるなら数えずにエラー）
+    pub fn reserve(&self, size: u64) -> io::Result<()> {
+        let used = self.used.fetch_add(size, Ordering::Relaxed) + size;
+        match self.limit {
+            Some(limit) if used > limit => {
+                self.used.fetch_sub(size, Ordering::Relaxed);
+                Err(io::Error::new(
+                    io::ErrorKind::StorageFull,
+                    format!(
+                        "一時ディレクトリ ({}) の上限 ({} バイト) を超えます (--tmpdir-limit で上限を変えられます)",
+                        self.dir.display(),
+                        limit
+                    ),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// 一時ディレクトリを作る場所と上限を設定する（使い始める前に呼ぶ）
+pub fn configure(parent: Option<PathBuf>, limit: Option<u64>) {
+    *SETTINGS.lock().unwrap() = Some((parent.unwrap_or_else(std::env::temp_dir), limit));
+}
+
+/// このプロセスの一時ディレクトリ（初めて使うときに作る）
+pub fn current() -> io::Result<Arc<Scratch>> {
+    let mut current = CURRENT.lock().unwrap();
+    if let Some(scratch) = &*current {
+        return Ok(scratch.clone());
+    }
+    let (parent, limit) = SETTINGS
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| (std::env::temp_dir(), None));
+    let scratch = Arc::new(Scratch::create(&parent, limit)?);
+    *current = Some(scratch.clone());
+    Ok(scratch)
+}
+
+/// このプロセスの一時ディレクトリを消す（使っていなければ何もしない）
+pub fn cleanup() {
+    // ほかに使っているところがなくなった時点で Drop が消す
+    CURRENT.lock().unwrap().take();
+}
+
+/// `512M` や `2G` のような大きさ（K, M, G, T は 1024 倍ずつ。単位がなければバイト）を解析する
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(i) => text.split_at(i),
+        None => (text, ""),
+    };
+    let scale: u64 = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches("IB")
+        .trim_end_matches('B')
+    {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("大きさの単位が不正です: {}", text)),
+    };
+    match number.parse::<f64>() {
+        Ok(number) if number >= 0.0 && number.is_finite() => Ok((number * scale as f64) as u64),
+        _ => Err(format!("大きさが不正です: {}", text)),
+    }
+}
+
+/// ディレクトリの中のファイルの大きさの合計
+fn size_of(dir: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            size_of(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+/// 終わったプロセスの一時ディレクトリを消す（プロセスが終わったかどうかは /proc で確かめる。/proc がなければ消さない）
+fn remove_stale(parent: &Path) {
+    if !Path::new("/proc/self").exists() {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(parent) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(pid) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(PREFIX))
+            .and_then(|pid| pid.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if pid != std::process::id() && !Path::new("/proc").join(pid.to_string()).exists() {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! gRPC のサービス（`layon serve --grpc`。grpc フィーチャーを有効にした場合）。
+//!
+//! proto/layon.proto の Layon サービスを tonic で提供する。HTTP のサーバーと同じ `Server` を共有するので、
+//! `POST /datasets` で加えたデータセットも gRPC から集計できる。
+//! 集計と点の検索は tokio のブロッキング用のスレッドで計算し、集計の結果と変換した Feature は 1 つずつ送る。
+
+use super::{aggregate, parse_metric, Response as HttpResponse, Server};
+use crate::source::{Input, InputOptions};
+use geo::Point;
+use std::{io, sync::Arc};
+use tokio::{net::TcpListener, sync::mpsc, task};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Code, Request, Response, Status};
+
+/// proto/layon.proto から生成したメッセージとサービス
+pub mod proto {
+    tonic::include_proto!("layon.v1");
+}
+
+use proto::{
+    layon_server::{Layon, LayonServer},
+    AggregateRequest, AggregateResponse, ConvertRequest, ConvertResponse, LocateRequest,
+    LocateResponse,
+};
+
+/// ストリームで送り終えていない応答の数の上限（受け手が遅いときに溜め込みすぎない）
+const QUEUED: usize = 64;
+
+/// `address` で gRPC のサービスを待ち受ける（戻らない）
+pub fn listen(server: Arc<Server>, address: &str) -> io::Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let listener = TcpListener::bind(address).await?;
+        serve(server, listener).await
+    })
+}
+
+/// 待ち受けを始めた `listener` で応答する
+pub async fn serve(server: Arc<Server>, listener: TcpListener) -> io::Result<()> {
+    tonic::transport::Server::builder()
+        .add_service(LayonServer::new(Service { server }))
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await
+        .map_err(io::Error::other)
+}
+
+struct Service {
+    server: Arc<Server>,
+}
+
+#[tonic::async_trait]
+impl Layon for Service {
+    type AggregateStream = ReceiverStream<Result<AggregateResponse, Status>>;
+    type ConvertStream = ReceiverStream<Result<ConvertResponse, Status>>;
+
+    async fn aggregate(
+        &self,
+        request: Request<AggregateRequest>,
+    ) -> Result<Response<Self::AggregateStream>, Status> {
+        let request = request.into_inner();
+        let server = Arc::clone(&self.server);
+        let rows = blocking(move || {
+            let dataset = server.dataset(given(&request.dataset)).map_err(failure)?;
+            let group_by = given(&request.group_by).unwrap_or(&server.group_by);
+            let metric = given(&request.metric).unwrap_or(&server.metric);
+            aggregate(&dataset, group_by, metric, given(&request.r#where))
+                .map_err(|message| (Code::InvalidArgument, message))
+        })
+        .await?;
+
+        let (send, receive) = mpsc::channel(QUEUED);
+        tokio::spawn(async move {
+            for row in rows {
+                let response = AggregateResponse {
+                    key: row.key,
+                    area: row.area,
+                    count: row.count as u64,
+                };
+                if send.send(Ok(response)).await.is_err() {
+                    // 受け手が切断した
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receive)))
+    }
+
+    async fn locate(
+        &self,
+        request: Request<LocateRequest>,
+    ) -> Result<Response<LocateResponse>, Status> {
+        let request = request.into_inner();
+        let server = Arc::clone(&self.server);
+        let response = blocking(move || {
+            let dataset = server.dataset(given(&request.dataset)).map_err(failure)?;
+            if !request.x.is_finite() || !request.y.is_finite() {
+                return Err((Code::InvalidArgument, "x, y の値が不正です".to_string()));
+            }
+            let metric = parse_metric(given(&request.metric).unwrap_or(&server.metric))
+                .map_err(|message| (Code::InvalidArgument, message))?;
+            let hit = dataset
+                .index()
+                .locate(Point::new(request.x, request.y), metric, None);
+            Ok(match hit {
+                Some(hit) => LocateResponse {
+                    found: true,
+                    feature: hit.feature as u64,
+                    distance: hit.distance,
+                    properties: serde_json::to_string(
+                        &dataset.collection.features[hit.feature].properties,
+                    )
+                    .unwrap(),
+                },
+                None => LocateResponse::default(),
+            })
+        })
+        .await?;
+        Ok(Response::new(response))
+    }
+
+    async fn convert(
+        &self,
+        request: Request<ConvertRequest>,
+    ) -> Result<Response<Self::ConvertStream>, Status> {
+        let source = request.into_inner().source;
+        if !self.server.registry.lock().unwrap().accepts() {
+            return Err(Status::permission_denied(
+                "--max-datasets が 1 のため、入力元を読み込めません",
+            ));
+        }
+        let input =
+            Input::parse(&source, InputOptions::default()).map_err(Status::invalid_argument)?;
+        self.server
+            .check_source(&input)
+            .map_err(Status::permission_denied)?;
+
+        let (send, receive) = mpsc::channel(QUEUED);
+        task::spawn_blocking(move || {
+            // 受け手が切断したら読むのをやめる
+            let result = input.stream(&mut |feature| {
+                let feature = serde_json::to_string(&feature).unwrap();
+                send.blocking_send(Ok(ConvertResponse { feature })).is_ok()
+            });
+            if let Err(err) = result {
+                let message = format!("{} を読み込めません: {}", source, err);
+                let _ = send.blocking_send(Err(Status::invalid_argument(message)));
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receive)))
+    }
+}
+
+/// 空の文字列は省略したものとする
+fn given(value: &str) -> Option<&str> {
+    Some(value).filter(|value| !value.is_empty())
+}
+
+/// `f` をブロッキング用のスレッドで実行する（エラーは gRPC の状態のコードとメッセージ）
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, (Code, String)> + Send + 'static,
+) -> Result<T, Status> {
+    task::spawn_blocking(f)
+        .await
+        .map_err(|err| Status::internal(err.to_string()))?
+        .map_err(|(code, message)| Status::new(code, message))
+}
+
+/// HTTP のエラーの応答を gRPC の状態のコードとメッセージにする
+fn failure(response: HttpResponse) -> (Code, String) {
+    let message = serde_json::from_slice::<serde_json::Value>(&response.body)
+        .ok()
+        .and_then(|body| body["error"].as_str().map(str::to_string))
+        .unwrap_or_default();
+    let code = match response.status {
+        400 => Code::InvalidArgument,
+        403 => Code::PermissionDenied,
+        404 => Code::NotFound,
+        _ => Code::Internal,
+    };
+    (code, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serve::registry::{Dataset, Registration};
+    use geo::InteriorPoint;
+    use proto::layon_client::LayonClient;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    #[test]
+    fn every_rpc_answers_like_the_http_server() {
+        let collection = Input::parse(FIXTURE, InputOptions::default())
+            .unwrap()
+            .read()
+            .unwrap();
+        let features = collection.features.len();
+        // 最初の Feature の内側の点
+        let first = &collection.features[0];
+        let city = first.properties.as_ref().unwrap()["N03_004"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let inside = geo::Geometry::<f64>::try_from(first.geometry.clone().unwrap().value)
+            .unwrap()
+            .interior_point()
+            .unwrap();
+        let dataset = Dataset::new("default", "sample", collection);
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+        let server = Arc::new(
+            Server::new(dataset, "N03_004", "area", 0.001)
+                .allow_register(Registration::new(fixtures, false).unwrap()),
+        );
+        let expected = aggregate(&server.dataset(None).unwrap(), "N03_004", "area", None).unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            tokio::spawn(serve(Arc::clone(&server), listener));
+            let mut client = LayonClient::connect(format!("http://{}", address))
+                .await
+                .unwrap();
+
+            let mut stream = client
+                .aggregate(AggregateRequest::default())
+                .await
+                .unwrap()
+                .into_inner();
+            let mut rows = Vec::new();
+            while let Some(row) = stream.message().await.unwrap() {
+                rows.push(row);
+            }
+            assert_eq!(rows.len(), expected.len());
+            for (row, expected) in rows.iter().zip(&expected) {
+                assert_eq!(
+                    (&row.key, row.area, row.count),
+                    (&expected.key, expected.area, expected.count as u64)
+                );
+            }
+
+            let request = AggregateRequest {
+                r#where: format!("N03_004 == '{}'", city),
+                ..Default::default()
+            };
+            let mut stream = client.aggregate(request).await.unwrap().into_inner();
+            assert_eq!(stream.message().await.unwrap().unwrap().key, city);
+            assert!(stream.message().await.unwrap().is_none());
+
+            let request = AggregateRequest {
+                dataset: "missing".to_string(),
+                ..Default::default()
+            };
+            let err = client.aggregate(request).await.unwrap_err();
+            assert_eq!(err.code(), tonic::Code::NotFound);
+
+            let request = LocateRequest {
+                x: inside.x(),
+                y: inside.y(),
+                ..Default::default()
+            };
+            let located = client.locate(request).await.unwrap().into_inner();
+            assert!(located.found);
+            assert_eq!(located.distance, 0.0);
+            assert!(located.properties.contains(&city), "{}", located.properties);
+
+            let request = ConvertRequest {
+                source: FIXTURE.to_string(),
+            };
+            let mut stream = client.convert(request).await.unwrap().into_inner();
+            let mut converted = 0;
+            while let Some(response) = stream.message().await.unwrap() {
+                let feature: geojson::Feature = response.feature.parse().unwrap();
+                assert!(feature.geometry.is_some());
+                converted += 1;
+            }
+            assert_eq!(converted, features);
+        });
+    }
+}
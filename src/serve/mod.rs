@@ -0,0 +1,1057 @@
+//! 読み込んだデータセットの集計を HTTP で返すサーバー（`layon serve`）。
+//!
+//! 境界のデータを 1 度だけ読み込んでメモリに置き、集計キー・集計値・条件式を変えた集計をその場で返す。
+//! 小さなチームが内部向けの境界の統計サービスを 1 つのコマンドで立てられるように、
+//! 集計表、地図のプレビュー、条件の入力欄を持つページ（`/dashboard`）も組み込んである。
+//! 境界の版や都道府県の違うデータセットも、名前を付けて実行中に読み込める（`registry`）。
+//! 依存クレートを増やさないよう、HTTP/1.1 を標準ライブラリの TcpListener で処理する
+//! （接続ごとにスレッドを立て、応答したら接続を閉じる。リクエストの行とヘッダーの大きさと、同時に応答する接続の数には上限を設ける）。
+//!
+//! - `GET /dashboard`（`/` も同じ）: ダッシュボードのページ
+//! - `GET /api/aggregate?dataset=&group_by=&metric=&where=`: 集計結果の JSON（省略した値は起動時の設定）
+//! - `GET /api/boundaries?dataset=&group_by=`: グループごとにディゾルブして簡略化した境界の GeoJSON（集計キーごとに作って使い回す）
+//! - `GET /api/locate?dataset=&x=&y=`: 点を含む（なければ最も近い）Feature のプロパティ
+//! - `GET /datasets`: 読み込んだデータセットの一覧（最も新しく使ったものから）
+//! - `POST /datasets`: `{"name": …, "source": …}` の入力元（`--input` と同じ書式）を読み込んで加える
+//!   （`--allow-register` で決めたディレクトリの下のファイルだけ。指定しなければ受け付けない）
+//! - `DELETE /datasets/<name>`: データセットを取り除く
+//!
+//! grpc フィーチャーを有効にすると、同じデータセットの一覧を使う gRPC のサービスも並べて提供できる（`grpc`）。
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod registry;
+
+use crate::{
+    aggregate::{GroupResult, Metric},
+    cancel::CancellationToken,
+    filter::Filter,
+    log,
+    markup::escape,
+    projection::Projection,
+    schedule::Schedule,
+    source::{Input, InputOptions},
+    time,
+};
+use geo::Point;
+use geojson::FeatureCollection;
+use rayon::prelude::*;
+use registry::{Dataset, Registration, Registry};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// リクエストの行とヘッダーを読み終えるまで待つ時間
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// リクエストの本文の大きさの上限
+const MAX_BODY: usize = 64 * 1024;
+
+/// リクエストの行とヘッダーを合わせた大きさの上限
+const MAX_HEADER: u64 = 16 * 1024;
+
+/// 同時に応答する接続の数の上限（超えた接続には 503 を返してすぐに閉じる）
+pub const MAX_CONNECTIONS: usize = 64;
+
+/// `--max-datasets` の既定値
+pub const DEFAULT_MAX_DATASETS: usize = 4;
+
+/// 読み込んだデータセットと既定の集計の設定
+pub struct Server {
+    /// ページの見出し（起動時のデータセットの入力元）
+    pub name: String,
+    registry: Mutex<Registry>,
+    /// 実行中に読み込める入力元の範囲（None なら登録を受け付けない）
+    registration: Option<Registration>,
+    group_by: String,
+    /// 既定の集計値（`--metric` と同じ名前）
+    metric: String,
+    /// 地図の境界を簡略化する許容誤差（座標の単位）
+    tolerance: f64,
+}
+
+/// HTTP の応答
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: &serde_json::Value) -> Response {
+        Response {
+            status,
+            content_type: "application/json; charset=utf-8",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response::json(status, &json!({ "error": message }))
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            201 => "Created",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            431 => "Request Header Fields Too Large",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        }
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(
+            out,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            self.reason(),
+            self.content_type,
+            self.body.len()
+        )?;
+        out.write_all(&self.body)?;
+        out.flush()
+    }
+}
+
+impl Server {
+    /// `dataset` を起動時のデータセット（追い出さず、`dataset=` を省略したときに使う）にする
+    pub fn new(
+        dataset: Dataset,
+        group_by: impl Into<String>,
+        metric: impl Into<String>,
+        tolerance: f64,
+    ) -> Server {
+        Server {
+            name: dataset.source.clone(),
+            registry: Mutex::new(Registry::new(dataset, DEFAULT_MAX_DATASETS)),
+            registration: None,
+            group_by: group_by.into(),
+            metric: metric.into(),
+            tolerance,
+        }
+    }
+
+    /// 起動時のものも含めて置いておくデータセットの数（1 なら登録を受け付けない）
+    pub fn max_datasets(mut self, max: usize) -> Server {
+        self.registry.get_mut().unwrap().set_capacity(max);
+        self
+    }
+
+    /// `POST /datasets` で `registration` の範囲の入力元を受け付ける
+    pub fn allow_register(mut self, registration: Registration) -> Server {
+        self.registration = Some(registration);
+        self
+    }
+
+    /// 実行中に読み込んでよい入力元か（よくなければ理由）
+    fn check_source(&self, input: &Input) -> Result<(), String> {
+        match &self.registration {
+            Some(registration) => registration.check(input),
+            None => Err(
+                "入力元の読み込みは無効です (--allow-register <DIR> で有効にします)".to_string(),
+            ),
+        }
+    }
+
+    /// `address` で待ち受け、接続ごとにスレッドを立てて応答する（戻らない）。
+    /// gRPC のサービスとデータセットの一覧を共有できるよう、`Arc` に入れたまま受け取る
+    pub fn listen(self: Arc<Self>, address: &str) -> io::Result<()> {
+        self.accept(TcpListener::bind(address)?)
+    }
+
+    /// `listener` の接続に応答し続ける（戻らない）。`MAX_CONNECTIONS` を超えた接続には 503 を返して閉じる
+    pub fn accept(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        let server = self;
+        let active = Arc::new(AtomicUsize::new(0));
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                active.fetch_sub(1, Ordering::SeqCst);
+                let _ = stream.set_write_timeout(Some(READ_TIMEOUT));
+                let response = Response::error(503, "同時に応答できる接続の数を超えました");
+                let _ = response.write_to(&mut &stream);
+                continue;
+            }
+            let server = Arc::clone(&server);
+            let active = Arc::clone(&active);
+            thread::spawn(move || {
+                let result = server.respond(stream);
+                active.fetch_sub(1, Ordering::SeqCst);
+                if let Err(err) = result {
+                    log::warning!("警告: HTTP の応答に失敗しました: {}", err);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn respond(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        // リクエストの行とヘッダーは MAX_HEADER バイトまでしか読まない
+        let mut head = Read::take(&mut reader, MAX_HEADER);
+        let mut line = String::new();
+        head.read_line(&mut line)?;
+        // ヘッダーは本文の長さだけを使う
+        let mut length = 0;
+        let mut complete = false;
+        let mut header = String::new();
+        while head.read_line(&mut header)? > 0 {
+            if header.trim().is_empty() {
+                complete = true;
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap_or(0);
+                }
+            }
+            header.clear();
+        }
+        let too_large = !complete && head.limit() == 0;
+        let mut parts = line.split_whitespace();
+        let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        let response = if too_large {
+            Response::error(
+                431,
+                &format!("リクエストの行とヘッダーは {} バイトまでです", MAX_HEADER),
+            )
+        } else if length > MAX_BODY {
+            Response::error(413, &format!("本文は {} バイトまでです", MAX_BODY))
+        } else {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            self.handle(method, target, &body)
+        };
+        response.write_to(&mut &stream)
+    }
+
+    /// 1 つのリクエストに応答する（`target` はパスとクエリ文字列）
+    pub fn handle(&self, method: &str, target: &str, body: &[u8]) -> Response {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query = parse_query(query);
+        let param = |name: &str| {
+            query
+                .get(name)
+                .map(String::as_str)
+                .filter(|v| !v.is_empty())
+        };
+        let result = match (method, path) {
+            ("GET", "/" | "/dashboard") => {
+                return Response {
+                    status: 200,
+                    content_type: "text/html; charset=utf-8",
+                    body: DASHBOARD
+                        .replace("{{title}}", &escape(&self.name))
+                        .replace("{{group_by}}", &escape(&self.group_by))
+                        .replace("{{metric}}", &escape(&self.metric))
+                        .into_bytes(),
+                }
+            }
+            ("GET", "/api/aggregate") => self.dataset(param("dataset")).and_then(|dataset| {
+                let group_by = param("group_by").unwrap_or(&self.group_by);
+                let metric = param("metric").unwrap_or(&self.metric);
+                let rows = aggregate(&dataset, group_by, metric, param("where"))
+                    .map_err(|message| Response::error(400, &message))?;
+                Ok(Response::json(
+                    200,
+                    &json!({
+                        "dataset": dataset.name,
+                        "group_by": group_by,
+                        "metric": metric,
+                        "where": param("where"),
+                        "total": {
+                            "area": rows.iter().map(|row| row.area).sum::<f64>(),
+                            "count": rows.iter().map(|row| row.count).sum::<usize>(),
+                        },
+                        "rows": rows,
+                    }),
+                ))
+            }),
+            ("GET", "/api/boundaries") => self.dataset(param("dataset")).map(|dataset| {
+                let group_by = param("group_by").unwrap_or(&self.group_by);
+                Response {
+                    status: 200,
+                    content_type: "application/geo+json",
+                    body: dataset
+                        .boundaries(group_by, self.tolerance)
+                        .as_bytes()
+                        .to_vec(),
+                }
+            }),
+            ("GET", "/api/locate") => self.dataset(param("dataset")).and_then(|dataset| {
+                let metric = param("metric").unwrap_or(&self.metric);
+                locate(&dataset, param("x"), param("y"), metric)
+            }),
+            ("GET", "/datasets") => Ok(self.list()),
+            ("POST", "/datasets") => self.register(body),
+            ("DELETE", _) if path.starts_with("/datasets/") => {
+                self.unregister(&decode(&path["/datasets/".len()..]))
+            }
+            (_, "/" | "/dashboard" | "/api/aggregate" | "/api/boundaries" | "/api/locate")
+            | (_, "/datasets") => Err(Response::error(
+                405,
+                &format!("{} は {} に対応していません", path, method),
+            )),
+            _ => Err(Response::error(404, &format!("{} はありません", path))),
+        };
+        result.unwrap_or_else(|response| response)
+    }
+
+    /// 名前のデータセット（省略したら起動時のデータセット）
+    fn dataset(&self, name: Option<&str>) -> Result<Arc<Dataset>, Response> {
+        let mut registry = self.registry.lock().unwrap();
+        let name = name.unwrap_or(registry.pinned()).to_string();
+        registry.get(&name).ok_or_else(|| {
+            Response::error(
+                404,
+                &format!("データセット {} はありません（GET /datasets で一覧）", name),
+            )
+        })
+    }
+
+    fn list(&self) -> Response {
+        let registry = self.registry.lock().unwrap();
+        let datasets: Vec<_> = registry
+            .list()
+            .map(|dataset| describe(dataset, registry.pinned()))
+            .collect();
+        Response::json(200, &json!({ "datasets": datasets }))
+    }
+
+    /// `{"name": …, "source": …}` の入力元を読み込んで加える。
+    /// 読み込んでいる間は一覧をロックしないので、ほかのリクエストはそのまま応答できる
+    fn register(&self, body: &[u8]) -> Result<Response, Response> {
+        let request: serde_json::Value = serde_json::from_slice(body)
+            .map_err(|err| Response::error(400, &format!("本文の JSON が不正です: {}", err)))?;
+        let field = |name: &str| {
+            request[name].as_str().ok_or_else(|| {
+                Response::error(400, &format!("本文に文字列の {} がありません", name))
+            })
+        };
+        let (name, source) = (field("name")?, field("source")?);
+        registry::check_name(name).map_err(|message| Response::error(400, &message))?;
+        {
+            let registry = self.registry.lock().unwrap();
+            if !registry.accepts() {
+                return Err(Response::error(
+                    403,
+                    "--max-datasets が 1 のため、データセットを登録できません",
+                ));
+            }
+            if name == registry.pinned() {
+                return Err(Response::error(
+                    400,
+                    &format!("{} は起動時のデータセットのため置き換えられません", name),
+                ));
+            }
+        }
+        let input = Input::parse(source, InputOptions::default())
+            .map_err(|message| Response::error(400, &message))?;
+        self.check_source(&input)
+            .map_err(|message| Response::error(403, &message))?;
+        let collection = input.read().map_err(|err| {
+            Response::error(400, &format!("{} を読み込めません: {}", source, err))
+        })?;
+        let dataset = Dataset::new(name, input.describe(), collection);
+        let mut registry = self.registry.lock().unwrap();
+        let evicted = registry
+            .insert(dataset)
+            .map_err(|message| Response::error(400, &message))?;
+        let dataset = registry.get(name).unwrap();
+        Ok(Response::json(
+            201,
+            &json!({
+                "dataset": describe(&dataset, registry.pinned()),
+                "evicted": evicted,
+            }),
+        ))
+    }
+
+    fn unregister(&self, name: &str) -> Result<Response, Response> {
+        let removed = self
+            .registry
+            .lock()
+            .unwrap()
+            .remove(name)
+            .map_err(|message| Response::error(400, &message))?;
+        match removed {
+            Some(dataset) => Ok(Response::json(200, &json!({ "removed": dataset.name }))),
+            None => Err(Response::error(
+                404,
+                &format!("データセット {} はありません", name),
+            )),
+        }
+    }
+}
+
+/// 一覧に書くデータセットの説明
+fn describe(dataset: &Dataset, pinned: &str) -> serde_json::Value {
+    json!({
+        "name": dataset.name,
+        "source": dataset.source,
+        "features": dataset.collection.features.len(),
+        "loaded_at": time::rfc3339(dataset.loaded_at),
+        "pinned": dataset.name == pinned,
+    })
+}
+
+/// 条件式を満たす Feature を集計する
+fn aggregate(
+    dataset: &Dataset,
+    group_by: &str,
+    metric: &str,
+    filter: Option<&str>,
+) -> Result<Vec<GroupResult>, String> {
+    let metric = parse_metric(metric)?;
+    let rows = match filter {
+        None => crate::aggregate::aggregate_with(
+            &dataset.collection,
+            group_by,
+            false,
+            metric,
+            Schedule::ByCost,
+            &CancellationToken::new(),
+        ),
+        Some(expression) => {
+            let filter =
+                Filter::parse(expression).map_err(|err| format!("条件式が不正です: {}", err))?;
+            let subset = FeatureCollection {
+                bbox: None,
+                features: dataset
+                    .collection
+                    .features
+                    .par_iter()
+                    .filter(|feature| filter.matches(feature))
+                    .cloned()
+                    .collect(),
+                foreign_members: None,
+            };
+            crate::aggregate::aggregate_with(
+                &subset,
+                group_by,
+                false,
+                metric,
+                Schedule::ByCost,
+                &CancellationToken::new(),
+            )
+        }
+    };
+    // 中断しないトークンなので Err にはならない
+    rows.map_err(|err| err.to_string())
+}
+
+/// 点を含む（なければ最も近い）Feature。距離は geodesic-area なら km、そうでなければ座標の単位
+fn locate(
+    dataset: &Dataset,
+    x: Option<&str>,
+    y: Option<&str>,
+    metric: &str,
+) -> Result<Response, Response> {
+    let coordinate = |name: &str, value: Option<&str>| {
+        value
+            .and_then(|value| value.parse::<f64>().ok())
+            .filter(|value| value.is_finite())
+            .ok_or_else(|| Response::error(400, &format!("{} の値が不正です", name)))
+    };
+    let point = Point::new(coordinate("x", x)?, coordinate("y", y)?);
+    let metric = parse_metric(metric).map_err(|message| Response::error(400, &message))?;
+    let hit = dataset.index().locate(point, metric, None);
+    Ok(Response::json(
+        200,
+        &json!({
+            "dataset": dataset.name,
+            "x": point.x(),
+            "y": point.y(),
+            "feature": hit.map(|hit| hit.feature),
+            "distance": hit.map(|hit| hit.distance),
+            "properties": hit.and_then(|hit| dataset.collection.features[hit.feature].properties.clone()),
+        }),
+    ))
+}
+
+/// `area`, `geodesic-area`, `length` または投影法の名前（`layon serve --metric` と `/api/aggregate` の metric）
+pub fn parse_metric(name: &str) -> Result<Metric, String> {
+    match name {
+        "area" => Ok(Metric::Area),
+        "geodesic-area" => Ok(Metric::GeodesicArea),
+        "length" => Ok(Metric::GeodesicLength),
+        _ => Projection::parse(name)
+            .map(Metric::Projected)
+            .map_err(|_| format!("不明な集計値です: {}", name)),
+    }
+}
+
+/// クエリ文字列の名前と値（%XX と + を戻す。同じ名前は後のもの）
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(name), decode(value))
+        })
+        .collect()
+}
+
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => match text
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    out.push(byte);
+                    i += 2;
+                }
+                None => out.push(b'%'),
+            },
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// ダッシュボード。集計表と地図は `/api/aggregate` と `/api/boundaries` から取り、入力欄やデータセットを変えると読み直す
+const DASHBOARD: &str = r##"<!DOCTYPE html>
+<html lang="ja">
+<head>
+<meta charset="utf-8">
+<title>{{title}} - layon</title>
+<style>
+body { font-family: sans-serif; margin: 1em; color: #222; }
+form { display: flex; flex-wrap: wrap; gap: 0.5em 1em; align-items: end; margin-bottom: 1em; }
+label { display: flex; flex-direction: column; font-size: 90%; }
+input[name=where] { width: 24em; }
+#status { color: #666; }
+#status.error { color: #c00; }
+main { display: flex; flex-wrap: wrap; gap: 1em; }
+svg { flex: 1 1 480px; max-height: 80vh; border: 1px solid #ccc; background: #f6f8fa; }
+path { stroke: #555; stroke-width: 0.5; vector-effect: non-scaling-stroke; }
+path.selected, tr.selected td { outline: 2px solid #d33; }
+tr.selected td { background: #fee; }
+section { flex: 1 1 320px; max-height: 80vh; overflow: auto; }
+table { border-collapse: collapse; width: 100%; }
+th { cursor: pointer; position: sticky; top: 0; background: #eee; }
+th, td { padding: 2px 6px; border-bottom: 1px solid #ddd; }
+td.number { text-align: right; font-variant-numeric: tabular-nums; }
+</style>
+</head>
+<body>
+<h1>{{title}}</h1>
+<form id="query">
+<label>データセット <select name="dataset"></select></label>
+<label>集計キー <input name="group_by" value="{{group_by}}"></label>
+<label>集計値 <select name="metric">
+<option value="area">area (座標の単位)</option>
+<option value="geodesic-area">geodesic-area (km²)</option>
+<option value="length">length (線の km)</option>
+<option value="jgd2011-albers">jgd2011-albers (km²)</option>
+<option value="utm-auto">utm-auto (km²)</option>
+</select></label>
+<label>条件式 (--where と同じ書式) <input name="where" placeholder="N03_004 != '秩父市'"></label>
+<button>集計</button>
+<span id="status"></span>
+</form>
+<main>
+<svg id="map" xmlns="http://www.w3.org/2000/svg"></svg>
+<section>
+<table id="rows"><thead><tr><th data-key="key">名前</th><th data-key="area">集計値</th><th data-key="count">Feature の数</th></tr></thead><tbody></tbody></table>
+</section>
+</main>
+<script>
+(function () {
+  var SVG = "http://www.w3.org/2000/svg";
+  var COLORS = ["#edf8e9", "#bae4b3", "#74c476", "#31a354", "#006d2c"];
+  var form = document.getElementById("query");
+  var status = document.getElementById("status");
+  var map = document.getElementById("map");
+  var body = document.querySelector("#rows tbody");
+  form.metric.value = "{{metric}}";
+  var boundaries = { key: null, features: [] };
+  var rows = [], paths = {}, cells = {};
+  var order = { key: "area", descending: true };
+
+  function show(text, error) {
+    status.textContent = text;
+    status.className = error ? "error" : "";
+  }
+
+  function fetchJson(url) {
+    return fetch(url).then(function (response) {
+      return response.json().then(function (data) {
+        if (!response.ok) throw new Error(data.error || response.statusText);
+        return data;
+      });
+    });
+  }
+
+  function rings(geometry) {
+    if (!geometry) return [];
+    if (geometry.type === "Polygon") return geometry.coordinates;
+    if (geometry.type === "MultiPolygon") return [].concat.apply([], geometry.coordinates);
+    return [];
+  }
+
+  function select(name) {
+    Object.keys(paths).forEach(function (key) { paths[key].classList.toggle("selected", key === name); });
+    Object.keys(cells).forEach(function (key) { cells[key].classList.toggle("selected", key === name); });
+    if (cells[name]) cells[name].scrollIntoView({ block: "nearest" });
+  }
+
+  function drawMap() {
+    map.textContent = "";
+    paths = {};
+    var west = Infinity, east = -Infinity, south = Infinity, north = -Infinity;
+    boundaries.features.forEach(function (feature) {
+      rings(feature.geometry).forEach(function (ring) {
+        ring.forEach(function (c) {
+          west = Math.min(west, c[0]); east = Math.max(east, c[0]);
+          south = Math.min(south, c[1]); north = Math.max(north, c[1]);
+        });
+      });
+    });
+    if (west > east) return;
+    var scale = Math.cos((south + north) / 2 * Math.PI / 180) || 1;
+    map.setAttribute("viewBox", [west * scale, -north, (east - west) * scale || 1, (north - south) || 1].join(" "));
+    // 集計値の五分位で塗る。条件式で外れたグループは灰色
+    var values = {};
+    rows.forEach(function (row) { values[row.key] = row.area; });
+    var sorted = rows.map(function (row) { return row.area; }).sort(function (a, b) { return a - b; });
+    function color(value) {
+      if (value === undefined) return "#ddd";
+      var rank = 0;
+      while (rank < sorted.length && sorted[rank] < value) rank++;
+      return COLORS[Math.min(COLORS.length - 1, Math.floor(rank * COLORS.length / Math.max(sorted.length, 1)))];
+    }
+    boundaries.features.forEach(function (feature) {
+      var name = feature.properties.name;
+      var path = document.createElementNS(SVG, "path");
+      path.setAttribute("d", rings(feature.geometry).map(function (ring) {
+        return "M" + ring.map(function (c) { return (c[0] * scale) + "," + (-c[1]); }).join("L") + "Z";
+      }).join(""));
+      path.setAttribute("fill", color(values[name]));
+      path.setAttribute("fill-rule", "evenodd");
+      var title = document.createElementNS(SVG, "title");
+      title.textContent = name + (values[name] === undefined ? "" : ": " + values[name]);
+      path.appendChild(title);
+      path.addEventListener("click", function () { select(name); });
+      map.appendChild(path);
+      paths[name] = path;
+    });
+  }
+
+  function drawTable() {
+    body.textContent = "";
+    cells = {};
+    rows.slice().sort(function (a, b) {
+      var c = order.key === "key" ? a.key.localeCompare(b.key) : a[order.key] - b[order.key];
+      return order.descending ? -c : c;
+    }).forEach(function (row) {
+      var tr = document.createElement("tr");
+      [row.key, row.area, row.count].forEach(function (value, i) {
+        var td = document.createElement("td");
+        td.textContent = value;
+        if (i > 0) td.className = "number";
+        tr.appendChild(td);
+      });
+      tr.addEventListener("click", function () { select(row.key); });
+      body.appendChild(tr);
+      cells[row.key] = tr;
+    });
+  }
+
+  document.querySelectorAll("#rows th").forEach(function (th) {
+    th.addEventListener("click", function () {
+      var key = th.dataset.key;
+      order = { key: key, descending: order.key === key ? !order.descending : key !== "key" };
+      drawTable();
+    });
+  });
+
+  function run() {
+    var params = new URLSearchParams(new FormData(form));
+    var query = { dataset: form.dataset.value, group_by: form.group_by.value };
+    var key = JSON.stringify(query);
+    show("集計しています…");
+    var pending = [fetchJson("/api/aggregate?" + params)];
+    if (boundaries.key !== key) {
+      pending.push(fetchJson("/api/boundaries?" + new URLSearchParams(query)));
+    }
+    Promise.all(pending).then(function (results) {
+      rows = results[0].rows;
+      if (results[1]) boundaries = { key: key, features: results[1].features };
+      drawTable();
+      drawMap();
+      show(rows.length + " グループ、Feature " + results[0].total.count + " 個");
+    }).catch(function (err) { show(err.message, true); });
+  }
+
+  form.addEventListener("submit", function (event) {
+    event.preventDefault();
+    run();
+  });
+  form.dataset.addEventListener("change", run);
+  // データセットの一覧を選択肢にしてから集計する（起動時のデータセットを選んでおく）
+  fetchJson("/datasets").then(function (data) {
+    data.datasets.forEach(function (dataset) {
+      var option = document.createElement("option");
+      option.value = dataset.name;
+      option.textContent = dataset.name + " (" + dataset.source + ")";
+      option.selected = dataset.pinned;
+      form.dataset.appendChild(option);
+    });
+    run();
+  }).catch(function (err) { show(err.message, true); });
+})();
+</script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Pipeline;
+    use std::fs;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+    const GROUP_BY: &str = "N03_004";
+
+    #[test]
+    fn server_answers_aggregate_queries_with_filters_and_serves_the_dashboard() {
+        let collection = Input::parse(FIXTURE, InputOptions::default())
+            .unwrap()
+            .read()
+            .unwrap();
+        let expected = Pipeline::read(FIXTURE)
+            .group_by(GROUP_BY)
+            .metric(Metric::GeodesicArea)
+            .run()
+            .unwrap()
+            .rows;
+        let dataset = Dataset::new("default", "sample", collection);
+        let server = Server::new(dataset, GROUP_BY, "area", 0.001);
+        let get = |target: &str| {
+            let response = server.handle("GET", target, &[]);
+            let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+            (response.status, body)
+        };
+
+        let (status, body) = get("/api/aggregate?metric=geodesic-area");
+        assert_eq!(status, 200);
+        let rows = body["rows"].as_array().unwrap();
+        assert_eq!(rows.len(), expected.len());
+        assert_eq!(rows[0]["key"], expected[0].key.as_str());
+        assert!((rows[0]["area"].as_f64().unwrap() - expected[0].area).abs() < 1e-9);
+
+        // 条件式はクエリ文字列でエンコードして渡す（+ は空白）
+        let target = format!(
+            "/api/aggregate?metric=geodesic-area&where=N03_004+%3D%3D+%27{}%27",
+            expected[1]
+                .key
+                .bytes()
+                .map(|b| format!("%{:02X}", b))
+                .collect::<String>()
+        );
+        let (status, body) = get(&target);
+        assert_eq!(status, 200);
+        assert_eq!(body["rows"].as_array().unwrap().len(), 1);
+        assert_eq!(body["rows"][0]["key"], expected[1].key.as_str());
+        assert_eq!(body["total"]["count"], expected[1].count);
+
+        let (status, body) = get("/api/boundaries");
+        assert_eq!(status, 200);
+        assert_eq!(body["features"].as_array().unwrap().len(), expected.len());
+
+        let (status, body) = get("/api/aggregate?metric=volume");
+        assert_eq!(status, 400);
+        assert!(body["error"].as_str().unwrap().contains("volume"));
+        let (status, _) = get("/api/aggregate?where=N03_004+%3D%3D");
+        assert_eq!(status, 400);
+        assert_eq!(get("/nothing").0, 404);
+
+        let page = server.handle("GET", "/dashboard", &[]);
+        assert_eq!(page.status, 200);
+        assert!(page.content_type.starts_with("text/html"));
+        let page = String::from_utf8(page.body).unwrap();
+        assert!(page.contains("<title>sample - layon</title>"));
+        assert!(page.contains("/api/aggregate?"));
+    }
+
+    #[test]
+    fn server_registers_datasets_and_evicts_the_least_recently_used() {
+        let dir = std::env::temp_dir().join(format!("layon-serve-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let square = |name: &str, city: &str| {
+            let path = dir.join(format!("{}.geojson", name));
+            fs::write(
+                &path,
+                format!(
+                    r#"{{"type":"FeatureCollection","features":[{{"type":"Feature","properties":{{"N03_004":"{}"}},"geometry":{{"type":"Polygon","coordinates":[[[0,0],[1,0],[1,1],[0,1],[0,0]]]}}}}]}}"#,
+                    city
+                ),
+            )
+            .unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let collection = Input::parse(FIXTURE, InputOptions::default())
+            .unwrap()
+            .read()
+            .unwrap();
+        let server = Server::new(
+            Dataset::new("n03-2024", "sample", collection),
+            GROUP_BY,
+            "area",
+            0.0,
+        )
+        .max_datasets(3)
+        .allow_register(Registration::new(&dir, false).unwrap());
+        let request = |method: &str, target: &str, body: serde_json::Value| {
+            let body = if body.is_null() {
+                Vec::new()
+            } else {
+                body.to_string().into_bytes()
+            };
+            let response = server.handle(method, target, &body);
+            let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+            (response.status, body)
+        };
+        let register = |name: &str, city: &str| {
+            request(
+                "POST",
+                "/datasets",
+                serde_json::json!({ "name": name, "source": square(name, city) }),
+            )
+        };
+        let names = || {
+            let (_, body) = request("GET", "/datasets", serde_json::Value::Null);
+            body["datasets"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|dataset| dataset["name"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let (status, body) = register("a", "甲");
+        assert_eq!(status, 201);
+        assert_eq!(body["dataset"]["features"], 1);
+        assert_eq!(body["evicted"], serde_json::json!([]));
+        assert_eq!(register("b", "乙").0, 201);
+        assert_eq!(names(), ["b", "a", "n03-2024"]);
+
+        // 使ったデータセットは新しくなり、超えたときは最も長く使われていない b を追い出す
+        let (status, body) = request("GET", "/api/aggregate?dataset=a", serde_json::Value::Null);
+        assert_eq!(status, 200);
+        assert_eq!(body["rows"][0]["key"], "甲");
+        let (status, body) = register("c", "丙");
+        assert_eq!(status, 201);
+        assert_eq!(body["evicted"], serde_json::json!(["b"]));
+        assert_eq!(names(), ["c", "a", "n03-2024"]);
+        let (status, _) = request("GET", "/api/aggregate?dataset=b", serde_json::Value::Null);
+        assert_eq!(status, 404);
+        // dataset を省略したら起動時のデータセット
+        let (_, body) = request("GET", "/api/aggregate", serde_json::Value::Null);
+        assert_eq!(body["dataset"], "n03-2024");
+
+        // 点の検索はデータセットごとの索引で
+        let (status, body) = request(
+            "GET",
+            "/api/locate?dataset=c&x=0.5&y=0.5",
+            serde_json::Value::Null,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["properties"]["N03_004"], "丙");
+        assert_eq!(body["distance"], 0.0);
+        let (_, body) = request(
+            "GET",
+            "/api/locate?dataset=c&x=3&y=0.5",
+            serde_json::Value::Null,
+        );
+        assert_eq!(body["distance"], 2.0);
+        assert_eq!(
+            request(
+                "GET",
+                "/api/locate?dataset=c&x=east",
+                serde_json::Value::Null
+            )
+            .0,
+            400
+        );
+
+        // 起動時のデータセットは置き換えも削除もできない
+        assert_eq!(register("n03-2024", "丁").0, 400);
+        assert_eq!(register("../etc", "丁").0, 400);
+        let (status, body) = request(
+            "POST",
+            "/datasets",
+            serde_json::json!({ "name": "d", "source": dir.join("none.geojson").to_str().unwrap() }),
+        );
+        assert_eq!(status, 400, "{}", body);
+        // 登録できるのは --allow-register のディレクトリの下のファイルだけ（リンクをたどった先も確かめる）
+        let (status, body) = request(
+            "POST",
+            "/datasets",
+            serde_json::json!({ "name": "d", "source": FIXTURE }),
+        );
+        assert_eq!(status, 403, "{}", body);
+        #[cfg(unix)]
+        {
+            let link = dir.join("link.geojson");
+            let _ = fs::remove_file(&link);
+            std::os::unix::fs::symlink(fs::canonicalize(FIXTURE).unwrap(), &link).unwrap();
+            let (status, _) = request(
+                "POST",
+                "/datasets",
+                serde_json::json!({ "name": "d", "source": link.to_str().unwrap() }),
+            );
+            assert_eq!(status, 403);
+        }
+        // 外部コマンドを使う入力元は --allow-external-sources を指定したときだけ
+        for source in [
+            "postgres://localhost/gis?table=cities".to_string(),
+            dir.join("a.zip").to_str().unwrap().to_string(),
+        ] {
+            let (status, body) = request(
+                "POST",
+                "/datasets",
+                serde_json::json!({ "name": "d", "source": source }),
+            );
+            assert_eq!(status, 403, "{}: {}", source, body);
+            assert!(body["error"]
+                .as_str()
+                .unwrap()
+                .contains("--allow-external-sources"));
+        }
+        assert_eq!(
+            request("DELETE", "/datasets/n03-2024", serde_json::Value::Null).0,
+            400
+        );
+        let (status, body) = request("DELETE", "/datasets/a", serde_json::Value::Null);
+        assert_eq!((status, body["removed"].as_str()), (200, Some("a")));
+        assert_eq!(names(), ["c", "n03-2024"]);
+        assert_eq!(
+            request("DELETE", "/datasets/a", serde_json::Value::Null).0,
+            404
+        );
+        assert_eq!(request("PUT", "/datasets", serde_json::Value::Null).0, 405);
+
+        let single = Server::new(
+            Dataset::new(
+                "default",
+                "sample",
+                FeatureCollection {
+                    bbox: None,
+                    features: Vec::new(),
+                    foreign_members: None,
+                },
+            ),
+            GROUP_BY,
+            "area",
+            0.0,
+        )
+        .max_datasets(1)
+        .allow_register(Registration::new(&dir, false).unwrap());
+        let body = serde_json::json!({ "name": "a", "source": square("a", "甲") }).to_string();
+        assert_eq!(
+            single.handle("POST", "/datasets", body.as_bytes()).status,
+            403
+        );
+        // --allow-register を指定しなければ登録を受け付けない
+        let closed = Server::new(
+            Dataset::new(
+                "default",
+                "sample",
+                FeatureCollection {
+                    bbox: None,
+                    features: Vec::new(),
+                    foreign_members: None,
+                },
+            ),
+            GROUP_BY,
+            "area",
+            0.0,
+        );
+        let response = closed.handle("POST", "/datasets", body.as_bytes());
+        assert_eq!(response.status, 403);
+        assert!(String::from_utf8(response.body)
+            .unwrap()
+            .contains("--allow-register"));
+    }
+
+    #[test]
+    fn server_limits_header_bytes_and_concurrent_connections() {
+        use std::{
+            io::{Read, Write},
+            net::{TcpListener, TcpStream},
+            sync::Arc,
+        };
+
+        let dataset = Dataset::new(
+            "default",
+            "sample",
+            FeatureCollection {
+                bbox: None,
+                features: Vec::new(),
+                foreign_members: None,
+            },
+        );
+        let server = Arc::new(Server::new(dataset, GROUP_BY, "area", 0.0));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || server.accept(listener));
+        let send = |request: &[u8]| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(request).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        assert!(send(b"GET /datasets HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 200 OK"));
+        // 上限の 16 KiB を読んでもヘッダーが終わらなければ、それ以上は読まずに断る
+        let mut request = b"GET /datasets HTTP/1.1\r\nX-Padding: ".to_vec();
+        request.resize(16 * 1024, b'a');
+        assert!(send(&request).starts_with("HTTP/1.1 431"));
+
+        // リクエストを送らないまま上限まで接続すると、次の接続には 503 を返す
+        let idle: Vec<_> = (0..MAX_CONNECTIONS)
+            .map(|_| TcpStream::connect(address).unwrap())
+            .collect();
+        let mut response = String::new();
+        TcpStream::connect(address)
+            .unwrap()
+            .read_to_string(&mut response)
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+        drop(idle);
+    }
+}
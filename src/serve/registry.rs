@@ -0,0 +1,239 @@
+//! 名前を付けて読み込んだデータセットの一覧（`POST /datasets`）。
+//!
+//! 境界の版（年次）や都道府県ごとのデータを 1 つのサーバーで切り替えて集計できるようにする。
+//! メモリを使い切らないよう、決まった数を超えたら最も長く使われていないものから追い出す
+//! （起動時に読み込んだデータセットは追い出さない）。
+//! 追い出したデータセットも、それを使っている途中のリクエストが終わるまではメモリに残る。
+
+use crate::{aggregate, locate::Index, prefecture, sink, source::Input};
+use geojson::FeatureCollection;
+use rayon::prelude::*;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+
+/// データセットの名前の長さの上限（URL のパスに使う）
+const MAX_NAME: usize = 64;
+
+/// 名前を付けて読み込んだデータセット
+pub struct Dataset {
+    pub name: String,
+    /// 入力元の説明
+    pub source: String,
+    pub collection: FeatureCollection,
+    pub loaded_at: SystemTime,
+    /// 点の検索に使う索引（初めて検索したときに作る）
+    index: OnceLock<Index>,
+    /// 集計キーごとの境界の GeoJSON
+    boundaries: Mutex<HashMap<String, Arc<String>>>,
+}
+
+impl Dataset {
+    pub fn new(
+        name: impl Into<String>,
+        source: impl Into<String>,
+        collection: FeatureCollection,
+    ) -> Dataset {
+        Dataset {
+            name: name.into(),
+            source: source.into(),
+            collection,
+            loaded_at: SystemTime::now(),
+            index: OnceLock::new(),
+            boundaries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// ポリゴンの索引
+    pub fn index(&self) -> &Index {
+        self.index.get_or_init(|| Index::new(&self.collection))
+    }
+
+    /// 集計キーごとにディゾルブして簡略化した境界の GeoJSON（初めての集計キーなら作る）
+    pub fn boundaries(&self, group_by: &str, tolerance: f64) -> Arc<String> {
+        if let Some(text) = self.boundaries.lock().unwrap().get(group_by) {
+            return Arc::clone(text);
+        }
+        let mut rows = aggregate::aggregate(&self.collection, group_by, true);
+        if tolerance > 0.0 {
+            rows.par_iter_mut().for_each(|row| {
+                row.geometry = row
+                    .geometry
+                    .take()
+                    .map(|polygons| prefecture::simplify(&polygons, tolerance));
+            });
+        }
+        let text = Arc::new(sink::geojson::dissolved(&rows).to_string());
+        // 同時に作った場合は後のもので置き換わるだけ
+        self.boundaries
+            .lock()
+            .unwrap()
+            .insert(group_by.to_string(), Arc::clone(&text));
+        text
+    }
+}
+
+/// データセットの名前として使えるか（英数字と `-`, `_`, `.`）
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty()
+        || name.len() > MAX_NAME
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(format!(
+            "データセットの名前が不正です: {} ({} 文字までの英数字と -, _, .)",
+            name, MAX_NAME
+        ));
+    }
+    Ok(())
+}
+
+/// 実行中に読み込める入力元の範囲（`--allow-register`）。
+/// 登録の API には認証がないため、読めるのは決めたディレクトリの下のファイルだけにする
+pub struct Registration {
+    /// 登録できるファイルを置くディレクトリ（シンボリックリンクを解決した絶対パス）
+    root: PathBuf,
+    /// PostGIS と外部コマンド（unzip, psql）を使う入力元も受け付ける
+    external: bool,
+}
+
+impl Registration {
+    /// `root` の下のファイルだけを受け付ける（`external` なら外部コマンドを使う入力元も）
+    pub fn new(root: impl AsRef<Path>, external: bool) -> io::Result<Registration> {
+        Ok(Registration {
+            root: fs::canonicalize(root)?,
+            external,
+        })
+    }
+
+    /// 入力元を読み込んでよいか（よくなければ理由）
+    pub fn check(&self, input: &Input) -> Result<(), String> {
+        if let Some(command) = input.external_command() {
+            if !self.external {
+                return Err(format!(
+                    "{} は {} を使うため登録できません (--allow-external-sources で有効にします)",
+                    input.describe(),
+                    command
+                ));
+            }
+        }
+        let Some(path) = input.path() else {
+            // PostGIS は外部コマンドを許したときだけここに来る
+            return Ok(());
+        };
+        // まだないファイルは置き場所のディレクトリで確かめる（読み込めないことは読み込むときに返す）
+        let path = Path::new(path);
+        let resolved = fs::canonicalize(path).or_else(|err| {
+            let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+            match (dir, path.file_name()) {
+                (dir, Some(name)) => {
+                    Ok(fs::canonicalize(dir.unwrap_or(Path::new(".")))?.join(name))
+                }
+                _ => Err(err),
+            }
+        });
+        match resolved {
+            Ok(path) if path.starts_with(&self.root) => Ok(()),
+            Ok(_) => Err(format!(
+                "{} は登録できるディレクトリ ({}) の外にあります",
+                input.describe(),
+                self.root.display()
+            )),
+            Err(err) => Err(format!("{} を読み込めません: {}", input.describe(), err)),
+        }
+    }
+}
+
+/// 読み込んだデータセットの一覧
+pub struct Registry {
+    /// 起動時のものも含めて置いておくデータセットの数
+    capacity: usize,
+    /// 追い出さないデータセット
+    pinned: String,
+    /// 使われた順（最後が最も新しい）
+    datasets: Vec<Arc<Dataset>>,
+}
+
+impl Registry {
+    pub fn new(pinned: Dataset, capacity: usize) -> Registry {
+        Registry {
+            capacity,
+            pinned: pinned.name.clone(),
+            datasets: vec![Arc::new(pinned)],
+        }
+    }
+
+    /// 置いておく数を変える（次に加えたときに超えた分を追い出す）
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    /// 追い出さないデータセットの名前
+    pub fn pinned(&self) -> &str {
+        &self.pinned
+    }
+
+    /// 登録を受け付けるか（1 つしか置けない場合は起動時のものだけ）
+    pub fn accepts(&self) -> bool {
+        self.capacity > 1
+    }
+
+    /// 名前のデータセット（最も新しく使ったものにする）
+    pub fn get(&mut self, name: &str) -> Option<Arc<Dataset>> {
+        let i = self.datasets.iter().position(|d| d.name == name)?;
+        let dataset = self.datasets.remove(i);
+        self.datasets.push(Arc::clone(&dataset));
+        Some(dataset)
+    }
+
+    /// データセットを加え（同じ名前のものは置き換える）、追い出したデータセットの名前を返す
+    pub fn insert(&mut self, dataset: Dataset) -> Result<Vec<String>, String> {
+        if !self.accepts() {
+            return Err("起動時のデータセットしか置けないため、登録できません".to_string());
+        }
+        if dataset.name == self.pinned {
+            return Err(format!(
+                "{} は起動時のデータセットのため置き換えられません",
+                dataset.name
+            ));
+        }
+        self.datasets.retain(|d| d.name != dataset.name);
+        self.datasets.push(Arc::new(dataset));
+        let mut evicted = Vec::new();
+        while self.datasets.len() > self.capacity {
+            // 最後に加えたものと起動時のものは残す
+            let i = self
+                .datasets
+                .iter()
+                .position(|d| d.name != self.pinned)
+                .unwrap();
+            evicted.push(self.datasets.remove(i).name.clone());
+        }
+        Ok(evicted)
+    }
+
+    /// データセットを取り除く（なければ None）
+    pub fn remove(&mut self, name: &str) -> Result<Option<Arc<Dataset>>, String> {
+        if name == self.pinned {
+            return Err(format!(
+                "{} は起動時のデータセットのため取り除けません",
+                name
+            ));
+        }
+        Ok(self
+            .datasets
+            .iter()
+            .position(|d| d.name == name)
+            .map(|i| self.datasets.remove(i)))
+    }
+
+    /// 最も新しく使ったものから順のデータセット
+    pub fn list(&self) -> impl Iterator<Item = &Arc<Dataset>> {
+        self.datasets.iter().rev()
+    }
+}
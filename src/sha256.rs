@@ -0,0 +1,15 @@
+//! SHA-256。出力の来歴に入力ファイルのハッシュ値を記録するほか、キャッシュや一時ファイルの名前に使う。
+
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+
+/// 読み込み元の内容全体の SHA-256 を 16 進の文字列で返す
+pub fn hex_digest<R: Read>(mut reader: R) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
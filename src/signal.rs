@@ -0,0 +1,109 @@
+//! SIGINT / SIGTERM を受け取ったらトークンを中断する。
+//! 長時間の集計を Ctrl-C で止めても、それまでの結果を書き出してから終了できるようにする。
+//! 2 回目のシグナルでは待たずにすぐ終了する。
+//!
+//! シグナルは signal-hook で受け取り、最初に登録したときに始める 1 つの監視のスレッドが、
+//! 登録されたすべてのトークンを中断する。
+
+use crate::cancel::CancellationToken;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, MutexGuard, OnceLock, PoisonError,
+};
+
+/// シグナルを受け取ったか
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// シグナルを受け取ったら中断するトークン
+static TOKENS: Mutex<Vec<CancellationToken>> = Mutex::new(Vec::new());
+
+/// 監視のスレッドを始められたか（Unix 以外や、シグナルを受け取れない場合は false）
+static WATCHER: OnceLock<bool> = OnceLock::new();
+
+/// SIGINT / SIGTERM を受け取ったら `token` を中断するようにする（Unix 以外では何もしない）。
+/// すでにシグナルを受け取っていれば、すぐに中断する
+pub fn cancel_on_signal(token: &CancellationToken) {
+    if !*WATCHER.get_or_init(watch) {
+        return;
+    }
+    let mut tokens = tokens();
+    if INTERRUPTED.load(Ordering::Relaxed) {
+        token.cancel();
+    } else {
+        tokens.push(token.clone());
+    }
+}
+
+/// 登録されたトークン（シグナルを受け取ったかの確認と登録は、このロックを取ってから行う）
+fn tokens() -> MutexGuard<'static, Vec<CancellationToken>> {
+    TOKENS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(unix)]
+fn watch() -> bool {
+    use signal_hook::{
+        consts::{SIGINT, SIGTERM},
+        iterator::Signals,
+    };
+
+    let Ok(mut signals) = Signals::new([SIGINT, SIGTERM]) else {
+        return false;
+    };
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            let mut tokens = tokens();
+            if INTERRUPTED.swap(true, Ordering::Relaxed) {
+                // 2 回目は書き出しを待たずに終了する（シェルの慣例に合わせて 128 + シグナル番号）
+                signal_hook::low_level::exit(128 + signal);
+            }
+            for token in tokens.drain(..) {
+                token.cancel();
+            }
+        }
+    });
+    true
+}
+
+#[cfg(not(unix))]
+fn watch() -> bool {
+    false
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{pipeline::Pipeline, sink::Output};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn a_signal_writes_the_partial_result() {
+        let cancel = CancellationToken::new();
+        cancel_on_signal(&cancel);
+        signal_hook::low_level::raise(signal_hook::consts::SIGTERM).unwrap();
+        let start = Instant::now();
+        while !cancel.is_cancelled() {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "シグナルで中断されません"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // シグナルを受け取った後に登録したトークンはすぐに中断される
+        let late = CancellationToken::new();
+        cancel_on_signal(&late);
+        assert!(late.is_cancelled());
+
+        let path = std::env::temp_dir().join(format!("layon-signal-{}.csv", std::process::id()));
+        let result = Pipeline::read("tests/fixtures/n03_11_sample.geojson")
+            .cancellation(cancel)
+            .partial(true)
+            .sink(Output::parse(path.to_str().unwrap()).unwrap())
+            .run()
+            .unwrap();
+        assert!(result.cancelled.is_some());
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.starts_with("City,Area"), "{}", written);
+        assert_eq!(written.lines().count(), result.rows.len() + 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
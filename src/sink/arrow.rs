@@ -0,0 +1,441 @@
+use super::flatbuffer::{Table, Value};
+use crate::aggregate::GroupResult;
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+/// 1 つのレコードバッチに含める行数
+const BATCH_ROWS: usize = 65536;
+
+/// ファイル形式の先頭と末尾に置くマジックナンバー
+const MAGIC: &[u8] = b"ARROW1";
+/// メッセージの前に置く継続マーカー
+const CONTINUATION: u32 = 0xFFFF_FFFF;
+
+// Arrow の FlatBuffers スキーマ（Schema.fbs / Message.fbs）上の定数
+const METADATA_V5: i16 = 4;
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_RECORD_BATCH: u8 = 3;
+const TYPE_INT: u8 = 2;
+const TYPE_FLOATING_POINT: u8 = 3;
+const TYPE_UTF8: u8 = 5;
+const PRECISION_DOUBLE: i16 = 2;
+
+/// Arrow IPC の形式
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    /// ランダムアクセス可能なファイル形式（.arrow / .feather）
+    File,
+    /// ストリーミング形式（.arrows）
+    Stream,
+}
+
+/// 書き出し済みメッセージの位置（ファイル形式のフッターで使う）
+struct Block {
+    offset: i64,
+    metadata_length: i32,
+    body_length: i64,
+}
+
+/// 列の値の取り出し元
+#[derive(Clone, Copy)]
+enum Column {
+    Layer,
+    City,
+    Area,
+    Count,
+    /// `GroupResult::values` の位置（数値のプロパティの集計値と計算した列）
+    Value(usize),
+    /// `GroupResult::kept` の位置
+    Kept(usize),
+    Class,
+}
+
+/// 列の型
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Utf8,
+    Float64,
+    Int64,
+}
+
+impl Column {
+    fn kind(self) -> Kind {
+        match self {
+            Column::Layer | Column::City | Column::Kept(_) | Column::Class => Kind::Utf8,
+            Column::Area | Column::Value(_) => Kind::Float64,
+            Column::Count => Kind::Int64,
+        }
+    }
+
+    /// 値のない行がありうるか
+    fn nullable(self) -> bool {
+        matches!(self, Column::Value(_) | Column::Class)
+    }
+
+    fn text(self, row: &GroupResult) -> Option<&str> {
+        match self {
+            Column::Layer => Some(row.layer.as_deref().unwrap_or_default()),
+            Column::City => Some(&row.key),
+            Column::Kept(i) => row.kept.get(i).map(|(_, value)| value.as_str()),
+            Column::Class => row.class.as_deref(),
+            _ => None,
+        }
+    }
+
+    fn number(self, row: &GroupResult) -> Option<f64> {
+        match self {
+            Column::Area => Some(row.area),
+            Column::Value(i) => row.values.get(i).and_then(|(_, value)| *value),
+            _ => None,
+        }
+    }
+}
+
+/// 書き出す列と名前。CSV と同じ列（レイヤーごとに分けた場合の Layer、数値の集計値と計算した列、
+/// 引き継いだプロパティ、階級分けした場合の Class）に、集計した Feature の数の Count を加える
+fn columns(rows: &[GroupResult]) -> Vec<(String, Column)> {
+    let mut columns = Vec::new();
+    if rows.iter().any(|row| row.layer.is_some()) {
+        columns.push(("Layer".to_string(), Column::Layer));
+    }
+    columns.extend([
+        ("City".to_string(), Column::City),
+        ("Area".to_string(), Column::Area),
+        ("Count".to_string(), Column::Count),
+    ]);
+    if let Some(first) = rows.first() {
+        columns.extend(
+            first
+                .values
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| (name.clone(), Column::Value(i))),
+        );
+        columns.extend(
+            first
+                .kept
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| (name.clone(), Column::Kept(i))),
+        );
+    }
+    if rows.iter().any(|row| row.class.is_some()) {
+        columns.push(("Class".to_string(), Column::Class));
+    }
+    columns
+}
+
+/// 集計結果を Arrow IPC 形式（City: utf8, Area: float64, Count: int64 と `columns` の列）で出力する
+pub fn write(path: &str, format: Format, rows: &[GroupResult]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = Counting::new(BufWriter::new(File::create(path)?));
+    let columns = columns(rows);
+
+    if format == Format::File {
+        wtr.write_all(MAGIC)?;
+        wtr.write_all(&[0, 0])?;
+    }
+
+    write_message(&mut wtr, HEADER_SCHEMA, schema(&columns), &[])?;
+
+    let mut blocks = Vec::new();
+    for batch in rows.chunks(BATCH_ROWS) {
+        let (header, body) = record_batch(&columns, batch);
+        blocks.push(write_message(&mut wtr, HEADER_RECORD_BATCH, header, &body)?);
+    }
+
+    // ストリームの終端
+    wtr.write_all(&CONTINUATION.to_le_bytes())?;
+    wtr.write_all(&0u32.to_le_bytes())?;
+
+    if format == Format::File {
+        let footer = Table::new()
+            .field(0, Value::I16(METADATA_V5))
+            .field(1, Value::Table(schema(&columns)))
+            .field(2, Value::Structs(vec![]))
+            .field(3, Value::Structs(blocks.iter().map(encode_block).collect()))
+            .finish();
+        wtr.write_all(&footer)?;
+        wtr.write_all(&(footer.len() as i32).to_le_bytes())?;
+        wtr.write_all(MAGIC)?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 列定義
+fn schema(columns: &[(String, Column)]) -> Table {
+    let fields = columns
+        .iter()
+        .map(|(name, column)| {
+            let (type_type, type_table) = match column.kind() {
+                Kind::Utf8 => (TYPE_UTF8, Table::new()),
+                Kind::Float64 => (
+                    TYPE_FLOATING_POINT,
+                    Table::new().field(0, Value::I16(PRECISION_DOUBLE)),
+                ),
+                // 符号付きの 64 ビット整数
+                Kind::Int64 => (
+                    TYPE_INT,
+                    Table::new()
+                        .field(0, Value::I32(64))
+                        .field(1, Value::Bool(true)),
+                ),
+            };
+            Table::new()
+                .field(0, Value::String(name.clone()))
+                .field(1, Value::Bool(column.nullable()))
+                .field(2, Value::U8(type_type))
+                .field(3, Value::Table(type_table))
+                .field(5, Value::Tables(vec![]))
+        })
+        .collect();
+
+    Table::new()
+        .field(0, Value::I16(0)) // リトルエンディアン
+        .field(1, Value::Tables(fields))
+}
+
+/// レコードバッチのメタデータと本体を組み立てる
+fn record_batch(columns: &[(String, Column)], rows: &[GroupResult]) -> (Table, Vec<u8>) {
+    let mut body = Vec::new();
+    let mut buffers = Vec::new();
+    let mut nodes = Vec::new();
+    let mut push_buffer = |body: &mut Vec<u8>, bytes: &[u8]| {
+        buffers.push(((body.len() as i64), bytes.len() as i64));
+        body.extend_from_slice(bytes);
+        pad(body);
+    };
+
+    for &(_, column) in columns {
+        // 値のある行のビット（null がなければ空のバッファにする）
+        let valid: Vec<bool> = rows
+            .iter()
+            .map(|row| match column.kind() {
+                Kind::Utf8 => column.text(row).is_some(),
+                Kind::Float64 => column.number(row).is_some(),
+                Kind::Int64 => true,
+            })
+            .collect();
+        let nulls = valid.iter().filter(|valid| !**valid).count();
+        if nulls == 0 {
+            push_buffer(&mut body, &[]);
+        } else {
+            let mut bitmap = vec![0u8; rows.len().div_ceil(8)];
+            for (i, _) in valid.iter().enumerate().filter(|(_, valid)| **valid) {
+                bitmap[i / 8] |= 1 << (i % 8);
+            }
+            push_buffer(&mut body, &bitmap);
+        }
+        nodes.push(
+            [
+                (rows.len() as i64).to_le_bytes(),
+                (nulls as i64).to_le_bytes(),
+            ]
+            .concat(),
+        );
+
+        match column.kind() {
+            // オフセットと UTF-8 のデータ
+            Kind::Utf8 => {
+                let mut offsets = Vec::with_capacity((rows.len() + 1) * 4);
+                let mut data = Vec::new();
+                offsets.extend_from_slice(&0i32.to_le_bytes());
+                for row in rows {
+                    data.extend_from_slice(column.text(row).unwrap_or_default().as_bytes());
+                    offsets.extend_from_slice(&(data.len() as i32).to_le_bytes());
+                }
+                push_buffer(&mut body, &offsets);
+                push_buffer(&mut body, &data);
+            }
+            Kind::Float64 => {
+                let values: Vec<u8> = rows
+                    .iter()
+                    .flat_map(|row| column.number(row).unwrap_or_default().to_le_bytes())
+                    .collect();
+                push_buffer(&mut body, &values);
+            }
+            Kind::Int64 => {
+                let values: Vec<u8> = rows
+                    .iter()
+                    .flat_map(|row| (row.count as i64).to_le_bytes())
+                    .collect();
+                push_buffer(&mut body, &values);
+            }
+        }
+    }
+
+    let length = rows.len() as i64;
+    let header = Table::new()
+        .field(0, Value::I64(length))
+        .field(1, Value::Structs(nodes))
+        .field(
+            2,
+            Value::Structs(
+                buffers
+                    .iter()
+                    .map(|(offset, len)| [offset.to_le_bytes(), len.to_le_bytes()].concat())
+                    .collect(),
+            ),
+        );
+    (header, body)
+}
+
+/// メッセージを「継続マーカー・メタデータ長・メタデータ・本体」の順に書き出す
+fn write_message<W: Write>(
+    wtr: &mut Counting<W>,
+    header_type: u8,
+    header: Table,
+    body: &[u8],
+) -> std::io::Result<Block> {
+    let offset = wtr.position as i64;
+    let metadata = Table::new()
+        .field(0, Value::I16(METADATA_V5))
+        .field(1, Value::U8(header_type))
+        .field(2, Value::Table(header))
+        .field(3, Value::I64(body.len() as i64))
+        .finish();
+
+    wtr.write_all(&CONTINUATION.to_le_bytes())?;
+    wtr.write_all(&(metadata.len() as i32).to_le_bytes())?;
+    wtr.write_all(&metadata)?;
+    wtr.write_all(body)?;
+
+    Ok(Block {
+        offset,
+        metadata_length: 8 + metadata.len() as i32,
+        body_length: body.len() as i64,
+    })
+}
+
+fn encode_block(block: &Block) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(24);
+    bytes.extend_from_slice(&block.offset.to_le_bytes());
+    bytes.extend_from_slice(&block.metadata_length.to_le_bytes());
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&block.body_length.to_le_bytes());
+    bytes
+}
+
+/// 本体のバッファは 8 バイト境界に揃える
+fn pad(body: &mut Vec<u8>) {
+    while !body.len().is_multiple_of(8) {
+        body.push(0);
+    }
+}
+
+/// 書き込んだバイト数を数える Writer（フッターに各メッセージの位置を記録するため）
+struct Counting<W: Write> {
+    inner: W,
+    position: usize,
+}
+
+impl<W: Write> Counting<W> {
+    fn new(inner: W) -> Self {
+        Counting { inner, position: 0 }
+    }
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.position += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::{
+        array::{Array, AsArray},
+        datatypes::{DataType, Float64Type, Int64Type},
+        ipc::reader::{FileReader, StreamReader},
+        record_batch::RecordBatch,
+    };
+
+    fn row(
+        key: &str,
+        area: f64,
+        count: usize,
+        mean: Option<f64>,
+        class: Option<&str>,
+    ) -> GroupResult {
+        GroupResult {
+            key: key.to_string(),
+            layer: Some("N03".to_string()),
+            area,
+            count,
+            geometry: None,
+            ids: Vec::new(),
+            values: vec![
+                ("pop_mean".to_string(), mean),
+                ("density".to_string(), Some(area * 2.0)),
+            ],
+            kept: vec![("N03_001".to_string(), "埼玉県".to_string())],
+            class: class.map(str::to_string),
+        }
+    }
+
+    fn check(batch: &RecordBatch) {
+        let schema = batch.schema();
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(
+            names,
+            ["Layer", "City", "Area", "Count", "pop_mean", "density", "N03_001", "Class"]
+        );
+        assert_eq!(schema.field(3).data_type(), &DataType::Int64);
+        assert_eq!(batch.num_rows(), 2);
+        let city = batch.column(1).as_string::<i32>();
+        assert_eq!((city.value(0), city.value(1)), ("川越市", "所沢市"));
+        let count = batch.column(3).as_primitive::<Int64Type>();
+        assert_eq!((count.value(0), count.value(1)), (3, 1));
+        let mean = batch.column(4).as_primitive::<Float64Type>();
+        assert_eq!(mean.value(0), 12.5);
+        assert!(mean.is_null(1));
+        let density = batch.column(5).as_primitive::<Float64Type>();
+        assert_eq!(density.value(1), 4.0);
+        assert_eq!(batch.column(6).as_string::<i32>().value(1), "埼玉県");
+        let class = batch.column(7).as_string::<i32>();
+        assert_eq!(class.value(0), "high");
+        assert!(class.is_null(1));
+    }
+
+    #[test]
+    fn every_column_can_be_read_back() {
+        let rows = [
+            row("川越市", 10.0, 3, Some(12.5), Some("high")),
+            row("所沢市", 2.0, 1, None, None),
+        ];
+        for format in [Format::File, Format::Stream] {
+            let path = std::env::temp_dir().join(format!(
+                "layon-arrow-{}-{}.arrow",
+                format == Format::File,
+                std::process::id()
+            ));
+            let path = path.to_str().unwrap();
+            write(path, format, &rows).unwrap();
+            let file = File::open(path).unwrap();
+            let batches: Vec<RecordBatch> = match format {
+                Format::File => FileReader::try_new(file, None)
+                    .unwrap()
+                    .collect::<Result<_, _>>()
+                    .unwrap(),
+                Format::Stream => StreamReader::try_new(file, None)
+                    .unwrap()
+                    .collect::<Result<_, _>>()
+                    .unwrap(),
+            };
+            assert_eq!(batches.len(), 1);
+            check(&batches[0]);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! ディゾルブした境界、集計結果、小さな地図のビューアーを 1 つの HTML ファイルにまとめた出力（`prefectures -o result.html`）。
+//!
+//! 分析の結果を保存したり人に渡したりするときに、ファイルが 1 つで、ブラウザーで開くだけで見られるようにする。
+//! データは `<script type="application/json" id="layon-data">` に JSON のまま埋め込むので、
+//! HTML から取り出して別のツールで読むこともできる（ビューアーからも GeoJSON と CSV で保存できる）。
+//! 外部のスクリプトや地図タイルは読み込まない。
+
+use crate::{aggregate::GroupResult, markup::escape, time, transform::GeometryTransform};
+use geojson::FeatureCollection;
+use serde::Serialize;
+use std::{error::Error, fs, time::SystemTime};
+
+/// 結果に添える説明
+pub struct Summary {
+    /// ページの見出し
+    pub title: String,
+    /// 入力元
+    pub input: String,
+    /// 集計キーのプロパティ
+    pub group_by: String,
+    /// 集計した値（単位も含めた説明）
+    pub metric: String,
+    /// 境界を簡略化した許容誤差
+    pub simplify: f64,
+}
+
+/// 埋め込む JSON
+#[derive(Serialize)]
+struct Bundle<'a> {
+    title: &'a str,
+    version: &'static str,
+    created_at: String,
+    input: &'a str,
+    group_by: &'a str,
+    metric: &'a str,
+    simplify: f64,
+    /// 集計結果（ジオメトリのないグループも含む）
+    rows: Vec<Row<'a>>,
+    /// ディゾルブした境界（プロパティは name, area, count）
+    boundaries: FeatureCollection,
+}
+
+#[derive(Serialize)]
+struct Row<'a> {
+    name: &'a str,
+    area: f64,
+    count: usize,
+}
+
+/// `rows` とそのディゾルブしたジオメトリを 1 つの HTML ファイルに書き出す
+pub fn write(
+    path: &str,
+    rows: &[GroupResult],
+    transform: &GeometryTransform,
+    summary: &Summary,
+) -> Result<(), Box<dyn Error>> {
+    let mut boundaries = super::geojson::dissolved(rows);
+    transform.apply_all(&mut boundaries);
+    let bundle = Bundle {
+        title: &summary.title,
+        version: env!("CARGO_PKG_VERSION"),
+        created_at: time::rfc3339(SystemTime::now()),
+        input: &summary.input,
+        group_by: &summary.group_by,
+        metric: &summary.metric,
+        simplify: summary.simplify,
+        rows: rows
+            .iter()
+            .map(|row| Row {
+                name: &row.key,
+                area: row.area,
+                count: row.count,
+            })
+            .collect(),
+        boundaries,
+    };
+    // JSON の中の < をエスケープして、値に </script> などがあっても script 要素が終わらないようにする
+    let data = serde_json::to_string(&bundle)?.replace('<', "\\u003c");
+    let page = TEMPLATE
+        .replace("{{title}}", &escape(&summary.title))
+        .replace("{{data}}", &data);
+    fs::write(path, page)?;
+    Ok(())
+}
+
+/// ビューアー。地図は経度・緯度を中央の緯度で正距円筒に投影した SVG で、面積の五分位で塗り分ける
+const TEMPLATE: &str = r##"<!DOCTYPE html>
+<html lang="ja">
+<head>
+<meta charset="utf-8">
+<title>{{title}}</title>
+<style>
+body { font-family: sans-serif; margin: 1em; color: #222; }
+main { display: flex; flex-wrap: wrap; gap: 1em; }
+svg { flex: 1 1 480px; max-height: 80vh; border: 1px solid #ccc; background: #f6f8fa; }
+path { stroke: #555; stroke-width: 0.5; vector-effect: non-scaling-stroke; }
+path.selected, tr.selected td { outline: 2px solid #d33; }
+tr.selected td { background: #fee; }
+section { flex: 1 1 320px; max-height: 80vh; overflow: auto; }
+table { border-collapse: collapse; width: 100%; }
+th { cursor: pointer; position: sticky; top: 0; background: #eee; }
+th, td { padding: 2px 6px; border-bottom: 1px solid #ddd; }
+td.number { text-align: right; font-variant-numeric: tabular-nums; }
+dl { display: grid; grid-template-columns: max-content auto; gap: 0 1em; font-size: 90%; }
+dd { margin: 0; }
+</style>
+</head>
+<body>
+<h1>{{title}}</h1>
+<dl id="summary"></dl>
+<p><a id="geojson" download="boundaries.geojson">GeoJSON で保存</a> ・ <a id="csv" download="rows.csv">CSV で保存</a></p>
+<main>
+<svg id="map" xmlns="http://www.w3.org/2000/svg"></svg>
+<section>
+<table id="rows"><thead><tr><th data-key="name">名前</th><th data-key="area">面積</th><th data-key="count">Feature の数</th></tr></thead><tbody></tbody></table>
+</section>
+</main>
+<script type="application/json" id="layon-data">{{data}}</script>
+<script>
+(function () {
+  var data = JSON.parse(document.getElementById("layon-data").textContent);
+  var SVG = "http://www.w3.org/2000/svg";
+  var COLORS = ["#edf8e9", "#bae4b3", "#74c476", "#31a354", "#006d2c"];
+
+  var summary = document.getElementById("summary");
+  [["入力元", data.input], ["集計キー", data.group_by], ["集計値", data.metric],
+   ["簡略化の許容誤差", data.simplify], ["作成日時", data.created_at], ["layon", data.version]]
+    .forEach(function (item) {
+      var dt = document.createElement("dt"), dd = document.createElement("dd");
+      dt.textContent = item[0];
+      dd.textContent = item[1];
+      summary.appendChild(dt);
+      summary.appendChild(dd);
+    });
+
+  function link(id, text, type) {
+    document.getElementById(id).href = URL.createObjectURL(new Blob([text], { type: type }));
+  }
+  link("geojson", JSON.stringify(data.boundaries), "application/geo+json");
+  link("csv", "Name,Area,Count\n" + data.rows.map(function (row) {
+    return ['"' + row.name.replace(/"/g, '""') + '"', row.area, row.count].join(",");
+  }).join("\n") + "\n", "text/csv");
+
+  // 面積の五分位
+  var areas = data.rows.map(function (row) { return row.area; }).sort(function (a, b) { return a - b; });
+  function color(area) {
+    var rank = 0;
+    while (rank < areas.length && areas[rank] < area) rank++;
+    return COLORS[Math.min(COLORS.length - 1, Math.floor(rank * COLORS.length / Math.max(areas.length, 1)))];
+  }
+
+  // ポリゴンのリングの一覧
+  function rings(geometry) {
+    if (!geometry) return [];
+    if (geometry.type === "Polygon") return geometry.coordinates;
+    if (geometry.type === "MultiPolygon") return [].concat.apply([], geometry.coordinates);
+    if (geometry.type === "GeometryCollection") return [].concat.apply([], geometry.geometries.map(rings));
+    return [];
+  }
+  var features = data.boundaries.features;
+  var west = Infinity, east = -Infinity, south = Infinity, north = -Infinity;
+  features.forEach(function (feature) {
+    rings(feature.geometry).forEach(function (ring) {
+      ring.forEach(function (c) {
+        west = Math.min(west, c[0]); east = Math.max(east, c[0]);
+        south = Math.min(south, c[1]); north = Math.max(north, c[1]);
+      });
+    });
+  });
+  var scale = Math.cos((south + north) / 2 * Math.PI / 180) || 1;
+  var map = document.getElementById("map");
+  if (west <= east) {
+    map.setAttribute("viewBox", [west * scale, -north, (east - west) * scale || 1, (north - south) || 1].join(" "));
+  }
+
+  var paths = {}, cells = {};
+  function select(name) {
+    Object.keys(paths).forEach(function (key) { paths[key].classList.toggle("selected", key === name); });
+    Object.keys(cells).forEach(function (key) { cells[key].classList.toggle("selected", key === name); });
+    if (cells[name]) cells[name].scrollIntoView({ block: "nearest" });
+  }
+  features.forEach(function (feature) {
+    var properties = feature.properties;
+    var d = rings(feature.geometry).map(function (ring) {
+      return "M" + ring.map(function (c) { return (c[0] * scale) + "," + (-c[1]); }).join("L") + "Z";
+    }).join("");
+    var path = document.createElementNS(SVG, "path");
+    path.setAttribute("d", d);
+    path.setAttribute("fill", color(properties.area));
+    path.setAttribute("fill-rule", "evenodd");
+    var title = document.createElementNS(SVG, "title");
+    title.textContent = properties.name + ": " + properties.area;
+    path.appendChild(title);
+    path.addEventListener("click", function () { select(properties.name); });
+    map.appendChild(path);
+    paths[properties.name] = path;
+  });
+
+  var body = document.querySelector("#rows tbody");
+  function render(rows) {
+    body.textContent = "";
+    rows.forEach(function (row) {
+      var tr = document.createElement("tr");
+      [row.name, row.area, row.count].forEach(function (value, i) {
+        var td = document.createElement("td");
+        td.textContent = value;
+        if (i > 0) td.className = "number";
+        tr.appendChild(td);
+      });
+      tr.addEventListener("click", function () { select(row.name); });
+      body.appendChild(tr);
+      cells[row.name] = tr;
+    });
+  }
+  var order = { key: "area", descending: true };
+  document.querySelectorAll("#rows th").forEach(function (th) {
+    th.addEventListener("click", function () {
+      var key = th.dataset.key;
+      order = { key: key, descending: order.key === key ? !order.descending : key !== "name" };
+      render(data.rows.slice().sort(function (a, b) {
+        var c = key === "name" ? a.name.localeCompare(b.name) : a[key] - b[key];
+        return order.descending ? -c : c;
+      }));
+    });
+  });
+  render(data.rows);
+})();
+</script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aggregate::Metric,
+        source::{Input, InputOptions},
+    };
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    #[test]
+    fn embeds_boundaries_and_rows_as_json_in_one_html_file() {
+        let input = Input::parse(FIXTURE, InputOptions::default()).unwrap();
+        let rows =
+            crate::prefecture::dissolve(input, "N03_004", Metric::GeodesicArea, 0.001).unwrap();
+        let output = std::env::temp_dir().join(format!("layon-bundle-{}.html", std::process::id()));
+        let summary = Summary {
+            title: "埼玉県 <抜粋>".to_string(),
+            input: FIXTURE.to_string(),
+            group_by: "N03_004".to_string(),
+            metric: "楕円体上の面積 (km²)".to_string(),
+            simplify: 0.001,
+        };
+        write(
+            output.to_str().unwrap(),
+            &rows,
+            &GeometryTransform::default(),
+            &summary,
+        )
+        .unwrap();
+
+        let page = fs::read_to_string(&output).unwrap();
+        fs::remove_file(&output).unwrap();
+        assert!(page.contains("<title>埼玉県 &lt;抜粋&gt;</title>"));
+        let start = page.find(r#"id="layon-data">"#).unwrap() + r#"id="layon-data">"#.len();
+        let end = start + page[start..].find("</script>").unwrap();
+        // 埋め込んだ JSON には < がそのまま現れない
+        assert!(!page[start..end].contains('<'));
+        let data: serde_json::Value = serde_json::from_str(&page[start..end]).unwrap();
+        assert_eq!(data["title"], "埼玉県 <抜粋>");
+        assert_eq!(data["group_by"], "N03_004");
+        assert_eq!(data["rows"].as_array().unwrap().len(), rows.len());
+        let boundaries: FeatureCollection =
+            serde_json::from_value(data["boundaries"].clone()).unwrap();
+        assert_eq!(boundaries.features.len(), 8);
+        for (feature, row) in boundaries.features.iter().zip(&rows) {
+            assert_eq!(feature.property("name").unwrap(), row.key.as_str());
+            let area = feature.property("area").unwrap().as_f64().unwrap();
+            assert!((area - row.area).abs() <= 1e-12 * row.area);
+        }
+    }
+}
@@ -0,0 +1,783 @@
+use super::number::NumberFormat;
+use crate::{
+    aggregate::GroupResult,
+    approx::EstimateRow,
+    audit,
+    batch::{FileResult, Outcome},
+    breaks::ClassBreak,
+    classify::ClassTotal,
+    compare::MethodRow,
+    dedup::Duplicate,
+    extent::ExtentRow,
+    h3::H3Row,
+    holes::RingArea,
+    hull::HullRow,
+    label::LabelRow,
+    locate::Location,
+    mesh::MeshRow,
+    overlay::OverlayRow,
+    pivot::Crosstab,
+    source::Layer,
+    timeseries::TimeSeriesRow,
+    verify::VerifyRow,
+    zonal::{BandRow, ZonalRow},
+};
+use csv::{QuoteStyle, Terminator, Writer, WriterBuilder};
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, Write},
+};
+
+/// 集計結果（`--output` の CSV）の書式
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Format {
+    /// 面積などの数値の書き方
+    pub numbers: NumberFormat,
+    /// 区切り文字や引用符などの CSV の方言
+    pub dialect: Dialect,
+}
+
+impl Format {
+    /// 数値の区切り文字と CSV の区切り文字の組み合わせを確かめる
+    pub fn check(&self) -> Result<(), String> {
+        self.numbers.check()?;
+        let delimiter = self.dialect.delimiter as char;
+        if self.dialect.quote == Quote::Never
+            && (self.numbers.decimal_mark == delimiter || self.numbers.thousands == Some(delimiter))
+        {
+            return Err(format!(
+                "--quote never では、数値の区切り文字や小数点に CSV の区切り文字 ({}) は使えません",
+                delimiter.escape_default()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// CSV の方言（`--delimiter`, `--quote`, `--line-ending`, `--bom`）。
+/// 日本語版の Excel は BOM のない UTF-8 を Shift_JIS として読むため、`--bom` を付けると文字化けせずに開ける
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dialect {
+    /// 区切り文字（ASCII の 1 文字）
+    pub delimiter: u8,
+    pub quote: Quote,
+    /// 行の終わりを CRLF にする
+    pub crlf: bool,
+    /// 先頭に UTF-8 の BOM を書く
+    pub bom: bool,
+}
+
+impl Default for Dialect {
+    fn default() -> Dialect {
+        Dialect {
+            delimiter: b',',
+            quote: Quote::Necessary,
+            crlf: false,
+            bom: false,
+        }
+    }
+}
+
+/// 値を引用符で囲む場合
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quote {
+    /// 区切り文字や引用符、改行を含む値だけ
+    Necessary,
+    /// すべての値
+    Always,
+    /// 数値でない値
+    NonNumeric,
+    /// 囲まない（区切り文字を含む値があると読み戻せない）
+    Never,
+}
+
+impl Quote {
+    /// `--quote` の値
+    pub fn parse(name: &str) -> Result<Quote, String> {
+        match name {
+            "necessary" => Ok(Quote::Necessary),
+            "always" => Ok(Quote::Always),
+            "non-numeric" => Ok(Quote::NonNumeric),
+            "never" => Ok(Quote::Never),
+            _ => Err(format!("不明な引用符の付け方です: {}", name)),
+        }
+    }
+}
+
+impl Dialect {
+    /// `--delimiter` の値（ASCII の 1 文字か、タブなら `tab` または `\t`）
+    pub fn parse_delimiter(value: &str) -> Result<u8, String> {
+        match value {
+            "tab" | "\\t" | "\t" => Ok(b'\t'),
+            _ => match value.as_bytes() {
+                [byte] if byte.is_ascii() && !matches!(byte, b'"' | b'\r' | b'\n') => Ok(*byte),
+                _ => Err(format!(
+                    "--delimiter には引用符と改行以外の ASCII の 1 文字か tab を指定してください: {}",
+                    value
+                )),
+            },
+        }
+    }
+
+    /// この方言で書き込む Writer（BOM は書かない）
+    fn writer<W: Write>(&self, out: W) -> Writer<W> {
+        WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .quote_style(match self.quote {
+                Quote::Necessary => QuoteStyle::Necessary,
+                Quote::Always => QuoteStyle::Always,
+                Quote::NonNumeric => QuoteStyle::NonNumeric,
+                Quote::Never => QuoteStyle::Never,
+            })
+            .terminator(if self.crlf {
+                Terminator::CRLF
+            } else {
+                Terminator::Any(b'\n')
+            })
+            .from_writer(out)
+    }
+
+    /// ファイルを作り、必要なら BOM を書いてから、この方言で書き込む Writer を返す
+    fn create(&self, path: &str) -> Result<Writer<File>, Box<dyn Error>> {
+        let mut file = File::create(path)?;
+        if self.bom {
+            file.write_all("\u{feff}".as_bytes())?;
+        }
+        Ok(self.writer(file))
+    }
+}
+
+/// 集計結果を CSV に出力する（グループが多くても速いように、行は並列に書式化する）
+pub fn write(path: &str, rows: &[GroupResult], format: &Format) -> Result<(), Box<dyn Error>> {
+    let mut wtr = format.dialect.create(path)?;
+    // レイヤーごとに分けて集計した場合は先頭にレイヤーの列を置く
+    let layered = rows.iter().any(|row| row.layer.is_some());
+    // 数値のプロパティの集計値は面積の後ろの列に（値を持つ Feature がなければ空欄）
+    let mut header = if layered {
+        vec!["Layer", "City", "Area"]
+    } else {
+        vec!["City", "Area"]
+    };
+    if let Some(first) = rows.first() {
+        header.extend(first.values.iter().map(|(name, _)| name.as_str()));
+        // 引き継いだプロパティの値はその後ろに、プロパティの名前の列で
+        header.extend(first.kept.iter().map(|(name, _)| name.as_str()));
+    }
+    let classified = rows.iter().any(|row| row.class.is_some());
+    if classified {
+        header.push("Class");
+    }
+    wtr.write_record(&header)?;
+    wtr.flush()?;
+
+    let mut file = wtr.into_inner().map_err(|err| err.into_error())?;
+    super::write_ordered(&mut file, rows, |chunk, _| {
+        let mut wtr = format.dialect.writer(Vec::new());
+        for row in chunk {
+            // 算出される面積は正確ではないが、並列処理の勉強用なので許容
+            let number = |value: f64| format.numbers.format(value);
+            let mut record = Vec::new();
+            if layered {
+                record.push(row.layer.clone().unwrap_or_default());
+            }
+            record.extend([row.key.clone(), number(row.area)]);
+            record.extend(
+                row.values
+                    .iter()
+                    .map(|(_, value)| value.map(number).unwrap_or_default()),
+            );
+            record.extend(row.kept.iter().map(|(_, value)| value.clone()));
+            if classified {
+                record.push(row.class.clone().unwrap_or_default());
+            }
+            wtr.write_record(&record)?;
+        }
+        wtr.into_inner().map_err(|err| err.into_error())
+    })?;
+    Ok(())
+}
+
+/// 2 つのレイヤーの交差面積を縦持ちの CSV に出力する
+pub(crate) fn write_overlay(path: &str, rows: &[OverlayRow]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = Writer::from_path(path)?;
+    wtr.write_record(["City", "Zone", "Area"])?;
+
+    for row in rows {
+        wtr.write_record([
+            row.city.as_str(),
+            row.zone.as_str(),
+            row.area.to_string().as_str(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 画素値の統計を CSV に出力する（画素がない場合、平均・最小・最大は空欄）
+pub(crate) fn write_zonal(path: &str, rows: &[ZonalRow]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = Writer::from_path(path)?;
+    wtr.write_record(["City", "Count", "Sum", "Mean", "Min", "Max"])?;
+
+    for row in rows {
+        let (mean, min, max) = match row.mean() {
+            Some(mean) => (mean.to_string(), row.min.to_string(), row.max.to_string()),
+            None => Default::default(),
+        };
+        wtr.write_record([
+            row.city.as_str(),
+            row.count.to_string().as_str(),
+            row.sum.to_string().as_str(),
+            mean.as_str(),
+            min.as_str(),
+            max.as_str(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 画素値のヒストグラムを縦持ちの CSV に出力する
+pub(crate) fn write_histogram(path: &str, rows: &[ZonalRow]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = Writer::from_path(path)?;
+    wtr.write_record(["City", "Value", "Count"])?;
+
+    for row in rows {
+        for (value, count) in &row.histogram {
+            wtr.write_record([
+                row.city.as_str(),
+                value.to_string().as_str(),
+                count.to_string().as_str(),
+            ])?;
+        }
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 集計キーと値の帯ごとの画素数と面積を CSV に出力する
+pub(crate) fn write_bands(path: &str, rows: &[BandRow]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = Writer::from_path(path)?;
+    wtr.write_record(["City", "Band", "Pixels", "Area"])?;
+
+    for row in rows {
+        wtr.write_record([
+            row.city.as_str(),
+            row.band.as_str(),
+            row.pixels.to_string().as_str(),
+            row.area.to_string().as_str(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 範囲と重心を CSV に出力する（`path` が "-" なら標準出力）。全体の行の Name は空欄
+pub(crate) fn write_extents(path: &str, rows: &[ExtentRow]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = open(path)?;
+    wtr.write_record([
+        "Name",
+        "MinX",
+        "MinY",
+        "MaxX",
+        "MaxY",
+        "CentroidX",
+        "CentroidY",
+    ])?;
+
+    for row in rows {
+        let (min, max) = (row.rect.min(), row.rect.max());
+        wtr.write_record([
+            row.name.clone().unwrap_or_default(),
+            min.x.to_string(),
+            min.y.to_string(),
+            max.x.to_string(),
+            max.y.to_string(),
+            row.centroid.x.to_string(),
+            row.centroid.y.to_string(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 入力のレイヤーの一覧を CSV に出力する（`path` が "-" なら標準出力。わからない値は空欄）
+pub(crate) fn write_layers(path: &str, layers: &[Layer]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = open(path)?;
+    wtr.write_record(["Layer", "Features", "Geometry"])?;
+
+    for layer in layers {
+        wtr.write_record([
+            layer.name.clone(),
+            layer.features.map(|n| n.to_string()).unwrap_or_default(),
+            layer.geometry.clone().unwrap_or_default(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// ラベルの位置を CSV に出力する（`path` が "-" なら標準出力）
+pub(crate) fn write_labels(path: &str, rows: &[LabelRow]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = open(path)?;
+    wtr.write_record(["Name", "X", "Y", "Distance"])?;
+
+    for row in rows {
+        wtr.write_record([
+            row.name.clone(),
+            row.point.x.to_string(),
+            row.point.y.to_string(),
+            row.distance.to_string(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// グループごとの包む形の面積と充填率を CSV に出力する（充填率が求まらなければ空）
+pub(crate) fn write_hulls(path: &str, rows: &[HullRow]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = open(path)?;
+    wtr.write_record(["Name", "Area", "HullArea", "FillRatio"])?;
+
+    for row in rows {
+        wtr.write_record([
+            row.name.clone(),
+            row.area.to_string(),
+            row.hull_area.to_string(),
+            row.fill_ratio()
+                .map(|ratio| ratio.to_string())
+                .unwrap_or_default(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// ディゾルブした都道府県などの面積を CSV に出力する（`path` が "-" なら標準出力）
+pub(crate) fn write_dissolved(path: &str, rows: &[GroupResult]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = open(path)?;
+    wtr.write_record(["Name", "Area", "Count"])?;
+
+    for row in rows {
+        wtr.write_record([row.key.clone(), row.area.to_string(), row.count.to_string()])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 集計キー × メッシュコードごとの面積を CSV に出力する（`path` が "-" なら標準出力）
+pub(crate) fn write_mesh(path: &str, rows: &[MeshRow]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = open(path)?;
+    wtr.write_record(["City", "Mesh", "Area"])?;
+
+    for row in rows {
+        wtr.write_record([row.city.as_str(), row.mesh.as_str(), &row.area.to_string()])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 集計キー × H3 のセルごとの面積を CSV に出力する（`path` が "-" なら標準出力）
+pub(crate) fn write_h3(path: &str, rows: &[H3Row]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = open(path)?;
+    wtr.write_record(["City", "H3", "Area"])?;
+
+    for row in rows {
+        wtr.write_record([row.city.as_str(), row.cell.as_str(), &row.area.to_string()])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 点の検索結果を CSV に出力する（見つからなかった点はプロパティと距離が空欄）
+pub(crate) fn write_locations(
+    path: &str,
+    columns: &[String],
+    rows: &[Location],
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = open(path)?;
+    let mut header = vec!["X", "Y"];
+    header.extend(columns.iter().map(String::as_str));
+    header.push("Distance");
+    wtr.write_record(&header)?;
+
+    for row in rows {
+        let mut record = vec![row.point.x().to_string(), row.point.y().to_string()];
+        record.extend(row.values.iter().cloned());
+        record.push(row.distance.map(|d| d.to_string()).unwrap_or_default());
+        wtr.write_record(&record)?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 取り除いた重複した Feature の一覧を CSV に出力する（位置は入力の Feature の 0 始まりの番号）
+pub(crate) fn write_duplicates(path: &str, rows: &[Duplicate]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = open(path)?;
+    wtr.write_record(["Index", "Id", "Original", "OriginalId", "Name"])?;
+
+    for row in rows {
+        wtr.write_record([
+            row.index.to_string(),
+            row.id.clone(),
+            row.original.to_string(),
+            row.original_id.clone(),
+            row.name.clone().unwrap_or_default(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Feature ごとの外周と穴の面積の内訳を CSV に出力する
+pub(crate) fn write_rings(path: &str, rows: &[RingArea]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = open(path)?;
+    wtr.write_record([
+        "Id", "City", "Polygons", "Holes", "Exterior", "HoleArea", "Net",
+    ])?;
+
+    for row in rows {
+        wtr.write_record([
+            row.id.clone(),
+            row.key.clone(),
+            row.polygons.to_string(),
+            row.holes.to_string(),
+            row.exterior.to_string(),
+            row.hole_area.to_string(),
+            row.net().to_string(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 前処理で外した Feature とジオメトリを変えた Feature の記録を CSV に出力する（面積がなければ空）
+pub fn write_audit(path: &str, entries: &[audit::Entry]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = open(path)?;
+    wtr.write_record([
+        "Id",
+        "City",
+        "Action",
+        "Reason",
+        "AreaBefore",
+        "AreaAfter",
+        "AreaChange",
+    ])?;
+
+    for entry in entries {
+        let after = entry
+            .area_after
+            .map(|area| area.to_string())
+            .unwrap_or_default();
+        let change = entry
+            .area_after
+            .map(|area| (area - entry.area_before).to_string())
+            .unwrap_or_default();
+        wtr.write_record([
+            entry.id.clone(),
+            entry.key.clone(),
+            entry.action.to_string(),
+            entry.reason.clone(),
+            entry.area_before.to_string(),
+            after,
+            change,
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// グループごとに集計した Feature の ID を CSV に出力する（1 行に 1 つの Feature）
+pub(crate) fn write_ids(path: &str, rows: &[GroupResult]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = open(path)?;
+    wtr.write_record(["City", "Id"])?;
+
+    for row in rows {
+        for id in &row.ids {
+            wtr.write_record([row.key.as_str(), id.as_str()])?;
+        }
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 面積の比較結果を CSV に出力する（片方にしかない市町村は値のない列が空欄）
+pub(crate) fn write_verification(path: &str, rows: &[VerifyRow]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = Writer::from_path(path)?;
+    wtr.write_record(["City", "Computed", "Official", "Error", "RelativeError"])?;
+
+    let text = |value: Option<f64>| value.map(|v| v.to_string()).unwrap_or_default();
+    for row in rows {
+        wtr.write_record([
+            row.city.clone(),
+            text(row.computed),
+            text(row.official),
+            text(row.error()),
+            text(row.relative_error()),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 年次ごとの面積を横持ちの CSV に出力する（その年次にない市町村は空欄）
+pub(crate) fn write_timeseries(
+    path: &str,
+    labels: &[&str],
+    rows: &[TimeSeriesRow],
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = Writer::from_path(path)?;
+    let mut header = vec!["City"];
+    header.extend(labels);
+    header.push("Changes");
+    wtr.write_record(&header)?;
+
+    for row in rows {
+        let mut record = vec![row.city.clone()];
+        record.extend(
+            row.areas
+                .iter()
+                .map(|area| area.map(|a| a.to_string()).unwrap_or_default()),
+        );
+        record.push(row.changes.join("; "));
+        wtr.write_record(&record)?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// batch で集計できたファイルの集計結果を、入力元の列を付けて CSV に出力する
+pub(crate) fn write_batch(path: &str, files: &[FileResult]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = open(path)?;
+    wtr.write_record(["Source", "City", "Area", "Count"])?;
+
+    for file in files {
+        let Outcome::Done(rows) = &file.outcome else {
+            continue;
+        };
+        for row in rows {
+            wtr.write_record([
+                file.source.as_str(),
+                &row.key,
+                &row.area.to_string(),
+                &row.count.to_string(),
+            ])?;
+        }
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 階級の境界を CSV に出力する（階級は 1 始まり）
+pub(crate) fn write_breaks(path: &str, classes: &[ClassBreak]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = open(path)?;
+    wtr.write_record(["Class", "Lower", "Upper", "Groups"])?;
+
+    for (i, class) in classes.iter().enumerate() {
+        wtr.write_record([
+            (i + 1).to_string(),
+            class.lower.to_string(),
+            class.upper.to_string(),
+            class.groups.to_string(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 階級ごとの小計を CSV に出力する
+pub(crate) fn write_class_totals(path: &str, totals: &[ClassTotal]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = Writer::from_path(path)?;
+    wtr.write_record(["Class", "Groups", "Count", "Area"])?;
+
+    for total in totals {
+        wtr.write_record([
+            total.class.as_str(),
+            total.groups.to_string().as_str(),
+            total.count.to_string().as_str(),
+            total.area.to_string().as_str(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// クロス集計の表を横持ちの CSV に出力する（その組の Feature がなければ空欄。最後の行は列ごとの合計）
+pub(crate) fn write_crosstab(
+    path: &str,
+    rows: &str,
+    crosstab: &Crosstab,
+    format: &Format,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = format.dialect.create(path)?;
+    let mut header = vec![rows];
+    header.extend(crosstab.columns.iter().map(String::as_str));
+    header.push("Total");
+    wtr.write_record(&header)?;
+
+    let number = |value: f64| format.numbers.format(value);
+    for row in &crosstab.rows {
+        let mut record = vec![row.key.clone()];
+        record.extend(
+            row.values
+                .iter()
+                .map(|value| value.map(number).unwrap_or_default()),
+        );
+        record.push(number(row.total));
+        wtr.write_record(&record)?;
+    }
+    let mut totals = vec!["Total".to_string()];
+    totals.extend(crosstab.totals.iter().map(|&total| number(total)));
+    totals.push(number(crosstab.total));
+    wtr.write_record(&totals)?;
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 平面上の面積と楕円体上の面積の比較結果を CSV に出力する（相対差を求められない行は空欄）
+pub(crate) fn write_methods(
+    path: &str,
+    rows: &[MethodRow],
+    format: &Format,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = format.dialect.create(path)?;
+    wtr.write_record([
+        "City",
+        "Planar",
+        "Geodesic",
+        "Difference",
+        "RelativeDifference",
+    ])?;
+
+    let number = |value: f64| format.numbers.format(value);
+    for row in rows {
+        wtr.write_record([
+            row.key.clone(),
+            number(row.planar),
+            number(row.geodesic),
+            number(row.difference()),
+            row.relative_difference().map(number).unwrap_or_default(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 抽出した Feature から推定した概算を CSV に出力する（推定値と 95% 信頼区間、抽出した Feature の数）
+pub(crate) fn write_estimates(
+    path: &str,
+    rows: &[EstimateRow],
+    format: &Format,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = format.dialect.create(path)?;
+    wtr.write_record([
+        "City",
+        "EstimatedArea",
+        "AreaLow",
+        "AreaHigh",
+        "EstimatedCount",
+        "CountLow",
+        "CountHigh",
+        "SampledCount",
+    ])?;
+
+    let number = |value: f64| format.numbers.format(value);
+    for row in rows {
+        let (area_low, area_high) = row.area_interval();
+        let (count_low, count_high) = row.count_interval();
+        wtr.write_record([
+            row.key.clone(),
+            number(row.area),
+            number(area_low),
+            number(area_high),
+            number(row.count),
+            number(count_low),
+            number(count_high),
+            row.sampled.to_string(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// ファイル（"-" なら標準出力）に書き込む CSV の Writer
+fn open(path: &str) -> Result<Writer<Box<dyn io::Write>>, Box<dyn Error>> {
+    let out: Box<dyn io::Write> = if path == "-" {
+        Box::new(io::stdout())
+    } else {
+        Box::new(File::create(path)?)
+    };
+    Ok(Writer::from_writer(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delimiters_and_quote_styles_are_parsed() {
+        assert_eq!(Dialect::parse_delimiter("tab"), Ok(b'\t'));
+        assert_eq!(Dialect::parse_delimiter(";"), Ok(b';'));
+        assert!(Dialect::parse_delimiter("\"").is_err());
+        assert!(Dialect::parse_delimiter("、").is_err());
+        assert_eq!(Quote::parse("non-numeric"), Ok(Quote::NonNumeric));
+        assert!(Quote::parse("sometimes").is_err());
+    }
+
+    #[test]
+    fn unquoted_numbers_cannot_contain_the_delimiter() {
+        let mut format = Format::default();
+        format.numbers.decimal_mark = ',';
+        assert!(format.check().is_ok());
+        format.dialect.quote = Quote::Never;
+        assert!(format.check().is_err());
+        format.dialect.delimiter = b';';
+        assert!(format.check().is_ok());
+    }
+
+    #[test]
+    fn crosstabs_end_with_the_column_totals() {
+        let crosstab = Crosstab {
+            columns: vec!["入間郡".to_string(), "比企郡".to_string()],
+            rows: vec![crate::pivot::CrosstabRow {
+                key: "埼玉県".to_string(),
+                values: vec![Some(1.5), None],
+                total: 1.5,
+            }],
+            totals: vec![1.5, 0.0],
+            total: 1.5,
+        };
+        let path = std::env::temp_dir().join(format!("layon-crosstab-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        write_crosstab(path, "N03_001", &crosstab, &Format::default()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "N03_001,入間郡,比企郡,Total\n埼玉県,1.5,,1.5\nTotal,1.5,0,1.5\n"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+//! Arrow IPC のメタデータを書き出すための最小限の FlatBuffers エンコーダ。
+//!
+//! テーブル・文字列・ベクターをツリーとして組み立て、先頭から順に書き出す。
+//! 子オブジェクトは必ず親の後ろに置かれるので、オフセットは常に前方参照になる。
+
+/// テーブル 1 つ分のフィールド（スキーマ上のフィールド番号と値の組）
+#[derive(Default)]
+pub struct Table {
+    fields: Vec<(u16, Value)>,
+}
+
+/// フィールドの値
+pub enum Value {
+    U8(u8),
+    Bool(bool),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Table(Table),
+    String(String),
+    /// テーブルのベクター
+    Tables(Vec<Table>),
+    /// 構造体のベクター（要素はエンコード済みのバイト列、アラインメントは 8 バイト）
+    Structs(Vec<Vec<u8>>),
+}
+
+impl Table {
+    pub fn new() -> Table {
+        Table::default()
+    }
+
+    pub fn field(mut self, id: u16, value: Value) -> Table {
+        self.fields.push((id, value));
+        self
+    }
+
+    /// ルートテーブルとしてバイト列にする（長さは 8 の倍数に揃える）
+    pub fn finish(self) -> Vec<u8> {
+        let mut buf = vec![0; 4];
+        let root = write_table(&mut buf, &self);
+        patch_offset(&mut buf, 0, root);
+        align(&mut buf, 8);
+        buf
+    }
+}
+
+impl Value {
+    /// インラインに置かれる部分のサイズ（オフセットは 4 バイト）
+    fn inline_size(&self) -> usize {
+        match self {
+            Value::U8(_) | Value::Bool(_) => 1,
+            Value::I16(_) => 2,
+            Value::I32(_) => 4,
+            Value::I64(_) => 8,
+            _ => 4,
+        }
+    }
+}
+
+fn align(buf: &mut Vec<u8>, alignment: usize) {
+    while !buf.len().is_multiple_of(alignment) {
+        buf.push(0);
+    }
+}
+
+/// `at` に置いた uoffset を `target` を指すように書き換える
+fn patch_offset(buf: &mut [u8], at: usize, target: usize) {
+    let offset = (target - at) as u32;
+    buf[at..at + 4].copy_from_slice(&offset.to_le_bytes());
+}
+
+/// テーブルを書き出し、テーブル先頭の位置を返す
+fn write_table(buf: &mut Vec<u8>, table: &Table) -> usize {
+    // サイズの大きいフィールドから順に並べると、パディングなしでアラインメントが揃う
+    let mut order: Vec<&(u16, Value)> = table.fields.iter().collect();
+    order.sort_by_key(|(_, value)| std::cmp::Reverse(value.inline_size()));
+
+    // テーブル内のフィールド位置（先頭 4 バイトは vtable への soffset）
+    let mut positions = Vec::with_capacity(order.len());
+    let mut inline_size: usize = 4;
+    for (_, value) in &order {
+        let size = value.inline_size();
+        inline_size = inline_size.div_ceil(size) * size;
+        positions.push(inline_size);
+        inline_size += size;
+    }
+
+    // vtable: [vtable のサイズ, テーブルのサイズ, 各フィールドの位置...]
+    let slots = table
+        .fields
+        .iter()
+        .map(|(id, _)| *id as usize + 1)
+        .max()
+        .unwrap_or(0);
+    let mut vtable = vec![0u16; 2 + slots];
+    vtable[0] = (vtable.len() * 2) as u16;
+    vtable[1] = inline_size as u16;
+    for ((id, _), position) in order.iter().zip(&positions) {
+        vtable[2 + *id as usize] = *position as u16;
+    }
+
+    align(buf, 2);
+    let vtable_pos = buf.len();
+    for entry in &vtable {
+        buf.extend_from_slice(&entry.to_le_bytes());
+    }
+
+    // テーブル本体は 8 バイト境界から始める
+    align(buf, 8);
+    let table_pos = buf.len();
+    buf.extend_from_slice(&((table_pos - vtable_pos) as i32).to_le_bytes());
+    buf.resize(table_pos + inline_size, 0);
+
+    let mut children = Vec::new();
+    for ((_, value), position) in order.iter().zip(&positions) {
+        let at = table_pos + position;
+        match value {
+            Value::U8(v) => buf[at] = *v,
+            Value::Bool(v) => buf[at] = *v as u8,
+            Value::I16(v) => buf[at..at + 2].copy_from_slice(&v.to_le_bytes()),
+            Value::I32(v) => buf[at..at + 4].copy_from_slice(&v.to_le_bytes()),
+            Value::I64(v) => buf[at..at + 8].copy_from_slice(&v.to_le_bytes()),
+            _ => children.push((at, value)),
+        }
+    }
+
+    // 子オブジェクトはテーブルの後ろに置く
+    for (at, value) in children {
+        let target = write_child(buf, value);
+        patch_offset(buf, at, target);
+    }
+    table_pos
+}
+
+/// オフセットで参照されるオブジェクトを書き出し、その位置を返す
+fn write_child(buf: &mut Vec<u8>, value: &Value) -> usize {
+    match value {
+        Value::Table(table) => write_table(buf, table),
+        Value::String(s) => {
+            align(buf, 4);
+            let pos = buf.len();
+            buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            buf.extend_from_slice(s.as_bytes());
+            buf.push(0);
+            pos
+        }
+        Value::Tables(tables) => {
+            align(buf, 4);
+            let pos = buf.len();
+            buf.extend_from_slice(&(tables.len() as u32).to_le_bytes());
+            let slots = buf.len();
+            buf.resize(slots + 4 * tables.len(), 0);
+            for (i, table) in tables.iter().enumerate() {
+                let target = write_table(buf, table);
+                patch_offset(buf, slots + 4 * i, target);
+            }
+            pos
+        }
+        Value::Structs(structs) => {
+            // 要素が 8 バイト境界に来るよう、長さの前を詰める
+            align(buf, 4);
+            if buf.len().is_multiple_of(8) {
+                buf.extend_from_slice(&[0; 4]);
+            }
+            let pos = buf.len();
+            buf.extend_from_slice(&(structs.len() as u32).to_le_bytes());
+            for bytes in structs {
+                buf.extend_from_slice(bytes);
+            }
+            pos
+        }
+        _ => unreachable!("スカラー値はインラインに書き出す"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(buf: &[u8], at: usize) -> usize {
+        u32::from_le_bytes(buf[at..at + 4].try_into().unwrap()) as usize
+    }
+
+    /// テーブルのフィールドの位置（vtable を引く。ないフィールドは None）
+    fn field(buf: &[u8], table: usize, id: usize) -> Option<usize> {
+        let soffset = i32::from_le_bytes(buf[table..table + 4].try_into().unwrap());
+        let vtable = (table as i64 - soffset as i64) as usize;
+        let entry = vtable + 4 + 2 * id;
+        let size = u16::from_le_bytes(buf[vtable..vtable + 2].try_into().unwrap()) as usize;
+        if 4 + 2 * id >= size {
+            return None;
+        }
+        match u16::from_le_bytes(buf[entry..entry + 2].try_into().unwrap()) {
+            0 => None,
+            position => Some(table + position as usize),
+        }
+    }
+
+    #[test]
+    fn scalars_strings_and_child_tables_can_be_read_back() {
+        let buf = Table::new()
+            .field(0, Value::I16(-2))
+            .field(2, Value::String("City".to_string()))
+            .field(3, Value::Table(Table::new().field(0, Value::I64(1 << 40))))
+            .finish();
+        assert_eq!(buf.len() % 8, 0);
+        let root = u32_at(&buf, 0);
+        assert_eq!(root % 8, 0);
+
+        let at = field(&buf, root, 0).unwrap();
+        assert_eq!(i16::from_le_bytes([buf[at], buf[at + 1]]), -2);
+        assert_eq!(field(&buf, root, 1), None);
+
+        let at = field(&buf, root, 2).unwrap();
+        let string = at + u32_at(&buf, at);
+        let len = u32_at(&buf, string);
+        assert_eq!(&buf[string + 4..string + 4 + len], b"City");
+        assert_eq!(buf[string + 4 + len], 0);
+
+        let at = field(&buf, root, 3).unwrap();
+        let child = at + u32_at(&buf, at);
+        let at = field(&buf, child, 0).unwrap();
+        assert_eq!(at % 8, 0);
+        assert_eq!(
+            i64::from_le_bytes(buf[at..at + 8].try_into().unwrap()),
+            1 << 40
+        );
+    }
+}
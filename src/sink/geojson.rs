@@ -0,0 +1,307 @@
+use crate::{
+    aggregate::GroupResult, breaks, extent::ExtentRow, hull::HullRow, label::LabelRow,
+    transform::GeometryTransform,
+};
+use geojson::{Feature, FeatureCollection, JsonObject, JsonValue};
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+/// FeatureCollection のジオメトリを `transform` で変換し、GeoJSON で書き出す（`path` が "-" なら標準出力）。
+/// Feature は並列に書式化し、元の順につなげる（書式は FeatureCollection を 1 つの JSON として書き出した場合と同じ）
+pub(crate) fn write(
+    path: &str,
+    collection: &mut FeatureCollection,
+    transform: &GeometryTransform,
+) -> Result<(), Box<dyn Error>> {
+    transform.apply_all(collection);
+    let mut out: Box<dyn Write> = if path == "-" {
+        Box::new(BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(BufWriter::new(File::create(path)?))
+    };
+    // Feature 以外のメンバー（type, bbox, name など）はキーの順に並べて、features だけを並列に書式化する
+    let features = std::mem::take(&mut collection.features);
+    let members = JsonObject::from(&*collection);
+    collection.features = features;
+    out.write_all(b"{")?;
+    for (i, (key, value)) in members.iter().enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut out, key)?;
+        out.write_all(b":")?;
+        if key != "features" {
+            serde_json::to_writer(&mut out, value)?;
+            continue;
+        }
+        out.write_all(b"[")?;
+        super::write_ordered(&mut out, &collection.features, |chunk, first| {
+            let mut buffer = Vec::new();
+            for (i, feature) in chunk.iter().enumerate() {
+                if !(first && i == 0) {
+                    buffer.push(b',');
+                }
+                serde_json::to_writer(&mut buffer, feature)?;
+            }
+            Ok(buffer)
+        })?;
+        out.write_all(b"]")?;
+    }
+    out.write_all(b"}\n")?;
+    out.flush()?;
+    Ok(())
+}
+
+/// 範囲を長方形の Feature にして書き出す（重心はプロパティに入れる）
+pub(crate) fn write_extents(
+    path: &str,
+    rows: &[ExtentRow],
+    transform: &GeometryTransform,
+) -> Result<(), Box<dyn Error>> {
+    let features = rows
+        .iter()
+        .map(|row| {
+            let mut properties = JsonObject::new();
+            properties.insert(
+                "name".to_string(),
+                row.name.clone().map_or(JsonValue::Null, JsonValue::from),
+            );
+            properties.insert("centroid_x".to_string(), JsonValue::from(row.centroid.x));
+            properties.insert("centroid_y".to_string(), JsonValue::from(row.centroid.y));
+            Feature {
+                bbox: Some(vec![
+                    row.rect.min().x,
+                    row.rect.min().y,
+                    row.rect.max().x,
+                    row.rect.max().y,
+                ]),
+                geometry: Some(geojson::Geometry::new(geojson::Value::from(
+                    &row.rect.to_polygon(),
+                ))),
+                id: None,
+                properties: Some(properties),
+                foreign_members: None,
+            }
+        })
+        .collect();
+    write(
+        path,
+        &mut FeatureCollection {
+            bbox: None,
+            features,
+            foreign_members: None,
+        },
+        transform,
+    )
+}
+
+/// グループのディゾルブしたジオメトリに階級（1 始まり）を付けて書き出す（ジオメトリのないグループは除く）
+pub(crate) fn write_classes(
+    path: &str,
+    rows: &[GroupResult],
+    breaks: &[f64],
+    transform: &GeometryTransform,
+) -> Result<(), Box<dyn Error>> {
+    let features = rows
+        .iter()
+        .filter_map(|row| {
+            let geometry = row.geometry.as_ref()?;
+            let mut properties = JsonObject::new();
+            properties.insert("name".to_string(), JsonValue::from(row.key.as_str()));
+            properties.insert("area".to_string(), JsonValue::from(row.area));
+            properties.insert("count".to_string(), JsonValue::from(row.count));
+            properties.insert(
+                "class".to_string(),
+                JsonValue::from(breaks::class_of(breaks, row.area)),
+            );
+            Some(Feature {
+                bbox: None,
+                geometry: Some(geojson::Geometry::new(geojson::Value::from(geometry))),
+                id: None,
+                properties: Some(properties),
+                foreign_members: None,
+            })
+        })
+        .collect();
+    write(
+        path,
+        &mut FeatureCollection {
+            bbox: None,
+            features,
+            foreign_members: None,
+        },
+        transform,
+    )
+}
+
+/// グループのディゾルブしたジオメトリを名前、面積、Feature の数とともに書き出す（ジオメトリのないグループは除く）
+pub(crate) fn write_dissolved(
+    path: &str,
+    rows: &[GroupResult],
+    transform: &GeometryTransform,
+) -> Result<(), Box<dyn Error>> {
+    write(path, &mut dissolved(rows), transform)
+}
+
+/// グループのディゾルブしたジオメトリに名前 (name)、面積 (area)、Feature の数 (count) を付けた FeatureCollection
+pub fn dissolved(rows: &[GroupResult]) -> FeatureCollection {
+    let features = rows
+        .iter()
+        .filter_map(|row| {
+            let geometry = row.geometry.as_ref()?;
+            let mut properties = JsonObject::new();
+            properties.insert("name".to_string(), JsonValue::from(row.key.as_str()));
+            properties.insert("area".to_string(), JsonValue::from(row.area));
+            properties.insert("count".to_string(), JsonValue::from(row.count));
+            Some(Feature {
+                bbox: None,
+                geometry: Some(geojson::Geometry::new(geojson::Value::from(geometry))),
+                id: None,
+                properties: Some(properties),
+                foreign_members: None,
+            })
+        })
+        .collect();
+    FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    }
+}
+
+/// グループごとの包む形を、名前、面積、充填率を付けた GeoJSON で書き出す
+pub(crate) fn write_hulls(
+    path: &str,
+    rows: &[HullRow],
+    transform: &GeometryTransform,
+) -> Result<(), Box<dyn Error>> {
+    let features = rows
+        .iter()
+        .map(|row| {
+            let mut properties = JsonObject::new();
+            properties.insert("name".to_string(), JsonValue::from(row.name.as_str()));
+            properties.insert("area".to_string(), JsonValue::from(row.area));
+            properties.insert("hull_area".to_string(), JsonValue::from(row.hull_area));
+            properties.insert("fill_ratio".to_string(), JsonValue::from(row.fill_ratio()));
+            Feature {
+                bbox: None,
+                geometry: row
+                    .geometry
+                    .as_ref()
+                    .map(|geometry| geojson::Geometry::new(geojson::Value::from(geometry))),
+                id: None,
+                properties: Some(properties),
+                foreign_members: None,
+            }
+        })
+        .collect();
+    write(
+        path,
+        &mut FeatureCollection {
+            bbox: None,
+            features,
+            foreign_members: None,
+        },
+        transform,
+    )
+}
+
+/// ラベルの位置を点の Feature にして書き出す
+pub(crate) fn write_labels(
+    path: &str,
+    rows: &[LabelRow],
+    transform: &GeometryTransform,
+) -> Result<(), Box<dyn Error>> {
+    let features = rows
+        .iter()
+        .map(|row| {
+            let mut properties = JsonObject::new();
+            properties.insert("name".to_string(), JsonValue::from(row.name.as_str()));
+            properties.insert("distance".to_string(), JsonValue::from(row.distance));
+            Feature {
+                bbox: None,
+                geometry: Some(geojson::Geometry::new(geojson::Value::Point(vec![
+                    row.point.x,
+                    row.point.y,
+                ]))),
+                id: None,
+                properties: Some(properties),
+                foreign_members: None,
+            }
+        })
+        .collect();
+    write(
+        path,
+        &mut FeatureCollection {
+            bbox: None,
+            features,
+            foreign_members: None,
+        },
+        transform,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{polygon, MultiPolygon};
+
+    #[test]
+    fn parallel_output_matches_serde_json() {
+        let mut collection: FeatureCollection =
+            include_str!("../../tests/fixtures/n03_11_sample.geojson")
+                .parse()
+                .unwrap();
+        // 並列に書式化するチャンク（1024 個）をまたぐように増やす
+        let features = collection.features.clone();
+        while collection.features.len() < 3000 {
+            collection.features.extend(features.iter().cloned());
+        }
+        let output =
+            std::env::temp_dir().join(format!("layon-features-{}.geojson", std::process::id()));
+        write(
+            output.to_str().unwrap(),
+            &mut collection,
+            &GeometryTransform::default(),
+        )
+        .unwrap();
+        // write で変換した後の collection を 1 つの JSON として書き出したものと同じ
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            format!("{}\n", collection)
+        );
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn dissolved_rows_without_geometry_are_left_out() {
+        let row = |key: &str, geometry| GroupResult {
+            key: key.to_string(),
+            layer: None,
+            area: 2.0,
+            count: 4,
+            geometry,
+            ids: Vec::new(),
+            values: Vec::new(),
+            kept: Vec::new(),
+            class: None,
+        };
+        let triangle = MultiPolygon::new(vec![polygon![
+            (x: 0.0, y: 0.0),
+            (x: 2.0, y: 0.0),
+            (x: 0.0, y: 2.0),
+        ]]);
+        let collection = dissolved(&[row("川越市", Some(triangle)), row("所沢市", None)]);
+        assert_eq!(collection.features.len(), 1);
+        let feature = &collection.features[0];
+        assert_eq!(feature.property("name"), Some(&JsonValue::from("川越市")));
+        assert_eq!(feature.property("count"), Some(&JsonValue::from(4)));
+        assert!(matches!(
+            feature.geometry.as_ref().unwrap().value,
+            geojson::Value::MultiPolygon(_)
+        ));
+    }
+}
@@ -0,0 +1,110 @@
+//! 包含関係のグラフ（`hierarchy::Hierarchy`）の書き出し。JSON（節点と辺の配列）と GraphML に対応する。
+
+use crate::{hierarchy::Hierarchy, markup::escape};
+use serde_json::json;
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+fn create(path: &str) -> Result<Box<dyn Write>, Box<dyn Error>> {
+    Ok(if path == "-" {
+        Box::new(BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(BufWriter::new(File::create(path)?))
+    })
+}
+
+/// `{"nodes": [{id, name, level, area, count}], "edges": [{source, target, contained}]}` の JSON で書き出す
+pub fn write_json(path: &str, hierarchy: &Hierarchy) -> Result<(), Box<dyn Error>> {
+    let nodes: Vec<_> = hierarchy
+        .nodes
+        .iter()
+        .map(|node| {
+            json!({
+                "id": node.id,
+                "name": node.name,
+                "level": node.level,
+                "area": node.area,
+                "count": node.count,
+            })
+        })
+        .collect();
+    let edges: Vec<_> = hierarchy
+        .edges
+        .iter()
+        .map(|edge| {
+            json!({
+                "source": hierarchy.nodes[edge.parent].id,
+                "target": hierarchy.nodes[edge.child].id,
+                "contained": edge.contained,
+            })
+        })
+        .collect();
+    let mut out = create(path)?;
+    serde_json::to_writer_pretty(&mut out, &json!({ "nodes": nodes, "edges": edges }))?;
+    out.write_all(b"\n")?;
+    out.flush()?;
+    Ok(())
+}
+
+/// GraphML（有向グラフ）で書き出す。包含を確かめていない辺には contained を付けない
+pub fn write_graphml(path: &str, hierarchy: &Hierarchy) -> Result<(), Box<dyn Error>> {
+    let mut out = create(path)?;
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    for (id, target, name, kind) in [
+        ("name", "node", "name", "string"),
+        ("level", "node", "level", "string"),
+        ("area", "node", "area", "double"),
+        ("count", "node", "count", "int"),
+        ("contained", "edge", "contained", "boolean"),
+    ] {
+        writeln!(
+            out,
+            r#"  <key id="{}" for="{}" attr.name="{}" attr.type="{}"/>"#,
+            id, target, name, kind
+        )?;
+    }
+    writeln!(out, r#"  <graph id="hierarchy" edgedefault="directed">"#)?;
+    for node in &hierarchy.nodes {
+        writeln!(out, r#"    <node id="{}">"#, escape(&node.id))?;
+        writeln!(
+            out,
+            r#"      <data key="name">{}</data>"#,
+            escape(&node.name)
+        )?;
+        writeln!(
+            out,
+            r#"      <data key="level">{}</data>"#,
+            escape(&node.level)
+        )?;
+        writeln!(out, r#"      <data key="area">{}</data>"#, node.area)?;
+        writeln!(out, r#"      <data key="count">{}</data>"#, node.count)?;
+        writeln!(out, "    </node>")?;
+    }
+    for edge in &hierarchy.edges {
+        let source = escape(&hierarchy.nodes[edge.parent].id);
+        let target = escape(&hierarchy.nodes[edge.child].id);
+        match edge.contained {
+            Some(contained) => {
+                writeln!(out, r#"    <edge source="{}" target="{}">"#, source, target)?;
+                writeln!(out, r#"      <data key="contained">{}</data>"#, contained)?;
+                writeln!(out, "    </edge>")?;
+            }
+            None => writeln!(
+                out,
+                r#"    <edge source="{}" target="{}"/>"#,
+                source, target
+            )?,
+        }
+    }
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</graphml>")?;
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,228 @@
+//! キーと値を保存するストア（Redis など）への出力。
+//! 集計キーごとに `<prefix><集計キー>` へ面積、`<prefix><集計キー>:count` へ Feature の数を書き込み、
+//! 集計し直さなくても「X 市の面積」を引けるようにする。
+
+use crate::aggregate::GroupResult;
+use std::{
+    error::Error,
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+};
+
+/// Redis の既定のポート
+const REDIS_PORT: u16 = 6379;
+/// キーの接頭辞の既定値
+const DEFAULT_PREFIX: &str = "layon:";
+
+/// キーと値の組を書き込めるストア
+pub trait KeyValueStore {
+    /// キーと値の組をまとめて書き込む（既存のキーは上書きする）
+    fn put_all(&mut self, pairs: &[(String, String)]) -> Result<(), Box<dyn Error>>;
+}
+
+/// 集計結果をキーと値の組にしてストアに書き込む
+pub fn write(
+    store: &mut dyn KeyValueStore,
+    prefix: &str,
+    rows: &[GroupResult],
+) -> Result<(), Box<dyn Error>> {
+    let mut pairs = Vec::with_capacity(rows.len() * 2);
+    for row in rows {
+        pairs.push((format!("{}{}", prefix, row.key), row.area.to_string()));
+        pairs.push((
+            format!("{}{}:count", prefix, row.key),
+            row.count.to_string(),
+        ));
+    }
+    store.put_all(&pairs)
+}
+
+/// Redis の書き込み先
+pub struct RedisTarget {
+    /// 接続先（host:port）
+    pub address: String,
+    /// `user:password@` の認証情報（ユーザー名は省略できる）
+    user: Option<String>,
+    password: Option<String>,
+    /// データベースの番号
+    db: Option<u32>,
+    /// キーの接頭辞
+    pub prefix: String,
+}
+
+impl RedisTarget {
+    /// `redis://[[user]:password@]host[:port][/db][?prefix=layon:]` 形式の URL を解析する
+    pub fn parse(url: &str) -> Result<RedisTarget, String> {
+        let rest = url
+            .strip_prefix("redis://")
+            .ok_or_else(|| format!("Redis の URL ではありません: {}", url))?;
+        let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let (credentials, rest) = match rest.rsplit_once('@') {
+            Some((credentials, rest)) => (Some(credentials), rest),
+            None => (None, rest),
+        };
+        let (host, db) = match rest.split_once('/') {
+            Some((host, "")) => (host, None),
+            Some((host, db)) => {
+                let db = db
+                    .parse()
+                    .map_err(|_| format!("Redis のデータベース番号が不正です: {}", db))?;
+                (host, Some(db))
+            }
+            None => (rest, None),
+        };
+        if host.is_empty() {
+            return Err(format!("Redis のホストを指定してください: {}", url));
+        }
+
+        let (user, password) = match credentials.map(|c| c.split_once(':')) {
+            Some(Some((user, password))) => (
+                (!user.is_empty()).then(|| user.to_string()),
+                Some(password.to_string()),
+            ),
+            // `password@` だけの場合もパスワードとみなす
+            Some(None) => (None, credentials.map(str::to_string)),
+            None => (None, None),
+        };
+
+        let mut prefix = DEFAULT_PREFIX.to_string();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            match pair.split_once('=') {
+                Some(("prefix", value)) => prefix = value.to_string(),
+                _ => return Err(format!("不明な Redis のパラメータです: {}", pair)),
+            }
+        }
+
+        Ok(RedisTarget {
+            address: if host.contains(':') {
+                host.to_string()
+            } else {
+                format!("{}:{}", host, REDIS_PORT)
+            },
+            user,
+            password,
+            db,
+            prefix,
+        })
+    }
+}
+
+/// Redis への接続（RESP で直接やり取りする）
+pub struct RedisStore {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl RedisStore {
+    /// 接続し、必要なら認証とデータベースの選択を行う
+    pub fn connect(target: &RedisTarget) -> Result<RedisStore, Box<dyn Error>> {
+        let stream = TcpStream::connect(&target.address)
+            .map_err(|err| format!("Redis ({}) に接続できません: {}", target.address, err))?;
+        let writer = stream.try_clone()?;
+        let mut store = RedisStore {
+            reader: BufReader::new(stream),
+            writer,
+        };
+        if let Some(password) = &target.password {
+            let mut command = vec!["AUTH"];
+            command.extend(target.user.as_deref());
+            command.push(password);
+            store.command(&command)?;
+        }
+        if let Some(db) = target.db {
+            store.command(&["SELECT", &db.to_string()])?;
+        }
+        Ok(store)
+    }
+
+    /// コマンドを送り、応答を待つ（エラーの応答は Err にする）
+    fn command(&mut self, args: &[&str]) -> Result<(), Box<dyn Error>> {
+        let mut request = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            write!(request, "${}\r\n{}\r\n", arg.len(), arg)?;
+        }
+        self.writer.write_all(&request)?;
+        self.writer.flush()?;
+
+        let mut reply = String::new();
+        self.reader.read_line(&mut reply)?;
+        match reply.chars().next() {
+            Some('-') => Err(format!("Redis のエラー: {}", reply[1..].trim_end()).into()),
+            Some(_) => Ok(()),
+            None => Err("Redis との接続が切れました".into()),
+        }
+    }
+}
+
+impl KeyValueStore for RedisStore {
+    /// MSET でまとめて書き込む（すべてのキーが一度に更新される）
+    fn put_all(&mut self, pairs: &[(String, String)]) -> Result<(), Box<dyn Error>> {
+        if pairs.is_empty() {
+            return Ok(());
+        }
+        let mut args = vec!["MSET"];
+        for (key, value) in pairs {
+            args.push(key);
+            args.push(value);
+        }
+        self.command(&args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Memory(Vec<(String, String)>);
+
+    impl KeyValueStore for Memory {
+        fn put_all(&mut self, pairs: &[(String, String)]) -> Result<(), Box<dyn Error>> {
+            self.0.extend_from_slice(pairs);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn urls_carry_credentials_database_and_prefix() {
+        let target = RedisTarget::parse("redis://:secret@cache/2?prefix=n03:").unwrap();
+        assert_eq!(target.address, "cache:6379");
+        assert_eq!(
+            (target.user, target.password.as_deref(), target.db),
+            (None, Some("secret"), Some(2))
+        );
+        assert_eq!(target.prefix, "n03:");
+        let target = RedisTarget::parse("redis://localhost:6380").unwrap();
+        assert_eq!(
+            (target.address.as_str(), target.prefix.as_str()),
+            ("localhost:6380", "layon:")
+        );
+        assert!(RedisTarget::parse("redis:///0").is_err());
+        assert!(RedisTarget::parse("redis://host/x").is_err());
+        assert!(RedisTarget::parse("redis://host?ttl=60").is_err());
+    }
+
+    #[test]
+    fn each_row_writes_its_area_and_count() {
+        let row = GroupResult {
+            key: "川越市".to_string(),
+            layer: None,
+            area: 109.13,
+            count: 3,
+            geometry: None,
+            ids: Vec::new(),
+            values: Vec::new(),
+            kept: Vec::new(),
+            class: None,
+        };
+        let mut store = Memory::default();
+        write(&mut store, "layon:", &[row]).unwrap();
+        assert_eq!(
+            store.0,
+            [
+                ("layon:川越市".to_string(), "109.13".to_string()),
+                ("layon:川越市:count".to_string(), "3".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,340 @@
+pub mod arrow;
+pub(crate) mod bundle;
+pub mod csv;
+mod flatbuffer;
+pub mod geojson;
+pub(crate) mod graph;
+pub mod kv;
+pub mod number;
+pub mod postgis;
+pub mod report;
+
+use crate::{aggregate::GroupResult, psql};
+use kv::{RedisStore, RedisTarget};
+use postgis::PostgisTarget;
+use rayon::prelude::*;
+use std::{
+    error::Error,
+    io::{self, Write},
+};
+
+/// 出力を並列に書式化するときの 1 つのバッファのレコードの数
+const CHUNK: usize = 1024;
+
+/// 出力するファイルの形式（`--output` の拡張子で判定する）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileFormat {
+    /// CSV（`.csv`）
+    Csv,
+    /// JSON（`.json`）
+    Json,
+    /// GeoJSON（`.geojson` と `.json`）
+    GeoJson,
+    /// Apache Arrow IPC のファイル形式（`.arrow` と `.feather`）
+    Arrow,
+    /// Apache Arrow IPC のストリーミング形式（`.arrows`）
+    ArrowStream,
+    /// SQL スクリプト（`.sql`）
+    Sql,
+    /// GraphML（`.graphml`）
+    GraphMl,
+}
+
+impl FileFormat {
+    /// この形式の拡張子
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            FileFormat::Csv => &["csv"],
+            FileFormat::Json => &["json"],
+            FileFormat::GeoJson => &["geojson", "json"],
+            FileFormat::Arrow => &["arrow", "feather"],
+            FileFormat::ArrowStream => &["arrows"],
+            FileFormat::Sql => &["sql"],
+            FileFormat::GraphMl => &["graphml"],
+        }
+    }
+
+    /// `accepted` のうち、拡張子（大文字・小文字は区別しない）が `target` に合う最初の形式
+    pub fn detect(target: &str, accepted: &[FileFormat]) -> Option<FileFormat> {
+        let lower = target.to_lowercase();
+        accepted.iter().copied().find(|format| {
+            format
+                .extensions()
+                .iter()
+                .any(|extension| lower.ends_with(&format!(".{}", extension)))
+        })
+    }
+
+    /// エラーのメッセージ用の、`accepted` の拡張子の一覧（例: `*.csv, *.geojson, *.json`）
+    fn patterns(accepted: &[FileFormat]) -> String {
+        let mut patterns: Vec<String> = Vec::new();
+        for extension in accepted.iter().flat_map(|format| format.extensions()) {
+            let pattern = format!("*.{}", extension);
+            if !patterns.contains(&pattern) {
+                patterns.push(pattern);
+            }
+        }
+        patterns.join(", ")
+    }
+}
+
+/// サブコマンドの `--output` のファイルの形式を拡張子で判定する（`accepted` の形式でなければエラー）。
+/// 標準出力（`-`）に書けるサブコマンドは、`-` のときは呼ばずに既定の形式で書く
+pub fn check_output(
+    command: &str,
+    target: &str,
+    accepted: &[FileFormat],
+) -> Result<FileFormat, String> {
+    FileFormat::detect(target, accepted).ok_or_else(|| {
+        format!(
+            "{} の出力先の形式が分かりません: {} ({} のいずれかを指定してください)",
+            command,
+            target,
+            FileFormat::patterns(accepted)
+        )
+    })
+}
+
+/// 集計結果の出力先
+pub enum Output {
+    /// CSV ファイル
+    Csv(String, csv::Format),
+    /// JSON ファイル（`GroupResult` の配列）
+    Json(String),
+    /// Apache Arrow IPC（ファイル形式またはストリーミング形式）
+    Arrow(String, arrow::Format),
+    /// PostGIS 向けの SQL スクリプトファイル
+    Sql(String, PostgisTarget),
+    /// PostGIS データベース（psql 経由で書き込む）
+    Postgis(PostgisTarget),
+    /// Redis（集計キーごとのキーに集計値を書き込む）
+    Redis(RedisTarget),
+    /// 利用者のテンプレートで書式化したファイル
+    Template(String, Box<report::Report>),
+}
+
+impl Output {
+    /// 集計結果を書き出せるファイルの形式
+    const FILE_FORMATS: [FileFormat; 5] = [
+        FileFormat::Csv,
+        FileFormat::Json,
+        FileFormat::Arrow,
+        FileFormat::ArrowStream,
+        FileFormat::Sql,
+    ];
+
+    /// `--output` の値から出力先を判定する（拡張子の分からないファイルはエラー）
+    pub fn parse(target: &str) -> Result<Output, String> {
+        if psql::is_url(target) {
+            Ok(Output::Postgis(PostgisTarget::parse(target)?))
+        } else if target.starts_with("redis://") {
+            Ok(Output::Redis(RedisTarget::parse(target)?))
+        } else {
+            let path = target.to_string();
+            match FileFormat::detect(target, &Output::FILE_FORMATS) {
+                Some(FileFormat::Csv) => Ok(Output::Csv(path, csv::Format::default())),
+                Some(FileFormat::Json) => Ok(Output::Json(path)),
+                Some(FileFormat::Arrow) => Ok(Output::Arrow(path, arrow::Format::File)),
+                Some(FileFormat::ArrowStream) => Ok(Output::Arrow(path, arrow::Format::Stream)),
+                Some(FileFormat::Sql) => Ok(Output::Sql(path, PostgisTarget::default())),
+                _ => Err(format!(
+                    "出力先の形式が分かりません: {} ({}, postgres://…, redis://… のいずれかを指定してください)",
+                    target,
+                    FileFormat::patterns(&Output::FILE_FORMATS)
+                )),
+            }
+        }
+    }
+
+    /// テンプレートで書くファイル（拡張子は問わない）
+    pub fn template(target: &str, report: report::Report) -> Result<Output, String> {
+        if psql::is_url(target) || target.starts_with("redis://") {
+            return Err("--template はファイルへの出力でのみ使えます".to_string());
+        }
+        Ok(Output::Template(target.to_string(), Box::new(report)))
+    }
+
+    /// PostGIS の出力（SQL と postgres://）の集計キーの列名を、URL の `key` で指定していなければ
+    /// 集計キーのプロパティの名前（小文字にしたもの。例: N03_004 なら n03_004）にする
+    pub fn name_key_column(&mut self, group_by: &str) {
+        if let Output::Sql(_, target) | Output::Postgis(target) = self {
+            target.key.get_or_insert_with(|| group_by.to_lowercase());
+        }
+    }
+
+    /// 出力にディゾルブしたジオメトリが必要かどうか
+    pub fn needs_geometry(&self) -> bool {
+        matches!(self, Output::Sql(..) | Output::Postgis(_))
+    }
+
+    /// 出力が集計結果のすべての列（レイヤーや派生した列も）を持てるかどうか
+    pub fn has_columns(&self) -> bool {
+        matches!(
+            self,
+            Output::Csv(..) | Output::Json(_) | Output::Template(..)
+        )
+    }
+
+    pub fn write(&self, rows: &[GroupResult]) -> Result<(), Box<dyn Error>> {
+        // ほかの出力先はレイヤーの列を持たず、同じ集計キーの行が重なってしまう
+        if rows.iter().any(|row| row.layer.is_some()) && !self.has_columns() {
+            return Err(
+                "複数のレイヤーを集計した結果は CSV と JSON (またはテンプレート) にのみ出力できます (--layer で 1 つのレイヤーを選んでください)"
+                    .into(),
+            );
+        }
+        match self {
+            Output::Csv(path, format) => csv::write(path, rows, format),
+            Output::Json(path) => write_json(path, rows),
+            Output::Arrow(path, format) => arrow::write(path, *format, rows),
+            Output::Sql(path, target) => postgis::write_file(path, target, rows),
+            Output::Postgis(target) => postgis::write_database(target, rows),
+            Output::Redis(target) => {
+                kv::write(&mut RedisStore::connect(target)?, &target.prefix, rows)
+            }
+            Output::Template(path, report) => report.write(path, rows),
+        }
+    }
+
+    /// 出力先のファイルに書き込めるか確かめる（既存のファイルの内容は変えない）。
+    /// PostGIS と Redis への接続は確かめない
+    pub fn check_writable(&self) -> Result<(), String> {
+        let Some(path) = self.path() else {
+            return Ok(());
+        };
+        let error = |err: std::io::Error| format!("{} に書き込めません: {}", path, err);
+        if std::path::Path::new(path).exists() {
+            std::fs::OpenOptions::new()
+                .append(true)
+                .open(path)
+                .map_err(error)?;
+        } else {
+            // 作成できるか試して、すぐに消す
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+                .map_err(error)?;
+            std::fs::remove_file(path).map_err(error)?;
+        }
+        Ok(())
+    }
+
+    /// 出力先のファイルのパス（データベースなら None）
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Output::Csv(path, _)
+            | Output::Json(path)
+            | Output::Arrow(path, _)
+            | Output::Sql(path, _)
+            | Output::Template(path, _) => Some(path),
+            Output::Postgis(_) | Output::Redis(_) => None,
+        }
+    }
+
+    /// 完了メッセージ用の出力先の説明
+    pub fn describe(&self) -> String {
+        match self {
+            Output::Csv(path, _) => format!("CSV ファイル ({})", path),
+            Output::Json(path) => format!("JSON ファイル ({})", path),
+            Output::Arrow(path, _) => format!("Arrow IPC ファイル ({})", path),
+            Output::Sql(path, _) => format!("SQL ファイル ({})", path),
+            Output::Postgis(target) => format!("PostGIS テーブル ({})", target.table),
+            Output::Redis(target) => format!("Redis ({} の {}*)", target.address, target.prefix),
+            Output::Template(path, report) => {
+                format!(
+                    "テンプレート {} で書式化したファイル ({})",
+                    report.template.path, path
+                )
+            }
+        }
+    }
+}
+
+/// 集計結果を JSON の配列で書き出す（`serde_json::to_writer_pretty` と同じ書式）
+fn write_json(path: &str, rows: &[GroupResult]) -> Result<(), Box<dyn Error>> {
+    let mut out = io::BufWriter::new(std::fs::File::create(path)?);
+    if rows.is_empty() {
+        out.write_all(b"[]")?;
+    } else {
+        out.write_all(b"[\n  ")?;
+        write_ordered(&mut out, rows, |chunk, first| {
+            let mut buffer = Vec::new();
+            for (i, row) in chunk.iter().enumerate() {
+                if !(first && i == 0) {
+                    buffer.extend_from_slice(b",\n  ");
+                }
+                // 配列の要素として 1 段深く字下げする（文字列の中の改行はエスケープされているので、改行はすべて書式のもの）
+                let json = serde_json::to_string_pretty(row)?;
+                buffer.extend_from_slice(json.replace('\n', "\n  ").as_bytes());
+            }
+            Ok(buffer)
+        })?;
+        out.write_all(b"\n]")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// `items` を `CHUNK` 個ずつ並列に書式化し、元の順に `out` に書き出す。
+/// `format` はチャンクと、それが最初のチャンクかどうかを受け取る（区切りを先頭に付けないため）。
+/// 一度に書式化するのはスレッドの数の 2 倍のチャンクまでにして、出力全体をメモリに持たないようにする
+pub(crate) fn write_ordered<T: Sync>(
+    out: &mut impl Write,
+    items: &[T],
+    format: impl Fn(&[T], bool) -> io::Result<Vec<u8>> + Sync,
+) -> io::Result<()> {
+    let batch = CHUNK * rayon::current_num_threads() * 2;
+    for (b, items) in items.chunks(batch).enumerate() {
+        let buffers = items
+            .par_chunks(CHUNK)
+            .enumerate()
+            .map(|(c, chunk)| format(chunk, b == 0 && c == 0))
+            .collect::<io::Result<Vec<_>>>()?;
+        for buffer in buffers {
+            out.write_all(&buffer)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_extensions_are_rejected() {
+        assert!(matches!(Output::parse("out.csv"), Ok(Output::Csv(..))));
+        assert!(matches!(Output::parse("out.json"), Ok(Output::Json(_))));
+        assert!(matches!(Output::parse("OUT.CSV"), Ok(Output::Csv(..))));
+        assert!(Output::parse("out.xlsx").is_err());
+        assert!(Output::parse("output").is_err());
+        assert!(Output::parse("out.geojson").is_err());
+    }
+
+    #[test]
+    fn subcommand_outputs_are_checked_against_their_formats() {
+        let both = [FileFormat::Csv, FileFormat::GeoJson];
+        assert_eq!(check_output("bbox", "out.csv", &both), Ok(FileFormat::Csv));
+        assert_eq!(
+            check_output("bbox", "out.json", &both),
+            Ok(FileFormat::GeoJson)
+        );
+        assert_eq!(
+            check_output("bbox", "Out.GeoJSON", &both),
+            Ok(FileFormat::GeoJson)
+        );
+        // 先に挙げた形式の拡張子を優先する
+        let graph = [FileFormat::Json, FileFormat::GraphMl];
+        assert_eq!(
+            check_output("hierarchy", "tree.json", &graph),
+            Ok(FileFormat::Json)
+        );
+        assert_eq!(
+            check_output("bbox", "out.xlsx", &both).unwrap_err(),
+            "bbox の出力先の形式が分かりません: out.xlsx (*.csv, *.geojson, *.json のいずれかを指定してください)"
+        );
+        assert!(check_output("mesh", "-", &[FileFormat::Csv]).is_err());
+        assert!(check_output("mesh", "mesh.csv.gz", &[FileFormat::Csv]).is_err());
+    }
+}
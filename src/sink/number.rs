@@ -0,0 +1,154 @@
+//! CSV に書き出す数値の書き方（`--number-format`, `--decimals`, `--thousands-separator`, `--decimal-mark`）。
+//! 既定の `f64::to_string()` は `0.06868719…` のように必要な桁をすべて書くため、
+//! 表計算ソフトで見やすいように桁数をそろえたり、ロケールに合わせた区切り文字にしたりできるようにする。
+
+/// fixed で `--decimals` を指定しない場合の小数点以下の桁数
+pub const DEFAULT_DECIMALS: usize = 6;
+
+/// 数値の表記
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Style {
+    /// `f64::to_string()` と同じ（値を表すのに必要な桁だけ）
+    #[default]
+    Plain,
+    /// 小数点以下の桁数をそろえる
+    Fixed,
+    /// 指数表記（例: 1.23e-5）
+    Scientific,
+}
+
+impl Style {
+    /// `--number-format` の値
+    pub fn parse(name: &str) -> Result<Style, String> {
+        match name {
+            "plain" => Ok(Style::Plain),
+            "fixed" => Ok(Style::Fixed),
+            "scientific" => Ok(Style::Scientific),
+            _ => Err(format!("不明な数値の書き方です: {}", name)),
+        }
+    }
+}
+
+/// 数値の書き方
+#[derive(Clone, Debug, PartialEq)]
+pub struct NumberFormat {
+    pub style: Style,
+    /// 小数点以下の桁数（None なら fixed は `DEFAULT_DECIMALS`、scientific は必要な桁だけ）
+    pub decimals: Option<usize>,
+    /// 整数部を 3 桁ごとに区切る文字（指数表記では使わない）
+    pub thousands: Option<char>,
+    /// 小数点の文字
+    pub decimal_mark: char,
+}
+
+impl Default for NumberFormat {
+    fn default() -> NumberFormat {
+        NumberFormat {
+            style: Style::Plain,
+            decimals: None,
+            thousands: None,
+            decimal_mark: '.',
+        }
+    }
+}
+
+impl NumberFormat {
+    /// 区切り文字が小数点と同じだと読み戻せないため、エラーにする
+    pub fn check(&self) -> Result<(), String> {
+        if self.thousands == Some(self.decimal_mark) {
+            return Err(format!(
+                "--thousands-separator と --decimal-mark に同じ文字 ({}) は指定できません",
+                self.decimal_mark
+            ));
+        }
+        Ok(())
+    }
+
+    /// この書き方で書式化した数値を読み戻す（数値でなければ None）
+    pub fn parse(&self, text: &str) -> Option<f64> {
+        let text: String = text
+            .trim()
+            .chars()
+            .filter(|&c| Some(c) != self.thousands)
+            .map(|c| if c == self.decimal_mark { '.' } else { c })
+            .collect();
+        text.parse().ok()
+    }
+
+    /// 数値を書式化する（NaN と無限大はそのまま）
+    pub fn format(&self, value: f64) -> String {
+        if !value.is_finite() {
+            return value.to_string();
+        }
+        let text = match (self.style, self.decimals) {
+            (Style::Plain, None) => value.to_string(),
+            (Style::Plain | Style::Fixed, decimals) => {
+                format!("{:.*}", decimals.unwrap_or(DEFAULT_DECIMALS), value)
+            }
+            (Style::Scientific, Some(decimals)) => format!("{:.*e}", decimals, value),
+            (Style::Scientific, None) => format!("{:e}", value),
+        };
+        if self.style == Style::Scientific {
+            return text.replacen('.', &self.decimal_mark.to_string(), 1);
+        }
+
+        let (sign, digits) = match text.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", text.as_str()),
+        };
+        let (integer, fraction) = match digits.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (digits, None),
+        };
+        let mut result = sign.to_string();
+        for (i, digit) in integer.chars().enumerate() {
+            if let Some(separator) = self.thousands {
+                if i > 0 && (integer.len() - i) % 3 == 0 {
+                    result.push(separator);
+                }
+            }
+            result.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            result.push(self.decimal_mark);
+            result.push_str(fraction);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separators_follow_the_locale_and_read_back() {
+        let format = NumberFormat {
+            style: Style::Fixed,
+            decimals: Some(2),
+            thousands: Some('.'),
+            decimal_mark: ',',
+        };
+        assert_eq!(format.format(-1234567.891), "-1.234.567,89");
+        assert_eq!(format.format(999.0), "999,00");
+        assert_eq!(format.parse(" 1.234.567,89 "), Some(1234567.89));
+        assert_eq!(format.format(f64::NAN), "NaN");
+    }
+
+    #[test]
+    fn scientific_ignores_the_thousands_separator() {
+        let format = NumberFormat {
+            style: Style::Scientific,
+            decimals: Some(3),
+            thousands: Some(','),
+            decimal_mark: ',',
+        };
+        assert_eq!(format.format(12345.678), "1,235e4");
+        assert_eq!(
+            NumberFormat::default().format(0.1 + 0.2),
+            "0.30000000000000004"
+        );
+        // 区切り文字と小数点が同じでは読み戻せない
+        assert!(format.check().is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use crate::{aggregate::GroupResult, psql};
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write},
+    process::Stdio,
+};
+
+/// 1 つの INSERT 文にまとめる行数
+const BATCH_SIZE: usize = 500;
+/// テーブル名の既定値
+const DEFAULT_TABLE: &str = "municipal_area";
+/// 集計キーの列名を決めていない場合の列名
+const DEFAULT_KEY: &str = "city";
+
+/// PostGIS の書き込み先（接続文字列とテーブル名、集計キーの列名）
+pub struct PostgisTarget {
+    pub connection: String,
+    pub table: String,
+    /// 集計キーの列名（None なら `city`。`Output::name_key_column` で集計キーのプロパティの名前にする）
+    pub key: Option<String>,
+}
+
+impl Default for PostgisTarget {
+    fn default() -> Self {
+        PostgisTarget {
+            connection: String::new(),
+            table: DEFAULT_TABLE.to_string(),
+            key: None,
+        }
+    }
+}
+
+impl PostgisTarget {
+    /// `postgres://user@host/db?table=municipal_area&key=city` 形式の URL を解析する
+    pub fn parse(url: &str) -> Result<PostgisTarget, String> {
+        let url = psql::ConnectionUrl::parse(url)?;
+        Ok(PostgisTarget {
+            connection: url.connection,
+            table: url.table.unwrap_or_else(|| DEFAULT_TABLE.to_string()),
+            key: url.key,
+        })
+    }
+
+    /// 集計キーの列名
+    pub fn key_column(&self) -> &str {
+        self.key.as_deref().unwrap_or(DEFAULT_KEY)
+    }
+}
+
+/// SQL スクリプトをファイルに出力する
+pub fn write_file(
+    path: &str,
+    target: &PostgisTarget,
+    rows: &[GroupResult],
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = BufWriter::new(File::create(path)?);
+    write_sql(&mut wtr, target, rows)?;
+    wtr.flush()?;
+    Ok(())
+}
+
+/// psql を起動し、標準入力に SQL を流し込んでデータベースに書き込む
+pub fn write_database(target: &PostgisTarget, rows: &[GroupResult]) -> Result<(), Box<dyn Error>> {
+    let mut child = psql::command(&target.connection)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("psql を起動できませんでした: {}", err))?;
+
+    {
+        let mut stdin = BufWriter::new(child.stdin.take().unwrap());
+        write_sql(&mut stdin, target, rows)?;
+        stdin.flush()?;
+        // ここで stdin が閉じられ、psql に入力の終わりが伝わる
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("psql がエラー終了しました ({})", status).into());
+    }
+    Ok(())
+}
+
+/// テーブル作成と、まとめて INSERT する upsert 文を書き出す（全体を 1 トランザクションにする）
+fn write_sql<W: Write>(
+    w: &mut W,
+    target: &PostgisTarget,
+    rows: &[GroupResult],
+) -> std::io::Result<()> {
+    let table = psql::quote_table(&target.table);
+    let key = psql::quote_identifier(target.key_column());
+
+    writeln!(w, "BEGIN;")?;
+    writeln!(
+        w,
+        "CREATE TABLE IF NOT EXISTS {} ({} text PRIMARY KEY, area double precision NOT NULL, geom geometry(MultiPolygon, 4326));",
+        table, key
+    )?;
+
+    for batch in rows.chunks(BATCH_SIZE) {
+        writeln!(w, "INSERT INTO {} ({}, area, geom) VALUES", table, key)?;
+        for (i, row) in batch.iter().enumerate() {
+            let separator = if i + 1 == batch.len() { "" } else { "," };
+            writeln!(
+                w,
+                "  ({}, {}, {}){}",
+                quote(&row.key),
+                float_sql(row.area),
+                geometry_sql(row),
+                separator
+            )?;
+        }
+        writeln!(
+            w,
+            "ON CONFLICT ({}) DO UPDATE SET area = EXCLUDED.area, geom = EXCLUDED.geom;",
+            key
+        )?;
+    }
+
+    writeln!(w, "COMMIT;")?;
+    Ok(())
+}
+
+/// double precision の値の SQL の表記（Rust の `NaN` や `inf` はそのままでは SQL にならない）
+fn float_sql(value: f64) -> String {
+    if value.is_nan() {
+        "'NaN'::float8".to_string()
+    } else if value.is_infinite() {
+        let sign = if value < 0.0 { "-" } else { "" };
+        format!("'{}Infinity'::float8", sign)
+    } else {
+        value.to_string()
+    }
+}
+
+/// ディゾルブしたジオメトリを GeoJSON 経由で PostGIS のジオメトリに変換する式
+fn geometry_sql(row: &GroupResult) -> String {
+    match &row.geometry {
+        Some(geometry) if !geometry.0.is_empty() => {
+            let geojson = geojson::Geometry::new(geojson::Value::from(geometry));
+            format!(
+                "ST_SetSRID(ST_Multi(ST_GeomFromGeoJSON({})), 4326)",
+                quote(&geojson.to_string())
+            )
+        }
+        _ => "NULL".to_string(),
+    }
+}
+
+/// SQL の文字列リテラルとしてエスケープする
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{polygon, MultiPolygon};
+
+    #[test]
+    fn rows_are_upserted_in_one_transaction_with_quoted_values() {
+        let row = |key: &str, geometry| GroupResult {
+            key: key.to_string(),
+            layer: None,
+            area: 1.5,
+            count: 1,
+            geometry,
+            ids: Vec::new(),
+            values: Vec::new(),
+            kept: Vec::new(),
+            class: None,
+        };
+        let square = MultiPolygon::new(vec![polygon![
+            (x: 0.0, y: 0.0),
+            (x: 1.0, y: 0.0),
+            (x: 1.0, y: 1.0),
+        ]]);
+        let rows = [row("O'Hare", None), row("川越市", Some(square))];
+        let mut sql = Vec::new();
+        write_sql(&mut sql, &PostgisTarget::default(), &rows).unwrap();
+        let sql = String::from_utf8(sql).unwrap();
+        let lines: Vec<&str> = sql.lines().collect();
+        assert_eq!(lines[0], "BEGIN;");
+        assert_eq!(lines[lines.len() - 1], "COMMIT;");
+        assert!(sql.contains("  ('O''Hare', 1.5, NULL),\n"), "{}", sql);
+        assert!(
+            sql.contains("  ('川越市', 1.5, ST_SetSRID(ST_Multi(ST_GeomFromGeoJSON('{"),
+            "{}",
+            sql
+        );
+        assert_eq!(sql.matches("ON CONFLICT (\"city\")").count(), 1);
+    }
+
+    #[test]
+    fn key_columns_and_non_finite_areas_are_written_as_sql() {
+        let row = |key: &str, area| GroupResult {
+            key: key.to_string(),
+            layer: None,
+            area,
+            count: 1,
+            geometry: None,
+            ids: Vec::new(),
+            values: Vec::new(),
+            kept: Vec::new(),
+            class: None,
+        };
+        let rows = [
+            row("a", f64::NAN),
+            row("b", f64::INFINITY),
+            row("c", f64::NEG_INFINITY),
+            row("d", 2.0),
+        ];
+        let target =
+            PostgisTarget::parse("postgres://localhost/gis?table=gis.zones&key=zone_id").unwrap();
+        let mut sql = Vec::new();
+        write_sql(&mut sql, &target, &rows).unwrap();
+        let sql = String::from_utf8(sql).unwrap();
+        assert!(
+            sql.contains(
+                "CREATE TABLE IF NOT EXISTS \"gis\".\"zones\" (\"zone_id\" text PRIMARY KEY,"
+            ),
+            "{}",
+            sql
+        );
+        assert!(sql.contains("INSERT INTO \"gis\".\"zones\" (\"zone_id\", area, geom) VALUES"));
+        assert!(sql.contains("ON CONFLICT (\"zone_id\") DO UPDATE"));
+        assert!(sql.contains("  ('a', 'NaN'::float8, NULL),\n"), "{}", sql);
+        assert!(sql.contains("  ('b', 'Infinity'::float8, NULL),\n"));
+        assert!(sql.contains("  ('c', '-Infinity'::float8, NULL),\n"));
+        assert!(sql.contains("  ('d', 2, NULL)\n"));
+
+        assert!(PostgisTarget::parse("postgres://localhost/gis?key=zone;drop").is_err());
+        assert_eq!(PostgisTarget::default().key_column(), "city");
+    }
+}
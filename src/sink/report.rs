@@ -0,0 +1,283 @@
+//! 利用者のテンプレートによる集計結果の書き出し（`--template report.hbs`）。
+//!
+//! Markdown の報告書、LaTeX の表、独自の XML などを、後から変換するスクリプトなしで作れるようにする。
+//! テンプレートは Handlebars の書式で書き、handlebars で書式化する（`{{ … }}` は HTML の特殊文字をエスケープし、`{{{ … }}}` はしない）。
+//! 組み込みのヘルパー（`each`, `if`, `unless`, `with`, `eq` など）のほかに、
+//! `{{round 値 桁数}}`（小数点以下の桁数に丸める）と `{{{latex 値}}}`（LaTeX の特殊文字をエスケープする）が使える。
+//! 知らないヘルパーを呼んでいるテンプレートは、読み込むときにエラーにする。
+//!
+//! 一番外側では `rows`（JSON の出力と同じ行の配列）、`total.area`, `total.count`, `total.rows`、
+//! `input`, `group_by`, `metric`, `created_at`, `version` が使える。
+
+use crate::{aggregate::GroupResult, time};
+use handlebars::{
+    handlebars_helper,
+    template::{Parameter, TemplateElement},
+    Handlebars, TemplateErrorReason,
+};
+use serde_json::{json, Value};
+use std::{error::Error, fs, io::Write, time::SystemTime};
+
+/// テンプレートから呼べるヘルパー（handlebars の組み込みのものと `round`, `latex`）
+const HELPERS: [&str; 18] = [
+    "each", "if", "unless", "with", "lookup", "log", "eq", "ne", "gt", "gte", "lt", "lte", "and",
+    "or", "not", "len", "round", "latex",
+];
+
+/// テンプレートに渡す集計の説明
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub template: Template,
+    /// 入力元
+    pub input: String,
+    /// 集計キーのプロパティ
+    pub group_by: String,
+    /// 集計値の名前（`--metric` の値）
+    pub metric: String,
+}
+
+impl Report {
+    /// 集計結果をテンプレートで書式化して `path` に書き出す（"-" なら標準出力）
+    pub fn write(&self, path: &str, rows: &[GroupResult]) -> Result<(), Box<dyn Error>> {
+        let context = json!({
+            "rows": rows,
+            "total": {
+                "area": rows.iter().map(|row| row.area).sum::<f64>(),
+                "count": rows.iter().map(|row| row.count).sum::<usize>(),
+                "rows": rows.len(),
+            },
+            "input": self.input,
+            "group_by": self.group_by,
+            "metric": self.metric,
+            "created_at": time::rfc3339(SystemTime::now()),
+            "version": env!("CARGO_PKG_VERSION"),
+        });
+        let text = self.template.render(&context)?;
+        if path == "-" {
+            std::io::stdout().lock().write_all(text.as_bytes())?;
+        } else {
+            fs::write(path, text)?;
+        }
+        Ok(())
+    }
+}
+
+/// 解析済みのテンプレート
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    /// テンプレートのファイル
+    pub path: String,
+    template: handlebars::Template,
+}
+
+impl Template {
+    /// テンプレートのファイルを読み込んで解析する
+    pub fn read(path: &str) -> Result<Template, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("テンプレート {} を読み込めません: {}", path, err))?;
+        Template::parse(path, &text)
+    }
+
+    /// `text` を解析する（`path` はエラーの表示に使う）
+    pub fn parse(path: &str, text: &str) -> Result<Template, String> {
+        let template = handlebars::Template::compile(text).map_err(|err| {
+            let message = match err.reason() {
+                TemplateErrorReason::MismatchingClosedHelper(open, close) => format!(
+                    "{{{{#{}}}}} のブロックを {{{{/{}}}}} で閉じています",
+                    open, close
+                ),
+                reason => reason.to_string(),
+            };
+            match err.pos() {
+                Some((line, _)) => format!("テンプレート {} の {} 行目: {}", path, line, message),
+                None => format!("テンプレート {}: {}", path, message),
+            }
+        })?;
+        check_helpers(&template).map_err(|(line, name)| {
+            format!(
+                "テンプレート {} の {} 行目: 知らないヘルパーです: {}",
+                path, line, name
+            )
+        })?;
+        Ok(Template {
+            path: path.to_string(),
+            template,
+        })
+    }
+
+    /// `context` の値を埋め込んだ文字列
+    pub fn render(&self, context: &Value) -> Result<String, String> {
+        let mut registry = Handlebars::new();
+        registry.register_helper("round", Box::new(round));
+        registry.register_helper("latex", Box::new(latex));
+        registry.register_template(&self.path, self.template.clone());
+        registry
+            .render(&self.path, context)
+            .map_err(|err| format!("テンプレート {} を書式化できません: {}", self.path, err))
+    }
+}
+
+handlebars_helper!(round: |*args| {
+    let digits = args
+        .get(1)
+        .and_then(|digits| digits.as_f64())
+        .map_or(0, |digits| digits.clamp(0.0, 17.0) as usize);
+    match args.first() {
+        Some(Value::Number(number)) => {
+            Value::from(format!("{:.*}", digits, number.as_f64().unwrap_or_default()))
+        }
+        Some(value) => (*value).clone(),
+        None => Value::Null,
+    }
+});
+
+handlebars_helper!(latex: |*args| {
+    Value::from(escape_latex(&args.first().map(|value| display(value)).unwrap_or_default()))
+});
+
+/// 知らないヘルパーを呼んでいれば、その行とヘルパーの名前
+fn check_helpers(template: &handlebars::Template) -> Result<(), (usize, String)> {
+    for (i, element) in template.elements.iter().enumerate() {
+        let line = template.mapping.get(i).map_or(0, |mapping| mapping.0);
+        check_element(element, line)?;
+    }
+    Ok(())
+}
+
+fn check_element(element: &TemplateElement, line: usize) -> Result<(), (usize, String)> {
+    let helper = match element {
+        TemplateElement::Expression(helper)
+        | TemplateElement::HtmlExpression(helper)
+        | TemplateElement::HelperBlock(helper) => helper,
+        _ => return Ok(()),
+    };
+    let called = helper.block || !helper.params.is_empty() || !helper.hash.is_empty();
+    if let Some(name) = helper.name.as_name().filter(|_| called) {
+        if !HELPERS.contains(&name) {
+            return Err((line, name.to_string()));
+        }
+    }
+    for parameter in helper.params.iter().chain(helper.hash.values()) {
+        if let Parameter::Subexpression(subexpression) = parameter {
+            check_element(subexpression.as_element(), line)?;
+        }
+    }
+    for inner in [&helper.template, &helper.inverse].into_iter().flatten() {
+        check_helpers(inner)?;
+    }
+    Ok(())
+}
+
+/// 値の文字列（null は空、配列とオブジェクトは JSON）
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn escape_latex(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\textbackslash{}"),
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aggregate::Metric, pipeline::Pipeline};
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    #[test]
+    fn helpers_are_checked_inside_blocks_and_subexpressions() {
+        let render = |text: &str| {
+            Template::parse("t.hbs", text)
+                .unwrap()
+                .render(&json!({"a": 2.345, "b": "x"}))
+        };
+        assert_eq!(
+            render("{{round a 2}} {{round a}} {{round b 1}}").unwrap(),
+            "2.35 2 x"
+        );
+        assert_eq!(render("{{#if (eq b \"x\")}}yes{{/if}}").unwrap(), "yes");
+        for text in [
+            "{{#each a}}\n{{upper this}}\n{{/each}}",
+            "{{#if (upper b)}}{{/if}}",
+        ] {
+            let err = Template::parse("t.hbs", text).unwrap_err();
+            assert!(err.contains("知らないヘルパーです: upper"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn templates_render_rows_with_blocks_helpers_and_escaping() {
+        let dir = std::env::temp_dir().join(format!("layon-template-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rows = Pipeline::read(FIXTURE)
+            .group_by("N03_004")
+            .metric(Metric::GeodesicArea)
+            .run()
+            .unwrap()
+            .rows;
+        let text = "\
+{{! 先頭の 2 行だけを表にする }}
+| 市区町村 | 面積 |
+|---|---:|
+{{#each rows}}
+{{#if @first}}
+| **{{key}}** | {{round area 1}} |
+{{else}}
+| {{key}} | {{round area 1}} |
+{{/if}}
+{{/each}}
+合計 {{total.rows}} 行 ({{metric}})
+";
+        let template = Template::parse("table.md", text).unwrap();
+        let report = Report {
+            template,
+            input: FIXTURE.to_string(),
+            group_by: "N03_004".to_string(),
+            metric: "geodesic-area".to_string(),
+        };
+        let output = dir.join("table.md");
+        report.write(output.to_str().unwrap(), &rows[..2]).unwrap();
+        let expected = format!(
+            "| 市区町村 | 面積 |\n|---|---:|\n| **{}** | {:.1} |\n| {} | {:.1} |\n合計 2 行 (geodesic-area)\n",
+            rows[0].key, rows[0].area, rows[1].key, rows[1].area
+        );
+        assert_eq!(fs::read_to_string(&output).unwrap(), expected);
+
+        let context = serde_json::json!({"name": "A&B_1 <x>", "items": []});
+        let render = |text: &str| {
+            Template::parse("inline", text)
+                .unwrap()
+                .render(&context)
+                .unwrap()
+        };
+        assert_eq!(render("{{name}}"), "A&amp;B_1 &lt;x&gt;");
+        assert_eq!(render("{{{name}}}"), "A&B_1 <x>");
+        assert_eq!(render("{{{latex name}}}"), r"A\&B\_1 <x>");
+        assert_eq!(render("{{#each items}}x{{else}}なし{{/each}}"), "なし");
+        assert_eq!(render("a  {{~#unless items}} b {{~/unless~}}  c"), "a bc");
+        let err = Template::parse("bad.hbs", "行 1\n\n{{upper key}}\n").unwrap_err();
+        assert!(err.contains("bad.hbs の 3 行目"), "{}", err);
+        let err = Template::parse("bad.hbs", "{{#if x}}\n{{/each}}\n").unwrap_err();
+        assert!(err.contains("{{/each}}"), "{}", err);
+    }
+}
@@ -0,0 +1,139 @@
+//! 隣接するグループとの境界の長さで重み付けした平滑化（`--smooth`）。
+//!
+//! 市町村ごとの値はばらつきが大きく、地図にすると小さな市町村の値が目立ちやすい。探索的な空間分析のために、
+//! 各グループの値を、隣接するグループの値と共有する境界の長さで重み付けして平均した列を加える。
+//! 隣接は、異なるグループの Feature のリングが同じ辺（両端の座標が一致する線分）を持つことで判定する。
+//! 国土数値情報の行政区域のように、隣り合うポリゴンが境界の頂点を共有するデータを想定している。
+//! 複数のレイヤーを集計した場合も、レイヤーは区別せず集計キーで隣接を判定する。
+
+use crate::{aggregate::GroupResult, derive, flat::FlatPolygons};
+use geo::{Coord, HaversineDistance, Point};
+use geojson::{FeatureCollection, JsonValue};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// グループの隣接関係
+#[derive(Debug, Default)]
+pub struct Adjacency {
+    /// 集計キーの組（小さいほうが先）と共有する境界の長さ (km)
+    pub borders: HashMap<(String, String), f64>,
+}
+
+/// 向きをそろえた辺の両端の座標（ビット列で比べる）
+type Edge = ((u64, u64), (u64, u64));
+
+impl Adjacency {
+    /// `group_by` の値ごとに、共有する辺から隣接するグループと境界の長さを求める。座標は経度・緯度であること
+    pub fn detect(collection: &FeatureCollection, group_by: &str) -> Adjacency {
+        let edges: Vec<(&str, Vec<(Edge, f64)>)> = collection
+            .features
+            .par_iter()
+            .filter_map(|feature| {
+                let key = feature.property(group_by).and_then(JsonValue::as_str)?;
+                let mut flat = FlatPolygons::default();
+                flat.load(&feature.geometry.as_ref()?.value);
+                let edges = flat
+                    .rings()
+                    .flat_map(|ring| ring.windows(2))
+                    .filter(|pair| pair[0] != pair[1])
+                    .map(|pair| {
+                        let length = Point::from(pair[0]).haversine_distance(&Point::from(pair[1]));
+                        (edge(pair[0], pair[1]), length / 1e3)
+                    })
+                    .collect();
+                Some((key, edges))
+            })
+            .collect();
+
+        // 辺 -> 最初に見つけたグループ
+        let mut owners = HashMap::<Edge, &str>::new();
+        let mut borders = HashMap::<(String, String), f64>::new();
+        for (key, edges) in &edges {
+            for &(edge, length) in edges {
+                match owners.get(&edge) {
+                    Some(&owner) if owner != *key => {
+                        let pair = if owner < *key {
+                            (owner.to_string(), key.to_string())
+                        } else {
+                            (key.to_string(), owner.to_string())
+                        };
+                        *borders.entry(pair).or_default() += length;
+                    }
+                    Some(_) => {}
+                    None => {
+                        owners.insert(edge, key);
+                    }
+                }
+            }
+        }
+        Adjacency { borders }
+    }
+}
+
+/// 辺の両端を小さい順に並べる（隣り合うポリゴンでは同じ辺が逆向きに現れる）
+fn edge(a: Coord<f64>, b: Coord<f64>) -> Edge {
+    // 0.0 と -0.0 を同じ座標として扱う
+    let bits = |c: Coord<f64>| ((c.x + 0.0).to_bits(), (c.y + 0.0).to_bits());
+    let (a, b) = (bits(a), bits(b));
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// 平滑化する列
+#[derive(Clone, Debug, PartialEq)]
+pub struct Smoothing {
+    /// 元の列（`area`, `count` または `values` の列）
+    pub column: String,
+}
+
+impl Smoothing {
+    pub fn new(column: impl Into<String>) -> Smoothing {
+        Smoothing {
+            column: column.into(),
+        }
+    }
+
+    /// 加える列の名前
+    pub fn name(&self) -> String {
+        format!("{}_smoothed", self.column)
+    }
+
+    /// 行ごとに平滑化した値を `GroupResult::values` の後ろに加える。
+    /// 自分の値には隣接するグループとの境界の長さの合計と同じ重みを付ける（自分と隣接するグループ全体を半々に平均する）。
+    /// 値のない隣接するグループは除き、隣接するグループがなければ（島など）自分の値のままにする
+    pub fn apply(&self, rows: &mut [GroupResult], adjacency: &Adjacency) {
+        let values: HashMap<String, Option<f64>> = rows
+            .iter()
+            .map(|row| (row.key.clone(), derive::column(row, &self.column)))
+            .collect();
+        let mut neighbors = HashMap::<&str, Vec<(&str, f64)>>::new();
+        for ((a, b), &length) in &adjacency.borders {
+            neighbors.entry(a).or_default().push((b, length));
+            neighbors.entry(b).or_default().push((a, length));
+        }
+        let name = self.name();
+        for row in rows {
+            let smoothed = values[&row.key].map(|value| {
+                let (weighted, total) = neighbors
+                    .get(row.key.as_str())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|&(neighbor, length)| {
+                        Some((values.get(neighbor).copied().flatten()? * length, length))
+                    })
+                    .fold((0.0, 0.0), |(sum, total), (weighted, length)| {
+                        (sum + weighted, total + length)
+                    });
+                if total > 0.0 {
+                    (value * total + weighted) / (2.0 * total)
+                } else {
+                    value
+                }
+            });
+            row.values.push((name.clone(), smoothed));
+        }
+    }
+}
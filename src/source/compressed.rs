@@ -0,0 +1,103 @@
+//! gzip / zstd で圧縮した GeoJSON, 1 行 1 Feature の GeoJSON, CSV / TSV の読み込み（`*.geojson.gz`, `*.geojson.zst` など）。
+//!
+//! 全国のデータを圧縮したまま置いておけるように、メモリに展開してから中の形式のまま解析する。
+//! `bgzip` で圧縮したファイル（BGZF。64 KiB ごとの独立したメンバー）は、メンバーごとにすべてのコアで並列に展開する。
+//! `gzip` や `pigz` で圧縮したファイルは 1 つの DEFLATE のストリームなので 1 スレッドでしか展開できず、
+//! 大きなファイルでは展開が解析より遅くなる（`bgzip -@ 8 N03.geojson` などで圧縮し直すとよい）。
+//! zstd も同じで、複数のフレームに分けたもの（`zstd -T0 -B 1MiB`, `pzstd`, seekable 形式）はフレームごとに並列に展開し、
+//! 1 つのフレームのものは 1 スレッドで展開する。
+
+use super::{csv, geojson, seq, Input};
+use crate::inflate;
+use ::geojson::FeatureCollection;
+use std::{error::Error, fs};
+
+/// 圧縮の形式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// ファイルの拡張子から判定する（.gz, .zst）
+    pub fn of(path: &str) -> Option<(Compression, &str)> {
+        [(Compression::Gzip, "gz"), (Compression::Zstd, "zst")]
+            .into_iter()
+            .find(|(_, extension)| super::has_extension(path, extension))
+            .map(|(compression, extension)| {
+                (compression, &path[..path.len() - extension.len() - 1])
+            })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+}
+
+/// 圧縮して読み込める形式か
+pub fn supports(inner: &Input) -> bool {
+    matches!(
+        inner,
+        Input::GeoJson(_) | Input::GeoJsonSeq { .. } | Input::Csv { .. }
+    )
+}
+
+/// `path` を展開し、`inner` の形式で解析する
+pub fn read(
+    path: &str,
+    compression: Compression,
+    inner: &Input,
+) -> Result<FeatureCollection, Box<dyn Error>> {
+    parse(path, &decompress(path, compression)?, inner)
+}
+
+/// `path` 全体を展開する
+pub fn decompress(path: &str, compression: Compression) -> Result<Vec<u8>, Box<dyn Error>> {
+    let data = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+    let data = match compression {
+        Compression::Gzip => inflate::gunzip(&data),
+        Compression::Zstd => inflate::unzstd(&data),
+    };
+    Ok(data.map_err(|err| format!("{}: {}", path, err))?)
+}
+
+/// 展開した `data` を `inner` の形式で解析する
+pub fn parse(path: &str, data: &[u8], inner: &Input) -> Result<FeatureCollection, Box<dyn Error>> {
+    match inner {
+        Input::GeoJson(_) => {
+            let text = std::str::from_utf8(data)
+                .map_err(|err| format!("{}: UTF-8 ではありません: {}", path, err))?;
+            geojson::parse(path, text)
+        }
+        Input::GeoJsonSeq { skip_invalid, .. } => seq::parse(data, path, *skip_invalid),
+        Input::Csv {
+            delimiter,
+            geometry_column,
+            ..
+        } => csv::parse(data, *delimiter, geometry_column.as_deref()),
+        _ => Err(format!("{}: 圧縮したこの形式は読み込めません", path).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_extension_selects_the_compression_and_the_inner_path() {
+        assert_eq!(
+            Compression::of("N03.geojson.gz"),
+            Some((Compression::Gzip, "N03.geojson"))
+        );
+        assert_eq!(
+            Compression::of("data/N03.CSV.ZST"),
+            Some((Compression::Zstd, "data/N03.CSV"))
+        );
+        assert_eq!(Compression::of("N03.geojson"), None);
+        assert_eq!(Compression::of("gz"), None);
+    }
+}
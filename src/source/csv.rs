@@ -0,0 +1,165 @@
+use super::{wkb, wkt};
+use csv::{ReaderBuilder, StringRecord};
+use geojson::{Feature, FeatureCollection, JsonObject, JsonValue};
+use std::{error::Error, io::Read};
+
+/// ジオメトリ列の名前を指定しなかった場合に探す列名（大文字・小文字は区別しない）
+const GEOMETRY_COLUMNS: &[&str] = &["wkt", "wkb", "geom", "geometry", "the_geom", "geom_wkt"];
+
+/// ジオメトリを WKT または 16 進 WKB の列として持つ CSV / TSV を読み込む。
+/// ジオメトリ以外の列は文字列の properties になる。
+pub fn read(
+    path: &str,
+    delimiter: u8,
+    geometry_column: Option<&str>,
+) -> Result<FeatureCollection, Box<dyn Error>> {
+    read_from(
+        ReaderBuilder::new().delimiter(delimiter).from_path(path)?,
+        geometry_column,
+    )
+}
+
+/// 展開したものなど、メモリにある CSV / TSV を読み込む
+pub fn parse(
+    data: &[u8],
+    delimiter: u8,
+    geometry_column: Option<&str>,
+) -> Result<FeatureCollection, Box<dyn Error>> {
+    read_from(
+        ReaderBuilder::new().delimiter(delimiter).from_reader(data),
+        geometry_column,
+    )
+}
+
+fn read_from<R: Read>(
+    mut rdr: csv::Reader<R>,
+    geometry_column: Option<&str>,
+) -> Result<FeatureCollection, Box<dyn Error>> {
+    let headers = rdr.headers()?.clone();
+
+    let index = geometry_index(&headers, geometry_column)?;
+
+    let mut features = Vec::new();
+    for (line, record) in rdr.records().enumerate() {
+        if let Some(feature) = to_feature(&headers, index, &record?, line)? {
+            features.push(feature);
+        }
+    }
+
+    Ok(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    })
+}
+
+/// 行数を数え、先頭の `sample` 行だけを Feature にする（`--dry-run` 用）
+pub fn inspect(
+    path: &str,
+    delimiter: u8,
+    geometry_column: Option<&str>,
+    sample: usize,
+) -> Result<(usize, Vec<Feature>), Box<dyn Error>> {
+    let mut rdr = ReaderBuilder::new().delimiter(delimiter).from_path(path)?;
+    let headers = rdr.headers()?.clone();
+    let index = geometry_index(&headers, geometry_column)?;
+
+    let mut count = 0;
+    let mut features = Vec::new();
+    for (line, record) in rdr.records().enumerate() {
+        let record = record?;
+        count += 1;
+        if features.len() < sample {
+            features.extend(to_feature(&headers, index, &record, line)?);
+        }
+    }
+    Ok((count, features))
+}
+
+/// ジオメトリ列の位置
+fn geometry_index(headers: &StringRecord, geometry_column: Option<&str>) -> Result<usize, String> {
+    match geometry_column {
+        Some(name) => headers.iter().position(|h| h == name),
+        None => headers
+            .iter()
+            .position(|h| GEOMETRY_COLUMNS.iter().any(|c| h.eq_ignore_ascii_case(c))),
+    }
+    .ok_or_else(|| {
+        format!(
+            "ジオメトリ列が見つかりません (--geometry-column で指定してください。列: {})",
+            headers.iter().collect::<Vec<_>>().join(", ")
+        )
+    })
+}
+
+/// 1 行を Feature にする（ジオメトリ列が空の行は None）。`line` は 0 始まりのデータ行の番号
+fn to_feature(
+    headers: &StringRecord,
+    index: usize,
+    record: &StringRecord,
+    line: usize,
+) -> Result<Option<Feature>, String> {
+    let text = record.get(index).unwrap_or("");
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let geometry = if wkb::looks_like_hex(text) {
+        Some(wkb::parse_hex(text))
+    } else {
+        wkt::parse(text).transpose()
+    };
+    let geometry = match geometry {
+        Some(Ok(value)) => Some(geojson::Geometry::new(value)),
+        Some(Err(err)) => return Err(format!("{} 行目: {}", line + 2, err)),
+        None => None,
+    };
+
+    let mut properties = JsonObject::new();
+    for (i, (header, value)) in headers.iter().zip(record.iter()).enumerate() {
+        if i != index {
+            properties.insert(header.to_string(), JsonValue::from(value));
+        }
+    }
+
+    Ok(Some(Feature {
+        bbox: None,
+        geometry,
+        id: None,
+        properties: Some(properties),
+        foreign_members: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wkt_and_hex_wkb_columns_become_geometries() {
+        let data = "name\tWKT\n川越市\tPOINT (139.48 35.92)\n所沢市\t0101000000000000000000F03F0000000000000040\n";
+        let collection = parse(data.as_bytes(), b'\t', None).unwrap();
+        assert_eq!(collection.features.len(), 2);
+        let feature = &collection.features[1];
+        assert_eq!(
+            feature.geometry.as_ref().unwrap().value,
+            geojson::Value::Point(vec![1.0, 2.0])
+        );
+        assert_eq!(feature.property("name"), Some(&JsonValue::from("所沢市")));
+        // ジオメトリの列は properties に含めない
+        assert!(feature.property("WKT").is_none());
+    }
+
+    #[test]
+    fn errors_name_the_columns_and_the_line() {
+        let err = parse(b"name,shape\na,POINT (0 0)\n", b',', None).unwrap_err();
+        assert!(err.to_string().ends_with("列: name, shape)"), "{}", err);
+        let err = parse(
+            b"name,shape\na,POINT (0 0)\nb,POINT (1\n",
+            b',',
+            Some("shape"),
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("3 行目: "), "{}", err);
+    }
+}
@@ -0,0 +1,130 @@
+//! 3 次元以上の座標（Z / M 値）を持つジオメトリの扱い。
+//! 書き出したツールによっては使われていない高さ（Z = 0 など）が残っていることがあるため、
+//! 読み込んだ後に x と y 以外の値を捨てる（面積などはもともと x と y だけで求める）。
+
+use geojson::{Feature, FeatureCollection, PointType, Value};
+use rayon::prelude::*;
+
+/// 座標の x と y 以外の値を捨て、捨てた値を持っていた Feature の数を返す。
+/// x と y のそろっていない座標があればエラー（何番目の Feature か添える）
+pub fn drop_extra(collection: &mut FeatureCollection) -> Result<usize, String> {
+    let dropped = collection
+        .features
+        .par_iter_mut()
+        .enumerate()
+        .map(|(i, feature)| match &mut feature.geometry {
+            Some(geometry) => {
+                drop_value(&mut geometry.value).map_err(|err| format!("features[{}]: {}", i, err))
+            }
+            None => Ok(false),
+        })
+        .collect::<Result<Vec<bool>, String>>()?;
+    Ok(dropped.into_iter().filter(|&dropped| dropped).count())
+}
+
+/// 1 つの Feature の座標の x と y 以外の値を捨てる（捨てた値があれば true）
+pub fn drop_feature(feature: &mut Feature) -> Result<bool, String> {
+    match &mut feature.geometry {
+        Some(geometry) => drop_value(&mut geometry.value),
+        None => Ok(false),
+    }
+}
+
+/// ジオメトリの座標の余分な値を捨てる（捨てた値があれば true）
+fn drop_value(value: &mut Value) -> Result<bool, String> {
+    let mut dropped = false;
+    match value {
+        Value::Point(position) => dropped = drop_position(position)?,
+        Value::MultiPoint(points) | Value::LineString(points) => dropped = drop_positions(points)?,
+        Value::MultiLineString(lines) | Value::Polygon(lines) => {
+            for line in lines {
+                dropped |= drop_positions(line)?;
+            }
+        }
+        Value::MultiPolygon(polygons) => {
+            for ring in polygons.iter_mut().flatten() {
+                dropped |= drop_positions(ring)?;
+            }
+        }
+        Value::GeometryCollection(geometries) => {
+            for geometry in geometries {
+                dropped |= drop_value(&mut geometry.value)?;
+            }
+        }
+    }
+    Ok(dropped)
+}
+
+fn drop_positions(positions: &mut [PointType]) -> Result<bool, String> {
+    let mut dropped = false;
+    for position in positions {
+        dropped |= drop_position(position)?;
+    }
+    Ok(dropped)
+}
+
+fn drop_position(position: &mut PointType) -> Result<bool, String> {
+    match position.len() {
+        0 | 1 => Err(format!(
+            "座標には x と y が必要です: {:?}",
+            position.as_slice()
+        )),
+        2 => Ok(false),
+        _ => {
+            position.truncate(2);
+            Ok(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geojson::Geometry;
+
+    fn feature(value: Value) -> Feature {
+        Feature {
+            geometry: Some(Geometry::new(value)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn extra_values_are_dropped_and_counted_per_feature() {
+        let mut collection = FeatureCollection {
+            bbox: None,
+            features: vec![
+                feature(Value::Polygon(vec![vec![
+                    vec![0.0, 0.0, 5.0],
+                    vec![1.0, 0.0, 5.0],
+                    vec![0.0, 1.0, 5.0, 9.0],
+                    vec![0.0, 0.0, 5.0],
+                ]])),
+                feature(Value::Point(vec![1.0, 2.0])),
+                Feature::default(),
+            ],
+            foreign_members: None,
+        };
+        assert_eq!(drop_extra(&mut collection).unwrap(), 1);
+        match &collection.features[0].geometry.as_ref().unwrap().value {
+            Value::Polygon(rings) => assert!(rings[0].iter().all(|p| p.len() == 2)),
+            other => panic!("Polygon ではありません: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn positions_without_y_are_errors() {
+        let mut collection = FeatureCollection {
+            bbox: None,
+            features: vec![
+                feature(Value::Point(vec![1.0, 2.0])),
+                feature(Value::LineString(vec![vec![1.0, 2.0], vec![3.0]])),
+            ],
+            foreign_members: None,
+        };
+        assert_eq!(
+            drop_extra(&mut collection).unwrap_err(),
+            "features[1]: 座標には x と y が必要です: [3.0]"
+        );
+    }
+}
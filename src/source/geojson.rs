@@ -0,0 +1,357 @@
+use super::Schema;
+use crate::{cancel::CancellationToken, ids, log};
+use geojson::{Feature, FeatureCollection, GeoJson, JsonObject, JsonValue};
+use rayon::prelude::*;
+use std::{error::Error, fs, ops::Range};
+
+/// GeoJSON ファイルを読み込む。
+/// JSON の解析は 1 スレッドでは時間がかかるため、`features` 配列の要素の境目だけを先に走査し、
+/// Feature ごとに並列に解析する。配列が見つからない場合は全体をそのまま解析する。
+/// 最上位が Feature やジオメトリの場合は、それを 1 つだけ含む FeatureCollection にする
+/// （ジオメトリだけの場合はプロパティがないため、集計キーを持たない Feature になる）。
+pub fn read(path: &str) -> Result<FeatureCollection, Box<dyn Error>> {
+    parse(path, &fs::read_to_string(path)?)
+}
+
+/// 読み込んだ GeoJSON のテキストを解析する（`path` はエラーのメッセージ用）
+pub fn parse(path: &str, text: &str) -> Result<FeatureCollection, Box<dyn Error>> {
+    Ok(parse_until(path, text, &CancellationToken::new())?.0)
+}
+
+/// `parse` と同じく解析し、`cancel` が中断されると残りの Feature を解析せずにやめる。
+/// 中断された場合は先頭から解析し終えた Feature までを返す（2 つ目の値が false になる）
+pub fn parse_until(
+    path: &str,
+    text: &str,
+    cancel: &CancellationToken,
+) -> Result<(FeatureCollection, bool), Box<dyn Error>> {
+    let text = text.trim_start_matches('\u{feff}');
+
+    let mut complete = true;
+    let geojson: GeoJson = match features_array(text) {
+        Some((array, elements)) => {
+            let parsed: Vec<Option<Result<Feature, String>>> = elements
+                .par_iter()
+                .enumerate()
+                .map(|(i, range)| {
+                    if cancel.is_cancelled() {
+                        return None;
+                    }
+                    Some(parse_element(path, i, &text[range.clone()]))
+                })
+                .collect();
+            complete = parsed.iter().all(Option::is_some);
+            let features = parsed
+                .into_iter()
+                .map_while(|feature| feature)
+                .collect::<Result<Vec<_>, _>>()?;
+            // features 以外（type, bbox, crs など）は配列を空にした残りから読む
+            let rest = format!("{}[]{}", &text[..array.start], &text[array.end..]);
+            match rest.parse::<GeoJson>() {
+                Ok(GeoJson::FeatureCollection(collection)) => {
+                    GeoJson::FeatureCollection(FeatureCollection {
+                        features,
+                        ..collection
+                    })
+                }
+                // features が最上位の Feature の独自のメンバーだった場合などは、全体を解析し直す
+                _ => parse_root(path, text)?,
+            }
+        }
+        None => parse_root(path, text)?,
+    };
+
+    let collection = match geojson {
+        GeoJson::FeatureCollection(collection) => collection,
+        GeoJson::Feature(feature) => FeatureCollection {
+            bbox: None,
+            features: vec![feature],
+            foreign_members: None,
+        },
+        GeoJson::Geometry(geometry) => {
+            log::warning!(
+                "警告: {} はジオメトリだけの GeoJSON のため、プロパティ (集計キー) のない 1 つの Feature として読み込みます",
+                path
+            );
+            FeatureCollection {
+                bbox: None,
+                features: vec![Feature {
+                    bbox: None,
+                    geometry: Some(geometry),
+                    id: None,
+                    properties: None,
+                    foreign_members: None,
+                }],
+                foreign_members: None,
+            }
+        }
+    };
+    Ok((collection, complete))
+}
+
+/// `features` 配列の `i` 番目の要素を解析する。
+/// エラーの位置は Feature の中での行と列になるため、何番目の Feature か（と ID）を添える
+pub fn parse_element(path: &str, i: usize, element: &str) -> Result<Feature, String> {
+    element
+        .parse::<Feature>()
+        .map_err(|err| match ids::from_json(element) {
+            Some(id) => format!("{}: features[{}] (id: {}): {}", path, i, id, err),
+            None => format!("{}: features[{}]: {}", path, i, err),
+        })
+}
+
+/// 最上位が FeatureCollection の場合の、`features` 配列の要素の範囲（`parse_element` で解析する）。
+/// 配列が見つからないか、最上位が FeatureCollection でなければ None
+pub fn elements(text: &str) -> Option<Vec<Range<usize>>> {
+    let trimmed = text.trim_start_matches('\u{feff}');
+    let bom = text.len() - trimmed.len();
+    let (array, elements) = features_array(trimmed)?;
+    let rest = format!("{}[]{}", &trimmed[..array.start], &trimmed[array.end..]);
+    match rest.parse::<GeoJson>() {
+        Ok(GeoJson::FeatureCollection(_)) => Some(
+            elements
+                .into_iter()
+                .map(|range| range.start + bom..range.end + bom)
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// GeoJSON 全体を解析する。GeoJSON として使えない場合は、最上位の何が問題かを伝える
+fn parse_root(path: &str, text: &str) -> Result<GeoJson, Box<dyn Error>> {
+    let err = match text.parse::<GeoJson>() {
+        Ok(geojson) => return Ok(geojson),
+        Err(err) => err,
+    };
+    let message = match serde_json::from_str::<JsonValue>(text) {
+        Err(_) => format!("{}: JSON として解析できません: {}", path, err),
+        Ok(JsonValue::Object(object)) => match object.get("type") {
+            Some(JsonValue::String(kind)) if !ROOT_TYPES.contains(&kind.as_str()) => format!(
+                "{}: GeoJSON の最上位の type が {} です (FeatureCollection, Feature, ジオメトリ ({}) のいずれかにしてください)",
+                path,
+                kind,
+                ROOT_TYPES[2..].join(", ")
+            ),
+            Some(_) => format!("{}: GeoJSON として解析できません: {}", path, err),
+            None => format!(
+                "{}: GeoJSON の最上位のオブジェクトに type がありません (FeatureCollection などを指定してください)",
+                path
+            ),
+        },
+        Ok(value) => format!(
+            "{}: GeoJSON の最上位が JSON のオブジェクトではありません ({})",
+            path,
+            json_kind(&value)
+        ),
+    };
+    Err(message.into())
+}
+
+/// GeoJSON の最上位に置ける type
+const ROOT_TYPES: [&str; 9] = [
+    "FeatureCollection",
+    "Feature",
+    "Point",
+    "MultiPoint",
+    "LineString",
+    "MultiLineString",
+    "Polygon",
+    "MultiPolygon",
+    "GeometryCollection",
+];
+
+fn json_kind(value: &JsonValue) -> &'static str {
+    match value {
+        JsonValue::Null => "null",
+        JsonValue::Bool(_) => "真偽値",
+        JsonValue::Number(_) => "数値",
+        JsonValue::String(_) => "文字列",
+        JsonValue::Array(_) => "配列",
+        JsonValue::Object(_) => "オブジェクト",
+    }
+}
+
+/// Feature の数を数え、先頭の `sample` 個だけを解析する（`--dry-run` 用）。
+/// 座標参照系は旧仕様の `crs` メンバーの名前
+pub fn inspect(path: &str, sample: usize) -> Result<Schema, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    let text = text.trim_start_matches('\u{feff}');
+
+    let Some((array, elements)) = features_array(text) else {
+        // 配列の境目がわからない場合は全体を解析する
+        let collection = read(path)?;
+        let count = collection.features.len();
+        let crs = crs_name(collection.foreign_members.as_ref());
+        let mut features = collection.features;
+        features.truncate(sample);
+        return Ok(Schema {
+            feature_count: Some(count),
+            sample: Some(features),
+            crs,
+        });
+    };
+    let features = elements
+        .iter()
+        .take(sample)
+        .enumerate()
+        .map(|(i, range)| {
+            text[range.clone()]
+                .parse::<Feature>()
+                .map_err(|err| format!("{}: features[{}]: {}", path, i, err))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let rest = format!("{}[]{}", &text[..array.start], &text[array.end..]);
+    let crs = match serde_json::from_str::<JsonValue>(&rest)? {
+        JsonValue::Object(object) => crs_name(Some(&object)),
+        _ => None,
+    };
+    Ok(Schema {
+        feature_count: Some(elements.len()),
+        sample: Some(features),
+        crs,
+    })
+}
+
+/// `"crs": {"type": "name", "properties": {"name": ...}}` の名前
+fn crs_name(members: Option<&JsonObject>) -> Option<String> {
+    members?
+        .get("crs")?
+        .get("properties")?
+        .get("name")?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// 最上位のオブジェクトの `features` 配列の範囲（角括弧を含む）と、各要素の範囲を探す。
+/// 文字列の中の括弧やカンマは数えない。JSON として不正な場合は None（解析は serde に任せる）
+fn features_array(text: &str) -> Option<(Range<usize>, Vec<Range<usize>>)> {
+    let bytes = text.as_bytes();
+    let mut depth = 0usize;
+    let mut i = 0;
+    // 最上位のオブジェクトの中で、直前に読んだ文字列（キーかもしれない）
+    let mut last_string = None;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                let end = string_end(bytes, i)?;
+                if depth == 1 {
+                    last_string = Some(i + 1..end - 1);
+                }
+                i = end;
+                continue;
+            }
+            b':' if depth == 1 => {
+                let is_features = last_string
+                    .take()
+                    .is_some_and(|key| &bytes[key] == b"features");
+                let start = skip_whitespace(bytes, i + 1);
+                if is_features && bytes.get(start) == Some(&b'[') {
+                    return array_elements(bytes, start);
+                }
+            }
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth = depth.checked_sub(1)?,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// `start` の `[` から始まる配列の範囲と、その要素の範囲（前後の空白を除く）
+fn array_elements(bytes: &[u8], start: usize) -> Option<(Range<usize>, Vec<Range<usize>>)> {
+    let mut elements = Vec::new();
+    let mut depth = 0usize;
+    let mut element_start = start + 1;
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                i = string_end(bytes, i)?;
+                continue;
+            }
+            b'{' | b'[' => depth += 1,
+            b'}' => depth = depth.checked_sub(1)?,
+            b']' if depth > 0 => depth -= 1,
+            b',' | b']' if depth == 0 => {
+                let element = trim(bytes, element_start..i);
+                if !element.is_empty() {
+                    elements.push(element);
+                }
+                if bytes[i] == b']' {
+                    return Some((start..i + 1, elements));
+                }
+                element_start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// `start` の `"` から始まる文字列の終わりの次の位置（エスケープを考慮する）
+fn string_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+fn skip_whitespace(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    i
+}
+
+fn trim(bytes: &[u8], range: Range<usize>) -> Range<usize> {
+    let start = skip_whitespace(bytes, range.start).min(range.end);
+    let mut end = range.end;
+    while end > start && bytes[end - 1].is_ascii_whitespace() {
+        end -= 1;
+    }
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = r#"{"type":"FeatureCollection","features":[
+        {"type":"Feature","properties":{"N03_004":"川越市"},"geometry":null},
+        {"type":"Feature","properties":{"N03_004":"所沢市"},"geometry":null}
+    ]}"#;
+
+    #[test]
+    fn features_are_parsed_in_parallel() {
+        let collection = parse("test.geojson", TEXT).unwrap();
+        assert_eq!(collection.features.len(), 2);
+        assert_eq!(
+            collection.features[1].property("N03_004"),
+            Some(&JsonValue::from("所沢市"))
+        );
+    }
+
+    #[test]
+    fn parsing_stops_when_cancelled() {
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let (collection, complete) = parse_until("test.geojson", TEXT, &cancel).unwrap();
+        assert!(!complete);
+        assert!(collection.features.is_empty());
+    }
+
+    #[test]
+    fn errors_name_the_feature() {
+        let text = r#"{"type":"FeatureCollection","features":[{"type":"Feature","id":"a1","geometry":{"type":"Nope"},"properties":null}]}"#;
+        let err = parse("test.geojson", text).unwrap_err().to_string();
+        assert!(err.contains("features[0] (id: a1)"), "{}", err);
+    }
+}
@@ -0,0 +1,291 @@
+//! GeoPackage（SQLite のファイルにベクターのレイヤーを入れたもの）の読み込み。
+//!
+//! gpkg_contents に features として登録されたテーブルを 1 つのレイヤーとし、ジオメトリの列
+//! （GeoPackage のヘッダーを付けた WKB）とほかの列をプロパティにした Feature を読み込む。
+//! INTEGER PRIMARY KEY の列（fid など）は Feature の ID にする。
+//! 座標は変換しないため、経度・緯度の座標参照系（EPSG:4326, 6668 など）でなければ警告する。
+
+use super::{
+    sqlite::{Database, Table, Value},
+    wkb, Layer, LAYER_PROPERTY,
+};
+use crate::log;
+use geojson::{feature::Id, Feature, FeatureCollection, Geometry, JsonObject, JsonValue};
+use rayon::prelude::*;
+use std::{collections::HashMap, error::Error};
+
+/// features のテーブルとジオメトリの列
+struct FeatureTable {
+    name: String,
+    geometry_column: String,
+    geometry_type: Option<String>,
+    srs_id: Option<i64>,
+}
+
+/// レイヤーの一覧（Feature の数はテーブルの行数）
+pub fn layers(path: &str) -> Result<Vec<Layer>, Box<dyn Error>> {
+    let db = Database::open(path)?;
+    let mut layers = Vec::new();
+    for table in feature_tables(&db, path)? {
+        let count = db.count(&open_table(&db, &table.name)?)?;
+        layers.push(Layer {
+            name: table.name,
+            features: Some(count),
+            geometry: table.geometry_type,
+        });
+    }
+    Ok(layers)
+}
+
+/// `wanted` のレイヤー（空ならすべてのレイヤー）を読み込む。Feature の `layer` プロパティにレイヤーの名前を入れる
+pub fn read(path: &str, wanted: &[String]) -> Result<FeatureCollection, Box<dyn Error>> {
+    let db = Database::open(path)?;
+    let tables = super::select_layers(path, feature_tables(&db, path)?, wanted, |table| {
+        &table.name
+    })?;
+    let geographic = geographic_srs(&db)?;
+
+    let mut features = Vec::new();
+    for table in tables {
+        if let Some(srs_id) = table.srs_id {
+            if geographic.get(&srs_id) == Some(&false) {
+                log::warning!(
+                    "警告: {} のレイヤー {} の座標参照系 (srs_id {}) は経度・緯度ではありません（座標は変換せずに使います）",
+                    path, table.name, srs_id
+                );
+            }
+        }
+        let sqlite_table = open_table(&db, &table.name)?;
+        let geometry = sqlite_table.column(&table.geometry_column)?;
+        let mut records = Vec::new();
+        db.scan(&sqlite_table, |values| {
+            records.push(values);
+            Ok(())
+        })?;
+        let decoded = records
+            .into_par_iter()
+            .enumerate()
+            .map(|(i, values)| {
+                feature(&sqlite_table, geometry, values, &table.name).map_err(|err| {
+                    format!(
+                        "{}: レイヤー {} の {} 行目: {}",
+                        path,
+                        table.name,
+                        i + 1,
+                        err
+                    )
+                })
+            })
+            .collect::<Result<Vec<Feature>, String>>()?;
+        features.extend(decoded);
+    }
+
+    Ok(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    })
+}
+
+/// gpkg_contents に features として登録されたテーブル（登録の順）
+fn feature_tables(db: &Database, path: &str) -> Result<Vec<FeatureTable>, String> {
+    let contents = db.table("gpkg_contents")?.ok_or_else(|| {
+        format!(
+            "{} は GeoPackage ではありません (gpkg_contents テーブルがありません)",
+            path
+        )
+    })?;
+    let (name, data_type) = (
+        contents.column("table_name")?,
+        contents.column("data_type")?,
+    );
+    let mut names = Vec::new();
+    db.scan(&contents, |values| {
+        if text(&values[data_type]) == Some("features") {
+            names.extend(text(&values[name]).map(str::to_string));
+        }
+        Ok(())
+    })?;
+
+    let columns = db
+        .table("gpkg_geometry_columns")?
+        .ok_or("GeoPackage に gpkg_geometry_columns テーブルがありません")?;
+    let (table_name, column_name, type_name, srs_id) = (
+        columns.column("table_name")?,
+        columns.column("column_name")?,
+        columns.column("geometry_type_name")?,
+        columns.column("srs_id")?,
+    );
+    let mut geometry_columns = HashMap::new();
+    db.scan(&columns, |values| {
+        if let (Some(table), Some(column)) = (text(&values[table_name]), text(&values[column_name]))
+        {
+            geometry_columns.insert(
+                table.to_lowercase(),
+                (
+                    column.to_string(),
+                    text(&values[type_name]).map(str::to_string),
+                    values[srs_id].integer(),
+                ),
+            );
+        }
+        Ok(())
+    })?;
+
+    names
+        .into_iter()
+        .map(|name| {
+            let (geometry_column, geometry_type, srs_id) = geometry_columns
+                .remove(&name.to_lowercase())
+                .ok_or_else(|| format!("レイヤー {} のジオメトリの列が登録されていません", name))?;
+            Ok(FeatureTable {
+                name,
+                geometry_column,
+                geometry_type,
+                srs_id,
+            })
+        })
+        .collect()
+}
+
+fn open_table(db: &Database, name: &str) -> Result<Table, String> {
+    db.table(name)?
+        .ok_or_else(|| format!("レイヤー {} のテーブルがありません", name))
+}
+
+/// 座標参照系ごとに経度・緯度かどうか（WKT の定義が GEOGCS などで始まるか）
+fn geographic_srs(db: &Database) -> Result<HashMap<i64, bool>, String> {
+    let mut geographic = HashMap::new();
+    let Some(table) = db.table("gpkg_spatial_ref_sys")? else {
+        return Ok(geographic);
+    };
+    let (srs_id, definition) = (table.column("srs_id")?, table.column("definition")?);
+    db.scan(&table, |values| {
+        if let (Some(id), Some(definition)) = (values[srs_id].integer(), text(&values[definition]))
+        {
+            // -1（未定義の直交座標）と 0（未定義の経度・緯度）は、何の座標かわからないため警告しない
+            let definition = definition.trim_start().to_uppercase();
+            let is_geographic = id <= 0
+                || ["GEOGCS", "GEOGCRS", "GEODCRS"]
+                    .iter()
+                    .any(|keyword| definition.starts_with(keyword));
+            geographic.insert(id, is_geographic);
+        }
+        Ok(())
+    })?;
+    Ok(geographic)
+}
+
+/// 1 行を Feature にする
+fn feature(
+    table: &Table,
+    geometry: usize,
+    values: Vec<Value>,
+    layer: &str,
+) -> Result<Feature, String> {
+    let mut properties = JsonObject::new();
+    let mut id = None;
+    let mut shape = None;
+    for (i, (column, value)) in table.columns().iter().zip(values).enumerate() {
+        if i == geometry {
+            if let Some(blob) = value.blob() {
+                shape = decode(blob)?;
+            }
+            continue;
+        }
+        if Some(i) == table.rowid_column() {
+            id = value.integer().map(|rowid| Id::Number(rowid.into()));
+            continue;
+        }
+        let value = match value {
+            Value::Null | Value::Blob(_) => JsonValue::Null,
+            Value::Integer(v) => v.into(),
+            Value::Real(v) => v.into(),
+            Value::Text(s) => s.into(),
+        };
+        properties.insert(column.clone(), value);
+    }
+    properties.insert(LAYER_PROPERTY.to_string(), layer.into());
+    Ok(Feature {
+        bbox: None,
+        geometry: shape.map(Geometry::new),
+        id,
+        properties: Some(properties),
+        foreign_members: None,
+    })
+}
+
+/// GeoPackage のジオメトリ（"GP"、バージョン、フラグ、SRS ID、範囲の後に WKB）を解析する。空のジオメトリは None
+fn decode(blob: &[u8]) -> Result<Option<geojson::Value>, String> {
+    if blob.len() < 8 || &blob[..2] != b"GP" {
+        return Err("GeoPackage のジオメトリのヘッダーがありません".to_string());
+    }
+    let flags = blob[3];
+    if flags & 0b10_0000 != 0 {
+        return Err("拡張された GeoPackage のジオメトリには対応していません".to_string());
+    }
+    if flags & 0b1_0000 != 0 {
+        return Ok(None);
+    }
+    let envelope = match (flags >> 1) & 0b111 {
+        0 => 0,
+        1 => 32,
+        2 | 3 => 48,
+        4 => 64,
+        other => {
+            return Err(format!(
+                "GeoPackage のジオメトリの範囲の種類が不正です: {}",
+                other
+            ))
+        }
+    };
+    let body = blob
+        .get(8 + envelope..)
+        .ok_or("GeoPackage のジオメトリが途中で終わっています")?;
+    wkb::parse(body).map(Some)
+}
+
+fn text(value: &Value) -> Option<&str> {
+    match value {
+        Value::Text(s) => Some(s),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ヘッダー（範囲の種類 `envelope`、リトルエンディアン）と WKB の点
+    fn blob(flags: u8, envelope: usize) -> Vec<u8> {
+        let mut blob = b"GP\0".to_vec();
+        blob.push(flags);
+        blob.extend(4326i32.to_le_bytes());
+        blob.extend(std::iter::repeat_n(0, envelope));
+        blob.push(1);
+        blob.extend(1u32.to_le_bytes());
+        blob.extend(139.5f64.to_le_bytes());
+        blob.extend(35.9f64.to_le_bytes());
+        blob
+    }
+
+    #[test]
+    fn the_envelope_is_skipped_before_the_wkb() {
+        let point = Some(geojson::Value::Point(vec![139.5, 35.9]));
+        assert_eq!(decode(&blob(0b1, 0)).unwrap(), point);
+        assert_eq!(decode(&blob(0b1 | (1 << 1), 32)).unwrap(), point);
+        assert_eq!(decode(&blob(0b1 | (4 << 1), 64)).unwrap(), point);
+        // 空のジオメトリ
+        assert_eq!(decode(&blob(0b1_0001, 0)).unwrap(), None);
+    }
+
+    #[test]
+    fn malformed_headers_are_errors() {
+        assert!(decode(b"XX\0\x01\0\0\0\0").is_err());
+        assert_eq!(
+            decode(&blob(0b1 | (5 << 1), 0)).unwrap_err(),
+            "GeoPackage のジオメトリの範囲の種類が不正です: 5"
+        );
+        assert!(decode(&blob(0b10_0001, 0)).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use super::{
+    xml::{self, Element},
+    zip::unzip,
+};
+use geojson::{Feature, FeatureCollection, JsonObject, JsonValue, Value};
+use std::{error::Error, fs};
+
+/// KML ファイルを読み込む
+pub fn read(path: &str) -> Result<FeatureCollection, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    parse(&text)
+}
+
+/// KMZ（KML を ZIP にまとめたもの）を unzip で展開して読み込む
+pub fn read_kmz(path: &str) -> Result<FeatureCollection, Box<dyn Error>> {
+    // KMZ では最初の .kml ファイル（通常は doc.kml）が本体になる
+    let list = unzip(&["-Z1", path])?;
+    let entry = list
+        .lines()
+        .find(|name| name.to_lowercase().ends_with(".kml"))
+        .ok_or_else(|| format!("{} に KML ファイルが含まれていません", path))?;
+    let text = unzip(&["-p", path, entry])?;
+    parse(&text)
+}
+
+/// Placemark のうちポリゴンを持つものを Feature に変換する
+fn parse(text: &str) -> Result<FeatureCollection, Box<dyn Error>> {
+    let root = xml::parse(text)?;
+
+    let mut placemarks = Vec::new();
+    root.descendants("Placemark", &mut placemarks);
+
+    let features = placemarks
+        .into_iter()
+        .filter_map(|placemark| {
+            let geometry = polygons(placemark)?;
+            Some(Feature {
+                bbox: None,
+                geometry: Some(geojson::Geometry::new(geometry)),
+                id: placemark
+                    .attribute("id")
+                    .map(|id| geojson::feature::Id::String(id.to_string())),
+                properties: Some(properties(placemark)),
+                foreign_members: None,
+            })
+        })
+        .collect();
+
+    Ok(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    })
+}
+
+/// Placemark 内の Polygon を集める（MultiGeometry の場合は MultiPolygon にする）
+fn polygons(placemark: &Element) -> Option<Value> {
+    let mut found = Vec::new();
+    placemark.descendants("Polygon", &mut found);
+
+    let mut polygons: Vec<Vec<Vec<Vec<f64>>>> = found
+        .into_iter()
+        .filter_map(|polygon| {
+            let mut rings = Vec::new();
+            for (boundary, max) in [("outerBoundaryIs", Some(1)), ("innerBoundaryIs", None)] {
+                for element in polygon.elements().filter(|e| e.is(boundary)) {
+                    let mut found = Vec::new();
+                    element.descendants("coordinates", &mut found);
+                    let limit = max.unwrap_or(found.len());
+                    rings.extend(
+                        found
+                            .into_iter()
+                            .take(limit)
+                            .map(|c| coordinates(&c.text())),
+                    );
+                }
+            }
+            // 外周がないものは無視する
+            if rings.first().is_none_or(|ring| ring.len() < 4) {
+                return None;
+            }
+            Some(rings)
+        })
+        .collect();
+
+    match polygons.len() {
+        0 => None,
+        1 => Some(Value::Polygon(polygons.pop().unwrap())),
+        _ => Some(Value::MultiPolygon(polygons)),
+    }
+}
+
+/// `経度,緯度[,高度]` を空白区切りで並べた座標列を解析する（高度は捨てる）
+fn coordinates(text: &str) -> Vec<Vec<f64>> {
+    text.split_whitespace()
+        .filter_map(|tuple| {
+            let mut values = tuple.split(',').map(|v| v.trim().parse::<f64>());
+            match (values.next(), values.next()) {
+                (Some(Ok(lon)), Some(Ok(lat))) => Some(vec![lon, lat]),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// name, description と ExtendedData（Data / SchemaData）を properties にする
+fn properties(placemark: &Element) -> JsonObject {
+    let mut properties = JsonObject::new();
+    for key in ["name", "description"] {
+        if let Some(element) = placemark.child(key) {
+            properties.insert(key.to_string(), JsonValue::from(element.text().trim()));
+        }
+    }
+
+    if let Some(extended) = placemark.child("ExtendedData") {
+        let mut data = Vec::new();
+        extended.descendants("Data", &mut data);
+        for element in data {
+            if let (Some(name), Some(value)) = (element.attribute("name"), element.child("value")) {
+                properties.insert(name.to_string(), JsonValue::from(value.text().trim()));
+            }
+        }
+
+        let mut simple = Vec::new();
+        extended.descendants("SimpleData", &mut simple);
+        for element in simple {
+            if let Some(name) = element.attribute("name") {
+                properties.insert(name.to_string(), JsonValue::from(element.text().trim()));
+            }
+        }
+    }
+    properties
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placemarks_with_polygons_become_features() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2"><Document>
+  <Placemark id="p1">
+    <name> 川越市 </name>
+    <ExtendedData><SchemaData><SimpleData name="N03_007">11201</SimpleData></SchemaData></ExtendedData>
+    <MultiGeometry>
+      <Polygon><outerBoundaryIs><LinearRing><coordinates>0,0,10 1,0,10 1,1,10 0,0,10</coordinates></LinearRing></outerBoundaryIs></Polygon>
+      <Polygon><outerBoundaryIs><LinearRing><coordinates>2,0 3,0 3,1 2,0</coordinates></LinearRing></outerBoundaryIs></Polygon>
+    </MultiGeometry>
+  </Placemark>
+  <Placemark><name>点</name><Point><coordinates>0,0</coordinates></Point></Placemark>
+</Document></kml>"#;
+        let collection = parse(text).unwrap();
+        assert_eq!(collection.features.len(), 1);
+        let feature = &collection.features[0];
+        assert_eq!(
+            feature.id,
+            Some(geojson::feature::Id::String("p1".to_string()))
+        );
+        assert_eq!(feature.property("name"), Some(&JsonValue::from("川越市")));
+        assert_eq!(feature.property("N03_007"), Some(&JsonValue::from("11201")));
+        match &feature.geometry.as_ref().unwrap().value {
+            Value::MultiPolygon(polygons) => {
+                assert_eq!(polygons.len(), 2);
+                // 高度は捨てる
+                assert_eq!(polygons[0][0][1], vec![1.0, 0.0]);
+            }
+            other => panic!("MultiPolygon ではありません: {:?}", other),
+        }
+    }
+}
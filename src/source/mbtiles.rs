@@ -0,0 +1,492 @@
+//! MBTiles に入ったベクタータイル（Mapbox Vector Tile）からポリゴンを取り出す。
+//!
+//! 指定したズームレベルのタイルをすべて並列に復号し、タイル座標を経度・緯度に戻す。
+//! タイルのバッファ部分が隣と重複しないよう、ポリゴンはタイルの範囲で切り抜いてから変換する。
+
+use super::protobuf::{zigzag, Field, Reader};
+use super::sqlite::{Database, Value};
+use super::{Layer, LAYER_PROPERTY};
+use crate::inflate;
+use geo::{BooleanOps, BoundingRect, Coord, LineString, MapCoords, MultiPolygon, Polygon, Rect};
+use geojson::{feature::Id, Feature, FeatureCollection, JsonObject, JsonValue};
+use rayon::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    f64::consts::PI,
+};
+
+/// 1 枚のタイル（y は MBTiles の TMS 方式で、南から数える）
+struct Tile {
+    zoom: u8,
+    column: u32,
+    row: u32,
+    data: Vec<u8>,
+}
+
+/// MBTiles を読み込み、ポリゴンの Feature を返す。
+/// `zoom` を省略した場合は最も詳細なズームレベルを、`layers` が空ならすべてのレイヤーを読む。
+/// Feature の `layer` プロパティにレイヤーの名前を入れる
+pub fn read(
+    path: &str,
+    zoom: Option<u8>,
+    layers: &[String],
+) -> Result<FeatureCollection, Box<dyn Error>> {
+    let db = Database::open(path)?;
+    // メタデータにレイヤーの一覧があれば、ないレイヤーを指定した場合にエラーにする
+    let listed = metadata_layers(&db)?;
+    if !listed.is_empty() {
+        super::select_layers(path, listed, layers, |layer| &layer.name)?;
+    }
+    let tiles = tiles(&db, zoom)?;
+    if tiles.is_empty() {
+        return Err(match zoom {
+            Some(zoom) => format!("{} にズームレベル {} のタイルがありません", path, zoom),
+            None => format!("{} にタイルがありません", path),
+        }
+        .into());
+    }
+
+    let features = tiles
+        .par_iter()
+        .map(|tile| decode_tile(tile, layers))
+        .collect::<Result<Vec<Vec<Feature>>, String>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+
+    Ok(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    })
+}
+
+/// レイヤーの一覧（メタデータの json の vector_layers と、あれば tilestats の Feature の数とジオメトリの種類）
+pub fn layers(path: &str) -> Result<Vec<Layer>, Box<dyn Error>> {
+    let layers = metadata_layers(&Database::open(path)?)?;
+    if layers.is_empty() {
+        return Err(format!(
+            "{} のメタデータにレイヤーの一覧 (vector_layers) がありません",
+            path
+        )
+        .into());
+    }
+    Ok(layers)
+}
+
+fn metadata_layers(db: &Database) -> Result<Vec<Layer>, String> {
+    let Some(table) = db.table("metadata")? else {
+        return Ok(Vec::new());
+    };
+    let (name, value) = (table.column("name")?, table.column("value")?);
+    let mut json = None;
+    db.scan(&table, |values| {
+        if let (Value::Text(key), Value::Text(text)) = (&values[name], &values[value]) {
+            if key == "json" {
+                json = Some(text.clone());
+            }
+        }
+        Ok(())
+    })?;
+    let Some(json) = json.and_then(|text| serde_json::from_str::<JsonValue>(&text).ok()) else {
+        return Ok(Vec::new());
+    };
+    let stats = json["tilestats"]["layers"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    Ok(json["vector_layers"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|layer| layer["id"].as_str())
+        .map(|id| {
+            let stat = stats.iter().find(|stat| stat["layer"].as_str() == Some(id));
+            Layer {
+                name: id.to_string(),
+                features: stat
+                    .and_then(|stat| stat["count"].as_u64())
+                    .map(|n| n as usize),
+                geometry: stat
+                    .and_then(|stat| stat["geometry"].as_str())
+                    .map(str::to_string),
+            }
+        })
+        .collect())
+}
+
+/// 対象のズームレベルのタイルを集める。
+/// tiles テーブルのほか、重複排除のために map と images に分けてビューにした構成にも対応する。
+fn tiles(db: &Database, zoom: Option<u8>) -> Result<Vec<Tile>, String> {
+    // ズームレベルが未指定なら、それまでに見つかった最大のズームレベルだけを残していく
+    let mut max_zoom = zoom;
+    let mut keep = |z: i64| -> bool {
+        let Ok(z) = u8::try_from(z) else {
+            return false;
+        };
+        match max_zoom {
+            Some(max) if zoom.is_some() => z == max,
+            Some(max) if z < max => false,
+            _ => {
+                max_zoom = Some(z);
+                true
+            }
+        }
+    };
+
+    if let Some(table) = db.table("tiles")? {
+        let (z, x, y, data) = (
+            table.column("zoom_level")?,
+            table.column("tile_column")?,
+            table.column("tile_row")?,
+            table.column("tile_data")?,
+        );
+        let mut found = Vec::new();
+        db.scan(&table, |mut values| {
+            if let (Some(zoom), Some(column), Some(row)) = (
+                values[z].integer(),
+                values[x].integer(),
+                values[y].integer(),
+            ) {
+                if keep(zoom) {
+                    let data = std::mem::replace(&mut values[data], Value::Null);
+                    found.push((zoom as u8, column as u32, row as u32, data));
+                }
+            }
+            Ok(())
+        })?;
+        let max_zoom = max_zoom;
+        return Ok(found
+            .into_iter()
+            .filter(|(zoom, ..)| Some(*zoom) == max_zoom)
+            .filter_map(|(zoom, column, row, data)| {
+                Some(Tile {
+                    zoom,
+                    column,
+                    row,
+                    data: data.blob()?.to_vec(),
+                })
+            })
+            .collect());
+    }
+
+    let (Some(map), Some(images)) = (db.table("map")?, db.table("images")?) else {
+        return Err(
+            "MBTiles に tiles テーブル（または map と images テーブル）がありません".to_string(),
+        );
+    };
+    let (z, x, y, id) = (
+        map.column("zoom_level")?,
+        map.column("tile_column")?,
+        map.column("tile_row")?,
+        map.column("tile_id")?,
+    );
+    let mut found = Vec::new();
+    db.scan(&map, |values| {
+        if let (Some(zoom), Some(column), Some(row), Some(id)) = (
+            values[z].integer(),
+            values[x].integer(),
+            values[y].integer(),
+            values[id].key(),
+        ) {
+            if keep(zoom) {
+                found.push((zoom as u8, column as u32, row as u32, id));
+            }
+        }
+        Ok(())
+    })?;
+    let max_zoom = max_zoom;
+    let found: Vec<(u8, u32, u32, String)> = found
+        .into_iter()
+        .filter(|(zoom, ..)| Some(*zoom) == max_zoom)
+        .collect();
+
+    let wanted: HashSet<&str> = found.iter().map(|(.., id)| id.as_str()).collect();
+    let mut images_by_id = HashMap::new();
+    let (id, data) = (images.column("tile_id")?, images.column("tile_data")?);
+    db.scan(&images, |mut values| {
+        if let Some(key) = values[id].key() {
+            if wanted.contains(key.as_str()) {
+                images_by_id.insert(key, std::mem::replace(&mut values[data], Value::Null));
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(found
+        .into_iter()
+        .filter_map(|(zoom, column, row, id)| {
+            Some(Tile {
+                zoom,
+                column,
+                row,
+                data: images_by_id.get(&id)?.blob()?.to_vec(),
+            })
+        })
+        .collect())
+}
+
+/// タイルを復号し、ポリゴンの Feature を取り出す
+fn decode_tile(tile: &Tile, layers: &[String]) -> Result<Vec<Feature>, String> {
+    // タイルは gzip（まれに zlib）で圧縮されていることが多い
+    let data = match tile.data.as_slice() {
+        [0x1F, 0x8B, ..] => inflate::gzip(&tile.data)?,
+        [0x78, ..] => inflate::zlib(&tile.data)?,
+        _ => tile.data.clone(),
+    };
+    let at = |err: String| {
+        format!(
+            "タイル {}/{}/{} を復号できません: {}",
+            tile.zoom, tile.column, tile.row, err
+        )
+    };
+
+    let mut features = Vec::new();
+    let mut reader = Reader::new(&data);
+    while let Some((number, field)) = reader.next_field().map_err(at)? {
+        if number == 3 {
+            decode_layer(tile, field.bytes().map_err(at)?, layers, &mut features).map_err(at)?;
+        }
+    }
+    Ok(features)
+}
+
+fn decode_layer(
+    tile: &Tile,
+    data: &[u8],
+    wanted: &[String],
+    out: &mut Vec<Feature>,
+) -> Result<(), String> {
+    let mut name = String::new();
+    let mut extent = 4096u32;
+    let mut keys = Vec::new();
+    let mut values = Vec::new();
+    let mut features = Vec::new();
+
+    let mut reader = Reader::new(data);
+    while let Some((number, field)) = reader.next_field()? {
+        match number {
+            1 => name = String::from_utf8_lossy(field.bytes()?).into_owned(),
+            2 => features.push(field.bytes()?),
+            3 => keys.push(String::from_utf8_lossy(field.bytes()?).into_owned()),
+            4 => values.push(decode_value(field.bytes()?)?),
+            5 => extent = field.varint()? as u32,
+            _ => {}
+        }
+    }
+    if !wanted.is_empty() && !wanted.contains(&name) {
+        return Ok(());
+    }
+
+    for data in features {
+        let mut id = None;
+        let mut tags = Vec::new();
+        let mut kind = 0;
+        let mut geometry = Vec::new();
+        let mut reader = Reader::new(data);
+        while let Some((number, field)) = reader.next_field()? {
+            match number {
+                1 => id = Some(field.varint()?),
+                2 => tags = field.packed()?,
+                3 => kind = field.varint()?,
+                4 => geometry = field.packed()?,
+                _ => {}
+            }
+        }
+        // 3 = POLYGON。点と線は面積を持たないので読み飛ばす
+        if kind != 3 {
+            continue;
+        }
+        let Some(polygons) = project(tile, extent, polygons(&geometry)?) else {
+            continue;
+        };
+
+        let mut properties = JsonObject::new();
+        for pair in tags.chunks_exact(2) {
+            if let (Some(key), Some(value)) =
+                (keys.get(pair[0] as usize), values.get(pair[1] as usize))
+            {
+                properties.insert(key.clone(), value.clone());
+            }
+        }
+        properties.insert(LAYER_PROPERTY.to_string(), JsonValue::from(name.as_str()));
+        out.push(Feature {
+            bbox: None,
+            geometry: Some(geojson::Geometry::new(geojson::Value::from(&polygons))),
+            id: id.map(|id| Id::Number(id.into())),
+            properties: Some(properties),
+            foreign_members: None,
+        });
+    }
+    Ok(())
+}
+
+/// レイヤーの値テーブルの要素
+fn decode_value(data: &[u8]) -> Result<JsonValue, String> {
+    let mut reader = Reader::new(data);
+    let mut value = JsonValue::Null;
+    while let Some((number, field)) = reader.next_field()? {
+        value = match (number, field) {
+            (1, Field::Bytes(s)) => JsonValue::from(String::from_utf8_lossy(s).into_owned()),
+            (2, Field::Fixed32(bits)) => JsonValue::from(f32::from_bits(bits) as f64),
+            (3, Field::Fixed64(bits)) => JsonValue::from(f64::from_bits(bits)),
+            (4, Field::Varint(v)) => JsonValue::from(v as i64),
+            (5, Field::Varint(v)) => JsonValue::from(v),
+            (6, Field::Varint(v)) => JsonValue::from(zigzag(v)),
+            (7, Field::Varint(v)) => JsonValue::from(v != 0),
+            _ => continue,
+        };
+    }
+    Ok(value)
+}
+
+/// ジオメトリのコマンド列をタイル座標のポリゴンにする。
+/// 符号付き面積が正のリングが外周、負のリングが直前の外周の穴になる。
+fn polygons(commands: &[u64]) -> Result<Vec<Polygon<f64>>, String> {
+    let mut rings = Vec::new();
+    let mut ring: Vec<Coord<f64>> = Vec::new();
+    let (mut x, mut y) = (0i64, 0i64);
+
+    let mut i = 0;
+    while i < commands.len() {
+        let (command, count) = (commands[i] & 7, (commands[i] >> 3) as usize);
+        i += 1;
+        match command {
+            // MoveTo / LineTo
+            1 | 2 => {
+                let params = commands
+                    .get(i..i + count * 2)
+                    .ok_or("ジオメトリのコマンドの引数が足りません")?;
+                i += count * 2;
+                if command == 1 {
+                    ring.clear();
+                }
+                for pair in params.chunks_exact(2) {
+                    x += zigzag(pair[0]);
+                    y += zigzag(pair[1]);
+                    ring.push(Coord {
+                        x: x as f64,
+                        y: y as f64,
+                    });
+                }
+            }
+            // ClosePath
+            7 => {
+                if ring.len() >= 3 {
+                    rings.push(LineString::new(std::mem::take(&mut ring)));
+                }
+            }
+            other => return Err(format!("不明なジオメトリのコマンドです: {}", other)),
+        }
+    }
+
+    let mut polygons: Vec<Polygon<f64>> = Vec::new();
+    for mut ring in rings {
+        ring.close();
+        let area = ring
+            .lines()
+            .map(|line| line.start.x * line.end.y - line.end.x * line.start.y)
+            .sum::<f64>();
+        if area > 0.0 {
+            polygons.push(Polygon::new(ring, vec![]));
+        } else if area < 0.0 {
+            if let Some(polygon) = polygons.last_mut() {
+                polygon.interiors_push(ring);
+            }
+        }
+    }
+    Ok(polygons)
+}
+
+/// タイルの範囲で切り抜き、タイル座標を経度・緯度に変換する
+fn project(tile: &Tile, extent: u32, polygons: Vec<Polygon<f64>>) -> Option<MultiPolygon<f64>> {
+    let extent = extent as f64;
+    let mut polygons = MultiPolygon::new(polygons);
+    let bounds = Rect::new(
+        Coord { x: 0.0, y: 0.0 },
+        Coord {
+            x: extent,
+            y: extent,
+        },
+    );
+    let inside = polygons.bounding_rect().is_some_and(|rect| {
+        rect.min().x >= 0.0
+            && rect.min().y >= 0.0
+            && rect.max().x <= extent
+            && rect.max().y <= extent
+    });
+    if !inside {
+        polygons = polygons.intersection(&MultiPolygon::new(vec![bounds.to_polygon()]));
+    }
+    if polygons.0.is_empty() {
+        return None;
+    }
+
+    // MBTiles の行番号は南から数えるので、北から数える XYZ 方式に直す
+    let n = 2f64.powi(tile.zoom as i32);
+    let (column, row) = (tile.column as f64, n - 1.0 - tile.row as f64);
+    Some(polygons.map_coords(|Coord { x, y }| {
+        let lon = (column + x / extent) / n * 360.0 - 180.0;
+        let lat = (PI * (1.0 - 2.0 * (row + y / extent) / n))
+            .sinh()
+            .atan()
+            .to_degrees();
+        Coord { x: lon, y: lat }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::Area;
+
+    /// MoveTo, LineTo, ClosePath のコマンド（引数は zigzag 符号化した差分）
+    fn ring(points: &[(i64, i64)]) -> Vec<u64> {
+        let encode = |v: i64| ((v << 1) ^ (v >> 63)) as u64;
+        let mut commands = Vec::new();
+        let mut previous = (0, 0);
+        for (i, &(x, y)) in points.iter().enumerate() {
+            if i == 0 {
+                commands.push(1 | (1 << 3));
+            } else if i == 1 {
+                commands.push(2 | (((points.len() - 1) as u64) << 3));
+            }
+            commands.push(encode(x - previous.0));
+            commands.push(encode(y - previous.1));
+            previous = (x, y);
+        }
+        commands.push(7 | (1 << 3));
+        commands
+    }
+
+    #[test]
+    fn rings_with_negative_area_are_holes_of_the_previous_polygon() {
+        // タイル座標は y が下向きなので、時計回りに見えるリングが外周になる
+        let mut commands = ring(&[(0, 0), (10, 0), (10, 10), (0, 10)]);
+        commands.extend(ring(&[(2, 2), (2, 4), (4, 4), (4, 2)]));
+        commands.extend(ring(&[(20, 0), (30, 0), (30, 10), (20, 10)]));
+        let decoded = polygons(&commands).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].interiors().len(), 1);
+        assert_eq!(decoded[0].unsigned_area(), 96.0);
+        assert!(polygons(&[(9 << 3) | 3]).is_err());
+    }
+
+    #[test]
+    fn tile_coordinates_are_projected_to_longitude_and_latitude() {
+        let tile = Tile {
+            zoom: 1,
+            column: 1,
+            row: 1,
+            data: Vec::new(),
+        };
+        let square = polygons(&ring(&[(0, 0), (4096, 0), (4096, 4096), (0, 4096)])).unwrap();
+        let projected = project(&tile, 4096, square).unwrap();
+        let rect = projected.bounding_rect().unwrap();
+        // ズーム 1 の北東のタイル
+        assert!((rect.min().x - 0.0).abs() < 1e-9 && (rect.max().x - 180.0).abs() < 1e-9);
+        assert!(rect.min().y.abs() < 1e-9 && (rect.max().y - 85.0511287798).abs() < 1e-6);
+    }
+}
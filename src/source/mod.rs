@@ -0,0 +1,732 @@
+mod compressed;
+mod csv;
+mod dimension;
+mod geojson;
+mod gpkg;
+mod kml;
+mod mbtiles;
+#[cfg(feature = "gdal")]
+mod ogr;
+mod osm;
+mod postgis;
+mod protobuf;
+mod seq;
+mod sqlite;
+mod wkb;
+mod wkt;
+mod xml;
+mod zip;
+
+use crate::{
+    cancel::{CancellationToken, Cancelled},
+    log, psql,
+    timing::{Stage, Timings},
+};
+use ::geojson::{Feature, FeatureCollection};
+pub use compressed::Compression;
+use rayon::prelude::*;
+use std::{error::Error, fs::File, io::Read};
+
+/// 中断を確かめながらファイルを読み込むときの、一度に読む大きさ
+const READ_CHUNK: usize = 16 << 20;
+
+/// `inspect` で解析する先頭の Feature の数
+const SAMPLE_FEATURES: usize = 100;
+
+/// 複数のレイヤーを持つ入力（GeoPackage, MBTiles, ZIP など）で、Feature のレイヤーの名前を入れるプロパティ
+pub const LAYER_PROPERTY: &str = "layer";
+
+/// 入力形式ごとのオプション
+#[derive(Default)]
+pub struct InputOptions {
+    /// PostGIS から読み込むクエリ (`--sql`)
+    pub sql: Option<String>,
+    /// CSV / TSV のジオメトリ列 (`--geometry-column`)
+    pub geometry_column: Option<String>,
+    /// OSM PBF から取り出す行政界の階層 (`--admin-level`)
+    pub admin_level: Option<String>,
+    /// MBTiles から読み込むズームレベル (`--zoom`)
+    pub zoom: Option<String>,
+    /// GeoPackage, MBTiles, ZIP などから読み込むレイヤー (`--layer`。空ならすべてのレイヤー)
+    pub layers: Vec<String>,
+    /// 1 行 1 Feature の GeoJSON の解析できない行を読み飛ばす (`--skip-invalid`)
+    pub skip_invalid: bool,
+}
+
+/// 入力元
+pub enum Input {
+    /// GeoJSON ファイル
+    GeoJson(String),
+    /// 1 行に 1 つの Feature を並べた GeoJSON ファイル（NDJSON / GeoJSON Text Sequences）
+    GeoJsonSeq { path: String, skip_invalid: bool },
+    /// gzip / zstd で圧縮したファイル（`inner` は .gz, .zst を除いたパスから判定した中の形式）
+    Compressed {
+        path: String,
+        compression: Compression,
+        inner: Box<Input>,
+    },
+    /// ジオメトリを WKT / 16 進 WKB の列に持つ CSV / TSV ファイル
+    Csv {
+        path: String,
+        delimiter: u8,
+        geometry_column: Option<String>,
+    },
+    /// KML ファイル
+    Kml(String),
+    /// KMZ ファイル（unzip で展開して読み込む）
+    Kmz(String),
+    /// OpenStreetMap の PBF ファイル（行政界リレーションを取り出す）
+    Osm {
+        path: String,
+        admin_level: Option<String>,
+    },
+    /// MBTiles のベクタータイル（SQLite のファイルを直接読む）
+    Mbtiles {
+        path: String,
+        zoom: Option<u8>,
+        layers: Vec<String>,
+    },
+    /// GeoPackage のベクターのレイヤー（SQLite のファイルを直接読む）
+    GeoPackage { path: String, layers: Vec<String> },
+    /// 複数のファイルをまとめた ZIP（unzip で展開して、ファイルごとのレイヤーを読み込む）
+    Zip { path: String, layers: Vec<String> },
+    /// GDAL が対応する形式のファイル（GDAL のライブラリの OGR で読み込む）
+    #[cfg(feature = "gdal")]
+    Gdal { path: String, layers: Vec<String> },
+    /// PostGIS のクエリ結果（psql 経由で読み込む）
+    Postgis { connection: String, sql: String },
+}
+
+impl Input {
+    /// `--input` の値と入力形式ごとのオプションから入力元を判定する
+    pub fn parse(input: &str, options: InputOptions) -> Result<Input, String> {
+        if let Some((compression, stem)) = Some(input)
+            .filter(|input| !psql::is_url(input))
+            .and_then(Compression::of)
+        {
+            let inner = Input::parse(stem, options)?;
+            if !compressed::supports(&inner) {
+                return Err(format!(
+                    "{} で圧縮したファイルは GeoJSON, 1 行 1 Feature の GeoJSON, CSV / TSV のみ読み込めます: {}",
+                    compression.name(),
+                    input
+                ));
+            }
+            return Ok(Input::Compressed {
+                path: input.to_string(),
+                compression,
+                inner: Box::new(inner),
+            });
+        }
+        let InputOptions {
+            sql,
+            geometry_column,
+            admin_level,
+            zoom,
+            layers,
+            skip_invalid,
+        } = options;
+
+        let is_csv = has_extension(input, "csv") || has_extension(input, "tsv");
+        if geometry_column.is_some() && !is_csv {
+            return Err(
+                "--geometry-column は CSV / TSV を読み込む場合のみ指定できます".to_string(),
+            );
+        }
+        let is_osm = has_extension(input, "pbf");
+        if admin_level.is_some() && !is_osm {
+            return Err("--admin-level は OSM PBF を読み込む場合のみ指定できます".to_string());
+        }
+        let is_mbtiles = has_extension(input, "mbtiles");
+        if zoom.is_some() && !is_mbtiles {
+            return Err("--zoom は MBTiles を読み込む場合のみ指定できます".to_string());
+        }
+        let is_gpkg = has_extension(input, "gpkg");
+        let is_zip = has_extension(input, "zip");
+        #[cfg(feature = "gdal")]
+        let is_layered = is_mbtiles || is_gpkg || is_zip || ogr::handles(input);
+        #[cfg(not(feature = "gdal"))]
+        let is_layered = is_mbtiles || is_gpkg || is_zip;
+        if !layers.is_empty() && !is_layered {
+            return Err(
+                "--layer は GeoPackage, MBTiles, ZIP (と GDAL で読み込む FileGDB など) を読み込む場合のみ指定できます"
+                    .to_string(),
+            );
+        }
+        let is_seq = ["geojsonl", "geojsons", "ndjson", "jsonl"]
+            .iter()
+            .any(|extension| has_extension(input, extension));
+        if skip_invalid && !is_seq {
+            return Err(
+                "--skip-invalid は 1 行 1 Feature の GeoJSON (*.geojsonl, *.geojsons, *.ndjson, *.jsonl) を読み込む場合のみ指定できます"
+                    .to_string(),
+            );
+        }
+
+        if psql::is_url(input) {
+            let url = psql::ConnectionUrl::parse(input)?;
+            // --sql がなければ URL の table パラメータのテーブル全体を読み込む
+            let sql = match (sql, url.table) {
+                (Some(sql), _) => sql,
+                (None, Some(table)) => format!("SELECT * FROM {}", psql::quote_table(&table)),
+                (None, None) => {
+                    return Err(
+                        "PostGIS から読み込む場合は --sql か URL の table を指定してください"
+                            .to_string(),
+                    )
+                }
+            };
+            Ok(Input::Postgis {
+                connection: url.connection,
+                sql,
+            })
+        } else if sql.is_some() {
+            Err("--sql は PostGIS から読み込む場合のみ指定できます".to_string())
+        } else if is_csv {
+            Ok(Input::Csv {
+                path: input.to_string(),
+                delimiter: if has_extension(input, "tsv") {
+                    b'\t'
+                } else {
+                    b','
+                },
+                geometry_column,
+            })
+        } else if is_osm {
+            Ok(Input::Osm {
+                path: input.to_string(),
+                admin_level,
+            })
+        } else if is_mbtiles {
+            let zoom = zoom
+                .map(|zoom| match zoom.parse::<u8>() {
+                    Ok(zoom) if zoom <= 30 => Ok(zoom),
+                    _ => Err(format!("--zoom の値が不正です (0〜30): {}", zoom)),
+                })
+                .transpose()?;
+            Ok(Input::Mbtiles {
+                path: input.to_string(),
+                zoom,
+                layers,
+            })
+        } else if is_gpkg {
+            Ok(Input::GeoPackage {
+                path: input.to_string(),
+                layers,
+            })
+        } else if is_zip {
+            Ok(Input::Zip {
+                path: input.to_string(),
+                layers,
+            })
+        } else if is_seq {
+            Ok(Input::GeoJsonSeq {
+                path: input.to_string(),
+                skip_invalid,
+            })
+        } else if has_extension(input, "kml") {
+            Ok(Input::Kml(input.to_string()))
+        } else if has_extension(input, "kmz") {
+            Ok(Input::Kmz(input.to_string()))
+        } else {
+            #[cfg(feature = "gdal")]
+            if ogr::handles(input) {
+                return Ok(Input::Gdal {
+                    path: input.to_string(),
+                    layers,
+                });
+            }
+            Ok(Input::GeoJson(input.to_string()))
+        }
+    }
+
+    /// Feature を読み込む（座標の Z / M 値は警告を出して捨てる）
+    pub fn read(&self) -> Result<FeatureCollection, Box<dyn Error>> {
+        let mut collection = self.read_raw()?;
+        drop_dimensions(&mut collection)?;
+        Ok(collection)
+    }
+
+    /// Feature を 1 つずつ `visit` に渡す（`visit` が false を返したら読むのをやめる）。
+    /// PostGIS と 1 行 1 Feature の GeoJSON は届いた行から渡し、すべての Feature を溜め込まない。
+    /// ほかの形式はすべて読み込んでから順に渡す。座標の Z / M 値は `read` と同じく捨てる
+    pub fn stream(&self, visit: &mut dyn FnMut(Feature) -> bool) -> Result<(), Box<dyn Error>> {
+        let mut dropped = 0;
+        let mut error = None;
+        let mut each = |mut feature: Feature| match dimension::drop_feature(&mut feature) {
+            Ok(true) => {
+                dropped += 1;
+                visit(feature)
+            }
+            Ok(false) => visit(feature),
+            Err(err) => {
+                error = Some(err);
+                false
+            }
+        };
+        match self {
+            Input::GeoJsonSeq { path, skip_invalid } => {
+                seq::stream_file(path, *skip_invalid, &mut each)?
+            }
+            Input::Postgis { connection, sql } => postgis::stream(connection, sql, &mut each)?,
+            _ => {
+                for feature in self.read_raw()?.features {
+                    if !each(feature) {
+                        break;
+                    }
+                }
+            }
+        }
+        if let Some(err) = error {
+            return Err(err.into());
+        }
+        warn_dropped(dropped);
+        Ok(())
+    }
+
+    /// Feature を解析する前の `size` 個ずつのまとまりにして `send` に渡す（false を返したらやめる）。
+    /// GeoJSON は features 配列の要素、1 行 1 Feature の GeoJSON は行のまま渡し、解析は `ChunkParser` に任せる
+    /// （読み込みと解析を別のスレッドで進められる）。ほかの形式は `stream` で読み込んだ Feature を渡す。
+    /// `cancel` が中断されると読み込みをやめて false を返す
+    pub fn read_chunks(
+        &self,
+        size: usize,
+        cancel: &CancellationToken,
+        send: &mut dyn FnMut(Chunk) -> bool,
+    ) -> Result<bool, Box<dyn Error>> {
+        match self {
+            Input::GeoJson(path) => {
+                let Some(text) = read_text(path, cancel)? else {
+                    return Ok(false);
+                };
+                let Some(elements) = geojson::elements(&text) else {
+                    // Feature やジオメトリだけの GeoJSON などは全体を解析する
+                    let mut collection = geojson::parse(path, &text)?;
+                    drop_dimensions(&mut collection)?;
+                    send(Chunk(Raw::Features(collection.features)));
+                    return Ok(true);
+                };
+                for (i, batch) in elements.chunks(size).enumerate() {
+                    if cancel.is_cancelled() {
+                        return Ok(false);
+                    }
+                    let texts = batch
+                        .iter()
+                        .map(|range| text[range.clone()].to_string())
+                        .collect();
+                    if !send(Chunk(Raw::Elements {
+                        path: path.clone(),
+                        first: i * size,
+                        texts,
+                    })) {
+                        break;
+                    }
+                }
+                Ok(true)
+            }
+            Input::GeoJsonSeq { path, skip_invalid } => {
+                seq::read_lines(path, size, cancel, &mut |lines| {
+                    send(Chunk(Raw::Lines {
+                        path: path.clone(),
+                        skip_invalid: *skip_invalid,
+                        lines,
+                    }))
+                })
+            }
+            _ => {
+                let mut features = Vec::with_capacity(size);
+                let mut complete = true;
+                self.stream(&mut |feature| {
+                    features.push(feature);
+                    complete = !cancel.is_cancelled();
+                    if (features.len() == size || !complete)
+                        && !send(Chunk(Raw::Features(std::mem::take(&mut features))))
+                    {
+                        return false;
+                    }
+                    complete
+                })?;
+                if !features.is_empty() {
+                    send(Chunk(Raw::Features(features)));
+                }
+                Ok(complete)
+            }
+        }
+    }
+
+    fn read_raw(&self) -> Result<FeatureCollection, Box<dyn Error>> {
+        match self {
+            Input::GeoJson(path) => geojson::read(path),
+            Input::GeoJsonSeq { path, skip_invalid } => seq::read_file(path, *skip_invalid),
+            Input::Compressed {
+                path,
+                compression,
+                inner,
+            } => compressed::read(path, *compression, inner),
+            Input::Csv {
+                path,
+                delimiter,
+                geometry_column,
+            } => csv::read(path, *delimiter, geometry_column.as_deref()),
+            Input::Kml(path) => kml::read(path),
+            Input::Osm { path, admin_level } => osm::read(path, admin_level.as_deref()),
+            Input::Kmz(path) => kml::read_kmz(path),
+            Input::Mbtiles { path, zoom, layers } => mbtiles::read(path, *zoom, layers),
+            Input::GeoPackage { path, layers } => gpkg::read(path, layers),
+            Input::Zip { path, layers } => zip::read(path, layers),
+            #[cfg(feature = "gdal")]
+            Input::Gdal { path, layers } => ogr::read(path, layers),
+            Input::Postgis { connection, sql } => postgis::read(connection, sql),
+        }
+    }
+}
+
+impl Input {
+    /// `read` と同じく読み込み、かかった時間を `timings` に記録する。
+    /// GeoJSON はファイルの読み込み（read）と解析（parse）を分けて測る（圧縮したものは展開までを read にする）。
+    /// ほかの形式は分けられないため read にまとめる。
+    ///
+    /// `cancel` が中断されると、GeoJSON のファイルの読み込みと Feature の解析、1 行 1 Feature の GeoJSON と
+    /// PostGIS の行の読み込みを途中でやめ、それまでに読み込めた Feature と中断の理由を返す
+    /// （ほかの形式は読み込み終えてから中断を確かめる）
+    pub fn read_timed(
+        &self,
+        timings: &mut Timings,
+        cancel: &CancellationToken,
+    ) -> Result<(FeatureCollection, Option<Cancelled>), Box<dyn Error>> {
+        let empty = || FeatureCollection {
+            bbox: None,
+            features: Vec::new(),
+            foreign_members: None,
+        };
+        let collection = match self {
+            Input::GeoJson(path) => {
+                let Some(text) = timings.time(Stage::Read, || read_text(path, cancel))? else {
+                    return Ok((empty(), cancel.check().err()));
+                };
+                timings.time(Stage::Parse, || {
+                    let (mut collection, complete) = geojson::parse_until(path, &text, cancel)?;
+                    drop_dimensions(&mut collection)?;
+                    Ok::<_, Box<dyn Error>>((collection, complete))
+                })?
+            }
+            Input::Compressed {
+                path,
+                compression,
+                inner,
+            } => {
+                let data =
+                    timings.time(Stage::Read, || compressed::decompress(path, *compression))?;
+                if cancel.is_cancelled() {
+                    return Ok((empty(), cancel.check().err()));
+                }
+                timings.time(Stage::Parse, || {
+                    let mut collection = compressed::parse(path, &data, inner)?;
+                    drop_dimensions(&mut collection)?;
+                    Ok::<_, Box<dyn Error>>((collection, true))
+                })?
+            }
+            Input::GeoJsonSeq { .. } | Input::Postgis { .. } => {
+                timings.time(Stage::Read, || {
+                    let mut collection = empty();
+                    let mut complete = true;
+                    self.stream(&mut |feature| {
+                        collection.features.push(feature);
+                        complete = !cancel.is_cancelled();
+                        complete
+                    })?;
+                    Ok::<_, Box<dyn Error>>((collection, complete))
+                })?
+            }
+            _ => (timings.time(Stage::Read, || self.read())?, true),
+        };
+        let (collection, complete) = collection;
+        Ok((collection, cancel.check().err().filter(|_| !complete)))
+    }
+}
+
+/// ファイル全体を文字列として読み込む（中断されたら None）
+fn read_text(path: &str, cancel: &CancellationToken) -> std::io::Result<Option<String>> {
+    let mut file = File::open(path)?;
+    let mut data = Vec::with_capacity(file.metadata().map_or(0, |m| m.len() as usize));
+    loop {
+        if cancel.is_cancelled() {
+            return Ok(None);
+        }
+        let read = (&mut file).take(READ_CHUNK as u64).read_to_end(&mut data)?;
+        if read == 0 {
+            break;
+        }
+    }
+    String::from_utf8(data)
+        .map(Some)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+}
+
+/// 座標の Z / M 値を捨て、捨てた Feature があれば警告する
+fn drop_dimensions(collection: &mut FeatureCollection) -> Result<(), String> {
+    warn_dropped(dimension::drop_extra(collection)?);
+    Ok(())
+}
+
+fn warn_dropped(dropped: usize) {
+    if dropped > 0 {
+        log::warning!(
+            "警告: {} 個の Feature の座標の Z / M 値を捨てました（x と y だけを使います）",
+            dropped
+        );
+    }
+}
+
+/// `Input::read_chunks` で読み込んだ、解析する前の Feature のまとまり（`ChunkParser` で解析する）
+pub struct Chunk(Raw);
+
+enum Raw {
+    /// GeoJSON の features 配列の要素（`first` は最初の要素の番号）
+    Elements {
+        path: String,
+        first: usize,
+        texts: Vec<String>,
+    },
+    /// 1 行 1 Feature の GeoJSON の行
+    Lines {
+        path: String,
+        skip_invalid: bool,
+        lines: Vec<seq::Line>,
+    },
+    /// 読み込みながら解析した Feature（座標の Z / M 値は捨ててある）
+    Features(Vec<Feature>),
+}
+
+/// `Chunk` を Feature に解析する。読み飛ばした行と座標の Z / M 値を捨てた Feature は数えておき、
+/// `finish` で `Input::read` と同じ警告をまとめて出す
+#[derive(Default)]
+pub struct ChunkParser {
+    /// 解析した Feature の数
+    parsed: usize,
+    /// 読み飛ばした行があった入力と、その数
+    skipped: Option<(String, usize)>,
+    /// 座標の Z / M 値を捨てた Feature の数
+    dropped: usize,
+}
+
+impl ChunkParser {
+    /// まとまりの Feature を並列に解析する
+    pub fn parse(&mut self, chunk: Chunk) -> Result<Vec<Feature>, String> {
+        let mut features = match chunk.0 {
+            Raw::Elements { path, first, texts } => texts
+                .par_iter()
+                .enumerate()
+                .map(|(i, text)| geojson::parse_element(&path, first + i, text))
+                .collect::<Result<Vec<_>, _>>()?,
+            Raw::Lines {
+                path,
+                skip_invalid,
+                lines,
+            } => {
+                let (features, skipped) = seq::parse_lines(&lines, &path, skip_invalid)?;
+                if skipped > 0 {
+                    let total = self.skipped.take().map_or(0, |(_, total)| total);
+                    self.skipped = Some((path, total + skipped));
+                }
+                features
+            }
+            Raw::Features(features) => return Ok(features),
+        };
+        for feature in &mut features {
+            if dimension::drop_feature(feature)? {
+                self.dropped += 1;
+            }
+        }
+        self.parsed += features.len();
+        Ok(features)
+    }
+
+    /// 読み飛ばした行と Z / M 値を捨てた Feature を警告する
+    pub fn finish(self) {
+        if let Some((path, skipped)) = &self.skipped {
+            seq::warn_skipped(path, *skipped, self.parsed);
+        }
+        warn_dropped(self.dropped);
+    }
+}
+
+/// すべては読み込まずに調べた入力の概要（`--dry-run` 用）
+pub struct Schema {
+    /// Feature の数（数えられない形式では None。CSV では空の行も含めた行数）
+    pub feature_count: Option<usize>,
+    /// 先頭の Feature（調べられない形式では None）
+    pub sample: Option<Vec<Feature>>,
+    /// 宣言されている座標参照系
+    pub crs: Option<String>,
+}
+
+impl Input {
+    /// Feature の数を数え、先頭の Feature だけを解析する。
+    /// GeoJSON（1 行 1 Feature のものも）と CSV / TSV 以外は読み込むだけで時間がかかるため、何も調べない
+    pub fn inspect(&self) -> Result<Schema, Box<dyn Error>> {
+        match self {
+            Input::GeoJson(path) => geojson::inspect(path, SAMPLE_FEATURES),
+            Input::GeoJsonSeq { path, .. } => {
+                let (count, sample) = seq::inspect(path, SAMPLE_FEATURES)?;
+                Ok(Schema {
+                    feature_count: Some(count),
+                    sample: Some(sample),
+                    crs: None,
+                })
+            }
+            Input::Csv {
+                path,
+                delimiter,
+                geometry_column,
+            } => {
+                let (count, sample) = csv::inspect(
+                    path,
+                    *delimiter,
+                    geometry_column.as_deref(),
+                    SAMPLE_FEATURES,
+                )?;
+                Ok(Schema {
+                    feature_count: Some(count),
+                    sample: Some(sample),
+                    crs: None,
+                })
+            }
+            _ => Ok(Schema {
+                feature_count: None,
+                sample: None,
+                crs: None,
+            }),
+        }
+    }
+
+    /// 実行計画の表示用の入力元の説明
+    pub fn describe(&self) -> String {
+        match self {
+            Input::GeoJson(path) => format!("GeoJSON ファイル ({})", path),
+            Input::GeoJsonSeq { path, .. } => {
+                format!("1 行 1 Feature の GeoJSON ファイル ({})", path)
+            }
+            Input::Compressed {
+                path, compression, ..
+            } => format!("{} で圧縮したファイル ({})", compression.name(), path),
+            Input::Csv { path, .. } => format!("CSV ファイル ({})", path),
+            Input::Kml(path) => format!("KML ファイル ({})", path),
+            Input::Kmz(path) => format!("KMZ ファイル ({})", path),
+            Input::Osm { path, .. } => format!("OSM PBF ファイル ({})", path),
+            Input::Mbtiles { path, .. } => format!("MBTiles ファイル ({})", path),
+            Input::GeoPackage { path, .. } => format!("GeoPackage ファイル ({})", path),
+            Input::Zip { path, .. } => format!("ZIP ファイル ({})", path),
+            #[cfg(feature = "gdal")]
+            Input::Gdal { path, .. } => format!("GDAL で読み込むファイル ({})", path),
+            Input::Postgis { sql, .. } => format!("PostGIS のクエリ ({})", sql),
+        }
+    }
+
+    /// 入力元のファイルのパス（PostGIS なら None）
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Input::GeoJson(path)
+            | Input::GeoJsonSeq { path, .. }
+            | Input::Compressed { path, .. }
+            | Input::Csv { path, .. }
+            | Input::Kml(path)
+            | Input::Kmz(path)
+            | Input::Osm { path, .. }
+            | Input::Mbtiles { path, .. }
+            | Input::GeoPackage { path, .. }
+            | Input::Zip { path, .. } => Some(path),
+            #[cfg(feature = "gdal")]
+            Input::Gdal { path, .. } => Some(path),
+            Input::Postgis { .. } => None,
+        }
+    }
+
+    /// 読み込むのに使う外部コマンド（unzip, psql。使わない形式なら None）
+    pub fn external_command(&self) -> Option<&'static str> {
+        match self {
+            Input::Compressed { inner, .. } => inner.external_command(),
+            Input::Kmz(_) | Input::Zip { .. } => Some("unzip"),
+            Input::Postgis { .. } => Some("psql"),
+            _ => None,
+        }
+    }
+
+    /// 複数のレイヤーを持てる形式か（読み込んだ Feature の `LAYER_PROPERTY` にレイヤーの名前が入る）
+    pub fn has_layers(&self) -> bool {
+        match self {
+            Input::Mbtiles { .. } | Input::GeoPackage { .. } | Input::Zip { .. } => true,
+            #[cfg(feature = "gdal")]
+            Input::Gdal { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// レイヤーの一覧（`layon layers`）。複数のレイヤーを持てない形式ならエラー
+    pub fn layers(&self) -> Result<Vec<Layer>, Box<dyn Error>> {
+        match self {
+            Input::Mbtiles { path, .. } => mbtiles::layers(path),
+            Input::GeoPackage { path, .. } => gpkg::layers(path),
+            Input::Zip { path, .. } => zip::layers(path),
+            #[cfg(feature = "gdal")]
+            Input::Gdal { path, .. } => ogr::layers(path),
+            _ => Err(format!(
+                "{} はレイヤーを持つ形式ではありません (GeoPackage, MBTiles, ZIP などを指定してください)",
+                self.describe()
+            )
+            .into()),
+        }
+    }
+}
+
+/// 複数のレイヤーを持つ入力の 1 つのレイヤー
+pub struct Layer {
+    pub name: String,
+    /// Feature の数（読み込まないと数えられない形式では None）
+    pub features: Option<usize>,
+    /// ジオメトリの種類（形式が記録していなければ None）
+    pub geometry: Option<String>,
+}
+
+/// `available` のうち `wanted` の名前のもの（`wanted` の順。空ならすべて）。ないレイヤーを指定した場合はエラー
+fn select_layers<T>(
+    path: &str,
+    mut available: Vec<T>,
+    wanted: &[String],
+    name: impl Fn(&T) -> &String,
+) -> Result<Vec<T>, String> {
+    if wanted.is_empty() {
+        return Ok(available);
+    }
+    let mut selected = Vec::new();
+    for layer in wanted {
+        match available
+            .iter()
+            .position(|candidate| name(candidate) == layer)
+        {
+            Some(i) => selected.push(available.remove(i)),
+            None if selected.iter().any(|candidate| name(candidate) == layer) => {}
+            None => {
+                let names: Vec<&str> = available
+                    .iter()
+                    .chain(&selected)
+                    .map(|candidate| name(candidate).as_str())
+                    .collect();
+                return Err(format!(
+                    "{} にレイヤー {} がありません (あるレイヤー: {})",
+                    path,
+                    layer,
+                    names.join(", ")
+                ));
+            }
+        }
+    }
+    Ok(selected)
+}
+
+/// 拡張子が一致するか（大文字・小文字は区別しない）
+fn has_extension(path: &str, extension: &str) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
@@ -0,0 +1,109 @@
+//! GDAL が対応する形式を、gdal クレート経由で GDAL のライブラリ（OGR）で読み込む橋渡し。
+//! 自前で読み込めない形式（FileGDB, DXF, Shapefile など）は GDAL のドライバーで開き、
+//! ジオメトリを WGS84 の経緯度（経度, 緯度の順）に変換してから GeoJSON のジオメトリにする。
+
+use super::{Layer, LAYER_PROPERTY};
+use ::gdal::{
+    spatial_ref::{AxisMappingStrategy, CoordTransform, SpatialRef},
+    vector::{geometry_type_to_name, FieldValue, LayerAccess},
+    Dataset,
+};
+use geojson::{Feature, FeatureCollection, JsonObject, JsonValue};
+use std::error::Error;
+
+/// FileGDB など複数のレイヤーを持つ形式のために、レイヤーごとに読み込んで Feature の `layer` プロパティにレイヤーの名前を入れる
+/// （`wanted` が空ならすべてのレイヤー）
+pub fn read(path: &str, wanted: &[String]) -> Result<FeatureCollection, Box<dyn Error>> {
+    let dataset = open(path)?;
+    let layers = super::select_layers(path, layers_of(&dataset), wanted, |layer| &layer.name)?;
+    let mut wgs84 = SpatialRef::from_epsg(4326)?;
+    // GDAL 3 の EPSG:4326 は緯度, 経度の順のため、GeoJSON と同じ経度, 緯度の順にそろえる
+    wgs84.set_axis_mapping_strategy(AxisMappingStrategy::TraditionalGisOrder);
+
+    let mut features = Vec::new();
+    for selected in layers {
+        let mut layer = dataset.layer_by_name(&selected.name)?;
+        // 座標系のないレイヤーは経緯度のものとしてそのまま読む
+        let transform = match layer.spatial_ref() {
+            Some(mut source) => {
+                source.set_axis_mapping_strategy(AxisMappingStrategy::TraditionalGisOrder);
+                Some(CoordTransform::new(&source, &wgs84)?)
+            }
+            None => None,
+        };
+        for feature in layer.features() {
+            let geometry = match feature.geometry() {
+                Some(geometry) => {
+                    let json = match &transform {
+                        Some(transform) => geometry.transform(transform)?.json()?,
+                        None => geometry.json()?,
+                    };
+                    Some(json.parse::<geojson::Geometry>()?)
+                }
+                None => None,
+            };
+            let mut properties: JsonObject = feature
+                .fields()
+                .map(|(name, value)| (name, value.map_or(JsonValue::Null, field_value)))
+                .collect();
+            properties.insert(LAYER_PROPERTY.to_string(), selected.name.clone().into());
+            features.push(Feature {
+                bbox: None,
+                geometry,
+                id: None,
+                properties: Some(properties),
+                foreign_members: None,
+            });
+        }
+    }
+    Ok(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    })
+}
+
+/// レイヤーの名前と Feature の数、ジオメトリの種類（例: `Multi Polygon`）
+pub fn layers(path: &str) -> Result<Vec<Layer>, Box<dyn Error>> {
+    Ok(layers_of(&open(path)?))
+}
+
+fn open(path: &str) -> Result<Dataset, Box<dyn Error>> {
+    Dataset::open(path).map_err(|err| format!("GDAL で {} を開けません: {}", path, err).into())
+}
+
+fn layers_of(dataset: &Dataset) -> Vec<Layer> {
+    dataset
+        .layers()
+        .map(|layer| Layer {
+            name: layer.name(),
+            features: layer.try_feature_count().map(|count| count as usize),
+            geometry: Some(geometry_type_to_name(layer.defn().geometry_type())),
+        })
+        .collect()
+}
+
+/// 属性の値を JSON の値にする（日付は ISO 8601 の文字列）
+fn field_value(value: FieldValue) -> JsonValue {
+    match value {
+        FieldValue::IntegerValue(v) => v.into(),
+        FieldValue::Integer64Value(v) => v.into(),
+        FieldValue::RealValue(v) => v.into(),
+        FieldValue::StringValue(v) => v.into(),
+        FieldValue::IntegerListValue(v) => v.into(),
+        FieldValue::Integer64ListValue(v) => v.into(),
+        FieldValue::RealListValue(v) => v.into(),
+        FieldValue::StringListValue(v) => v.into(),
+        FieldValue::DateValue(v) => v.to_string().into(),
+        FieldValue::DateTimeValue(v) => v.to_rfc3339().into(),
+    }
+}
+
+/// GDAL で読み込む対象かどうか（自前で読み込める形式以外の拡張子を持つファイル）
+pub fn handles(path: &str) -> bool {
+    ![
+        "geojson", "json", "csv", "tsv", "kml", "kmz", "pbf", "mbtiles", "gpkg", "zip",
+    ]
+    .iter()
+    .any(|extension| super::has_extension(path, extension))
+}
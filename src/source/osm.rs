@@ -0,0 +1,484 @@
+//! OpenStreetMap の PBF ファイルから行政界（boundary=administrative）のリレーションを取り出し、
+//! メンバーのウェイをつないだリングからポリゴンを組み立てる。
+//!
+//! ファイル全体を 3 回走査する（リレーション → 必要なウェイ → 必要なノード）。
+//! 各走査ではブロックごとに並列に展開・解析する。
+
+use super::protobuf::{zigzag, Field, Reader};
+use crate::{inflate, log};
+use geo::{Contains, Coord, LineString, MultiPolygon, Point, Polygon};
+use geojson::{feature::Id, Feature, FeatureCollection, JsonObject, JsonValue};
+use rayon::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs,
+};
+
+/// ノード ID と座標（経度, 緯度）
+type NodeCoord = (i64, (f64, f64));
+
+/// 対象とするリレーション
+struct Relation {
+    id: i64,
+    tags: Vec<(String, String)>,
+    /// メンバーのウェイ（ID と、inner かどうか）
+    ways: Vec<(i64, bool)>,
+}
+
+/// PrimitiveBlock の中身（文字列テーブルと座標の変換パラメータ、PrimitiveGroup の列）
+struct Block<'a> {
+    strings: Vec<&'a [u8]>,
+    granularity: i64,
+    lat_offset: i64,
+    lon_offset: i64,
+    groups: Vec<&'a [u8]>,
+}
+
+impl Block<'_> {
+    fn string(&self, index: u64) -> String {
+        self.strings
+            .get(index as usize)
+            .map(|s| String::from_utf8_lossy(s).into_owned())
+            .unwrap_or_default()
+    }
+
+    fn coord(&self, lat: i64, lon: i64) -> (f64, f64) {
+        (
+            1e-9 * (self.lon_offset + self.granularity * lon) as f64,
+            1e-9 * (self.lat_offset + self.granularity * lat) as f64,
+        )
+    }
+}
+
+/// PBF ファイルを読み込み、行政界を Feature として返す。
+/// `admin_level` を指定した場合はその階層のリレーションだけを対象にする。
+pub fn read(path: &str, admin_level: Option<&str>) -> Result<FeatureCollection, Box<dyn Error>> {
+    let data = fs::read(path)?;
+    let blobs = blobs(&data)?;
+
+    // 1 回目: 行政界のリレーション
+    let relations: Vec<Relation> = each_block(&blobs, |block, out| {
+        for group in &block.groups {
+            for_each_field(group, 4, |relation| {
+                if let Some(relation) = parse_relation(block, relation)? {
+                    out.push(relation);
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    })?
+    .into_iter()
+    .filter(|relation| {
+        let tag = |key: &str| {
+            relation
+                .tags
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        };
+        matches!(tag("type"), Some("boundary") | Some("multipolygon"))
+            && tag("boundary") == Some("administrative")
+            && admin_level.is_none_or(|level| tag("admin_level") == Some(level))
+    })
+    .collect();
+
+    // 2 回目: リレーションが参照するウェイのノード列
+    let wanted_ways: HashSet<i64> = relations
+        .iter()
+        .flat_map(|relation| relation.ways.iter().map(|(id, _)| *id))
+        .collect();
+    let ways: HashMap<i64, Vec<i64>> = each_block(&blobs, |block, out| {
+        for group in &block.groups {
+            for_each_field(group, 3, |way| {
+                let (id, refs) = parse_way(way)?;
+                if wanted_ways.contains(&id) {
+                    out.push((id, refs));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    })?
+    .into_iter()
+    .collect();
+
+    // 3 回目: ウェイが参照するノードの座標
+    let wanted_nodes: HashSet<i64> = ways.values().flatten().copied().collect();
+    let nodes: HashMap<i64, (f64, f64)> = each_block(&blobs, |block, out| {
+        for group in &block.groups {
+            for_each_field(group, 1, |node| {
+                let (id, coord) = parse_node(block, node)?;
+                if wanted_nodes.contains(&id) {
+                    out.push((id, coord));
+                }
+                Ok(())
+            })?;
+            for_each_field(group, 2, |dense| {
+                for (id, coord) in parse_dense(block, dense)? {
+                    if wanted_nodes.contains(&id) {
+                        out.push((id, coord));
+                    }
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    })?
+    .into_iter()
+    .collect();
+
+    let features = relations
+        .par_iter()
+        .filter_map(|relation| build_feature(relation, &ways, &nodes))
+        .collect();
+
+    Ok(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    })
+}
+
+/// ファイルを BlobHeader と Blob の組に分け、OSMData の Blob だけを返す
+fn blobs(data: &[u8]) -> Result<Vec<&[u8]>, String> {
+    let mut blobs = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let len = data
+            .get(pos..pos + 4)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()) as usize)
+            .ok_or("PBF の BlobHeader の長さが欠けています")?;
+        pos += 4;
+        let header = data
+            .get(pos..pos + len)
+            .ok_or("PBF の BlobHeader が途中で終わっています")?;
+        pos += len;
+
+        let mut kind = Vec::new();
+        let mut size = 0;
+        let mut reader = Reader::new(header);
+        while let Some((number, field)) = reader.next_field()? {
+            match number {
+                1 => kind = field.bytes()?.to_vec(),
+                3 => size = field.varint()? as usize,
+                _ => {}
+            }
+        }
+
+        let blob = data
+            .get(pos..pos + size)
+            .ok_or("PBF の Blob が途中で終わっています")?;
+        pos += size;
+        if kind == b"OSMData" {
+            blobs.push(blob);
+        }
+    }
+    Ok(blobs)
+}
+
+/// Blob を展開する（非圧縮と zlib のみ対応）
+fn decompress(blob: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = Reader::new(blob);
+    while let Some((number, field)) = reader.next_field()? {
+        match number {
+            1 => return Ok(field.bytes()?.to_vec()),
+            3 => return inflate::zlib(field.bytes()?),
+            4..=7 => return Err("zlib 以外で圧縮された PBF には対応していません".to_string()),
+            _ => {}
+        }
+    }
+    Err("PBF の Blob にデータがありません".to_string())
+}
+
+/// すべてのブロックを並列に展開・解析し、各ブロックで集めた値をまとめて返す
+fn each_block<T: Send>(
+    blobs: &[&[u8]],
+    visit: impl Fn(&Block, &mut Vec<T>) -> Result<(), String> + Sync,
+) -> Result<Vec<T>, String> {
+    let parts = blobs
+        .par_iter()
+        .map(|blob| {
+            let data = decompress(blob)?;
+            let block = parse_block(&data)?;
+            let mut out = Vec::new();
+            visit(&block, &mut out)?;
+            Ok(out)
+        })
+        .collect::<Result<Vec<Vec<T>>, String>>()?;
+    Ok(parts.into_iter().flatten().collect())
+}
+
+fn parse_block(data: &[u8]) -> Result<Block<'_>, String> {
+    let mut block = Block {
+        strings: Vec::new(),
+        granularity: 100,
+        lat_offset: 0,
+        lon_offset: 0,
+        groups: Vec::new(),
+    };
+    let mut reader = Reader::new(data);
+    while let Some((number, field)) = reader.next_field()? {
+        match number {
+            1 => {
+                let mut table = Reader::new(field.bytes()?);
+                while let Some((number, s)) = table.next_field()? {
+                    if number == 1 {
+                        block.strings.push(s.bytes()?);
+                    }
+                }
+            }
+            2 => block.groups.push(field.bytes()?),
+            17 => block.granularity = field.varint()? as i64,
+            19 => block.lat_offset = field.varint()? as i64,
+            20 => block.lon_offset = field.varint()? as i64,
+            _ => {}
+        }
+    }
+    Ok(block)
+}
+
+/// メッセージ内の指定した番号のフィールド（埋め込みメッセージ）をすべて処理する
+fn for_each_field(
+    message: &[u8],
+    wanted: u32,
+    mut visit: impl FnMut(&[u8]) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut reader = Reader::new(message);
+    while let Some((number, field)) = reader.next_field()? {
+        if number == wanted {
+            visit(field.bytes()?)?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_relation(block: &Block, data: &[u8]) -> Result<Option<Relation>, String> {
+    let mut id = 0;
+    let (mut keys, mut vals) = (Vec::new(), Vec::new());
+    let (mut roles, mut members, mut types) = (Vec::new(), Vec::new(), Vec::new());
+
+    let mut reader = Reader::new(data);
+    while let Some((number, field)) = reader.next_field()? {
+        match number {
+            1 => id = field.varint()? as i64,
+            2 => keys = field.packed()?,
+            3 => vals = field.packed()?,
+            8 => roles = field.packed()?,
+            9 => members = delta(field.packed_sint()?),
+            10 => types = field.packed()?,
+            _ => {}
+        }
+    }
+
+    let tags: Vec<(String, String)> = keys
+        .iter()
+        .zip(&vals)
+        .map(|(k, v)| (block.string(*k), block.string(*v)))
+        .collect();
+    if !tags.iter().any(|(k, _)| k == "boundary") {
+        return Ok(None);
+    }
+
+    // types: 0 = ノード, 1 = ウェイ, 2 = リレーション
+    let ways = members
+        .iter()
+        .zip(&types)
+        .zip(&roles)
+        .filter(|((_, kind), _)| **kind == 1)
+        .map(|((id, _), role)| (*id, block.string(*role) == "inner"))
+        .collect();
+    Ok(Some(Relation { id, tags, ways }))
+}
+
+fn parse_way(data: &[u8]) -> Result<(i64, Vec<i64>), String> {
+    let mut id = 0;
+    let mut refs = Vec::new();
+    let mut reader = Reader::new(data);
+    while let Some((number, field)) = reader.next_field()? {
+        match number {
+            1 => id = field.varint()? as i64,
+            8 => refs = delta(field.packed_sint()?),
+            _ => {}
+        }
+    }
+    Ok((id, refs))
+}
+
+fn parse_node(block: &Block, data: &[u8]) -> Result<NodeCoord, String> {
+    let (mut id, mut lat, mut lon) = (0, 0, 0);
+    let mut reader = Reader::new(data);
+    while let Some((number, field)) = reader.next_field()? {
+        match (number, field) {
+            (1, Field::Varint(v)) => id = zigzag(v),
+            (8, Field::Varint(v)) => lat = zigzag(v),
+            (9, Field::Varint(v)) => lon = zigzag(v),
+            _ => {}
+        }
+    }
+    Ok((id, block.coord(lat, lon)))
+}
+
+fn parse_dense(block: &Block, data: &[u8]) -> Result<Vec<NodeCoord>, String> {
+    let (mut ids, mut lats, mut lons) = (Vec::new(), Vec::new(), Vec::new());
+    let mut reader = Reader::new(data);
+    while let Some((number, field)) = reader.next_field()? {
+        match number {
+            1 => ids = delta(field.packed_sint()?),
+            8 => lats = delta(field.packed_sint()?),
+            9 => lons = delta(field.packed_sint()?),
+            _ => {}
+        }
+    }
+    Ok(ids
+        .into_iter()
+        .zip(lats.into_iter().zip(lons))
+        .map(|(id, (lat, lon))| (id, block.coord(lat, lon)))
+        .collect())
+}
+
+/// 差分符号化された列を元の値に戻す
+fn delta(values: Vec<i64>) -> Vec<i64> {
+    let mut current = 0;
+    values
+        .into_iter()
+        .map(|v| {
+            current += v;
+            current
+        })
+        .collect()
+}
+
+/// リレーションのメンバーからポリゴンを組み立てて Feature にする
+fn build_feature(
+    relation: &Relation,
+    ways: &HashMap<i64, Vec<i64>>,
+    nodes: &HashMap<i64, (f64, f64)>,
+) -> Option<Feature> {
+    let to_ring = |ids: Vec<i64>| -> Option<LineString<f64>> {
+        ids.iter()
+            .map(|id| nodes.get(id).map(|&(x, y)| Coord { x, y }))
+            .collect::<Option<Vec<_>>>()
+            .map(LineString::new)
+    };
+
+    let mut skipped = 0;
+    let mut rings = |inner: bool| -> Vec<LineString<f64>> {
+        let members = relation
+            .ways
+            .iter()
+            .filter(|(_, is_inner)| *is_inner == inner)
+            .filter_map(|(id, _)| ways.get(id).cloned())
+            .collect();
+        let (closed, unclosed) = join_ways(members);
+        skipped += unclosed;
+        closed
+            .into_iter()
+            .filter_map(|ids| {
+                let ring = to_ring(ids);
+                if ring.is_none() {
+                    skipped += 1;
+                }
+                ring
+            })
+            .collect()
+    };
+    let outers = rings(false);
+    let inners = rings(true);
+
+    if skipped > 0 {
+        log::warning!(
+            "Warning: リレーション {} の閉じていない（またはノードが欠けた）リングを {} 個スキップしました",
+            relation.id, skipped
+        );
+    }
+    if outers.is_empty() {
+        return None;
+    }
+
+    // 内周は、それを含む外周のポリゴンに穴として加える
+    let mut polygons: Vec<Polygon<f64>> = outers
+        .into_iter()
+        .map(|ring| Polygon::new(ring, vec![]))
+        .collect();
+    for inner in inners {
+        let point = Point::from(inner.0[0]);
+        if let Some(polygon) = polygons.iter_mut().find(|p| p.contains(&point)) {
+            polygon.interiors_push(inner);
+        }
+    }
+
+    let mut properties = JsonObject::new();
+    for (key, value) in &relation.tags {
+        properties.insert(key.clone(), JsonValue::from(value.as_str()));
+    }
+    properties.insert("@id".to_string(), JsonValue::from(relation.id));
+
+    Some(Feature {
+        bbox: None,
+        geometry: Some(geojson::Geometry::new(geojson::Value::from(
+            &MultiPolygon::new(polygons),
+        ))),
+        id: Some(Id::Number(relation.id.into())),
+        properties: Some(properties),
+        foreign_members: None,
+    })
+}
+
+/// ウェイを端点でつないで閉じたリングにする。閉じられなかったリングの数も返す。
+fn join_ways(mut pool: Vec<Vec<i64>>) -> (Vec<Vec<i64>>, usize) {
+    pool.retain(|way| way.len() >= 2);
+    let mut rings = Vec::new();
+    let mut unclosed = 0;
+
+    while let Some(mut ring) = pool.pop() {
+        while ring.first() != ring.last() {
+            let end = *ring.last().unwrap();
+            let Some(index) = pool
+                .iter()
+                .position(|way| way[0] == end || *way.last().unwrap() == end)
+            else {
+                break;
+            };
+            let mut way = pool.swap_remove(index);
+            if way[0] != end {
+                way.reverse();
+            }
+            ring.extend_from_slice(&way[1..]);
+        }
+
+        if ring.len() >= 4 && ring.first() == ring.last() {
+            rings.push(ring);
+        } else {
+            unclosed += 1;
+        }
+    }
+    (rings, unclosed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ways_are_joined_at_either_end_into_closed_rings() {
+        let (rings, unclosed) = join_ways(vec![
+            vec![1, 2, 3],
+            vec![5, 4, 3],
+            vec![5, 6, 1],
+            vec![10, 11, 12],
+            vec![7],
+        ]);
+        assert_eq!(unclosed, 1);
+        assert_eq!(rings.len(), 1);
+        let ring = &rings[0];
+        assert_eq!(ring.len(), 7);
+        assert_eq!(ring.first(), ring.last());
+    }
+
+    #[test]
+    fn delta_coded_values_are_summed() {
+        assert_eq!(delta(vec![10, 1, -2, 5]), vec![10, 11, 9, 14]);
+    }
+}
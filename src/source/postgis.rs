@@ -0,0 +1,44 @@
+use super::seq;
+use crate::psql;
+use geojson::{Feature, FeatureCollection};
+use std::error::Error;
+
+/// psql がサーバー側カーソルで一度に取得する行数
+const FETCH_COUNT: usize = 1000;
+
+/// PostGIS のクエリ結果を Feature として読み込む
+pub fn read(connection: &str, sql: &str) -> Result<FeatureCollection, Box<dyn Error>> {
+    let mut features = Vec::new();
+    stream(connection, sql, &mut |feature| {
+        features.push(feature);
+        true
+    })?;
+    Ok(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    })
+}
+
+/// PostGIS のクエリ結果を、届いた行から Feature として `visit` に渡す（false を返したらクエリを止める）
+///
+/// ST_AsGeoJSON に行全体を渡すと、ジオメトリ以外の列が properties になった
+/// Feature が 1 行に 1 つずつ返ってくる。FETCH_COUNT を指定すると psql は
+/// カーソルを使って少しずつ取得するので、結果全体がクライアント側で溜め込まれることはない。
+pub fn stream(
+    connection: &str,
+    sql: &str,
+    visit: &mut dyn FnMut(Feature) -> bool,
+) -> Result<(), Box<dyn Error>> {
+    let query = format!(
+        "SELECT ST_AsGeoJSON(q.*) FROM ({}) AS q",
+        sql.trim().trim_end_matches(';')
+    );
+
+    let mut command = psql::command(connection);
+    command
+        .args(["--no-align", "--tuples-only"])
+        .args(["--set", &format!("FETCH_COUNT={}", FETCH_COUNT)])
+        .args(["--command", &query]);
+    seq::stream_command(command, "psql", visit)
+}
@@ -0,0 +1,141 @@
+//! Protocol Buffers のワイヤーフォーマットを読むための最小限のデコーダ。
+//! スキーマは持たず、フィールド番号と値を順に取り出すだけ。
+
+/// フィールドの値
+pub enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed64(u64),
+    Fixed32(u32),
+}
+
+impl<'a> Field<'a> {
+    pub fn varint(&self) -> Result<u64, String> {
+        match self {
+            Field::Varint(v) => Ok(*v),
+            _ => Err("varint のフィールドではありません".to_string()),
+        }
+    }
+
+    pub fn bytes(&self) -> Result<&'a [u8], String> {
+        match self {
+            Field::Bytes(b) => Ok(b),
+            _ => Err("length-delimited のフィールドではありません".to_string()),
+        }
+    }
+
+    /// packed 形式の varint 列
+    pub fn packed(&self) -> Result<Vec<u64>, String> {
+        let mut reader = Reader::new(self.bytes()?);
+        let mut values = Vec::new();
+        while !reader.is_empty() {
+            values.push(reader.varint()?);
+        }
+        Ok(values)
+    }
+
+    /// packed 形式の sint（zigzag 符号化）列
+    pub fn packed_sint(&self) -> Result<Vec<i64>, String> {
+        Ok(self.packed()?.into_iter().map(zigzag).collect())
+    }
+}
+
+/// zigzag 符号化された値を符号付き整数に戻す
+pub fn zigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+/// メッセージ内のフィールドを順に読む
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or("protobuf の varint が途中で終わっています")?;
+            self.pos += 1;
+            value |= u64::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("protobuf の varint が長すぎます".to_string())
+    }
+
+    fn slice(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let slice = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or("protobuf のフィールドが途中で終わっています")?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    /// 次のフィールド（フィールド番号と値）。末尾に達したら None
+    pub fn next_field(&mut self) -> Result<Option<(u32, Field<'a>)>, String> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let number = (key >> 3) as u32;
+        let field = match key & 7 {
+            0 => Field::Varint(self.varint()?),
+            1 => Field::Fixed64(u64::from_le_bytes(self.slice(8)?.try_into().unwrap())),
+            2 => {
+                let len = self.varint()? as usize;
+                Field::Bytes(self.slice(len)?)
+            }
+            5 => Field::Fixed32(u32::from_le_bytes(self.slice(4)?.try_into().unwrap())),
+            wire => return Err(format!("未対応の protobuf ワイヤー型です: {}", wire)),
+        };
+        Ok(Some((number, field)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_are_read_in_order() {
+        // 1: varint 300, 2: packed [3, 270], 3: fixed32 7, 4: packed sint [-1, 1]
+        let data = [
+            0x08, 0xAC, 0x02, 0x12, 0x03, 0x03, 0x8E, 0x02, 0x1D, 7, 0, 0, 0, 0x22, 0x02, 0x01,
+            0x02,
+        ];
+        let mut reader = Reader::new(&data);
+        let (number, field) = reader.next_field().unwrap().unwrap();
+        assert_eq!((number, field.varint().unwrap()), (1, 300));
+        let (number, field) = reader.next_field().unwrap().unwrap();
+        assert_eq!((number, field.packed().unwrap()), (2, vec![3, 270]));
+        let (number, field) = reader.next_field().unwrap().unwrap();
+        assert!(matches!((number, field), (3, Field::Fixed32(7))));
+        let (number, field) = reader.next_field().unwrap().unwrap();
+        assert_eq!((number, field.packed_sint().unwrap()), (4, vec![-1, 1]));
+        assert!(reader.next_field().unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_messages_are_errors() {
+        assert!(Reader::new(&[0x08, 0x80]).next_field().is_err());
+        assert!(Reader::new(&[0x12, 0x05, 0x01]).next_field().is_err());
+        assert_eq!(
+            Reader::new(&[0x0B]).next_field().err().unwrap(),
+            "未対応の protobuf ワイヤー型です: 3"
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! 1 行に 1 つの Feature を並べた GeoJSON（NDJSON / GeoJSON Text Sequences）の読み込み。
+//! 外部コマンド（psql など）の出力と、`*.geojsonl` などのファイルを読む。
+//! ファイルは何時間もかけて書き出した大きなものもあるため、`skip_invalid` を指定すると
+//! 解析できない行を位置（バイト目と行番号）とともに警告して読み飛ばし、残りを読み続ける。
+//! 外部コマンドの出力（PostGIS のクエリ結果など）は届いた行から 1 つずつ渡すこともでき、すべてを溜め込まずに済む。
+
+use crate::{cancel::CancellationToken, log};
+use geojson::{Feature, FeatureCollection};
+use rayon::prelude::*;
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+};
+
+/// 1 行に 1 つの Feature を出力する外部コマンド（psql など）を実行し、出力の行から解析した Feature を届いた順に `visit` に渡す。
+/// `visit` が false を返したらコマンドを止める。どの場合もコマンドの終了を待ってから返す
+pub fn stream_command(
+    mut command: Command,
+    program: &str,
+    visit: &mut dyn FnMut(Feature) -> bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("{} を起動できませんでした: {}", program, err))?;
+
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut stopped = false;
+    let result = for_each_line(stdout, program, false, &mut |feature| {
+        stopped = !visit(feature);
+        !stopped
+    });
+    if result.is_err() || stopped {
+        // 終了を待てるように止める（すでに終了していれば何もしない）
+        let _ = child.kill();
+    }
+    let status = child.wait()?;
+    result?;
+    if !stopped && !status.success() {
+        return Err(format!("{} がエラー終了しました ({})", program, status).into());
+    }
+    Ok(())
+}
+
+/// 1 行に 1 つの Feature を並べたファイルを読み込む
+pub fn read_file(path: &str, skip_invalid: bool) -> Result<FeatureCollection, Box<dyn Error>> {
+    let mut features = Vec::new();
+    stream_file(path, skip_invalid, &mut |feature| {
+        features.push(feature);
+        true
+    })?;
+    Ok(collection(features))
+}
+
+/// 1 行に 1 つの Feature を並べたファイルを、先頭から 1 つずつ `visit` に渡す（false を返したらやめる）
+pub fn stream_file(
+    path: &str,
+    skip_invalid: bool,
+    visit: &mut dyn FnMut(Feature) -> bool,
+) -> Result<(), Box<dyn Error>> {
+    let file = File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+    for_each_line(BufReader::new(file), path, skip_invalid, visit)
+}
+
+/// 展開したものなど、メモリにある 1 行 1 Feature の GeoJSON を読み込む（`source` はメッセージ用）
+pub fn parse(
+    data: &[u8],
+    source: &str,
+    skip_invalid: bool,
+) -> Result<FeatureCollection, Box<dyn Error>> {
+    let mut features = Vec::new();
+    for_each_line(data, source, skip_invalid, &mut |feature| {
+        features.push(feature);
+        true
+    })?;
+    Ok(collection(features))
+}
+
+/// 解析する前の 1 行
+pub struct Line {
+    /// 行の先頭のバイト目（0 始まり）
+    offset: u64,
+    /// 行番号（1 始まり）
+    number: usize,
+    text: Vec<u8>,
+}
+
+/// 1 行に 1 つの Feature を並べたファイルを、解析せずに `size` 行ずつ `send` に渡す（false を返したらやめる）。
+/// `cancel` が中断されたら、その行までを渡してやめ、false を返す
+pub fn read_lines(
+    path: &str,
+    size: usize,
+    cancel: &CancellationToken,
+    send: &mut dyn FnMut(Vec<Line>) -> bool,
+) -> Result<bool, Box<dyn Error>> {
+    let file = File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+    let mut reader = BufReader::new(file);
+    let mut lines = Vec::with_capacity(size);
+    let (mut offset, mut number) = (0u64, 0usize);
+    loop {
+        let mut text = Vec::new();
+        let read = reader.read_until(b'\n', &mut text)?;
+        if read == 0 {
+            break;
+        }
+        number += 1;
+        lines.push(Line {
+            offset,
+            number,
+            text,
+        });
+        offset += read as u64;
+        let cancelled = cancel.is_cancelled();
+        if lines.len() == size || cancelled {
+            if !send(std::mem::replace(&mut lines, Vec::with_capacity(size))) {
+                return Ok(true);
+            }
+            if cancelled {
+                return Ok(false);
+            }
+        }
+    }
+    if !lines.is_empty() {
+        send(lines);
+    }
+    Ok(true)
+}
+
+/// `read_lines` で読んだ行を並列に解析する（`source` はエラーのメッセージ用）。
+/// 解析できない行は `skip_invalid` なら警告して読み飛ばし、読み飛ばした数を返す
+pub fn parse_lines(
+    lines: &[Line],
+    source: &str,
+    skip_invalid: bool,
+) -> Result<(Vec<Feature>, usize), String> {
+    let parsed: Vec<_> = lines
+        .par_iter()
+        .map(|line| parse_line(&line.text))
+        .collect();
+    let mut features = Vec::with_capacity(parsed.len());
+    let mut skipped = 0;
+    for (line, parsed) in lines.iter().zip(parsed) {
+        match parsed {
+            Some(Ok(feature)) => features.push(feature),
+            Some(Err(err)) => {
+                let message = invalid(source, line.offset, line.number, &err);
+                if !skip_invalid {
+                    return Err(message);
+                }
+                log::warning!("警告: 不正な Feature を読み飛ばしました: {}", message);
+                skipped += 1;
+            }
+            None => {}
+        }
+    }
+    Ok((features, skipped))
+}
+
+/// 読み飛ばした不正な Feature の数を警告する（`read` は読み込めた Feature の数）
+pub fn warn_skipped(source: &str, skipped: usize, read: usize) {
+    if skipped > 0 {
+        log::warning!(
+            "警告: {} の不正な Feature を {} 個読み飛ばしました（{} 個を読み込みました）",
+            source,
+            skipped,
+            read
+        );
+    }
+}
+
+/// Feature の行の数を数え、先頭の `sample` 個だけを解析する（`--dry-run` 用。解析できない行は飛ばす）
+pub fn inspect(path: &str, sample: usize) -> Result<(usize, Vec<Feature>), Box<dyn Error>> {
+    let mut count = 0;
+    let mut features = Vec::new();
+    for line in BufReader::new(File::open(path)?).split(b'\n') {
+        let line = line?;
+        let Some(text) = feature_text(&line) else {
+            continue;
+        };
+        count += 1;
+        if features.len() < sample {
+            if let Ok(feature) = text.parse::<Feature>() {
+                features.push(feature);
+            }
+        }
+    }
+    Ok((count, features))
+}
+
+/// 行ごとに Feature を解析して `visit` に渡す（false を返したらやめる。`source` はエラーのメッセージ用）
+fn for_each_line(
+    mut reader: impl BufRead,
+    source: &str,
+    skip_invalid: bool,
+    visit: &mut dyn FnMut(Feature) -> bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut read_features = 0;
+    let mut line = Vec::new();
+    // 行の先頭のバイト目（0 始まり）と行番号（1 始まり）
+    let (mut offset, mut number) = (0u64, 0usize);
+    let mut skipped = 0;
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+        number += 1;
+        match parse_line(&line) {
+            Some(Ok(feature)) => {
+                read_features += 1;
+                if !visit(feature) {
+                    break;
+                }
+            }
+            Some(Err(err)) => {
+                let message = invalid(source, offset, number, &err);
+                if !skip_invalid {
+                    return Err(message.into());
+                }
+                log::warning!("警告: 不正な Feature を読み飛ばしました: {}", message);
+                skipped += 1;
+            }
+            None => {}
+        }
+        offset += read as u64;
+    }
+    warn_skipped(source, skipped, read_features);
+    Ok(())
+}
+
+/// 1 行の Feature を解析する（空の行は None）
+fn parse_line(line: &[u8]) -> Option<Result<Feature, String>> {
+    match feature_text(line) {
+        Some(text) => Some(text.parse::<Feature>().map_err(|err| err.to_string())),
+        None if std::str::from_utf8(line).is_err() => {
+            Some(Err("UTF-8 として読めません".to_string()))
+        }
+        None => None,
+    }
+}
+
+/// 解析できない行のメッセージ
+fn invalid(source: &str, offset: u64, number: usize, err: &str) -> String {
+    format!("{}: {} バイト目 ({} 行目): {}", source, offset, number, err)
+}
+
+/// 1 行の Feature の JSON（空の行と UTF-8 でない行は None）。
+/// GeoJSON Text Sequences (RFC 8142) の区切り文字 (RS) も許容する
+fn feature_text(line: &[u8]) -> Option<&str> {
+    let text = std::str::from_utf8(line).ok()?;
+    let text = text.trim_start_matches('\u{1e}').trim();
+    (!text.is_empty()).then_some(text)
+}
+
+fn collection(features: Vec<Feature>) -> FeatureCollection {
+    FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEATURE: &str = r#"{"type":"Feature","geometry":null,"properties":{"N03_004":"川越市"}}"#;
+
+    #[cfg(unix)]
+    fn shell(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        command
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn stopping_early_ends_a_command_that_keeps_writing() {
+        let mut features = Vec::new();
+        stream_command(
+            shell(&format!("yes '{}'", FEATURE)),
+            "yes",
+            &mut |feature| {
+                features.push(feature);
+                features.len() < 3
+            },
+        )
+        .unwrap();
+        assert_eq!(features.len(), 3);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn parse_errors_end_the_command_and_are_reported() {
+        let script = format!("echo '{}'; echo 'not json'; sleep 30", FEATURE);
+        let start = std::time::Instant::now();
+        let mut count = 0;
+        let err = stream_command(shell(&script), "sh", &mut |_| {
+            count += 1;
+            true
+        })
+        .unwrap_err();
+        assert_eq!(count, 1);
+        assert!(err.to_string().contains("2 行目"), "{}", err);
+        // sleep の終わりを待たずに止めている
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn failing_commands_are_errors() {
+        let err = stream_command(shell("exit 3"), "sh", &mut |_| true).unwrap_err();
+        assert!(err.to_string().contains("エラー終了"), "{}", err);
+    }
+
+    const LINES: &str = concat!(
+        "{\"type\":\"Feature\",\"properties\":{\"N03_004\":\"川越市\"},\"geometry\":null}\n",
+        "\n",
+        "\u{1e}{\"type\":\"Feature\",\"properties\":{\"N03_004\":\"所沢市\"},\"geometry\":null}\n",
+        "{\"type\":\"Feature\",\"properties\":\n",
+        "{\"type\":\"Feature\",\"properties\":{\"N03_004\":\"秩父市\"},\"geometry\":null}\n",
+    );
+
+    #[test]
+    fn invalid_lines_are_reported_by_offset_and_line_number() {
+        let err = parse(LINES.as_bytes(), "test.geojsonl", false)
+            .unwrap_err()
+            .to_string();
+        let offset = LINES
+            .find("{\"type\":\"Feature\",\"properties\":\n")
+            .unwrap();
+        assert!(
+            err.starts_with(&format!("test.geojsonl: {} バイト目 (4 行目): ", offset)),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn invalid_lines_can_be_skipped() {
+        let collection = parse(LINES.as_bytes(), "test.geojsonl", true).unwrap();
+        let cities: Vec<_> = collection
+            .features
+            .iter()
+            .map(|feature| feature.property("N03_004").unwrap().as_str().unwrap())
+            .collect();
+        assert_eq!(cities, ["川越市", "所沢市", "秩父市"]);
+    }
+}
@@ -0,0 +1,258 @@
+//! SQLite のデータベースファイルの読み込み（rusqlite で SQLite を組み込んで使う）。
+//!
+//! MBTiles と GeoPackage を読むために、テーブルの定義を調べて全レコードを取り出す。
+//! 読み取り専用で開くため、WAL に残った変更も読めるが、ファイルを書き換えることはない。
+
+use rusqlite::{types::ValueRef, Connection, ErrorCode, OpenFlags};
+
+/// レコードの値
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    /// 整数値（整数の値を持つ実数も受け付ける）
+    pub fn integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(v) => Some(*v),
+            Value::Real(v) if v.fract() == 0.0 => Some(*v as i64),
+            _ => None,
+        }
+    }
+
+    pub fn blob(&self) -> Option<&[u8]> {
+        match self {
+            Value::Blob(b) => Some(b),
+            Value::Text(s) => Some(s.as_bytes()),
+            _ => None,
+        }
+    }
+
+    /// 結合のキーとして比較するための文字列表現
+    pub fn key(&self) -> Option<String> {
+        match self {
+            Value::Integer(v) => Some(v.to_string()),
+            Value::Text(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl From<ValueRef<'_>> for Value {
+    fn from(value: ValueRef) -> Value {
+        match value {
+            ValueRef::Null => Value::Null,
+            ValueRef::Integer(v) => Value::Integer(v),
+            ValueRef::Real(v) => Value::Real(v),
+            ValueRef::Text(s) => Value::Text(String::from_utf8_lossy(s).into_owned()),
+            ValueRef::Blob(b) => Value::Blob(b.to_vec()),
+        }
+    }
+}
+
+/// テーブルの定義（列名）
+pub struct Table {
+    name: String,
+    columns: Vec<String>,
+    /// INTEGER PRIMARY KEY の列（rowid の別名）
+    rowid_column: Option<usize>,
+}
+
+impl Table {
+    /// 列の位置。列がなければエラー
+    pub fn column(&self, name: &str) -> Result<usize, String> {
+        self.columns
+            .iter()
+            .position(|column| column.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("{} テーブルに {} 列がありません", self.name, name))
+    }
+
+    /// 列名（定義の順）
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// INTEGER PRIMARY KEY の列の位置
+    pub fn rowid_column(&self) -> Option<usize> {
+        self.rowid_column
+    }
+
+    /// `SELECT` で使う、引用符で囲んだテーブル名
+    fn quoted(&self) -> String {
+        quote(&self.name)
+    }
+}
+
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    pub fn open(path: &str) -> Result<Database, String> {
+        let connection = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .map_err(|err| format!("{} を読み込めません: {}", path, err))?;
+        // ヘッダーは最初の問い合わせで読まれる
+        connection
+            .query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+            .map_err(|err| match err.sqlite_error_code() {
+                Some(ErrorCode::NotADatabase) => {
+                    format!("{} は SQLite のデータベースではありません", path)
+                }
+                _ => format!("{} を読み込めません: {}", path, err),
+            })?;
+        Ok(Database { connection })
+    }
+
+    /// テーブルの定義を探す（ビューは対象外）。見つからなければ None
+    pub fn table(&self, name: &str) -> Result<Option<Table>, String> {
+        let error = |err: rusqlite::Error| format!("{} テーブルの定義を読めません: {}", name, err);
+        let found: Option<String> = self
+            .connection
+            .query_row(
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?1 COLLATE NOCASE",
+                [name],
+                |row| row.get(0),
+            )
+            .map(Some)
+            .or_else(|err| match err {
+                rusqlite::Error::QueryReturnedNoRows => Ok(None),
+                err => Err(err),
+            })
+            .map_err(error)?;
+        let Some(name) = found else {
+            return Ok(None);
+        };
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT name, type, pk FROM pragma_table_info(?1) ORDER BY cid")
+            .map_err(error)?;
+        let info = statement
+            .query_map([&name], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(error)?;
+        // 主キーが 1 列の INTEGER であれば rowid の別名になる
+        let rowid_column = match info.iter().filter(|(_, _, pk)| *pk > 0).count() {
+            1 => info
+                .iter()
+                .position(|(_, kind, pk)| *pk == 1 && kind.eq_ignore_ascii_case("INTEGER")),
+            _ => None,
+        };
+        Ok(Some(Table {
+            name,
+            columns: info.into_iter().map(|(column, _, _)| column).collect(),
+            rowid_column,
+        }))
+    }
+
+    /// テーブルの行数
+    pub fn count(&self, table: &Table) -> Result<usize, String> {
+        self.connection
+            .query_row(
+                &format!("SELECT count(*) FROM {}", table.quoted()),
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .map(|count| count as usize)
+            .map_err(|err| format!("{} テーブルを読めません: {}", table.name, err))
+    }
+
+    /// テーブルの全レコード（値は列の定義の順）を順に処理する
+    pub fn scan(
+        &self,
+        table: &Table,
+        mut visit: impl FnMut(Vec<Value>) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let columns = table
+            .columns
+            .iter()
+            .map(|column| quote(column))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let error = |err: rusqlite::Error| format!("{} テーブルを読めません: {}", table.name, err);
+        let mut statement = self
+            .connection
+            .prepare(&format!("SELECT {} FROM {}", columns, table.quoted()))
+            .map_err(error)?;
+        let mut rows = statement.query([]).map_err(error)?;
+        while let Some(row) = rows.next().map_err(error)? {
+            let values = (0..table.columns.len())
+                .map(|i| row.get_ref(i).map(Value::from))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(error)?;
+            visit(values)?;
+        }
+        Ok(())
+    }
+}
+
+/// 識別子を二重引用符で囲む
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_are_read_with_their_columns() {
+        let path = std::env::temp_dir().join(format!("layon-sqlite-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let connection = Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE \"Cities\" (fid INTEGER PRIMARY KEY, \"name \"\"x\"\"\" TEXT, area REAL, shape BLOB);
+                 INSERT INTO Cities VALUES (3, '川越市', 109.13, x'0102');
+                 INSERT INTO Cities VALUES (7, NULL, 72.11, NULL);
+                 CREATE VIEW v AS SELECT * FROM Cities;",
+            )
+            .unwrap();
+        drop(connection);
+
+        let db = Database::open(path.to_str().unwrap()).unwrap();
+        let table = db.table("cities").unwrap().unwrap();
+        assert_eq!(table.columns(), ["fid", "name \"x\"", "area", "shape"]);
+        assert_eq!(table.rowid_column(), Some(0));
+        assert_eq!(table.column("AREA").unwrap(), 2);
+        assert!(table.column("missing").is_err());
+        assert_eq!(db.count(&table).unwrap(), 2);
+        let mut rows = Vec::new();
+        db.scan(&table, |values| {
+            rows.push(values);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(rows[0][0].integer(), Some(3));
+        assert_eq!(rows[0][1].key().as_deref(), Some("川越市"));
+        assert_eq!(rows[0][3].blob(), Some(&[1u8, 2][..]));
+        assert!(matches!(rows[1][1], Value::Null));
+        assert!(db.table("v").unwrap().is_none());
+
+        std::fs::write(
+            &path,
+            b"not a database, but long enough to have a header....",
+        )
+        .unwrap();
+        let err = Database::open(path.to_str().unwrap()).err().unwrap();
+        assert!(
+            err.contains("SQLite のデータベースではありません"),
+            "{}",
+            err
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+//! WKB（ISO WKB と PostGIS の EWKB。16 進文字列で表したものも）を GeoJSON のジオメトリに変換する。
+//! Z / M 値は読み捨てる。
+
+use geojson::Value;
+
+type Position = Vec<f64>;
+
+// EWKB の型番号に立てられるフラグ
+const EWKB_Z: u32 = 0x8000_0000;
+const EWKB_M: u32 = 0x4000_0000;
+const EWKB_SRID: u32 = 0x2000_0000;
+
+/// 16 進表記の WKB かどうか（WKT と区別するために使う）
+pub fn looks_like_hex(text: &str) -> bool {
+    let text = text.trim();
+    text.len() >= 10 && text.len().is_multiple_of(2) && text.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// 16 進表記の WKB を解析する
+pub fn parse_hex(text: &str) -> Result<Value, String> {
+    let text = text.trim();
+    let bytes = (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| "WKB の 16 進表記が不正です".to_string())?;
+    parse(&bytes)
+}
+
+/// バイト列の WKB を解析する（GeoPackage のジオメトリの本体など）
+pub fn parse(bytes: &[u8]) -> Result<Value, String> {
+    let mut reader = Reader {
+        bytes,
+        pos: 0,
+        little_endian: true,
+    };
+    let value = geometry(&mut reader)?;
+    if reader.pos != bytes.len() {
+        return Err("WKB の末尾に余分なデータがあります".to_string());
+    }
+    Ok(value)
+}
+
+fn geometry(r: &mut Reader) -> Result<Value, String> {
+    r.little_endian = match r.u8()? {
+        0 => false,
+        1 => true,
+        other => return Err(format!("WKB のバイトオーダーが不正です: {}", other)),
+    };
+
+    let raw = r.u32()?;
+    let mut dims = 2;
+    if raw & EWKB_Z != 0 {
+        dims += 1;
+    }
+    if raw & EWKB_M != 0 {
+        dims += 1;
+    }
+    if raw & EWKB_SRID != 0 {
+        r.u32()?;
+    }
+    // ISO WKB は 1000 (Z), 2000 (M), 3000 (ZM) を型番号に足す
+    let code = raw & 0x0FFF_FFFF;
+    dims += match code / 1000 {
+        1 | 2 => 1,
+        3 => 2,
+        _ => 0,
+    };
+
+    let value = match code % 1000 {
+        1 => Value::Point(r.position(dims)?),
+        2 => Value::LineString(r.positions(dims)?),
+        3 => Value::Polygon(r.rings(dims)?),
+        4 => Value::MultiPoint(members(r, |v| match v {
+            Value::Point(p) => Some(p),
+            _ => None,
+        })?),
+        5 => Value::MultiLineString(members(r, |v| match v {
+            Value::LineString(l) => Some(l),
+            _ => None,
+        })?),
+        6 => Value::MultiPolygon(members(r, |v| match v {
+            Value::Polygon(p) => Some(p),
+            _ => None,
+        })?),
+        7 => {
+            let n = r.u32()?;
+            let geometries = (0..n)
+                .map(|_| geometry(r).map(geojson::Geometry::new))
+                .collect::<Result<_, _>>()?;
+            Value::GeometryCollection(geometries)
+        }
+        other => return Err(format!("未対応の WKB ジオメトリ型です: {}", other)),
+    };
+    Ok(value)
+}
+
+/// Multi 系の要素（それぞれがヘッダー付きの WKB）を読む
+fn members<T>(r: &mut Reader, extract: fn(Value) -> Option<T>) -> Result<Vec<T>, String> {
+    let n = r.u32()?;
+    (0..n)
+        .map(|_| extract(geometry(r)?).ok_or_else(|| "WKB の要素の型が不正です".to_string()))
+        .collect()
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    little_endian: bool,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + N)
+            .ok_or("WKB が途中で終わっています")?;
+        self.pos += N;
+        Ok(slice.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take()?;
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        let bytes = self.take()?;
+        Ok(if self.little_endian {
+            f64::from_le_bytes(bytes)
+        } else {
+            f64::from_be_bytes(bytes)
+        })
+    }
+
+    fn position(&mut self, dims: usize) -> Result<Position, String> {
+        let x = self.f64()?;
+        let y = self.f64()?;
+        for _ in 2..dims {
+            self.f64()?;
+        }
+        Ok(vec![x, y])
+    }
+
+    fn positions(&mut self, dims: usize) -> Result<Vec<Position>, String> {
+        let n = self.u32()?;
+        (0..n).map(|_| self.position(dims)).collect()
+    }
+
+    fn rings(&mut self, dims: usize) -> Result<Vec<Vec<Position>>, String> {
+        let n = self.u32()?;
+        (0..n).map(|_| self.positions(dims)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ewkb_with_srid_and_z_is_read_as_two_dimensional() {
+        // SRID=4326;POINT Z (1 2 3) のビッグエンディアンの EWKB
+        let hex = "00A0000001000010E63FF000000000000040000000000000004008000000000000";
+        assert!(looks_like_hex(hex));
+        assert_eq!(parse_hex(hex).unwrap(), Value::Point(vec![1.0, 2.0]));
+    }
+
+    #[test]
+    fn iso_wkb_polygons_are_read() {
+        let mut bytes = vec![1];
+        bytes.extend(3u32.to_le_bytes());
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(4u32.to_le_bytes());
+        for (x, y) in [(0.0f64, 0.0f64), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)] {
+            bytes.extend(x.to_le_bytes());
+            bytes.extend(y.to_le_bytes());
+        }
+        let ring = vec![
+            vec![0.0, 0.0],
+            vec![1.0, 0.0],
+            vec![1.0, 1.0],
+            vec![0.0, 0.0],
+        ];
+        assert_eq!(parse(&bytes).unwrap(), Value::Polygon(vec![ring]));
+        bytes.push(0);
+        assert_eq!(
+            parse(&bytes).unwrap_err(),
+            "WKB の末尾に余分なデータがあります"
+        );
+    }
+
+    #[test]
+    fn wkt_is_not_mistaken_for_hex() {
+        assert!(!looks_like_hex("POINT (1 2)"));
+        assert!(!looks_like_hex("0101"));
+    }
+}
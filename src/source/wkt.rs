@@ -0,0 +1,223 @@
+//! WKT（Well-Known Text）と EWKT を GeoJSON のジオメトリに変換する。
+//! Z / M 値は読み捨て、`SRID=…;` の接頭辞は無視する。
+
+use geojson::Value;
+
+type Position = Vec<f64>;
+
+/// WKT 文字列を解析する。EMPTY のジオメトリは None を返す。
+pub fn parse(text: &str) -> Result<Option<Value>, String> {
+    let text = text.trim();
+    // EWKT の SRID 指定は読み飛ばす
+    let text = match text.split_once(';') {
+        Some((srid, rest)) if srid.trim().to_uppercase().starts_with("SRID=") => rest,
+        _ => text,
+    };
+
+    let mut tokens = Tokens::new(text);
+    let value = geometry(&mut tokens)?;
+    if let Some(token) = tokens.next() {
+        return Err(format!("WKT の末尾に余分な文字があります: {}", token));
+    }
+    Ok(value)
+}
+
+fn geometry(tokens: &mut Tokens) -> Result<Option<Value>, String> {
+    let kind = tokens.next().ok_or("WKT が空です")?.to_uppercase();
+
+    // 次元指定（Z, M, ZM）は無視する
+    if let Some(dim) = tokens.peek() {
+        if ["Z", "M", "ZM"].contains(&dim.to_uppercase().as_str()) {
+            tokens.next();
+        }
+    }
+    if tokens
+        .peek()
+        .is_some_and(|t| t.eq_ignore_ascii_case("EMPTY"))
+    {
+        tokens.next();
+        return Ok(None);
+    }
+
+    let value = match kind.as_str() {
+        "POINT" => {
+            tokens.expect("(")?;
+            let point = position(tokens)?;
+            tokens.expect(")")?;
+            Value::Point(point)
+        }
+        "LINESTRING" => Value::LineString(positions(tokens)?),
+        "POLYGON" => Value::Polygon(rings(tokens)?),
+        "MULTIPOINT" => {
+            // `MULTIPOINT (1 2, 3 4)` と `MULTIPOINT ((1 2), (3 4))` の両方を受け付ける
+            tokens.expect("(")?;
+            let mut points = Vec::new();
+            loop {
+                if tokens.peek() == Some("(") {
+                    tokens.next();
+                    points.push(position(tokens)?);
+                    tokens.expect(")")?;
+                } else {
+                    points.push(position(tokens)?);
+                }
+                if !tokens.comma_or_close()? {
+                    break;
+                }
+            }
+            Value::MultiPoint(points)
+        }
+        "MULTILINESTRING" => Value::MultiLineString(list(tokens, positions)?),
+        "MULTIPOLYGON" => Value::MultiPolygon(list(tokens, rings)?),
+        "GEOMETRYCOLLECTION" => {
+            tokens.expect("(")?;
+            let mut geometries = Vec::new();
+            loop {
+                if let Some(value) = geometry(tokens)? {
+                    geometries.push(geojson::Geometry::new(value));
+                }
+                if !tokens.comma_or_close()? {
+                    break;
+                }
+            }
+            Value::GeometryCollection(geometries)
+        }
+        _ => return Err(format!("未対応の WKT ジオメトリです: {}", kind)),
+    };
+    Ok(Some(value))
+}
+
+/// `( 要素, 要素, ... )` を読む
+fn list<T>(
+    tokens: &mut Tokens,
+    item: fn(&mut Tokens) -> Result<T, String>,
+) -> Result<Vec<T>, String> {
+    tokens.expect("(")?;
+    let mut items = Vec::new();
+    loop {
+        items.push(item(tokens)?);
+        if !tokens.comma_or_close()? {
+            return Ok(items);
+        }
+    }
+}
+
+fn rings(tokens: &mut Tokens) -> Result<Vec<Vec<Position>>, String> {
+    list(tokens, positions)
+}
+
+fn positions(tokens: &mut Tokens) -> Result<Vec<Position>, String> {
+    list(tokens, position)
+}
+
+/// 座標 1 つ（`x y [z [m]]`）を読み、x と y だけを返す
+fn position(tokens: &mut Tokens) -> Result<Position, String> {
+    let mut values = Vec::new();
+    while let Some(token) = tokens.peek() {
+        if token == "," || token == ")" {
+            break;
+        }
+        let value = token
+            .parse::<f64>()
+            .map_err(|_| format!("座標が数値ではありません: {}", token))?;
+        values.push(value);
+        tokens.next();
+    }
+    if values.len() < 2 {
+        return Err("座標には x と y が必要です".to_string());
+    }
+    values.truncate(2);
+    Ok(values)
+}
+
+/// 括弧・カンマと、それ以外の語に分割するトークナイザ
+struct Tokens<'a> {
+    rest: &'a str,
+    peeked: Option<&'a str>,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(text: &'a str) -> Self {
+        Tokens {
+            rest: text,
+            peeked: None,
+        }
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        if let Some(token) = self.peeked.take() {
+            return Some(token);
+        }
+        self.rest = self.rest.trim_start();
+        let first = self.rest.chars().next()?;
+        let len = if "(),".contains(first) {
+            1
+        } else {
+            self.rest
+                .find(|c: char| c.is_whitespace() || "(),".contains(c))
+                .unwrap_or(self.rest.len())
+        };
+        let (token, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Some(token)
+    }
+
+    fn peek(&mut self) -> Option<&'a str> {
+        if self.peeked.is_none() {
+            self.peeked = self.next();
+        }
+        self.peeked
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!(
+                "WKT に {} が必要ですが {} がありました",
+                expected, token
+            )),
+            None => Err(format!("WKT に {} が必要ですが末尾に達しました", expected)),
+        }
+    }
+
+    /// カンマなら true、閉じ括弧なら false を返す
+    fn comma_or_close(&mut self) -> Result<bool, String> {
+        match self.next() {
+            Some(",") => Ok(true),
+            Some(")") => Ok(false),
+            Some(token) => Err(format!("WKT に , か ) が必要ですが {} がありました", token)),
+            None => Err("WKT の括弧が閉じられていません".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ewkt_with_z_values_is_read_as_two_dimensional() {
+        assert_eq!(
+            parse("SRID=6668;POLYGON Z ((0 0 5, 1 0 5, 1 1 5, 0 0 5))").unwrap(),
+            Some(Value::Polygon(vec![vec![
+                vec![0.0, 0.0],
+                vec![1.0, 0.0],
+                vec![1.0, 1.0],
+                vec![0.0, 0.0],
+            ]]))
+        );
+        assert_eq!(parse("multipolygon empty").unwrap(), None);
+    }
+
+    #[test]
+    fn both_multipoint_forms_are_accepted() {
+        let expected = Some(Value::MultiPoint(vec![vec![1.0, 2.0], vec![3.0, 4.0]]));
+        assert_eq!(parse("MULTIPOINT (1 2, 3 4)").unwrap(), expected);
+        assert_eq!(parse("MULTIPOINT ((1 2), (3 4))").unwrap(), expected);
+    }
+
+    #[test]
+    fn trailing_text_is_an_error() {
+        let err = parse("POINT (1 2) x").unwrap_err();
+        assert_eq!(err, "WKT の末尾に余分な文字があります: x");
+    }
+}
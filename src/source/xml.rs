@@ -0,0 +1,197 @@
+//! KML などを読み込むための XML の要素の木（quick-xml で読む）。
+//!
+//! 要素・属性・テキスト（CDATA と文字参照を含む）だけを扱い、DTD や名前空間の解決は行わない。
+//! 名前空間の接頭辞（`kml:Placemark` など）は取り除いたローカル名で比較する。
+
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+
+/// XML の要素
+#[derive(Default)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    /// 属性の値を取得する
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| local_name(key) == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// 子要素を順に返す
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    /// 指定した名前の最初の子要素
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|element| element.is(name))
+    }
+
+    /// 子孫要素から指定した名前の要素をすべて集める（見つかった要素の中までは探さない）
+    pub fn descendants<'a>(&'a self, name: &str, found: &mut Vec<&'a Element>) {
+        for element in self.elements() {
+            if element.is(name) {
+                found.push(element);
+            } else {
+                element.descendants(name, found);
+            }
+        }
+    }
+
+    /// 要素内のテキストを連結して返す
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for node in &self.children {
+            match node {
+                Node::Text(t) => text.push_str(t),
+                Node::Element(element) => text.push_str(&element.text()),
+            }
+        }
+        text
+    }
+
+    /// 名前空間の接頭辞を除いた要素名が一致するか
+    pub fn is(&self, name: &str) -> bool {
+        local_name(&self.name) == name
+    }
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+/// 文書全体を解析し、ルート要素を返す
+pub fn parse(input: &str) -> Result<Element, String> {
+    let mut reader = Reader::from_str(input);
+    // 内容を入れるための仮のルート
+    let mut stack = vec![Element::default()];
+    let error = |reader: &Reader<&[u8]>, err: quick_xml::Error| {
+        format!("{} バイト目: {}", reader.error_position(), err)
+    };
+
+    loop {
+        match reader.read_event().map_err(|err| error(&reader, err))? {
+            Event::Start(start) => stack.push(element(&start)?),
+            Event::Empty(start) => {
+                let element = element(&start)?;
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .children
+                    .push(Node::Element(element));
+            }
+            // 開始タグとの対応は quick-xml が確かめる
+            Event::End(_) => {
+                let element = stack.pop().unwrap();
+                stack
+                    .last_mut()
+                    .ok_or("終了タグに対応する開始タグがありません")?
+                    .children
+                    .push(Node::Element(element));
+            }
+            Event::Text(text) => {
+                // 知らない実体参照はそのまま残す
+                let text = match text.unescape() {
+                    Ok(text) => text.into_owned(),
+                    Err(_) => String::from_utf8_lossy(&text).into_owned(),
+                };
+                push_text(&mut stack, text);
+            }
+            Event::CData(text) => {
+                push_text(&mut stack, String::from_utf8_lossy(&text).into_owned())
+            }
+            Event::Eof => break,
+            // 宣言、コメント、処理命令、DTD は読み飛ばす
+            _ => {}
+        }
+    }
+
+    if stack.len() != 1 {
+        return Err(format!(
+            "<{}> が閉じられていません",
+            stack.last().unwrap().name
+        ));
+    }
+    stack
+        .pop()
+        .unwrap()
+        .children
+        .into_iter()
+        .find_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+        .ok_or_else(|| "ルート要素がありません".to_string())
+}
+
+/// 開始タグ（または自己終了タグ）の要素名と属性
+fn element(start: &BytesStart) -> Result<Element, String> {
+    let mut element = Element {
+        name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+        ..Element::default()
+    };
+    for attribute in start.attributes() {
+        let attribute =
+            attribute.map_err(|err| format!("<{}> の属性が不正です: {}", element.name, err))?;
+        let value = attribute
+            .unescape_value()
+            .map_err(|err| format!("<{}> の属性の値が不正です: {}", element.name, err))?;
+        element.attributes.push((
+            String::from_utf8_lossy(attribute.key.as_ref()).into_owned(),
+            value.into_owned(),
+        ));
+    }
+    Ok(element)
+}
+
+fn push_text(stack: &mut [Element], text: String) {
+    if !text.is_empty() {
+        stack.last_mut().unwrap().children.push(Node::Text(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elements_attributes_and_text_are_read() {
+        let root = parse(
+            r#"<?xml version="1.0"?>
+<!-- comment -->
+<kml:kml xmlns:kml="http://www.opengis.net/kml/2.2">
+  <kml:Placemark id="a&amp;b"><name>川越&#24066;</name><description><![CDATA[<b>x</b>]]></description><Point/></kml:Placemark>
+</kml:kml>"#,
+        )
+        .unwrap();
+        assert!(root.is("kml"));
+        let placemark = root.child("Placemark").unwrap();
+        assert_eq!(placemark.attribute("id"), Some("a&b"));
+        assert_eq!(placemark.child("name").unwrap().text(), "川越市");
+        assert_eq!(placemark.child("description").unwrap().text(), "<b>x</b>");
+        assert!(placemark.child("Point").is_some());
+    }
+
+    #[test]
+    fn mismatched_and_unclosed_tags_are_errors() {
+        assert!(parse("<a><b></a>").is_err());
+        assert!(parse("<a><b></b>").is_err());
+        assert!(parse("text only").is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! 複数のファイルをまとめた ZIP の読み込み（unzip を利用する）。
+//!
+//! 読み込める形式のファイル（GeoJSON, CSV / TSV, KML など。gdal フィーチャーが有効ならシェープファイルも）を
+//! 1 つのレイヤーとし、一時ディレクトリ（`scratch`、`--tmpdir` で変えられる）に展開してから、その形式のまま読み込む。
+//! レイヤーの名前は ZIP の中のパスから拡張子を除いたもの（例: `boundary/N03`）。
+
+use super::{has_extension, Input, InputOptions, Layer, LAYER_PROPERTY};
+use crate::scratch::{self, Scratch};
+use geojson::{Feature, FeatureCollection};
+use std::{error::Error, path::Path, process::Command};
+
+/// レイヤーとして読み込むファイルの拡張子
+const EXTENSIONS: &[&str] = &[
+    "geojson",
+    "json",
+    "geojsonl",
+    "geojsons",
+    "ndjson",
+    "jsonl",
+    "csv",
+    "tsv",
+    "kml",
+    "kmz",
+    #[cfg(feature = "gdal")]
+    "shp",
+];
+
+/// ZIP の中のレイヤーにするファイル
+struct Entry {
+    /// ZIP の中のパス
+    path: String,
+    /// レイヤーの名前
+    name: String,
+}
+
+/// レイヤーの一覧（Feature の数は展開しないとわからないため数えない）
+pub fn layers(path: &str) -> Result<Vec<Layer>, Box<dyn Error>> {
+    Ok(entries(path)?
+        .into_iter()
+        .map(|entry| Layer {
+            name: entry.name,
+            features: None,
+            geometry: None,
+        })
+        .collect())
+}
+
+/// `wanted` のレイヤー（空ならすべてのレイヤー）を読み込む。Feature の `layer` プロパティにレイヤーの名前を入れる
+pub fn read(path: &str, wanted: &[String]) -> Result<FeatureCollection, Box<dyn Error>> {
+    let entries = super::select_layers(path, entries(path)?, wanted, |entry| &entry.name)?;
+    let scratch = scratch::current()?;
+    let mut features = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let dir = scratch.subdir(&format!("zip-{}", i))?;
+        let result = read_entry(path, entry, &dir, &scratch);
+        // 展開したファイルは、読み込めなかった場合も残さない
+        let _ = scratch.remove_subdir(&dir);
+        features.extend(result?);
+    }
+    Ok(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    })
+}
+
+fn read_entry(
+    path: &str,
+    entry: &Entry,
+    dir: &Path,
+    scratch: &Scratch,
+) -> Result<Vec<Feature>, Box<dyn Error>> {
+    // シェープファイルは .dbf や .prj なども要るため、拡張子の違う同じ名前のファイルもまとめて展開する
+    let pattern = format!("{}.*", entry.name);
+    unzip(&["-qq", "-o", path, &pattern, "-d", &dir.to_string_lossy()])?;
+    scratch.account(dir).map_err(|err| err.to_string())?;
+
+    let file = dir.join(&entry.path);
+    let mut collection =
+        Input::parse(&file.to_string_lossy(), InputOptions::default())?.read_raw()?;
+    for feature in &mut collection.features {
+        feature.set_property(LAYER_PROPERTY, entry.name.as_str());
+    }
+    Ok(collection.features)
+}
+
+/// ZIP の中のレイヤーにするファイル（ZIP の中の順）
+fn entries(path: &str) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let list = unzip(&["-Z1", path])?;
+    let entries: Vec<Entry> = list
+        .lines()
+        // macOS の Finder で作った ZIP に入るリソースフォーク
+        .filter(|entry| !entry.starts_with("__MACOSX/"))
+        .filter(|entry| EXTENSIONS.iter().any(|ext| has_extension(entry, ext)))
+        .map(|entry| Entry {
+            path: entry.to_string(),
+            name: entry
+                .rsplit_once('.')
+                .map_or(entry, |(name, _)| name)
+                .to_string(),
+        })
+        .collect();
+    if entries.is_empty() {
+        return Err(format!(
+            "{} に読み込める形式のファイル ({}) が含まれていません",
+            path,
+            EXTENSIONS.join(", ")
+        )
+        .into());
+    }
+    Ok(entries)
+}
+
+/// unzip を実行し、標準出力を返す
+pub(super) fn unzip(args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let output = Command::new("unzip")
+        .args(args)
+        .output()
+        .map_err(|err| format!("unzip を起動できませんでした: {}", err))?;
+    if !output.status.success() {
+        return Err(format!("unzip がエラー終了しました ({})", output.status).into());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
@@ -0,0 +1,560 @@
+//! メッセージのストリームから Feature を受け取り続けて集計する（`layon stream`）。
+//! 集計値はメモリ上で更新し続け、呼び出し側が一定の間隔でスナップショットを出力先に書き出す。
+//!
+//! 受け取れるのは標準入力（1 行 1 Feature の GeoJSON）と NATS の subject。
+//! Kafka などは `kcat -C -t <topic> | layon stream` のように標準入力に流す。
+//!
+//! 受け取り → 解析 → 計算 → 集計 → 出力はそれぞれ別のスレッドで動かし、上限のあるチャネルでつなぐ。
+//! 出力先（ネットワーク越しのデータベースなど）への書き込みが遅くても、受け取ったメッセージが溜まり続けないように、
+//! 後のステージが追いつかなければ前のステージが待つ（標準入力なら、書き込む側のパイプも止まる）。
+
+use crate::{
+    aggregate::{self, GroupResult, Metric},
+    filter::Filter,
+};
+use geojson::{Feature, GeoJson};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+/// NATS の既定のポート
+const NATS_PORT: u16 = 4222;
+
+/// ステージの間のチャネルに溜めておくメッセージの数の上限
+pub const CAPACITY: usize = 256;
+
+/// 受け取ったメッセージの本文と、受け取り元での位置
+pub struct Message {
+    pub text: String,
+    pub position: Position,
+}
+
+/// メッセージの受け取り元での位置（読み飛ばしたメッセージを知らせるときに使う）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Position {
+    /// 標準入力の行番号（1 始まり）と、その行の先頭までのバイト数
+    Line { number: usize, offset: usize },
+    /// NATS から受け取った順の番号（1 始まり）。ストリームにはバイトの位置がない
+    Sequence(usize),
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Position::Line { number, offset } => {
+                write!(f, "{} 行目、{} バイト目", number, offset)
+            }
+            Position::Sequence(number) => write!(f, "{} 件目", number),
+        }
+    }
+}
+
+/// メッセージの受け取り元
+pub enum StreamSource {
+    /// 標準入力（1 行が 1 メッセージ）
+    Stdin,
+    /// NATS の subject（`nats://host:port/subject`）
+    Nats { address: String, subject: String },
+}
+
+impl StreamSource {
+    /// `--source` の値を解析する（`-` は標準入力）
+    pub fn parse(source: &str) -> Result<StreamSource, String> {
+        if source == "-" {
+            return Ok(StreamSource::Stdin);
+        }
+        let Some(rest) = source.strip_prefix("nats://") else {
+            return Err(format!(
+                "受け取り元は - か nats://host:port/subject で指定してください: {}",
+                source
+            ));
+        };
+        let (host, subject) = rest
+            .split_once('/')
+            .filter(|(_, subject)| !subject.is_empty())
+            .ok_or_else(|| format!("NATS の subject を指定してください: {}", source))?;
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{}:{}", host, NATS_PORT)
+        };
+        Ok(StreamSource::Nats {
+            address,
+            subject: subject.to_string(),
+        })
+    }
+
+    /// 表示用の受け取り元の説明
+    pub fn describe(&self) -> String {
+        match self {
+            StreamSource::Stdin => "標準入力".to_string(),
+            StreamSource::Nats { address, subject } => {
+                format!("NATS ({} の {})", address, subject)
+            }
+        }
+    }
+
+    /// 別のスレッドでメッセージを受け取り始める（受け取ったメッセージは `CAPACITY` 件まで溜める）。
+    /// 受け取り元が終わる（標準入力の終わり、接続が切れる）とチャネルが閉じる
+    pub fn subscribe(&self) -> Result<Receiver<Result<Message, String>>, Box<dyn Error>> {
+        match self {
+            StreamSource::Stdin => Ok(subscribe_lines(BufReader::new(std::io::stdin()))),
+            StreamSource::Nats { address, subject } => {
+                let (sender, receiver) = mpsc::sync_channel(CAPACITY);
+                // 接続できないことはすぐに知らせたいので、購読までは呼び出したスレッドで行う
+                let (mut reader, mut writer) = nats_subscribe(address, subject)?;
+                thread::spawn(move || {
+                    let mut number = 0;
+                    loop {
+                        let message = nats_next(&mut reader, &mut writer);
+                        let done = !matches!(message, Ok(Some(_)));
+                        let message = message.transpose().map(|message| {
+                            message.map(|text| {
+                                number += 1;
+                                Message {
+                                    text,
+                                    position: Position::Sequence(number),
+                                }
+                            })
+                        });
+                        if let Some(message) = message {
+                            if sender.send(message).is_err() {
+                                break;
+                            }
+                        }
+                        if done {
+                            break;
+                        }
+                    }
+                });
+                Ok(receiver)
+            }
+        }
+    }
+}
+
+/// 別のスレッドで `reader` の 1 行を 1 メッセージとして受け取る（行の終わりの改行は除く）
+fn subscribe_lines(reader: impl BufRead + Send + 'static) -> Receiver<Result<Message, String>> {
+    let (sender, receiver) = mpsc::sync_channel(CAPACITY);
+    thread::spawn(move || {
+        let mut reader = reader;
+        let (mut number, mut offset) = (0, 0);
+        loop {
+            let mut line = String::new();
+            let message = match reader.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(size) => {
+                    number += 1;
+                    let position = Position::Line { number, offset };
+                    offset += size;
+                    let text = line.strip_suffix('\n').unwrap_or(&line);
+                    Ok(Message {
+                        text: text.strip_suffix('\r').unwrap_or(text).to_string(),
+                        position,
+                    })
+                }
+                Err(err) => Err(err.to_string()),
+            };
+            let failed = message.is_err();
+            if sender.send(message).is_err() || failed {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// NATS のサーバーに接続して subject を購読する
+fn nats_subscribe(
+    address: &str,
+    subject: &str,
+) -> Result<(BufReader<TcpStream>, TcpStream), Box<dyn Error>> {
+    let stream = TcpStream::connect(address)
+        .map_err(|err| format!("NATS ({}) に接続できません: {}", address, err))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    // 最初にサーバーの情報（INFO {...}）が届く
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("INFO") {
+        return Err(format!("NATS のサーバーではありません: {}", address).into());
+    }
+    write!(
+        writer,
+        "CONNECT {{\"verbose\":false,\"pedantic\":false,\"name\":\"layon\"}}\r\nSUB {} 1\r\n",
+        subject
+    )?;
+    writer.flush()?;
+    Ok((reader, writer))
+}
+
+/// 次のメッセージの本文を受け取る（接続が切れたら None）
+fn nats_next(
+    reader: &mut BufReader<TcpStream>,
+    writer: &mut TcpStream,
+) -> Result<Option<String>, String> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).map_err(|err| err.to_string())? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        let mut words = line.split_whitespace();
+        match words.next() {
+            // MSG <subject> <sid> [reply-to] <#bytes>
+            Some("MSG") => {
+                let size: usize = words
+                    .last()
+                    .and_then(|size| size.parse().ok())
+                    .ok_or_else(|| format!("NATS のメッセージの形式が不正です: {}", line))?;
+                // 本文の後には \r\n が続く
+                let mut payload = vec![0; size + 2];
+                reader
+                    .read_exact(&mut payload)
+                    .map_err(|err| err.to_string())?;
+                payload.truncate(size);
+                return String::from_utf8(payload)
+                    .map(Some)
+                    .map_err(|_| "NATS のメッセージが UTF-8 ではありません".to_string());
+            }
+            Some("PING") => writer
+                .write_all(b"PONG\r\n")
+                .map_err(|err| err.to_string())?,
+            Some("-ERR") => return Err(format!("NATS のエラー: {}", line)),
+            // +OK、PONG、INFO（クラスターの変更の通知）は読み飛ばす
+            _ => {}
+        }
+    }
+}
+
+/// 集計キーと集計値の求め方（集計キーのプロパティ、集計値、条件）
+pub struct Measure {
+    group_by: String,
+    metric: Metric,
+    filter: Option<Filter>,
+}
+
+impl Measure {
+    pub fn new(group_by: &str, metric: Metric, filter: Option<Filter>) -> Measure {
+        Measure {
+            group_by: group_by.to_string(),
+            metric,
+            filter,
+        }
+    }
+
+    /// Feature の集計キーと面積（条件に合わないか集計キーがなければ None）
+    pub fn measure(&self, feature: &Feature) -> Option<(String, f64)> {
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !filter.matches(feature))
+        {
+            return None;
+        }
+        let key = feature
+            .properties
+            .as_ref()
+            .and_then(|properties| properties.get(&self.group_by))
+            .and_then(|value| value.as_str())?;
+        Some((
+            key.to_string(),
+            aggregate::feature_area(feature, self.metric),
+        ))
+    }
+}
+
+/// メッセージの各行（Feature または FeatureCollection の GeoJSON）を解析する
+pub fn parse_message(message: &str) -> Result<Vec<Feature>, String> {
+    let mut features = Vec::new();
+    for line in message.lines().filter(|line| !line.trim().is_empty()) {
+        match line.parse::<GeoJson>().map_err(|err| err.to_string())? {
+            GeoJson::Feature(feature) => features.push(feature),
+            GeoJson::FeatureCollection(collection) => features.extend(collection.features),
+            GeoJson::Geometry(_) => {
+                return Err("ジオメトリだけのメッセージは集計できません".to_string())
+            }
+        }
+    }
+    Ok(features)
+}
+
+/// 計算のステージから集計のステージに渡す、1 つのメッセージの結果
+pub enum Measured {
+    /// 集計に加える Feature の集計キーと面積
+    Features(Vec<(String, f64)>),
+    /// 解析できなかったメッセージ（受け取り元での位置）
+    Skipped { position: Position, error: String },
+}
+
+/// 受け取ったメッセージを解析するステージと、集計キーと面積を求めるステージをそれぞれ別のスレッドで始める。
+/// 受け取り元 → 解析 → 計算の間は `CAPACITY` 件までのチャネルでつなぐため、
+/// 後のステージが追いつかなければ前のステージが待ち、溜まるメッセージの数は増え続けない
+pub fn spawn_stages(
+    messages: Receiver<Result<Message, String>>,
+    measure: Measure,
+) -> Receiver<Result<Measured, String>> {
+    let (parsed_sender, parsed) = mpsc::sync_channel(CAPACITY);
+    thread::spawn(move || {
+        for message in messages {
+            let parsed = message.map(|message| (message.position, parse_message(&message.text)));
+            if parsed_sender.send(parsed).is_err() {
+                break;
+            }
+        }
+    });
+    let (measured_sender, measured) = mpsc::sync_channel(CAPACITY);
+    thread::spawn(move || {
+        for parsed in parsed {
+            let measured = parsed.map(|(position, features)| match features {
+                Ok(features) => Measured::Features(
+                    features
+                        .iter()
+                        .filter_map(|feature| measure.measure(feature))
+                        .collect(),
+                ),
+                Err(error) => Measured::Skipped { position, error },
+            });
+            if measured_sender.send(measured).is_err() {
+                break;
+            }
+        }
+    });
+    measured
+}
+
+/// メモリ上で更新し続ける集計値
+#[derive(Default)]
+pub struct RollingAggregate {
+    /// 集計キー -> (面積, Feature の数)
+    groups: HashMap<String, (f64, usize)>,
+    /// 集計した Feature の数
+    features: usize,
+}
+
+impl RollingAggregate {
+    /// Feature の集計キーと面積を集計に加える
+    pub fn add(&mut self, values: Vec<(String, f64)>) {
+        for (key, area) in values {
+            let entry = self.groups.entry(key).or_insert((0.0, 0));
+            entry.0 += area;
+            entry.1 += 1;
+            self.features += 1;
+        }
+    }
+
+    /// これまでに集計した Feature の数
+    pub fn features(&self) -> usize {
+        self.features
+    }
+
+    /// 現時点の集計結果（面積の降順）
+    pub fn snapshot(&self) -> Vec<GroupResult> {
+        let mut rows: Vec<GroupResult> = self
+            .groups
+            .iter()
+            .map(|(key, &(area, count))| GroupResult {
+                key: key.clone(),
+                layer: None,
+                area,
+                count,
+                geometry: None,
+                ids: Vec::new(),
+                values: Vec::new(),
+                kept: Vec::new(),
+                class: None,
+            })
+            .collect();
+        rows.sort_by(|a, b| b.area.total_cmp(&a.area));
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Cursor, net::TcpListener};
+
+    /// x が `x` から始まる、一辺が `size` の正方形の Feature の GeoJSON（1 行）
+    fn feature(city: &str, x: f64, size: f64) -> String {
+        format!(
+            r#"{{"type":"Feature","properties":{{"N03_004":"{city}"}},"geometry":{{"type":"Polygon","coordinates":[[[{x},0],[{x1},0],[{x1},{size}],[{x},{size}],[{x},0]]]}}}}"#,
+            x1 = x + size
+        )
+    }
+
+    fn measure() -> Measure {
+        Measure::new("N03_004", Metric::Area, None)
+    }
+
+    fn keys_and_areas(rows: &[GroupResult]) -> Vec<(&str, f64, usize)> {
+        rows.iter()
+            .map(|row| (row.key.as_str(), row.area, row.count))
+            .collect()
+    }
+
+    #[test]
+    fn stdin_lines_update_the_rolling_snapshot() {
+        let input = format!(
+            "{}\n{}\r\n{{\"type\":\n\n{}\n",
+            feature("甲", 0.0, 1.0),
+            feature("乙", 1.0, 2.0),
+            feature("甲", 3.0, 1.0),
+        );
+        // 読み飛ばす 3 行目の先頭までのバイト数
+        let skipped_offset = input.find("{\"type\":\n").unwrap();
+        let measured = spawn_stages(subscribe_lines(Cursor::new(input.into_bytes())), measure());
+
+        let mut rolling = RollingAggregate::default();
+        let mut snapshots = Vec::new();
+        let mut skipped = Vec::new();
+        for measured in measured {
+            match measured.unwrap() {
+                Measured::Features(values) => rolling.add(values),
+                Measured::Skipped { position, .. } => skipped.push(position),
+            }
+            snapshots.push(rolling.snapshot());
+        }
+
+        assert_eq!(
+            skipped,
+            [Position::Line {
+                number: 3,
+                offset: skipped_offset
+            }]
+        );
+        // 空の行も 1 つのメッセージ（Feature なし）として数える
+        assert_eq!(snapshots.len(), 5);
+        assert_eq!(keys_and_areas(&snapshots[0]), [("甲", 1.0, 1)]);
+        // CRLF の行も読める。集計値は受け取るたびに更新され、面積の降順に並ぶ
+        assert_eq!(
+            keys_and_areas(&snapshots[1]),
+            [("乙", 4.0, 1), ("甲", 1.0, 1)]
+        );
+        assert_eq!(keys_and_areas(&snapshots[3]), keys_and_areas(&snapshots[1]));
+        assert_eq!(
+            keys_and_areas(&snapshots[4]),
+            [("乙", 4.0, 1), ("甲", 2.0, 2)]
+        );
+        assert_eq!(rolling.features(), 3);
+        assert_eq!(
+            Position::Line {
+                number: 3,
+                offset: 12
+            }
+            .to_string(),
+            "3 行目、12 バイト目"
+        );
+    }
+
+    #[test]
+    fn nats_messages_are_received_with_their_sequence_numbers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let messages = [
+            feature("甲", 0.0, 1.0),
+            "{\"type\":".to_string(),
+            // 1 つのメッセージに改行区切りで複数の Feature を入れられる
+            format!("{}\n{}", feature("乙", 1.0, 2.0), feature("甲", 3.0, 1.0)),
+        ];
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            writer
+                .write_all(b"INFO {\"server_id\":\"fake\",\"max_payload\":1048576}\r\n")
+                .unwrap();
+            let mut received = Vec::new();
+            for _ in 0..2 {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                received.push(line);
+            }
+            // サーバーからの PING には PONG を返す
+            writer.write_all(b"PING\r\n").unwrap();
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            received.push(line);
+            for (i, body) in messages.iter().enumerate() {
+                // 返信先 (reply-to) の付いたメッセージや、+OK の間に挟まったものも読める
+                let reply = if i == 2 { " _INBOX.1" } else { "" };
+                write!(
+                    writer,
+                    "+OK\r\nMSG cities 1{} {}\r\n{}\r\n",
+                    reply,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+            writer.flush().unwrap();
+            // 接続を閉じると、受け取り元が終わる
+            received
+        });
+
+        let source = StreamSource::parse(&format!("nats://{}/cities", address)).unwrap();
+        let measured = spawn_stages(source.subscribe().unwrap(), measure());
+        let mut rolling = RollingAggregate::default();
+        let mut skipped = Vec::new();
+        for measured in measured {
+            match measured.unwrap() {
+                Measured::Features(values) => rolling.add(values),
+                Measured::Skipped { position, error } => skipped.push((position, error)),
+            }
+        }
+        let received = server.join().unwrap();
+
+        assert!(received[0].starts_with("CONNECT {"), "{}", received[0]);
+        assert_eq!(received[1], "SUB cities 1\r\n");
+        assert_eq!(received[2], "PONG\r\n");
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0, Position::Sequence(2));
+        assert_eq!(skipped[0].0.to_string(), "2 件目");
+        assert_eq!(
+            keys_and_areas(&rolling.snapshot()),
+            [("乙", 4.0, 1), ("甲", 2.0, 2)]
+        );
+    }
+
+    #[test]
+    fn nats_errors_and_bad_sources_are_reported() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            writer.write_all(b"INFO {}\r\n").unwrap();
+            // CONNECT と SUB を読んでから、購読を拒む
+            for _ in 0..2 {
+                reader.read_line(&mut String::new()).unwrap();
+            }
+            writer
+                .write_all(b"-ERR 'Permissions Violation for Subscription to cities'\r\n")
+                .unwrap();
+        });
+        let source = StreamSource::parse(&format!("nats://{}/cities", address)).unwrap();
+        let messages: Vec<_> = source.subscribe().unwrap().into_iter().collect();
+        server.join().unwrap();
+        assert_eq!(messages.len(), 1);
+        let Err(error) = &messages[0] else {
+            panic!("エラーになるはず");
+        };
+        assert!(error.contains("Permissions Violation"), "{}", error);
+
+        assert!(StreamSource::parse("nats://localhost").is_err());
+        assert!(StreamSource::parse("kafka://localhost/cities").is_err());
+        let Ok(StreamSource::Nats { address, .. }) = StreamSource::parse("nats://localhost/a.b")
+        else {
+            panic!("NATS の受け取り元になるはず");
+        };
+        assert_eq!(address, "localhost:4222");
+    }
+}
@@ -0,0 +1,130 @@
+//! 入力の一部の Feature の取り出し（`layon head` / `layon sample`）。
+//! テスト用の小さなデータを作るために、先頭の Feature か、無作為に抽出した Feature を返す。
+
+use crate::source::Input;
+use geojson::{Feature, FeatureCollection};
+use rayon::prelude::*;
+use std::error::Error;
+
+/// 入力の先頭の `count` 個の Feature を読み込む。
+/// PostGIS や 1 行 1 Feature の GeoJSON は `count` 個を読んだところでやめ、残りは読まない
+pub fn read_head(input: &Input, count: usize) -> Result<FeatureCollection, Box<dyn Error>> {
+    let mut features = Vec::with_capacity(count.min(1 << 16));
+    if count > 0 {
+        input.stream(&mut |feature| {
+            features.push(feature);
+            features.len() < count
+        })?;
+    }
+    Ok(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    })
+}
+
+/// Feature をそれぞれ確率 `fraction` で選ぶ（元の順序は保つ）。
+/// 選ぶかどうかは `seed` と Feature の位置だけで決まるので、同じ入力と seed なら結果も同じになる。
+pub fn sample(collection: FeatureCollection, fraction: f64, seed: u64) -> FeatureCollection {
+    let threshold = fraction.clamp(0.0, 1.0);
+    let features: Vec<Feature> = collection
+        .features
+        .into_par_iter()
+        .enumerate()
+        .filter(|(i, _)| uniform(seed, *i as u64) < threshold)
+        .map(|(_, feature)| feature)
+        .collect();
+    FeatureCollection {
+        features,
+        ..collection
+    }
+}
+
+/// seed と位置から [0, 1) の一様乱数を作る（SplitMix64 の出力関数）
+pub(crate) fn uniform(seed: u64, index: u64) -> f64 {
+    let mut z = seed.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    // 上位 53 ビットを仮数に使う
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::InputOptions;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/n03_11_sample.geojson"
+    );
+
+    fn input(path: &str) -> Input {
+        Input::parse(path, InputOptions::default()).unwrap()
+    }
+
+    /// `count` 個の Feature（`index` プロパティに位置）
+    fn numbered(count: usize) -> FeatureCollection {
+        let features: Vec<String> = (0..count)
+            .map(|i| {
+                format!(
+                    r#"{{"type":"Feature","properties":{{"index":{}}},"geometry":null}}"#,
+                    i
+                )
+            })
+            .collect();
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        )
+        .parse()
+        .unwrap()
+    }
+
+    fn indices(collection: &FeatureCollection) -> Vec<u64> {
+        collection
+            .features
+            .iter()
+            .map(|feature| feature.property("index").unwrap().as_u64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn head_reads_the_first_features_and_stops() {
+        let all = input(FIXTURE).read().unwrap();
+        let head = read_head(&input(FIXTURE), 3).unwrap();
+        assert_eq!(head.features, all.features[..3]);
+        assert!(read_head(&input(FIXTURE), 0).unwrap().features.is_empty());
+        assert_eq!(read_head(&input(FIXTURE), 100).unwrap().features.len(), 8);
+
+        // 1 行 1 Feature の GeoJSON は `count` 個を読んだら残りの行を読まない
+        let path = std::env::temp_dir().join(format!("layon-head-{}.geojsonl", std::process::id()));
+        let lines: Vec<String> = numbered(2)
+            .features
+            .iter()
+            .map(|feature| feature.to_string())
+            .collect();
+        std::fs::write(&path, format!("{}\n解析できない行\n", lines.join("\n"))).unwrap();
+        let head = read_head(&input(path.to_str().unwrap()), 2).unwrap();
+        assert_eq!(indices(&head), [0, 1]);
+        assert!(read_head(&input(path.to_str().unwrap()), 3).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn samples_keep_the_order_and_depend_only_on_the_seed() {
+        let sampled = sample(numbered(10_000), 0.3, 42);
+        let chosen = indices(&sampled);
+        // 選ぶ数は二項分布に従う (標準偏差はおよそ 46)
+        assert!((2800..3200).contains(&chosen.len()), "{}", chosen.len());
+        assert!(chosen.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(indices(&sample(numbered(10_000), 0.3, 42)), chosen);
+        assert_ne!(indices(&sample(numbered(10_000), 0.3, 43)), chosen);
+
+        assert_eq!(sample(numbered(100), 1.0, 7).features.len(), 100);
+        assert!(sample(numbered(100), 0.0, 7).features.is_empty());
+        // 範囲外の割合は 0 から 1 に丸める
+        assert_eq!(sample(numbered(100), 1.5, 7).features.len(), 100);
+    }
+}
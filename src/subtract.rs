@@ -0,0 +1,150 @@
+//! 別のレイヤーのポリゴンを差し引いてから集計する（`--subtract lakes.geojson`）。
+//! 行政区域の面積には湖や河川などの水面も含まれるため、水域のレイヤーを差し引いて陸地の面積を求める。
+//! 差し引くレイヤーのポリゴンは R-tree に入れ、外接矩形が重なるものだけを Feature ごとに並列に差し引く。
+
+use crate::aggregate::to_multi_polygon;
+use geo::{BooleanOps, BoundingRect, Geometry, MultiPolygon};
+use geojson::{FeatureCollection, Value};
+use rayon::prelude::*;
+use rstar::{primitives::GeomWithData, primitives::Rectangle, RTree, AABB};
+
+/// 差し引くポリゴン
+pub struct Mask {
+    polygons: Vec<MultiPolygon<f64>>,
+    tree: RTree<GeomWithData<Rectangle<[f64; 2]>, usize>>,
+}
+
+impl Mask {
+    /// レイヤーのポリゴン（Polygon、MultiPolygon、GeometryCollection の中のポリゴン）から作る
+    pub fn new(collection: &FeatureCollection) -> Mask {
+        let polygons: Vec<MultiPolygon<f64>> = collection
+            .features
+            .par_iter()
+            .filter_map(|feature| {
+                let geometry: Geometry<f64> =
+                    feature.geometry.as_ref()?.value.clone().try_into().ok()?;
+                to_multi_polygon(geometry)
+            })
+            .collect();
+        let tree = RTree::bulk_load(
+            polygons
+                .iter()
+                .enumerate()
+                .filter_map(|(i, polygons)| {
+                    let rect = polygons.bounding_rect()?;
+                    Some(GeomWithData::new(
+                        Rectangle::from_corners(
+                            [rect.min().x, rect.min().y],
+                            [rect.max().x, rect.max().y],
+                        ),
+                        i,
+                    ))
+                })
+                .collect(),
+        );
+        Mask { polygons, tree }
+    }
+
+    /// ポリゴンから、重なるマスクのポリゴンを差し引く（重なるものがなければ None）
+    pub fn subtract(&self, polygons: &MultiPolygon<f64>) -> Option<MultiPolygon<f64>> {
+        let rect = polygons.bounding_rect()?;
+        let envelope =
+            AABB::from_corners([rect.min().x, rect.min().y], [rect.max().x, rect.max().y]);
+        let mut result: Option<MultiPolygon<f64>> = None;
+        for candidate in self.tree.locate_in_envelope_intersecting(&envelope) {
+            let current = result.as_ref().unwrap_or(polygons);
+            result = Some(current.difference(&self.polygons[candidate.data]));
+        }
+        result
+    }
+}
+
+/// すべての Feature のポリゴンからマスクを差し引き、外接矩形が重なった Feature の位置を返す（`--audit-log`）。
+/// GeometryCollection は、置き換えると線や点のメンバーがなくなるため差し引かない
+pub fn subtract_each(collection: &mut FeatureCollection, mask: &Mask) -> Vec<usize> {
+    collection
+        .features
+        .par_iter_mut()
+        .enumerate()
+        .filter_map(|(position, feature)| {
+            let geometry = feature.geometry.as_mut()?;
+            if !matches!(geometry.value, Value::Polygon(_) | Value::MultiPolygon(_)) {
+                return None;
+            }
+            let polygons = Geometry::<f64>::try_from(geometry.value.clone())
+                .ok()
+                .and_then(to_multi_polygon)?;
+            let rest = mask.subtract(&polygons)?;
+            geometry.value = Value::from(&rest);
+            Some(position)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregate::{self, Metric};
+
+    fn square(x0: f64, y0: f64, x1: f64, y1: f64) -> String {
+        format!(
+            r#"{{"type":"Polygon","coordinates":[[[{x0},{y0}],[{x1},{y0}],[{x1},{y1}],[{x0},{y1}],[{x0},{y0}]]]}}"#
+        )
+    }
+
+    fn collection(geometries: &[String]) -> FeatureCollection {
+        let features: Vec<String> = geometries
+            .iter()
+            .map(|geometry| {
+                format!(
+                    r#"{{"type":"Feature","properties":{{}},"geometry":{}}}"#,
+                    geometry
+                )
+            })
+            .collect();
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        )
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn lakes_are_subtracted_from_the_land_they_cover() {
+        let mut land = collection(&[
+            square(0.0, 0.0, 10.0, 10.0),
+            square(10.0, 0.0, 20.0, 10.0),
+            // 湖と重ならない
+            square(30.0, 0.0, 31.0, 1.0),
+            // 湖に覆われる
+            square(40.0, 0.0, 41.0, 1.0),
+            format!(
+                r#"{{"type":"GeometryCollection","geometries":[{}]}}"#,
+                square(0.0, 0.0, 10.0, 10.0)
+            ),
+        ]);
+        let lakes = collection(&[
+            // 1 つ目の市の中の 2 × 3 の湖
+            square(2.0, 2.0, 4.0, 5.0),
+            // 2 つの市にまたがる 4 × 2 の湖
+            square(8.0, 4.0, 12.0, 6.0),
+            square(39.0, -1.0, 42.0, 2.0),
+            // ポリゴンでないものは使わない
+            r#"{"type":"LineString","coordinates":[[30,0],[31,1]]}"#.to_string(),
+        ]);
+        let mask = Mask::new(&lakes);
+        assert_eq!(subtract_each(&mut land, &mask), [0, 1, 3]);
+        let areas: Vec<f64> = land
+            .features
+            .iter()
+            .map(|feature| aggregate::feature_area(feature, Metric::Area))
+            .collect();
+        assert_eq!(areas, [100.0 - 6.0 - 4.0, 100.0 - 4.0, 1.0, 0.0, 100.0]);
+        // 市の中の湖は穴になる
+        assert!(matches!(
+            &land.features[0].geometry.as_ref().unwrap().value,
+            Value::MultiPolygon(polygons) if polygons.len() == 1 && polygons[0].len() == 2
+        ));
+    }
+}
@@ -0,0 +1,232 @@
+//! 複数のプロパティから集計キーを作るテンプレート（`--group-by-template`）。
+//!
+//! 例: `{N03_001}/{N03_004}` は "埼玉県/川越市"、`{N03_007/^(\d\d)/}` は団体コードの先頭 2 桁（都道府県）。
+//! - `{PROPERTY}` はプロパティの値（数値や真偽値は文字列にする）
+//! - `{PROPERTY/REGEX/}` は値のうち正規表現に一致した部分（括弧があれば 1 つ目の括弧の部分。`/` は `\/` と書く）。
+//!   正規表現の書式は regex クレートのもの
+//! - `{{` と `}}` は `{` と `}` そのもの
+//!
+//! プロパティがない、null である、正規表現に一致しない Feature には集計キーを付けない（集計に含めない）。
+
+use geojson::{FeatureCollection, JsonObject, JsonValue};
+use rayon::prelude::*;
+use regex::Regex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+enum Part {
+    Text(String),
+    Property { name: String, regex: Option<Regex> },
+}
+
+/// 解析済みのテンプレート
+pub struct KeyTemplate {
+    source: String,
+    parts: Vec<Part>,
+}
+
+impl KeyTemplate {
+    pub fn parse(text: &str) -> Result<KeyTemplate, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => {
+                    return Err(
+                        "テンプレートの } に対応する { がありません ( } そのものは }} と書きます)"
+                            .to_string(),
+                    )
+                }
+                '{' => {
+                    let mut name = String::new();
+                    let mut regex = None;
+                    loop {
+                        match chars.next() {
+                            None => {
+                                return Err(format!("テンプレートの {{{} が閉じていません", name))
+                            }
+                            Some('}') => break,
+                            Some('/') => {
+                                let mut pattern = String::new();
+                                loop {
+                                    match chars.next() {
+                                        None => {
+                                            return Err(format!(
+                                            "テンプレートの {{{}/ の正規表現が / で閉じていません",
+                                            name
+                                        ))
+                                        }
+                                        Some('/') => break,
+                                        Some('\\') if chars.peek() == Some(&'/') => {
+                                            chars.next();
+                                            pattern.push('/');
+                                        }
+                                        Some('\\') => {
+                                            pattern.push('\\');
+                                            pattern.extend(chars.next());
+                                        }
+                                        Some(c) => pattern.push(c),
+                                    }
+                                }
+                                regex = Some(Regex::new(&pattern).map_err(|err| {
+                                    format!(
+                                        "テンプレートの {{{}}} の正規表現が不正です: {}",
+                                        name, err
+                                    )
+                                })?);
+                                if chars.next() != Some('}') {
+                                    return Err(format!(
+                                        "テンプレートの {{{}/{}/ の後ろは }} で閉じてください",
+                                        name, pattern
+                                    ));
+                                }
+                                break;
+                            }
+                            Some(c) => name.push(c),
+                        }
+                    }
+                    let name = name.trim().to_string();
+                    if name.is_empty() {
+                        return Err("テンプレートの {} にプロパティの名前がありません".to_string());
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Property { name, regex });
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+        if !parts
+            .iter()
+            .any(|part| matches!(part, Part::Property { .. }))
+        {
+            return Err(
+                "テンプレートにプロパティ ({PROPERTY} の形) が 1 つもありません".to_string(),
+            );
+        }
+        Ok(KeyTemplate {
+            source: text.to_string(),
+            parts,
+        })
+    }
+
+    /// 集計キーを入れるプロパティの名前（テンプレートの文字列そのもの）
+    pub fn property(&self) -> &str {
+        &self.source
+    }
+
+    /// テンプレートが参照するプロパティ（重複を除いて現れる順）
+    pub fn properties(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for part in &self.parts {
+            if let Part::Property { name, .. } = part {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+
+    /// プロパティから集計キーを作る（作れなければ None）
+    pub fn render(&self, properties: &JsonObject) -> Option<String> {
+        let mut key = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => key.push_str(text),
+                Part::Property { name, regex } => {
+                    let value = match properties.get(name)? {
+                        JsonValue::String(s) => s.clone(),
+                        JsonValue::Null => return None,
+                        other => other.to_string(),
+                    };
+                    match regex {
+                        Some(regex) => key.push_str(extract(regex, &value)?),
+                        None => key.push_str(&value),
+                    }
+                }
+            }
+        }
+        Some(key)
+    }
+
+    /// 各 Feature の `property()` に集計キーを入れ、集計キーを作れなかった Feature の数を返す
+    pub fn assign_all(&self, collection: &mut FeatureCollection) -> usize {
+        let missing = AtomicUsize::new(0);
+        collection.features.par_iter_mut().for_each(|feature| {
+            let key = feature
+                .properties
+                .as_ref()
+                .and_then(|properties| self.render(properties));
+            match key {
+                Some(key) => feature.set_property(self.property(), key),
+                None => {
+                    missing.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+        missing.into_inner()
+    }
+}
+
+/// 最初に一致した部分（括弧があれば 1 つ目の括弧の部分。一致しなければ None）
+fn extract<'t>(regex: &Regex, text: &'t str) -> Option<&'t str> {
+    let captures = regex.captures(text)?;
+    let group = if regex.captures_len() > 1 { 1 } else { 0 };
+    captures.get(group).map(|m| m.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regexes_extract_the_first_group_or_the_whole_match() {
+        let regex = |pattern| Regex::new(pattern).unwrap();
+        assert_eq!(extract(&regex(r"\d+"), "第12区"), Some("12"));
+        assert_eq!(extract(&regex(r"^(.+?)(?:市|町)$"), "川越市"), Some("川越"));
+        assert_eq!(extract(&regex(r"^(\d\d)"), "1"), None);
+        // 1 つ目の括弧が一致に加わらなければ集計キーを作らない
+        assert_eq!(extract(&regex(r"(a)?b"), "b"), None);
+    }
+
+    #[test]
+    fn templates_render_properties_and_escaped_braces() {
+        let template = KeyTemplate::parse(r"{{{N03_001}}}/{N03_007/^(\d\d)/}/{N03_001}").unwrap();
+        assert_eq!(template.properties(), ["N03_001", "N03_007"]);
+        let properties: JsonObject =
+            serde_json::from_str(r#"{"N03_001": "埼玉県", "N03_007": 11201}"#).unwrap();
+        assert_eq!(
+            template.render(&properties).as_deref(),
+            Some("{埼玉県}/11/埼玉県")
+        );
+        let properties: JsonObject = serde_json::from_str(r#"{"N03_001": null}"#).unwrap();
+        assert_eq!(template.render(&properties), None);
+    }
+
+    #[test]
+    fn malformed_templates_are_rejected() {
+        for text in [
+            "川越",
+            "{N03_004",
+            "{N03_004/(/}",
+            "{N03_004/x/ }",
+            "{ }",
+            "a}",
+        ] {
+            assert!(KeyTemplate::parse(text).is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! 集計結果を端末に描く粗い地図（`--term-map`）。
+//! SSH 越しでも結果をざっと確かめられるよう、文字のセルごとに中心が入るグループを探し、
+//! 集計値の四分位で濃さの違うブロック文字（░▒▓█）を置く。
+
+use crate::aggregate::GroupResult;
+use geo::{BoundingRect, Contains, Point, Rect};
+use rayon::prelude::*;
+
+/// 四分位ごとの文字（値の小さい順）
+const SHADES: [char; 4] = ['░', '▒', '▓', '█'];
+
+/// 端末の文字のセルの縦と横の比（縦長なので、縦 1 セルを横 2 セル分として扱う）
+const CELL_ASPECT: f64 = 2.0;
+
+/// 幅 `width` 桁の地図と凡例を描く。ジオメトリのない行は描かない（すべてなければ None）
+pub fn render(rows: &[GroupResult], width: usize) -> Option<String> {
+    let shapes: Vec<_> = rows
+        .iter()
+        .filter_map(|row| {
+            let geometry = row.geometry.as_ref()?;
+            Some((row, geometry, geometry.bounding_rect()?))
+        })
+        .collect();
+    let bounds = shapes.iter().map(|(_, _, rect)| *rect).reduce(|a, b| {
+        Rect::new(
+            (a.min().x.min(b.min().x), a.min().y.min(b.min().y)),
+            (a.max().x.max(b.max().x), a.max().y.max(b.max().y)),
+        )
+    })?;
+    let width = width.max(1);
+
+    // 経度・緯度なら真ん中の緯度で経度方向を縮める（高緯度で横に伸びないように）
+    let geographic = bounds.min().x >= -180.0
+        && bounds.max().x <= 180.0
+        && bounds.min().y >= -90.0
+        && bounds.max().y <= 90.0;
+    let x_scale = if geographic {
+        ((bounds.min().y + bounds.max().y) / 2.0).to_radians().cos()
+    } else {
+        1.0
+    };
+    let cell_width = (bounds.width() / width as f64).max(f64::MIN_POSITIVE);
+    let cell_height = cell_width * CELL_ASPECT * x_scale;
+    let height = ((bounds.height() / cell_height).ceil() as usize).max(1);
+
+    // 集計値の四分位の区切り
+    let mut values: Vec<f64> = shapes.iter().map(|(row, _, _)| row.area).collect();
+    values.sort_by(f64::total_cmp);
+    let quartile = |i: usize| values[(values.len() - 1) * i / SHADES.len()];
+    let shade = |value: f64| {
+        let level = (1..SHADES.len()).filter(|&i| value > quartile(i)).count();
+        SHADES[level]
+    };
+
+    let lines: Vec<String> = (0..height)
+        .into_par_iter()
+        .map(|line| {
+            let y = bounds.max().y - (line as f64 + 0.5) * cell_height;
+            (0..width)
+                .map(|column| {
+                    let x = bounds.min().x + (column as f64 + 0.5) * cell_width;
+                    let point = Point::new(x, y);
+                    shapes
+                        .iter()
+                        .find(|(_, geometry, rect)| {
+                            rect.contains(&point) && geometry.contains(&point)
+                        })
+                        .map_or(' ', |(row, _, _)| shade(row.area))
+                })
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        })
+        .collect();
+
+    let mut map = lines.join("\n");
+    map.push('\n');
+    for (i, c) in SHADES.iter().enumerate() {
+        map.push_str(&format!(
+            "{}  {:.6} 〜 {:.6}\n",
+            c,
+            quartile(i),
+            quartile(i + 1)
+        ));
+    }
+    Some(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{polygon, MultiPolygon};
+
+    fn row(key: &str, area: f64, rect: (f64, f64, f64, f64)) -> GroupResult {
+        let (x0, y0, x1, y1) = rect;
+        let square = polygon![(x: x0, y: y0), (x: x1, y: y0), (x: x1, y: y1), (x: x0, y: y1)];
+        GroupResult {
+            key: key.to_string(),
+            layer: None,
+            area,
+            count: 1,
+            geometry: Some(MultiPolygon::new(vec![square])),
+            ids: Vec::new(),
+            values: Vec::new(),
+            kept: Vec::new(),
+            class: None,
+        }
+    }
+
+    #[test]
+    fn draws_known_rows_with_their_quartile_shades() {
+        // 経緯度の範囲の外の座標なので縮めずに、横 1 単位を 1 桁、縦 2 単位を 1 行として描く
+        let rows = [
+            row("small", 1.0, (1000.0, 0.0, 1002.0, 2.0)),
+            row("large", 2.0, (1004.0, 0.0, 1006.0, 4.0)),
+        ];
+        assert_eq!(
+            render(&rows, 6).unwrap(),
+            "    ██\n\
+             ░░  ██\n\
+             ░  1.000000 〜 1.000000\n\
+             ▒  1.000000 〜 1.000000\n\
+             ▓  1.000000 〜 1.000000\n\
+             █  1.000000 〜 2.000000\n"
+        );
+    }
+
+    #[test]
+    fn rows_without_geometry_draw_nothing() {
+        let mut empty = row("empty", 1.0, (0.0, 0.0, 1.0, 1.0));
+        empty.geometry = None;
+        assert!(render(&[empty], 80).is_none());
+    }
+}
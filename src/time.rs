@@ -0,0 +1,35 @@
+//! 日時の書式（外部のクレートを使わずに UTC の時刻を書き出す）。
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// RFC 3339 形式の UTC の時刻（例: 2024-01-01T00:00:00.000Z）
+pub fn rfc3339(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = elapsed.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let second_of_day = seconds % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        second_of_day / 3600,
+        second_of_day / 60 % 60,
+        second_of_day % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+/// 1970-01-01 からの日数を年月日にする（Howard Hinnant の civil_from_days）
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
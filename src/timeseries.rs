@@ -0,0 +1,228 @@
+//! 複数の年次の行政区域データを並べた面積の推移と、合併・分割の検出。
+
+use crate::{
+    aggregate::{self, Metric},
+    log, overlay,
+};
+use geojson::FeatureCollection;
+use std::collections::{BTreeMap, HashMap};
+
+/// 隣り合う年次で重なりとみなす、交差面積の割合（小さい方の面積に対する）。
+/// 境界の精度の違いによる細い重なりを合併と取り違えないようにする
+const OVERLAP_SHARE: f64 = 0.01;
+
+/// 1 つの年次のデータ
+pub struct Vintage {
+    /// 列名にする年次（例: 2024）
+    pub label: String,
+    pub collection: FeatureCollection,
+}
+
+/// 市町村ごとの面積の推移
+pub struct TimeSeriesRow {
+    pub city: String,
+    /// 年次ごとの面積（その年次にない市町村は None）
+    pub areas: Vec<Option<f64>>,
+    /// 合併・分割などの変化（例: "2024: 川越市・仮町 が合併して 川越市"）
+    pub changes: Vec<String>,
+}
+
+/// 年次ごとに `group_by` のプロパティで面積を集計し、市町村名の順に横持ちの表にする。
+/// 隣り合う年次のポリゴンを重ね、1 つの市町村が前の年次の複数の市町村と重なれば合併、
+/// 前の年次の 1 つの市町村が複数の市町村と重なれば分割として記録する。
+pub fn timeseries(vintages: &[Vintage], group_by: &str) -> Vec<TimeSeriesRow> {
+    let mut rows = BTreeMap::<String, TimeSeriesRow>::new();
+    let mut areas = Vec::with_capacity(vintages.len());
+    for (i, vintage) in vintages.iter().enumerate() {
+        let totals: HashMap<String, f64> =
+            aggregate::aggregate(&vintage.collection, group_by, false)
+                .into_iter()
+                .map(|row| (row.key, row.area))
+                .collect();
+        for (city, &area) in &totals {
+            rows.entry(city.clone())
+                .or_insert_with(|| TimeSeriesRow {
+                    city: city.clone(),
+                    areas: vec![None; vintages.len()],
+                    changes: Vec::new(),
+                })
+                .areas[i] = Some(area);
+        }
+        areas.push(totals);
+    }
+
+    for (i, pair) in vintages.windows(2).enumerate() {
+        let (before, after) = (&pair[0], &pair[1]);
+        let (before_areas, after_areas) = (&areas[i], &areas[i + 1]);
+
+        // (前の市町村, 後の市町村) の組のうち、十分に重なるもの
+        let mut sources = BTreeMap::<&str, Vec<&str>>::new();
+        let mut targets = BTreeMap::<&str, Vec<&str>>::new();
+        let pairs = overlay::overlay(
+            &before.collection,
+            group_by,
+            &after.collection,
+            group_by,
+            Metric::Area,
+        );
+        for pair in &pairs {
+            // 重ねるときと面積を集計するときで集計キーの取り出し方が違えば、面積のない組もありうる
+            let (Some(&before_area), Some(&after_area)) =
+                (before_areas.get(&pair.city), after_areas.get(&pair.zone))
+            else {
+                log::warning!(
+                    "警告: {} の {} と {} の {} の重なりは、面積を集計できなかったため無視します",
+                    before.label,
+                    pair.city,
+                    after.label,
+                    pair.zone
+                );
+                continue;
+            };
+            let smaller = before_area.min(after_area);
+            if smaller > 0.0 && pair.area / smaller >= OVERLAP_SHARE {
+                sources.entry(&pair.zone).or_default().push(&pair.city);
+                targets.entry(&pair.city).or_default().push(&pair.zone);
+            }
+        }
+
+        let mut note = |city: &str, change: String| {
+            if let Some(row) = rows.get_mut(city) {
+                row.changes.push(format!("{}: {}", after.label, change));
+            }
+        };
+        for (city, olds) in &sources {
+            if olds.len() > 1 {
+                let change = format!("{} が合併して {}", olds.join("・"), city);
+                for old in olds.iter().filter(|old| *old != city) {
+                    note(old, change.clone());
+                }
+                note(city, change);
+            } else if olds[0] != *city
+                && !before_areas.contains_key(*city)
+                && targets.get(olds[0]).is_some_and(|news| news.len() == 1)
+            {
+                // 分割した先の新しい市町村は名称変更にしない
+                let change = format!("{} から {} に名称変更", olds[0], city);
+                note(olds[0], change.clone());
+                note(city, change);
+            }
+        }
+        for (city, news) in &targets {
+            if news.len() > 1 {
+                let change = format!("{} が {} に分割", city, news.join("・"));
+                for new in news.iter().filter(|new| *new != city) {
+                    note(new, change.clone());
+                }
+                note(city, change);
+            }
+        }
+        // 重なりでは説明できない変化（範囲外の市町村の追加、データの欠落など）
+        for city in before_areas.keys() {
+            if !after_areas.contains_key(city) && !targets.contains_key(city.as_str()) {
+                note(city, "消滅".to_string());
+            }
+        }
+        for city in after_areas.keys() {
+            if !before_areas.contains_key(city) && !sources.contains_key(city.as_str()) {
+                note(city, "新設".to_string());
+            }
+        }
+    }
+
+    rows.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// x が `from` から `to` までの高さ 1 の長方形を並べた年次
+    fn vintage(label: &str, cities: &[(&str, f64, f64)]) -> Vintage {
+        let features: Vec<String> = cities
+            .iter()
+            .map(|(city, from, to)| {
+                format!(
+                    r#"{{"type":"Feature","properties":{{"N03_004":"{}"}},"geometry":{{"type":"Polygon","coordinates":[[[{from},0],[{to},0],[{to},1],[{from},1],[{from},0]]]}}}}"#,
+                    city
+                )
+            })
+            .collect();
+        Vintage {
+            label: label.to_string(),
+            collection: format!(
+                r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+                features.join(",")
+            )
+            .parse()
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn merges_splits_renames_and_disappearances_are_recorded() {
+        let vintages = [
+            vintage(
+                "2020",
+                &[
+                    ("甲", 0.0, 1.0),
+                    ("乙", 1.0, 2.0),
+                    ("丙", 2.0, 3.0),
+                    ("丁", 3.0, 4.0),
+                    ("戊", 5.0, 6.0),
+                ],
+            ),
+            vintage(
+                "2024",
+                &[
+                    // 甲と乙が合併して甲、丙は丙と己に分割、丁は庚に名称変更、戊は消滅
+                    ("甲", 0.0, 2.0),
+                    ("丙", 2.0, 2.75),
+                    ("己", 2.75, 3.0),
+                    ("庚", 3.0, 4.0),
+                ],
+            ),
+        ];
+        let rows = timeseries(&vintages, "N03_004");
+        // 合併した市町村は名前の順（符号位置の順）に、分割した先は重なりの大きい順に並べる
+        let find = |city: &str| rows.iter().find(|row| row.city == city).unwrap();
+
+        assert_eq!(find("甲").areas, [Some(1.0), Some(2.0)]);
+        assert_eq!(find("甲").changes, ["2024: 乙・甲 が合併して 甲"]);
+        assert_eq!(find("乙").areas, [Some(1.0), None]);
+        assert_eq!(find("乙").changes, ["2024: 乙・甲 が合併して 甲"]);
+        assert_eq!(find("丙").changes, ["2024: 丙 が 丙・己 に分割"]);
+        assert_eq!(find("己").areas, [None, Some(0.25)]);
+        assert_eq!(find("己").changes, ["2024: 丙 が 丙・己 に分割"]);
+        assert_eq!(find("丁").changes, ["2024: 丁 から 庚 に名称変更"]);
+        assert_eq!(find("庚").changes, ["2024: 丁 から 庚 に名称変更"]);
+        assert_eq!(find("戊").areas, [Some(1.0), None]);
+        assert_eq!(find("戊").changes, ["2024: 消滅"]);
+        assert_eq!(rows.len(), 7);
+    }
+
+    #[test]
+    fn thin_overlaps_and_new_cities_are_not_merges() {
+        let vintages = [
+            vintage("2020", &[("甲", 0.0, 1.0)]),
+            // 境界の精度の違いによる 0.1% の重なりは合併にしない
+            vintage(
+                "2024",
+                &[("甲", 0.0, 1.0), ("乙", 0.999, 2.0), ("丙", 5.0, 6.0)],
+            ),
+        ];
+        let rows = timeseries(&vintages, "N03_004");
+        let changes: Vec<_> = rows
+            .iter()
+            .map(|row| (row.city.as_str(), row.changes.clone()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("丙", vec!["2024: 新設".to_string()]),
+                ("乙", vec!["2024: 新設".to_string()]),
+                ("甲", vec![]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! 処理の段階ごとの時間（`--timing-json`）。
+//! 全体の処理時間だけでは、遅くなったときにどの段階が原因かわからないため、段階ごとに測って記録する。
+//!
+//! 変換（convert）と計算（compute）は Feature ごとに並列に交互に行うため、
+//! 経過時間ではなく全スレッドでかかった時間の合計を記録する（スレッドが多いと全体の時間より長くなる）。
+
+use serde::Serialize;
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write},
+    time::{Duration, Instant},
+};
+
+/// 処理の段階
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// ファイルなどからの読み込み（GeoJSON 以外の形式では解析も含む）
+    Read,
+    /// GeoJSON の解析
+    Parse,
+    /// 絞り込み、重複の除去、ポリゴンの修復
+    Prepare,
+    /// GeoJSON の座標を面積の計算用の形に変換する（全スレッドの合計）
+    Convert,
+    /// 面積の計算と集計（全スレッドの合計）
+    Compute,
+    /// 集計結果の並べ替えとディゾルブ
+    Reduce,
+    /// 書き出し
+    Write,
+}
+
+impl Stage {
+    /// 表示用の名前
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::Read => "読み込み",
+            Stage::Parse => "解析",
+            Stage::Prepare => "前処理",
+            Stage::Convert => "変換 (全スレッドの合計)",
+            Stage::Compute => "計算 (全スレッドの合計)",
+            Stage::Reduce => "集約",
+            Stage::Write => "書き出し",
+        }
+    }
+}
+
+/// 段階ごとの時間（記録した順）
+#[derive(Clone, Default)]
+pub struct Timings {
+    stages: Vec<(Stage, Duration)>,
+    /// 全体の経過時間
+    pub total: Duration,
+}
+
+impl Timings {
+    /// 段階の時間を記録する（同じ段階を何度か記録すると足し合わせる）
+    pub fn record(&mut self, stage: Stage, duration: Duration) {
+        match self.stages.iter_mut().find(|(s, _)| *s == stage) {
+            Some((_, total)) => *total += duration,
+            None => self.stages.push((stage, duration)),
+        }
+    }
+
+    /// `f` を実行し、かかった時間を `stage` として記録する
+    pub fn time<T>(&mut self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let value = f();
+        self.record(stage, start.elapsed());
+        value
+    }
+
+    /// 別に測った段階の時間を足し合わせる
+    pub fn merge(&mut self, other: Timings) {
+        for (stage, duration) in other.stages {
+            self.record(stage, duration);
+        }
+    }
+
+    /// 段階の時間（記録していなければ None）
+    pub fn get(&self, stage: Stage) -> Option<Duration> {
+        self.stages
+            .iter()
+            .find(|(s, _)| *s == stage)
+            .map(|&(_, duration)| duration)
+    }
+
+    /// 記録した段階と時間
+    pub fn stages(&self) -> impl Iterator<Item = (Stage, Duration)> + '_ {
+        self.stages.iter().copied()
+    }
+
+    /// JSON ファイルに書き出す（時間は秒）
+    pub fn write_json(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        #[derive(Serialize)]
+        struct StageRecord {
+            stage: Stage,
+            seconds: f64,
+        }
+        #[derive(Serialize)]
+        struct Record {
+            total_seconds: f64,
+            threads: usize,
+            stages: Vec<StageRecord>,
+        }
+        let record = Record {
+            total_seconds: self.total.as_secs_f64(),
+            threads: rayon::current_num_threads(),
+            stages: self
+                .stages()
+                .map(|(stage, duration)| StageRecord {
+                    stage,
+                    seconds: duration.as_secs_f64(),
+                })
+                .collect(),
+        };
+        let mut file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut file, &record)?;
+        writeln!(file)?;
+        file.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+//! ジオメトリを書き出す前の変換。GeoJSON を書き出すすべての処理で同じ変換を使う。
+//!
+//! - 座標の丸め（`--coord-precision`）: 小数点以下の桁数を減らして出力を小さくする。
+//!   丸めて同じ点が続いた場合は 1 つにまとめる
+//! - リングの向きの統一: RFC 7946 の右手の法則（外周は反時計回り、穴は時計回り）にそろえる。
+//!   向きが違うと一部のウェブ地図のクライアントで塗りつぶしが崩れるため。
+//!   `--legacy-winding` では逆向き（外周が時計回り）にする
+
+use crate::area;
+use geojson::{FeatureCollection, PointType, Value};
+use rayon::prelude::*;
+
+/// 座標の小数点以下の桁数の上限（f64 で意味のある桁数）
+pub const MAX_PRECISION: u32 = 15;
+
+/// ポリゴンのリングの向き
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Winding {
+    /// 外周は反時計回り、穴は時計回り（RFC 7946）
+    #[default]
+    Rfc7946,
+    /// 外周は時計回り、穴は反時計回り（RFC 7946 より前の慣習）
+    Legacy,
+}
+
+/// 書き出すジオメトリに適用する変換（既定ではリングの向きだけを RFC 7946 にそろえる）
+#[derive(Clone, Copy, Default)]
+pub struct GeometryTransform {
+    /// 座標を丸める小数点以下の桁数
+    pub precision: Option<u32>,
+    /// ポリゴンのリングの向き
+    pub winding: Winding,
+}
+
+impl GeometryTransform {
+    /// すべての Feature のジオメトリを変換する
+    pub fn apply_all(&self, collection: &mut FeatureCollection) {
+        collection.features.par_iter_mut().for_each(|feature| {
+            if let Some(geometry) = &mut feature.geometry {
+                self.apply(&mut geometry.value);
+            }
+        });
+    }
+
+    /// ジオメトリを変換する
+    pub fn apply(&self, value: &mut Value) {
+        if let Some(precision) = self.precision {
+            self.round(value, precision);
+        }
+        match value {
+            Value::Polygon(rings) => self.orient(rings),
+            Value::MultiPolygon(polygons) => {
+                polygons.iter_mut().for_each(|rings| self.orient(rings))
+            }
+            Value::GeometryCollection(geometries) => {
+                for geometry in geometries {
+                    self.apply(&mut geometry.value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// 1 つのポリゴンのリングの向きをそろえる（先頭が外周、残りが穴）
+    fn orient(&self, rings: &mut [Vec<PointType>]) {
+        for (i, ring) in rings.iter_mut().enumerate() {
+            // 外周を反時計回り（符号付き面積が正）にするか
+            let counter_clockwise = (i == 0) == (self.winding == Winding::Rfc7946);
+            let area = area::signed_position_area(ring);
+            if area != 0.0 && (area > 0.0) != counter_clockwise {
+                ring.reverse();
+            }
+        }
+    }
+
+    fn round(&self, value: &mut Value, precision: u32) {
+        let scale = 10f64.powi(precision as i32);
+        let round = |point: &mut PointType| {
+            for x in point.iter_mut() {
+                *x = (*x * scale).round() / scale;
+            }
+        };
+        match value {
+            Value::Point(point) => round(point),
+            Value::MultiPoint(points) => points.iter_mut().for_each(round),
+            Value::LineString(line) => round_line(line, 2, round),
+            Value::MultiLineString(lines) => {
+                for line in lines {
+                    round_line(line, 2, round);
+                }
+            }
+            Value::Polygon(rings) => {
+                for ring in rings {
+                    round_line(ring, 4, round);
+                }
+            }
+            Value::MultiPolygon(polygons) => {
+                for ring in polygons.iter_mut().flatten() {
+                    round_line(ring, 4, round);
+                }
+            }
+            // 入れ子のジオメトリは apply から辿る
+            Value::GeometryCollection(_) => {}
+        }
+    }
+}
+
+/// 線（またはリング）の座標を丸め、同じ点が続いたらまとめる。
+/// まとめると `min_points` 点より少なくなる場合はまとめない（リングが閉じなくならないように）
+fn round_line(line: &mut Vec<PointType>, min_points: usize, round: impl Fn(&mut PointType)) {
+    line.iter_mut().for_each(round);
+    let mut deduped = line.clone();
+    deduped.dedup();
+    if deduped.len() >= min_points {
+        *line = deduped;
+    }
+}
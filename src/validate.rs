@@ -0,0 +1,296 @@
+//! 入力が RFC 7946（GeoJSON）の規則に従っているかの検証（`--validate-input`）。
+//! 面積の計算は、座標の範囲やリングの向きが規則に従っていなくてもエラーにならずに進んでしまうため、
+//! 集計の前に確かめて、違反を Feature の ID とともに報告する。
+//!
+//! 確かめる規則:
+//! - 旧仕様の `crs` メンバーがないこと（座標は WGS84 の経度・緯度）
+//! - 座標が経度 [-180, 180]、緯度 [-90, 90] の範囲にあること（緯度と経度が逆なら、その旨を添える）
+//! - リングが 4 点以上で、最初と最後の点が同じであること
+//! - 外周が反時計回り、穴が時計回りであること
+
+use crate::{area, ids};
+use geojson::{FeatureCollection, PointType, Value};
+use rayon::prelude::*;
+use std::{error::Error, fmt};
+
+/// 報告に表示する違反の数（残りは規則ごとの件数にだけ数える）
+const SHOWN: usize = 20;
+
+/// 違反した規則
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rule {
+    Crs,
+    Range,
+    SwappedAxes,
+    ShortRing,
+    UnclosedRing,
+    ExteriorWinding,
+    HoleWinding,
+}
+
+impl Rule {
+    const ALL: [Rule; 7] = [
+        Rule::Crs,
+        Rule::Range,
+        Rule::SwappedAxes,
+        Rule::ShortRing,
+        Rule::UnclosedRing,
+        Rule::ExteriorWinding,
+        Rule::HoleWinding,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Rule::Crs => "crs メンバーがある",
+            Rule::Range => "座標が経度・緯度の範囲外",
+            Rule::SwappedAxes => "緯度と経度が逆",
+            Rule::ShortRing => "リングの点が 4 点未満",
+            Rule::UnclosedRing => "リングが閉じていない",
+            Rule::ExteriorWinding => "外周が時計回り",
+            Rule::HoleWinding => "穴が反時計回り",
+        }
+    }
+}
+
+/// 1 つの違反
+pub struct Violation {
+    /// Feature の ID（なければ入力の中での位置 `#0`, `#1`, …。collection 全体の違反なら空）
+    pub feature: String,
+    pub rule: Rule,
+    /// 違反した場所（`ポリゴン 1 のリング 2` や座標）
+    pub detail: String,
+}
+
+/// 違反があった場合のエラー（規則ごとの件数と先頭の違反を表示する）
+pub struct Report {
+    pub violations: Vec<Violation>,
+}
+
+/// 入力を検証し、違反を入力の順に返す
+pub fn validate(collection: &FeatureCollection) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    if let Some(crs) = collection
+        .foreign_members
+        .as_ref()
+        .and_then(|members| members.get("crs"))
+    {
+        violations.push(Violation {
+            feature: String::new(),
+            rule: Rule::Crs,
+            detail: crs.to_string(),
+        });
+    }
+    let features: Vec<Vec<Violation>> = collection
+        .features
+        .par_iter()
+        .enumerate()
+        .map(|(position, feature)| {
+            let mut found = Vec::new();
+            if let Some(geometry) = &feature.geometry {
+                check(&geometry.value, &mut |rule, detail| {
+                    found.push(Violation {
+                        feature: ids::text_or_position(feature, position),
+                        rule,
+                        detail,
+                    })
+                });
+            }
+            found
+        })
+        .collect();
+    violations.extend(features.into_iter().flatten());
+    violations
+}
+
+/// ジオメトリの違反を `report` に渡す
+fn check(value: &Value, report: &mut impl FnMut(Rule, String)) {
+    match value {
+        Value::Point(position) => check_positions(std::slice::from_ref(position), report),
+        Value::MultiPoint(positions) | Value::LineString(positions) => {
+            check_positions(positions, report)
+        }
+        Value::MultiLineString(lines) => {
+            for line in lines {
+                check_positions(line, report);
+            }
+        }
+        Value::Polygon(rings) => check_polygon(rings, None, report),
+        Value::MultiPolygon(polygons) => {
+            for (i, rings) in polygons.iter().enumerate() {
+                check_polygon(rings, Some(i + 1), report);
+            }
+        }
+        Value::GeometryCollection(geometries) => {
+            for geometry in geometries {
+                check(&geometry.value, report);
+            }
+        }
+    }
+}
+
+/// 範囲外の座標は、ジオメトリごとに最初の 1 つだけ報告する
+fn check_positions(positions: &[PointType], report: &mut impl FnMut(Rule, String)) {
+    let in_range = |x: f64, y: f64| (-180.0..=180.0).contains(&x) && (-90.0..=90.0).contains(&y);
+    if let Some(position) = positions
+        .iter()
+        .find(|position| !in_range(position[0], position[1]))
+    {
+        let (x, y) = (position[0], position[1]);
+        let rule = if in_range(y, x) {
+            Rule::SwappedAxes
+        } else {
+            Rule::Range
+        };
+        report(rule, format!("({}, {})", x, y));
+    }
+}
+
+fn check_polygon(
+    rings: &[Vec<PointType>],
+    polygon: Option<usize>,
+    report: &mut impl FnMut(Rule, String),
+) {
+    for (i, ring) in rings.iter().enumerate() {
+        let place = match polygon {
+            Some(polygon) => format!("ポリゴン {} のリング {}", polygon, i + 1),
+            None => format!("リング {}", i + 1),
+        };
+        check_positions(ring, report);
+        if ring.len() < 4 {
+            report(Rule::ShortRing, format!("{} ({} 点)", place, ring.len()));
+            continue;
+        }
+        if ring.first() != ring.last() {
+            report(Rule::UnclosedRing, place);
+            continue;
+        }
+        let area = area::signed_position_area(ring);
+        if i == 0 && area < 0.0 {
+            report(Rule::ExteriorWinding, place);
+        } else if i > 0 && area > 0.0 {
+            report(Rule::HoleWinding, place);
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "入力が RFC 7946 に従っていません (違反 {} 件)",
+            self.violations.len()
+        )?;
+        for rule in Rule::ALL {
+            let count = self.violations.iter().filter(|v| v.rule == rule).count();
+            if count > 0 {
+                write!(f, "\n  {}: {} 件", rule.label(), count)?;
+            }
+        }
+        for violation in self.violations.iter().take(SHOWN) {
+            let feature = match violation.feature.as_str() {
+                "" => "FeatureCollection",
+                feature => feature,
+            };
+            write!(
+                f,
+                "\n  {}: {} ({})",
+                feature,
+                violation.rule.label(),
+                violation.detail
+            )?;
+        }
+        if self.violations.len() > SHOWN {
+            write!(f, "\n  … ほか {} 件", self.violations.len() - SHOWN)?;
+        }
+        Ok(())
+    }
+}
+
+// main から返したときにもメッセージがそのまま表示されるようにする
+impl fmt::Debug for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Error for Report {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLLECTION: &str = r#"{"type":"FeatureCollection","crs":{"type":"name","properties":{"name":"EPSG:6668"}},"features":[
+        {"type":"Feature","id":"ok","properties":{},"geometry":{"type":"Polygon","coordinates":[[[0,0],[1,0],[1,1],[0,1],[0,0]],[[0.2,0.2],[0.2,0.8],[0.8,0.8],[0.8,0.2],[0.2,0.2]]]}},
+        {"type":"Feature","id":"far","properties":{},"geometry":{"type":"LineString","coordinates":[[139,36],[200,10],[300,10]]}},
+        {"type":"Feature","id":"swapped","properties":{},"geometry":{"type":"Point","coordinates":[35.9,139.5]}},
+        {"type":"Feature","properties":{},"geometry":{"type":"Polygon","coordinates":[[[0,0],[1,0],[1,1],[0,1]]]}},
+        {"type":"Feature","id":5,"properties":{},"geometry":{"type":"Polygon","coordinates":[[[0,0],[0,1],[1,1],[1,0],[0,0]],[[0.2,0.2],[0.8,0.2],[0.8,0.8],[0.2,0.8],[0.2,0.2]]]}},
+        {"type":"Feature","id":"multi","properties":{},"geometry":{"type":"MultiPolygon","coordinates":[[[[0,0],[1,0],[1,1],[0,0]]],[[[0,0],[1,0],[0,0]]]]}},
+        {"type":"Feature","id":"empty","properties":{},"geometry":null}
+    ]}"#;
+
+    #[test]
+    fn violations_name_the_feature_and_the_place() {
+        let collection: FeatureCollection = COLLECTION.parse().unwrap();
+        let violations = validate(&collection);
+        let found: Vec<(&str, Rule, &str)> = violations
+            .iter()
+            .map(|v| (v.feature.as_str(), v.rule, v.detail.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "",
+                    Rule::Crs,
+                    r#"{"properties":{"name":"EPSG:6668"},"type":"name"}"#
+                ),
+                // 範囲外の座標はジオメトリごとに最初の 1 つだけ
+                ("far", Rule::Range, "(200, 10)"),
+                ("swapped", Rule::SwappedAxes, "(35.9, 139.5)"),
+                ("#3", Rule::UnclosedRing, "リング 1"),
+                ("5", Rule::ExteriorWinding, "リング 1"),
+                ("5", Rule::HoleWinding, "リング 2"),
+                ("multi", Rule::ShortRing, "ポリゴン 2 のリング 1 (3 点)"),
+            ]
+        );
+
+        let report = Report { violations }.to_string();
+        assert!(
+            report.starts_with(
+                "入力が RFC 7946 に従っていません (違反 7 件)\n  crs メンバーがある: 1 件\n"
+            ),
+            "{}",
+            report
+        );
+        assert!(report.contains("\n  FeatureCollection: crs メンバーがある ("));
+        assert!(report.contains("\n  #3: リングが閉じていない (リング 1)"));
+        assert!(report.ends_with("\n  multi: リングの点が 4 点未満 (ポリゴン 2 のリング 1 (3 点))"));
+    }
+
+    #[test]
+    fn only_the_first_violations_are_listed() {
+        let features: Vec<String> = (0..SHOWN + 5)
+            .map(|i| {
+                format!(
+                    r#"{{"type":"Feature","id":"p{}","properties":{{}},"geometry":{{"type":"Point","coordinates":[500,500]}}}}"#,
+                    i
+                )
+            })
+            .collect();
+        let collection: FeatureCollection = format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        )
+        .parse()
+        .unwrap();
+        let violations = validate(&collection);
+        assert_eq!(violations.len(), SHOWN + 5);
+        assert!(violations.iter().all(|v| v.rule == Rule::Range));
+        let report = Report { violations }.to_string();
+        assert!(report.contains("座標が経度・緯度の範囲外: 25 件"));
+        assert!(report.contains("\n  p19: "));
+        assert!(!report.contains("\n  p20: "));
+        assert!(report.ends_with("\n  … ほか 5 件"));
+    }
+}
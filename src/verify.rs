@@ -0,0 +1,391 @@
+//! 計算した面積と公式の面積（国土地理院「全国都道府県市区町村別面積調」）の比較。
+//! 面積調の表は利用者が CSV（UTF-8）にして渡す。
+//! 市区町村名だけでは同じ名前の市町村（東京都と広島県の府中市など）を区別できないため、
+//! 都道府県名と市区町村名の組や、全国地方公共団体コードでも突き合わせられる（`Join`）。
+
+use crate::{aggregate::to_multi_polygon, area::geodesic_area, log};
+use geo::Geometry;
+use geojson::{Feature, FeatureCollection};
+use rayon::prelude::*;
+use std::{collections::HashMap, error::Error};
+
+/// 市町村ごとの比較結果（片方にしかない市町村は反対側が None）
+pub struct VerifyRow {
+    pub city: String,
+    /// 楕円体（WGS84）上で計算した面積 (km²)
+    pub computed: Option<f64>,
+    /// 公式の面積 (km²)
+    pub official: Option<f64>,
+}
+
+impl VerifyRow {
+    /// 絶対誤差 (計算値 - 公式値, km²)
+    pub fn error(&self) -> Option<f64> {
+        Some(self.computed? - self.official?)
+    }
+
+    /// 相対誤差（公式値に対する割合）
+    pub fn relative_error(&self) -> Option<f64> {
+        let official = self.official?;
+        (official != 0.0).then_some(self.error()? / official)
+    }
+}
+
+/// 公式の面積の表と入力の Feature を突き合わせる方法
+#[derive(Clone, Debug, PartialEq)]
+pub enum Join {
+    /// 市区町村名（入力は `group_by` のプロパティ）
+    Name,
+    /// 都道府県名と市区町村名の組（入力の都道府県名は `property` のプロパティ）
+    Prefecture { property: String },
+    /// 全国地方公共団体コード（入力は `group_by` のプロパティ）。
+    /// 検査数字の付いた 6 桁のコードは先頭の 5 桁で、先頭の 0 が落ちた 4 桁のコードは 0 を補って突き合わせる
+    Code,
+}
+
+impl Join {
+    /// `name`, `prefecture`, `code` を解析する（`prefecture` は都道府県名のプロパティ）
+    pub fn parse(name: &str, prefecture: &str) -> Result<Join, String> {
+        match name {
+            "name" => Ok(Join::Name),
+            "prefecture" => Ok(Join::Prefecture {
+                property: prefecture.to_string(),
+            }),
+            "code" => Ok(Join::Code),
+            _ => Err(format!(
+                "不明な突き合わせ方です: {} (name, prefecture, code)",
+                name
+            )),
+        }
+    }
+
+    /// Feature の突き合わせる値（プロパティがなければ None）
+    fn feature_key(&self, feature: &Feature, group_by: &str) -> Option<String> {
+        let value = feature.property(group_by)?.as_str()?;
+        match self {
+            Join::Name => Some(value.to_string()),
+            Join::Prefecture { property } => {
+                Some(prefecture_key(feature.property(property)?.as_str()?, value))
+            }
+            Join::Code => code_key(value),
+        }
+    }
+}
+
+/// 都道府県名と市区町村名の組の突き合わせる値（例: "東京都 府中市"）
+fn prefecture_key(prefecture: &str, city: &str) -> String {
+    format!("{} {}", prefecture.trim(), city.trim())
+}
+
+/// 全国地方公共団体コードを 5 桁にそろえる（数字でなければ None）
+fn code_key(code: &str) -> Option<String> {
+    let code = code.trim();
+    if code.is_empty() || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    match code.len() {
+        // 先頭の 0 が落ちたもの（例: 北海道札幌市の 1100）
+        1..=4 => Some(format!("{:0>5}", code)),
+        5 => Some(code.to_string()),
+        // 検査数字付きのもの（例: 112011）
+        6 => Some(code[..5].to_string()),
+        _ => None,
+    }
+}
+
+/// 面積の値の先頭の数（桁区切りのカンマは無視する）。"12.3※1" は 12.3、"-" や "境界未定" は None
+fn leading_number(value: &str) -> Option<f64> {
+    let value = value.trim().replace(',', "");
+    let end = value
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && c == '-')))
+        .map_or(value.len(), |(i, _)| i);
+    value[..end].parse().ok()
+}
+
+/// 公式の面積の表で探す列名の候補（先に見つかったものを使う）
+const KEY_COLUMNS: [&str; 4] = ["市区町村名", "市区町村", "City", "name"];
+const PREFECTURE_COLUMNS: [&str; 4] = ["都道府県名", "都道府県", "Prefecture", "prefecture"];
+const CODE_COLUMNS: [&str; 6] = [
+    "標準地域コード",
+    "団体コード",
+    "市区町村コード",
+    "コード",
+    "Code",
+    "code",
+];
+const AREA_COLUMNS: [&str; 4] = ["面積", "面積(km2)", "Area", "area"];
+
+/// 公式の面積の CSV を読み込む（突き合わせる値 -> km²）。
+/// 列を指定しなければ、見出しから市区町村名（`Join::Code` ならコード）、都道府県名と面積の列を探す。
+/// 面積の値は先頭の数を使い、桁区切りのカンマや後ろの注記（※1 など）は無視する。
+pub fn read_official(
+    path: &str,
+    join: &Join,
+    key_column: Option<&str>,
+    prefecture_column: Option<&str>,
+    area_column: Option<&str>,
+) -> Result<HashMap<String, f64>, Box<dyn Error>> {
+    let mut rdr = csv::ReaderBuilder::new().flexible(true).from_path(path)?;
+    let headers = rdr
+        .headers()
+        .map_err(|err| {
+            format!(
+                "{}: 見出しを読み込めません（UTF-8 に変換してください）: {}",
+                path, err
+            )
+        })?
+        .clone();
+    let find = |name: Option<&str>, candidates: &[&str], label: &str| {
+        let position = match name {
+            Some(name) => headers.iter().position(|h| h.trim() == name),
+            None => candidates
+                .iter()
+                .find_map(|candidate| headers.iter().position(|h| h.trim().starts_with(candidate))),
+        };
+        position.ok_or_else(|| {
+            format!(
+                "{} に{}の列が見つかりません（見出し: {}）",
+                path,
+                label,
+                headers.iter().collect::<Vec<_>>().join(", ")
+            )
+        })
+    };
+    let key = match join {
+        Join::Code => find(key_column, &CODE_COLUMNS, "全国地方公共団体コード")?,
+        _ => find(key_column, &KEY_COLUMNS, "市区町村名")?,
+    };
+    let prefecture = match join {
+        Join::Prefecture { .. } => {
+            Some(find(prefecture_column, &PREFECTURE_COLUMNS, "都道府県名")?)
+        }
+        _ => None,
+    };
+    let area = find(area_column, &AREA_COLUMNS, "面積")?;
+
+    let mut official = HashMap::new();
+    for record in rdr.records() {
+        let record = record.map_err(|err| format!("{}: {}", path, err))?;
+        let (Some(name), Some(value)) = (record.get(key), record.get(area)) else {
+            continue;
+        };
+        let name = name.trim();
+        // 合計の行や値のない行（境界未定の市町村など）は飛ばす
+        let Some(value) = leading_number(value) else {
+            continue;
+        };
+        // 都道府県の行（市区町村名が空）や合計・小計の行も飛ばす
+        if name.is_empty() || name.ends_with('計') {
+            continue;
+        }
+        let name = match (join, prefecture) {
+            // 都道府県のコード（例: 11000）の行も飛ばす
+            (Join::Code, _) => match code_key(name) {
+                Some(code) if !code.ends_with("000") => code,
+                _ => continue,
+            },
+            (_, Some(prefecture)) => prefecture_key(record.get(prefecture).unwrap_or(""), name),
+            _ => name.to_string(),
+        };
+        if official.insert(name.clone(), value).is_some() {
+            let hint = match join {
+                Join::Name => {
+                    "。同じ名前の市町村なら --join prefecture か --join code を使ってください"
+                }
+                _ => "",
+            };
+            log::warning!(
+                "警告: {} に {} が複数あります（後の行の値を使います{}）",
+                path,
+                name,
+                hint
+            );
+        }
+    }
+    Ok(official)
+}
+
+/// `join` で突き合わせる値ごとに面積を計算し、公式の面積と突き合わせる（突き合わせる値の順）
+pub fn verify(
+    collection: &FeatureCollection,
+    group_by: &str,
+    join: &Join,
+    official: &HashMap<String, f64>,
+) -> Vec<VerifyRow> {
+    let computed = collection
+        .features
+        .par_iter()
+        .filter_map(|feature| {
+            let name = join.feature_key(feature, group_by)?;
+            let geometry: Geometry<f64> =
+                feature.geometry.as_ref()?.value.clone().try_into().ok()?;
+            let area: f64 = to_multi_polygon(geometry)?.iter().map(geodesic_area).sum();
+            Some((name, area / 1e6))
+        })
+        .fold(HashMap::<String, f64>::new, |mut map, (name, area)| {
+            *map.entry(name).or_insert(0.0) += area;
+            map
+        })
+        .reduce(HashMap::new, |mut a, b| {
+            for (name, area) in b {
+                *a.entry(name).or_insert(0.0) += area;
+            }
+            a
+        });
+
+    let mut rows: Vec<VerifyRow> = computed
+        .iter()
+        .map(|(city, &area)| VerifyRow {
+            city: city.clone(),
+            computed: Some(area),
+            official: official.get(city).copied(),
+        })
+        .chain(
+            official
+                .iter()
+                .filter(|(city, _)| !computed.contains_key(*city))
+                .map(|(city, &area)| VerifyRow {
+                    city: city.clone(),
+                    computed: None,
+                    official: Some(area),
+                }),
+        )
+        .collect();
+    rows.sort_by(|a, b| a.city.cmp(&b.city));
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 公式の面積の表を一時ファイルに書く
+    fn official(name: &str, contents: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("layon-verify-{}-{}.csv", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    /// 一辺が `size` 度の正方形の Feature を並べる
+    fn collection(features: &[(&str, &str, &str, f64)]) -> FeatureCollection {
+        let features: Vec<String> = features
+            .iter()
+            .enumerate()
+            .map(|(i, (prefecture, city, code, size))| {
+                let x = i as f64;
+                format!(
+                    r#"{{"type":"Feature","properties":{{"N03_001":"{prefecture}","N03_004":"{city}","N03_007":"{code}"}},"geometry":{{"type":"Polygon","coordinates":[[[{x},0],[{x1},0],[{x1},{size}],[{x},{size}],[{x},0]]]}}}}"#,
+                    x1 = x + size
+                )
+            })
+            .collect();
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        )
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn leading_numbers_ignore_notes_and_separators() {
+        assert_eq!(leading_number("12.3※1"), Some(12.3));
+        assert_eq!(leading_number(" 1,234.56 "), Some(1234.56));
+        assert_eq!(leading_number("109.13(注)"), Some(109.13));
+        assert_eq!(leading_number("-0.5"), Some(-0.5));
+        assert_eq!(leading_number("-"), None);
+        assert_eq!(leading_number("境界未定"), None);
+        assert_eq!(leading_number(""), None);
+    }
+
+    #[test]
+    fn official_areas_are_read_by_name() {
+        let path = official(
+            "name",
+            "都道府県名,市区町村名,面積(km2)\n埼玉県,,\"3,797.75\"\n埼玉県,川越市,109.13※1\n埼玉県,熊谷市,159.82\n埼玉県,境界未定の町,-\n,合計,\"3,797.75\"\n",
+        );
+        let areas = read_official(&path, &Join::Name, None, None, None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut areas: Vec<_> = areas.into_iter().collect();
+        areas.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            areas,
+            [
+                ("川越市".to_string(), 109.13),
+                ("熊谷市".to_string(), 159.82)
+            ]
+        );
+    }
+
+    #[test]
+    fn cities_with_the_same_name_are_joined_with_their_prefecture() {
+        let path = official(
+            "prefecture",
+            "都道府県,市区町村,面積\n東京都,府中市,29.43\n広島県,府中市,195.75\n",
+        );
+        let join = Join::parse("prefecture", "N03_001").unwrap();
+        let areas = read_official(&path, &join, None, None, None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(areas.get("東京都 府中市"), Some(&29.43));
+        assert_eq!(areas.get("広島県 府中市"), Some(&195.75));
+
+        let collection = collection(&[
+            ("東京都", "府中市", "13206", 0.1),
+            ("広島県", "府中市", "34208", 0.2),
+            ("広島県", "府中市", "34208", 0.2),
+        ]);
+        let rows = verify(&collection, "N03_004", &join, &areas);
+        let cities: Vec<_> = rows.iter().map(|row| row.city.as_str()).collect();
+        assert_eq!(cities, ["広島県 府中市", "東京都 府中市"]);
+        // 広島県の府中市は 2 つのポリゴンの合計
+        assert!((rows[0].computed.unwrap() / rows[1].computed.unwrap() - 8.0).abs() < 0.01);
+        assert_eq!(rows[0].official, Some(195.75));
+        assert_eq!(rows[1].official, Some(29.43));
+
+        // 市区町村名だけでは区別できない
+        let rows = verify(&collection, "N03_004", &Join::Name, &areas);
+        assert_eq!(rows.iter().filter(|row| row.official.is_none()).count(), 1);
+    }
+
+    #[test]
+    fn cities_are_joined_with_their_codes() {
+        let path = official(
+            "code",
+            "団体コード,都道府県名,市区町村名,面積\n110001,埼玉県,,\"3,797.75\"\n112011,埼玉県,川越市,109.13\n1100,北海道,札幌市,1121.26\n",
+        );
+        let areas = read_official(&path, &Join::Code, None, None, None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut codes: Vec<_> = areas.keys().map(String::as_str).collect();
+        codes.sort();
+        assert_eq!(codes, ["01100", "11201"]);
+
+        let collection = collection(&[
+            ("埼玉県", "川越市", "11201", 0.1),
+            ("北海道", "札幌市", "01100", 0.1),
+            ("埼玉県", "所属未定地", "", 0.1),
+        ]);
+        let rows = verify(&collection, "N03_007", &Join::Code, &areas);
+        let matched: Vec<_> = rows
+            .iter()
+            .map(|row| (row.city.as_str(), row.official))
+            .collect();
+        // コードのない Feature は突き合わせない
+        assert_eq!(matched, [("01100", Some(1121.26)), ("11201", Some(109.13))]);
+        assert!(rows.iter().all(|row| row.computed.is_some()));
+    }
+
+    #[test]
+    fn unknown_joins_and_missing_columns_are_errors() {
+        assert!(Join::parse("kana", "N03_001").is_err());
+        let path = official("columns", "市区町村名,面積\n川越市,109.13\n");
+        let err = read_official(&path, &Join::Code, None, None, None).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            err.to_string().contains("全国地方公共団体コード"),
+            "{}",
+            err
+        );
+    }
+}
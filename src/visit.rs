@@ -0,0 +1,105 @@
+//! Feature ごとに利用者の関数を並列に呼び出す API。
+//! 集計の処理を使わずに、読み込みと並列処理の仕組みだけを独自の分析に使う場合に使う。
+//!
+//! ```no_run
+//! use layon::pipeline::Pipeline;
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//!
+//! // 郡に属する町村の Feature を数える
+//! let count = AtomicUsize::new(0);
+//! Pipeline::read("src/N03-20240101_11.geojson")
+//!     .filter("N03_003")
+//!     .for_each(|_feature| {
+//!         count.fetch_add(1, Ordering::Relaxed);
+//!     })?;
+//! println!("{}", count.into_inner());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::{
+    cancel::{CancellationToken, Cancelled},
+    schedule,
+};
+use geo::Geometry;
+use geojson::{Feature, FeatureCollection, JsonObject, JsonValue};
+use rayon::prelude::*;
+
+/// 関数に渡す Feature。ジオメトリは必要になったときに geo の型に変換する
+pub struct FeatureView<'a> {
+    /// 入力の中での位置（0 始まり。絞り込みや重複の除去の後の位置）
+    pub index: usize,
+    pub feature: &'a Feature,
+}
+
+impl FeatureView<'_> {
+    /// プロパティの値
+    pub fn property(&self, name: &str) -> Option<&JsonValue> {
+        self.feature.property(name)
+    }
+
+    /// 文字列のプロパティの値
+    pub fn text(&self, name: &str) -> Option<&str> {
+        self.property(name)?.as_str()
+    }
+
+    pub fn properties(&self) -> Option<&JsonObject> {
+        self.feature.properties.as_ref()
+    }
+
+    /// geo のジオメトリ（ジオメトリがないか変換できなければ None）
+    pub fn geometry(&self) -> Option<Geometry<f64>> {
+        self.feature
+            .geometry
+            .as_ref()
+            .and_then(|geometry| geometry.value.clone().try_into().ok())
+    }
+}
+
+/// Feature ごとに `map` を並列に呼び、結果を `reduce` でまとめる（呼ばれる順序は決まっていない）。
+/// `identity` は Feature がない場合の値と、まとめるときの初期値になる。
+/// `cancel` が中断されると残りのチャンクを処理せずに `Cancelled` を返す
+pub fn map_reduce_with<T, M, I, R>(
+    collection: &FeatureCollection,
+    cancel: &CancellationToken,
+    map: M,
+    identity: I,
+    reduce: R,
+) -> Result<T, Cancelled>
+where
+    T: Send,
+    M: Fn(FeatureView) -> T + Sync,
+    I: Fn() -> T + Sync + Send,
+    R: Fn(T, T) -> T + Sync + Send,
+{
+    // 集計と同じく、頂点数の合計が揃うように分けたチャンクを 1 つずつ別のタスクにする
+    let chunks = schedule::chunks_by_cost(&collection.features);
+    let mut offsets = Vec::with_capacity(chunks.len());
+    let mut offset = 0;
+    for chunk in &chunks {
+        offsets.push(offset);
+        offset += chunk.len();
+    }
+
+    let value = chunks
+        .into_par_iter()
+        .zip(offsets)
+        .with_max_len(1)
+        .map(|(chunk, offset)| {
+            if cancel.is_cancelled() {
+                return identity();
+            }
+            chunk
+                .iter()
+                .enumerate()
+                .map(|(i, feature)| {
+                    map(FeatureView {
+                        index: offset + i,
+                        feature,
+                    })
+                })
+                .fold(identity(), &reduce)
+        })
+        .reduce(&identity, &reduce);
+    cancel.check()?;
+    Ok(value)
+}
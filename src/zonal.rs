@@ -0,0 +1,478 @@
+//! ラスターの画素値のポリゴンごとの集計（`layon zonal`）。
+//! 画素値の統計とヒストグラムのほか、標高などの値の帯（`--bands 10,100`）ごとの面積も求める。
+
+use crate::aggregate::{to_multi_polygon, Metric};
+use geo::{BoundingRect, Geometry, Polygon};
+use geojson::FeatureCollection;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// 集計に使うラスター
+pub use crate::raster::Raster;
+
+/// 集計キーごとの画素値の統計
+pub struct ZonalRow {
+    pub city: String,
+    /// ポリゴン内に中心がある画素（欠損値を除く）の数
+    pub count: usize,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+    /// 画素値（`bin_width` を指定した場合は階級の下限）ごとの画素数
+    pub histogram: Vec<(f64, usize)>,
+}
+
+impl ZonalRow {
+    /// 平均値（画素がなければ None）
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+}
+
+/// 途中の集計値
+#[derive(Default)]
+struct Stats {
+    count: usize,
+    sum: f64,
+    min: f64,
+    max: f64,
+    /// 値のビット表現 -> 画素数
+    histogram: HashMap<u64, usize>,
+}
+
+impl Stats {
+    fn add(&mut self, value: f64, bin: Option<f64>) {
+        if self.count == 0 {
+            (self.min, self.max) = (value, value);
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        self.sum += value;
+        if let Some(width) = bin {
+            let key = if width > 0.0 {
+                (value / width).floor() * width
+            } else {
+                value
+            };
+            *self.histogram.entry(key.to_bits()).or_insert(0) += 1;
+        }
+    }
+
+    fn merge(&mut self, other: Stats) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            (self.min, self.max) = (other.min, other.max);
+        } else {
+            self.min = self.min.min(other.min);
+            self.max = self.max.max(other.max);
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        for (key, count) in other.histogram {
+            *self.histogram.entry(key).or_insert(0) += count;
+        }
+    }
+}
+
+/// 値の帯（`--bands 10,100` なら 10 未満、10 以上 100 未満、100 以上の 3 つ）
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bands {
+    /// 帯の境界（昇順）
+    bounds: Vec<f64>,
+}
+
+impl Bands {
+    /// 境界をカンマで区切って並べたものを解析する
+    pub fn parse(text: &str) -> Result<Bands, String> {
+        let bounds = text
+            .split(',')
+            .map(|bound| {
+                bound
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|bound| bound.is_finite())
+                    .ok_or_else(|| format!("--bands の境界が不正です: {}", bound))
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+        if bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(format!("--bands の境界は昇順に並べてください: {}", text));
+        }
+        Ok(Bands { bounds })
+    }
+
+    /// 帯の数
+    pub fn count(&self) -> usize {
+        self.bounds.len() + 1
+    }
+
+    /// 値の帯の番号（0 始まり。境界の値は上の帯に入れる）
+    pub fn index(&self, value: f64) -> usize {
+        self.bounds.partition_point(|&bound| bound <= value)
+    }
+
+    /// 帯の名前（`<10`, `10-100`, `>=100`）
+    pub fn label(&self, index: usize) -> String {
+        match (
+            index.checked_sub(1).map(|i| self.bounds[i]),
+            self.bounds.get(index),
+        ) {
+            (None, Some(upper)) => format!("<{}", upper),
+            (Some(lower), Some(upper)) => format!("{}-{}", lower, upper),
+            (Some(lower), None) => format!(">={}", lower),
+            (None, None) => "all".to_string(),
+        }
+    }
+}
+
+/// 集計キーと帯ごとの面積
+pub struct BandRow {
+    pub city: String,
+    /// 帯の名前
+    pub band: String,
+    /// 帯に入る画素（ポリゴン内に中心があり、欠損値でないもの）の数
+    pub pixels: usize,
+    /// 画素の面積の合計（単位は `Metric` による）
+    pub area: f64,
+}
+
+/// ポリゴンごとに中心が内側にある画素を集め、`group_by` のプロパティと値の帯ごとに面積を合計する。
+/// どの集計キーにもすべての帯の行を出力する（画素がなければ 0）
+pub fn bands(
+    collection: &FeatureCollection,
+    group_by: &str,
+    raster: &Raster,
+    bands: &Bands,
+    metric: Metric,
+) -> Vec<BandRow> {
+    let pixel_areas = raster.pixel_areas(metric);
+    let totals = collection
+        .features
+        .par_iter()
+        .fold(
+            HashMap::<String, Vec<(usize, f64)>>::new,
+            |mut map, feature| {
+                let Some(name) = feature
+                    .properties
+                    .as_ref()
+                    .and_then(|properties| properties.get(group_by))
+                    .and_then(|name| name.as_str())
+                else {
+                    return map;
+                };
+                let geometry: Option<Geometry<f64>> = feature
+                    .geometry
+                    .as_ref()
+                    .and_then(|geometry| geometry.value.clone().try_into().ok());
+                let Some(polygons) = geometry.and_then(to_multi_polygon) else {
+                    return map;
+                };
+
+                let totals = map
+                    .entry(name.to_string())
+                    .or_insert_with(|| vec![(0, 0.0); bands.count()]);
+                for polygon in &polygons {
+                    for_each_pixel(raster, polygon, |col, row| {
+                        if let Some(value) = raster.value(col, row) {
+                            let total = &mut totals[bands.index(value)];
+                            total.0 += 1;
+                            total.1 += pixel_areas[row];
+                        }
+                    });
+                }
+                map
+            },
+        )
+        .reduce(HashMap::new, |mut a, b| {
+            for (key, totals) in b {
+                let entry = a
+                    .entry(key)
+                    .or_insert_with(|| vec![(0, 0.0); bands.count()]);
+                for (sum, (pixels, area)) in entry.iter_mut().zip(totals) {
+                    sum.0 += pixels;
+                    sum.1 += area;
+                }
+            }
+            a
+        });
+
+    let mut cities: Vec<(String, Vec<(usize, f64)>)> = totals.into_iter().collect();
+    cities.sort_by(|a, b| a.0.cmp(&b.0));
+    cities
+        .into_iter()
+        .flat_map(|(city, totals)| {
+            totals
+                .into_iter()
+                .enumerate()
+                .map(move |(i, (pixels, area))| BandRow {
+                    city: city.clone(),
+                    band: bands.label(i),
+                    pixels,
+                    area,
+                })
+        })
+        .collect()
+}
+
+/// ポリゴンごとに中心が内側にある画素を集め、`group_by` のプロパティごとに統計をとる。
+/// `bin` を指定するとヒストグラムも作る（0 なら値そのもの、正の値ならその幅の階級ごと）。
+pub fn zonal(
+    collection: &FeatureCollection,
+    group_by: &str,
+    raster: &Raster,
+    bin: Option<f64>,
+) -> Vec<ZonalRow> {
+    let stats = collection
+        .features
+        .par_iter()
+        .fold(HashMap::<String, Stats>::new, |mut map, feature| {
+            let Some(name) = feature
+                .properties
+                .as_ref()
+                .and_then(|properties| properties.get(group_by))
+                .and_then(|name| name.as_str())
+            else {
+                return map;
+            };
+            let geometry: Option<Geometry<f64>> = feature
+                .geometry
+                .as_ref()
+                .and_then(|geometry| geometry.value.clone().try_into().ok());
+            let Some(polygons) = geometry.and_then(to_multi_polygon) else {
+                return map;
+            };
+
+            let stats = map.entry(name.to_string()).or_default();
+            for polygon in &polygons {
+                for_each_pixel(raster, polygon, |col, row| {
+                    if let Some(value) = raster.value(col, row) {
+                        stats.add(value, bin);
+                    }
+                });
+            }
+            map
+        })
+        .reduce(HashMap::new, |mut a, b| {
+            for (key, stats) in b {
+                a.entry(key).or_default().merge(stats);
+            }
+            a
+        });
+
+    let mut rows: Vec<ZonalRow> = stats
+        .into_iter()
+        .map(|(city, stats)| {
+            let mut histogram: Vec<(f64, usize)> = stats
+                .histogram
+                .into_iter()
+                .map(|(bits, count)| (f64::from_bits(bits), count))
+                .collect();
+            histogram.sort_by(|a, b| a.0.total_cmp(&b.0));
+            ZonalRow {
+                city,
+                count: stats.count,
+                sum: stats.sum,
+                min: stats.min,
+                max: stats.max,
+                histogram,
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| a.city.cmp(&b.city));
+    rows
+}
+
+/// ポリゴンの内側に中心がある画素を走査線で塗りつぶすように列挙する（穴は偶奇規則で除く）
+fn for_each_pixel(raster: &Raster, polygon: &Polygon<f64>, mut visit: impl FnMut(usize, usize)) {
+    let Some(rect) = polygon.bounding_rect() else {
+        return;
+    };
+    let (cols, rows) = raster.window(rect.min().x_y(), rect.max().x_y());
+    if cols.is_empty() {
+        return;
+    }
+
+    let edges: Vec<_> = std::iter::once(polygon.exterior())
+        .chain(polygon.interiors())
+        .flat_map(|ring| ring.lines())
+        .collect();
+    let mut crossings = Vec::new();
+    for row in rows {
+        let (_, y) = raster.center(0, row);
+        crossings.clear();
+        crossings.extend(edges.iter().filter_map(|line| {
+            let (a, b) = (line.start, line.end);
+            ((a.y > y) != (b.y > y)).then(|| a.x + (y - a.y) * (b.x - a.x) / (b.y - a.y))
+        }));
+        crossings.sort_by(f64::total_cmp);
+
+        for pair in crossings.chunks_exact(2) {
+            let (span, _) = raster.window((pair[0], y), (pair[1], y));
+            // 右端の交点ちょうどにある画素は含めない
+            for col in span.start.max(cols.start)..span.end.min(cols.end) {
+                if raster.center(col, row).0 < pair[1] {
+                    visit(col, row);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 原点 (0, 4)、画素が 1 × 1 の 4 × 4 のラスター（値は左上から 1..=16、16 は欠損値）
+    fn raster() -> Raster {
+        Raster {
+            width: 4,
+            height: 4,
+            origin: (0.0, 4.0),
+            pixel_size: (1.0, 1.0),
+            nodata: Some(16.0),
+            values: (1..=16).map(f64::from).collect(),
+        }
+    }
+
+    fn polygons(features: &[(&str, &str)]) -> FeatureCollection {
+        let features: Vec<String> = features
+            .iter()
+            .map(|(city, rings)| {
+                format!(
+                    r#"{{"type":"Feature","properties":{{"N03_004":"{}"}},"geometry":{{"type":"Polygon","coordinates":{}}}}}"#,
+                    city, rings
+                )
+            })
+            .collect();
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        )
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn statistics_and_histograms_of_the_pixels_inside() {
+        let collection = polygons(&[
+            // 左下の 2 × 2 画素 (9, 10, 13, 14)
+            ("a", "[[[0,0],[2,0],[2,2],[0,2],[0,0]]]"),
+            // 右上の 1 画素 (4)。同じ集計キーの Feature はまとめる
+            ("a", "[[[3,3],[4,3],[4,4],[3,4],[3,3]]]"),
+            // 全体から中央の 2 × 2 画素 (6, 7, 10, 11) の穴を除く。16 は欠損値
+            (
+                "b",
+                "[[[0,0],[4,0],[4,4],[0,4],[0,0]],[[1,1],[1,3],[3,3],[3,1],[1,1]]]",
+            ),
+            // 右端がちょうど画素の中心を通る場合、その画素は含めない (13 だけ)
+            ("c", "[[[0,0],[1.5,0],[1.5,1],[0,1],[0,0]]]"),
+            // 画素の中心を含まない
+            ("d", "[[[0.1,0.1],[0.4,0.1],[0.4,0.4],[0.1,0.1]]]"),
+        ]);
+        let rows = zonal(&collection, "N03_004", &raster(), Some(4.0));
+        let summary: Vec<_> = rows
+            .iter()
+            .map(|row| (row.city.as_str(), row.count, row.sum, row.mean()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("a", 5, 50.0, Some(10.0)),
+                ("b", 11, 86.0, Some(86.0 / 11.0)),
+                ("c", 1, 13.0, Some(13.0)),
+                ("d", 0, 0.0, None),
+            ]
+        );
+        assert_eq!((rows[0].min, rows[0].max), (4.0, 14.0));
+        assert_eq!((rows[1].min, rows[1].max), (1.0, 15.0));
+        // 1, 2, 3 | 4, 5 | 8, 9 | 12, 13, 14, 15
+        assert_eq!(rows[1].histogram, [(0.0, 3), (4.0, 2), (8.0, 2), (12.0, 4)]);
+
+        // 幅 0 なら値そのもののヒストグラム、指定しなければ作らない
+        let rows = zonal(&collection, "N03_004", &raster(), Some(0.0));
+        assert_eq!(
+            rows[0].histogram,
+            [(4.0, 1), (9.0, 1), (10.0, 1), (13.0, 1), (14.0, 1)]
+        );
+        let rows = zonal(&collection, "N03_004", &raster(), None);
+        assert!(rows.iter().all(|row| row.histogram.is_empty()));
+    }
+
+    #[test]
+    fn pixels_are_visited_once_inside_the_window() {
+        let raster = raster();
+        let polygon = Polygon::new(
+            vec![(-5.0, -5.0), (9.0, -5.0), (9.0, 9.0), (-5.0, 9.0)].into(),
+            vec![],
+        );
+        let mut visited = Vec::new();
+        for_each_pixel(&raster, &polygon, |col, row| visited.push((col, row)));
+        visited.sort();
+        let all: Vec<_> = (0..4)
+            .flat_map(|col| (0..4).map(move |row| (col, row)))
+            .collect();
+        assert_eq!(visited, all);
+
+        // 外側のポリゴンは画素を訪れない
+        let outside = Polygon::new(
+            vec![(10.0, 10.0), (11.0, 10.0), (11.0, 11.0)].into(),
+            vec![],
+        );
+        for_each_pixel(&raster, &outside, |_, _| panic!("範囲外の画素"));
+    }
+
+    #[test]
+    fn boundary_values_go_to_the_upper_band() {
+        let bands = Bands::parse("10, 100").unwrap();
+        assert_eq!(bands.count(), 3);
+        let indices: Vec<usize> = [-1.0, 9.99, 10.0, 99.0, 100.0, 1e9]
+            .iter()
+            .map(|&value| bands.index(value))
+            .collect();
+        assert_eq!(indices, [0, 0, 1, 1, 2, 2]);
+        let labels: Vec<String> = (0..bands.count()).map(|i| bands.label(i)).collect();
+        assert_eq!(labels, ["<10", "10-100", ">=100"]);
+
+        assert!(Bands::parse("100,10").is_err());
+        assert!(Bands::parse("10,10").is_err());
+        assert!(Bands::parse("10,inf").is_err());
+        assert!(Bands::parse("10,").is_err());
+    }
+
+    #[test]
+    fn every_city_has_a_row_for_each_band() {
+        let collection = polygons(&[
+            // 左の列 (1, 5, 9, 13)
+            ("b", "[[[0,0],[1,0],[1,4],[0,4],[0,0]]]"),
+            // 下の行の右の 3 画素 (14, 15, 16)。16 は欠損値
+            ("a", "[[[1,0],[4,0],[4,1],[1,1],[1,0]]]"),
+            // 画素の中心を含まない
+            ("c", "[[[0.1,0.1],[0.4,0.1],[0.4,0.4],[0.1,0.1]]]"),
+        ]);
+        // 境界の 5 と 13 は上の帯に入る
+        let bands = Bands::parse("5,13").unwrap();
+        let rows = super::bands(&collection, "N03_004", &raster(), &bands, Metric::Area);
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|row| (row.city.as_str(), row.band.as_str(), row.pixels, row.area))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("a", "<5", 0, 0.0),
+                ("a", "5-13", 0, 0.0),
+                ("a", ">=13", 2, 2.0),
+                ("b", "<5", 1, 1.0),
+                ("b", "5-13", 2, 2.0),
+                ("b", ">=13", 1, 1.0),
+                ("c", "<5", 0, 0.0),
+                ("c", "5-13", 0, 0.0),
+                ("c", ">=13", 0, 0.0),
+            ]
+        );
+    }
+}
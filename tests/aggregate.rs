@@ -0,0 +1,436 @@
+//! 集計（面積の golden、集計キーの扱い、分割や書き出しを伴う集計、監査や平滑化など）を、読み込みから書き出しまで通して確かめる。
+
+mod common;
+
+use common::*;
+use geo::HaversineDistance;
+use layon::{
+    aggregate::Metric,
+    pipeline::{
+        Action, Adjacency, Event, Keep, KeyFilter, KeyNormalization, KeyTemplate, Pipeline, Policy,
+        Smoothing,
+    },
+    sink,
+    source::{Input, InputOptions},
+};
+use std::{fs, sync::Mutex};
+
+#[test]
+fn planar_area_matches_golden() {
+    let output = scratch("planar").join("area.csv");
+    aggregate_csv(&fixture(), GROUP_BY, Metric::Area, &output);
+    assert_golden("n03_11_sample_area.csv", &output);
+}
+
+#[test]
+fn geodesic_area_matches_golden() {
+    let output = scratch("geodesic").join("area.csv");
+    aggregate_csv(&fixture(), GROUP_BY, Metric::GeodesicArea, &output);
+    assert_golden("n03_11_sample_geodesic.csv", &output);
+}
+
+#[test]
+fn normalized_keys_are_aggregated_together() {
+    let all = KeyNormalization::parse("all").unwrap();
+    assert_eq!(all.apply("ｻｲﾀﾏ ｼ "), "サイタマ シ");
+    assert_eq!(all.apply("ｶﾞｯｺｳ ﾊﾟﾝ"), "ガッコウ パン");
+    assert_eq!(all.apply("ＡＢＣ　"), "abc");
+    assert_eq!(all.apply("田中 様"), "田中");
+    assert!(KeyNormalization::parse("trim,unknown").is_err());
+
+    let square = |x: f64| {
+        format!(
+            "[[[{x},0],[{},0],[{},1],[{x},1],[{x},0]]]",
+            x + 1.0,
+            x + 1.0
+        )
+    };
+    let feature = |name: &str, x: f64| {
+        format!(
+            r#"{{"type":"Feature","properties":{{"name":"{}"}},"geometry":{{"type":"Polygon","coordinates":{}}}}}"#,
+            name,
+            square(x)
+        )
+    };
+    let input = scratch("normalize").join("input.geojson");
+    fs::write(
+        &input,
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{},{},{}]}}"#,
+            feature("さいたま市", 0.0),
+            feature("さいたま市　", 2.0),
+            feature("川越市", 4.0)
+        ),
+    )
+    .unwrap();
+    let result = Pipeline::read(input.to_str().unwrap())
+        .group_by("name")
+        .normalize_keys(KeyNormalization::parse("trim").unwrap())
+        .run()
+        .unwrap();
+    assert_eq!(result.normalized, 1);
+    let rows: Vec<(&str, f64)> = result
+        .rows
+        .iter()
+        .map(|row| (row.key.as_str(), row.area))
+        .collect();
+    assert_eq!(rows, [("さいたま市", 2.0), ("川越市", 1.0)]);
+}
+
+#[test]
+fn group_by_template_builds_composite_keys() {
+    let output = scratch("template").join("template.csv");
+    let expected = aggregate_csv(&fixture(), GROUP_BY, Metric::Area, &output);
+    let result = Pipeline::read(fixture())
+        .group_by_template(KeyTemplate::parse("{N03_001}/{N03_004}").unwrap())
+        .run()
+        .unwrap();
+    let rows: Vec<(String, f64)> = result
+        .rows
+        .iter()
+        .map(|row| (row.key.clone(), row.area))
+        .collect();
+    let composite: Vec<(String, f64)> = expected
+        .iter()
+        .map(|(key, area)| (format!("埼玉県/{}", key), *area))
+        .collect();
+    assert_eq!(rows.len(), composite.len());
+    for ((key, area), (expected_key, expected_area)) in rows.iter().zip(&composite) {
+        assert_eq!(key, expected_key);
+        assert!((area - expected_area).abs() < 1e-12);
+    }
+
+    // 正規表現で値の一部を取り出す（括弧があれば 1 つ目の括弧の部分）
+    let template = KeyTemplate::parse(r"{code/^(\d{2})/}-{name/(.+?)(?:市|町)$/}").unwrap();
+    let properties = |code: &str, name: &str| {
+        let mut properties = geojson::JsonObject::new();
+        properties.insert("code".to_string(), code.into());
+        properties.insert("name".to_string(), name.into());
+        properties
+    };
+    assert_eq!(
+        template.render(&properties("11201", "川越市")),
+        Some("11-川越".to_string())
+    );
+    assert_eq!(
+        template.render(&properties("11347", "吉見町")),
+        Some("11-吉見".to_string())
+    );
+    assert_eq!(template.render(&properties("11", "東秩父村")), None);
+    assert!(KeyTemplate::parse("{name/[/}").is_err());
+    assert!(KeyTemplate::parse("no placeholder").is_err());
+}
+
+#[test]
+fn only_and_exclude_restrict_group_keys() {
+    let list = scratch("keys").join("exclude.txt");
+    fs::write(&list, "# 除く市町村\n\u{feff}本庄市\n\n  鴻巣市  \n").unwrap();
+    let keys = KeyFilter {
+        only: Some(KeyFilter::split("本庄市, 川島町,上尾市,存在しない市")),
+        exclude: KeyFilter::read(list.to_str().unwrap()).unwrap(),
+    };
+    assert_eq!(keys.exclude, ["本庄市", "鴻巣市"]);
+    let result = Pipeline::read(fixture()).keys(keys).run().unwrap();
+    let cities: Vec<&str> = result.rows.iter().map(|row| row.key.as_str()).collect();
+    assert_eq!(cities, ["上尾市", "川島町"]);
+    assert_eq!(result.missing_keys, ["存在しない市"]);
+}
+
+#[test]
+fn progress_reports_stages_and_processed_features_in_order() {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let sender = Mutex::new(sender);
+    let result = Pipeline::read(fixture())
+        .on_progress(move |event| sender.lock().unwrap().send(event.clone()).unwrap())
+        .run()
+        .unwrap();
+    let events: Vec<Event> = receiver.try_iter().collect();
+    let stages: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Stage(stage) => Some(stage.label()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(stages, ["読み込み", "前処理", "計算 (全スレッドの合計)"]);
+    let snapshots: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Processed(snapshot) => Some(*snapshot),
+            _ => None,
+        })
+        .collect();
+    // 並列に通知するので前後することはあるが、すべて集計し終えたことは必ず通知する
+    let done = snapshots
+        .iter()
+        .max_by_key(|snapshot| snapshot.processed)
+        .unwrap();
+    assert_eq!((done.processed, done.total), (result.processed, 8));
+    assert_eq!(done.fraction(), 1.0);
+    assert_eq!(done.eta(), Some(std::time::Duration::ZERO));
+    assert!(matches!(events.last(), Some(Event::Finished { .. })));
+}
+
+#[test]
+fn audit_lists_skipped_deduplicated_and_repaired_features_with_areas() {
+    let dir = scratch("audit");
+    let square = "[[[0,0],[1,0],[1,1],[0,1],[0,0]]]";
+    let bowtie = "[[[0,0],[2,2],[2,0],[0,2],[0,0]]]";
+    let feature = |id: &str, key: Option<&str>, coordinates: &str| {
+        let properties = match key {
+            Some(key) => format!(r#"{{"N03_004":"{}"}}"#, key),
+            None => "{}".to_string(),
+        };
+        format!(
+            r#"{{"type":"Feature","id":"{}","properties":{},"geometry":{{"type":"Polygon","coordinates":{}}}}}"#,
+            id, properties, coordinates
+        )
+    };
+    let features = [
+        feature("a", Some("A"), square),
+        feature("a2", Some("A"), square),
+        feature("b", Some("B"), bowtie),
+        feature("c", Some("C"), square),
+        feature("d", None, square),
+    ];
+    let path = dir.join("audit.geojson");
+    fs::write(
+        &path,
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        ),
+    )
+    .unwrap();
+
+    let result = Pipeline::read(path.to_str().unwrap())
+        .filter("N03_004 != 'C'")
+        .dedup(None)
+        .make_valid(true)
+        .audit(true)
+        .run()
+        .unwrap();
+    let entries: Vec<_> = result
+        .audit
+        .iter()
+        .map(|entry| (entry.id.as_str(), entry.action, entry.reason.as_str()))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            ("c", Action::Skipped, "条件式 N03_004 != 'C' を満たさない"),
+            ("a2", Action::Deduplicated, "a と同じ内容"),
+            (
+                "b",
+                Action::Repaired,
+                "自己交差したリングを分けて組み立て直した"
+            ),
+            ("d", Action::Skipped, "集計キーのプロパティ N03_004 がない"),
+        ]
+    );
+    // 8 の字は両側が打ち消し合って 0 になり、修復すると 2 つの三角形の面積になる
+    let repaired = &result.audit[2];
+    assert!(repaired.area_before.abs() < 1e-12);
+    assert!((repaired.area_after.unwrap() - 2.0).abs() < 1e-12);
+    assert_eq!(result.audit[1].area_before, 1.0);
+    assert_eq!(result.audit[1].area_after, None);
+
+    let csv = dir.join("audit.csv");
+    sink::csv::write_audit(csv.to_str().unwrap(), &result.audit).unwrap();
+    let text = fs::read_to_string(&csv).unwrap();
+    let mut lines = text.lines();
+    assert_eq!(
+        lines.next(),
+        Some("Id,City,Action,Reason,AreaBefore,AreaAfter,AreaChange")
+    );
+    assert_eq!(lines.nth(1), Some("a2,A,deduplicated,a と同じ内容,1,,"));
+}
+
+#[test]
+fn smoothing_weights_neighbors_by_shared_border_length() {
+    let dir = scratch("smooth");
+    let square = |key: &str, west: f64, east: f64| {
+        format!(
+            r#"{{"type":"Feature","properties":{{"N03_004":"{}"}},"geometry":{{"type":"Polygon","coordinates":[[[{w},0],[{e},0],[{e},1],[{w},1],[{w},0]]]}}}}"#,
+            key,
+            w = west,
+            e = east
+        )
+    };
+    // A は 2 つの Feature に分かれていて、その間の辺は隣接に数えない。D はどこにも接しない
+    let features = [
+        square("A", 0.0, 0.5),
+        square("A", 0.5, 1.0),
+        square("B", 1.0, 2.0),
+        square("C", 2.0, 3.0),
+        square("D", 5.0, 6.0),
+        square("D", 5.0, 6.0),
+        square("D", 5.0, 6.0),
+    ];
+    let path = dir.join("smooth.geojson");
+    fs::write(
+        &path,
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        ),
+    )
+    .unwrap();
+
+    let collection = Input::parse(path.to_str().unwrap(), InputOptions::default())
+        .unwrap()
+        .read()
+        .unwrap();
+    let adjacency = Adjacency::detect(&collection, "N03_004");
+    let mut borders: Vec<_> = adjacency.borders.keys().cloned().collect();
+    borders.sort();
+    assert_eq!(
+        borders,
+        [
+            ("A".to_string(), "B".to_string()),
+            ("B".to_string(), "C".to_string())
+        ]
+    );
+
+    let rows = Pipeline::read(path.to_str().unwrap())
+        .smooth(Smoothing::new("count"))
+        .run()
+        .unwrap()
+        .rows;
+    let smoothed: Vec<_> = rows
+        .iter()
+        .map(|row| {
+            (
+                row.key.as_str(),
+                row.values[0].0.as_str(),
+                row.values[0].1.unwrap(),
+            )
+        })
+        .collect();
+    // 境界の長さはどれも同じなので、A = (2 + 1) / 2、B = (1 + (2 + 1) / 2) / 2、C = (1 + 1) / 2
+    let expected = [("A", 1.5), ("B", 1.25), ("C", 1.0), ("D", 3.0)];
+    for (key, value) in expected {
+        let &(_, column, actual) = smoothed.iter().find(|(k, _, _)| *k == key).unwrap();
+        assert_eq!(column, "count_smoothed");
+        assert!((actual - value).abs() < 1e-12, "{}: {}", key, actual);
+    }
+}
+
+#[test]
+fn kept_properties_resolve_conflicting_values_by_policy() {
+    let dir = scratch("keep");
+    let feature = |city: &str, prefecture: &str| {
+        format!(
+            r#"{{"type":"Feature","properties":{{"N03_004":"{}","N03_001":"{}"}},"geometry":{{"type":"Polygon","coordinates":[[[0,0],[1,0],[1,1],[0,0]]]}}}}"#,
+            city, prefecture
+        )
+    };
+    // 府中市は東京都に 1 つ、広島県に 2 つ
+    let features = [
+        feature("府中市", "東京都"),
+        feature("府中市", "広島県"),
+        feature("府中市", "広島県"),
+        feature("秩父市", "埼玉県"),
+    ];
+    let path = dir.join("keep.geojson");
+    fs::write(
+        &path,
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        ),
+    )
+    .unwrap();
+    let run = |policy| {
+        Pipeline::read(path.to_str().unwrap())
+            .group_by(GROUP_BY)
+            .keep(Keep {
+                properties: vec!["N03_001".to_string(), "N03_007".to_string()],
+                policy,
+            })
+            .run()
+    };
+    let kept = |policy| {
+        let mut rows: Vec<_> = run(policy)
+            .unwrap()
+            .rows
+            .into_iter()
+            .map(|row| (row.key, row.kept))
+            .collect();
+        rows.sort();
+        rows
+    };
+    let row = |city: &str, prefecture: &str| {
+        (
+            city.to_string(),
+            vec![
+                ("N03_001".to_string(), prefecture.to_string()),
+                ("N03_007".to_string(), String::new()),
+            ],
+        )
+    };
+
+    assert_eq!(
+        kept(Policy::First),
+        [row("府中市", "東京都"), row("秩父市", "埼玉県")]
+    );
+    assert_eq!(
+        kept(Policy::Majority),
+        [row("府中市", "広島県"), row("秩父市", "埼玉県")]
+    );
+    assert_eq!(
+        kept(Policy::ListAll),
+        [row("府中市", "東京都; 広島県"), row("秩父市", "埼玉県")]
+    );
+    let conflicts = run(Policy::First).unwrap().conflicts;
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(
+        conflicts[0].describe(),
+        "府中市 の N03_001: 東京都 (1), 広島県 (2)"
+    );
+    let err = run(Policy::Error).err().unwrap().to_string();
+    assert!(err.contains("府中市 の N03_001"), "{}", err);
+}
+
+#[test]
+fn length_metric_sums_geodesic_line_lengths_per_group() {
+    let dir = scratch("length");
+    let path = dir.join("roads.geojson");
+    fs::write(
+        &path,
+        r#"{"type":"FeatureCollection","features":[
+{"type":"Feature","properties":{"N03_004":"A"},"geometry":{"type":"LineString","coordinates":[[139,35],[139,36]]}},
+{"type":"Feature","properties":{"N03_004":"A"},"geometry":{"type":"MultiLineString","coordinates":[[[139,35],[140,35]],[[140,35],[140,35.5]]]}},
+{"type":"Feature","properties":{"N03_004":"B"},"geometry":{"type":"LineString","coordinates":[[0,0],[0,1]]}},
+{"type":"Feature","properties":{"N03_004":"B"},"geometry":{"type":"Polygon","coordinates":[[[0,0],[1,0],[1,1],[0,0]]]}}
+]}"#,
+    )
+    .unwrap();
+    let result = Pipeline::read(path.to_str().unwrap())
+        .group_by(GROUP_BY)
+        .metric(Metric::GeodesicLength)
+        .run()
+        .unwrap();
+
+    let km = |a: (f64, f64), b: (f64, f64)| {
+        geo::Point::new(a.0, a.1).haversine_distance(&geo::Point::new(b.0, b.1)) / 1e3
+    };
+    let expected = [
+        (
+            "A".to_string(),
+            km((139.0, 35.0), (139.0, 36.0))
+                + km((139.0, 35.0), (140.0, 35.0))
+                + km((140.0, 35.0), (140.0, 35.5)),
+        ),
+        // ポリゴンの周長は数えない
+        ("B".to_string(), km((0.0, 0.0), (0.0, 1.0))),
+    ];
+    let actual: Vec<_> = result
+        .rows
+        .iter()
+        .map(|row| (row.key.clone(), row.area))
+        .collect();
+    assert_rows(&actual, &expected, "length");
+    assert_eq!(result.rows[0].count, 2);
+    assert_eq!(result.types.non_linear(), 1);
+}
@@ -0,0 +1,189 @@
+//! GeometryCollection を持つ Feature の集計を確かめる。
+//! 飛び地や、線（道路の境界など）が混ざった N03 のような境界データを想定し、
+//! 入れ子になったものも含めてメンバーを平らに並べ、親の Feature のグループに数えることを確かめる。
+
+use geo::Area;
+use geojson::{Feature, FeatureCollection, Geometry, JsonObject, JsonValue, Value};
+use layon::{
+    aggregate::{self, Extras, GroupResult, Metric},
+    pipeline::{CancellationToken, Schedule},
+};
+
+/// 左下が (x, y) で幅 `width`、高さ `height` の長方形のポリゴン
+fn rectangle(x: f64, y: f64, width: f64, height: f64) -> Value {
+    Value::Polygon(vec![vec![
+        vec![x, y],
+        vec![x + width, y],
+        vec![x + width, y + height],
+        vec![x, y + height],
+        vec![x, y],
+    ]])
+}
+
+fn collection(members: Vec<Value>) -> Value {
+    Value::GeometryCollection(members.into_iter().map(Geometry::new).collect())
+}
+
+/// N03 と同じプロパティを持つ Feature
+fn n03_feature(city: &str, value: Value) -> Feature {
+    let mut properties = JsonObject::new();
+    properties.insert("N03_001".to_string(), JsonValue::from("埼玉県"));
+    properties.insert("N03_002".to_string(), JsonValue::Null);
+    properties.insert("N03_003".to_string(), JsonValue::Null);
+    properties.insert("N03_004".to_string(), JsonValue::from(city));
+    properties.insert("N03_007".to_string(), JsonValue::from("11201"));
+    Feature {
+        bbox: None,
+        geometry: Some(Geometry::new(value)),
+        id: None,
+        properties: Some(properties),
+        foreign_members: None,
+    }
+}
+
+/// 本体のポリゴンと、飛び地（入れ子の GeometryCollection の中にもある）と線を持つ市
+fn sample() -> FeatureCollection {
+    let enclave = collection(vec![
+        rectangle(139.50, 35.90, 0.02, 0.01),
+        collection(vec![
+            rectangle(139.60, 35.90, 0.01, 0.01),
+            Value::LineString(vec![vec![139.40, 35.90], vec![139.43, 35.94]]),
+        ]),
+        Value::Point(vec![139.45, 35.95]),
+    ]);
+    FeatureCollection {
+        bbox: None,
+        features: vec![
+            n03_feature("川越市", rectangle(139.40, 35.80, 0.05, 0.05)),
+            n03_feature("川越市", enclave),
+            n03_feature("所沢市", rectangle(139.40, 35.70, 0.03, 0.02)),
+        ],
+        foreign_members: None,
+    }
+}
+
+/// 同じポリゴンを MultiPolygon で持つ場合（比べる基準）
+fn flattened() -> FeatureCollection {
+    let multi = |values: Vec<Value>| {
+        Value::MultiPolygon(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Polygon(rings) => rings,
+                    _ => unreachable!(),
+                })
+                .collect(),
+        )
+    };
+    FeatureCollection {
+        bbox: None,
+        features: vec![
+            n03_feature("川越市", rectangle(139.40, 35.80, 0.05, 0.05)),
+            n03_feature(
+                "川越市",
+                multi(vec![
+                    rectangle(139.50, 35.90, 0.02, 0.01),
+                    rectangle(139.60, 35.90, 0.01, 0.01),
+                ]),
+            ),
+            n03_feature("所沢市", rectangle(139.40, 35.70, 0.03, 0.02)),
+        ],
+        foreign_members: None,
+    }
+}
+
+fn aggregate(collection: &FeatureCollection, metric: Metric, extras: &Extras) -> Vec<GroupResult> {
+    let aggregation = aggregate::aggregate_until(
+        collection,
+        "N03_004",
+        extras,
+        metric,
+        Schedule::ByCost,
+        &CancellationToken::new(),
+    );
+    assert!(aggregation.cancelled.is_none());
+    aggregation.rows
+}
+
+fn row<'a>(rows: &'a [GroupResult], key: &str) -> &'a GroupResult {
+    rows.iter().find(|row| row.key == key).unwrap()
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() <= 1e-9 * expected.abs().max(1.0),
+        "{} != {}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn members_count_toward_parent_group() {
+    for metric in [Metric::Area, Metric::GeodesicArea] {
+        let rows = aggregate(&sample(), metric, &Extras::default());
+        let expected = aggregate(&flattened(), metric, &Extras::default());
+        for key in ["川越市", "所沢市"] {
+            assert_close(row(&rows, key).area, row(&expected, key).area);
+            assert_eq!(row(&rows, key).count, row(&expected, key).count);
+        }
+        assert_eq!(row(&rows, "川越市").count, 2);
+    }
+}
+
+#[test]
+fn dissolve_includes_nested_members() {
+    let extras = Extras {
+        geometry: true,
+        ..Extras::default()
+    };
+    let rows = aggregate(&sample(), Metric::Area, &extras);
+    let kawagoe = row(&rows, "川越市");
+    let geometry = kawagoe.geometry.as_ref().unwrap();
+    assert_eq!(geometry.0.len(), 3);
+    assert_close(geometry.unsigned_area(), kawagoe.area);
+}
+
+#[test]
+fn to_multi_polygon_flattens_nested_collections() {
+    let feature = &sample().features[1];
+    let geometry: geo::Geometry<f64> = feature
+        .geometry
+        .as_ref()
+        .unwrap()
+        .value
+        .clone()
+        .try_into()
+        .unwrap();
+    let polygons = aggregate::to_multi_polygon(geometry).unwrap();
+    assert_eq!(polygons.0.len(), 2);
+    assert_close(polygons.unsigned_area(), 0.02 * 0.01 + 0.01 * 0.01);
+
+    let lines = collection(vec![Value::LineString(vec![
+        vec![139.40, 35.90],
+        vec![139.43, 35.94],
+    ])]);
+    let geometry: geo::Geometry<f64> = lines.try_into().unwrap();
+    assert!(aggregate::to_multi_polygon(geometry).is_none());
+}
+
+#[test]
+fn lines_and_points_in_collections_are_measured() {
+    let extras = Extras {
+        geometry_types: true,
+        ..Extras::default()
+    };
+    let rows = aggregate(&sample(), Metric::Area, &extras);
+    let value = |name: &str| {
+        row(&rows, "川越市")
+            .values
+            .iter()
+            .find(|(column, _)| column == name)
+            .and_then(|(_, value)| *value)
+            .unwrap()
+    };
+    assert_eq!(value("polygon_count"), 2.0);
+    assert_eq!(value("line_count"), 0.0);
+    assert_close(value("line_length"), 0.05);
+    assert_eq!(value("point_count"), 1.0);
+}
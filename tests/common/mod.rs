@@ -0,0 +1,92 @@
+//! 統合テストで共有する、同梱した小さな境界データ（`tests/fixtures/n03_11_sample.geojson`）と golden ファイルの扱い。
+//! 意図して結果を変えた場合は `LAYON_UPDATE_GOLDEN=1 cargo test` で `tests/golden/` を書き直す。
+
+// テストのファイルごとに使う関数が違う
+#![allow(dead_code)]
+
+use layon::{
+    aggregate::Metric,
+    pipeline::{Csv, Pipeline},
+};
+use std::{fs, path::PathBuf};
+
+pub const GROUP_BY: &str = "N03_004";
+
+pub fn fixture() -> String {
+    format!(
+        "{}/tests/fixtures/n03_11_sample.geojson",
+        env!("CARGO_MANIFEST_DIR")
+    )
+}
+
+pub fn golden(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name)
+}
+
+/// テストごとの一時ディレクトリ
+pub fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("layon-golden-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// 集計して CSV に書き出し、その行（見出しを除く）を返す
+pub fn aggregate_csv(
+    input: &str,
+    group_by: &str,
+    metric: Metric,
+    output: &PathBuf,
+) -> Vec<(String, f64)> {
+    Pipeline::read(input)
+        .group_by(group_by)
+        .metric(metric)
+        .sink(Csv::new(output.to_str().unwrap()))
+        .run()
+        .unwrap();
+    read_rows(&fs::read_to_string(output).unwrap())
+}
+
+pub fn read_rows(text: &str) -> Vec<(String, f64)> {
+    text.lines()
+        .skip(1)
+        .map(|line| {
+            let (key, area) = line.rsplit_once(',').unwrap();
+            (key.to_string(), area.parse().unwrap())
+        })
+        .collect()
+}
+
+/// 書き出した CSV を golden ファイルと比べる（面積は相対誤差 1e-9 まで許す）
+pub fn assert_golden(name: &str, output: &PathBuf) {
+    let path = golden(name);
+    if std::env::var_os("LAYON_UPDATE_GOLDEN").is_some() {
+        fs::copy(output, &path).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("{} を読み込めません: {}", path.display(), err));
+    let expected = read_rows(&expected);
+    let actual = read_rows(&fs::read_to_string(output).unwrap());
+    assert_rows(&actual, &expected, name);
+}
+
+pub fn assert_rows(actual: &[(String, f64)], expected: &[(String, f64)], context: &str) {
+    assert_eq!(
+        actual.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+        expected.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+        "{}: 集計キーの並びが違う",
+        context
+    );
+    for ((key, actual), (_, expected)) in actual.iter().zip(expected) {
+        assert!(
+            (actual - expected).abs() <= 1e-9 * expected.abs(),
+            "{}: {} の面積 {} != {}",
+            context,
+            key,
+            actual,
+            expected
+        );
+    }
+}
@@ -0,0 +1,359 @@
+//! 入力形式の読み込み（圧縮、GeoPackage のレイヤー、CSV の書式など）を、読み込みから書き出しまで通して確かめる。
+
+mod common;
+
+use common::*;
+use geojson::{FeatureCollection, PointType, Value};
+use layon::{
+    aggregate::Metric,
+    pipeline::{Csv, Pipeline},
+    sink,
+    source::{Input, InputOptions},
+};
+use std::{fmt::Write, fs};
+
+#[test]
+fn feature_and_geometry_roots_are_read_as_feature_collections() {
+    let dir = scratch("roots");
+    let collection: FeatureCollection = fs::read_to_string(fixture()).unwrap().parse().unwrap();
+    let feature = &collection.features[0];
+    let read = |name: &str, text: String| {
+        let path = dir.join(name);
+        fs::write(&path, text).unwrap();
+        Input::parse(path.to_str().unwrap(), InputOptions::default())
+            .unwrap()
+            .read()
+    };
+
+    let single = read("feature.geojson", feature.to_string()).unwrap();
+    assert_eq!(single.features.len(), 1);
+    assert_eq!(single.features[0].properties, feature.properties);
+    let geometry = feature.geometry.as_ref().unwrap();
+    let bare = read("geometry.geojson", geometry.to_string()).unwrap();
+    assert_eq!(bare.features.len(), 1);
+    assert_eq!(bare.features[0].geometry.as_ref(), Some(geometry));
+    assert!(bare.features[0].properties.is_none());
+
+    let err = read("topology.geojson", r#"{"type":"Topology"}"#.to_string()).unwrap_err();
+    assert!(
+        err.to_string().contains("最上位の type が Topology です"),
+        "{}",
+        err
+    );
+    let err = read("array.geojson", "[]".to_string()).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("JSON のオブジェクトではありません"),
+        "{}",
+        err
+    );
+}
+
+/// 無圧縮の DEFLATE ブロックで `data` を 1 つの gzip のメンバーにする（`bgzf` なら BC サブフィールドに大きさを書く）
+fn gzip_member(data: &[u8], bgzf: bool) -> Vec<u8> {
+    let mut body = Vec::new();
+    let mut chunks = data.chunks(0xFFFF).peekable();
+    if chunks.peek().is_none() {
+        body.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        body.push(u8::from(chunks.peek().is_none()));
+        body.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+        body.extend_from_slice(&(!(chunk.len() as u16)).to_le_bytes());
+        body.extend_from_slice(chunk);
+    }
+    let mut member = vec![0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 255];
+    if bgzf {
+        member[3] = 4;
+        let size = (10 + 8 + body.len() + 8 - 1) as u16;
+        member.extend_from_slice(&[6, 0, b'B', b'C', 2, 0]);
+        member.extend_from_slice(&size.to_le_bytes());
+    }
+    member.extend_from_slice(&body);
+    let crc = data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    });
+    member.extend_from_slice(&(!crc).to_le_bytes());
+    member.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    member
+}
+
+#[test]
+fn gzip_bgzf_and_zstd_inputs_match_the_uncompressed_file() {
+    let dir = scratch("gzip");
+    let text = fs::read(fixture()).unwrap();
+    let expected = Pipeline::read(fixture()).run().unwrap().rows;
+
+    // BGZF は 64 KiB 未満のメンバーに分け、最後に空のメンバーを置く
+    let mut bgzf: Vec<u8> = text
+        .chunks(20_000)
+        .flat_map(|chunk| gzip_member(chunk, true))
+        .collect();
+    bgzf.extend(gzip_member(b"", true));
+    // 普通の gzip のメンバーをつないだもの
+    let (first, second) = text.split_at(text.len() / 2);
+    let mut members = gzip_member(first, false);
+    members.extend(gzip_member(second, false));
+    // 1 つのフレームの zstd と、フレームに分けた zstd（フレームごとに並列に展開する）
+    let zstd = zstd::bulk::compress(&text, 3).unwrap();
+    let frames: Vec<u8> = text
+        .chunks(20_000)
+        .flat_map(|chunk| zstd::bulk::compress(chunk, 3).unwrap())
+        .collect();
+
+    for (name, data) in [
+        ("bgzf.geojson.gz", bgzf),
+        ("members.geojson.gz", members),
+        ("zstd.geojson.zst", zstd),
+        ("frames.geojson.zst", frames),
+    ] {
+        let path = dir.join(name);
+        fs::write(&path, data).unwrap();
+        let input = Input::parse(path.to_str().unwrap(), InputOptions::default()).unwrap();
+        let rows = Pipeline::from_input(input).run().unwrap().rows;
+        assert_eq!(rows.len(), expected.len(), "{}", name);
+        for (row, expected) in rows.iter().zip(&expected) {
+            assert_eq!(
+                (&row.key, row.area),
+                (&expected.key, expected.area),
+                "{}",
+                name
+            );
+        }
+    }
+
+    // 壊れたメンバーはチェックサムで見つける
+    let mut broken = gzip_member(&text, false);
+    let crc = broken.len() - 8;
+    broken[crc] ^= 1;
+    let path = dir.join("broken.geojson.gz");
+    fs::write(&path, broken).unwrap();
+    let input = Input::parse(path.to_str().unwrap(), InputOptions::default()).unwrap();
+    assert!(input
+        .read()
+        .unwrap_err()
+        .to_string()
+        .contains("チェックサム"));
+    assert!(Input::parse("N03.kml.zst", InputOptions::default()).is_err());
+}
+
+/// 同じ Feature を別の形式で書いて読み込んでも、GeoJSON と同じ結果になる
+#[test]
+fn other_formats_match_geojson() {
+    let dir = scratch("formats");
+    let collection = Input::parse(&fixture(), InputOptions::default())
+        .unwrap()
+        .read()
+        .unwrap();
+    let expected = aggregate_csv(&fixture(), GROUP_BY, Metric::Area, &dir.join("geojson.csv"));
+
+    let csv = dir.join("fixture.csv");
+    fs::write(&csv, to_wkt_csv(&collection)).unwrap();
+    let actual = aggregate_csv(
+        csv.to_str().unwrap(),
+        GROUP_BY,
+        Metric::Area,
+        &dir.join("csv.csv"),
+    );
+    assert_rows(&actual, &expected, "CSV (WKT)");
+
+    let kml = dir.join("fixture.kml");
+    fs::write(&kml, to_kml(&collection)).unwrap();
+    let actual = aggregate_csv(
+        kml.to_str().unwrap(),
+        "name",
+        Metric::Area,
+        &dir.join("kml.csv"),
+    );
+    assert_rows(&actual, &expected, "KML");
+}
+
+#[test]
+fn csv_dialect_and_number_format_are_applied() {
+    let dir = scratch("dialect");
+    let output = dir.join("areas.csv");
+    let format = sink::csv::Format {
+        numbers: sink::number::NumberFormat {
+            style: sink::number::Style::Fixed,
+            decimals: Some(12),
+            thousands: None,
+            decimal_mark: ',',
+        },
+        dialect: sink::csv::Dialect {
+            delimiter: b';',
+            crlf: true,
+            bom: true,
+            ..Default::default()
+        },
+    };
+    Pipeline::read(fixture())
+        .group_by(GROUP_BY)
+        .sink(Csv::new(output.to_str().unwrap()).format(format))
+        .run()
+        .unwrap();
+
+    let text = fs::read_to_string(&output).unwrap();
+    let text = text.strip_prefix('\u{feff}').expect("BOM がない");
+    assert!(text.ends_with("\r\n") && !text.replace("\r\n", "").contains('\n'));
+    // 区切り文字を戻し、小数点を '.' にすれば golden と同じ（12 桁に丸めた分の誤差を除く）
+    let rows: Vec<(String, f64)> = text
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let (key, area) = line.rsplit_once(';').unwrap();
+            (key.to_string(), area.replace(',', ".").parse().unwrap())
+        })
+        .collect();
+    let expected = read_rows(&fs::read_to_string(golden("n03_11_sample_area.csv")).unwrap());
+    assert_eq!(rows.len(), expected.len());
+    for ((key, actual), (expected_key, expected)) in rows.iter().zip(&expected) {
+        assert_eq!(key, expected_key);
+        assert!(
+            (actual - expected).abs() < 1e-12,
+            "{}: {} != {}",
+            key,
+            actual,
+            expected
+        );
+    }
+}
+
+/// 2 つのレイヤー（rivers: X の 1×1 と Y の 2×2 の正方形と空のジオメトリ、lakes: X の 3×3 の正方形）の GeoPackage
+fn two_layers() -> String {
+    format!(
+        "{}/tests/fixtures/two_layers.gpkg",
+        env!("CARGO_MANIFEST_DIR")
+    )
+}
+
+#[test]
+fn geopackage_layers_are_listed_and_aggregated_separately() {
+    let input = Input::parse(&two_layers(), InputOptions::default()).unwrap();
+    let layers: Vec<(String, Option<usize>)> = input
+        .layers()
+        .unwrap()
+        .into_iter()
+        .map(|layer| (layer.name, layer.features))
+        .collect();
+    assert_eq!(
+        layers,
+        [
+            ("rivers".to_string(), Some(3)),
+            ("lakes".to_string(), Some(1))
+        ]
+    );
+
+    let result = Pipeline::from_input(input).group_by("name").run().unwrap();
+    let rows: Vec<(Option<&str>, &str, f64)> = result
+        .rows
+        .iter()
+        .map(|row| (row.layer.as_deref(), row.key.as_str(), row.area))
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            (Some("lakes"), "X", 9.0),
+            (Some("rivers"), "Y", 4.0),
+            (Some("rivers"), "X", 1.0)
+        ]
+    );
+
+    // 1 つのレイヤーだけを選べば、レイヤーの列は付かない
+    let rivers = || {
+        let options = InputOptions {
+            layers: vec!["rivers".to_string()],
+            ..Default::default()
+        };
+        Input::parse(&two_layers(), options).unwrap()
+    };
+    let collection = rivers().read().unwrap();
+    assert_eq!(collection.features.len(), 3);
+    let feature = &collection.features[1];
+    assert_eq!(feature.id, Some(geojson::feature::Id::Number(2.into())));
+    assert_eq!(feature.property("pop"), Some(&20.into()));
+    assert_eq!(feature.property("layer"), Some(&"rivers".into()));
+    assert!(collection.features[2].geometry.is_none());
+    let result = Pipeline::from_input(rivers())
+        .group_by("name")
+        .run()
+        .unwrap();
+    assert!(result.rows.iter().all(|row| row.layer.is_none()));
+}
+
+/// 集計キーのポリゴン（Polygon または MultiPolygon）
+fn polygons(collection: &FeatureCollection) -> Vec<(String, Vec<Vec<Vec<PointType>>>)> {
+    collection
+        .features
+        .iter()
+        .map(|feature| {
+            let key = feature
+                .property(GROUP_BY)
+                .unwrap()
+                .as_str()
+                .unwrap()
+                .to_string();
+            let polygons = match &feature.geometry.as_ref().unwrap().value {
+                Value::Polygon(rings) => vec![rings.clone()],
+                Value::MultiPolygon(polygons) => polygons.clone(),
+                other => panic!("ポリゴンではないジオメトリ: {}", other.type_name()),
+            };
+            (key, polygons)
+        })
+        .collect()
+}
+
+fn to_wkt_csv(collection: &FeatureCollection) -> String {
+    let mut text = format!("{},wkt\n", GROUP_BY);
+    for (key, polygons) in polygons(collection) {
+        let polygons: Vec<String> = polygons
+            .iter()
+            .map(|rings| {
+                let rings: Vec<String> = rings
+                    .iter()
+                    .map(|ring| {
+                        let points: Vec<String> =
+                            ring.iter().map(|p| format!("{} {}", p[0], p[1])).collect();
+                        format!("({})", points.join(", "))
+                    })
+                    .collect();
+                format!("({})", rings.join(", "))
+            })
+            .collect();
+        writeln!(text, "{},\"MULTIPOLYGON ({})\"", key, polygons.join(", ")).unwrap();
+    }
+    text
+}
+
+fn to_kml(collection: &FeatureCollection) -> String {
+    let mut text = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<kml xmlns=\"http://www.opengis.net/kml/2.2\"><Document>\n",
+    );
+    for (key, polygons) in polygons(collection) {
+        write!(text, "<Placemark><name>{}</name><MultiGeometry>", key).unwrap();
+        for rings in polygons {
+            text.push_str("<Polygon>");
+            for (i, ring) in rings.iter().enumerate() {
+                let boundary = if i == 0 {
+                    "outerBoundaryIs"
+                } else {
+                    "innerBoundaryIs"
+                };
+                let points: Vec<String> =
+                    ring.iter().map(|p| format!("{},{}", p[0], p[1])).collect();
+                write!(
+                    text,
+                    "<{0}><LinearRing><coordinates>{1}</coordinates></LinearRing></{0}>",
+                    boundary,
+                    points.join(" ")
+                )
+                .unwrap();
+            }
+            text.push_str("</Polygon>");
+        }
+        text.push_str("</MultiGeometry></Placemark>\n");
+    }
+    text.push_str("</Document></kml>\n");
+    text
+}
@@ -0,0 +1,36 @@
+// layon schema で tests/fixtures/n03_11_sample.geojson の先頭 8 個の Feature から生成した。手で編集しないこと。
+
+/// Feature のプロパティ
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct N03Feature {
+    /// N03_001（例: "埼玉県"）
+    #[serde(rename = "N03_001")]
+    pub prefecture: String,
+    /// N03_003（例: "入間郡"）
+    #[serde(rename = "N03_003", default)]
+    pub county: Option<String>,
+    /// N03_004（例: "本庄市"）
+    #[serde(rename = "N03_004")]
+    pub city: String,
+    /// N03_005
+    #[serde(rename = "N03_005", default)]
+    pub ward: serde_json::Value,
+    /// N03_007（例: "11211"）
+    #[serde(rename = "N03_007")]
+    pub code: String,
+}
+
+impl N03Feature {
+    /// 元のプロパティの名前（フィールドの順）
+    pub const PROPERTIES: [&str; 5] = ["N03_001", "N03_003", "N03_004", "N03_005", "N03_007"];
+
+    /// プロパティを読む（必須のプロパティがない、または型が合わない場合はエラー）
+    pub fn from_properties(properties: &geojson::JsonObject) -> Result<Self, serde_json::Error> {
+        serde_json::from_value(serde_json::Value::Object(properties.clone()))
+    }
+
+    /// Feature のプロパティを読む（プロパティのない Feature は空として読む）
+    pub fn from_feature(feature: &geojson::Feature) -> Result<Self, serde_json::Error> {
+        Self::from_properties(feature.properties.as_ref().unwrap_or(&Default::default()))
+    }
+}